@@ -0,0 +1,79 @@
+use std::borrow::Cow;
+
+use symphonia::core::audio::{AudioBuffer, AudioBufferRef, SignalSpec};
+
+pub mod equalizer;
+pub mod sample_tap;
+pub mod transition;
+
+use equalizer::Equalizer;
+use sample_tap::SampleTap;
+use transition::Transitions;
+
+/// A stage that processes decoded audio in place, before it's written to the output
+pub trait AudioProcessor {
+    /// Inactive stages are skipped; when every stage is inactive,
+    /// decoded audio goes straight to the output without converting it
+    fn is_active(&self) -> bool;
+
+    /// Processes one packet, as f32 planes (one per channel)
+    fn process(&mut self, spec: &SignalSpec, planes: &mut [&mut [f32]]);
+}
+
+/// The processing stages between decoding and the audio output.
+/// To add a stage, add a field and include it in the stages array in process.
+#[derive(Default)]
+pub struct DspPipeline {
+    pub equalizer: Equalizer,
+    pub transitions: Transitions,
+    /// Last, so that a visualizer shows what's heard
+    pub sample_tap: SampleTap,
+    /// Reused between packets to avoid allocating
+    buf: Option<AudioBuffer<f32>>,
+}
+
+impl std::fmt::Debug for DspPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DspPipeline")
+            .field("equalizer", &self.equalizer)
+            .field("transitions", &self.transitions)
+            .field("sample_tap", &self.sample_tap)
+            .finish()
+    }
+}
+
+impl DspPipeline {
+    pub fn process<'a>(&'a mut self, decoded: AudioBufferRef<'a>) -> AudioBufferRef<'a> {
+        let mut stages: [&mut dyn AudioProcessor; 3] = [
+            &mut self.equalizer,
+            &mut self.transitions,
+            &mut self.sample_tap,
+        ];
+        if !stages.iter().any(|stage| stage.is_active()) {
+            return decoded;
+        }
+
+        let spec = *decoded.spec();
+        let capacity = decoded.capacity();
+        let fits = self
+            .buf
+            .as_ref()
+            .map(|buf| *buf.spec() == spec && buf.capacity() >= capacity)
+            .unwrap_or_default();
+        if !fits {
+            self.buf = None;
+        }
+        let buf = self.buf.get_or_insert_with(|| decoded.make_equivalent());
+
+        decoded.convert(buf);
+
+        {
+            let mut planes = buf.planes_mut();
+            for stage in stages.iter_mut().filter(|stage| stage.is_active()) {
+                stage.process(&spec, planes.planes());
+            }
+        }
+
+        AudioBufferRef::F32(Cow::Borrowed(buf))
+    }
+}
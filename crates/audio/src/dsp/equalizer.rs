@@ -0,0 +1,280 @@
+use std::f32::consts::PI;
+
+use symphonia::core::audio::SignalSpec;
+
+use super::AudioProcessor;
+
+/// The center frequency of each band, in Hz
+pub const BAND_FREQUENCIES: [f32; BAND_COUNT] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+pub const BAND_COUNT: usize = 10;
+
+/// The range of each band's gain, in dB
+pub const MAX_BAND_GAIN: f32 = 12.0;
+
+/// Bandwidth of about one octave, to match the band spacing
+const BAND_Q: f32 = 1.41;
+
+/// The gain for each band, in dB
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EqCurve(pub [f32; BAND_COUNT]);
+
+impl EqCurve {
+    pub fn is_flat(&self) -> bool {
+        self.0.iter().all(|gain| *gain == 0.0)
+    }
+
+    /// Builds a curve from saved gains, clamped to the allowed range.
+    /// None if the wrong number of bands was saved.
+    pub fn from_saved(gains: &[f64]) -> Option<Self> {
+        let gains: [f64; BAND_COUNT] = gains.try_into().ok()?;
+        let gains = gains.map(|gain| (gain as f32).clamp(-MAX_BAND_GAIN, MAX_BAND_GAIN));
+
+        Some(Self(gains))
+    }
+
+    pub fn to_saved(self) -> Vec<f64> {
+        self.0.iter().map(|gain| f64::from(*gain)).collect()
+    }
+
+    /// The preset with this exact curve, if any
+    pub fn preset(&self) -> Option<EqPreset> {
+        EqPreset::ALL
+            .into_iter()
+            .find(|preset| preset.curve() == *self)
+    }
+
+    /// An attenuation that leaves room for the largest boost, to avoid clipping
+    fn headroom(&self) -> f32 {
+        let max_boost = self.0.iter().copied().fold(0.0, f32::max);
+        db_to_linear(-max_boost)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EqPreset {
+    Flat,
+    BassBoost,
+    TrebleBoost,
+    Vocal,
+    Loudness,
+}
+
+impl EqPreset {
+    pub const ALL: [EqPreset; 5] = [
+        Self::Flat,
+        Self::BassBoost,
+        Self::TrebleBoost,
+        Self::Vocal,
+        Self::Loudness,
+    ];
+
+    pub fn curve(&self) -> EqCurve {
+        let gains = match self {
+            Self::Flat => [0.0; BAND_COUNT],
+            Self::BassBoost => [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            Self::TrebleBoost => [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0],
+            Self::Vocal => [-2.0, -2.0, -1.0, 1.0, 3.0, 3.0, 2.0, 1.0, 0.0, -1.0],
+            Self::Loudness => [5.0, 4.0, 2.0, 0.0, -1.0, -1.0, 0.0, 2.0, 4.0, 5.0],
+        };
+
+        EqCurve(gains)
+    }
+}
+
+impl std::fmt::Display for EqPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Flat => "Flat",
+            Self::BassBoost => "Bass Boost",
+            Self::TrebleBoost => "Treble Boost",
+            Self::Vocal => "Vocal",
+            Self::Loudness => "Loudness",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// A 10-band graphic equalizer, made of peaking biquad filters
+#[derive(Debug, Default)]
+pub struct Equalizer {
+    curve: EqCurve,
+    /// The sample rate the filters were designed for; None = needs a redesign
+    designed_rate: Option<u32>,
+    /// The filter for each band, for each channel
+    channels: Vec<[Biquad; BAND_COUNT]>,
+}
+
+impl Equalizer {
+    pub fn curve(&self) -> EqCurve {
+        self.curve
+    }
+
+    /// NOTE this keeps the filter state, to avoid a click when changing the curve mid-song
+    pub fn set_curve(&mut self, curve: EqCurve) {
+        self.curve = curve;
+        self.designed_rate = None;
+    }
+
+    fn design(&mut self, rate: u32, channel_count: usize) {
+        if self.channels.len() != channel_count {
+            self.channels = vec![Default::default(); channel_count];
+        }
+
+        let bands = BAND_FREQUENCIES.iter().zip(self.curve.0);
+        for (band, (frequency, gain_db)) in bands.enumerate() {
+            let coefficients = Coefficients::peaking(*frequency, gain_db, rate as f32);
+            for filters in &mut self.channels {
+                filters[band].coefficients = coefficients;
+            }
+        }
+
+        self.designed_rate = Some(rate);
+    }
+}
+
+impl AudioProcessor for Equalizer {
+    fn is_active(&self) -> bool {
+        !self.curve.is_flat()
+    }
+
+    fn process(&mut self, spec: &SignalSpec, planes: &mut [&mut [f32]]) {
+        if self.designed_rate != Some(spec.rate) || self.channels.len() != planes.len() {
+            self.design(spec.rate, planes.len());
+        }
+
+        let headroom = self.curve.headroom();
+
+        for (filters, plane) in self.channels.iter_mut().zip(planes.iter_mut()) {
+            for sample in plane.iter_mut() {
+                let mut value = *sample * headroom;
+                for filter in filters.iter_mut() {
+                    value = filter.process(value);
+                }
+                *sample = value;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Biquad {
+    coefficients: Coefficients,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    // transposed direct form II
+    fn process(&mut self, input: f32) -> f32 {
+        let Coefficients { b0, b1, b2, a1, a2 } = self.coefficients;
+
+        let output = b0 * input + self.z1;
+        self.z1 = b1 * input - a1 * output + self.z2;
+        self.z2 = b2 * input - a2 * output;
+
+        output
+    }
+}
+
+/// Normalized biquad coefficients (a0 = 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Default for Coefficients {
+    /// Passes samples through unchanged
+    fn default() -> Self {
+        Self {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+        }
+    }
+}
+
+impl Coefficients {
+    /// A peaking filter, from the RBJ audio eq cookbook
+    fn peaking(frequency: f32, gain_db: f32, rate: f32) -> Self {
+        // bands too close to nyquist can't be represented at low sample rates
+        if gain_db == 0.0 || frequency >= rate * 0.45 {
+            return Self::default();
+        }
+
+        let amplitude = 10_f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / rate;
+        let alpha = w0.sin() / (2.0 * BAND_Q);
+        let cos_w0 = w0.cos();
+
+        let a0 = 1.0 + alpha / amplitude;
+
+        Self {
+            b0: (1.0 + alpha * amplitude) / a0,
+            b1: (-2.0 * cos_w0) / a0,
+            b2: (1.0 - alpha * amplitude) / a0,
+            a1: (-2.0 * cos_w0) / a0,
+            a2: (1.0 - alpha / amplitude) / a0,
+        }
+    }
+}
+
+fn db_to_linear(db: f32) -> f32 {
+    10_f32.powf(db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::audio::Channels;
+
+    use super::*;
+
+    #[test]
+    fn boosted_band_amplifies_its_frequency() {
+        let rate = 48_000;
+        let spec = SignalSpec::new(rate, Channels::FRONT_LEFT);
+
+        let mut gains = [0.0; BAND_COUNT];
+        gains[5] = 6.0; // 1 kHz
+        let mut equalizer = Equalizer::default();
+        equalizer.set_curve(EqCurve(gains));
+
+        let sine = |frequency: f32| -> Vec<f32> {
+            (0..rate)
+                .map(|n| (2.0 * PI * frequency * n as f32 / rate as f32).sin() * 0.25)
+                .collect()
+        };
+        let peak = |samples: &[f32]| {
+            // skip the filter's settling time
+            let settled = &samples[samples.len() / 2..];
+            settled.iter().copied().map(f32::abs).fold(0.0, f32::max)
+        };
+
+        let mut at_band = sine(1000.0);
+        equalizer.process(&spec, &mut [&mut at_band]);
+        let mut far_from_band = sine(60.0);
+        equalizer.process(&spec, &mut [&mut far_from_band]);
+
+        // +6 dB at the band, then -6 dB of headroom everywhere
+        assert!((peak(&at_band) - 0.25).abs() < 0.01);
+        assert!((peak(&far_from_band) - 0.125).abs() < 0.01);
+    }
+
+    #[test]
+    fn saved_curves_must_have_every_band() {
+        let curve = EqPreset::Vocal.curve();
+        assert_eq!(EqCurve::from_saved(&curve.to_saved()), Some(curve));
+        assert_eq!(EqCurve::from_saved(&[1.0, 2.0]), None);
+
+        let too_loud = EqCurve::from_saved(&[100.0; BAND_COUNT]).unwrap();
+        assert_eq!(too_loud.0[0], MAX_BAND_GAIN);
+    }
+}
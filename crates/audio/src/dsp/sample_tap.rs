@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+
+use symphonia::core::audio::SignalSpec;
+
+use super::AudioProcessor;
+
+/// The number of samples kept for a visualizer; about 46ms at 44.1kHz
+pub const TAP_LENGTH: usize = 2048;
+
+/// The latest samples sent to the output, mixed down to mono.
+/// Shared between the player, which fills it, and the ui, which draws it
+#[derive(Debug, Clone, Default)]
+pub struct SampleRing(Arc<Mutex<RingState>>);
+
+#[derive(Debug)]
+struct RingState {
+    samples: Vec<f32>,
+    /// Where the next sample goes; the oldest sample is here too, once the ring is full
+    next: usize,
+    sample_rate: u32,
+}
+
+impl Default for RingState {
+    fn default() -> Self {
+        Self {
+            samples: vec![0.0; TAP_LENGTH],
+            next: 0,
+            sample_rate: 44_100,
+        }
+    }
+}
+
+/// The same ring, rather than the same samples
+impl PartialEq for SampleRing {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl SampleRing {
+    /// The latest samples, oldest first, with their sample rate
+    pub fn snapshot(&self) -> (Vec<f32>, u32) {
+        let Ok(state) = self.0.lock() else {
+            return (vec![0.0; TAP_LENGTH], 44_100);
+        };
+
+        let (newer, older) = state.samples.split_at(state.next);
+        (
+            older.iter().chain(newer).copied().collect(),
+            state.sample_rate,
+        )
+    }
+
+    fn push(&self, spec: &SignalSpec, planes: &[&mut [f32]]) {
+        let Ok(mut state) = self.0.lock() else {
+            return;
+        };
+        state.sample_rate = spec.rate;
+
+        let frames = planes.first().map(|plane| plane.len()).unwrap_or_default();
+        // NOTE only the end of a long packet fits anyway
+        for frame in frames.saturating_sub(TAP_LENGTH)..frames {
+            let sum: f32 = planes.iter().map(|plane| plane[frame]).sum();
+            let next = state.next;
+            state.samples[next] = sum / planes.len() as f32;
+            state.next = (next + 1) % TAP_LENGTH;
+        }
+    }
+}
+
+/// Copies the audio into a ring for the ui's visualizer, without changing it
+#[derive(Debug, Default)]
+pub struct SampleTap {
+    /// None = no visualizer is shown
+    ring: Option<SampleRing>,
+}
+
+impl SampleTap {
+    pub fn set_ring(&mut self, ring: Option<SampleRing>) {
+        self.ring = ring;
+    }
+}
+
+impl AudioProcessor for SampleTap {
+    fn is_active(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    fn process(&mut self, spec: &SignalSpec, planes: &mut [&mut [f32]]) {
+        if let Some(ring) = &self.ring {
+            ring.push(spec, planes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::audio::Channels;
+
+    #[test]
+    fn the_ring_keeps_the_latest_samples_mixed_to_mono() {
+        let ring = SampleRing::default();
+        let mut tap = SampleTap::default();
+        assert!(!tap.is_active());
+        tap.set_ring(Some(ring.clone()));
+        assert!(tap.is_active());
+
+        let spec = SignalSpec::new(48_000, Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+        let mut left: Vec<f32> = (0..TAP_LENGTH + 10).map(|i| i as f32).collect();
+        let mut right = vec![0.0; TAP_LENGTH + 10];
+        tap.process(&spec, &mut [&mut left, &mut right]);
+
+        let mut left = vec![1.0, 1.0];
+        let mut right = vec![0.0, 1.0];
+        tap.process(&spec, &mut [&mut left, &mut right]);
+
+        let (samples, sample_rate) = ring.snapshot();
+        assert_eq!(sample_rate, 48_000);
+        assert_eq!(samples.len(), TAP_LENGTH);
+        assert_eq!(samples[0], 6.0);
+        assert_eq!(samples[TAP_LENGTH - 3], (TAP_LENGTH + 9) as f32 / 2.0);
+        assert_eq!(samples[TAP_LENGTH - 2..], [0.5, 1.0]);
+    }
+}
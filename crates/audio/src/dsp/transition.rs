@@ -0,0 +1,289 @@
+use symphonia::core::audio::SignalSpec;
+
+use super::AudioProcessor;
+
+/// How far ahead of a packet to look for a change in gain.
+/// Packets are much shorter than this, so a fade never starts mid-packet unnoticed.
+const LOOKAHEAD_SECONDS: f64 = 0.5;
+
+/// How long a skipped song fades out, rather than cutting off
+pub const SKIP_FADE_SECONDS: f64 = 0.15;
+
+/// How one song hands off to the next, when the queue moves on by itself
+pub trait Transition: std::fmt::Debug + Send {
+    /// The gain at a point in a song, to shape its start or end
+    fn gain(&self, position: &SongPosition) -> f32;
+
+    /// Silence to leave between the end of one song and the start of the next
+    fn gap_seconds(&self) -> f64 {
+        0.0
+    }
+}
+
+/// A point in the current song, for a transition to shape
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SongPosition {
+    pub elapsed_seconds: f64,
+    pub total_seconds: f64,
+    /// The song followed the previous one by itself,
+    /// rather than being chosen, skipped to, or resumed
+    pub followed_previous: bool,
+}
+
+impl SongPosition {
+    fn remaining_seconds(&self) -> f64 {
+        self.total_seconds - self.elapsed_seconds
+    }
+
+    fn advanced_by(&self, seconds: f64) -> Self {
+        Self {
+            elapsed_seconds: self.elapsed_seconds + seconds,
+            ..*self
+        }
+    }
+}
+
+/// The transitions to choose from; saved by name per session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransitionKind {
+    /// Straight into the next song, gapless when the files allow it
+    #[default]
+    Cut,
+    Gap,
+    FadeOutIn,
+}
+
+impl TransitionKind {
+    pub const ALL: [TransitionKind; 3] = [Self::Cut, Self::Gap, Self::FadeOutIn];
+
+    /// None for a name saved by a later version
+    pub fn from_saved(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.to_saved() == name)
+    }
+
+    pub fn to_saved(self) -> &'static str {
+        match self {
+            Self::Cut => "cut",
+            Self::Gap => "gap",
+            Self::FadeOutIn => "fade_out_in",
+        }
+    }
+
+    fn transition(self) -> Box<dyn Transition> {
+        match self {
+            Self::Cut => Box::new(Cut),
+            Self::Gap => Box::new(Gap { seconds: 2.0 }),
+            Self::FadeOutIn => Box::new(FadeOutIn { seconds: 3.0 }),
+        }
+    }
+}
+
+impl std::fmt::Display for TransitionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Cut => "Cut",
+            Self::Gap => "Gap",
+            Self::FadeOutIn => "Fade Out/In",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug)]
+struct Cut;
+
+impl Transition for Cut {
+    fn gain(&self, _position: &SongPosition) -> f32 {
+        1.0
+    }
+}
+
+#[derive(Debug)]
+struct Gap {
+    seconds: f64,
+}
+
+impl Transition for Gap {
+    fn gain(&self, _position: &SongPosition) -> f32 {
+        1.0
+    }
+
+    fn gap_seconds(&self) -> f64 {
+        self.seconds
+    }
+}
+
+/// Fades out the end of every song,
+/// and fades in the start of songs that followed one
+#[derive(Debug)]
+struct FadeOutIn {
+    seconds: f64,
+}
+
+impl Transition for FadeOutIn {
+    fn gain(&self, position: &SongPosition) -> f32 {
+        let fade_out = position.remaining_seconds() / self.seconds;
+        let fade_in = if position.followed_previous {
+            position.elapsed_seconds / self.seconds
+        } else {
+            1.0
+        };
+
+        fade_out.min(fade_in).clamp(0.0, 1.0) as f32
+    }
+}
+
+/// A quick fade to silence from a point in the current song, ie when it's skipped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FadeOut {
+    pub from_seconds: f64,
+    pub seconds: f64,
+}
+
+impl FadeOut {
+    pub fn skip(from_seconds: f64) -> Self {
+        Self {
+            from_seconds,
+            seconds: SKIP_FADE_SECONDS,
+        }
+    }
+
+    pub fn is_silent_at(&self, elapsed_seconds: f64) -> bool {
+        elapsed_seconds >= self.from_seconds + self.seconds
+    }
+
+    fn gain(&self, position: &SongPosition) -> f32 {
+        let faded = (position.elapsed_seconds - self.from_seconds) / self.seconds;
+        (1.0 - faded).clamp(0.0, 1.0) as f32
+    }
+}
+
+/// Applies the chosen transition to the current song
+#[derive(Debug)]
+pub struct Transitions {
+    transition: Box<dyn Transition>,
+    /// The position of the next packet; None = unknown, so nothing is shaped
+    position: Option<SongPosition>,
+    /// Shapes the current song along with the transition, until the song changes
+    fade_out: Option<FadeOut>,
+}
+
+impl Default for Transitions {
+    fn default() -> Self {
+        Self {
+            transition: TransitionKind::default().transition(),
+            position: None,
+            fade_out: None,
+        }
+    }
+}
+
+impl Transitions {
+    pub fn set_kind(&mut self, kind: TransitionKind) {
+        self.transition = kind.transition();
+    }
+
+    /// Set before processing each packet
+    pub fn set_position(&mut self, position: Option<SongPosition>) {
+        self.position = position;
+    }
+
+    /// Set before processing each packet, along with the position
+    pub fn set_fade_out(&mut self, fade_out: Option<FadeOut>) {
+        self.fade_out = fade_out;
+    }
+
+    pub fn gap_seconds(&self) -> f64 {
+        self.transition.gap_seconds()
+    }
+
+    fn gain(&self, position: &SongPosition) -> f32 {
+        let gain = self.transition.gain(position);
+
+        match &self.fade_out {
+            Some(fade_out) => gain.min(fade_out.gain(position)),
+            None => gain,
+        }
+    }
+}
+
+impl AudioProcessor for Transitions {
+    fn is_active(&self) -> bool {
+        let Some(position) = self.position else {
+            return false;
+        };
+
+        let lookahead = position.advanced_by(LOOKAHEAD_SECONDS);
+        self.gain(&position) < 1.0 || self.gain(&lookahead) < 1.0
+    }
+
+    fn process(&mut self, spec: &SignalSpec, planes: &mut [&mut [f32]]) {
+        let Some(position) = self.position else {
+            return;
+        };
+
+        let frame_seconds = 1.0 / f64::from(spec.rate);
+        let frames = planes.first().map(|plane| plane.len()).unwrap_or_default();
+
+        for frame in 0..frames {
+            let at_frame = position.advanced_by(frame as f64 * frame_seconds);
+            let gain = self.gain(&at_frame);
+
+            for plane in planes.iter_mut() {
+                plane[frame] *= gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fade_in_only_follows_another_song() {
+        let fade = FadeOutIn { seconds: 3.0 };
+        let at = |elapsed_seconds, followed_previous| SongPosition {
+            elapsed_seconds,
+            total_seconds: 100.0,
+            followed_previous,
+        };
+
+        assert_eq!(fade.gain(&at(1.5, true)), 0.5);
+        assert_eq!(fade.gain(&at(1.5, false)), 1.0);
+        assert_eq!(fade.gain(&at(50.0, true)), 1.0);
+        assert_eq!(fade.gain(&at(98.5, false)), 0.5);
+        assert_eq!(fade.gain(&at(100.0, false)), 0.0);
+    }
+
+    #[test]
+    fn a_skip_fades_out_under_the_transition() {
+        let mut transitions = Transitions::default();
+        transitions.set_kind(TransitionKind::FadeOutIn);
+        transitions.set_fade_out(Some(FadeOut::skip(10.0)));
+        let at = |elapsed_seconds| SongPosition {
+            elapsed_seconds,
+            total_seconds: 100.0,
+            followed_previous: false,
+        };
+
+        assert_eq!(transitions.gain(&at(10.0)), 1.0);
+        assert_eq!(transitions.gain(&at(10.075)), 0.5);
+        assert_eq!(transitions.gain(&at(10.15)), 0.0);
+        assert!(!FadeOut::skip(10.0).is_silent_at(10.1));
+        assert!(FadeOut::skip(10.0).is_silent_at(10.15));
+
+        transitions.set_fade_out(None);
+        assert_eq!(transitions.gain(&at(10.15)), 1.0);
+    }
+
+    #[test]
+    fn saved_names_round_trip() {
+        for kind in TransitionKind::ALL {
+            assert_eq!(TransitionKind::from_saved(kind.to_saved()), Some(kind));
+        }
+
+        assert_eq!(TransitionKind::from_saved("crossfade"), None);
+    }
+}
@@ -0,0 +1,103 @@
+use camino::Utf8Path;
+use symphonia::core::codecs::{
+    CODEC_TYPE_AAC, CODEC_TYPE_MP1, CODEC_TYPE_MP2, CODEC_TYPE_MP3,
+};
+
+use crate::metadata::probe;
+use crate::track_info::first_supported_track;
+
+/// Words in an album's title or genre that mean its songs run into each other
+const GAPLESS_ALBUM_MARKERS: [&str; 8] = [
+    "live",
+    "dj mix",
+    "dj-mix",
+    "mixed by",
+    "continuous mix",
+    "mixtape",
+    "in concert",
+    "unplugged",
+];
+
+/// Why a song will have silence at its start or end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedGap {
+    /// An mp3 without the LAME header that says how much padding the encoder added
+    MissingEncoderDelay,
+    /// NOTE symphonia 0.5 doesn't read the iTunes gapless info from m4a files
+    UntrimmedAac,
+}
+
+impl std::fmt::Display for ExpectedGap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Self::MissingEncoderDelay => "mp3 without encoder delay info",
+            Self::UntrimmedAac => "aac, which isn't trimmed",
+        };
+
+        write!(f, "{reason}")
+    }
+}
+
+/// Whether an album sounds like a live recording or dj mix, going by its title and genres
+pub fn is_gapless_album<'a>(
+    title: Option<&'a str>,
+    genres: impl IntoIterator<Item = &'a str>,
+) -> bool {
+    title.into_iter().chain(genres).map(words).any(|words| {
+        GAPLESS_ALBUM_MARKERS
+            .iter()
+            .any(|marker| words.contains(&format!(" {marker} ")))
+    })
+}
+
+/// Lowercased and padded with spaces, so that markers only match whole words
+fn words(text: &str) -> String {
+    let mut words = String::from(" ");
+    for word in text
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '-')
+        .filter(|word| !word.is_empty())
+    {
+        words.push_str(word);
+        words.push(' ');
+    }
+
+    words
+}
+
+/// Checks a file's encoder delay info, without decoding it;
+/// None for files that play gaplessly, or that can't be read
+pub fn expected_gap(path: &Utf8Path) -> Option<ExpectedGap> {
+    let probed = probe(path)?;
+    let track = first_supported_track(probed.format.tracks())?;
+    let params = &track.codec_params;
+
+    match params.codec {
+        CODEC_TYPE_MP1 | CODEC_TYPE_MP2 | CODEC_TYPE_MP3 if params.delay.is_none() => {
+            Some(ExpectedGap::MissingEncoderDelay)
+        }
+        CODEC_TYPE_AAC => Some(ExpectedGap::UntrimmedAac),
+
+        // NOTE lossless codecs have no encoder delay, and the ogg reader trims vorbis
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gapless_albums_are_found_by_whole_words() {
+        assert!(is_gapless_album(Some("Stop Making Sense (Live)"), []));
+        assert!(is_gapless_album(Some("Fabric 50: Mixed by Ben UFO"), []));
+        assert!(is_gapless_album(
+            Some("Selected Works"),
+            ["Electronic", "DJ Mix"]
+        ));
+
+        assert!(!is_gapless_album(Some("Alive"), []));
+        assert!(!is_gapless_album(Some("Remixes"), ["Electronic"]));
+        assert!(!is_gapless_album(None, []));
+    }
+}
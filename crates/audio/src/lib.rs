@@ -2,9 +2,15 @@
 #![deny(missing_debug_implementations)]
 #![forbid(unsafe_code)]
 
+pub mod dsp;
+pub mod gapless;
 pub mod metadata;
 pub mod player;
+pub mod replay_gain;
+pub mod resample_quality;
+pub mod shuffle_order;
 pub mod track_info;
+pub mod waveform;
 
 #[cfg(not(target_os = "linux"))]
 mod resampler;
@@ -3,26 +3,97 @@ use std::fs::File;
 
 use camino::Utf8Path;
 use log::error;
-use symphonia::core::meta::{Metadata, StandardTagKey};
+use symphonia::core::meta::{Metadata, StandardTagKey, StandardVisualKey};
 use symphonia::core::{
     formats::FormatOptions,
     io::MediaSourceStream,
     meta::{MetadataOptions, MetadataRevision},
-    probe::Hint,
+    probe::{Hint, ProbeResult},
 };
 use symphonia::default::get_probe;
 
 use crate::track_info::{first_supported_track, TrackInfo};
 
+/// File extensions for the containers enabled in symphonia's features.
+/// Opus and aiff aren't supported by symphonia 0.5.
+pub const AUDIO_EXTENSIONS: [&str; 7] =
+    ["mp3", "flac", "ogg", "oga", "m4a", "wav", "mka"];
+
+/// Extensions that symphonia's readers claim, but only for codecs it can't decode
+const UNDECODABLE_EXTENSIONS: [&str; 2] = ["opus", "spx"];
+
+/// Whether symphonia has a reader enabled for files with this extension.
+/// NOTE the mkv reader doesn't claim mka, but finds those files by their header.
+pub fn is_supported_audio_extension(extension: &str) -> bool {
+    use symphonia::core::probe::QueryDescriptor;
+    use symphonia::default::formats::*;
+
+    let extension = extension.to_ascii_lowercase();
+    if AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+        return true;
+    }
+    if UNDECODABLE_EXTENSIONS.contains(&extension.as_str()) {
+        return false;
+    }
+
+    [
+        FlacReader::query(),
+        MpaReader::query(),
+        AdtsReader::query(),
+        IsoMp4Reader::query(),
+        MkvReader::query(),
+        OggReader::query(),
+        WavReader::query(),
+    ]
+    .iter()
+    .flat_map(|descriptors| descriptors.iter())
+    .any(|descriptor| descriptor.extensions.contains(&extension.as_str()))
+}
+
 #[derive(Debug)]
 pub struct DecodedMetadata {
     pub tags: HashMap<TagKey, String>,
     pub total_seconds: u64,
+    /// Encoded image data from the file's picture tags, preferring the front cover
+    pub embedded_art: Option<Box<[u8]>>,
 }
 
 /// NOTE This includes an empty tag map if the tags are missing,
 /// and None for file not found or unsupported format
 pub fn decode_metadata(path: &Utf8Path) -> Option<DecodedMetadata> {
+    let mut probed = probe(path)?;
+
+    let Some(track) = first_supported_track(probed.format.tracks()) else {
+        error!("no supported track");
+        return None;
+    };
+    let track_info: TrackInfo = track.into();
+
+    let Some(times) = track_info.progress_times(0) else {
+        error!("missing time information for audio file: {path}");
+        return None;
+    };
+
+    let gathered = if let Some(metadata_rev) = probed.format.metadata().current() {
+        Some(gather_metadata(metadata_rev))
+    } else {
+        probed
+            .metadata
+            .get()
+            .as_ref()
+            .and_then(Metadata::current)
+            .map(gather_metadata)
+    };
+    let (tags, embedded_art) = gathered.unwrap_or_default();
+
+    let total_seconds = times.total.seconds;
+
+    Some(DecodedMetadata { tags, total_seconds, embedded_art })
+}
+
+/// Opens a file's container with gapless info, without decoding any packets;
+/// None for file not found or unsupported format
+pub(crate) fn probe(path: &Utf8Path) -> Option<ProbeResult> {
     let mut hint = Hint::new();
     let source = {
         // Provide the file extension as a hint.
@@ -47,48 +118,42 @@ pub fn decode_metadata(path: &Utf8Path) -> Option<DecodedMetadata> {
     };
     let metadata_opts: MetadataOptions = Default::default();
 
-    let mut probed = match get_probe().format(&hint, mss, &format_opts, &metadata_opts) {
-        Ok(p) => p,
+    match get_probe().format(&hint, mss, &format_opts, &metadata_opts) {
+        Ok(probed) => Some(probed),
         Err(e) => {
             let path_str = path.as_str();
             error!("file in unsupported format: {path_str} {e}");
-            return None;
+            None
         }
-    };
-
-    let Some(track) = first_supported_track(probed.format.tracks()) else {
-        error!("no supported track");
-        return None;
-    };
-    let track_info: TrackInfo = track.into();
-
-    let Some(times) = track_info.progress_times(0) else {
-        error!("missing time information for audio file: {path}");
-        return None;
-    };
+    }
+}
 
-    let tags = if let Some(metadata_rev) = probed.format.metadata().current() {
-        Some(gather_tags(metadata_rev))
-    } else {
-        probed
-            .metadata
-            .get()
-            .as_ref()
-            .and_then(Metadata::current)
-            .map(gather_tags)
-    };
-    let tags = tags.unwrap_or_default();
+fn gather_metadata(
+    metadata_rev: &MetadataRevision,
+) -> (HashMap<TagKey, String>, Option<Box<[u8]>>) {
+    let visuals = metadata_rev.visuals();
+    let front_cover = visuals
+        .iter()
+        .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+        .or_else(|| visuals.first());
 
-    let total_seconds = times.total.seconds;
+    let embedded_art = front_cover.map(|visual| visual.data.clone());
 
-    Some(DecodedMetadata { tags, total_seconds })
+    (gather_tags(metadata_rev), embedded_art)
 }
 
 fn gather_tags(metadata_rev: &MetadataRevision) -> HashMap<TagKey, String> {
     let mut result = HashMap::new();
 
     for tag in metadata_rev.tags().iter() {
-        if let Some(key) = tag.std_key.and_then(|key| TagKey::try_from(key).ok()) {
+        let key = match tag.std_key {
+            Some(std_key) => TagKey::try_from(std_key).ok(),
+            // NOTE symphonia 0.5 has no standard key for the musical key
+            None if is_initial_key_tag(&tag.key) => Some(TagKey::InitialKey),
+            None => None,
+        };
+
+        if let Some(key) = key {
             result.insert(key, tag.value.to_string());
         }
     }
@@ -96,17 +161,27 @@ fn gather_tags(metadata_rev: &MetadataRevision) -> HashMap<TagKey, String> {
     result
 }
 
+/// The id3 frame, and the vorbis comment and mp4 names, for the musical key
+fn is_initial_key_tag(key: &str) -> bool {
+    key == "TKEY" || key.eq_ignore_ascii_case("initialkey")
+}
+
 /// A limited set of standard tag keys used by the application
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum TagKey {
     Album,
     AlbumArtist,
     Artist,
+    Bpm,
+    Compilation,
     Composer,
     Conductor,
     Date,
     Description,
+    DiscNumber,
     Genre,
+    /// Not a standard key in symphonia; see is_initial_key_tag
+    InitialKey,
     Label,
     Language,
     Lyrics,
@@ -118,6 +193,10 @@ pub enum TagKey {
     Producer,
     ReleaseDate,
     Remixer,
+    ReplayGainAlbumGain,
+    ReplayGainAlbumPeak,
+    ReplayGainTrackGain,
+    ReplayGainTrackPeak,
     TrackNumber,
     TrackSubtitle,
     TrackTitle,
@@ -132,10 +211,13 @@ impl TryFrom<StandardTagKey> for TagKey {
             StandardTagKey::Album => Ok(TagKey::Album),
             StandardTagKey::AlbumArtist => Ok(TagKey::AlbumArtist),
             StandardTagKey::Artist => Ok(TagKey::Artist),
+            StandardTagKey::Bpm => Ok(TagKey::Bpm),
+            StandardTagKey::Compilation => Ok(TagKey::Compilation),
             StandardTagKey::Composer => Ok(TagKey::Composer),
             StandardTagKey::Conductor => Ok(TagKey::Conductor),
             StandardTagKey::Date => Ok(TagKey::Date),
             StandardTagKey::Description => Ok(TagKey::Description),
+            StandardTagKey::DiscNumber => Ok(TagKey::DiscNumber),
             StandardTagKey::Genre => Ok(TagKey::Genre),
             StandardTagKey::Label => Ok(TagKey::Label),
             StandardTagKey::Language => Ok(TagKey::Language),
@@ -148,6 +230,10 @@ impl TryFrom<StandardTagKey> for TagKey {
             StandardTagKey::Producer => Ok(TagKey::Producer),
             StandardTagKey::ReleaseDate => Ok(TagKey::ReleaseDate),
             StandardTagKey::Remixer => Ok(TagKey::Remixer),
+            StandardTagKey::ReplayGainAlbumGain => Ok(TagKey::ReplayGainAlbumGain),
+            StandardTagKey::ReplayGainAlbumPeak => Ok(TagKey::ReplayGainAlbumPeak),
+            StandardTagKey::ReplayGainTrackGain => Ok(TagKey::ReplayGainTrackGain),
+            StandardTagKey::ReplayGainTrackPeak => Ok(TagKey::ReplayGainTrackPeak),
             StandardTagKey::TrackNumber => Ok(TagKey::TrackNumber),
             StandardTagKey::TrackSubtitle => Ok(TagKey::TrackSubtitle),
             StandardTagKey::TrackTitle => Ok(TagKey::TrackTitle),
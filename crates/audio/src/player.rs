@@ -1,14 +1,17 @@
 use std::collections::VecDeque;
 use std::fs::File;
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, bail, Context};
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use flume::{Receiver, Sender, TryRecvError};
 use log::{error, info, trace, warn};
+use serde::Serialize;
 use souvlaki::{MediaPlayback, MediaPosition};
-use symphonia::core::audio::{AsAudioBufferRef, AudioBufferRef};
+use symphonia::core::audio::{
+    AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec,
+};
 use symphonia::core::codecs::Decoder;
 use symphonia::core::errors::Error as SymphoniaError;
 use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
@@ -17,7 +20,9 @@ use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 use symphonia::core::units::Time;
 
-use clef_db::queries::SongId;
+use clef_db::queries::{self, SavedQueue, SmartPlaylistId, SongId};
+use clef_db::SqlitePool;
+use clef_shared::crash_report;
 use clef_shared::queue::Queue;
 
 use self::preloader::{
@@ -25,12 +30,22 @@ use self::preloader::{
     PreloaderEffect,
 };
 
+use super::dsp::equalizer::EqCurve;
+use super::dsp::sample_tap::SampleRing;
+use super::dsp::transition::{FadeOut, SongPosition, TransitionKind};
+use super::dsp::DspPipeline;
+use super::replay_gain::{ReplayGain, ReplayGainSettings};
+use super::resample_quality::ResampleQuality;
+use super::shuffle_order::{CamelotKey, ShuffleOrder};
 use super::track_info::{first_supported_track, TrackInfo};
 
 mod media_controls;
 use media_controls::*;
+mod history;
+use history::CurrentPlay;
 mod output;
 use output::AudioOutput;
+pub use output::OutputTelemetry;
 mod preloader;
 
 #[allow(unused)]
@@ -53,17 +68,123 @@ pub enum AudioAction {
     PlayPaused,
     /// Swap between play/pause based on current state
     Toggle,
+    /// Stop playing, and drop the current queue
+    Stop,
     /// Seek to position (0) of the current song, if any
     /// Expected to be a proportion in range 0.0..=1.0
     Seek(f32),
+    /// Seek relative to the current position of the current song, if any
+    /// Clamped to the bounds of the song
+    SeekBy(SeekOffset),
+    /// Seek to a position (0) in the current song, if any, in seconds
+    /// Clamped to the bounds of the song
+    SeekTo(f32),
+    /// Mark point A of a loop at the current position, then point B, then clear the loop.
+    /// The loop only lasts for the current song
+    MarkLoopPoint,
     /// Play the next track, if any, or transition to stopped
     Forward,
     /// Seek to the beginning of the current song,
     /// or if near it already, go back a track in the queue, if possible
     Back,
+    /// Jump to a position (0) in the queue's play order, ie from the os track list
+    GoTo(usize),
+    /// Drop the song at a position (0) in the queue's play order;
+    /// the current song can't be removed
+    RemoveFromQueue(usize),
+    /// Move an upcoming song from one position (0) in the queue's play order
+    /// to another (1), without interrupting the current song
+    MoveInQueue(usize, usize),
+    /// Turn shuffle on or off for the current and future queues
+    SetShuffle(bool),
+    /// Choose how shuffle arranges the upcoming songs;
+    /// a shuffled queue is rearranged
+    SetShuffleOrder(ShuffleOrder),
+    /// Begin playing the queue (0) from a position in the current song (1), in seconds
+    ResumeQueue(Box<Queue<QueuedSong>>, f32),
+    /// Load the queue (0) saved during the last launch, paused at a position (1)
+    RestoreState(Box<Queue<QueuedSong>>, f32),
+    /// Add a song to the end of the current queue,
+    /// or play it immediately if stopped
+    Enqueue(Box<QueuedSong>),
+    /// Add a song to play after the current song,
+    /// or play it immediately if stopped
+    EnqueueNext(Box<QueuedSong>),
+    /// Add songs to the end of the current queue, in order,
+    /// or play them if stopped
+    EnqueueAll(Vec<QueuedSong>),
+    /// Reply with a snapshot of the player state, for debugging
+    DumpState,
+    /// Pass a smart playlist chosen from the os media controls on to the ui,
+    /// which builds its queue
+    ActivatePlaylist(SmartPlaylistId),
+    /// Switch to the named output device (0); None = the system default
+    /// If a song is playing, it continues on the new device
+    SetOutputDevice(Option<String>),
+    /// Resample songs that don't match the device's rate with this quality;
+    /// a change reopens the output, like SetOutputDevice
+    SetResampleQuality(ResampleQuality),
+    /// Apply an equalizer curve to following audio
+    SetEqualizer(EqCurve),
+    /// Use this transition between songs in the queue from now on
+    SetTransition(TransitionKind),
+    /// Send progress less often, to save battery
+    SetLowPower(bool),
+    /// Copy the audio into a ring shared with the ui, for its visualizer;
+    /// None = stop copying
+    SetSampleTap(Option<SampleRing>),
+    /// Apply replaygain with these settings, starting with the next packet
+    SetReplayGain(ReplayGainSettings),
+    /// Scale the output by a linear gain in 0.0..=1.0, starting with the next packet;
+    /// a new volume also unmutes
+    SetVolume(f32),
+    /// Silence the output without forgetting the volume, starting with the next packet
+    SetMuted(bool),
+    /// Whether to save the queue, play history, and unplayable songs to the db;
+    /// off while a damaged db waits for the user to restore it or carry on
+    SetDbWrites(bool),
+}
+
+/// A section of the current song to repeat, ie for transcribing it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AbLoop {
+    /// Point A, in seconds
+    pub start: f64,
+    /// Point B, in seconds; None = only A is marked so far
+    pub end: Option<f64>,
+}
+
+impl AbLoop {
+    /// The loop after marking a point: A, then B, then no loop.
+    /// NOTE a B before A swaps them, rather than looping nothing
+    fn mark(ab_loop: Option<Self>, elapsed_seconds: f64) -> Option<Self> {
+        match ab_loop {
+            None => Some(Self { start: elapsed_seconds, end: None }),
+            Some(Self { start, end: None }) if start == elapsed_seconds => ab_loop,
+            Some(Self { start, end: None }) => Some(Self {
+                start: start.min(elapsed_seconds),
+                end: Some(start.max(elapsed_seconds)),
+            }),
+            Some(_) => None,
+        }
+    }
+
+    /// Where to seek back to, once playback reaches B
+    fn restart_at(&self, elapsed_seconds: f64) -> Option<f64> {
+        let end = self.end?;
+        (elapsed_seconds >= end).then_some(self.start)
+    }
+}
+
+/// A signed offset for relative seeking; negative values seek backwards
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekOffset {
+    Seconds(f32),
+    /// A proportion of the song's total length, in range -1.0..=1.0
+    Proportion(f32),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct QueuedSong {
     pub id: SongId,
     pub path: Utf8PathBuf,
@@ -72,8 +193,15 @@ pub struct QueuedSong {
     pub album_title: Option<String>,
     pub resized_art: Option<Utf8PathBuf>,
     pub duration: Option<Duration>,
+    pub replay_gain: ReplayGain,
+    /// For ordering shuffled songs by feel
+    pub bpm: Option<f64>,
+    pub key: Option<CamelotKey>,
 }
 
+// NOTE bpm is never nan; the crawler only saves finite tempos
+impl Eq for QueuedSong {}
+
 /// An mpsc message to the main/ui thread from audio
 #[derive(Debug, Clone, PartialEq)]
 pub enum AudioMessage {
@@ -83,8 +211,65 @@ pub enum AudioMessage {
     /// The first update after a seek request from the UI
     SeekComplete(PlayerDisplay),
 
-    /// The audio thread died
+    /// The player failed; it's replaced with a fresh one, stopped,
+    /// unless it has failed too often or the ui is gone
     AudioDied,
+
+    /// A reply to AudioAction::DumpState
+    StateDump(Box<PlayerSnapshot>),
+
+    /// The output buffer grew to (0) milliseconds, after repeated underruns
+    OutputBufferGrown(usize),
+
+    /// There's no output device to play on, so playback paused
+    NoOutputDevice,
+
+    /// A song (0) couldn't be opened for a reason (1), so the queue moved past it
+    SongUnplayable(SongId, String),
+
+    /// The volume or mute changed, from the ui or the os media controls
+    VolumeChanged { volume: f32, muted: bool },
+
+    /// A smart playlist was chosen from the os media controls
+    PlaylistActivated(SmartPlaylistId),
+
+    /// The queue's songs or position changed; None = player stopped
+    QueueUpdate(Option<Box<QueueDisplay>>),
+}
+
+/// The whole queue, for showing and editing it
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueDisplay {
+    /// In play order, ie previous, current, then next
+    pub songs: Vec<QueuedSong>,
+    pub current_index: usize,
+}
+
+/// The player state at the time of a dump, for bug reports
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlayerSnapshot {
+    pub shuffle: bool,
+    /// None = stopped
+    pub state: Option<PlayerStateSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlayerStateSnapshot {
+    pub playing: bool,
+    pub current: SongId,
+    pub current_path: String,
+    pub previous: Vec<SongId>,
+    pub next: Vec<SongId>,
+    /// The queue order from before shuffling; None = not shuffled
+    pub unshuffled: Option<Vec<SongId>>,
+    pub timestamp: u64,
+    pub seek_ts: Option<u64>,
+    pub elapsed_seconds: f64,
+    pub track_id: u32,
+    pub duration: Option<u64>,
+    pub predecoded_packets: usize,
+    pub preloaded_path: Option<String>,
+    pub has_audio_output: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -92,6 +277,11 @@ pub struct PlayerDisplay {
     pub song_id: SongId,
     pub playing: bool,
     pub times: ProgressTimes,
+    /// None = no output is open yet
+    pub output: Option<OutputTelemetry>,
+    /// The current song's bit depth; None = not stored, ie for lossy formats
+    pub bits_per_sample: Option<u32>,
+    pub ab_loop: Option<AbLoop>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -118,16 +308,59 @@ pub struct Player {
     media_controls: WrappedControls,
     to_preloader: Sender<PreloaderAction>,
     from_preloader: Receiver<PreloaderEffect>,
+    /// Used to save the queue for the next launch
+    db: SqlitePool,
 
     #[allow(unused)]
     #[cfg(not(target_os = "linux"))]
     device_config: CpalDeviceConfig,
 }
 
+#[derive(Debug)]
+struct PlayerSettings {
+    shuffle: bool,
+    shuffle_order: ShuffleOrder,
+    replay_gain: ReplayGainSettings,
+    /// A linear gain, applied with replaygain
+    volume: f32,
+    /// Silences the output, keeping the volume to restore
+    muted: bool,
+    /// None = the system default
+    output_device: Option<String>,
+    /// Processing between decoding and the output
+    dsp: DspPipeline,
+    /// The length of audio to buffer ahead of the device;
+    /// grows after repeated underruns
+    output_buffer_ms: usize,
+    resample_quality: ResampleQuality,
+    low_power: bool,
+    db_writes: bool,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self {
+            shuffle: false,
+            shuffle_order: ShuffleOrder::default(),
+            replay_gain: Default::default(),
+            volume: 1.0,
+            muted: false,
+            output_device: None,
+            dsp: Default::default(),
+            output_buffer_ms: DEFAULT_OUTPUT_BUFFER_MS,
+            resample_quality: ResampleQuality::default(),
+            low_power: false,
+            db_writes: true,
+        }
+    }
+}
+
 struct PlayerState {
     reader: Box<dyn FormatReader>,
     decoder: Box<dyn Decoder>,
     audio_output: Option<Box<dyn output::AudioOutput>>,
+    /// the spec the audio output was opened with
+    output_spec: Option<OutputSpec>,
     playing: bool, // false = paused
     seek_ts: Option<u64>,
     track_info: TrackInfo,
@@ -137,6 +370,14 @@ struct PlayerState {
     preloaded_content: Option<PreloadedContent>,
     /// pre-decoded packets for the currrently playing song
     predecoded_packets: VecDeque<PredecodedPacket>,
+    /// The song followed the previous one by itself, so transitions apply to its start
+    followed_previous: bool,
+    /// Silence to write before the song starts, for a gap transition
+    silence_frames: u64,
+    /// A skip waiting for the song to fade out; None = not skipping
+    skip_fade: Option<FadeOut>,
+    /// A section to repeat; None = play through
+    ab_loop: Option<AbLoop>,
 }
 
 impl std::fmt::Debug for PlayerState {
@@ -148,15 +389,31 @@ impl std::fmt::Debug for PlayerState {
 
         f.debug_struct("PlayerState")
             .field("audio_output", &audio_output)
+            .field("output_spec", &self.output_spec)
             .field("playing", &self.playing)
             .field("seek_ts", &self.seek_ts)
             .field("track_info", &self.track_info)
             .field("queue", &self.queue)
             .field("timestamp", &self.timestamp)
+            .field("ab_loop", &self.ab_loop)
             .finish()
     }
 }
 
+/// The signal spec and buffer duration used to open an audio output.
+/// An open output can be reused by the next track if these still fit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OutputSpec {
+    spec: SignalSpec,
+    duration: u64,
+}
+
+impl OutputSpec {
+    fn fits(&self, spec: &SignalSpec, duration: u64) -> bool {
+        self.spec == *spec && duration <= self.duration
+    }
+}
+
 impl From<&PlayerState> for PlayerDisplay {
     fn from(player_state: &PlayerState) -> Self {
         let song_id = player_state.queue.current.id;
@@ -171,7 +428,19 @@ impl From<&PlayerState> for PlayerDisplay {
                 ProgressTimes::ZERO
             });
 
-        Self { song_id, playing, times }
+        let output = player_state
+            .audio_output
+            .as_deref()
+            .map(AudioOutput::telemetry);
+
+        Self {
+            song_id,
+            playing,
+            times,
+            output,
+            bits_per_sample: player_state.track_info.bits_per_sample,
+            ab_loop: player_state.ab_loop,
+        }
     }
 }
 
@@ -189,36 +458,45 @@ impl Player {
         inbox: Receiver<AudioAction>,
         to_ui: Sender<AudioMessage>,
         to_self: Sender<AudioAction>,
+        db: SqlitePool,
+        replay_gain: ReplayGainSettings,
     ) -> anyhow::Result<JoinHandle<()>> {
-        let (to_preloader, preloader_inbox) =
-            flume::unbounded::<preloader::PreloaderAction>();
-        let (to_player, from_preloader) =
-            flume::unbounded::<preloader::PreloaderEffect>();
-
-        Preloader::spawn(preloader_inbox, to_player)
-            .context("failed to spawn preloader")?;
+        let mut preloader = spawn_preloader()?;
 
         let join_handle = std::thread::Builder::new()
             .name("ClefAudioPlayer".to_string())
             .spawn(move || {
-                #[allow(unused)]
-                #[cfg(not(target_os = "linux"))]
-                let device_config = CpalDeviceConfig::get_default()
-                    .expect("failed to get default device config");
-
-                let player = Player::new(
-                    inbox,
-                    to_ui.clone(),
-                    to_self,
-                    to_preloader,
-                    from_preloader,
+                // NOTE the settings are kept when a failed player is replaced,
+                // since the ui only sends them when they change
+                let mut settings = PlayerSettings { replay_gain, ..Default::default() };
+                let mut restarts = 0;
+
+                loop {
+                    let (to_preloader, from_preloader) = preloader;
+
                     #[allow(unused)]
                     #[cfg(not(target_os = "linux"))]
-                    device_config,
-                )
-                .expect("failed to create player");
-
-                if let Err(err) = player.run_loop() {
+                    let device_config =
+                        CpalDeviceConfig::get(settings.output_device.as_deref())
+                            .or_else(|_| CpalDeviceConfig::get_default())
+                            .expect("failed to get default device config");
+
+                    let player = Player::new(
+                        inbox.clone(),
+                        to_ui.clone(),
+                        to_self.clone(),
+                        to_preloader,
+                        from_preloader,
+                        db.clone(),
+                        #[allow(unused)]
+                        #[cfg(not(target_os = "linux"))]
+                        device_config,
+                    )
+                    .expect("failed to create player");
+
+                    let Err(err) = player.run_loop(&mut settings) else {
+                        return;
+                    };
                     to_ui.send(AudioMessage::AudioDied).ok();
 
                     match err {
@@ -226,12 +504,22 @@ impl Player {
                             // This can happen both during startup and shutdown,
                             // before the the ui exists or after its closed.
                             // In both cases we just wait for the app.
+                            return;
+                        }
+
+                        // the queue was saved by run_loop, to offer resuming it
+                        AudioThreadError::Other(e) if restarts < MAX_PLAYER_RESTARTS => {
+                            error!("restarting player after error: {e:#}");
+                            restarts += 1;
                         }
 
                         AudioThreadError::Other(e) => {
                             panic!("unrecovered error: {e}");
                         }
                     }
+
+                    // NOTE the old preloader exits once the old player is dropped
+                    preloader = spawn_preloader().expect("failed to respawn preloader");
                 }
             })?;
 
@@ -244,12 +532,21 @@ impl Player {
         to_self: Sender<AudioAction>,
         to_preloader: Sender<PreloaderAction>,
         from_preloader: Receiver<PreloaderEffect>,
+        db: SqlitePool,
 
         #[allow(unused)]
         #[cfg(not(target_os = "linux"))]
         device_config: CpalDeviceConfig,
     ) -> anyhow::Result<Self> {
-        let media_controls = WrappedControls::new(to_self);
+        let media_controls = WrappedControls::new(to_self, db.clone());
+
+        // the player owns the device config; the preloader gets a copy
+        #[cfg(not(target_os = "linux"))]
+        to_preloader
+            .send(PreloaderAction::SetStreamConfig(
+                device_config.config.clone(),
+            ))
+            .ok();
 
         Ok(Self {
             state: None,
@@ -258,6 +555,7 @@ impl Player {
             media_controls,
             to_preloader,
             from_preloader,
+            db,
 
             #[allow(unused)]
             #[cfg(not(target_os = "linux"))]
@@ -265,7 +563,9 @@ impl Player {
         })
     }
 
-    pub fn run_loop(self) -> Result<(), AudioThreadError> {
+    /// Settings that outlive any one queue are borrowed,
+    /// so that they outlive the player too if it fails
+    fn run_loop(self, settings: &mut PlayerSettings) -> Result<(), AudioThreadError> {
         #[allow(unused)]
         #[cfg(target_os = "linux")]
         let Player {
@@ -275,6 +575,7 @@ impl Player {
             mut media_controls,
             to_preloader,
             from_preloader,
+            db,
         } = self;
 
         #[allow(unused)]
@@ -286,9 +587,18 @@ impl Player {
             mut media_controls,
             to_preloader,
             from_preloader,
-            device_config,
+            db,
+            mut device_config,
         } = self;
 
+        // NOTE the song ids are only copied after actions or song changes,
+        // since the ui gets an update for every packet
+        let mut last_queue: Option<SavedQueue> = None;
+        let mut last_saved_at = Instant::now();
+        let mut current_play: Option<CurrentPlay> = None;
+        let mut display_throttle = DisplayThrottle::default();
+        let mut last_queue_display: Option<QueueDisplay> = None;
+
         loop {
             let preloaded = match from_preloader.try_recv() {
                 Ok(action) => Some(action),
@@ -326,16 +636,106 @@ impl Player {
             };
 
             let was_playing = state.is_some();
+            let acted = action.is_some();
 
-            let effects =
-                Self::step(state, action).context("error during player step")?;
+            #[cfg(not(target_os = "linux"))]
+            let switched_device = matches!(action, Some(AudioAction::SetOutputDevice(_)));
+
+            let effects = match Self::step(state, settings, action) {
+                Ok(effects) => effects,
+                Err(e) => {
+                    if let Some(last_queue) = last_queue.filter(|_| settings.db_writes) {
+                        let crashed_queue = SavedQueue { from_crash: true, ..last_queue };
+                        persist_queue(&db, Some(&crashed_queue));
+                    }
+
+                    return Err(e.context("error during player step").into());
+                }
+            };
+
+            if acted || effects.audio_message.is_some() {
+                match (&effects.player_state, &mut last_queue) {
+                    (Some(state), Some(last))
+                        if !acted
+                            && last.song_ids.get(last.current_index)
+                                == Some(&state.queue.current.id) =>
+                    {
+                        last.elapsed_seconds = state.elapsed_seconds();
+                        last.playing = state.playing;
+
+                        if settings.db_writes
+                            && last_saved_at.elapsed() >= QUEUE_SAVE_INTERVAL
+                        {
+                            persist_queue(&db, Some(last));
+                            last_saved_at = Instant::now();
+                        }
+                    }
+
+                    (state, _) => {
+                        last_queue = state.as_ref().map(PlayerState::saved_queue);
+                        if settings.db_writes {
+                            persist_queue(&db, last_queue.as_ref());
+                            last_saved_at = Instant::now();
+                        }
+
+                        let snapshot = PlayerSnapshot {
+                            shuffle: settings.shuffle,
+                            state: state.as_ref().map(PlayerState::snapshot),
+                        };
+                        crash_report::set_state_summary("audio", format!("{snapshot:?}"));
+
+                        let queue_display =
+                            state.as_ref().map(PlayerState::queue_display);
+                        if queue_display != last_queue_display {
+                            let update = queue_display.clone().map(Box::new);
+                            to_ui.send(AudioMessage::QueueUpdate(update)).ok();
+                            last_queue_display = queue_display;
+                        }
+
+                        #[cfg(target_os = "linux")]
+                        if let Some(state) = state {
+                            media_controls
+                                .set_track_list(controls_track_list(&state.queue));
+                        }
+                    }
+                }
+            }
+
+            if settings.db_writes {
+                history::track_play(
+                    &db,
+                    &mut current_play,
+                    effects.player_state.as_ref(),
+                );
+            }
+
+            for (song_id, reason) in &effects.unplayable {
+                if settings.db_writes {
+                    mark_unplayable(&db, *song_id);
+                }
+                let unplayable = AudioMessage::SongUnplayable(*song_id, reason.clone());
+                to_ui.send(unplayable).ok();
+            }
+
+            if let Some(AudioMessage::VolumeChanged { volume, muted }) =
+                &effects.audio_message
+            {
+                let volume = if *muted { 0.0 } else { *volume };
+                media_controls.set_volume(f64::from(volume));
+            }
 
             if let Some(message) = effects.audio_message {
-                to_ui.send(message).ok();
+                if display_throttle.should_send(
+                    &message,
+                    settings.low_power,
+                    Instant::now(),
+                ) {
+                    to_ui.send(message).ok();
+                }
             }
 
             if let Some(metadata) = &effects.metadata {
-                media_controls.set_metadata(metadata.into());
+                media_controls.set_metadata(metadata);
             }
 
             if let Some(playback) = effects.playback {
@@ -346,6 +746,25 @@ impl Player {
                 media_controls.deinit();
             }
 
+            // keep the preloader's copy in step with the new device
+            #[cfg(not(target_os = "linux"))]
+            if switched_device {
+                let device_name = settings.output_device.as_deref();
+                let switched = CpalDeviceConfig::get(device_name)
+                    .or_else(|_| CpalDeviceConfig::get_default());
+
+                match switched {
+                    Ok(switched) => {
+                        let stream_config = switched.config.clone();
+                        to_preloader
+                            .send(PreloaderAction::SetStreamConfig(stream_config))
+                            .ok();
+                        device_config = switched;
+                    }
+                    Err(e) => error!("failed to get output device config: {e}"),
+                }
+            }
+
             if let Some(preload) = effects.preload {
                 to_preloader.send(preload).ok();
             }
@@ -354,36 +773,71 @@ impl Player {
         }
     }
 
-    fn step(state: Option<PlayerState>, msg: Option<AudioAction>) -> StepResult {
+    fn step(
+        state: Option<PlayerState>,
+        settings: &mut PlayerSettings,
+        msg: Option<AudioAction>,
+    ) -> StepResult {
         use AudioAction::*;
 
         match (msg, state) {
-            (Some(PlayQueue(queue)), _any_state) => {
-                let player_state = PlayerState::play_queue(*queue)?;
+            (Some(PlayQueue(queue)), any_state) => {
+                let queue = match_shuffle_setting(*queue, settings);
+
+                let mut unplayable = Vec::new();
+                let Some(mut player_state) =
+                    PlayerState::play_queue(queue, &mut unplayable)
+                else {
+                    return Ok(AudioEffects { unplayable, ..publish_stop() });
+                };
+                if let Some(old_state) = any_state {
+                    player_state
+                        .keep_output(old_state.audio_output, old_state.output_spec);
+                }
+
                 let mut effects = publish_display_update(player_state);
                 effects.preload_next();
+                effects.unplayable = unplayable;
 
                 Ok(effects)
             }
 
+            (Some(ResumeQueue(queue, seconds)), any_state) => {
+                Self::play_queue_from(any_state, settings, queue, seconds, true)
+            }
+
+            (Some(RestoreState(queue, seconds)), any_state) => {
+                Self::play_queue_from(any_state, settings, queue, seconds, false)
+            }
+
             (Some(Pause), Some(mut player_state)) if player_state.playing => {
-                player_state.playing = false;
+                player_state.set_playing(false);
                 Ok(publish_display_update(player_state))
             }
             (Some(Pause), state) => Ok(AudioEffects::none(state)),
 
             (Some(PlayPaused), Some(mut player_state)) if !player_state.playing => {
-                player_state.playing = true;
+                player_state.set_playing(true);
                 Ok(publish_display_update(player_state))
             }
             (Some(PlayPaused), state) => Ok(AudioEffects::none(state)),
 
             (Some(Toggle), Some(mut player_state)) => {
-                player_state.playing = !player_state.playing;
+                player_state.set_playing(!player_state.playing);
                 Ok(publish_display_update(player_state))
             }
             (Some(Toggle), None) => Ok(AudioEffects::none(None)),
 
+            (Some(Stop), Some(_)) => Ok(publish_stop()),
+            (Some(Stop), None) => Ok(AudioEffects::none(None)),
+
+            (Some(Forward), Some(mut player_state)) if player_state.can_fade_out() => {
+                let elapsed_seconds = player_state.elapsed_seconds();
+                player_state.skip_fade = Some(FadeOut::skip(elapsed_seconds));
+
+                Ok(AudioEffects::none(Some(player_state)))
+            }
+            // NOTE this includes a second skip during the fade-out
             (Some(Forward), Some(player_state)) => {
                 let mut effects = player_state.forward()?;
                 effects.preload_next();
@@ -405,6 +859,44 @@ impl Player {
             }
             (Some(Back), None) => Ok(AudioEffects::none(None)),
 
+            (Some(GoTo(position)), Some(player_state)) => {
+                let mut effects = player_state.go_to(position)?;
+                effects.preload_next();
+
+                Ok(effects)
+            }
+            (Some(GoTo(_)), None) => Ok(AudioEffects::none(None)),
+
+            (Some(RemoveFromQueue(position)), Some(mut player_state)) => {
+                let up_next_index = player_state.queue.previous.len() + 1;
+                if player_state.queue.remove(position).is_none() {
+                    warn!("no removable song at queue position {position}");
+                }
+
+                let mut effects = AudioEffects::none(Some(player_state));
+                if position == up_next_index {
+                    effects.preload_next();
+                }
+
+                Ok(effects)
+            }
+            (Some(RemoveFromQueue(_)), None) => Ok(AudioEffects::none(None)),
+
+            (Some(MoveInQueue(from, to)), Some(mut player_state)) => {
+                let up_next_index = player_state.queue.previous.len() + 1;
+                if !player_state.queue.move_upcoming(from, to) {
+                    warn!("can't move queue position {from} to {to}");
+                }
+
+                let mut effects = AudioEffects::none(Some(player_state));
+                if from == up_next_index || to == up_next_index {
+                    effects.preload_next();
+                }
+
+                Ok(effects)
+            }
+            (Some(MoveInQueue(..)), None) => Ok(AudioEffects::none(None)),
+
             (Some(Seek(proportion)), Some(player_state)) => {
                 let Some(ProgressTimes { total, .. }) = player_state
                     .track_info
@@ -423,178 +915,751 @@ impl Player {
             }
             (Some(Seek(_)), None) => Ok(AudioEffects::none(None)),
 
-            (None, Some(player_state)) if player_state.playing => {
-                let before = player_state.queue.current.id;
+            (Some(SeekBy(offset)), Some(player_state)) => {
+                // NOTE a seek that hasn't been decoded yet counts, for repeated presses
+                let Some(ProgressTimes { elapsed, total, .. }) = player_state
+                    .track_info
+                    .progress_times(player_state.optimistic_timestamp())
+                else {
+                    error!("missing track info: {:#?}", player_state.track_info);
+                    return Ok(publish_seek_complete(player_state));
+                };
 
-                let mut effects = player_state.continue_playing()?;
+                let elapsed_seconds = elapsed.seconds as f32 + elapsed.frac as f32;
+                let total_seconds = total.seconds as f32 + total.frac as f32;
 
-                let after = effects
-                    .player_state
-                    .as_ref()
-                    .map(|state| state.queue.current.id);
+                let offset_seconds = match offset {
+                    SeekOffset::Seconds(seconds) => seconds,
+                    SeekOffset::Proportion(proportion) => total_seconds * proportion,
+                };
+                let seek_seconds =
+                    (elapsed_seconds + offset_seconds).clamp(0.0, total_seconds);
 
-                // if we started playing a new song,
-                // then try to preload the one after that
-                if matches!(after, Some(after) if before != after) {
-                    effects.preload_next();
-                }
+                let player_state = player_state.seek_to(seek_seconds);
 
-                Ok(effects)
+                Ok(publish_seek_complete(player_state))
             }
-            (None, state) => Ok(AudioEffects::none(state)),
-        }
-    }
-}
+            (Some(SeekBy(_)), None) => Ok(AudioEffects::none(None)),
 
-type StepResult = anyhow::Result<AudioEffects>;
+            (Some(SeekTo(seconds)), Some(player_state)) => {
+                let Some(ProgressTimes { total, .. }) = player_state
+                    .track_info
+                    .progress_times(player_state.timestamp)
+                else {
+                    error!("missing track info: {:#?}", player_state.track_info);
+                    return Ok(publish_seek_complete(player_state));
+                };
 
-#[derive(Debug)]
-struct AudioEffects {
-    /// The new player state; this is 'required'; None = stopped
-    player_state: Option<PlayerState>,
-    /// ui message to send
-    audio_message: Option<AudioMessage>,
-    /// metadata to publish to media controls
-    metadata: Option<ControlsMetadata>,
-    /// playback & progress to publish to media controls
-    playback: Option<MediaPlayback>,
-    preload: Option<PreloaderAction>,
-}
+                let total_seconds = total.seconds as f32 + total.frac as f32;
+                let player_state =
+                    player_state.seek_to(seconds.clamp(0.0, total_seconds));
 
-impl AudioEffects {
-    /// preserve the player state, doing nothing else
-    fn none(player_state: Option<PlayerState>) -> Self {
-        Self {
-            player_state,
-            audio_message: None,
-            metadata: None,
-            playback: None,
-            preload: None,
-        }
-    }
+                Ok(publish_seek_complete(player_state))
+            }
+            (Some(SeekTo(_)), None) => Ok(AudioEffects::none(None)),
 
-    /// add a preload action for the next track if there is one
-    fn preload_next(&mut self) {
-        if let Some(up_next) = self.player_state.as_ref().and_then(PlayerState::up_next) {
-            self.preload = Some(PreloaderAction::Load(up_next.path.clone()));
-        }
-    }
-}
+            (Some(MarkLoopPoint), Some(mut player_state)) => {
+                let timestamp = player_state.optimistic_timestamp();
+                let Some(ProgressTimes { elapsed, .. }) =
+                    player_state.track_info.progress_times(timestamp)
+                else {
+                    error!("missing track info: {:#?}", player_state.track_info);
+                    return Ok(AudioEffects::none(Some(player_state)));
+                };
 
-impl PlayerState {
-    fn play_preloaded(queue: Queue<QueuedSong>, preloaded: PreloadedContent) -> Self {
-        Self {
-            queue,
-            reader: preloaded.reader,
-            decoder: preloaded.decoder,
-            track_info: preloaded.track_info,
-            seek_ts: None,
-            audio_output: None,
-            playing: true,
-            timestamp: 0,
-            preloaded_content: None,
-            predecoded_packets: preloaded.predecoded_packets,
-        }
-    }
+                let elapsed_seconds = elapsed.seconds as f64 + elapsed.frac;
+                player_state.ab_loop =
+                    AbLoop::mark(player_state.ab_loop, elapsed_seconds);
 
-    // This is based on the main loop in the symphonia-play example
-    fn play_queue(queue: Queue<QueuedSong>) -> anyhow::Result<Self> {
-        let mut hint = Hint::new();
+                Ok(publish_display_update(player_state))
+            }
+            (Some(MarkLoopPoint), None) => Ok(AudioEffects::none(None)),
 
-        // Provide the file extension as a hint.
-        if let Some(extension) = queue.current.path.extension() {
-            hint.with_extension(extension);
-        }
+            (Some(SetShuffle(shuffle)), state) => {
+                settings.shuffle = shuffle;
 
-        let file = File::open(&queue.current.path)
-            .with_context(|| format!("file not found: {}", &queue.current.path))?;
+                let Some(mut player_state) = state else {
+                    return Ok(AudioEffects::none(None));
+                };
 
-        let source = Box::new(file);
+                player_state.queue = if shuffle {
+                    shuffle_queue(player_state.queue, settings.shuffle_order)
+                } else {
+                    player_state.queue.unshuffled()
+                };
 
-        let mss = MediaSourceStream::new(source, Default::default());
+                let mut effects = AudioEffects::none(Some(player_state));
+                effects.preload_next();
 
-        let format_opts = FormatOptions {
-            enable_gapless: true,
-            ..Default::default()
-        };
+                Ok(effects)
+            }
 
-        let metadata_opts: MetadataOptions = Default::default();
+            (Some(SetShuffleOrder(order)), state) => {
+                settings.shuffle_order = order;
 
-        let probed = symphonia::default::get_probe()
-            .format(&hint, mss, &format_opts, &metadata_opts)
-            .context("The input was not supported by any format reader")?;
+                match state {
+                    Some(mut player_state) if settings.shuffle => {
+                        player_state.queue = shuffle_queue(player_state.queue, order);
 
-        let track =
-            first_supported_track(probed.format.tracks()).context("no playable track")?;
-        let track_info: TrackInfo = track.into();
+                        let mut effects = AudioEffects::none(Some(player_state));
+                        effects.preload_next();
 
-        // default decode opts (no verify)
-        let decoder = symphonia::default::get_codecs()
-            .make(&track.codec_params, &Default::default())
-            .context("making decoder")?;
+                        Ok(effects)
+                    }
+                    state => Ok(AudioEffects::none(state)),
+                }
+            }
 
-        Ok(Self {
-            reader: probed.format,
-            seek_ts: None,
-            audio_output: None,
-            playing: true,
-            timestamp: 0,
-            decoder,
-            track_info,
-            queue,
-            preloaded_content: None,
-            predecoded_packets: Default::default(),
-        })
-    }
+            (Some(Enqueue(song)), Some(mut player_state)) => {
+                let had_next = player_state.up_next().is_some();
+                player_state.queue.enqueue(*song);
 
-    fn seek_to(mut self, target: f32) -> Self {
-        let seek_to = SeekTo::Time {
-            time: Time::from(target),
-            track_id: Some(self.track_info.id),
-        };
+                let mut effects = AudioEffects::none(Some(player_state));
+                if !had_next {
+                    effects.preload_next();
+                }
 
-        self.seek_ts = match self.reader.seek(SeekMode::Accurate, seek_to) {
-            Ok(seeked_to) => Some(seeked_to.required_ts),
-            Err(e) => {
-                error!("seek error: {e}");
-                None
+                Ok(effects)
             }
-        };
-
-        self
-    }
 
-    fn forward(mut self) -> StepResult {
-        match self.queue.try_forward() {
-            Ok(new_queue) => {
-                let mut new_state = match self.preloaded_content {
-                    // hit preload
-                    Some(preloaded) if preloaded.path == new_queue.current.path => {
-                        trace!("hit preload");
-                        Self::play_preloaded(new_queue, preloaded)
-                    }
+            (Some(EnqueueNext(song)), Some(mut player_state)) => {
+                player_state.queue.enqueue_next(*song);
 
-                    // missed preload
-                    _ => {
-                        info!("missed preload");
-                        Self::play_queue(new_queue)?
-                    }
-                };
+                let mut effects = AudioEffects::none(Some(player_state));
+                effects.preload_next();
 
-                new_state.playing = self.playing;
+                Ok(effects)
+            }
 
-                Ok(publish_display_update(new_state))
+            (Some(Enqueue(song) | EnqueueNext(song)), None) => {
+                let queue = Queue::new(Vec::new(), *song, VecDeque::new());
+                Self::step(None, settings, Some(PlayQueue(Box::new(queue))))
             }
 
-            Err(_old_queue) => {
-                if let Some(output) = &mut self.audio_output {
-                    output.flush();
+            (Some(EnqueueAll(songs)), Some(mut player_state)) => {
+                let had_next = player_state.up_next().is_some();
+                for song in songs {
+                    player_state.queue.enqueue(song);
                 }
 
-                Ok(publish_stop())
-            }
-        }
-    }
+                let mut effects = AudioEffects::none(Some(player_state));
+                if !had_next {
+                    effects.preload_next();
+                }
+
+                Ok(effects)
+            }
+
+            (Some(EnqueueAll(songs)), None) => {
+                let mut songs = songs.into_iter();
+                let Some(first) = songs.next() else {
+                    return Ok(AudioEffects::none(None));
+                };
+
+                let queue = Queue::new(Vec::new(), first, songs.collect());
+                Self::step(None, settings, Some(PlayQueue(Box::new(queue))))
+            }
+
+            (Some(SetOutputDevice(device_name)), state) => {
+                settings.output_device = device_name;
+
+                let Some(mut player_state) = state else {
+                    return Ok(AudioEffects::none(None));
+                };
+
+                // NOTE continue_playing reopens the output on the new device
+                player_state.close_output();
+
+                Ok(AudioEffects::none(Some(player_state)))
+            }
+
+            (Some(SetResampleQuality(quality)), state) => {
+                // NOTE this is sent with every settings save, so only a change reopens
+                if settings.resample_quality == quality {
+                    return Ok(AudioEffects::none(state));
+                }
+                settings.resample_quality = quality;
+
+                let Some(mut player_state) = state else {
+                    return Ok(AudioEffects::none(None));
+                };
+                player_state.close_output();
+
+                Ok(AudioEffects::none(Some(player_state)))
+            }
+
+            (Some(SetEqualizer(curve)), state) => {
+                settings.dsp.equalizer.set_curve(curve);
+                Ok(AudioEffects::none(state))
+            }
+
+            (Some(SetTransition(kind)), state) => {
+                settings.dsp.transitions.set_kind(kind);
+                Ok(AudioEffects::none(state))
+            }
+
+            (Some(SetLowPower(low_power)), state) => {
+                settings.low_power = low_power;
+                Ok(AudioEffects::none(state))
+            }
+
+            (Some(SetDbWrites(db_writes)), state) => {
+                settings.db_writes = db_writes;
+                Ok(AudioEffects::none(state))
+            }
+
+            (Some(SetSampleTap(ring)), state) => {
+                settings.dsp.sample_tap.set_ring(ring);
+                Ok(AudioEffects::none(state))
+            }
+
+            (Some(SetReplayGain(replay_gain)), state) => {
+                settings.replay_gain = replay_gain;
+                Ok(AudioEffects::none(state))
+            }
+
+            (Some(SetVolume(volume)), state) => {
+                settings.volume = volume.clamp(0.0, 1.0);
+                settings.muted = false;
+                Ok(volume_changed(settings, state))
+            }
+
+            (Some(SetMuted(muted)), state) => {
+                settings.muted = muted;
+                Ok(volume_changed(settings, state))
+            }
+
+            (Some(DumpState), state) => {
+                let snapshot = PlayerSnapshot {
+                    shuffle: settings.shuffle,
+                    state: state.as_ref().map(PlayerState::snapshot),
+                };
+
+                let mut effects = AudioEffects::none(state);
+                effects.audio_message = Some(AudioMessage::StateDump(Box::new(snapshot)));
+
+                Ok(effects)
+            }
+
+            (Some(ActivatePlaylist(playlist_id)), state) => {
+                let mut effects = AudioEffects::none(state);
+                effects.audio_message =
+                    Some(AudioMessage::PlaylistActivated(playlist_id));
+
+                Ok(effects)
+            }
+
+            (None, Some(player_state)) if player_state.playing => {
+                let before = player_state.queue.current.id;
+
+                let mut effects = player_state.continue_playing(settings)?;
+
+                let after = effects
+                    .player_state
+                    .as_ref()
+                    .map(|state| state.queue.current.id);
+
+                // if we started playing a new song,
+                // then try to preload the one after that
+                if matches!(after, Some(after) if before != after) {
+                    effects.preload_next();
+                }
+
+                Ok(effects)
+            }
+            (None, state) => Ok(AudioEffects::none(state)),
+        }
+    }
+}
+
+impl Player {
+    fn play_queue_from(
+        state: Option<PlayerState>,
+        settings: &mut PlayerSettings,
+        queue: Box<Queue<QueuedSong>>,
+        seconds: f32,
+        playing: bool,
+    ) -> StepResult {
+        let effects = Self::step(state, settings, Some(AudioAction::PlayQueue(queue)))?;
+        let Some(mut player_state) = effects.player_state else {
+            return Ok(effects);
+        };
+
+        // NOTE the position was in a song that was skipped
+        let seconds = if effects.unplayable.is_empty() {
+            seconds
+        } else {
+            0.0
+        };
+
+        player_state.set_playing(playing);
+        let mut started = publish_seek_complete(player_state.seek_to(seconds));
+        started.preload = effects.preload;
+        started.unplayable = effects.unplayable;
+
+        Ok(started)
+    }
+}
+
+/// Tells the ui about a new volume, which may have come from the os media controls
+fn volume_changed(settings: &PlayerSettings, state: Option<PlayerState>) -> AudioEffects {
+    let message = AudioMessage::VolumeChanged {
+        volume: settings.volume,
+        muted: settings.muted,
+    };
+
+    AudioEffects {
+        audio_message: Some(message),
+        ..AudioEffects::none(state)
+    }
+}
+
+/// Opens the chosen output device, or the default if the chosen one is gone
+fn open_output(
+    spec: SignalSpec,
+    duration: u64,
+    settings: &PlayerSettings,
+) -> Option<Box<dyn AudioOutput>> {
+    let device_name = settings.output_device.as_deref();
+    let buffer_ms = settings.output_buffer_ms;
+    let quality = settings.resample_quality;
+
+    match output::try_open(spec, duration, device_name, buffer_ms, quality) {
+        Ok(audio_output) => Some(audio_output),
+
+        Err(_) if device_name.is_some() => {
+            warn!("unable to open {device_name:?}, falling back to the default device");
+            output::try_open(spec, duration, None, buffer_ms, quality).ok()
+        }
+
+        Err(_) => None,
+    }
+}
+
+/// The output devices that can be chosen with AudioAction::SetOutputDevice
+/// NOTE this is empty on linux, where pulse simple can't list sinks
+pub fn output_device_names() -> Vec<String> {
+    #[cfg(target_os = "linux")]
+    let names = Vec::new();
+
+    #[cfg(not(target_os = "linux"))]
+    let names = device_config::output_device_names();
+
+    names
+}
+
+/// Failed players replaced before giving up, for the life of the app
+const MAX_PLAYER_RESTARTS: usize = 3;
+
+/// How often to save the position in the current song during playback
+const QUEUE_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often to send progress in low power mode;
+/// otherwise the ui gets an update for every packet
+const LOW_POWER_DISPLAY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Skips progress updates in low power mode, when nothing but the time has changed
+#[derive(Debug, Default)]
+struct DisplayThrottle {
+    /// The song, whether it was playing, and when
+    last_sent: Option<(SongId, bool, Instant)>,
+}
+
+impl DisplayThrottle {
+    fn should_send(
+        &mut self,
+        message: &AudioMessage,
+        low_power: bool,
+        now: Instant,
+    ) -> bool {
+        let AudioMessage::DisplayUpdate(Some(display)) = message else {
+            self.last_sent = None;
+            return true;
+        };
+
+        let unchanged = match self.last_sent {
+            Some((song_id, playing, sent_at)) => {
+                song_id == display.song_id
+                    && playing == display.playing
+                    && now.duration_since(sent_at) < LOW_POWER_DISPLAY_INTERVAL
+            }
+            None => false,
+        };
+        if low_power && unchanged {
+            return false;
+        }
+
+        self.last_sent = Some((display.song_id, display.playing, now));
+        true
+    }
+}
+
+const DEFAULT_OUTPUT_BUFFER_MS: usize = 200;
+const MAX_OUTPUT_BUFFER_MS: usize = 1600;
+/// The output buffer doubles after this many underruns on one output
+const UNDERRUNS_BEFORE_GROWING: u64 = 3;
+
+/// A reader and decoder for a file's first supported track
+struct OpenedSong {
+    reader: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_info: TrackInfo,
+}
+
+// This is based on the main loop in the symphonia-play example
+fn open_song(path: &Utf8Path) -> anyhow::Result<OpenedSong> {
+    let mut hint = Hint::new();
+
+    // Provide the file extension as a hint.
+    if let Some(extension) = path.extension() {
+        hint.with_extension(extension);
+    }
+
+    let file = File::open(path).with_context(|| format!("file not found: {path}"))?;
+
+    let source = Box::new(file);
+
+    let mss = MediaSourceStream::new(source, Default::default());
+
+    let format_opts = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+
+    let metadata_opts: MetadataOptions = Default::default();
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, mss, &format_opts, &metadata_opts)
+        .context("The input was not supported by any format reader")?;
+
+    let track =
+        first_supported_track(probed.format.tracks()).context("no playable track")?;
+    let track_info: TrackInfo = track.into();
+
+    // default decode opts (no verify)
+    let decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &Default::default())
+        .context("making decoder")?;
+
+    Ok(OpenedSong {
+        reader: probed.format,
+        decoder,
+        track_info,
+    })
+}
+
+fn spawn_preloader(
+) -> anyhow::Result<(Sender<PreloaderAction>, Receiver<PreloaderEffect>)> {
+    let (to_preloader, preloader_inbox) = flume::unbounded::<PreloaderAction>();
+    let (to_player, from_preloader) = flume::unbounded::<PreloaderEffect>();
+
+    Preloader::spawn(preloader_inbox, to_player).context("failed to spawn preloader")?;
+
+    Ok((to_preloader, from_preloader))
+}
+
+/// NOTE songs from outside the library are only skipped
+fn mark_unplayable(db: &SqlitePool, song_id: SongId) {
+    if !song_id.is_saved() {
+        return;
+    }
+
+    let marked = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        conn.immediate_transaction(|tx| queries::set_song_unplayable(tx, song_id))
+            .map_err(anyhow::Error::from)
+    });
+    if let Err(e) = marked {
+        error!("failed to mark song unplayable: {e}");
+    }
+}
+
+/// Saves the queue for the next launch, or clears it when stopped
+fn persist_queue(db: &SqlitePool, saved_queue: Option<&SavedQueue>) {
+    // NOTE songs from outside the library can't be saved;
+    // this keeps the last library queue for the next launch instead
+    if saved_queue.is_some_and(|queue| !queue.song_ids.iter().all(SongId::is_saved)) {
+        return;
+    }
+
+    let persisted = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        conn.immediate_transaction(|tx| match saved_queue {
+            Some(saved_queue) => queries::save_queue(tx, saved_queue),
+            None => queries::clear_saved_queue(tx),
+        })
+        .map_err(anyhow::Error::from)
+    });
+
+    if let Err(e) = persisted {
+        error!("failed to save queue: {e}");
+    }
+}
+
+type StepResult = anyhow::Result<AudioEffects>;
+
+#[derive(Debug)]
+struct AudioEffects {
+    /// The new player state; this is 'required'; None = stopped
+    player_state: Option<PlayerState>,
+    /// ui message to send
+    audio_message: Option<AudioMessage>,
+    /// metadata to publish to media controls
+    metadata: Option<ControlsMetadata>,
+    /// playback & progress to publish to media controls
+    playback: Option<MediaPlayback>,
+    preload: Option<PreloaderAction>,
+    /// songs that couldn't be opened, and why; see PlayerState::play_queue
+    unplayable: Vec<(SongId, String)>,
+}
+
+impl AudioEffects {
+    /// preserve the player state, doing nothing else
+    fn none(player_state: Option<PlayerState>) -> Self {
+        Self {
+            player_state,
+            audio_message: None,
+            metadata: None,
+            playback: None,
+            preload: None,
+            unplayable: Vec::new(),
+        }
+    }
+
+    /// add a preload action for the next track if there is one
+    fn preload_next(&mut self) {
+        if let Some(up_next) = self.player_state.as_ref().and_then(PlayerState::up_next) {
+            self.preload = Some(PreloaderAction::Load(up_next.path.clone()));
+        }
+    }
+}
+
+impl PlayerState {
+    fn saved_queue(&self) -> SavedQueue {
+        let queue = &self.queue;
+
+        let mut song_ids: Vec<SongId> = queue.previous.iter().map(|s| s.id).collect();
+        song_ids.push(queue.current.id);
+        song_ids.extend(queue.next.iter().map(|s| s.id));
+
+        let unshuffled_song_ids = queue
+            .unshuffled
+            .as_ref()
+            .map(|unshuffled| unshuffled.iter().map(|s| s.id).collect());
+
+        SavedQueue {
+            song_ids,
+            current_index: queue.previous.len(),
+            elapsed_seconds: self.elapsed_seconds(),
+            from_crash: false,
+            playing: self.playing,
+            unshuffled_song_ids,
+        }
+    }
+
+    fn queue_display(&self) -> QueueDisplay {
+        let queue = &self.queue;
+
+        QueueDisplay {
+            songs: queue
+                .previous
+                .iter()
+                .chain(std::iter::once(&queue.current))
+                .chain(queue.next.iter())
+                .cloned()
+                .collect(),
+            current_index: queue.previous.len(),
+        }
+    }
+
+    fn snapshot(&self) -> PlayerStateSnapshot {
+        let queue = &self.queue;
+
+        PlayerStateSnapshot {
+            playing: self.playing,
+            current: queue.current.id,
+            current_path: queue.current.path.to_string(),
+            previous: queue.previous.iter().map(|s| s.id).collect(),
+            next: queue.next.iter().map(|s| s.id).collect(),
+            unshuffled: queue
+                .unshuffled
+                .as_ref()
+                .map(|original| original.iter().map(|s| s.id).collect()),
+            timestamp: self.timestamp,
+            seek_ts: self.seek_ts,
+            elapsed_seconds: self.elapsed_seconds(),
+            track_id: self.track_info.id,
+            duration: self.track_info.duration,
+            predecoded_packets: self.predecoded_packets.len(),
+            preloaded_path: self
+                .preloaded_content
+                .as_ref()
+                .map(|preloaded| preloaded.path.to_string()),
+            has_audio_output: self.audio_output.is_some(),
+        }
+    }
+
+    fn elapsed_seconds(&self) -> f64 {
+        self.track_info
+            .progress_times(self.timestamp)
+            .map(|times| times.elapsed.seconds as f64 + times.elapsed.frac)
+            .unwrap_or_default()
+    }
+
+    fn total_seconds(&self) -> f64 {
+        self.track_info
+            .progress_times(self.timestamp)
+            .map(|times| times.total.seconds as f64 + times.total.frac)
+            .unwrap_or_default()
+    }
+
+    fn play_preloaded(queue: Queue<QueuedSong>, preloaded: PreloadedContent) -> Self {
+        Self {
+            queue,
+            reader: preloaded.reader,
+            decoder: preloaded.decoder,
+            track_info: preloaded.track_info,
+            seek_ts: None,
+            audio_output: None,
+            output_spec: None,
+            playing: true,
+            timestamp: 0,
+            preloaded_content: None,
+            predecoded_packets: preloaded.predecoded_packets,
+            followed_previous: false,
+            silence_frames: 0,
+            skip_fade: None,
+            ab_loop: None,
+        }
+    }
+
+    /// Opens the queue's current song, moving forward past any that can't be opened,
+    /// ie deleted files or unsupported codecs; None when none of the rest can be
+    fn play_queue(
+        mut queue: Queue<QueuedSong>,
+        unplayable: &mut Vec<(SongId, String)>,
+    ) -> Option<Self> {
+        loop {
+            match open_song(&queue.current.path) {
+                Ok(OpenedSong { reader, decoder, track_info }) => {
+                    return Some(Self {
+                        reader,
+                        seek_ts: None,
+                        audio_output: None,
+                        output_spec: None,
+                        playing: true,
+                        timestamp: 0,
+                        decoder,
+                        track_info,
+                        queue,
+                        preloaded_content: None,
+                        predecoded_packets: Default::default(),
+                        followed_previous: false,
+                        silence_frames: 0,
+                        skip_fade: None,
+                        ab_loop: None,
+                    });
+                }
+
+                Err(e) => {
+                    warn!("skipping unplayable song: {} {e:#}", queue.current.path);
+                    unplayable.push((queue.current.id, format!("{e:#}")));
+                    queue = queue.try_forward().ok()?;
+                }
+            }
+        }
+    }
+
+    /// A skip fades out while playing, when there's a position to fade from
+    fn can_fade_out(&self) -> bool {
+        self.playing
+            && self.skip_fade.is_none()
+            && self.seek_ts.is_none()
+            && self.audio_output.is_some()
+            && self.track_info.progress_times(self.timestamp).is_some()
+    }
+
+    /// Also tells the output, so that it doesn't count a pause as an underrun
+    fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+
+        if let Some(output) = &mut self.audio_output {
+            output.set_paused(!playing);
+        }
+    }
+
+    /// Flushes and drops the output; the next packet reopens it
+    fn close_output(&mut self) {
+        if let Some(mut old_output) = self.audio_output.take() {
+            old_output.flush();
+        }
+        self.output_spec = None;
+    }
+
+    /// Keep the previous state's audio output open, to avoid a gap between tracks.
+    /// If the new track has a different spec, continue_playing will reopen it.
+    fn keep_output(
+        &mut self,
+        audio_output: Option<Box<dyn AudioOutput>>,
+        output_spec: Option<OutputSpec>,
+    ) {
+        self.audio_output = audio_output;
+        self.output_spec = output_spec;
+    }
+
+    fn seek_to(mut self, target: f32) -> Self {
+        self.skip_fade = None;
+
+        let seek_to = SeekTo::Time {
+            time: Time::from(target),
+            track_id: Some(self.track_info.id),
+        };
+
+        self.seek_ts = match self.reader.seek(SeekMode::Accurate, seek_to) {
+            Ok(seeked_to) => Some(seeked_to.required_ts),
+            Err(e) => {
+                error!("seek error: {e}");
+                None
+            }
+        };
+
+        self
+    }
+
+    fn forward(mut self) -> StepResult {
+        let mut unplayable = Vec::new();
+        let new_state = match self.queue.try_forward() {
+            Ok(new_queue) => match self.preloaded_content.take() {
+                // hit preload
+                Some(preloaded) if preloaded.path == new_queue.current.path => {
+                    trace!("hit preload");
+                    Some(Self::play_preloaded(new_queue, preloaded))
+                }
+
+                // missed preload
+                _ => {
+                    info!("missed preload");
+                    Self::play_queue(new_queue, &mut unplayable)
+                }
+            },
+
+            Err(_old_queue) => None,
+        };
+
+        let Some(mut new_state) = new_state else {
+            if let Some(output) = &mut self.audio_output {
+                output.flush();
+            }
+
+            return Ok(AudioEffects { unplayable, ..publish_stop() });
+        };
+
+        new_state.playing = self.playing;
+        new_state.keep_output(self.audio_output, self.output_spec);
+
+        let mut effects = publish_display_update(new_state);
+        effects.unplayable = unplayable;
+
+        Ok(effects)
+    }
 
     fn back(mut self) -> StepResult {
         let past_two_seconds = self
@@ -606,10 +1671,8 @@ impl PlayerState {
         if !past_two_seconds {
             match self.queue.try_back() {
                 Ok(new_queue) => {
-                    let mut new_state = Self::play_queue(new_queue)?;
-                    new_state.playing = self.playing;
-
-                    return Ok(publish_display_update(new_state));
+                    let output = (self.audio_output, self.output_spec);
+                    return Ok(Self::switch_to(new_queue, self.playing, output));
                 }
                 Err(old_queue) => {
                     self.queue = old_queue;
@@ -623,10 +1686,53 @@ impl PlayerState {
         Ok(publish_display_update(new_state))
     }
 
+    fn go_to(mut self, position: usize) -> StepResult {
+        match self.queue.try_go_to(position) {
+            Ok(new_queue) => {
+                let output = (self.audio_output, self.output_spec);
+                Ok(Self::switch_to(new_queue, self.playing, output))
+            }
+            Err(old_queue) => {
+                warn!("no song at queue position {position}");
+                self.queue = old_queue;
+
+                Ok(AudioEffects::none(Some(self)))
+            }
+        }
+    }
+
+    /// Plays another position in the same queue, keeping the output open;
+    /// see play_queue for songs that can't be opened
+    fn switch_to(
+        new_queue: Queue<QueuedSong>,
+        playing: bool,
+        (audio_output, output_spec): (Option<Box<dyn AudioOutput>>, Option<OutputSpec>),
+    ) -> AudioEffects {
+        let mut unplayable = Vec::new();
+        let Some(mut new_state) = Self::play_queue(new_queue, &mut unplayable) else {
+            if let Some(mut output) = audio_output {
+                output.flush();
+            }
+
+            return AudioEffects { unplayable, ..publish_stop() };
+        };
+        new_state.playing = playing;
+        new_state.keep_output(audio_output, output_spec);
+
+        let mut effects = publish_display_update(new_state);
+        effects.unplayable = unplayable;
+
+        effects
+    }
+
     // This is based on the main loop in the symphonia-play example
-    fn continue_playing(self) -> StepResult {
+    fn continue_playing(self, settings: &mut PlayerSettings) -> StepResult {
         let mut player_state = self;
 
+        if player_state.silence_frames > 0 {
+            return player_state.write_silence();
+        }
+
         let (timestamp, decoded) = {
             if let Some(PredecodedPacket { timestamp, decoded }) =
                 player_state.predecoded_packets.pop_front()
@@ -643,7 +1749,13 @@ impl PlayerState {
                     Err(SymphoniaError::IoError(io_error))
                         if io_error.kind() == std::io::ErrorKind::UnexpectedEof =>
                     {
-                        return player_state.forward();
+                        let gap_seconds = settings.dsp.transitions.gap_seconds();
+                        let mut effects = player_state.forward()?;
+                        if let Some(next_state) = &mut effects.player_state {
+                            next_state.follow_previous(gap_seconds);
+                        }
+
+                        return Ok(effects);
                     }
 
                     Err(error) => {
@@ -668,24 +1780,36 @@ impl PlayerState {
             }
         };
 
-        // If the audio output is not open, try to open it.
-        if player_state.audio_output.is_none() {
-            // Get the audio buffer specification. This is a description of the decoded
-            // audio buffer's sample format and sample rate.
-            let spec = *decoded.spec();
+        // Get the audio buffer specification. This is a description of the decoded
+        // audio buffer's sample format and sample rate.
+        let spec = *decoded.spec();
 
-            // Get the capacity of the decoded buffer. Note that this is capacity, not
-            // length! The capacity of the decoded buffer is constant for the life of the
-            // decoder, but the length is not.
-            let duration = decoded.capacity() as u64;
+        // Get the capacity of the decoded buffer. Note that this is capacity, not
+        // length! The capacity of the decoded buffer is constant for the life of the
+        // decoder, but the length is not.
+        let duration = decoded.capacity() as u64;
 
-            // FIXME this needs to happen much earlier,
-            //   but that means we need to be able to swap out the spec,
-            //   and reallocate  based on changing duration?
-            // Try to open the audio output.
-            let new_audio_output =
-                output::try_open(spec, duration).context("opening audio device")?;
+        // If the audio output is not open, or was opened for a track with a different spec,
+        // then try to (re)open it. Otherwise the output from the previous track is reused.
+        let output_fits = player_state
+            .output_spec
+            .map(|open| open.fits(&spec, duration))
+            .unwrap_or_default();
+        if player_state.audio_output.is_none() || !output_fits {
+            if let Some(mut old_output) = player_state.audio_output.take() {
+                old_output.flush();
+            }
+
+            let Some(new_audio_output) = open_output(spec, duration, settings) else {
+                // NOTE this keeps the queue position, so that playing again retries
+                error!("no audio device available; pausing");
+                player_state.set_playing(false);
+                let mut effects = publish_display_update(player_state);
+                effects.audio_message = Some(AudioMessage::NoOutputDevice);
+                return Ok(effects);
+            };
             player_state.audio_output.replace(new_audio_output);
+            player_state.output_spec = Some(OutputSpec { spec, duration });
         }
 
         // Write the decoded audio samples to the audio output
@@ -703,24 +1827,132 @@ impl PlayerState {
             player_state.seek_ts = None;
         }
 
+        let followed_previous = player_state.followed_previous;
+        let position = player_state
+            .track_info
+            .progress_times(timestamp)
+            .map(|times| SongPosition {
+                elapsed_seconds: times.elapsed.seconds as f64 + times.elapsed.frac,
+                total_seconds: times.total.seconds as f64 + times.total.frac,
+                followed_previous,
+            });
+
+        // NOTE this also catches a seek past B
+        let loop_restart = player_state
+            .ab_loop
+            .zip(position)
+            .and_then(|(ab_loop, position)| ab_loop.restart_at(position.elapsed_seconds));
+        if let Some(start) = loop_restart {
+            let player_state = player_state.seek_to(start as f32);
+            return Ok(publish_display_update(player_state));
+        }
+
+        // NOTE a skip plays on until its fade-out is silent
+        if let (Some(skip_fade), Some(position)) = (player_state.skip_fade, position) {
+            if skip_fade.is_silent_at(position.elapsed_seconds) {
+                return player_state.forward();
+            }
+        }
+
         let audio_output: &mut dyn AudioOutput = player_state
             .audio_output
             .as_deref_mut()
             .ok_or_else(|| anyhow!("no audio device"))?;
 
-        match decoded {
-            DecodedPacket::Preloaded((_ts, buf)) => audio_output
-                .write(buf.as_audio_buffer_ref())
-                .context("writing preloaded audio")?,
+        let replay_gain = &player_state.queue.current.replay_gain;
+        let gain = replay_gain.linear_gain(&settings.replay_gain);
+        let volume = if settings.muted { 0.0 } else { settings.volume };
+        audio_output.set_gain(gain.unwrap_or(1.0) * volume);
+        // NOTE a kept output may have been paused with the previous queue
+        audio_output.set_paused(false);
+
+        settings.dsp.transitions.set_position(position);
+        settings
+            .dsp
+            .transitions
+            .set_fade_out(player_state.skip_fade);
+
+        let written = match decoded {
+            DecodedPacket::Preloaded((_ts, buf)) => {
+                audio_output.write(settings.dsp.process(buf.as_audio_buffer_ref()))
+            }
 
             DecodedPacket::JustDecoded(buf) => {
-                audio_output.write(buf).context("writing audio")?
+                audio_output.write(settings.dsp.process(buf))
             }
+        };
+
+        // This is usually an unplugged device or a new system default.
+        // Dropping the output means the next packet reopens it.
+        if let Err(err) = written {
+            warn!("lost audio output, reopening: {err}");
+            player_state.close_output();
+        }
+
+        // Repeated underruns mean the buffer is too short for this machine
+        let underruns = player_state
+            .audio_output
+            .as_deref()
+            .and_then(|audio_output| audio_output.telemetry().underruns)
+            .unwrap_or_default();
+        if underruns >= UNDERRUNS_BEFORE_GROWING
+            && settings.output_buffer_ms < MAX_OUTPUT_BUFFER_MS
+        {
+            settings.output_buffer_ms *= 2;
+            warn!(
+                "{underruns} underruns, growing to {}ms",
+                settings.output_buffer_ms
+            );
+
+            // NOTE the next packet reopens the output with the longer buffer
+            player_state.close_output();
+
+            let grown = AudioMessage::OutputBufferGrown(settings.output_buffer_ms);
+            let mut effects = AudioEffects::none(Some(player_state));
+            effects.audio_message = Some(grown);
+
+            return Ok(effects);
         }
 
         Ok(publish_display_update(player_state))
     }
 
+    /// Marks a song that the queue moved on to by itself,
+    /// with the silence to leave before it
+    fn follow_previous(&mut self, gap_seconds: f64) {
+        self.followed_previous = true;
+
+        // NOTE without an output to keep, the next song starts right away
+        if let Some(output_spec) = &self.output_spec {
+            self.silence_frames = (gap_seconds * f64::from(output_spec.spec.rate)) as u64;
+        }
+    }
+
+    /// Writes one packet's worth of silence for a gap between songs
+    fn write_silence(mut self) -> StepResult {
+        let (Some(audio_output), Some(output_spec)) =
+            (self.audio_output.as_deref_mut(), self.output_spec)
+        else {
+            self.silence_frames = 0;
+            return Ok(AudioEffects::none(Some(self)));
+        };
+
+        let frames = self.silence_frames.min(output_spec.duration);
+        self.silence_frames -= frames;
+
+        let mut silence = AudioBuffer::<f32>::new(output_spec.duration, output_spec.spec);
+        silence.render_silence(Some(frames as usize));
+
+        audio_output.set_paused(false);
+        if let Err(err) = audio_output.write(silence.as_audio_buffer_ref()) {
+            warn!("lost audio output, reopening: {err}");
+            self.silence_frames = 0;
+            self.close_output();
+        }
+
+        Ok(AudioEffects::none(Some(self)))
+    }
+
     // NOTE This is to avoid flashing the 'old' timestamp while seeking
     // to the new timestamp; we publish the timestamp where we're going to.
     // This relies on resetting seek_ts to None in continue_playing
@@ -764,6 +1996,7 @@ fn publish_display_update(new_state: PlayerState) -> AudioEffects {
         metadata: Some(metadata),
         playback: Some(playback),
         preload: None,
+        unplayable: Vec::new(),
     }
 }
 
@@ -776,9 +2009,31 @@ fn publish_seek_complete(new_state: PlayerState) -> AudioEffects {
         metadata: Some(metadata),
         playback: Some(playback),
         preload: None,
+        unplayable: Vec::new(),
+    }
+}
+
+/// Shuffles or unshuffles a new queue to follow the shuffle setting;
+/// a restored queue keeps the shuffled order it was saved with
+fn match_shuffle_setting(
+    queue: Queue<QueuedSong>,
+    settings: &PlayerSettings,
+) -> Queue<QueuedSong> {
+    match (settings.shuffle, queue.is_shuffled()) {
+        (true, false) => shuffle_queue(queue, settings.shuffle_order),
+        (false, true) => queue.unshuffled(),
+        _ => queue,
     }
 }
 
+fn shuffle_queue(queue: Queue<QueuedSong>, order: ShuffleOrder) -> Queue<QueuedSong> {
+    let order = order.upcoming_order();
+
+    queue.shuffled_by(|current, upcoming| {
+        order.arrange(current, upcoming, &mut rand::thread_rng())
+    })
+}
+
 fn publish_stop() -> AudioEffects {
     AudioEffects {
         audio_message: Some(AudioMessage::DisplayUpdate(None)),
@@ -786,26 +2041,14 @@ fn publish_stop() -> AudioEffects {
         metadata: None,
         playback: None,
         preload: None,
+        unplayable: Vec::new(),
     }
 }
 
 fn prepare_publish(
     new_state: &PlayerState,
 ) -> (PlayerDisplay, ControlsMetadata, MediaPlayback) {
-    let current = &new_state.queue.current;
-
-    let cover_url = current
-        .resized_art
-        .as_ref()
-        .map(|path| format!("file://{path}"));
-
-    let metadata = ControlsMetadata {
-        title: current.title.clone(),
-        album: current.album_title.clone(),
-        artist: current.artist.clone(),
-        duration: current.duration,
-        cover_url,
-    };
+    let metadata = controls_metadata(&new_state.queue.current);
 
     let timestamp = new_state.optimistic_timestamp();
     let progress = new_state
@@ -824,6 +2067,32 @@ fn prepare_publish(
     (display, metadata, playback)
 }
 
+fn controls_metadata(song: &QueuedSong) -> ControlsMetadata {
+    ControlsMetadata {
+        title: song.title.clone(),
+        album: song.album_title.clone(),
+        artist: song.artist.clone(),
+        duration: song.duration,
+        cover_url: song.resized_art.as_deref().map(file_url),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn controls_track_list(queue: &Queue<QueuedSong>) -> ControlsTrackList {
+    let tracks = queue
+        .previous
+        .iter()
+        .chain(std::iter::once(&queue.current))
+        .chain(queue.next.iter())
+        .map(controls_metadata)
+        .collect();
+
+    ControlsTrackList {
+        tracks,
+        current: queue.previous.len(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -831,7 +2100,9 @@ mod tests {
     use super::*;
     use crate::player::output::AudioOutput;
     use mockall::mock;
-    use symphonia::core::formats::Track;
+    use symphonia::core::audio::{AudioBuffer, Channels};
+    use symphonia::core::formats::{SeekedTo, Track};
+    use symphonia::core::units::TimeBase;
 
     #[test]
     fn continue_playing_doesnt_crash_for_eof() {
@@ -839,6 +2110,7 @@ mod tests {
             id: 0,
             time_base: None,
             duration: None,
+            bits_per_sample: None,
         };
 
         let decoder = MockDecoder::new();
@@ -859,15 +2131,15 @@ mod tests {
             album_title: None,
             resized_art: None,
             duration: None,
+            replay_gain: Default::default(),
+            bpm: None,
+            key: None,
         };
-        let queue = Queue {
-            current,
-            previous: Default::default(),
-            next: Default::default(),
-        };
+        let queue = Queue::new(Default::default(), current, Default::default());
 
         let player_state = PlayerState {
             audio_output: Some(Box::new(output)),
+            output_spec: None,
             reader: Box::new(reader),
             decoder: Box::new(decoder),
             playing: true,
@@ -877,17 +2149,567 @@ mod tests {
             queue,
             predecoded_packets: Default::default(),
             preloaded_content: None,
+            followed_previous: false,
+            silence_frames: 0,
+            skip_fade: None,
+            ab_loop: None,
         };
 
-        let effects = player_state.continue_playing().unwrap();
+        let effects = player_state
+            .continue_playing(&mut PlayerSettings::default())
+            .unwrap();
 
-        assert!(matches!(effects.player_state, None));
+        assert!(effects.player_state.is_none());
         assert!(matches!(
             effects.audio_message,
             Some(AudioMessage::DisplayUpdate(None))
         ));
     }
 
+    #[test]
+    fn forward_to_preloaded_song_keeps_audio_output() {
+        let track_info = TrackInfo {
+            id: 0,
+            time_base: None,
+            duration: None,
+            bits_per_sample: None,
+        };
+
+        let current = fake_queued_song(1, "current");
+        let next = fake_queued_song(2, "next");
+        let queue =
+            Queue::new(Default::default(), current, VecDeque::from([next.clone()]));
+
+        let preloaded = PreloadedContent {
+            path: next.path,
+            reader: Box::new(MockReader::new()),
+            decoder: Box::new(MockDecoder::new()),
+            track_info: track_info.clone(),
+            predecoded_packets: Default::default(),
+        };
+
+        let player_state = PlayerState {
+            audio_output: Some(Box::<MockOutput>::default()),
+            track_info,
+            preloaded_content: Some(preloaded),
+            ..test_state(queue)
+        };
+
+        let effects = player_state.forward().unwrap();
+
+        let mut new_state = effects.player_state.unwrap();
+        assert_eq!(new_state.queue.current.id, SongId::new(2));
+
+        let mut output = new_state.audio_output.take().expect("kept audio output");
+        output.flush();
+    }
+
+    #[test]
+    fn forward_skips_songs_that_cant_be_opened() {
+        let current = fake_queued_song(1, "current");
+        let deleted = fake_queued_song(2, "/nonexistent/deleted.flac");
+        let unsupported = fake_queued_song(3, "/nonexistent/unsupported.xyz");
+        let queue = Queue::new(
+            Default::default(),
+            current,
+            VecDeque::from([deleted, unsupported]),
+        );
+
+        let player_state = PlayerState {
+            audio_output: Some(Box::<MockOutput>::default()),
+            ..test_state(queue)
+        };
+
+        let effects = player_state.forward().unwrap();
+
+        assert!(effects.player_state.is_none());
+        assert!(matches!(
+            effects.audio_message,
+            Some(AudioMessage::DisplayUpdate(None))
+        ));
+        let skipped: Vec<SongId> = effects.unplayable.iter().map(|(id, _)| *id).collect();
+        assert_eq!(skipped, [SongId::new(2), SongId::new(3)]);
+    }
+
+    #[test]
+    fn forward_while_playing_fades_out_before_skipping() {
+        let track_info = TrackInfo {
+            id: 0,
+            time_base: Some(TimeBase::new(1, 44_100)),
+            duration: Some(44_100 * 60),
+            bits_per_sample: None,
+        };
+
+        let current = fake_queued_song(1, "current");
+        let next = fake_queued_song(2, "next");
+        let queue =
+            Queue::new(Default::default(), current, VecDeque::from([next.clone()]));
+
+        let preloaded = PreloadedContent {
+            path: next.path,
+            reader: Box::new(MockReader::new()),
+            decoder: Box::new(MockDecoder::new()),
+            track_info: track_info.clone(),
+            predecoded_packets: Default::default(),
+        };
+
+        let player_state = PlayerState {
+            audio_output: Some(Box::<MockOutput>::default()),
+            track_info,
+            timestamp: 44_100 * 10,
+            preloaded_content: Some(preloaded),
+            ..test_state(queue)
+        };
+
+        let mut settings = PlayerSettings::default();
+        let effects = Player::step(
+            Some(player_state),
+            &mut settings,
+            Some(AudioAction::Forward),
+        )
+        .unwrap();
+
+        let fading = effects.player_state.unwrap();
+        assert_eq!(fading.queue.current.id, SongId::new(1));
+        assert_eq!(fading.skip_fade, Some(FadeOut::skip(10.0)));
+
+        // a second skip doesn't wait for the fade
+        let effects =
+            Player::step(Some(fading), &mut settings, Some(AudioAction::Forward))
+                .unwrap();
+
+        let mut new_state = effects.player_state.unwrap();
+        assert_eq!(new_state.queue.current.id, SongId::new(2));
+        assert_eq!(new_state.skip_fade, None);
+
+        let mut output = new_state.audio_output.take().expect("kept audio output");
+        output.flush();
+    }
+
+    #[test]
+    fn enqueue_next_preloads_the_enqueued_song() {
+        let current = fake_queued_song(1, "current");
+        let queued = fake_queued_song(2, "queued");
+        let queue = Queue::new(
+            Default::default(),
+            current,
+            VecDeque::from([fake_queued_song(3, "next")]),
+        );
+
+        let player_state = test_state(queue);
+
+        let mut settings = PlayerSettings::default();
+        let action = AudioAction::EnqueueNext(Box::new(queued.clone()));
+        let effects =
+            Player::step(Some(player_state), &mut settings, Some(action)).unwrap();
+
+        let next_ids: Vec<SongId> = effects
+            .player_state
+            .unwrap()
+            .queue
+            .next
+            .iter()
+            .map(|song| song.id)
+            .collect();
+        assert_eq!(next_ids, vec![SongId::new(2), SongId::new(3)]);
+        assert!(matches!(
+            effects.preload,
+            Some(PreloaderAction::Load(path)) if path == queued.path
+        ));
+    }
+
+    #[test]
+    fn removing_the_up_next_song_preloads_the_one_after() {
+        let after = fake_queued_song(3, "after");
+        let queue = Queue::new(
+            vec![fake_queued_song(0, "previous")],
+            fake_queued_song(1, "current"),
+            VecDeque::from([fake_queued_song(2, "next"), after.clone()]),
+        );
+
+        let player_state = test_state(queue);
+
+        let mut settings = PlayerSettings::default();
+        let action = AudioAction::RemoveFromQueue(2);
+        let effects =
+            Player::step(Some(player_state), &mut settings, Some(action)).unwrap();
+
+        let display = effects.player_state.as_ref().unwrap().queue_display();
+        let ids: Vec<SongId> = display.songs.iter().map(|song| song.id).collect();
+        assert_eq!(ids, vec![SongId::new(0), SongId::new(1), SongId::new(3)]);
+        assert_eq!(display.current_index, 1);
+        assert!(matches!(
+            effects.preload,
+            Some(PreloaderAction::Load(path)) if path == after.path
+        ));
+
+        // the current song stays put
+        let action = AudioAction::RemoveFromQueue(1);
+        let effects =
+            Player::step(effects.player_state, &mut settings, Some(action)).unwrap();
+        assert_eq!(
+            effects.player_state.unwrap().queue.current.id,
+            SongId::new(1)
+        );
+        assert!(effects.preload.is_none());
+    }
+
+    #[test]
+    fn set_output_device_drops_the_open_output() {
+        let queue = Queue::new(
+            Default::default(),
+            fake_queued_song(1, "current"),
+            Default::default(),
+        );
+
+        let player_state = PlayerState {
+            audio_output: Some(Box::<MockOutput>::default()),
+            ..test_state(queue)
+        };
+
+        let mut settings = PlayerSettings::default();
+        let action = AudioAction::SetOutputDevice(Some("headphones".to_string()));
+        let effects =
+            Player::step(Some(player_state), &mut settings, Some(action)).unwrap();
+
+        assert_eq!(settings.output_device.as_deref(), Some("headphones"));
+        let player_state = effects.player_state.unwrap();
+        assert!(player_state.audio_output.is_none());
+        assert!(player_state.playing);
+    }
+
+    #[test]
+    fn only_a_new_resample_quality_reopens_the_output() {
+        let player_state = fake_state_with_one_packet(MockOutput::default());
+        let mut settings = PlayerSettings::default();
+
+        let same = AudioAction::SetResampleQuality(ResampleQuality::default());
+        let effects =
+            Player::step(Some(player_state), &mut settings, Some(same)).unwrap();
+        let player_state = effects.player_state.unwrap();
+        assert!(player_state.audio_output.is_some());
+
+        let high = AudioAction::SetResampleQuality(ResampleQuality::High);
+        let effects =
+            Player::step(Some(player_state), &mut settings, Some(high)).unwrap();
+        assert_eq!(settings.resample_quality, ResampleQuality::High);
+        let player_state = effects.player_state.unwrap();
+        assert!(player_state.audio_output.is_none());
+        assert!(player_state.playing);
+    }
+
+    #[test]
+    fn muting_keeps_the_volume_to_restore() {
+        let mut settings = PlayerSettings::default();
+        let step = |settings: &mut PlayerSettings, action| {
+            Player::step(None, settings, Some(action))
+                .unwrap()
+                .audio_message
+        };
+
+        step(&mut settings, AudioAction::SetVolume(0.6));
+        let message = step(&mut settings, AudioAction::SetMuted(true));
+        assert_eq!(
+            message,
+            Some(AudioMessage::VolumeChanged { volume: 0.6, muted: true })
+        );
+
+        let message = step(&mut settings, AudioAction::SetMuted(false));
+        assert_eq!(
+            message,
+            Some(AudioMessage::VolumeChanged { volume: 0.6, muted: false })
+        );
+
+        // ie from the os media controls, while muted
+        step(&mut settings, AudioAction::SetMuted(true));
+        let message = step(&mut settings, AudioAction::SetVolume(0.8));
+        assert_eq!(
+            message,
+            Some(AudioMessage::VolumeChanged { volume: 0.8, muted: false })
+        );
+    }
+
+    #[test]
+    fn lost_audio_output_is_dropped_without_stopping() {
+        let output = MockOutput {
+            flushed: false,
+            lost: true,
+            underruns: None,
+        };
+        let player_state = fake_state_with_one_packet(output);
+
+        let effects = player_state
+            .continue_playing(&mut PlayerSettings::default())
+            .unwrap();
+
+        let player_state = effects.player_state.expect("still playing");
+        assert!(player_state.playing);
+        assert_eq!(player_state.timestamp, 100);
+        assert!(player_state.audio_output.is_none());
+        assert!(player_state.output_spec.is_none());
+    }
+
+    #[test]
+    fn repeated_underruns_grow_the_output_buffer() {
+        let output = MockOutput {
+            flushed: false,
+            lost: false,
+            underruns: Some(UNDERRUNS_BEFORE_GROWING),
+        };
+        let player_state = fake_state_with_one_packet(output);
+        let mut settings = PlayerSettings::default();
+
+        let effects = player_state.continue_playing(&mut settings).unwrap();
+
+        let grown = DEFAULT_OUTPUT_BUFFER_MS * 2;
+        assert_eq!(settings.output_buffer_ms, grown);
+        assert_eq!(
+            effects.audio_message,
+            Some(AudioMessage::OutputBufferGrown(grown))
+        );
+        let player_state = effects.player_state.expect("still playing");
+        assert!(player_state.audio_output.is_none());
+    }
+
+    #[test]
+    fn consecutive_seeks_start_from_the_pending_seek() {
+        let mut reader = MockReader::new();
+        reader.expect_seek().times(2).returning(|_mode, to| {
+            let SeekTo::Time { time, .. } = to else {
+                panic!("expected a seek by time");
+            };
+            let ts = ((time.seconds as f64 + time.frac) * 44_100.0) as u64;
+            Ok(SeekedTo {
+                track_id: 0,
+                required_ts: ts,
+                actual_ts: ts,
+            })
+        });
+
+        let queue = Queue::new(
+            Default::default(),
+            fake_queued_song(1, "current"),
+            Default::default(),
+        );
+        let player_state = PlayerState {
+            reader: Box::new(reader),
+            track_info: TrackInfo {
+                id: 0,
+                time_base: Some(TimeBase::new(1, 44_100)),
+                duration: Some(44_100 * 60),
+                bits_per_sample: None,
+            },
+            timestamp: 44_100 * 10,
+            ..test_state(queue)
+        };
+
+        let mut settings = PlayerSettings::default();
+        let mut seek_by = |player_state| {
+            let action = AudioAction::SeekBy(SeekOffset::Seconds(10.0));
+            let effects =
+                Player::step(Some(player_state), &mut settings, Some(action)).unwrap();
+            effects.player_state.unwrap()
+        };
+
+        // NOTE no packets are decoded between the two
+        let player_state = seek_by(player_state);
+        assert_eq!(player_state.seek_ts, Some(44_100 * 20));
+        let player_state = seek_by(player_state);
+        assert_eq!(player_state.seek_ts, Some(44_100 * 30));
+    }
+
+    #[test]
+    fn loop_points_mark_a_then_b_then_clear() {
+        let track_info = TrackInfo {
+            id: 0,
+            time_base: Some(TimeBase::new(1, 44_100)),
+            duration: Some(44_100 * 60),
+            bits_per_sample: None,
+        };
+        let queue = Queue::new(
+            Default::default(),
+            fake_queued_song(1, "current"),
+            Default::default(),
+        );
+
+        let player_state = PlayerState {
+            track_info,
+            timestamp: 44_100 * 20,
+            ..test_state(queue)
+        };
+
+        let mut settings = PlayerSettings::default();
+        let mut mark = |player_state, timestamp| {
+            let mut player_state: PlayerState = player_state;
+            player_state.timestamp = timestamp;
+            let effects = Player::step(
+                Some(player_state),
+                &mut settings,
+                Some(AudioAction::MarkLoopPoint),
+            )
+            .unwrap();
+            effects.player_state.unwrap()
+        };
+
+        let player_state = mark(player_state, 44_100 * 20);
+        let a_only = AbLoop { start: 20.0, end: None };
+        assert_eq!(player_state.ab_loop, Some(a_only));
+        assert_eq!(a_only.restart_at(59.0), None);
+
+        // NOTE marking B before A swaps them
+        let player_state = mark(player_state, 44_100 * 10);
+        let ab_loop = player_state.ab_loop.unwrap();
+        assert_eq!(ab_loop.start, 10.0);
+        assert_eq!(ab_loop.end, Some(20.0));
+        assert_eq!(ab_loop.restart_at(19.5), None);
+        assert_eq!(ab_loop.restart_at(20.0), Some(10.0));
+
+        let player_state = mark(player_state, 44_100 * 15);
+        assert_eq!(player_state.ab_loop, None);
+    }
+
+    #[test]
+    fn low_power_throttles_progress_for_the_same_song() {
+        let display = |song_id, playing| {
+            AudioMessage::DisplayUpdate(Some(PlayerDisplay {
+                song_id: SongId::new(song_id),
+                playing,
+                times: ProgressTimes::ZERO,
+                output: None,
+                bits_per_sample: None,
+                ab_loop: None,
+            }))
+        };
+        let start = Instant::now();
+        let later = start + Duration::from_millis(100);
+
+        let mut throttle = DisplayThrottle::default();
+        assert!(throttle.should_send(&display(1, true), true, start));
+        assert!(!throttle.should_send(&display(1, true), true, later));
+        assert!(throttle.should_send(&display(1, false), true, later));
+        assert!(throttle.should_send(&display(2, false), true, later));
+        assert!(throttle.should_send(&display(2, false), false, later));
+
+        let after_interval = later + LOW_POWER_DISPLAY_INTERVAL;
+        assert!(throttle.should_send(&display(2, false), true, after_interval));
+    }
+
+    #[test]
+    fn gap_transition_writes_silence_before_the_next_song() {
+        let output = MockOutput {
+            flushed: false,
+            lost: false,
+            underruns: None,
+        };
+        let mut player_state = fake_state_with_one_packet(output);
+        player_state.follow_previous(0.5);
+        assert_eq!(player_state.silence_frames, 22_050);
+
+        let effects = player_state
+            .continue_playing(&mut PlayerSettings::default())
+            .unwrap();
+
+        let mut player_state = effects.player_state.expect("still playing");
+        assert_eq!(player_state.silence_frames, 22_050 - 1024);
+        assert_eq!(player_state.predecoded_packets.len(), 1);
+        assert_eq!(player_state.timestamp, 0);
+        player_state.close_output();
+    }
+
+    #[test]
+    fn restored_queues_follow_the_shuffle_setting() {
+        let songs: Vec<QueuedSong> = (1..=4)
+            .map(|id| fake_queued_song(id, &format!("/nonexistent/{id}.flac")))
+            .collect();
+        let mut shuffled = Queue::new(
+            Vec::new(),
+            songs[0].clone(),
+            VecDeque::from([songs[3].clone(), songs[1].clone(), songs[2].clone()]),
+        );
+        shuffled.unshuffled = Some(songs.clone());
+
+        let mut settings = PlayerSettings::default();
+        let effects = Player::step(
+            None,
+            &mut settings,
+            Some(AudioAction::RestoreState(Box::new(shuffled.clone()), 0.0)),
+        )
+        .unwrap();
+        assert!(effects.player_state.is_none());
+        assert!(!settings.shuffle, "restoring keeps the user's setting");
+
+        let unshuffled = match_shuffle_setting(shuffled.clone(), &settings);
+        assert!(!unshuffled.is_shuffled());
+        let next_ids: Vec<SongId> = unshuffled.next.iter().map(|s| s.id).collect();
+        assert_eq!(next_ids, [SongId::new(2), SongId::new(3), SongId::new(4)]);
+
+        settings.shuffle = true;
+        let kept = match_shuffle_setting(shuffled.clone(), &settings);
+        assert_eq!(kept, shuffled);
+    }
+
+    /// A playing state with an open output, and one predecoded packet to write to it
+    fn fake_state_with_one_packet(output: MockOutput) -> PlayerState {
+        let spec = SignalSpec::new(44_100, Channels::FRONT_LEFT | Channels::FRONT_RIGHT);
+        let duration = 1024;
+        let packet = PredecodedPacket {
+            timestamp: 100,
+            decoded: AnyAudioBuffer::F32(AudioBuffer::new(duration, spec)),
+        };
+
+        let current = fake_queued_song(1, "current");
+        let queue = Queue::new(Default::default(), current, Default::default());
+
+        PlayerState {
+            audio_output: Some(Box::new(output)),
+            output_spec: Some(OutputSpec { spec, duration }),
+            predecoded_packets: VecDeque::from([packet]),
+            ..test_state(queue)
+        }
+    }
+
+    /// A playing state with no output, and a track without timing; tests set
+    /// the fields they need with struct update syntax
+    fn test_state(queue: Queue<QueuedSong>) -> PlayerState {
+        PlayerState {
+            audio_output: None,
+            output_spec: None,
+            reader: Box::new(MockReader::new()),
+            decoder: Box::new(MockDecoder::new()),
+            playing: true,
+            seek_ts: None,
+            track_info: TrackInfo {
+                id: 0,
+                time_base: None,
+                duration: None,
+                bits_per_sample: None,
+            },
+            timestamp: 0,
+            queue,
+            predecoded_packets: Default::default(),
+            preloaded_content: None,
+            followed_previous: false,
+            silence_frames: 0,
+            skip_fade: None,
+            ab_loop: None,
+        }
+    }
+
+    fn fake_queued_song(id: i32, path: &str) -> QueuedSong {
+        QueuedSong {
+            id: SongId::new(id),
+            path: Utf8PathBuf::from_str(path).unwrap(),
+            title: None,
+            artist: None,
+            album_title: None,
+            resized_art: None,
+            duration: None,
+            replay_gain: Default::default(),
+            bpm: None,
+            key: None,
+        }
+    }
+
     mock! {
         Reader {}
 
@@ -951,6 +2773,9 @@ mod tests {
     #[derive(Default)]
     struct MockOutput {
         flushed: bool,
+        /// write fails as if the device was unplugged
+        lost: bool,
+        underruns: Option<u64>,
     }
 
     impl AudioOutput for MockOutput {
@@ -958,11 +2783,29 @@ mod tests {
             self.flushed = true;
         }
 
+        fn set_gain(&mut self, _gain: f32) {}
+
+        fn set_paused(&mut self, _paused: bool) {}
+
+        fn telemetry(&self) -> OutputTelemetry {
+            OutputTelemetry {
+                sample_format: "f32",
+                resampling: None,
+                buffer_fill: None,
+                buffer_ms: None,
+                underruns: self.underruns,
+            }
+        }
+
         fn write(
             &mut self,
             _decoded: symphonia::core::audio::AudioBufferRef<'_>,
         ) -> output::Result<()> {
-            unimplemented!()
+            if self.lost {
+                return Err(output::AudioOutputError::StreamClosedError);
+            }
+
+            Ok(())
         }
     }
 
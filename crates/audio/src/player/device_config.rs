@@ -4,7 +4,7 @@ use std::fmt::Debug;
 
 use anyhow::bail;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, SupportedStreamConfig};
+use cpal::{Device, Host, SupportedStreamConfig};
 use log::*;
 
 pub struct CpalDeviceConfig {
@@ -14,12 +14,17 @@ pub struct CpalDeviceConfig {
 
 impl CpalDeviceConfig {
     pub fn get_default() -> anyhow::Result<Self> {
+        Self::get(None)
+    }
+
+    /// Gets the config for the named output device; None = the default device
+    pub fn get(name: Option<&str>) -> anyhow::Result<Self> {
         let host = cpal::default_host();
 
-        let device = match host.default_output_device() {
+        let device = match find_output_device(&host, name) {
             Some(device) => device,
             _ => {
-                bail!("failed to get default audio output device");
+                bail!("failed to get audio output device: {name:?}");
             }
         };
 
@@ -34,6 +39,26 @@ impl CpalDeviceConfig {
     }
 }
 
+/// Finds an output device by name; None = the default device
+pub fn find_output_device(host: &Host, name: Option<&str>) -> Option<Device> {
+    let Some(name) = name else {
+        return host.default_output_device();
+    };
+
+    let mut devices = host.output_devices().ok()?;
+    devices.find(|device| device.name().map(|n| n == name).unwrap_or_default())
+}
+
+pub fn output_device_names() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            error!("failed to list audio output devices: {e}");
+            Vec::new()
+        }
+    }
+}
+
 impl Debug for CpalDeviceConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CpalDeviceConfig")
@@ -0,0 +1,207 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::error;
+
+use clef_db::queries::{self, PlayId, SongId};
+use clef_db::SqlitePool;
+
+use super::PlayerState;
+
+/// A song counts as played after hearing this proportion of it...
+const PLAY_THRESHOLD: f64 = 0.5;
+/// ...or this many seconds of it, for long songs
+const PLAY_THRESHOLD_SECONDS: f64 = 240.0;
+/// Larger jumps in position aren't listening, even outside of a seek
+const MAX_LISTENING_STEP_SECONDS: f64 = 2.0;
+
+/// Listening to the current song, to record it in the play history
+#[derive(Debug)]
+pub(super) struct CurrentPlay {
+    song_id: SongId,
+    /// A unix timestamp, in seconds
+    started_at: i64,
+    total_seconds: f64,
+    /// Time spent listening, not counting seeks or pauses
+    listened_seconds: f64,
+    last_elapsed_seconds: f64,
+    /// Whether the last step was mid-seek; the step that lands it isn't listening
+    seeking: bool,
+    /// Set once the play passes the threshold and is saved
+    play_id: Option<PlayId>,
+}
+
+impl CurrentPlay {
+    fn new(song_id: SongId, total_seconds: f64, elapsed_seconds: f64) -> Self {
+        Self {
+            song_id,
+            started_at: unix_now(),
+            total_seconds,
+            listened_seconds: 0.0,
+            last_elapsed_seconds: elapsed_seconds,
+            seeking: false,
+            play_id: None,
+        }
+    }
+
+    /// Counts the position's progress since the last step as listening.
+    /// A paused song's position doesn't move, and a seek moves it without listening.
+    fn listen(&mut self, elapsed_seconds: f64, seeking: bool) {
+        let step = elapsed_seconds - self.last_elapsed_seconds;
+        let mid_seek = seeking || self.seeking;
+        if !mid_seek && step > 0.0 && step < MAX_LISTENING_STEP_SECONDS {
+            self.listened_seconds += step;
+        }
+
+        self.last_elapsed_seconds = elapsed_seconds;
+        self.seeking = seeking;
+    }
+
+    fn passed_threshold(&self) -> bool {
+        let heard_enough = self.total_seconds > 0.0
+            && self.listened_seconds >= self.total_seconds * PLAY_THRESHOLD;
+
+        heard_enough || self.listened_seconds >= PLAY_THRESHOLD_SECONDS
+    }
+
+    fn percent_played(&self) -> f64 {
+        if self.total_seconds <= 0.0 {
+            return 0.0;
+        }
+
+        (self.listened_seconds / self.total_seconds * 100.0).min(100.0)
+    }
+}
+
+/// Records the current song once it passes the play threshold,
+/// and finishes the recorded play when the song changes or playback stops
+pub(super) fn track_play(
+    db: &SqlitePool,
+    current_play: &mut Option<CurrentPlay>,
+    player_state: Option<&PlayerState>,
+) {
+    let song_id = player_state.map(|state| state.queue.current.id);
+    if current_play.as_ref().map(|play| play.song_id) != song_id {
+        if let Some(finished) = current_play.take() {
+            finish_play(db, &finished);
+        }
+    }
+
+    let Some(player_state) = player_state else {
+        return;
+    };
+
+    let elapsed_seconds = player_state.elapsed_seconds();
+    let play = current_play.get_or_insert_with(|| {
+        let song_id = player_state.queue.current.id;
+        CurrentPlay::new(song_id, player_state.total_seconds(), elapsed_seconds)
+    });
+    play.listen(elapsed_seconds, player_state.seek_ts.is_some());
+
+    // NOTE songs from outside the library have no row to record plays against
+    if play.play_id.is_none() && play.passed_threshold() && play.song_id.is_saved() {
+        play.play_id = start_play(db, play);
+    }
+}
+
+fn start_play(db: &SqlitePool, play: &CurrentPlay) -> Option<PlayId> {
+    let started = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        conn.immediate_transaction(|tx| {
+            queries::start_play(
+                tx,
+                play.song_id,
+                play.started_at,
+                play.percent_played(),
+                play.listened_seconds,
+            )
+        })
+        .map_err(anyhow::Error::from)
+    });
+
+    started
+        .map_err(|e| error!("failed to record play: {e}"))
+        .ok()
+}
+
+fn finish_play(db: &SqlitePool, play: &CurrentPlay) {
+    let Some(play_id) = play.play_id else {
+        // never passed the threshold
+        return;
+    };
+
+    let finished = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        conn.immediate_transaction(|tx| {
+            queries::finish_play(
+                tx,
+                play_id,
+                unix_now(),
+                play.percent_played(),
+                play.listened_seconds,
+            )
+        })
+        .map_err(anyhow::Error::from)
+    });
+
+    if let Err(e) = finished {
+        error!("failed to finish play: {e}");
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeking_does_not_count_as_listening() {
+        let mut play = CurrentPlay::new(SongId::new(1), 100.0, 0.0);
+
+        for tenth in 1..=200 {
+            play.listen(tenth as f64 * 0.1, false);
+        }
+        play.listen(90.0, false);
+        assert!(!play.passed_threshold());
+        assert!((play.percent_played() - 20.0).abs() < 0.01);
+
+        for tenth in 1..=310 {
+            play.listen(90.0 + tenth as f64 * 0.1, false);
+        }
+        assert!(play.passed_threshold());
+    }
+
+    #[test]
+    fn short_seeks_and_pauses_do_not_count_as_listening() {
+        let mut play = CurrentPlay::new(SongId::new(1), 100.0, 0.0);
+        for tenth in 1..=100 {
+            play.listen(tenth as f64 * 0.1, false);
+        }
+
+        // paused; the position doesn't move
+        play.listen(10.0, false);
+        play.listen(10.0, false);
+
+        // a one second seek, landing on a packet before the target
+        play.listen(10.0, true);
+        play.listen(10.8, true);
+        play.listen(11.0, false);
+        play.listen(11.5, false);
+
+        assert!((play.listened_seconds - 10.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn long_songs_count_after_a_few_minutes() {
+        let mut play = CurrentPlay::new(SongId::new(1), 3600.0, 0.0);
+
+        for second in 1..=240 {
+            play.listen(second as f64, false);
+        }
+
+        assert!(play.passed_threshold());
+    }
+}
@@ -1,19 +1,51 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::anyhow;
+use camino::Utf8Path;
 use flume::Sender;
 use log::{error, info, trace};
 use souvlaki::{
-    MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig,
+    MediaControlEvent, MediaMetadata, MediaPlayback, MediaPosition, SeekDirection,
 };
 
-use super::AudioAction;
+use clef_db::SqlitePool;
+
+use super::{AudioAction, SeekOffset};
+
+// NOTE on linux, clef serves mpris itself, to add the TrackList and Playlists interfaces
+#[cfg(target_os = "linux")]
+mod mpris;
+#[cfg(target_os = "linux")]
+use mpris::MediaControls;
+#[cfg(not(target_os = "linux"))]
+use souvlaki::MediaControls;
 
 pub struct WrappedControls {
     media_controls: Option<MediaControls>,
     controls_to_audio: Sender<AudioAction>,
+    /// For listing smart playlists over mpris
+    #[cfg(target_os = "linux")]
+    db: SqlitePool,
+    /// The last values sent to the os, to avoid re-sending them for every packet
+    last_metadata: Option<ControlsMetadata>,
+    last_playback: Option<PublishedPlayback>,
+    /// The volume to show in os controls that have one; 0 while muted
+    volume: f64,
+}
+
+#[derive(Debug)]
+struct PublishedPlayback {
+    playback: MediaPlayback,
+    published_at: Instant,
 }
 
+/// How often to re-send the position during normal playback
+const POSITION_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+/// How far the position can be from the expected position before it's re-sent
+/// ie after a seek
+const POSITION_TOLERANCE: Duration = Duration::from_secs(1);
+/// How far a seek without an amount goes, like the ui's arrow keys
+const CONTROLS_SEEK_SECONDS: f32 = 5.0;
+
 impl std::fmt::Debug for WrappedControls {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let media_controls = if self.media_controls.is_some() {
@@ -25,36 +57,92 @@ impl std::fmt::Debug for WrappedControls {
         f.debug_struct("WrappedControls")
             .field("media_controls", &media_controls)
             .field("controls_to_audio", &self.controls_to_audio)
+            .field("last_metadata", &self.last_metadata)
+            .field("last_playback", &self.last_playback)
+            .field("volume", &self.volume)
             .finish()
     }
 }
 
 impl WrappedControls {
-    pub fn new(controls_to_audio: Sender<AudioAction>) -> Self {
+    pub fn new(
+        controls_to_audio: Sender<AudioAction>,
+        #[allow(unused)] db: SqlitePool,
+    ) -> Self {
         Self {
             controls_to_audio,
+            #[cfg(target_os = "linux")]
+            db,
             media_controls: None,
+            last_metadata: None,
+            last_playback: None,
+            volume: 1.0,
         }
     }
 
-    pub fn set_metadata(&mut self, metadata: MediaMetadata<'_>) {
+    pub fn set_metadata(&mut self, metadata: &ControlsMetadata) {
+        if self.last_metadata.as_ref() == Some(metadata) {
+            return;
+        }
+
         self.ensure_init();
 
         if let Some(ref mut media_controls) = self.media_controls {
-            media_controls
-                .set_metadata(metadata)
+            let published = media_controls
+                .set_metadata(metadata.into())
                 .map_err(|e| error!("failed to set media controls metadata: {e:?}"))
-                .ok();
+                .is_ok();
+
+            if published {
+                self.last_metadata = Some(metadata.clone());
+            }
         }
     }
 
     pub fn set_playback(&mut self, playback: MediaPlayback) {
+        let now = Instant::now();
+        if !needs_publish(self.last_playback.as_ref(), &playback, now) {
+            return;
+        }
+
         self.ensure_init();
 
         if let Some(ref mut media_controls) = self.media_controls {
-            media_controls
-                .set_playback(playback)
+            let published = media_controls
+                .set_playback(playback.clone())
                 .map_err(|e| error!("failed to set media controls playback: {e:?}"))
+                .is_ok();
+
+            if published {
+                self.last_playback =
+                    Some(PublishedPlayback { playback, published_at: now });
+            }
+        }
+    }
+
+    /// Kept for the next init while the controls are closed, ie while stopped.
+    /// NOTE souvlaki doesn't show a volume, so this only reaches mpris
+    pub fn set_volume(&mut self, volume: f64) {
+        self.volume = volume;
+
+        #[cfg(target_os = "linux")]
+        if let Some(ref mut media_controls) = self.media_controls {
+            media_controls
+                .set_volume(volume)
+                .map_err(|e| error!("failed to set media controls volume: {e:?}"))
+                .ok();
+        }
+    }
+
+    /// The queue in play order, for os controls that can list it
+    #[cfg(target_os = "linux")]
+    pub fn set_track_list(&mut self, track_list: ControlsTrackList) {
+        self.ensure_init();
+
+        if let Some(ref mut media_controls) = self.media_controls {
+            media_controls
+                .set_track_list(track_list)
+                .map_err(|e| error!("failed to set media controls track list: {e:?}"))
                 .ok();
         }
     }
@@ -63,6 +151,8 @@ impl WrappedControls {
         // NOTE This relies on the controls releasing the dbus name on drop.
         // That previously caused problems with souvlaki 0.5.x, but seems resolved
         self.media_controls = None;
+        self.last_metadata = None;
+        self.last_playback = None;
     }
 
     fn ensure_init(&mut self) {
@@ -73,9 +163,27 @@ impl WrappedControls {
         }
     }
 
+    #[cfg(target_os = "linux")]
     fn init(&mut self) -> anyhow::Result<()> {
         trace!("initializing media controls");
 
+        let media_controls = MediaControls::new(
+            self.controls_to_audio.clone(),
+            self.db.clone(),
+            self.volume,
+        )?;
+        self.media_controls = Some(media_controls);
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn init(&mut self) -> anyhow::Result<()> {
+        use anyhow::anyhow;
+        use souvlaki::PlatformConfig;
+
+        trace!("initializing media controls");
+
         #[cfg(not(target_os = "windows"))]
         let hwnd = None;
 
@@ -99,36 +207,7 @@ impl WrappedControls {
         let controls_to_audio = self.controls_to_audio.clone();
 
         media_controls
-            .attach(move |e: MediaControlEvent| {
-                trace!("recieved media control event: {e:?}");
-
-                let action: Option<AudioAction> = match &e {
-                    MediaControlEvent::Play => Some(AudioAction::PlayPaused),
-                    MediaControlEvent::Pause => Some(AudioAction::Pause),
-                    MediaControlEvent::Next => Some(AudioAction::Forward),
-                    MediaControlEvent::Previous => Some(AudioAction::Back),
-                    MediaControlEvent::Toggle => Some(AudioAction::Toggle),
-
-                    MediaControlEvent::Stop => None,
-                    MediaControlEvent::Seek(_) => None,
-                    MediaControlEvent::SeekBy(_, _) => None,
-                    MediaControlEvent::SetPosition(_) => None,
-                    MediaControlEvent::OpenUri(_) => None,
-                    MediaControlEvent::Raise => None,
-                    MediaControlEvent::Quit => None,
-                };
-
-                if let Some(action) = action {
-                    controls_to_audio
-                        .send(action)
-                        .map_err(|e| {
-                            error!("failed to send from controls to audio: {e:?}")
-                        })
-                        .ok();
-                } else {
-                    info!("unsupported media control event: {e:?}");
-                }
-            })
+            .attach(move |e: MediaControlEvent| send_control_event(&controls_to_audio, e))
             .map_err(|_| anyhow!("failed to set up listening to media controls"))?;
 
         self.media_controls = Some(media_controls);
@@ -137,8 +216,74 @@ impl WrappedControls {
     }
 }
 
+/// Passes an event from the os on to the player, if it has an action
+fn send_control_event(controls_to_audio: &Sender<AudioAction>, event: MediaControlEvent) {
+    trace!("recieved media control event: {event:?}");
+
+    match control_action(&event) {
+        Some(action) => send_action(controls_to_audio, action),
+        None => info!("unsupported media control event: {event:?}"),
+    }
+}
+
+fn send_action(controls_to_audio: &Sender<AudioAction>, action: AudioAction) {
+    controls_to_audio
+        .send(action)
+        .map_err(|e| error!("failed to send from controls to audio: {e:?}"))
+        .ok();
+}
+
+/// The player action for an event from the os, if it has one
+fn control_action(event: &MediaControlEvent) -> Option<AudioAction> {
+    let signed = |direction: &SeekDirection, seconds: f32| match direction {
+        SeekDirection::Forward => seconds,
+        SeekDirection::Backward => -seconds,
+    };
+
+    match event {
+        MediaControlEvent::Play => Some(AudioAction::PlayPaused),
+        MediaControlEvent::Pause => Some(AudioAction::Pause),
+        MediaControlEvent::Next => Some(AudioAction::Forward),
+        MediaControlEvent::Previous => Some(AudioAction::Back),
+        MediaControlEvent::Toggle => Some(AudioAction::Toggle),
+        MediaControlEvent::Stop => Some(AudioAction::Stop),
+
+        MediaControlEvent::Seek(direction) => {
+            let seconds = signed(direction, CONTROLS_SEEK_SECONDS);
+            Some(AudioAction::SeekBy(SeekOffset::Seconds(seconds)))
+        }
+        MediaControlEvent::SeekBy(direction, duration) => {
+            let seconds = signed(direction, duration.as_secs_f32());
+            Some(AudioAction::SeekBy(SeekOffset::Seconds(seconds)))
+        }
+        // ie dragging the position in the macos now playing widget
+        MediaControlEvent::SetPosition(MediaPosition(position)) => {
+            Some(AudioAction::SeekTo(position.as_secs_f32()))
+        }
+
+        MediaControlEvent::OpenUri(_) => None,
+        MediaControlEvent::Raise => None,
+        MediaControlEvent::Quit => None,
+    }
+}
+
+/// A file url for cover art, escaping characters that aren't allowed in a url.
+/// NOTE macos won't load a url with spaces, and its app data is in 'Application Support'
+pub fn file_url(path: &Utf8Path) -> String {
+    let mut url = String::from("file://");
+    for byte in path.as_str().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => url.push(byte as char),
+            b'-' | b'.' | b'_' | b'~' | b'/' | b'\\' | b':' => url.push(byte as char),
+            _ => url.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    url
+}
+
 // an owned version of `souvlaki::MediaMetadata`
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ControlsMetadata {
     pub title: Option<String>,
     pub album: Option<String>,
@@ -147,6 +292,14 @@ pub struct ControlsMetadata {
     pub duration: Option<Duration>,
 }
 
+/// The queue in play order, with the current song's position
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControlsTrackList {
+    pub tracks: Vec<ControlsMetadata>,
+    pub current: usize,
+}
+
 impl<'a> From<&'a ControlsMetadata> for MediaMetadata<'a> {
     fn from(metadata: &'a ControlsMetadata) -> Self {
         MediaMetadata {
@@ -158,3 +311,87 @@ impl<'a> From<&'a ControlsMetadata> for MediaMetadata<'a> {
         }
     }
 }
+
+/// Whether a playback update differs from what the os already has.
+/// While playing, the os extrapolates the position from the last update,
+/// so the position is only re-sent periodically or when it jumps.
+fn needs_publish(
+    last: Option<&PublishedPlayback>,
+    playback: &MediaPlayback,
+    now: Instant,
+) -> bool {
+    let Some(last) = last else {
+        return true;
+    };
+
+    match (&last.playback, playback) {
+        (
+            MediaPlayback::Playing { progress: Some(last_position) },
+            MediaPlayback::Playing { progress: Some(position) },
+        ) => {
+            let since_published = now.duration_since(last.published_at);
+            if since_published >= POSITION_UPDATE_INTERVAL {
+                return true;
+            }
+
+            let expected = last_position.0 + since_published;
+            expected.abs_diff(position.0) > POSITION_TOLERANCE
+        }
+
+        (last_playback, playback) => last_playback != playback,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing_at(seconds: u64) -> MediaPlayback {
+        let position = MediaPosition(Duration::from_secs(seconds));
+        MediaPlayback::Playing { progress: Some(position) }
+    }
+
+    #[test]
+    fn file_url_escapes_spaces_and_unicode() {
+        let path = Utf8Path::new("/Users/me/Library/Application Support/Clef/Björk.bmp");
+        assert_eq!(
+            file_url(path),
+            "file:///Users/me/Library/Application%20Support/Clef/Bj%C3%B6rk.bmp"
+        );
+    }
+
+    #[test]
+    fn set_position_seeks_to_the_position() {
+        let event =
+            MediaControlEvent::SetPosition(MediaPosition(Duration::from_secs(90)));
+        assert!(
+            matches!(control_action(&event), Some(AudioAction::SeekTo(s)) if s == 90.0)
+        );
+
+        let event =
+            MediaControlEvent::SeekBy(SeekDirection::Backward, Duration::from_secs(15));
+        assert!(matches!(
+            control_action(&event),
+            Some(AudioAction::SeekBy(SeekOffset::Seconds(s))) if s == -15.0
+        ));
+    }
+
+    #[test]
+    fn needs_publish_skips_expected_positions_until_the_interval() {
+        let published_at = Instant::now();
+        let last = PublishedPlayback {
+            playback: playing_at(10),
+            published_at,
+        };
+
+        let soon = published_at + Duration::from_secs(2);
+        assert!(!needs_publish(Some(&last), &playing_at(12), soon));
+        assert!(needs_publish(Some(&last), &playing_at(40), soon));
+
+        let later = published_at + POSITION_UPDATE_INTERVAL;
+        assert!(needs_publish(Some(&last), &playing_at(15), later));
+
+        let paused = MediaPlayback::Paused { progress: None };
+        assert!(needs_publish(Some(&last), &paused, soon));
+    }
+}
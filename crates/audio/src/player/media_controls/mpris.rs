@@ -0,0 +1,646 @@
+//! The mpris service, in place of souvlaki's on linux.
+//! NOTE souvlaki owns the bus name and object path, so the TrackList and Playlists
+//! interfaces can't be served alongside its own Player interface.
+
+use std::collections::HashMap;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Context;
+use flume::{Receiver, Sender};
+use log::{error, info, trace};
+use souvlaki::{MediaControlEvent, MediaMetadata, MediaPlayback, MediaPosition};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, Value};
+use zbus::{dbus_interface, Connection, ConnectionBuilder, SignalContext};
+
+use clef_db::queries::{self, SmartPlaylist};
+use clef_db::SqlitePool;
+
+use super::{send_action, send_control_event, ControlsMetadata, ControlsTrackList};
+use crate::player::{AudioAction, SeekOffset};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.clef.player";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// Followed by a position in the queue's play order
+const TRACK_PATH_PREFIX: &str = "/org/clef/Track/";
+/// Followed by a smart playlist's id
+const PLAYLIST_PATH_PREFIX: &str = "/org/clef/Playlist/";
+/// The spec's track id for 'no track'
+const NO_TRACK_PATH: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// A handle to the mpris service thread; dropping it releases the bus name
+pub struct MediaControls {
+    to_service: Option<Sender<ServiceUpdate>>,
+    thread: Option<JoinHandle<()>>,
+    /// The last list sent, to avoid replacing it for every action
+    last_track_list: Option<ControlsTrackList>,
+}
+
+#[derive(Debug)]
+enum ServiceUpdate {
+    Metadata(ControlsMetadata),
+    Playback(MediaPlayback),
+    TrackList(ControlsTrackList),
+    Volume(f64),
+}
+
+impl MediaControls {
+    pub fn new(
+        controls_to_audio: Sender<AudioAction>,
+        db: SqlitePool,
+        volume: f64,
+    ) -> anyhow::Result<Self> {
+        let app = AppInterface {
+            controls_to_audio: controls_to_audio.clone(),
+        };
+        let player = PlayerInterface {
+            controls_to_audio: controls_to_audio.clone(),
+            metadata: None,
+            current_track: None,
+            playback: MediaPlayback::Stopped,
+            volume,
+        };
+        let track_list = TrackListInterface {
+            controls_to_audio: controls_to_audio.clone(),
+            track_list: ControlsTrackList::default(),
+        };
+        let playlists = PlaylistsInterface { controls_to_audio, db };
+
+        let connection = pollster::block_on(
+            ConnectionBuilder::session()?
+                .serve_at(OBJECT_PATH, app)?
+                .serve_at(OBJECT_PATH, player)?
+                .serve_at(OBJECT_PATH, track_list)?
+                .serve_at(OBJECT_PATH, playlists)?
+                .name(BUS_NAME)?
+                .build(),
+        )
+        .context("failed to connect to the session bus")?;
+
+        let (to_service, updates) = flume::unbounded();
+        let thread = std::thread::Builder::new()
+            .name("ClefMpris".to_string())
+            .spawn(move || {
+                if let Err(e) = pollster::block_on(run_service(connection, updates)) {
+                    error!("mpris service stopped: {e}");
+                }
+            })?;
+
+        Ok(Self {
+            to_service: Some(to_service),
+            thread: Some(thread),
+            last_track_list: None,
+        })
+    }
+
+    pub fn set_metadata(&mut self, metadata: MediaMetadata<'_>) -> anyhow::Result<()> {
+        self.send(ServiceUpdate::Metadata(metadata.into()))
+    }
+
+    pub fn set_playback(&mut self, playback: MediaPlayback) -> anyhow::Result<()> {
+        self.send(ServiceUpdate::Playback(playback))
+    }
+
+    pub fn set_volume(&mut self, volume: f64) -> anyhow::Result<()> {
+        self.send(ServiceUpdate::Volume(volume))
+    }
+
+    pub fn set_track_list(
+        &mut self,
+        track_list: ControlsTrackList,
+    ) -> anyhow::Result<()> {
+        if self.last_track_list.as_ref() == Some(&track_list) {
+            return Ok(());
+        }
+
+        self.send(ServiceUpdate::TrackList(track_list.clone()))?;
+        self.last_track_list = Some(track_list);
+
+        Ok(())
+    }
+
+    fn send(&self, update: ServiceUpdate) -> anyhow::Result<()> {
+        let to_service = self
+            .to_service
+            .as_ref()
+            .context("mpris service is closed")?;
+        to_service.send(update).context("mpris service stopped")
+    }
+}
+
+impl Drop for MediaControls {
+    fn drop(&mut self) {
+        // NOTE closing the channel ends the service loop, which drops the connection
+        self.to_service = None;
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("mpris service thread panicked");
+            }
+        }
+    }
+}
+
+impl From<MediaMetadata<'_>> for ControlsMetadata {
+    fn from(metadata: MediaMetadata<'_>) -> Self {
+        Self {
+            title: metadata.title.map(String::from),
+            album: metadata.album.map(String::from),
+            artist: metadata.artist.map(String::from),
+            cover_url: metadata.cover_url.map(String::from),
+            duration: metadata.duration,
+        }
+    }
+}
+
+async fn run_service(
+    connection: Connection,
+    updates: Receiver<ServiceUpdate>,
+) -> zbus::Result<()> {
+    let object_server = connection.object_server();
+    let player = object_server
+        .interface::<_, PlayerInterface>(OBJECT_PATH)
+        .await?;
+    let track_list = object_server
+        .interface::<_, TrackListInterface>(OBJECT_PATH)
+        .await?;
+
+    let ctxt = player.signal_context();
+
+    while let Ok(update) = updates.recv_async().await {
+        trace!("mpris update: {update:?}");
+
+        match update {
+            ServiceUpdate::Metadata(metadata) => {
+                let mut player = player.get_mut().await;
+                player.metadata = Some(metadata);
+                player.metadata_changed(ctxt).await?;
+            }
+
+            ServiceUpdate::Playback(playback) => {
+                let mut player = player.get_mut().await;
+                player.playback = playback;
+                player.playback_status_changed(ctxt).await?;
+            }
+
+            ServiceUpdate::Volume(volume) => {
+                let mut player = player.get_mut().await;
+                player.volume = volume;
+                player.volume_changed(ctxt).await?;
+            }
+
+            ServiceUpdate::TrackList(new_list) => {
+                let current = new_list.current;
+                let tracks = new_list.track_ids();
+                track_list.get_mut().await.track_list = new_list;
+
+                TrackListInterface::track_list_replaced(
+                    ctxt,
+                    tracks,
+                    track_path(current),
+                )
+                .await?;
+
+                // the current track's id is its position, which may have moved
+                let mut player = player.get_mut().await;
+                if player.current_track != Some(current) {
+                    player.current_track = Some(current);
+                    player.metadata_changed(ctxt).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct AppInterface {
+    controls_to_audio: Sender<AudioAction>,
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl AppInterface {
+    fn raise(&self) {
+        send_control_event(&self.controls_to_audio, MediaControlEvent::Raise);
+    }
+
+    fn quit(&self) {
+        send_control_event(&self.controls_to_audio, MediaControlEvent::Quit);
+    }
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn identity(&self) -> &str {
+        "Clef"
+    }
+
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct PlayerInterface {
+    controls_to_audio: Sender<AudioAction>,
+    metadata: Option<ControlsMetadata>,
+    /// The current song's position in the track list
+    current_track: Option<usize>,
+    playback: MediaPlayback,
+    /// 0 while muted
+    volume: f64,
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl PlayerInterface {
+    fn next(&self) {
+        send_control_event(&self.controls_to_audio, MediaControlEvent::Next);
+    }
+
+    fn previous(&self) {
+        send_control_event(&self.controls_to_audio, MediaControlEvent::Previous);
+    }
+
+    fn pause(&self) {
+        send_control_event(&self.controls_to_audio, MediaControlEvent::Pause);
+    }
+
+    fn play_pause(&self) {
+        send_control_event(&self.controls_to_audio, MediaControlEvent::Toggle);
+    }
+
+    fn stop(&self) {
+        send_control_event(&self.controls_to_audio, MediaControlEvent::Stop);
+    }
+
+    fn play(&self) {
+        send_control_event(&self.controls_to_audio, MediaControlEvent::Play);
+    }
+
+    /// An offset in microseconds; negative values seek backwards
+    fn seek(&self, offset: i64) {
+        let seconds = offset as f32 / 1_000_000.0;
+        send_action(
+            &self.controls_to_audio,
+            AudioAction::SeekBy(SeekOffset::Seconds(seconds)),
+        );
+    }
+
+    /// A position in microseconds; ignored if the track isn't the current one
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        let current = self.current_track.map(track_path);
+        if current.is_some_and(|current| current.as_str() != track_id.as_str()) {
+            info!("ignoring a position for another track: {track_id}");
+            return;
+        }
+
+        let Ok(micros) = u64::try_from(position) else {
+            return;
+        };
+
+        let position = MediaPosition(Duration::from_micros(micros));
+        send_control_event(
+            &self.controls_to_audio,
+            MediaControlEvent::SetPosition(position),
+        );
+    }
+
+    fn open_uri(&self, uri: String) {
+        send_control_event(&self.controls_to_audio, MediaControlEvent::OpenUri(uri));
+    }
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> &str {
+        match self.playback {
+            MediaPlayback::Playing { .. } => "Playing",
+            MediaPlayback::Paused { .. } => "Paused",
+            MediaPlayback::Stopped => "Stopped",
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<&'static str, Value<'static>> {
+        let Some(metadata) = &self.metadata else {
+            return HashMap::new();
+        };
+
+        let track_id = match self.current_track {
+            Some(position) => track_path(position),
+            None => no_track_path(),
+        };
+
+        metadata_dict(track_id, metadata)
+    }
+
+    /// In microseconds
+    #[dbus_interface(property)]
+    fn position(&self) -> i64 {
+        let position = match &self.playback {
+            MediaPlayback::Playing { progress: Some(position) }
+            | MediaPlayback::Paused { progress: Some(position) } => position.0,
+            _ => Duration::ZERO,
+        };
+
+        position.as_micros().try_into().unwrap_or_default()
+    }
+
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    /// NOTE the player unmutes for any new volume, so raising it restores the sound
+    #[dbus_interface(property)]
+    fn set_volume(&mut self, volume: f64) {
+        let volume = volume.clamp(0.0, 1.0) as f32;
+        send_action(&self.controls_to_audio, AudioAction::SetVolume(volume));
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+struct TrackListInterface {
+    controls_to_audio: Sender<AudioAction>,
+    track_list: ControlsTrackList,
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.TrackList")]
+impl TrackListInterface {
+    fn get_tracks_metadata(
+        &self,
+        track_ids: Vec<OwnedObjectPath>,
+    ) -> Vec<HashMap<&'static str, Value<'static>>> {
+        track_ids
+            .into_iter()
+            .filter_map(|track_id| {
+                let position = track_position(&track_id)?;
+                let metadata = self.track_list.tracks.get(position)?;
+
+                Some(metadata_dict(track_id, metadata))
+            })
+            .collect()
+    }
+
+    // NOTE the queue is only edited from the ui; CanEditTracks is false
+    fn add_track(
+        &self,
+        uri: String,
+        _after_track: ObjectPath<'_>,
+        _set_as_current: bool,
+    ) {
+        info!("ignoring request to add a track: {uri}");
+    }
+
+    fn remove_track(&self, track_id: ObjectPath<'_>) {
+        info!("ignoring request to remove a track: {track_id}");
+    }
+
+    fn go_to(&self, track_id: ObjectPath<'_>) {
+        match track_position(&track_id) {
+            Some(position) if position < self.track_list.tracks.len() => {
+                send_action(&self.controls_to_audio, AudioAction::GoTo(position));
+            }
+            _ => info!("ignoring unknown track: {track_id}"),
+        }
+    }
+
+    #[dbus_interface(signal)]
+    async fn track_list_replaced(
+        ctxt: &SignalContext<'_>,
+        tracks: Vec<OwnedObjectPath>,
+        current_track: OwnedObjectPath,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn tracks(&self) -> Vec<OwnedObjectPath> {
+        self.track_list.track_ids()
+    }
+
+    #[dbus_interface(property)]
+    fn can_edit_tracks(&self) -> bool {
+        false
+    }
+}
+
+/// A playlist as mpris describes it: (path, name, icon url)
+type MprisPlaylist = (OwnedObjectPath, String, String);
+
+struct PlaylistsInterface {
+    controls_to_audio: Sender<AudioAction>,
+    db: SqlitePool,
+}
+
+impl PlaylistsInterface {
+    /// NOTE these are read on every request; the ui can change them at any time
+    fn smart_playlists(&self) -> Vec<SmartPlaylist> {
+        let loaded = self
+            .db
+            .get()
+            .map_err(anyhow::Error::from)
+            .and_then(|mut conn| {
+                queries::all_smart_playlists(&mut conn).map_err(anyhow::Error::from)
+            });
+
+        loaded
+            .map_err(|e| error!("failed to load smart playlists for mpris: {e}"))
+            .unwrap_or_default()
+    }
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Playlists")]
+impl PlaylistsInterface {
+    fn activate_playlist(&self, playlist_id: ObjectPath<'_>) {
+        let id = playlist_id
+            .as_str()
+            .strip_prefix(PLAYLIST_PATH_PREFIX)
+            .and_then(|id| id.parse::<i32>().ok());
+
+        let playlist = self
+            .smart_playlists()
+            .into_iter()
+            .find(|playlist| Some(playlist.id.unpack()) == id);
+
+        match playlist {
+            Some(playlist) => send_action(
+                &self.controls_to_audio,
+                AudioAction::ActivatePlaylist(playlist.id),
+            ),
+            None => info!("ignoring unknown playlist: {playlist_id}"),
+        }
+    }
+
+    /// NOTE smart playlists are always listed by name; the order is ignored
+    fn get_playlists(
+        &self,
+        index: u32,
+        max_count: u32,
+        _order: String,
+        reverse_order: bool,
+    ) -> Vec<MprisPlaylist> {
+        let mut playlists = self.smart_playlists();
+        if reverse_order {
+            playlists.reverse();
+        }
+
+        playlists
+            .iter()
+            .skip(index as usize)
+            .take(max_count as usize)
+            .map(mpris_playlist)
+            .collect()
+    }
+
+    #[dbus_interface(property)]
+    fn playlist_count(&self) -> u32 {
+        self.smart_playlists().len() as u32
+    }
+
+    #[dbus_interface(property)]
+    fn orderings(&self) -> Vec<String> {
+        vec!["Alphabetical".to_string()]
+    }
+
+    /// NOTE a playing smart playlist is just a queue; none is ever 'active'
+    #[dbus_interface(property)]
+    fn active_playlist(&self) -> (bool, MprisPlaylist) {
+        let root = ObjectPath::from_static_str_unchecked("/").into();
+        (false, (root, String::new(), String::new()))
+    }
+}
+
+fn mpris_playlist(playlist: &SmartPlaylist) -> MprisPlaylist {
+    let path = format!("{PLAYLIST_PATH_PREFIX}{}", playlist.id.unpack());
+    let path = ObjectPath::try_from(path).expect("playlist ids make valid paths");
+
+    (path.into(), playlist.name.clone(), String::new())
+}
+
+fn track_path(position: usize) -> OwnedObjectPath {
+    let path = format!("{TRACK_PATH_PREFIX}{position}");
+    ObjectPath::try_from(path)
+        .expect("track positions make valid paths")
+        .into()
+}
+
+fn track_position(track_id: &ObjectPath<'_>) -> Option<usize> {
+    track_id
+        .as_str()
+        .strip_prefix(TRACK_PATH_PREFIX)?
+        .parse()
+        .ok()
+}
+
+fn no_track_path() -> OwnedObjectPath {
+    ObjectPath::from_static_str_unchecked(NO_TRACK_PATH).into()
+}
+
+impl ControlsTrackList {
+    fn track_ids(&self) -> Vec<OwnedObjectPath> {
+        (0..self.tracks.len()).map(track_path).collect()
+    }
+}
+
+fn metadata_dict(
+    track_id: OwnedObjectPath,
+    metadata: &ControlsMetadata,
+) -> HashMap<&'static str, Value<'static>> {
+    let mut dict = HashMap::new();
+    dict.insert("mpris:trackid", Value::new(track_id.into_inner()));
+
+    if let Some(length) = metadata.duration {
+        let micros = i64::try_from(length.as_micros()).unwrap_or(i64::MAX);
+        dict.insert("mpris:length", Value::new(micros));
+    }
+    if let Some(cover_url) = &metadata.cover_url {
+        dict.insert("mpris:artUrl", Value::new(cover_url.clone()));
+    }
+
+    if let Some(title) = &metadata.title {
+        dict.insert("xesam:title", Value::new(title.clone()));
+    }
+    if let Some(artist) = &metadata.artist {
+        dict.insert("xesam:artist", Value::new(vec![artist.clone()]));
+    }
+    if let Some(album) = &metadata.album {
+        dict.insert("xesam:album", Value::new(album.clone()));
+    }
+
+    dict
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_paths_round_trip_to_positions() {
+        assert_eq!(track_position(&track_path(0)), Some(0));
+        assert_eq!(track_position(&track_path(42)), Some(42));
+
+        let playlist = ObjectPath::try_from("/org/clef/Playlist/3").unwrap();
+        assert_eq!(track_position(&playlist), None);
+        assert_eq!(track_position(&no_track_path()), None);
+    }
+}
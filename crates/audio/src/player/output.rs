@@ -12,9 +12,34 @@ use std::result;
 use symphonia::core::audio::{AudioBufferRef, SignalSpec};
 use symphonia::core::units::Duration;
 
+use crate::resample_quality::ResampleQuality;
+
 pub trait AudioOutput {
     fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()>;
     fn flush(&mut self);
+    /// Sets a linear gain for following writes; 1.0 = unscaled
+    fn set_gain(&mut self, gain: f32);
+    /// Marks playback as deliberately paused,
+    /// so that the buffer running dry doesn't count as an underrun
+    fn set_paused(&mut self, paused: bool);
+    fn telemetry(&self) -> OutputTelemetry;
+}
+
+/// Diagnostics about an open output, for the debug overlay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputTelemetry {
+    /// The sample format sent to the device
+    pub sample_format: &'static str,
+    /// The (from, to) sample rates, in Hz, when resampling before the device
+    pub resampling: Option<(u32, u32)>,
+    /// The proportion of the output buffer that's full;
+    /// None = the buffer belongs to the sound server
+    pub buffer_fill: Option<f32>,
+    /// The length of the output buffer; None = the buffer belongs to the sound server
+    pub buffer_ms: Option<usize>,
+    /// The number of times the buffer ran dry during playback;
+    /// None = not visible to this output
+    pub underruns: Option<u64>,
 }
 
 #[allow(unused)]
@@ -27,13 +52,16 @@ pub enum AudioOutputError {
     PlayStreamError,
     #[error("StreamClosedError")]
     StreamClosedError,
+    /// The output follows the system default, which is now a different device
+    #[error("DefaultDeviceChangedError")]
+    DefaultDeviceChangedError,
 }
 
 pub type Result<T> = result::Result<T, AudioOutputError>;
 
 #[cfg(target_os = "linux")]
 mod pulseaudio {
-    use super::{AudioOutput, AudioOutputError, Result};
+    use super::{AudioOutput, AudioOutputError, OutputTelemetry, Result};
 
     use symphonia::core::audio::*;
     use symphonia::core::units::Duration;
@@ -46,12 +74,16 @@ mod pulseaudio {
     pub struct PulseAudioOutput {
         pa: psimple::Simple,
         sample_buf: RawSampleBuffer<f32>,
+        gain: f32,
+        /// interleaved bytes scaled by gain; unused at unity gain
+        gained_buf: Vec<u8>,
     }
 
     impl PulseAudioOutput {
         pub fn try_open(
             spec: SignalSpec,
             duration: Duration,
+            device_name: Option<&str>,
         ) -> Result<Box<dyn AudioOutput>> {
             // An interleaved buffer is required to send data to PulseAudio. Use a SampleBuffer to
             // move data between Symphonia AudioBuffers and the byte buffers required by PulseAudio.
@@ -84,7 +116,7 @@ mod pulseaudio {
                 None,                               // Use default server
                 "Clef",                             // Application name
                 pulse::stream::Direction::Playback, // Playback stream
-                None,                               // Default playback device
+                device_name,                        // Playback device; None = default
                 "Music",                            // Description of the stream
                 &pa_spec,                           // Signal specificaiton
                 pa_ch_map.as_ref(),                 // Channel map
@@ -92,7 +124,12 @@ mod pulseaudio {
             );
 
             match pa_result {
-                Ok(pa) => Ok(Box::new(PulseAudioOutput { pa, sample_buf })),
+                Ok(pa) => Ok(Box::new(PulseAudioOutput {
+                    pa,
+                    sample_buf,
+                    gain: 1.0,
+                    gained_buf: Vec::new(),
+                })),
                 Err(err) => {
                     error!("audio output stream open error: {}", err);
 
@@ -112,8 +149,22 @@ mod pulseaudio {
             // Interleave samples from the audio buffer into the sample buffer.
             self.sample_buf.copy_interleaved_ref(decoded);
 
+            let bytes = if self.gain == 1.0 {
+                self.sample_buf.as_bytes()
+            } else {
+                let gain = self.gain;
+                let scaled = self.sample_buf.as_bytes().chunks_exact(4).flat_map(|b| {
+                    let sample = f32::from_ne_bytes([b[0], b[1], b[2], b[3]]);
+                    (sample * gain).to_ne_bytes()
+                });
+
+                self.gained_buf.clear();
+                self.gained_buf.extend(scaled);
+                &self.gained_buf
+            };
+
             // Write interleaved samples to PulseAudio.
-            match self.pa.write(self.sample_buf.as_bytes()) {
+            match self.pa.write(bytes) {
                 Err(err) => {
                     error!("audio output stream write error: {}", err);
 
@@ -127,6 +178,24 @@ mod pulseaudio {
             // Flush is best-effort, ignore the returned result.
             let _ = self.pa.drain();
         }
+
+        fn set_gain(&mut self, gain: f32) {
+            self.gain = gain;
+        }
+
+        fn set_paused(&mut self, _paused: bool) {}
+
+        // NOTE pulse simple doesn't expose the server's buffer or underflows;
+        // resampling also happens in the server, if it's needed
+        fn telemetry(&self) -> OutputTelemetry {
+            OutputTelemetry {
+                sample_format: "f32",
+                resampling: None,
+                buffer_fill: None,
+                buffer_ms: None,
+                underruns: None,
+            }
+        }
     }
 
     /// Maps a set of Symphonia `Channels` to a PulseAudio channel map.
@@ -179,11 +248,17 @@ mod pulseaudio {
 
 #[cfg(not(target_os = "linux"))]
 mod cpal {
-    use super::{AudioOutput, AudioOutputError, Result};
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration as StdDuration, Instant};
+
+    use super::{AudioOutput, AudioOutputError, OutputTelemetry, Result};
+    use crate::player::device_config::find_output_device;
+    use crate::resample_quality::ResampleQuality;
     use crate::resampler::Resampler;
 
     use symphonia::core::audio::{AudioBufferRef, RawSample, SampleBuffer, SignalSpec};
-    use symphonia::core::conv::{ConvertibleSample, IntoSample};
+    use symphonia::core::conv::{ConvertibleSample, FromSample, IntoSample};
     use symphonia::core::units::Duration;
 
     use cpal;
@@ -192,6 +267,11 @@ mod cpal {
 
     use log::{error, trace};
 
+    /// How long to wait for the stream to make room in the ring buffer before checking again
+    const RING_BUF_POLL_INTERVAL: StdDuration = StdDuration::from_millis(5);
+    /// How often to check whether the system default device changed
+    const DEFAULT_DEVICE_CHECK_INTERVAL: StdDuration = StdDuration::from_secs(1);
+
     pub struct CpalAudioOutput;
 
     trait AudioOutputSample:
@@ -212,19 +292,28 @@ mod cpal {
         pub fn try_open(
             spec: SignalSpec,
             duration: Duration,
+            device_name: Option<&str>,
+            buffer_ms: usize,
+            resample_quality: ResampleQuality,
         ) -> Result<Box<dyn AudioOutput>> {
             // Get default host.
             let host = cpal::default_host();
 
-            // Get the default audio output device.
-            let device = match host.default_output_device() {
+            // Get the chosen audio output device, or the default.
+            let device = match find_output_device(&host, device_name) {
                 Some(device) => device,
                 _ => {
-                    error!("failed to get default audio output device");
+                    error!("failed to get audio output device: {device_name:?}");
                     return Err(AudioOutputError::OpenStreamError);
                 }
             };
 
+            // Only an output opened on the default follows it to another device
+            let default_device_name = match device_name {
+                Some(_) => None,
+                None => device.name().ok(),
+            };
+
             let config = match device.default_output_config() {
                 Ok(config) => config,
                 Err(err) => {
@@ -235,15 +324,30 @@ mod cpal {
 
             // Select proper playback routine based on sample format.
             match config.sample_format() {
-                cpal::SampleFormat::F32 => {
-                    CpalAudioOutputImpl::<f32>::try_open(spec, duration, &device)
-                }
-                cpal::SampleFormat::I16 => {
-                    CpalAudioOutputImpl::<i16>::try_open(spec, duration, &device)
-                }
-                cpal::SampleFormat::U16 => {
-                    CpalAudioOutputImpl::<u16>::try_open(spec, duration, &device)
-                }
+                cpal::SampleFormat::F32 => CpalAudioOutputImpl::<f32>::try_open(
+                    spec,
+                    duration,
+                    &device,
+                    default_device_name,
+                    buffer_ms,
+                    resample_quality,
+                ),
+                cpal::SampleFormat::I16 => CpalAudioOutputImpl::<i16>::try_open(
+                    spec,
+                    duration,
+                    &device,
+                    default_device_name,
+                    buffer_ms,
+                    resample_quality,
+                ),
+                cpal::SampleFormat::U16 => CpalAudioOutputImpl::<u16>::try_open(
+                    spec,
+                    duration,
+                    &device,
+                    default_device_name,
+                    buffer_ms,
+                    resample_quality,
+                ),
             }
         }
     }
@@ -252,10 +356,26 @@ mod cpal {
     where
         T: AudioOutputSample,
     {
+        /// kept to inspect the fill level
+        ring_buf: SpscRb<T>,
         ring_buf_producer: rb::Producer<T>,
         sample_buf: SampleBuffer<T>,
         stream: cpal::Stream,
         resampler: Option<Resampler<T>>,
+        /// (from, to) sample rates; None = not resampling
+        resampling: Option<(u32, u32)>,
+        buffer_ms: usize,
+        gain: f32,
+        /// interleaved samples scaled by gain; unused at unity gain
+        gained_buf: Vec<T>,
+        /// shared with the stream callback
+        paused: Arc<AtomicBool>,
+        underruns: Arc<AtomicU64>,
+        /// set by the error callback when the device goes away
+        device_lost: Arc<AtomicBool>,
+        /// The default device this was opened on; None = opened on a chosen device
+        default_device_name: Option<String>,
+        checked_default_at: Instant,
     }
 
     impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
@@ -263,6 +383,9 @@ mod cpal {
             spec: SignalSpec,
             duration: Duration,
             device: &cpal::Device,
+            default_device_name: Option<String>,
+            buffer_ms: usize,
+            resample_quality: ResampleQuality,
         ) -> Result<Box<dyn AudioOutput>> {
             let num_channels = spec.channels.count();
 
@@ -281,13 +404,23 @@ mod cpal {
                     .config()
             };
 
-            // Create a ring buffer with a capacity for up-to 200ms of audio.
-            let ring_len = ((200 * config.sample_rate.0 as usize) / 1000) * num_channels;
+            // Create a ring buffer with a capacity for up-to buffer_ms of audio.
+            let ring_len =
+                ((buffer_ms * config.sample_rate.0 as usize) / 1000) * num_channels;
 
             let ring_buf = SpscRb::new(ring_len);
             let (ring_buf_producer, ring_buf_consumer) =
                 (ring_buf.producer(), ring_buf.consumer());
 
+            let paused = Arc::new(AtomicBool::new(false));
+            let underruns = Arc::new(AtomicU64::new(0));
+            let (callback_paused, callback_underruns) =
+                (paused.clone(), underruns.clone());
+            let device_lost = Arc::new(AtomicBool::new(false));
+            let callback_device_lost = device_lost.clone();
+            // Nothing has been written yet, so the first callbacks aren't underruns
+            let mut starved = true;
+
             let stream_result = device.build_output_stream(
                 &config,
                 move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
@@ -295,10 +428,23 @@ mod cpal {
                     // output.
                     let written = ring_buf_consumer.read(data).unwrap_or(0);
 
+                    // Count each time the buffer runs dry, unless it's draining after a pause.
+                    let short = written < data.len();
+                    if short && !starved && !callback_paused.load(Ordering::Relaxed) {
+                        callback_underruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                    starved = short;
+
                     // Mute any remaining samples.
                     data[written..].iter_mut().for_each(|s| *s = T::MID);
                 },
-                move |err| error!("audio output error: {}", err),
+                move |err| {
+                    error!("audio output error: {}", err);
+
+                    if let cpal::StreamError::DeviceNotAvailable = err {
+                        callback_device_lost.store(true, Ordering::Relaxed);
+                    }
+                },
             );
 
             let stream = match stream_result {
@@ -323,17 +469,30 @@ mod cpal {
                 Some(Resampler::new(
                     spec,
                     config.sample_rate.0 as usize,
-                    duration,
+                    resample_quality,
                 ))
             } else {
                 None
             };
+            let resampling = resampler
+                .as_ref()
+                .map(|_| (spec.rate, config.sample_rate.0));
 
             Ok(Box::new(CpalAudioOutputImpl {
+                ring_buf,
                 ring_buf_producer,
                 sample_buf,
                 stream,
                 resampler,
+                resampling,
+                buffer_ms,
+                gain: 1.0,
+                gained_buf: Vec::new(),
+                paused,
+                underruns,
+                device_lost,
+                default_device_name,
+                checked_default_at: Instant::now(),
             }))
         }
     }
@@ -345,7 +504,11 @@ mod cpal {
                 return Ok(());
             }
 
-            let mut samples: &[T] = if let Some(resampler) = &mut self.resampler {
+            if self.default_device_changed() {
+                return Err(AudioOutputError::DefaultDeviceChangedError);
+            }
+
+            let samples: &[T] = if let Some(resampler) = &mut self.resampler {
                 // Resampling is required. The resampler will return interleaved
                 // samples in the correct sample format.
                 match resampler.resample(decoded) {
@@ -359,41 +522,139 @@ mod cpal {
                 self.sample_buf.samples()
             };
 
-            // Write all the interleaved samples to the ring buffer.
-            while let Some(written) = self.ring_buf_producer.write_blocking(samples) {
-                samples = &samples[written..];
-            }
+            let samples: &[T] = if self.gain == 1.0 {
+                samples
+            } else {
+                let gain = self.gain;
+                let scaled = samples.iter().map(|sample| {
+                    let sample: f32 = (*sample).into_sample();
+                    T::from_sample(sample * gain)
+                });
+
+                self.gained_buf.clear();
+                self.gained_buf.extend(scaled);
+                &self.gained_buf
+            };
 
-            Ok(())
+            // Write all the interleaved samples to the ring buffer.
+            write_all(&self.ring_buf_producer, &self.device_lost, samples)
         }
 
         fn flush(&mut self) {
             // If there is a resampler, then it may need to be flushed
             // depending on the number of samples it has.
             if let Some(resampler) = &mut self.resampler {
-                let mut remaining_samples = resampler.flush().unwrap_or_default();
-
-                while let Some(written) =
-                    self.ring_buf_producer.write_blocking(remaining_samples)
-                {
-                    remaining_samples = &remaining_samples[written..];
-                }
+                let remaining_samples = resampler.flush().unwrap_or_default();
+
+                // Flush is best-effort; the device may already be gone.
+                let _ = write_all(
+                    &self.ring_buf_producer,
+                    &self.device_lost,
+                    remaining_samples,
+                );
             }
 
             // Flush is best-effort, ignore the returned result.
             let _ = self.stream.pause();
         }
+
+        fn set_gain(&mut self, gain: f32) {
+            self.gain = gain;
+        }
+
+        fn set_paused(&mut self, paused: bool) {
+            self.paused.store(paused, Ordering::Relaxed);
+        }
+
+        fn telemetry(&self) -> OutputTelemetry {
+            let buffer_fill =
+                self.ring_buf.count() as f32 / self.ring_buf.capacity() as f32;
+
+            OutputTelemetry {
+                sample_format: std::any::type_name::<T>(),
+                resampling: self.resampling,
+                buffer_fill: Some(buffer_fill),
+                buffer_ms: Some(self.buffer_ms),
+                underruns: Some(self.underruns.load(Ordering::Relaxed)),
+            }
+        }
+    }
+
+    impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
+        /// Occasionally checks whether the system default moved to another device,
+        /// for outputs opened on the default
+        fn default_device_changed(&mut self) -> bool {
+            let Some(opened_on) = &self.default_device_name else {
+                return false;
+            };
+            if self.checked_default_at.elapsed() < DEFAULT_DEVICE_CHECK_INTERVAL {
+                return false;
+            }
+            self.checked_default_at = Instant::now();
+
+            let host = cpal::default_host();
+            let current = host
+                .default_output_device()
+                .and_then(|device| device.name().ok());
+
+            current.map(|name| name != *opened_on).unwrap_or_default()
+        }
+    }
+
+    /// Writes all the samples to the ring buffer, waiting for the stream to make room.
+    /// Fails instead of waiting forever if the device goes away.
+    fn write_all<T: AudioOutputSample>(
+        producer: &rb::Producer<T>,
+        device_lost: &AtomicBool,
+        mut samples: &[T],
+    ) -> Result<()> {
+        while !samples.is_empty() {
+            if device_lost.load(Ordering::Relaxed) {
+                return Err(AudioOutputError::StreamClosedError);
+            }
+
+            match producer.write(samples) {
+                Ok(written) => samples = &samples[written..],
+                Err(_full) => std::thread::sleep(RING_BUF_POLL_INTERVAL),
+            }
+        }
+
+        Ok(())
     }
 }
 
+/// device_name: the output device to use; None = the default device
+/// buffer_ms: unused; pulse manages the buffer on the server
+/// resample_quality: unused; pulse resamples on the server
 #[allow(unused)]
 #[cfg(target_os = "linux")]
-pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
-    pulseaudio::PulseAudioOutput::try_open(spec, duration)
+pub fn try_open(
+    spec: SignalSpec,
+    duration: Duration,
+    device_name: Option<&str>,
+    buffer_ms: usize,
+    resample_quality: ResampleQuality,
+) -> Result<Box<dyn AudioOutput>> {
+    pulseaudio::PulseAudioOutput::try_open(spec, duration, device_name)
 }
 
+/// device_name: the output device to use; None = the default device
+/// buffer_ms: the length of audio to buffer ahead of the device
+/// resample_quality: used when the song doesn't match the device's sample rate
 #[allow(unused)]
 #[cfg(not(target_os = "linux"))]
-pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
-    cpal::CpalAudioOutput::try_open(spec, duration)
+pub fn try_open(
+    spec: SignalSpec,
+    duration: Duration,
+    device_name: Option<&str>,
+    buffer_ms: usize,
+    resample_quality: ResampleQuality,
+) -> Result<Box<dyn AudioOutput>> {
+    cpal::CpalAudioOutput::try_open(
+        spec,
+        duration,
+        device_name,
+        buffer_ms,
+        resample_quality,
+    )
 }
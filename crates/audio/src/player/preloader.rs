@@ -18,20 +18,25 @@ use crate::track_info::{first_supported_track, TrackInfo};
 
 #[allow(unused)]
 #[cfg(not(target_os = "linux"))]
-use super::device_config::CpalDeviceConfig;
+use cpal::SupportedStreamConfig;
 
 pub struct Preloader {
     inbox: Receiver<PreloaderAction>,
     to_player: Sender<PreloaderEffect>,
 
+    /// The config of the player's output device, for resampling preloaded packets;
+    /// None until the player sends it
     #[allow(unused)]
     #[cfg(not(target_os = "linux"))]
-    device_config: CpalDeviceConfig,
+    stream_config: Option<SupportedStreamConfig>,
 }
 
 #[derive(Debug)]
 pub enum PreloaderAction {
     Load(Utf8PathBuf),
+    /// The player's output device config, sent at startup and after switching devices
+    #[cfg(not(target_os = "linux"))]
+    SetStreamConfig(SupportedStreamConfig),
 }
 
 #[derive(Debug)]
@@ -88,18 +93,7 @@ impl Preloader {
         std::thread::Builder::new()
             .name("ClefAudioPreloader".to_string())
             .spawn(move || {
-                #[allow(unused)]
-                #[cfg(not(target_os = "linux"))]
-                let device_config = CpalDeviceConfig::get_default()
-                    .expect("failed to get default device config");
-
-                let preloader = Self::new(
-                    inbox,
-                    to_player.clone(),
-                    #[allow(unused)]
-                    #[cfg(not(target_os = "linux"))]
-                    device_config,
-                );
+                let preloader = Self::new(inbox, to_player.clone());
 
                 if let Err(err) = preloader.run_loop() {
                     to_player.send(PreloaderEffect::PreloaderDied).ok();
@@ -117,14 +111,14 @@ impl Preloader {
     pub fn new(
         inbox: Receiver<PreloaderAction>,
         to_player: Sender<PreloaderEffect>,
-
-        #[allow(unused)]
-        #[cfg(not(target_os = "linux"))]
-        device_config: CpalDeviceConfig,
     ) -> Self {
         #[allow(unused)]
         #[cfg(not(target_os = "linux"))]
-        let new = Self { inbox, to_player, device_config };
+        let new = Self {
+            inbox,
+            to_player,
+            stream_config: None,
+        };
 
         #[allow(unused)]
         #[cfg(target_os = "linux")]
@@ -140,7 +134,7 @@ impl Preloader {
 
         #[allow(unused)]
         #[cfg(not(target_os = "linux"))]
-        let Preloader { inbox, to_player, device_config } = self;
+        let Preloader { inbox, to_player, mut stream_config } = self;
 
         loop {
             let action = inbox.recv().map_err(|_| PreloaderError::Disconnected)?;
@@ -149,6 +143,14 @@ impl Preloader {
 
             let content = match action {
                 PreloaderAction::Load(path) => preload(path)?,
+
+                // NOTE this isn't read until the preloader does its own resampling
+                #[allow(unused)]
+                #[cfg(not(target_os = "linux"))]
+                PreloaderAction::SetStreamConfig(config) => {
+                    stream_config = Some(config);
+                    continue;
+                }
             };
 
             trace!("Finished preload");
@@ -0,0 +1,148 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// ReplayGain tags for a song; gains are in dB, and peaks are linear sample amplitudes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+// NOTE the values are never nan; parse_gain and parse_peak only accept finite values
+impl Eq for ReplayGain {}
+
+/// Which gain tag to apply during playback
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGainMode {
+    Off,
+    #[default]
+    Track,
+    Album,
+}
+
+impl ReplayGainMode {
+    pub const ALL: [ReplayGainMode; 3] = [Self::Track, Self::Album, Self::Off];
+}
+
+impl std::fmt::Display for ReplayGainMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Off => "Off",
+            Self::Track => "Track Gain",
+            Self::Album => "Album Gain",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for ReplayGainMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "track" => Ok(Self::Track),
+            "album" => Ok(Self::Album),
+            other => anyhow::bail!("unknown replaygain mode: {other}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+    /// Added to the tagged gain, in dB
+    pub preamp_db: f32,
+}
+
+impl ReplayGain {
+    /// The linear gain to scale samples by, or None to leave them alone.
+    /// Falls back to the other kind of tag when the preferred one is missing,
+    /// and is limited by the peak so that boosted songs don't clip.
+    pub fn linear_gain(&self, settings: &ReplayGainSettings) -> Option<f32> {
+        let track = self.track_gain.map(|gain| (gain, self.track_peak));
+        let album = self.album_gain.map(|gain| (gain, self.album_peak));
+
+        let (gain_db, peak) = match settings.mode {
+            ReplayGainMode::Off => None,
+            ReplayGainMode::Track => track.or(album),
+            ReplayGainMode::Album => album.or(track),
+        }?;
+
+        let gain = db_to_linear(gain_db + settings.preamp_db);
+
+        match peak {
+            Some(peak) if peak > 0.0 => Some(gain.min(1.0 / peak)),
+            _ => Some(gain),
+        }
+    }
+}
+
+fn db_to_linear(db: f32) -> f32 {
+    10_f32.powf(db / 20.0)
+}
+
+/// Parses a gain tag like '-6.54 dB'
+pub fn parse_gain(tag: &str) -> Option<f32> {
+    let tag = tag.trim();
+    let number = tag
+        .strip_suffix("dB")
+        .or_else(|| tag.strip_suffix("db"))
+        .unwrap_or(tag);
+
+    parse_finite(number)
+}
+
+/// Parses a peak tag like '0.988525'
+pub fn parse_peak(tag: &str) -> Option<f32> {
+    parse_finite(tag).filter(|peak| *peak >= 0.0)
+}
+
+fn parse_finite(number: &str) -> Option<f32> {
+    let number: f32 = number.trim().parse().ok()?;
+
+    number.is_finite().then_some(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_gain_handles_units_and_signs() {
+        assert_eq!(parse_gain("-6.54 dB"), Some(-6.54));
+        assert_eq!(parse_gain("+2.10 dB"), Some(2.1));
+        assert_eq!(parse_gain("1.5"), Some(1.5));
+        assert_eq!(parse_gain("NaN dB"), None);
+        assert_eq!(parse_gain("loud"), None);
+    }
+
+    #[test]
+    fn linear_gain_falls_back_and_prevents_clipping() {
+        let album_only = ReplayGain {
+            album_gain: Some(-6.0),
+            ..Default::default()
+        };
+        let track_mode = ReplayGainSettings::default();
+        let gain = album_only.linear_gain(&track_mode).unwrap();
+        assert!((gain - 0.501).abs() < 0.001);
+
+        let boosted = ReplayGain {
+            track_gain: Some(6.0),
+            track_peak: Some(0.8),
+            ..Default::default()
+        };
+        assert_eq!(boosted.linear_gain(&track_mode), Some(1.25));
+
+        let off = ReplayGainSettings {
+            mode: ReplayGainMode::Off,
+            preamp_db: 0.0,
+        };
+        assert_eq!(boosted.linear_gain(&off), None);
+    }
+}
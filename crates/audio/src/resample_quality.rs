@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+/// How carefully to convert songs to the output device's sample rate;
+/// the choices trade cpu and latency for a cleaner filter. Saved by name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResampleQuality {
+    /// Short filters; the least cpu, with some aliasing near the top of the range
+    Fast,
+    #[default]
+    Medium,
+    /// Long filters; the most cpu and latency, for the cleanest treble
+    High,
+}
+
+impl ResampleQuality {
+    pub const ALL: [ResampleQuality; 3] = [Self::Fast, Self::Medium, Self::High];
+
+    /// The input frames resampled together; larger buffers more audio before the device
+    pub fn chunk_frames(self) -> usize {
+        match self {
+            Self::Fast => 1024,
+            Self::Medium => 2048,
+            Self::High => 4096,
+        }
+    }
+
+    /// The number of ffts each chunk is split into.
+    /// NOTE each fft is one filter, so fewer and longer ffts filter more sharply
+    pub fn sub_chunks(self) -> usize {
+        match self {
+            Self::Fast => 4,
+            Self::Medium => 2,
+            Self::High => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for ResampleQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Fast => "Fast",
+            Self::Medium => "Medium",
+            Self::High => "High",
+        };
+
+        write!(f, "{name}")
+    }
+}
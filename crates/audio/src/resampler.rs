@@ -9,6 +9,8 @@ use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Signal, SignalSpec};
 use symphonia::core::conv::{FromSample, IntoSample};
 use symphonia::core::sample::Sample;
 
+use crate::resample_quality::ResampleQuality;
+
 pub struct Resampler<T> {
     resampler: rubato::FftFixedIn<f32>,
     input: Vec<Vec<f32>>,
@@ -21,7 +23,8 @@ impl<T> Resampler<T>
 where
     T: Sample + FromSample<f32> + IntoSample<f32>,
 {
-    fn resample_inner(&mut self) -> &[T] {
+    /// Resamples one chunk, appending it to the interleaved output
+    fn resample_inner(&mut self) {
         {
             let mut input: arrayvec::ArrayVec<&[f32], 32> = Default::default();
 
@@ -46,15 +49,29 @@ where
 
         // Interleave the planar samples from Rubato.
         let num_channels = self.output.len();
+        let start = self.interleaved.len();
 
         self.interleaved
-            .resize(num_channels * self.output[0].len(), T::MID);
+            .resize(start + num_channels * self.output[0].len(), T::MID);
 
-        for (i, frame) in self.interleaved.chunks_exact_mut(num_channels).enumerate() {
+        for (i, frame) in self.interleaved[start..]
+            .chunks_exact_mut(num_channels)
+            .enumerate()
+        {
             for (ch, s) in frame.iter_mut().enumerate() {
                 *s = self.output[ch][i].into_sample();
             }
         }
+    }
+
+    /// Resamples every whole chunk in the input buffer.
+    fn resample_chunks(&mut self) -> &[T] {
+        self.interleaved.clear();
+
+        // NOTE a packet can hold more than one chunk, depending on the quality
+        while self.input[0].len() >= self.duration {
+            self.resample_inner();
+        }
 
         &self.interleaved
     }
@@ -64,15 +81,19 @@ impl<T> Resampler<T>
 where
     T: Sample + FromSample<f32> + IntoSample<f32>,
 {
-    pub fn new(spec: SignalSpec, to_sample_rate: usize, duration: u64) -> Self {
-        let duration = duration as usize;
+    pub fn new(
+        spec: SignalSpec,
+        to_sample_rate: usize,
+        quality: ResampleQuality,
+    ) -> Self {
+        let duration = quality.chunk_frames();
         let num_channels = spec.channels.count();
 
         let resampler = rubato::FftFixedIn::<f32>::new(
             spec.rate as usize,
             to_sample_rate,
             duration,
-            2,
+            quality.sub_chunks(),
             num_channels,
         )
         .unwrap();
@@ -102,7 +123,7 @@ where
             return None;
         }
 
-        Some(self.resample_inner())
+        Some(self.resample_chunks())
     }
 
     /// Resample any remaining samples in the resample buffer.
@@ -123,7 +144,7 @@ where
             }
         }
 
-        Some(self.resample_inner())
+        Some(self.resample_chunks())
     }
 }
 
@@ -0,0 +1,271 @@
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::player::QueuedSong;
+
+/// How many of the shuffled songs to choose the next one from, when ordering by feel.
+/// Smaller keeps more of the shuffle; larger makes smoother runs.
+const SMOOTH_CANDIDATES: usize = 8;
+
+/// The distance to assume for a missing tempo or key
+const UNKNOWN_DISTANCE: f64 = 1.5;
+
+/// A percent change in tempo that's about as jarring as one step around the key wheel
+const BPM_PERCENT_PER_KEY_STEP: f64 = 4.0;
+
+/// Arranges the upcoming songs when shuffle is on
+pub trait UpcomingOrder: std::fmt::Debug {
+    fn arrange(
+        &self,
+        current: &QueuedSong,
+        upcoming: &mut [QueuedSong],
+        rng: &mut dyn RngCore,
+    );
+}
+
+/// The orders to choose from in the settings; saved by name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShuffleOrder {
+    #[default]
+    Random,
+    /// Random, but avoiding big jumps in tempo or key, from the bpm and key tags
+    Smooth,
+}
+
+impl ShuffleOrder {
+    pub const ALL: [ShuffleOrder; 2] = [Self::Random, Self::Smooth];
+
+    pub fn upcoming_order(self) -> Box<dyn UpcomingOrder> {
+        match self {
+            Self::Random => Box::new(Random),
+            Self::Smooth => Box::new(Smooth { candidates: SMOOTH_CANDIDATES }),
+        }
+    }
+}
+
+impl std::fmt::Display for ShuffleOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Random => "Random",
+            Self::Smooth => "By Tempo and Key",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug)]
+struct Random;
+
+impl UpcomingOrder for Random {
+    fn arrange(
+        &self,
+        _current: &QueuedSong,
+        upcoming: &mut [QueuedSong],
+        rng: &mut dyn RngCore,
+    ) {
+        upcoming.shuffle(rng);
+    }
+}
+
+/// Shuffles, then picks each next song from the first few candidates,
+/// choosing the one closest in tempo and key to the song before it
+#[derive(Debug)]
+struct Smooth {
+    candidates: usize,
+}
+
+impl UpcomingOrder for Smooth {
+    fn arrange(
+        &self,
+        current: &QueuedSong,
+        upcoming: &mut [QueuedSong],
+        rng: &mut dyn RngCore,
+    ) {
+        upcoming.shuffle(rng);
+
+        let mut previous = Feel::of(current);
+        for index in 0..upcoming.len() {
+            let end = (index + self.candidates).min(upcoming.len());
+            let closest = (index..end)
+                .min_by(|&a, &b| {
+                    let a = previous.distance(&Feel::of(&upcoming[a]));
+                    let b = previous.distance(&Feel::of(&upcoming[b]));
+                    a.total_cmp(&b)
+                })
+                .unwrap_or(index);
+
+            upcoming.swap(index, closest);
+            previous = Feel::of(&upcoming[index]);
+        }
+    }
+}
+
+/// The parts of a song that make a jump to the next one jarring
+#[derive(Debug, Clone, Copy)]
+struct Feel {
+    bpm: Option<f64>,
+    key: Option<CamelotKey>,
+}
+
+impl Feel {
+    fn of(song: &QueuedSong) -> Self {
+        Self { bpm: song.bpm, key: song.key }
+    }
+
+    fn distance(&self, other: &Feel) -> f64 {
+        let key = match (self.key, other.key) {
+            (Some(a), Some(b)) => f64::from(a.steps_to(b)),
+            _ => UNKNOWN_DISTANCE,
+        };
+        let bpm = match (self.bpm, other.bpm) {
+            (Some(a), Some(b)) => bpm_percent_change(a, b) / BPM_PERCENT_PER_KEY_STEP,
+            _ => UNKNOWN_DISTANCE,
+        };
+
+        key + bpm
+    }
+}
+
+/// NOTE double and half time count as the same tempo
+fn bpm_percent_change(from: f64, to: f64) -> f64 {
+    [to, to * 2.0, to / 2.0]
+        .into_iter()
+        .map(|to| (from.max(to) / from.min(to) - 1.0) * 100.0)
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// A musical key as a position on the Camelot wheel, where neighbors mix well
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CamelotKey {
+    /// 1 through 12, in fifths
+    pub number: u8,
+    pub minor: bool,
+}
+
+impl CamelotKey {
+    /// Reads Camelot ('8A') or note names ('Am', 'F# minor', 'Eb'); None for anything else
+    pub fn parse(tag: &str) -> Option<Self> {
+        let tag = tag.trim();
+
+        if let Some(key) = Self::parse_camelot(tag) {
+            return Some(key);
+        }
+
+        let mut chars = tag.chars();
+        let note = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+
+        let rest = chars.as_str();
+        let (pitch_class, mode) = match rest.chars().next() {
+            Some('#' | '♯') => (note + 1, &rest[rest.chars().next()?.len_utf8()..]),
+            Some('b' | '♭') => (note + 11, &rest[rest.chars().next()?.len_utf8()..]),
+            _ => (note, rest),
+        };
+
+        let minor = match mode.trim().to_lowercase().as_str() {
+            "" | "maj" | "major" => false,
+            "m" | "min" | "minor" => true,
+            _ => return None,
+        };
+
+        // NOTE a minor key sits with its relative major, three semitones up
+        let major_pitch_class = if minor {
+            (pitch_class + 3) % 12
+        } else {
+            pitch_class % 12
+        };
+        let number = ((major_pitch_class * 7) % 12 + 7) % 12 + 1;
+
+        Some(Self { number, minor })
+    }
+
+    fn parse_camelot(tag: &str) -> Option<Self> {
+        let (number, letter) = tag.split_at(tag.len().checked_sub(1)?);
+        let number: u8 = number.parse().ok()?;
+        if !(1..=12).contains(&number) {
+            return None;
+        }
+
+        match letter {
+            "A" | "a" => Some(Self { number, minor: true }),
+            "B" | "b" => Some(Self { number, minor: false }),
+            _ => None,
+        }
+    }
+
+    /// Steps around the wheel, plus one for switching between major and minor
+    fn steps_to(self, other: CamelotKey) -> u8 {
+        let apart =
+            (i16::from(self.number) - i16::from(other.number)).rem_euclid(12) as u8;
+
+        apart.min(12 - apart) + u8::from(self.minor != other.minor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+    use clef_db::queries::SongId;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn keys_parse_from_camelot_and_note_names() {
+        let key = |number, minor| Some(CamelotKey { number, minor });
+
+        assert_eq!(CamelotKey::parse("8A"), key(8, true));
+        assert_eq!(CamelotKey::parse("12b"), key(12, false));
+        assert_eq!(CamelotKey::parse("Am"), key(8, true));
+        assert_eq!(CamelotKey::parse("C"), key(8, false));
+        assert_eq!(CamelotKey::parse("F# minor"), key(11, true));
+        assert_eq!(CamelotKey::parse("Eb"), key(5, false));
+        assert_eq!(CamelotKey::parse("B"), key(1, false));
+
+        assert_eq!(CamelotKey::parse("13A"), None);
+        assert_eq!(CamelotKey::parse("H"), None);
+        assert_eq!(CamelotKey::parse(""), None);
+    }
+
+    #[test]
+    fn smooth_order_follows_the_closest_tempo_and_key() {
+        let song = |id, bpm, key: &str| QueuedSong {
+            id: SongId::new(id),
+            path: Utf8PathBuf::from(format!("{id}")),
+            title: None,
+            artist: None,
+            album_title: None,
+            resized_art: None,
+            duration: None,
+            replay_gain: Default::default(),
+            bpm: Some(bpm),
+            key: CamelotKey::parse(key),
+        };
+
+        let current = song(1, 120.0, "8A");
+        let mut upcoming = vec![
+            song(2, 90.0, "3B"),
+            song(3, 122.0, "8A"),
+            song(4, 100.0, "9A"),
+        ];
+
+        let mut rng = StdRng::seed_from_u64(0);
+        ShuffleOrder::Smooth
+            .upcoming_order()
+            .arrange(&current, &mut upcoming, &mut rng);
+
+        let ids: Vec<_> = upcoming.iter().map(|song| song.id).collect();
+        assert_eq!(ids, vec![SongId::new(3), SongId::new(4), SongId::new(2)]);
+    }
+}
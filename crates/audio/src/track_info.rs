@@ -4,10 +4,15 @@ use symphonia::core::units::TimeBase;
 
 use crate::player::ProgressTimes;
 
+/// Finds the first track with a codec that symphonia was built with a decoder for.
+/// Containers like ogg and m4a can hold codecs that aren't enabled (ie opus).
 pub fn first_supported_track(tracks: &[Track]) -> Option<&Track> {
-    tracks
-        .iter()
-        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+    let codecs = symphonia::default::get_codecs();
+
+    tracks.iter().find(|t| {
+        t.codec_params.codec != CODEC_TYPE_NULL
+            && codecs.get_codec(t.codec_params.codec).is_some()
+    })
 }
 
 #[derive(Debug, Clone)]
@@ -15,6 +20,8 @@ pub struct TrackInfo {
     pub id: u32,
     pub time_base: Option<TimeBase>,
     pub duration: Option<u64>,
+    /// The source's bit depth; None = not stored, ie for lossy formats
+    pub bits_per_sample: Option<u32>,
 }
 
 impl TrackInfo {
@@ -35,12 +42,19 @@ impl TrackInfo {
 
 impl From<&Track> for TrackInfo {
     fn from(track: &Track) -> Self {
-        let CodecParameters { time_base, n_frames, start_ts, .. } = track.codec_params;
+        let CodecParameters {
+            time_base,
+            n_frames,
+            start_ts,
+            bits_per_sample,
+            ..
+        } = track.codec_params;
 
         Self {
             id: track.id,
             time_base,
             duration: n_frames.map(|frames| start_ts + frames),
+            bits_per_sample,
         }
     }
 }
@@ -0,0 +1,160 @@
+use camino::Utf8Path;
+use log::warn;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::errors::Error as SymphoniaError;
+
+use crate::metadata::probe;
+use crate::track_info::first_supported_track;
+
+/// The number of peaks in a song's waveform
+pub const WAVEFORM_LENGTH: usize = 400;
+/// Frames per peak while decoding, before they're grouped into the waveform's spans
+const CHUNK_FRAMES: usize = 1024;
+
+/// The loudest sample in each of WAVEFORM_LENGTH spans across a song, from 0 to 255;
+/// None for files that can't be decoded.
+/// NOTE this decodes the whole song, so it shouldn't run on the ui thread
+pub fn peaks(path: &Utf8Path) -> Option<Vec<u8>> {
+    let mut reader = probe(path)?.format;
+    let track = first_supported_track(reader.tracks())?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &Default::default())
+        .map_err(|e| warn!("failed to decode waveform for {path}: {e}"))
+        .ok()?;
+
+    let mut chunk_peaks = Vec::new();
+    let (mut chunk_peak, mut chunk_frames) = (0f32, 0);
+    let mut sample_buf: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+
+            // NOTE this is the normal end of the file; see continue_playing
+            Err(SymphoniaError::IoError(e))
+                if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break;
+            }
+
+            Err(e) => {
+                warn!("stopped reading waveform for {path}: {e}");
+                break;
+            }
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => {
+                warn!("stopped decoding waveform for {path}: {e}");
+                break;
+            }
+        };
+
+        let channels = decoded.spec().channels.count();
+        let needed = decoded.capacity() * channels;
+        let fits = sample_buf
+            .as_ref()
+            .is_some_and(|buf| buf.capacity() >= needed);
+        if !fits {
+            sample_buf = None;
+        }
+        let buf = sample_buf.get_or_insert_with(|| {
+            SampleBuffer::new(decoded.capacity() as u64, *decoded.spec())
+        });
+        buf.copy_interleaved_ref(decoded);
+
+        for frame in buf.samples().chunks(channels) {
+            let frame_peak = frame.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+            chunk_peak = chunk_peak.max(frame_peak);
+            chunk_frames += 1;
+
+            if chunk_frames == CHUNK_FRAMES {
+                chunk_peaks.push(chunk_peak);
+                (chunk_peak, chunk_frames) = (0.0, 0);
+            }
+        }
+    }
+
+    if chunk_frames > 0 {
+        chunk_peaks.push(chunk_peak);
+    }
+    if chunk_peaks.is_empty() {
+        return None;
+    }
+
+    Some(group_peaks(&chunk_peaks))
+}
+
+/// The loudest chunk in each span; short songs repeat chunks across spans
+fn group_peaks(chunk_peaks: &[f32]) -> Vec<u8> {
+    let count = chunk_peaks.len();
+
+    (0..WAVEFORM_LENGTH)
+        .map(|span| {
+            let start = (span * count / WAVEFORM_LENGTH).min(count - 1);
+            let end = ((span + 1) * count / WAVEFORM_LENGTH).clamp(start + 1, count);
+            let peak = chunk_peaks[start..end].iter().fold(0f32, |a, &b| a.max(b));
+
+            (peak.min(1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+
+    /// A 16 bit mono wav, quiet for its first half and loud for the second
+    fn write_test_wav(path: &Utf8Path) {
+        let frames = 44_100;
+        let samples = (0..frames).map(|frame| {
+            let amplitude = if frame < frames / 2 { 0.25 } else { 1.0 };
+            let sign = if frame % 2 == 0 { 1.0 } else { -1.0 };
+            (sign * amplitude * i16::MAX as f32) as i16
+        });
+
+        let data_len = frames * 2;
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend((36 + data_len as u32).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend(1u16.to_le_bytes()); // pcm
+        wav.extend(1u16.to_le_bytes()); // channels
+        wav.extend(44_100u32.to_le_bytes());
+        wav.extend((44_100u32 * 2).to_le_bytes());
+        wav.extend(2u16.to_le_bytes()); // block align
+        wav.extend(16u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend((data_len as u32).to_le_bytes());
+        wav.extend(samples.flat_map(i16::to_le_bytes));
+
+        std::fs::write(path, wav).unwrap();
+    }
+
+    #[test]
+    fn peaks_follow_the_loudness_across_the_song() {
+        let dir =
+            std::env::temp_dir().join(format!("clef-waveform-{}", std::process::id()));
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("song.wav");
+        write_test_wav(&path);
+
+        // NOTE the span around the middle holds a chunk with both halves
+        let waveform = peaks(&path).unwrap();
+        assert_eq!(waveform.len(), WAVEFORM_LENGTH);
+        assert!(waveform[..180].iter().all(|&p| p == 64), "{waveform:?}");
+        assert!(waveform[220..].iter().all(|&p| p == 255), "{waveform:?}");
+
+        assert_eq!(peaks(&dir.join("missing.wav")), None);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,2 @@
+alter table songs drop column deleted;
+alter table albums drop column deleted;
@@ -0,0 +1,2 @@
+alter table albums add column deleted boolean not null default false;
+alter table songs add column deleted boolean not null default false;
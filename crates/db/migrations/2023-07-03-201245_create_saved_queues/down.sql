@@ -0,0 +1,2 @@
+drop table saved_queue_songs;
+drop table saved_queues;
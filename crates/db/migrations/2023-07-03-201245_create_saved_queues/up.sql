@@ -0,0 +1,14 @@
+create table saved_queues (
+  id integer primary key not null,
+  current_index integer not null,
+  elapsed_seconds double not null,
+  from_crash boolean not null
+);
+
+create table saved_queue_songs (
+  saved_queue_id integer references saved_queues (id) on delete cascade not null,
+  position integer not null,
+  song_id integer references songs (id) not null,
+
+  primary key (saved_queue_id, position)
+);
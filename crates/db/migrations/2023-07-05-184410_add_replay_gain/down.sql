@@ -0,0 +1,4 @@
+alter table songs drop column track_peak;
+alter table songs drop column track_gain;
+alter table albums drop column album_peak;
+alter table albums drop column album_gain;
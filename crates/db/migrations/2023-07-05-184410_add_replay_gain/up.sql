@@ -0,0 +1,4 @@
+alter table albums add column album_gain double;
+alter table albums add column album_peak double;
+alter table songs add column track_gain double;
+alter table songs add column track_peak double;
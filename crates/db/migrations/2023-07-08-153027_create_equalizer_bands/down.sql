@@ -0,0 +1 @@
+drop table equalizer_bands;
@@ -0,0 +1,4 @@
+create table equalizer_bands (
+  band integer primary key not null,
+  gain_db double not null
+);
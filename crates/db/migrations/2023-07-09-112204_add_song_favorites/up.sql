@@ -0,0 +1 @@
+alter table songs add column favorite boolean not null default false;
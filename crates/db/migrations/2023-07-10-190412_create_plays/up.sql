@@ -0,0 +1,9 @@
+create table plays (
+  id integer primary key not null,
+  song_id integer references songs (id) not null,
+  started_at bigint not null,
+  completed_at bigint,
+  percent_played double not null
+);
+
+create index plays_song_id on plays (song_id);
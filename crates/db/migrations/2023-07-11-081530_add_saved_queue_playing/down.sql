@@ -0,0 +1 @@
+alter table saved_queues drop column playing;
@@ -0,0 +1 @@
+alter table saved_queues add column playing boolean not null default false;
@@ -0,0 +1,2 @@
+drop table smart_playlist_rules;
+drop table smart_playlists;
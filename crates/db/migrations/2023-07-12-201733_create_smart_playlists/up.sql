@@ -0,0 +1,13 @@
+create table smart_playlists (
+  id integer primary key not null,
+  name text not null
+);
+
+create table smart_playlist_rules (
+  smart_playlist_id integer references smart_playlists (id) on delete cascade not null,
+  position integer not null,
+  kind text not null,
+  value text not null,
+
+  primary key (smart_playlist_id, position)
+);
@@ -0,0 +1,6 @@
+delete from saved_queue_songs
+where saved_queue_id in (select id from saved_queues where session_id is not null);
+delete from saved_queues where session_id is not null;
+
+alter table saved_queues drop column session_id;
+drop table sessions;
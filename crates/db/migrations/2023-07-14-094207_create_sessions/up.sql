@@ -0,0 +1,8 @@
+create table sessions (
+  id integer primary key not null,
+  name text not null unique,
+  active boolean not null default false
+);
+
+-- null = the default session, which has no row in sessions
+alter table saved_queues add column session_id integer;
@@ -0,0 +1 @@
+alter table sessions add column transition text not null default 'cut';
@@ -0,0 +1 @@
+alter table albums drop column library_root;
@@ -0,0 +1 @@
+alter table albums add column library_root text;
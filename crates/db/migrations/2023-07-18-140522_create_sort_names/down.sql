@@ -0,0 +1 @@
+drop table sort_names;
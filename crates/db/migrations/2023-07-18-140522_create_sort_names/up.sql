@@ -0,0 +1,9 @@
+-- overrides for sorting the library, ie 'The Beatles' as 'Beatles'
+create table sort_names (
+  -- 'artist' or 'album'
+  kind text not null,
+  -- the artist or album title as tagged
+  name text not null,
+  sort_name text not null,
+  primary key (kind, name)
+);
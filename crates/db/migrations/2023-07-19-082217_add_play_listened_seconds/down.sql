@@ -0,0 +1 @@
+alter table plays drop column listened_seconds;
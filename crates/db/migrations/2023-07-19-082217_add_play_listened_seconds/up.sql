@@ -0,0 +1,7 @@
+alter table plays add column listened_seconds double not null default 0;
+
+-- earlier plays only recorded a percentage
+update plays set listened_seconds = percent_played / 100.0 * coalesce(
+    (select total_seconds from songs where songs.id = plays.song_id),
+    0
+);
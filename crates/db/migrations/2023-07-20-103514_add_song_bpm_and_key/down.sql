@@ -0,0 +1,2 @@
+alter table songs drop column initial_key;
+alter table songs drop column bpm;
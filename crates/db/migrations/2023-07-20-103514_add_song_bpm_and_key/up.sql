@@ -0,0 +1,2 @@
+alter table songs add column bpm double;
+alter table songs add column initial_key text;
@@ -0,0 +1,2 @@
+alter table albums drop column edited_artist;
+alter table albums drop column edited_title;
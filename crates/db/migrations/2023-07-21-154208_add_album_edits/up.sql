@@ -0,0 +1,3 @@
+-- the album title and artist as edited in clef, in place of the tags
+alter table albums add column edited_title text;
+alter table albums add column edited_artist text;
@@ -0,0 +1,7 @@
+-- kept apart from songs, which are all loaded at launch
+create table song_lyrics (
+  song_id integer primary key not null references songs(id),
+  -- from a .lrc file beside the song, or else its lyrics tag;
+  -- either may have lrc timestamps
+  lyrics text not null
+);
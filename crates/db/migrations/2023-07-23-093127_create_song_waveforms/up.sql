@@ -0,0 +1,6 @@
+-- computed once per song, for the seek bar
+create table song_waveforms (
+  song_id integer primary key not null references songs(id),
+  -- one byte per span of the song, from 0 (silent) to 255 (full scale)
+  peaks blob not null
+);
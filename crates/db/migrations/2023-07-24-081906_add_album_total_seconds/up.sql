@@ -0,0 +1,8 @@
+-- the length of the album's songs, kept up to date by the crawler
+alter table albums add column total_seconds bigint not null default 0;
+
+update albums set total_seconds = (
+  select coalesce(sum(songs.total_seconds), 0)
+  from songs
+  where songs.album_id = albums.id and not songs.deleted
+);
@@ -0,0 +1,2 @@
+alter table songs drop column musicbrainz_recording_id;
+alter table albums drop column musicbrainz_release_id;
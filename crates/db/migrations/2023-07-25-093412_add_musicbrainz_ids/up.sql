@@ -0,0 +1,3 @@
+-- the release and recordings that MusicBrainz lookups matched, for syncing with it later
+alter table albums add column musicbrainz_release_id text;
+alter table songs add column musicbrainz_recording_id text;
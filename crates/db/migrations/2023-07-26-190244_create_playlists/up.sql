@@ -0,0 +1,14 @@
+-- playlist files found under the music directories, ie .m3u or .pls
+create table playlists (
+  id integer primary key not null,
+  file text not null unique,
+  name text not null
+);
+
+create table playlist_songs (
+  playlist_id integer references playlists (id) on delete cascade not null,
+  position integer not null,
+  song_id integer references songs (id) not null,
+
+  primary key (playlist_id, position)
+);
@@ -0,0 +1,2 @@
+-- set by the player when a song's file can't be opened, ie an unsupported codec
+alter table songs add column unplayable boolean not null default false;
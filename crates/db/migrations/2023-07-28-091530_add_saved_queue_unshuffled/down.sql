@@ -0,0 +1 @@
+alter table saved_queue_songs drop column unshuffled_position;
@@ -0,0 +1,2 @@
+-- the order from before shuffling; null when the queue isn't shuffled
+alter table saved_queue_songs add column unshuffled_position integer;
@@ -0,0 +1,8 @@
+create table equalizer_bands_default (
+  band integer primary key not null,
+  gain_db double not null
+);
+insert into equalizer_bands_default (band, gain_db)
+  select band, gain_db from equalizer_bands where device = '';
+drop table equalizer_bands;
+alter table equalizer_bands_default rename to equalizer_bands;
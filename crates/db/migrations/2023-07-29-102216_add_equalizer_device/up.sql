@@ -0,0 +1,11 @@
+-- a curve per output device; '' is the default output, which had the only curve until now
+create table equalizer_bands_by_device (
+  device text not null,
+  band integer not null,
+  gain_db double not null,
+  primary key (device, band)
+);
+insert into equalizer_bands_by_device (device, band, gain_db)
+  select '', band, gain_db from equalizer_bands;
+drop table equalizer_bands;
+alter table equalizer_bands_by_device rename to equalizer_bands;
@@ -0,0 +1,8 @@
+-- copies of the playlists in the playlist folder, with what each side held
+-- when they were last synced, to tell which side changed since
+create table playlist_mirrors (
+  file text primary key not null,
+  playlist_file text not null,
+  mirrored text not null,
+  listed text not null
+);
@@ -0,0 +1 @@
+alter table albums drop column notes;
@@ -0,0 +1,2 @@
+-- free-text notes written in clef, like vinyl rip details or a review
+alter table albums add column notes text;
@@ -0,0 +1,213 @@
+use std::time::SystemTime;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use diesel::sql_types::Text;
+use diesel::{
+    Connection, ConnectionResult, QueryableByName, RunQueryDsl, SqliteConnection,
+};
+
+use crate::queries::DbError;
+
+// NOTE these all sit next to the db, so that a db given with --db keeps its own
+
+/// A copy of the db from the last time it passed a check
+const BACKUP_SUFFIX: &str = ".bak";
+/// Exists while the ui is open; left behind by a crash or a killed process
+const RUNNING_SUFFIX: &str = ".running";
+/// Asks the next launch to restore the backup, before anything else opens the db
+const RESTORE_SUFFIX: &str = ".restore";
+
+/// How thorough a check to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityCheck {
+    /// Skips matching the indexes against their tables; much faster on a big library
+    Quick,
+    Full,
+}
+
+/// What a failed check found, to offer the backup to the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseDamage {
+    /// Sqlite's descriptions of the problems
+    pub problems: Vec<String>,
+    /// When the backup was written, if there is one
+    pub backup_time: Option<SystemTime>,
+}
+
+#[derive(Debug, QueryableByName)]
+struct CheckRow {
+    #[diesel(sql_type = Text)]
+    result: String,
+}
+
+/// Opens the db outside of a pool, to check it before anything writes to it
+pub fn connect(db_path: &Utf8Path) -> ConnectionResult<SqliteConnection> {
+    SqliteConnection::establish(db_path.as_str())
+}
+
+/// The problems sqlite finds in the db; empty when it's healthy
+pub fn check_integrity(
+    conn: &mut SqliteConnection,
+    check: IntegrityCheck,
+) -> Result<Vec<String>, DbError> {
+    let query = match check {
+        IntegrityCheck::Quick => "SELECT quick_check AS result FROM pragma_quick_check",
+        IntegrityCheck::Full => {
+            "SELECT integrity_check AS result FROM pragma_integrity_check"
+        }
+    };
+
+    let rows: Vec<CheckRow> = diesel::sql_query(query).load(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| row.result)
+        .filter(|result| result != "ok")
+        .collect())
+}
+
+pub fn backup_path(db_path: &Utf8Path) -> Utf8PathBuf {
+    with_suffix(db_path, BACKUP_SUFFIX)
+}
+
+/// When the backup was written, or None if there isn't one
+pub fn backup_time(db_path: &Utf8Path) -> Option<SystemTime> {
+    std::fs::metadata(backup_path(db_path))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Replaces the backup with a compacted copy of the db.
+/// NOTE this should only follow a passing check, so that the backup stays restorable
+pub fn write_backup(
+    conn: &mut SqliteConnection,
+    db_path: &Utf8Path,
+) -> Result<(), BackupError> {
+    let backup_path = backup_path(db_path);
+    let partial_path = with_suffix(&backup_path, ".partial");
+
+    // NOTE 'vacuum into' refuses to overwrite a file, ie one left by an interrupted backup
+    remove_if_present(&partial_path)?;
+
+    let quoted_path = partial_path.as_str().replace('\'', "''");
+    diesel::sql_query(format!("VACUUM INTO '{quoted_path}'"))
+        .execute(conn)
+        .map_err(DbError::from)?;
+
+    std::fs::rename(&partial_path, &backup_path)?;
+
+    Ok(())
+}
+
+/// Copies the backup over the db.
+/// NOTE nothing can have the db open; sqlite doesn't expect its files to change underneath it
+pub fn restore_backup(db_path: &Utf8Path) -> std::io::Result<()> {
+    std::fs::copy(backup_path(db_path), db_path)?;
+
+    // NOTE these belong to the damaged db, and would be replayed into the restored one
+    remove_if_present(&with_suffix(db_path, "-wal"))?;
+    remove_if_present(&with_suffix(db_path, "-shm"))?;
+
+    Ok(())
+}
+
+/// Notes that the ui is open; true if the last launch didn't close cleanly
+pub fn mark_running(db_path: &Utf8Path) -> std::io::Result<bool> {
+    let running_path = with_suffix(db_path, RUNNING_SUFFIX);
+    let unclean = running_path.exists();
+    std::fs::write(&running_path, std::process::id().to_string())?;
+
+    Ok(unclean)
+}
+
+pub fn mark_stopped(db_path: &Utf8Path) -> std::io::Result<()> {
+    remove_if_present(&with_suffix(db_path, RUNNING_SUFFIX))
+}
+
+pub fn request_restore(db_path: &Utf8Path) -> std::io::Result<()> {
+    std::fs::write(with_suffix(db_path, RESTORE_SUFFIX), "")
+}
+
+/// Whether the last launch asked for the backup; it's only returned once
+pub fn take_restore_request(db_path: &Utf8Path) -> bool {
+    std::fs::remove_file(with_suffix(db_path, RESTORE_SUFFIX)).is_ok()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BackupError {
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+fn with_suffix(path: &Utf8Path, suffix: &str) -> Utf8PathBuf {
+    Utf8PathBuf::from(format!("{path}{suffix}"))
+}
+
+fn remove_if_present(path: &Utf8Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_pool, run_migrations};
+
+    #[test]
+    fn a_checked_db_is_backed_up_and_restored() {
+        let dir =
+            std::env::temp_dir().join(format!("clef-integrity-{}", std::process::id()));
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("db.sqlite");
+
+        let pool = create_pool(&db_path).unwrap();
+        run_migrations(&pool).unwrap();
+        let mut conn = pool.get().unwrap();
+
+        assert_eq!(
+            check_integrity(&mut conn, IntegrityCheck::Full).unwrap(),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            check_integrity(&mut conn, IntegrityCheck::Quick).unwrap(),
+            Vec::<String>::new()
+        );
+
+        assert_eq!(backup_time(&db_path), None);
+        write_backup(&mut conn, &db_path).unwrap();
+        assert!(backup_time(&db_path).is_some());
+        // NOTE a second backup replaces the first
+        write_backup(&mut conn, &db_path).unwrap();
+
+        drop(conn);
+        drop(pool);
+
+        assert!(!take_restore_request(&db_path));
+        request_restore(&db_path).unwrap();
+        assert!(take_restore_request(&db_path));
+        assert!(!take_restore_request(&db_path));
+
+        std::fs::write(&db_path, "not a database").unwrap();
+        restore_backup(&db_path).unwrap();
+        let pool = create_pool(&db_path).unwrap();
+        let mut conn = pool.get().unwrap();
+        assert_eq!(
+            check_integrity(&mut conn, IntegrityCheck::Quick).unwrap(),
+            Vec::<String>::new()
+        );
+
+        assert!(!mark_running(&db_path).unwrap());
+        assert!(mark_running(&db_path).unwrap());
+        mark_stopped(&db_path).unwrap();
+        assert!(!mark_running(&db_path).unwrap());
+
+        drop(conn);
+        drop(pool);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
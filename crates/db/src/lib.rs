@@ -10,6 +10,7 @@ use diesel::sqlite::SqliteConnection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use r2d2::{Pool, PooledConnection};
 
+pub mod integrity;
 pub mod models;
 pub mod queries;
 pub mod schema;
@@ -28,8 +29,16 @@ pub type SqlitePoolConn = PooledConnection<ConnectionManager<SqliteConnection>>;
 const CONNECTION_TIMEOUT: Duration = Duration::from_secs(1);
 
 pub fn create_pool(db_path: &Utf8Path) -> Result<SqlitePool, r2d2::Error> {
-    let manager = ConnectionManager::new(db_path.as_str());
+    pool_builder().build(ConnectionManager::new(db_path.as_str()))
+}
+
+/// A pool that doesn't connect until it's used, for a db that can't be opened;
+/// the ui still needs a pool to offer restoring the backup
+pub fn create_unchecked_pool(db_path: &Utf8Path) -> SqlitePool {
+    pool_builder().build_unchecked(ConnectionManager::new(db_path.as_str()))
+}
 
+fn pool_builder() -> r2d2::Builder<ConnectionManager<SqliteConnection>> {
     Pool::builder()
         .connection_timeout(CONNECTION_TIMEOUT)
         .connection_customizer(Box::new(ConnectionOptions {
@@ -37,7 +46,6 @@ pub fn create_pool(db_path: &Utf8Path) -> Result<SqlitePool, r2d2::Error> {
             enable_foreign_keys: true,
             busy_timeout: Some(Duration::from_secs(5)),
         }))
-        .build(manager)
 }
 
 // https://stackoverflow.com/questions/57123453/how-to-use-diesel-with-sqlite-connections-and-avoid-database-is-locked-type-of
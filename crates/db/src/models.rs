@@ -1,7 +1,20 @@
 use diesel::prelude::*;
 
 use super::schema::albums;
+use super::schema::equalizer_bands;
+use super::schema::playlist_mirrors;
+use super::schema::playlist_songs;
+use super::schema::playlists;
+use super::schema::plays;
+use super::schema::saved_queue_songs;
+use super::schema::saved_queues;
+use super::schema::sessions;
+use super::schema::smart_playlist_rules;
+use super::schema::smart_playlists;
+use super::schema::song_lyrics;
+use super::schema::song_waveforms;
 use super::schema::songs;
+use super::schema::sort_names;
 
 #[derive(Queryable, Debug)]
 pub(super) struct AlbumRow {
@@ -13,6 +26,15 @@ pub(super) struct AlbumRow {
     pub release_date: Option<String>,
     pub original_art: Option<String>,
     pub resized_art: Option<String>,
+    pub deleted: bool,
+    pub album_gain: Option<f64>,
+    pub album_peak: Option<f64>,
+    pub library_root: Option<String>,
+    pub edited_title: Option<String>,
+    pub edited_artist: Option<String>,
+    pub total_seconds: i64,
+    pub musicbrainz_release_id: Option<String>,
+    pub notes: Option<String>,
 }
 
 #[derive(Insertable, Debug)]
@@ -25,6 +47,9 @@ pub(super) struct NewAlbumRow {
     pub release_date: Option<String>,
     pub original_art: Option<String>,
     pub resized_art: Option<String>,
+    pub album_gain: Option<f64>,
+    pub album_peak: Option<f64>,
+    pub library_root: Option<String>,
 }
 
 #[derive(Queryable, Debug)]
@@ -37,6 +62,17 @@ pub(super) struct SongRow {
     pub title: Option<String>,
     pub artist: Option<String>,
     pub track_number: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub deleted: bool,
+    pub track_gain: Option<f64>,
+    pub track_peak: Option<f64>,
+    pub favorite: bool,
+    pub genre: Option<String>,
+    pub track_total: Option<i32>,
+    pub bpm: Option<f64>,
+    pub initial_key: Option<String>,
+    pub musicbrainz_recording_id: Option<String>,
+    pub unplayable: bool,
 }
 
 #[derive(Insertable, Debug)]
@@ -49,4 +85,173 @@ pub(super) struct NewSongRow {
     pub title: Option<String>,
     pub artist: Option<String>,
     pub track_number: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub track_gain: Option<f64>,
+    pub track_peak: Option<f64>,
+    pub genre: Option<String>,
+    pub track_total: Option<i32>,
+    pub bpm: Option<f64>,
+    pub initial_key: Option<String>,
+}
+
+#[derive(Queryable, Debug)]
+pub(super) struct SavedQueueRow {
+    pub id: i32,
+    pub current_index: i32,
+    pub elapsed_seconds: f64,
+    pub from_crash: bool,
+    pub playing: bool,
+    #[allow(unused)] // queries filter on it instead
+    pub session_id: Option<i32>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = saved_queues)]
+pub(super) struct NewSavedQueueRow {
+    pub current_index: i32,
+    pub elapsed_seconds: f64,
+    pub from_crash: bool,
+    pub playing: bool,
+    pub session_id: Option<i32>,
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[diesel(table_name = saved_queue_songs)]
+pub(super) struct SavedQueueSongRow {
+    pub saved_queue_id: i32,
+    pub position: i32,
+    pub song_id: i32,
+    pub unshuffled_position: Option<i32>,
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[diesel(table_name = equalizer_bands)]
+pub(super) struct EqualizerBandRow {
+    pub device: String,
+    pub band: i32,
+    pub gain_db: f64,
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[diesel(table_name = song_lyrics)]
+pub(super) struct SongLyricsRow {
+    pub song_id: i32,
+    pub lyrics: String,
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[diesel(table_name = song_waveforms)]
+pub(super) struct SongWaveformRow {
+    pub song_id: i32,
+    pub peaks: Vec<u8>,
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[diesel(table_name = sort_names)]
+pub(super) struct SortNameRow {
+    pub kind: String,
+    pub name: String,
+    pub sort_name: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = plays)]
+pub(super) struct NewPlayRow {
+    pub song_id: i32,
+    pub started_at: i64,
+    pub percent_played: f64,
+    pub listened_seconds: f64,
+}
+
+#[derive(Queryable, Debug)]
+pub(super) struct PlaylistRow {
+    pub id: i32,
+    pub file: String,
+    pub name: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = playlists)]
+pub(super) struct NewPlaylistRow {
+    pub file: String,
+    pub name: String,
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[diesel(table_name = playlist_mirrors)]
+pub(super) struct PlaylistMirrorRow {
+    pub file: String,
+    pub playlist_file: String,
+    pub mirrored: String,
+    pub listed: String,
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[diesel(table_name = playlist_songs)]
+pub(super) struct PlaylistSongRow {
+    pub playlist_id: i32,
+    pub position: i32,
+    pub song_id: i32,
+}
+
+#[derive(Queryable, Debug)]
+pub(super) struct SmartPlaylistRow {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = smart_playlists)]
+pub(super) struct NewSmartPlaylistRow {
+    pub name: String,
+}
+
+#[derive(Queryable, Insertable, Debug)]
+#[diesel(table_name = smart_playlist_rules)]
+pub(super) struct SmartPlaylistRuleRow {
+    pub smart_playlist_id: i32,
+    pub position: i32,
+    pub kind: String,
+    pub value: String,
+}
+
+#[derive(Queryable, Debug)]
+pub(super) struct SessionRow {
+    pub id: i32,
+    pub name: String,
+    pub active: bool,
+    pub transition: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = sessions)]
+pub(super) struct NewSessionRow {
+    pub name: String,
+}
+
+/// Listening time for one hour of one weekday; see listening_clock
+#[derive(QueryableByName, Debug)]
+pub(super) struct ClockRow {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub weekday: i32,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub hour: i32,
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    pub listened_seconds: f64,
+}
+
+/// The plays started on one local day; see listening_days
+#[derive(QueryableByName, Debug)]
+pub(super) struct ListeningDayRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub day: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub plays: i64,
+}
+
+/// A played song; see plays_on_day and on_this_day
+#[derive(QueryableByName, Debug)]
+pub(super) struct DayPlayRow {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub song_id: i32,
 }
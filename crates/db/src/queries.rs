@@ -1,10 +1,19 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
 use camino::{Utf8Path, Utf8PathBuf};
 use diesel::result::Error as DieselError;
 use diesel::SqliteConnection;
+use serde::Serialize;
 
-use super::models::{AlbumRow, NewAlbumRow, NewSongRow, SongRow};
+use super::models::{
+    AlbumRow, ClockRow, DayPlayRow, EqualizerBandRow, ListeningDayRow, NewAlbumRow,
+    NewPlayRow, NewPlaylistRow, NewSavedQueueRow, NewSessionRow, NewSmartPlaylistRow,
+    NewSongRow, PlaylistMirrorRow, PlaylistRow, PlaylistSongRow, SavedQueueRow,
+    SavedQueueSongRow, SessionRow, SmartPlaylistRow, SmartPlaylistRuleRow, SongLyricsRow,
+    SongRow, SongWaveformRow, SortNameRow,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 pub struct AlbumId(i32);
 
 impl AlbumId {
@@ -17,9 +26,14 @@ impl AlbumId {
     pub fn unpack(&self) -> i32 {
         self.0
     }
+
+    /// A new id for an album from outside the library, that's never saved
+    pub fn unsaved() -> Self {
+        Self(next_unsaved_id())
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 pub struct SongId(i32);
 
 impl SongId {
@@ -28,6 +42,62 @@ impl SongId {
     pub fn new(id: i32) -> Self {
         Self(id)
     }
+
+    pub fn unpack(&self) -> i32 {
+        self.0
+    }
+
+    /// A new id for a song from outside the library, that's never saved
+    pub fn unsaved() -> Self {
+        Self(next_unsaved_id())
+    }
+
+    /// False for songs from outside the library,
+    /// which can't be referenced by plays, queues, or favorites
+    pub fn is_saved(&self) -> bool {
+        self.0 > 0
+    }
+}
+
+/// NOTE sqlite ids start at 1, so negative ones never collide with saved rows
+static NEXT_UNSAVED_ID: AtomicI32 = AtomicI32::new(-1);
+
+fn next_unsaved_id() -> i32 {
+    NEXT_UNSAVED_ID.fetch_sub(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayId(i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlaylistId(i32);
+
+impl PlaylistId {
+    /// Exported for testing
+    #[cfg(debug_assertions)]
+    pub fn new(id: i32) -> Self {
+        Self(id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SmartPlaylistId(i32);
+
+impl SmartPlaylistId {
+    pub fn unpack(&self) -> i32 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionId(i32);
+
+impl SessionId {
+    /// Exported for testing
+    #[cfg(debug_assertions)]
+    pub fn new(id: i32) -> Self {
+        Self(id)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,11 +105,27 @@ pub struct Album {
     pub id: AlbumId,
     pub directory: Utf8PathBuf,
 
+    /// As edited in clef, or else as tagged
     pub title: Option<String>,
+    /// As edited in clef, or else as tagged
     pub artist: Option<String>,
+    pub tagged_title: Option<String>,
+    pub tagged_artist: Option<String>,
     pub release_date: Option<String>,
     pub original_art: Option<Utf8PathBuf>,
     pub resized_art: Option<Utf8PathBuf>,
+    /// ReplayGain album gain in dB
+    pub album_gain: Option<f64>,
+    /// ReplayGain album peak, as a linear sample amplitude
+    pub album_peak: Option<f64>,
+    /// The configured music directory the album was crawled from
+    pub library_root: Option<Utf8PathBuf>,
+    /// The length of all the album's songs; see update_album_runtime
+    pub total_seconds: i64,
+    /// The release matched by a MusicBrainz lookup; see fill_from_musicbrainz
+    pub musicbrainz_release_id: Option<String>,
+    /// Written in clef, like vinyl rip details or a review; see save_album_notes
+    pub notes: Option<String>,
 }
 
 impl From<AlbumRow> for Album {
@@ -47,22 +133,44 @@ impl From<AlbumRow> for Album {
         Self {
             id: AlbumId(row.id),
             directory: row.directory.into(),
-            title: row.title,
-            artist: row.artist,
+            title: row.edited_title.or_else(|| row.title.clone()),
+            artist: row.edited_artist.or_else(|| row.artist.clone()),
+            tagged_title: row.title,
+            tagged_artist: row.artist,
             release_date: row.release_date,
             original_art: row.original_art.map(Into::into),
             resized_art: row.resized_art.map(Into::into),
+            album_gain: row.album_gain,
+            album_peak: row.album_peak,
+            library_root: row.library_root.map(Into::into),
+            total_seconds: row.total_seconds,
+            musicbrainz_release_id: row.musicbrainz_release_id,
+            notes: row.notes,
         }
     }
 }
 
 impl Album {
+    /// Whether the title or artist were edited in clef
+    pub fn is_renamed(&self) -> bool {
+        (&self.title, &self.artist) != (&self.tagged_title, &self.tagged_artist)
+    }
+
+    /// Replaces the title and artist for display; None goes back to the tags
+    pub fn rename(&mut self, renamed: &AlbumRename) {
+        self.title = renamed.title.clone().or_else(|| self.tagged_title.clone());
+        self.artist = renamed
+            .artist
+            .clone()
+            .or_else(|| self.tagged_artist.clone());
+    }
+
     pub fn display_title(&self) -> Option<&str> {
         if self.title.is_some() {
             return self.title.as_deref();
         }
 
-        let directory_name = self.directory.components().last();
+        let directory_name = self.directory.components().next_back();
 
         directory_name.map(|c| c.as_str())
     }
@@ -78,6 +186,21 @@ pub struct Song {
     pub title: Option<String>,
     pub artist: Option<String>,
     pub track_number: Option<i32>,
+    pub disc_number: Option<i32>,
+    /// ReplayGain track gain in dB
+    pub track_gain: Option<f64>,
+    /// ReplayGain track peak, as a linear sample amplitude
+    pub track_peak: Option<f64>,
+    pub favorite: bool,
+    pub genre: Option<String>,
+    /// The number of tracks on the song's disc, from its tags
+    pub track_total: Option<i32>,
+    /// Beats per minute, from its tags
+    pub bpm: Option<f64>,
+    /// The musical key as tagged, ie 'Am' or '8A'
+    pub initial_key: Option<String>,
+    /// The player couldn't open the file; see set_song_unplayable
+    pub unplayable: bool,
 }
 
 impl From<SongRow> for Song {
@@ -90,6 +213,15 @@ impl From<SongRow> for Song {
             title: row.title,
             artist: row.artist,
             track_number: row.track_number,
+            disc_number: row.disc_number,
+            track_gain: row.track_gain,
+            track_peak: row.track_peak,
+            favorite: row.favorite,
+            genre: row.genre,
+            track_total: row.track_total,
+            bpm: row.bpm,
+            initial_key: row.initial_key,
+            unplayable: row.unplayable,
         }
     }
 }
@@ -104,6 +236,22 @@ impl Song {
     }
 }
 
+/// A now-playing queue saved for a later launch
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedQueue {
+    /// In play order, including previous songs
+    pub song_ids: Vec<SongId>,
+    pub current_index: usize,
+    pub elapsed_seconds: f64,
+    /// Saved by the audio thread as it died, rather than during normal use
+    pub from_crash: bool,
+    /// Playing rather than paused when it was saved,
+    /// ie the app was closed mid-song
+    pub playing: bool,
+    /// The order from before shuffling, with the same songs; None = not shuffled
+    pub unshuffled_song_ids: Option<Vec<SongId>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct NewAlbum {
     pub directory: Utf8PathBuf,
@@ -113,6 +261,36 @@ pub struct NewAlbum {
     pub release_date: Option<String>,
     pub original_art: Option<Utf8PathBuf>,
     pub resized_art: Option<Utf8PathBuf>,
+    pub album_gain: Option<f64>,
+    pub album_peak: Option<f64>,
+    pub library_root: Option<Utf8PathBuf>,
+    /// The discs this directory holds, from its songs' tags or the directory name;
+    /// only used to find the other discs of a split album, and never saved
+    pub disc_numbers: Vec<i32>,
+}
+
+impl NewAlbum {
+    /// The album as it would be saved, for files outside the library;
+    /// the songs' length is left to the caller
+    pub fn into_unsaved(self) -> Album {
+        Album {
+            id: AlbumId::unsaved(),
+            directory: self.directory,
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            tagged_title: self.title,
+            tagged_artist: self.artist,
+            release_date: self.release_date,
+            original_art: self.original_art,
+            resized_art: self.resized_art,
+            album_gain: self.album_gain,
+            album_peak: self.album_peak,
+            library_root: self.library_root,
+            total_seconds: 0,
+            musicbrainz_release_id: None,
+            notes: None,
+        }
+    }
 }
 
 impl From<NewAlbum> for NewAlbumRow {
@@ -121,10 +299,13 @@ impl From<NewAlbum> for NewAlbumRow {
             directory: album.directory.into(),
             original_art: album.original_art.map(Into::into),
             resized_art: album.resized_art.map(Into::into),
+            library_root: album.library_root.map(Into::into),
 
             title: album.title,
             artist: album.artist,
             release_date: album.release_date,
+            album_gain: album.album_gain,
+            album_peak: album.album_peak,
         }
     }
 }
@@ -138,6 +319,37 @@ pub struct NewSong {
     pub title: Option<String>,
     pub artist: Option<String>,
     pub track_number: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub track_gain: Option<f64>,
+    pub track_peak: Option<f64>,
+    pub genre: Option<String>,
+    pub track_total: Option<i32>,
+    pub bpm: Option<f64>,
+    pub initial_key: Option<String>,
+}
+
+impl NewSong {
+    /// The song as it would be saved, for files outside the library
+    pub fn into_unsaved(self) -> Song {
+        Song {
+            id: SongId::unsaved(),
+            album_id: self.album_id,
+            file: self.file,
+            total_seconds: self.total_seconds,
+            title: self.title,
+            artist: self.artist,
+            track_number: self.track_number,
+            disc_number: self.disc_number,
+            track_gain: self.track_gain,
+            track_peak: self.track_peak,
+            favorite: false,
+            genre: self.genre,
+            track_total: self.track_total,
+            bpm: self.bpm,
+            initial_key: self.initial_key,
+            unplayable: false,
+        }
+    }
 }
 
 impl From<NewSong> for NewSongRow {
@@ -149,18 +361,36 @@ impl From<NewSong> for NewSongRow {
             title: song.title,
             artist: song.artist,
             track_number: song.track_number,
+            disc_number: song.disc_number,
+            track_gain: song.track_gain,
+            track_peak: song.track_peak,
+            genre: song.genre,
+            track_total: song.track_total,
+            bpm: song.bpm,
+            initial_key: song.initial_key,
         }
     }
 }
 
+/// What find_or_insert did to a crawled album or song's row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reconciled {
+    Unchanged,
+    /// Inserted, or un-deleted after its files came back
+    Added,
+    /// Its tags or music directory changed
+    Updated,
+}
+
 pub fn find_or_insert_album(
     tx: &mut SqliteConnection,
-    new_album: NewAlbum,
-) -> Result<Album, DbError> {
+    mut new_album: NewAlbum,
+) -> Result<(Album, Reconciled), DbError> {
     use super::schema::albums;
     use albums::dsl::*;
     use diesel::prelude::*;
 
+    let disc_numbers = std::mem::take(&mut new_album.disc_numbers);
     let new_row: NewAlbumRow = new_album.into();
 
     let existing_row: Option<AlbumRow> = albums
@@ -169,20 +399,169 @@ pub fn find_or_insert_album(
         .optional()?;
 
     if let Some(existing_row) = existing_row {
-        return Ok(existing_row.into());
+        return refresh_album(tx, existing_row, &new_row);
+    }
+
+    // NOTE This merges albums split across directories (ie 'CD1' and 'CD2'),
+    // keeping the first crawled directory as the album's directory
+    if let (Some(album_title), Some(album_artist)) = (&new_row.title, &new_row.artist) {
+        let same_tags_rows: Vec<AlbumRow> = albums
+            .filter(title.eq(album_title))
+            .filter(artist.eq(album_artist))
+            .order(id.asc())
+            .load(tx)?;
+
+        for same_tags_row in same_tags_rows {
+            if is_split_disc(tx, &same_tags_row, &new_row, &disc_numbers)? {
+                return refresh_album(tx, same_tags_row, &new_row);
+            }
+        }
     }
 
     let created_row: AlbumRow = diesel::insert_into(albums::table)
         .values(&new_row)
         .get_result(tx)?;
 
-    Ok(created_row.into())
+    Ok((created_row.into(), Reconciled::Added))
+}
+
+/// Whether a crawled directory holds another disc of an already saved album
+/// with the same tags, rather than a different release like a deluxe edition;
+/// that is, its directory name only differs by a disc marker (ie 'CD2'),
+/// or it holds discs the album doesn't have yet,
+/// and it has the same release date if both are tagged with one
+fn is_split_disc(
+    tx: &mut SqliteConnection,
+    row: &AlbumRow,
+    new_row: &NewAlbumRow,
+    new_disc_numbers: &[i32],
+) -> Result<bool, DbError> {
+    use super::schema::songs;
+    use diesel::prelude::*;
+
+    let saved_songs: Vec<(String, Option<i32>)> = songs::table
+        .filter(songs::album_id.eq(row.id))
+        .select((songs::file, songs::disc_number))
+        .load(tx)?;
+
+    // NOTE a disc merged by an earlier crawl already has its songs saved to the album
+    let new_directory = Utf8Path::new(&new_row.directory);
+    if saved_songs
+        .iter()
+        .any(|(file, _)| Utf8Path::new(file).parent() == Some(new_directory))
+    {
+        return Ok(true);
+    }
+
+    if let (Some(saved_date), Some(new_date)) = (&row.release_date, &new_row.release_date)
+    {
+        if saved_date != new_date {
+            return Ok(false);
+        }
+    }
+
+    if let (Some((saved_rest, saved_disc)), Some((new_rest, new_disc))) = (
+        disc_marker(Utf8Path::new(&row.directory)),
+        disc_marker(new_directory),
+    ) {
+        if saved_rest == new_rest && saved_disc != new_disc {
+            return Ok(true);
+        }
+    }
+
+    let saved_disc_numbers: Vec<i32> = saved_songs
+        .into_iter()
+        .filter_map(|(_, disc)| disc)
+        .collect();
+    Ok(!saved_disc_numbers.is_empty()
+        && !new_disc_numbers.is_empty()
+        && !new_disc_numbers
+            .iter()
+            .any(|disc| saved_disc_numbers.contains(disc)))
+}
+
+/// The disc number at the end of a directory's name, ie 'Album CD1' or 'Album (Disc 2)',
+/// with the lowercased path before it
+pub fn disc_marker(directory: &Utf8Path) -> Option<(String, i32)> {
+    let dir_name = directory.file_name()?.to_lowercase();
+
+    let starts_word = |i: usize| {
+        dir_name[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric())
+    };
+
+    let (marker_start, prefix_end) = ["disc", "disk", "cd"]
+        .iter()
+        .flat_map(|prefix| {
+            dir_name
+                .rmatch_indices(prefix)
+                .find(|(i, _)| starts_word(*i))
+                .map(|(i, _)| (i, i + prefix.len()))
+        })
+        .max_by_key(|(_, prefix_end)| *prefix_end)?;
+
+    let digits: String = dir_name[prefix_end..]
+        .trim_start_matches([' ', '_', '-', '.'])
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    let disc_number = digits.parse().ok()?;
+
+    let parent = directory.parent().map(Utf8Path::as_str).unwrap_or_default();
+    let rest = dir_name[..marker_start].trim_end_matches([' ', '_', '-', '.', '(', '[']);
+    Some((format!("{}/{rest}", parent.to_lowercase()), disc_number))
+}
+
+/// Un-deletes an album found again by the crawler,
+/// and picks up replaygain tags added since it was last crawled,
+/// its artist as last read, and the music directory it was found in
+fn refresh_album(
+    tx: &mut SqliteConnection,
+    row: AlbumRow,
+    new_row: &NewAlbumRow,
+) -> Result<(Album, Reconciled), DbError> {
+    use super::schema::albums::dsl::*;
+    use diesel::prelude::*;
+
+    // NOTE the other directories of a split album may not have the tags
+    let new_gain = new_row.album_gain.or(row.album_gain);
+    let new_peak = new_row.album_peak.or(row.album_peak);
+    let gain_changed = (new_gain, new_peak) != (row.album_gain, row.album_peak);
+    let new_root = new_row.library_root.as_ref().or(row.library_root.as_ref());
+    let root_changed = new_root != row.library_root.as_ref();
+    let new_artist = new_row.artist.as_ref().or(row.artist.as_ref());
+    let artist_changed = new_artist != row.artist.as_ref();
+
+    if !row.deleted && !gain_changed && !root_changed && !artist_changed {
+        return Ok((row.into(), Reconciled::Unchanged));
+    }
+
+    let reconciled = if row.deleted {
+        Reconciled::Added
+    } else {
+        Reconciled::Updated
+    };
+
+    let refreshed_row: AlbumRow = diesel::update(albums)
+        .filter(id.eq(row.id))
+        .set((
+            deleted.eq(false),
+            album_gain.eq(new_gain),
+            album_peak.eq(new_peak),
+            library_root.eq(new_root),
+            artist.eq(new_artist),
+        ))
+        .get_result(tx)?;
+
+    Ok((refreshed_row.into(), reconciled))
 }
 
 pub fn find_or_insert_song(
     tx: &mut SqliteConnection,
     new_song: NewSong,
-) -> Result<Song, DbError> {
+) -> Result<(Song, Reconciled), DbError> {
     use super::schema::songs;
     use diesel::prelude::*;
     use songs::dsl::*;
@@ -192,14 +571,152 @@ pub fn find_or_insert_song(
         songs.filter(file.eq(&new_row.file)).first(tx).optional()?;
 
     if let Some(existing_row) = existing_row {
-        return Ok(existing_row.into());
+        let tags_changed = (
+            existing_row.track_gain,
+            existing_row.track_peak,
+            &existing_row.genre,
+            existing_row.track_total,
+            existing_row.bpm,
+            &existing_row.initial_key,
+        ) != (
+            new_row.track_gain,
+            new_row.track_peak,
+            &new_row.genre,
+            new_row.track_total,
+            new_row.bpm,
+            &new_row.initial_key,
+        );
+
+        if !existing_row.deleted && !tags_changed {
+            return Ok((existing_row.into(), Reconciled::Unchanged));
+        }
+
+        let reconciled = if existing_row.deleted {
+            Reconciled::Added
+        } else {
+            Reconciled::Updated
+        };
+
+        // the file came back (it may also have moved to another album),
+        // or its replaygain, genre, track total, bpm, or key tags changed;
+        // either way it's worth trying to play again
+        let refreshed_row: SongRow = diesel::update(songs)
+            .filter(id.eq(existing_row.id))
+            .set((
+                deleted.eq(false),
+                unplayable.eq(false),
+                album_id.eq(new_row.album_id),
+                track_gain.eq(new_row.track_gain),
+                track_peak.eq(new_row.track_peak),
+                genre.eq(&new_row.genre),
+                track_total.eq(new_row.track_total),
+                bpm.eq(new_row.bpm),
+                initial_key.eq(&new_row.initial_key),
+            ))
+            .get_result(tx)?;
+
+        return Ok((refreshed_row.into(), reconciled));
     }
 
     let created_row: SongRow = diesel::insert_into(songs::table)
         .values(&new_row)
         .get_result(tx)?;
 
-    Ok(created_row.into())
+    Ok((created_row.into(), Reconciled::Added))
+}
+
+pub fn all_albums(tx: &mut SqliteConnection) -> Result<Vec<Album>, DbError> {
+    use super::schema::albums::dsl::*;
+    use diesel::prelude::*;
+
+    let rows: Vec<AlbumRow> = albums.filter(deleted.eq(false)).load(tx)?;
+
+    Ok(rows.into_iter().map(Into::into).collect())
+}
+
+pub fn all_songs(tx: &mut SqliteConnection) -> Result<Vec<Song>, DbError> {
+    use super::schema::songs::dsl::*;
+    use diesel::prelude::*;
+
+    let rows: Vec<SongRow> = songs.filter(deleted.eq(false)).load(tx)?;
+
+    Ok(rows.into_iter().map(Into::into).collect())
+}
+
+pub fn soft_delete_songs(
+    tx: &mut SqliteConnection,
+    song_ids: &[SongId],
+) -> Result<(), DbError> {
+    use super::schema::songs::dsl::*;
+    use diesel::prelude::*;
+
+    let song_ids: Vec<i32> = song_ids.iter().map(|SongId(song_id)| *song_id).collect();
+
+    diesel::update(songs)
+        .filter(id.eq_any(&song_ids))
+        .set(deleted.eq(true))
+        .execute(tx)?;
+
+    let album_ids: Vec<i32> = songs
+        .filter(id.eq_any(&song_ids))
+        .select(album_id)
+        .distinct()
+        .load(tx)?;
+    for album in album_ids {
+        update_album_runtime(tx, AlbumId(album))?;
+    }
+
+    Ok(())
+}
+
+/// Caches the total length of the album's remaining songs, returning it
+pub fn update_album_runtime(
+    tx: &mut SqliteConnection,
+    AlbumId(album_id): AlbumId,
+) -> Result<i64, DbError> {
+    use super::schema::{albums, songs};
+    use diesel::prelude::*;
+
+    // NOTE this covers every directory of an album split across discs
+    let song_seconds: Vec<i64> = songs::table
+        .filter(songs::album_id.eq(album_id))
+        .filter(songs::deleted.eq(false))
+        .select(songs::total_seconds)
+        .load(tx)?;
+    let total_seconds: i64 = song_seconds.iter().sum();
+
+    diesel::update(albums::table)
+        .filter(albums::id.eq(album_id))
+        .set(albums::total_seconds.eq(total_seconds))
+        .execute(tx)?;
+
+    Ok(total_seconds)
+}
+
+/// Soft-deletes albums with no remaining songs, returning their ids
+pub fn soft_delete_empty_albums(
+    tx: &mut SqliteConnection,
+) -> Result<Vec<AlbumId>, DbError> {
+    use super::schema::{albums, songs};
+    use diesel::dsl::{exists, not};
+    use diesel::prelude::*;
+
+    let live_songs = songs::table
+        .filter(songs::album_id.eq(albums::id))
+        .filter(songs::deleted.eq(false));
+
+    let empty_album_ids: Vec<i32> = albums::table
+        .filter(albums::deleted.eq(false))
+        .filter(not(exists(live_songs)))
+        .select(albums::id)
+        .load(tx)?;
+
+    diesel::update(albums::table)
+        .filter(albums::id.eq_any(&empty_album_ids))
+        .set(albums::deleted.eq(true))
+        .execute(tx)?;
+
+    Ok(empty_album_ids.into_iter().map(AlbumId).collect())
 }
 
 pub fn add_resized_image_location(
@@ -219,8 +736,1577 @@ pub fn add_resized_image_location(
     Ok(())
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum DbError {
-    #[error(transparent)]
-    Diesel(#[from] DieselError),
+pub fn set_song_favorite(
+    tx: &mut SqliteConnection,
+    SongId(song_id): SongId,
+    is_favorite: bool,
+) -> Result<(), DbError> {
+    use super::schema::songs;
+    use diesel::prelude::*;
+
+    diesel::update(songs::table)
+        .filter(songs::id.eq(song_id))
+        .set(songs::favorite.eq(is_favorite))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// Marks a song whose file the player couldn't open; it's cleared when
+/// the crawler finds the file again after it was gone, or with new tags
+pub fn set_song_unplayable(
+    tx: &mut SqliteConnection,
+    SongId(song_id): SongId,
+) -> Result<(), DbError> {
+    use super::schema::songs;
+    use diesel::prelude::*;
+
+    diesel::update(songs::table)
+        .filter(songs::id.eq(song_id))
+        .set(songs::unplayable.eq(true))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// Replaces the active session's saved queue, if any
+pub fn save_queue(tx: &mut SqliteConnection, saved: &SavedQueue) -> Result<(), DbError> {
+    use super::schema::{saved_queue_songs, saved_queues};
+    use diesel::prelude::*;
+
+    clear_saved_queue(tx)?;
+
+    let new_row = NewSavedQueueRow {
+        current_index: saved.current_index as i32,
+        elapsed_seconds: saved.elapsed_seconds,
+        from_crash: saved.from_crash,
+        playing: saved.playing,
+        session_id: active_session_id(tx)?,
+    };
+    let created_row: SavedQueueRow = diesel::insert_into(saved_queues::table)
+        .values(&new_row)
+        .get_result(tx)?;
+
+    let unshuffled_positions = saved
+        .unshuffled_song_ids
+        .as_ref()
+        .and_then(|unshuffled| unshuffled_positions(&saved.song_ids, unshuffled));
+    let song_rows: Vec<SavedQueueSongRow> = saved
+        .song_ids
+        .iter()
+        .enumerate()
+        .map(|(position, SongId(song_id))| SavedQueueSongRow {
+            saved_queue_id: created_row.id,
+            position: position as i32,
+            song_id: *song_id,
+            unshuffled_position: unshuffled_positions
+                .as_ref()
+                .map(|positions| positions[position] as i32),
+        })
+        .collect();
+    diesel::insert_into(saved_queue_songs::table)
+        .values(&song_rows)
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// Loads and clears the active session's saved queue, if any.
+/// Restoring the queue saves it again, so it's only restored once.
+pub fn take_saved_queue(
+    tx: &mut SqliteConnection,
+) -> Result<Option<SavedQueue>, DbError> {
+    use super::schema::{saved_queue_songs, saved_queues};
+    use diesel::prelude::*;
+
+    let queue_row: Option<SavedQueueRow> = saved_queues::table
+        .filter(saved_queues::session_id.is(active_session_id(tx)?))
+        .first(tx)
+        .optional()?;
+
+    let Some(queue_row) = queue_row else {
+        return Ok(None);
+    };
+
+    let song_rows: Vec<SavedQueueSongRow> = saved_queue_songs::table
+        .filter(saved_queue_songs::saved_queue_id.eq(queue_row.id))
+        .order(saved_queue_songs::position)
+        .load(tx)?;
+
+    clear_saved_queue(tx)?;
+
+    let unshuffled_song_ids = song_rows
+        .iter()
+        .map(|row| Some((row.unshuffled_position?, SongId(row.song_id))))
+        .collect::<Option<Vec<_>>>()
+        .filter(|unshuffled| !unshuffled.is_empty())
+        .map(|mut unshuffled| {
+            unshuffled.sort_by_key(|(position, _)| *position);
+            unshuffled.into_iter().map(|(_, song_id)| song_id).collect()
+        });
+
+    Ok(Some(SavedQueue {
+        song_ids: song_rows
+            .into_iter()
+            .map(|row| SongId(row.song_id))
+            .collect(),
+        current_index: queue_row.current_index as usize,
+        elapsed_seconds: queue_row.elapsed_seconds,
+        from_crash: queue_row.from_crash,
+        playing: queue_row.playing,
+        unshuffled_song_ids,
+    }))
+}
+
+/// Where each song in play order falls in the order from before shuffling;
+/// None if the two orders don't hold the same songs
+fn unshuffled_positions(
+    song_ids: &[SongId],
+    unshuffled: &[SongId],
+) -> Option<Vec<usize>> {
+    if song_ids.len() != unshuffled.len() {
+        return None;
+    }
+
+    // NOTE duplicates are matched up in order
+    let mut positions: Vec<Option<usize>> = vec![None; song_ids.len()];
+    for (unshuffled_position, unshuffled_id) in unshuffled.iter().enumerate() {
+        let index = (0..song_ids.len()).find(|&index| {
+            song_ids[index] == *unshuffled_id && positions[index].is_none()
+        })?;
+        positions[index] = Some(unshuffled_position);
+    }
+
+    positions.into_iter().collect()
+}
+
+/// Clears the active session's saved queue, if any
+pub fn clear_saved_queue(tx: &mut SqliteConnection) -> Result<(), DbError> {
+    let session_id = active_session_id(tx)?;
+    clear_session_queue(tx, session_id)
+}
+
+/// None = the default session
+fn clear_session_queue(
+    tx: &mut SqliteConnection,
+    session_id: Option<i32>,
+) -> Result<(), DbError> {
+    use super::schema::{saved_queue_songs, saved_queues};
+    use diesel::prelude::*;
+
+    let queue_ids = saved_queues::table
+        .filter(saved_queues::session_id.is(session_id))
+        .select(saved_queues::id);
+
+    diesel::delete(saved_queue_songs::table)
+        .filter(saved_queue_songs::saved_queue_id.eq_any(queue_ids))
+        .execute(tx)?;
+    diesel::delete(saved_queues::table)
+        .filter(saved_queues::session_id.is(session_id))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// A named queue to switch between, like 'Work' or 'Workout',
+/// with its own position in its own queue.
+/// The default session, active until another is chosen, has no row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id: SessionId,
+    pub name: String,
+    /// Its queue is the one in the player
+    pub active: bool,
+    /// The name of its transition between songs
+    pub transition: String,
+}
+
+impl From<SessionRow> for Session {
+    fn from(row: SessionRow) -> Self {
+        Self {
+            id: SessionId(row.id),
+            name: row.name,
+            active: row.active,
+            transition: row.transition,
+        }
+    }
+}
+
+/// None = the default session
+fn active_session_id(tx: &mut SqliteConnection) -> Result<Option<i32>, DbError> {
+    use super::schema::sessions;
+    use diesel::prelude::*;
+
+    let session_id = sessions::table
+        .filter(sessions::active.eq(true))
+        .select(sessions::id)
+        .first(tx)
+        .optional()?;
+
+    Ok(session_id)
+}
+
+pub fn create_session(tx: &mut SqliteConnection, name: &str) -> Result<Session, DbError> {
+    use super::schema::sessions;
+    use diesel::prelude::*;
+
+    let new_row = NewSessionRow { name: name.to_string() };
+    let created_row: SessionRow = diesel::insert_into(sessions::table)
+        .values(&new_row)
+        .get_result(tx)?;
+
+    Ok(created_row.into())
+}
+
+/// All named sessions, by name
+pub fn all_sessions(tx: &mut SqliteConnection) -> Result<Vec<Session>, DbError> {
+    use super::schema::sessions;
+    use diesel::prelude::*;
+
+    let rows: Vec<SessionRow> = sessions::table.order(sessions::name).load(tx)?;
+
+    Ok(rows.into_iter().map(Session::from).collect())
+}
+
+pub fn set_session_transition(
+    tx: &mut SqliteConnection,
+    SessionId(session_id): SessionId,
+    transition: &str,
+) -> Result<(), DbError> {
+    use super::schema::sessions;
+    use diesel::prelude::*;
+
+    diesel::update(sessions::table.find(session_id))
+        .set(sessions::transition.eq(transition))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// Deletes a session along with its saved queue
+pub fn delete_session(
+    tx: &mut SqliteConnection,
+    SessionId(session_id): SessionId,
+) -> Result<(), DbError> {
+    use super::schema::sessions;
+    use diesel::prelude::*;
+
+    clear_session_queue(tx, Some(session_id))?;
+    diesel::delete(sessions::table.find(session_id)).execute(tx)?;
+
+    Ok(())
+}
+
+/// Makes a session (None = the default session) the active one,
+/// and takes its saved queue to restore in the player.
+///
+/// The player only saves its position every few seconds, so the session being left
+/// is given the ui's latest position in the current song (0) at (1) seconds.
+pub fn switch_session(
+    tx: &mut SqliteConnection,
+    session_id: Option<SessionId>,
+    leaving_position: Option<(SongId, f64)>,
+) -> Result<Option<SavedQueue>, DbError> {
+    use super::schema::sessions;
+    use diesel::prelude::*;
+
+    if let Some((song_id, elapsed_seconds)) = leaving_position {
+        update_saved_position(tx, song_id, elapsed_seconds)?;
+    }
+
+    diesel::update(sessions::table)
+        .set(sessions::active.eq(false))
+        .execute(tx)?;
+    if let Some(SessionId(session_id)) = session_id {
+        diesel::update(sessions::table.find(session_id))
+            .set(sessions::active.eq(true))
+            .execute(tx)?;
+    }
+
+    take_saved_queue(tx)
+}
+
+/// Updates the active session's saved position, if it's still on the song
+fn update_saved_position(
+    tx: &mut SqliteConnection,
+    SongId(song_id): SongId,
+    elapsed_seconds: f64,
+) -> Result<(), DbError> {
+    use super::schema::{saved_queue_songs, saved_queues};
+    use diesel::prelude::*;
+
+    let queue_row: Option<SavedQueueRow> = saved_queues::table
+        .filter(saved_queues::session_id.is(active_session_id(tx)?))
+        .first(tx)
+        .optional()?;
+    let Some(queue_row) = queue_row else {
+        return Ok(());
+    };
+
+    let current_song_id: Option<i32> = saved_queue_songs::table
+        .filter(saved_queue_songs::saved_queue_id.eq(queue_row.id))
+        .filter(saved_queue_songs::position.eq(queue_row.current_index))
+        .select(saved_queue_songs::song_id)
+        .first(tx)
+        .optional()?;
+
+    if current_song_id == Some(song_id) {
+        diesel::update(saved_queues::table.find(queue_row.id))
+            .set(saved_queues::elapsed_seconds.eq(elapsed_seconds))
+            .execute(tx)?;
+    }
+
+    Ok(())
+}
+
+/// The device column for the default output
+const DEFAULT_OUTPUT_DEVICE: &str = "";
+
+/// Replaces the equalizer curve saved for an output device (None = the default output),
+/// as a gain in dB per band
+pub fn save_equalizer(
+    tx: &mut SqliteConnection,
+    device_name: Option<&str>,
+    gains_db: &[f64],
+) -> Result<(), DbError> {
+    use super::schema::equalizer_bands;
+    use diesel::prelude::*;
+
+    let device_name = device_name.unwrap_or(DEFAULT_OUTPUT_DEVICE);
+    diesel::delete(
+        equalizer_bands::table.filter(equalizer_bands::device.eq(device_name)),
+    )
+    .execute(tx)?;
+
+    let band_rows: Vec<EqualizerBandRow> = gains_db
+        .iter()
+        .enumerate()
+        .map(|(band, gain_db)| EqualizerBandRow {
+            device: device_name.to_string(),
+            band: band as i32,
+            gain_db: *gain_db,
+        })
+        .collect();
+    diesel::insert_into(equalizer_bands::table)
+        .values(&band_rows)
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// The gain in dB for each band saved for an output device, in order; empty = none saved
+pub fn load_equalizer(
+    tx: &mut SqliteConnection,
+    device_name: Option<&str>,
+) -> Result<Vec<f64>, DbError> {
+    use super::schema::equalizer_bands;
+    use diesel::prelude::*;
+
+    let band_rows: Vec<EqualizerBandRow> = equalizer_bands::table
+        .filter(equalizer_bands::device.eq(device_name.unwrap_or(DEFAULT_OUTPUT_DEVICE)))
+        .order(equalizer_bands::band)
+        .load(tx)?;
+
+    Ok(band_rows.into_iter().map(|row| row.gain_db).collect())
+}
+
+/// What a sort name override applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SortKind {
+    Artist,
+    Album,
+}
+
+impl SortKind {
+    pub const ALL: [SortKind; 2] = [Self::Artist, Self::Album];
+
+    fn to_saved(self) -> &'static str {
+        match self {
+            Self::Artist => "artist",
+            Self::Album => "album",
+        }
+    }
+
+    fn from_saved(kind: &str) -> Option<Self> {
+        match kind {
+            "artist" => Some(Self::Artist),
+            "album" => Some(Self::Album),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SortKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Artist => "Artist",
+            Self::Album => "Album",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Sorts an artist or album title (name) as another (sort_name),
+/// ie 'The Beatles' as 'Beatles'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortName {
+    pub kind: SortKind,
+    /// As tagged
+    pub name: String,
+    pub sort_name: String,
+}
+
+pub fn all_sort_names(tx: &mut SqliteConnection) -> Result<Vec<SortName>, DbError> {
+    use super::schema::sort_names;
+    use diesel::prelude::*;
+
+    let rows: Vec<SortNameRow> = sort_names::table.load(tx)?;
+
+    let sort_names = rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(SortName {
+                kind: SortKind::from_saved(&row.kind)?,
+                name: row.name,
+                sort_name: row.sort_name,
+            })
+        })
+        .collect();
+
+    Ok(sort_names)
+}
+
+/// Adds or replaces the override for a name
+pub fn save_sort_name(
+    tx: &mut SqliteConnection,
+    sort_name: &SortName,
+) -> Result<(), DbError> {
+    use super::schema::sort_names;
+    use diesel::prelude::*;
+
+    let row = SortNameRow {
+        kind: sort_name.kind.to_saved().to_string(),
+        name: sort_name.name.clone(),
+        sort_name: sort_name.sort_name.clone(),
+    };
+    diesel::replace_into(sort_names::table)
+        .values(&row)
+        .execute(tx)?;
+
+    Ok(())
+}
+
+pub fn delete_sort_name(
+    tx: &mut SqliteConnection,
+    kind: SortKind,
+    name: &str,
+) -> Result<(), DbError> {
+    use super::schema::sort_names;
+    use diesel::prelude::*;
+
+    diesel::delete(
+        sort_names::table
+            .filter(sort_names::kind.eq(kind.to_saved()))
+            .filter(sort_names::name.eq(name)),
+    )
+    .execute(tx)?;
+
+    Ok(())
+}
+
+/// An album's title and artist as edited in clef; None keeps the tag
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AlbumRename {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+/// Saves the edits in the db, leaving the files' tags alone;
+/// they outlast rescans, since the crawler only updates the tagged columns
+pub fn rename_album(
+    tx: &mut SqliteConnection,
+    album_id: AlbumId,
+    renamed: &AlbumRename,
+) -> Result<(), DbError> {
+    use super::schema::albums;
+    use diesel::prelude::*;
+
+    diesel::update(albums::table)
+        .filter(albums::id.eq(album_id.0))
+        .set((
+            albums::edited_title.eq(&renamed.title),
+            albums::edited_artist.eq(&renamed.artist),
+        ))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// None or blank clears the notes; NOTE like renames, they outlast rescans
+pub fn save_album_notes(
+    tx: &mut SqliteConnection,
+    AlbumId(album_id): AlbumId,
+    notes: Option<&str>,
+) -> Result<(), DbError> {
+    use super::schema::albums;
+    use diesel::prelude::*;
+
+    let notes = notes.filter(|notes| !notes.trim().is_empty());
+    diesel::update(albums::table.find(album_id))
+        .set(albums::notes.eq(notes))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// An album's release on MusicBrainz, as found by a lookup
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicBrainzRelease {
+    pub release_id: String,
+    pub artist: Option<String>,
+    pub release_date: Option<String>,
+    pub tracks: Vec<MusicBrainzTrack>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MusicBrainzTrack {
+    pub recording_id: String,
+    pub title: String,
+    pub disc_number: i32,
+    pub track_number: i32,
+}
+
+/// Records the release and recording ids, and fills in the tags the album is missing.
+/// Songs are matched by disc and track number; tags that are already there are kept.
+/// NOTE the filled in tags are saved as tags, so the crawler keeps them
+/// unless the files gain their own
+pub fn fill_from_musicbrainz(
+    tx: &mut SqliteConnection,
+    AlbumId(album_id): AlbumId,
+    release: &MusicBrainzRelease,
+) -> Result<(Album, Vec<Song>), DbError> {
+    use super::schema::{albums, songs};
+    use diesel::prelude::*;
+
+    let album_row: AlbumRow = albums::table.find(album_id).first(tx)?;
+    let album_row: AlbumRow = diesel::update(albums::table.find(album_id))
+        .set((
+            albums::musicbrainz_release_id.eq(&release.release_id),
+            albums::artist.eq(album_row.artist.as_ref().or(release.artist.as_ref())),
+            albums::release_date.eq(album_row
+                .release_date
+                .as_ref()
+                .or(release.release_date.as_ref())),
+        ))
+        .get_result(tx)?;
+
+    let song_rows: Vec<SongRow> = songs::table
+        .filter(songs::album_id.eq(album_id))
+        .filter(songs::deleted.eq(false))
+        .load(tx)?;
+
+    let mut filled_songs = Vec::new();
+    for song_row in song_rows {
+        let track = release.tracks.iter().find(|track| {
+            song_row.track_number == Some(track.track_number)
+                && song_row.disc_number.unwrap_or(1) == track.disc_number
+        });
+        let unchanged = |track: &MusicBrainzTrack| {
+            song_row.title.is_some()
+                && song_row.musicbrainz_recording_id.as_ref() == Some(&track.recording_id)
+        };
+        let Some(track) = track.filter(|track| !unchanged(track)) else {
+            filled_songs.push(song_row.into());
+            continue;
+        };
+
+        let song_row: SongRow = diesel::update(songs::table.find(song_row.id))
+            .set((
+                songs::musicbrainz_recording_id.eq(&track.recording_id),
+                songs::title.eq(song_row.title.as_ref().unwrap_or(&track.title)),
+            ))
+            .get_result(tx)?;
+        filled_songs.push(song_row.into());
+    }
+
+    Ok((album_row.into(), filled_songs))
+}
+
+/// A song's lyrics as read by the crawler, or None if it has none
+pub fn song_lyrics(
+    tx: &mut SqliteConnection,
+    song_id: SongId,
+) -> Result<Option<String>, DbError> {
+    use super::schema::song_lyrics;
+    use diesel::prelude::*;
+
+    let row: Option<SongLyricsRow> =
+        song_lyrics::table.find(song_id.0).first(tx).optional()?;
+
+    Ok(row.map(|row| row.lyrics))
+}
+
+/// Replaces a song's lyrics; None removes them.
+/// NOTE unchanged lyrics aren't rewritten, since every crawl saves them
+pub fn save_song_lyrics(
+    tx: &mut SqliteConnection,
+    song_id: SongId,
+    lyrics: Option<&str>,
+) -> Result<(), DbError> {
+    use super::schema::song_lyrics;
+    use diesel::prelude::*;
+
+    if song_lyrics(tx, song_id)?.as_deref() == lyrics {
+        return Ok(());
+    }
+
+    match lyrics {
+        Some(lyrics) => {
+            let row = SongLyricsRow {
+                song_id: song_id.0,
+                lyrics: lyrics.to_string(),
+            };
+            diesel::replace_into(song_lyrics::table)
+                .values(&row)
+                .execute(tx)?;
+        }
+        None => {
+            diesel::delete(song_lyrics::table.find(song_id.0)).execute(tx)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A song's waveform for the seek bar, or None if it hasn't been computed
+pub fn song_waveform(
+    tx: &mut SqliteConnection,
+    song_id: SongId,
+) -> Result<Option<Vec<u8>>, DbError> {
+    use super::schema::song_waveforms;
+    use diesel::prelude::*;
+
+    let row: Option<SongWaveformRow> =
+        song_waveforms::table.find(song_id.0).first(tx).optional()?;
+
+    Ok(row.map(|row| row.peaks))
+}
+
+pub fn save_song_waveform(
+    tx: &mut SqliteConnection,
+    song_id: SongId,
+    peaks: &[u8],
+) -> Result<(), DbError> {
+    use super::schema::song_waveforms;
+    use diesel::prelude::*;
+
+    let row = SongWaveformRow {
+        song_id: song_id.0,
+        peaks: peaks.to_vec(),
+    };
+    diesel::replace_into(song_waveforms::table)
+        .values(&row)
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// The number of recorded plays of a song
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayCount {
+    pub song_id: SongId,
+    pub plays: i64,
+    /// When the latest play started, as a unix timestamp in seconds
+    pub last_played_at: i64,
+}
+
+/// Records a play of a song, which is finished later by finish_play.
+/// Times are unix timestamps in seconds; listened_seconds doesn't count seeks or pauses.
+pub fn start_play(
+    tx: &mut SqliteConnection,
+    SongId(song_id): SongId,
+    started_at: i64,
+    percent_played: f64,
+    listened_seconds: f64,
+) -> Result<PlayId, DbError> {
+    use super::schema::plays;
+    use diesel::prelude::*;
+
+    let new_row = NewPlayRow {
+        song_id,
+        started_at,
+        percent_played,
+        listened_seconds,
+    };
+    let play_id: i32 = diesel::insert_into(plays::table)
+        .values(&new_row)
+        .returning(plays::id)
+        .get_result(tx)?;
+
+    Ok(PlayId(play_id))
+}
+
+/// Marks a play as finished, when the song changed or playback stopped.
+/// Plays left unfinished were still going when the app closed.
+pub fn finish_play(
+    tx: &mut SqliteConnection,
+    PlayId(play_id): PlayId,
+    completed_at: i64,
+    percent_played: f64,
+    listened_seconds: f64,
+) -> Result<(), DbError> {
+    use super::schema::plays;
+    use diesel::prelude::*;
+
+    diesel::update(plays::table)
+        .filter(plays::id.eq(play_id))
+        .set((
+            plays::completed_at.eq(completed_at),
+            plays::percent_played.eq(percent_played),
+            plays::listened_seconds.eq(listened_seconds),
+        ))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// A recorded play, as kept in the play history
+#[derive(Debug, Clone, PartialEq)]
+pub struct Play {
+    pub song_id: SongId,
+    /// Unix timestamps in seconds; see start_play
+    pub started_at: i64,
+    /// None for plays still going when the app closed
+    pub completed_at: Option<i64>,
+    pub percent_played: f64,
+    pub listened_seconds: f64,
+}
+
+/// Every recorded play, oldest first
+pub fn all_plays(tx: &mut SqliteConnection) -> Result<Vec<Play>, DbError> {
+    use super::schema::plays;
+    use diesel::prelude::*;
+
+    let rows: Vec<(i32, i64, Option<i64>, f64, f64)> = plays::table
+        .order(plays::started_at)
+        .select((
+            plays::song_id,
+            plays::started_at,
+            plays::completed_at,
+            plays::percent_played,
+            plays::listened_seconds,
+        ))
+        .load(tx)?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(song_id, started_at, completed_at, percent_played, listened_seconds)| {
+                Play {
+                    song_id: SongId(song_id),
+                    started_at,
+                    completed_at,
+                    percent_played,
+                    listened_seconds,
+                }
+            },
+        )
+        .collect())
+}
+
+/// Records a play from another library's history, already finished or not
+pub fn insert_play(tx: &mut SqliteConnection, play: &Play) -> Result<(), DbError> {
+    use super::schema::plays;
+    use diesel::prelude::*;
+
+    diesel::insert_into(plays::table)
+        .values((
+            plays::song_id.eq(play.song_id.0),
+            plays::started_at.eq(play.started_at),
+            plays::completed_at.eq(play.completed_at),
+            plays::percent_played.eq(play.percent_played),
+            plays::listened_seconds.eq(play.listened_seconds),
+        ))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// The time spent listening across every recorded play, in seconds
+pub fn total_listened_seconds(tx: &mut SqliteConnection) -> Result<f64, DbError> {
+    use super::schema::plays;
+    use diesel::prelude::*;
+
+    let total: Option<f64> = plays::table
+        .select(diesel::dsl::sum(plays::listened_seconds))
+        .first(tx)?;
+
+    Ok(total.unwrap_or_default())
+}
+
+/// The play count of every song that has been played, in no particular order
+pub fn play_counts(tx: &mut SqliteConnection) -> Result<Vec<PlayCount>, DbError> {
+    use super::schema::plays;
+    use diesel::dsl::count_star;
+    use diesel::prelude::*;
+
+    let rows: Vec<(i32, i64, Option<i64>)> = plays::table
+        .group_by(plays::song_id)
+        .select((
+            plays::song_id,
+            count_star(),
+            diesel::dsl::max(plays::started_at),
+        ))
+        .load(tx)?;
+
+    let counts = rows
+        .into_iter()
+        .map(|(song_id, plays, last_played_at)| PlayCount {
+            song_id: SongId(song_id),
+            plays,
+            last_played_at: last_played_at.unwrap_or_default(),
+        })
+        .collect();
+
+    Ok(counts)
+}
+
+/// The plays of one song over a span of time
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongListening {
+    pub song_id: SongId,
+    pub plays: i64,
+    pub listened_seconds: f64,
+}
+
+/// Plays started since a unix timestamp, by song, in no particular order
+pub fn song_listening_since(
+    tx: &mut SqliteConnection,
+    since: i64,
+) -> Result<Vec<SongListening>, DbError> {
+    use super::schema::plays;
+    use diesel::dsl::count_star;
+    use diesel::prelude::*;
+
+    let rows: Vec<(i32, i64, Option<f64>)> = plays::table
+        .filter(plays::started_at.ge(since))
+        .group_by(plays::song_id)
+        .select((
+            plays::song_id,
+            count_star(),
+            diesel::dsl::sum(plays::listened_seconds),
+        ))
+        .load(tx)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(song_id, plays, listened_seconds)| SongListening {
+            song_id: SongId(song_id),
+            plays,
+            listened_seconds: listened_seconds.unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Seconds listened by local weekday (0 = sunday) and then hour
+pub type ListeningClock = [[f64; 24]; 7];
+
+/// Plays started since a unix timestamp, by when they started in the local time zone.
+/// NOTE each play counts toward the hour it started in
+pub fn listening_clock(
+    tx: &mut SqliteConnection,
+    since: i64,
+) -> Result<ListeningClock, DbError> {
+    use diesel::prelude::*;
+    use diesel::sql_types::BigInt;
+
+    let rows: Vec<ClockRow> = diesel::sql_query(
+        "SELECT \
+           CAST(strftime('%w', started_at, 'unixepoch', 'localtime') AS INTEGER) AS weekday, \
+           CAST(strftime('%H', started_at, 'unixepoch', 'localtime') AS INTEGER) AS hour, \
+           TOTAL(listened_seconds) AS listened_seconds \
+         FROM plays WHERE started_at >= ? GROUP BY weekday, hour",
+    )
+    .bind::<BigInt, _>(since)
+    .load(tx)?;
+
+    let mut clock = [[0.0; 24]; 7];
+    for row in rows {
+        if let Some(hours) = clock.get_mut(row.weekday as usize) {
+            if let Some(seconds) = hours.get_mut(row.hour as usize) {
+                *seconds = row.listened_seconds;
+            }
+        }
+    }
+
+    Ok(clock)
+}
+
+/// A local day with recorded plays, for rebuilding that day's listening session
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListeningDay {
+    /// The local date, as yyyy-mm-dd
+    pub day: String,
+    pub plays: i64,
+}
+
+/// The days with plays in the local time zone, latest first
+pub fn listening_days(
+    tx: &mut SqliteConnection,
+    limit: i64,
+) -> Result<Vec<ListeningDay>, DbError> {
+    use diesel::prelude::*;
+    use diesel::sql_types::BigInt;
+
+    let rows: Vec<ListeningDayRow> = diesel::sql_query(
+        "SELECT \
+           strftime('%Y-%m-%d', started_at, 'unixepoch', 'localtime') AS day, \
+           COUNT(*) AS plays \
+         FROM plays GROUP BY day ORDER BY day DESC LIMIT ?",
+    )
+    .bind::<BigInt, _>(limit)
+    .load(tx)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ListeningDay { day: row.day, plays: row.plays })
+        .collect())
+}
+
+/// The songs played on a local day (yyyy-mm-dd), in the order they started,
+/// including repeats; see listening_days
+pub fn plays_on_day(
+    tx: &mut SqliteConnection,
+    day: &str,
+) -> Result<Vec<SongId>, DbError> {
+    use diesel::prelude::*;
+    use diesel::sql_types::Text;
+
+    let rows: Vec<DayPlayRow> = diesel::sql_query(
+        "SELECT song_id FROM plays \
+         WHERE strftime('%Y-%m-%d', started_at, 'unixepoch', 'localtime') = ? \
+         ORDER BY started_at, id",
+    )
+    .bind::<Text, _>(day)
+    .load(tx)?;
+
+    Ok(rows.into_iter().map(|row| SongId(row.song_id)).collect())
+}
+
+/// The songs played at least min_plays times on today's local date in previous years,
+/// ie "on this day", most played first; now is a unix timestamp in seconds
+pub fn on_this_day(
+    tx: &mut SqliteConnection,
+    now: i64,
+    min_plays: i64,
+    limit: i64,
+) -> Result<Vec<SongId>, DbError> {
+    use diesel::prelude::*;
+    use diesel::sql_types::BigInt;
+
+    let rows: Vec<DayPlayRow> = diesel::sql_query(
+        "SELECT song_id FROM plays \
+         WHERE strftime('%m-%d', started_at, 'unixepoch', 'localtime') \
+             = strftime('%m-%d', ?1, 'unixepoch', 'localtime') \
+           AND strftime('%Y', started_at, 'unixepoch', 'localtime') \
+             < strftime('%Y', ?1, 'unixepoch', 'localtime') \
+         GROUP BY song_id HAVING COUNT(*) >= ?2 \
+         ORDER BY COUNT(*) DESC, MIN(started_at) LIMIT ?3",
+    )
+    .bind::<BigInt, _>(now)
+    .bind::<BigInt, _>(min_plays)
+    .bind::<BigInt, _>(limit)
+    .load(tx)?;
+
+    Ok(rows.into_iter().map(|row| SongId(row.song_id)).collect())
+}
+
+/// A playlist file from a music directory, ie an .m3u or .pls
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playlist {
+    pub id: PlaylistId,
+    pub file: Utf8PathBuf,
+    pub name: String,
+    /// The listed songs that are in the library, in order
+    pub songs: Vec<SongId>,
+}
+
+/// Saves a playlist file's songs, replacing what was saved from it by an earlier scan
+pub fn save_playlist(
+    tx: &mut SqliteConnection,
+    file: &Utf8Path,
+    name: &str,
+    song_ids: &[SongId],
+) -> Result<Playlist, DbError> {
+    use super::schema::{playlist_songs, playlists};
+    use diesel::prelude::*;
+
+    let existing: Option<i32> = playlists::table
+        .filter(playlists::file.eq(file.as_str()))
+        .select(playlists::id)
+        .first(tx)
+        .optional()?;
+
+    let playlist_id = match existing {
+        Some(playlist_id) => {
+            diesel::update(playlists::table)
+                .filter(playlists::id.eq(playlist_id))
+                .set(playlists::name.eq(name))
+                .execute(tx)?;
+            diesel::delete(playlist_songs::table)
+                .filter(playlist_songs::playlist_id.eq(playlist_id))
+                .execute(tx)?;
+
+            playlist_id
+        }
+
+        None => {
+            let new_row = NewPlaylistRow {
+                file: file.to_string(),
+                name: name.to_string(),
+            };
+
+            diesel::insert_into(playlists::table)
+                .values(&new_row)
+                .returning(playlists::id)
+                .get_result(tx)?
+        }
+    };
+
+    let song_rows: Vec<PlaylistSongRow> = song_ids
+        .iter()
+        .enumerate()
+        .map(|(position, SongId(song_id))| PlaylistSongRow {
+            playlist_id,
+            position: position as i32,
+            song_id: *song_id,
+        })
+        .collect();
+    diesel::insert_into(playlist_songs::table)
+        .values(&song_rows)
+        .execute(tx)?;
+
+    Ok(Playlist {
+        id: PlaylistId(playlist_id),
+        file: file.to_owned(),
+        name: name.to_string(),
+        songs: song_ids.to_vec(),
+    })
+}
+
+/// All playlist files, by name
+pub fn all_playlists(tx: &mut SqliteConnection) -> Result<Vec<Playlist>, DbError> {
+    use super::schema::{playlist_songs, playlists};
+    use diesel::prelude::*;
+
+    let playlist_rows: Vec<PlaylistRow> = playlists::table
+        .order((playlists::name, playlists::file))
+        .load(tx)?;
+    let song_rows: Vec<PlaylistSongRow> = playlist_songs::table
+        .order((playlist_songs::playlist_id, playlist_songs::position))
+        .load(tx)?;
+
+    let playlists = playlist_rows
+        .into_iter()
+        .map(|playlist_row| Playlist {
+            id: PlaylistId(playlist_row.id),
+            file: playlist_row.file.into(),
+            name: playlist_row.name,
+            songs: song_rows
+                .iter()
+                .filter(|song_row| song_row.playlist_id == playlist_row.id)
+                .map(|song_row| SongId(song_row.song_id))
+                .collect(),
+        })
+        .collect();
+
+    Ok(playlists)
+}
+
+/// Forgets playlists whose files are gone
+pub fn delete_playlists(
+    tx: &mut SqliteConnection,
+    playlist_ids: &[PlaylistId],
+) -> Result<(), DbError> {
+    use super::schema::{playlist_songs, playlists};
+    use diesel::prelude::*;
+
+    let playlist_ids: Vec<i32> = playlist_ids.iter().map(|PlaylistId(id)| *id).collect();
+    diesel::delete(playlist_songs::table)
+        .filter(playlist_songs::playlist_id.eq_any(&playlist_ids))
+        .execute(tx)?;
+    diesel::delete(playlists::table)
+        .filter(playlists::id.eq_any(&playlist_ids))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// A playlist's copy in the playlist folder, with what each side held when last synced
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistMirror {
+    pub file: Utf8PathBuf,
+    /// The playlist file it's a copy of
+    pub playlist_file: Utf8PathBuf,
+    /// The copy's contents
+    pub mirrored: String,
+    /// The playlist's songs, as a copy would list them
+    pub listed: String,
+}
+
+pub fn all_playlist_mirrors(
+    tx: &mut SqliteConnection,
+) -> Result<Vec<PlaylistMirror>, DbError> {
+    use super::schema::playlist_mirrors;
+    use diesel::prelude::*;
+
+    let rows: Vec<PlaylistMirrorRow> = playlist_mirrors::table.load(tx)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| PlaylistMirror {
+            file: row.file.into(),
+            playlist_file: row.playlist_file.into(),
+            mirrored: row.mirrored,
+            listed: row.listed,
+        })
+        .collect())
+}
+
+/// Saves a sync, replacing the last one for the same copy
+pub fn save_playlist_mirror(
+    tx: &mut SqliteConnection,
+    mirror: &PlaylistMirror,
+) -> Result<(), DbError> {
+    use super::schema::playlist_mirrors;
+    use diesel::prelude::*;
+
+    let row = PlaylistMirrorRow {
+        file: mirror.file.to_string(),
+        playlist_file: mirror.playlist_file.to_string(),
+        mirrored: mirror.mirrored.clone(),
+        listed: mirror.listed.clone(),
+    };
+    diesel::replace_into(playlist_mirrors::table)
+        .values(&row)
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// Forgets a copy that's no longer synced
+pub fn delete_playlist_mirror(
+    tx: &mut SqliteConnection,
+    file: &Utf8Path,
+) -> Result<(), DbError> {
+    use super::schema::playlist_mirrors;
+    use diesel::prelude::*;
+
+    diesel::delete(playlist_mirrors::table)
+        .filter(playlist_mirrors::file.eq(file.as_str()))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// A saved filter, played as a queue of the songs matching all of its rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmartPlaylist {
+    pub id: SmartPlaylistId,
+    pub name: String,
+    pub rules: Vec<SmartRule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmartRule {
+    /// The song artist contains the text, ignoring case
+    ArtistContains(String),
+    /// The song genre is the text, ignoring case
+    GenreIs(String),
+    Favorite,
+    /// Played at least this many times
+    PlayedAtLeast(i64),
+    /// Played within this many days
+    PlayedWithinDays(i64),
+    /// Not played within this many days, including never played
+    NotPlayedWithinDays(i64),
+}
+
+impl SmartRule {
+    /// The kind and value columns of a saved rule; exported rules use the same names
+    pub fn to_saved(&self) -> (&'static str, String) {
+        match self {
+            Self::ArtistContains(text) => ("artist_contains", text.clone()),
+            Self::GenreIs(genre) => ("genre_is", genre.clone()),
+            Self::Favorite => ("favorite", String::new()),
+            Self::PlayedAtLeast(count) => ("played_at_least", count.to_string()),
+            Self::PlayedWithinDays(days) => ("played_within_days", days.to_string()),
+            Self::NotPlayedWithinDays(days) => {
+                ("not_played_within_days", days.to_string())
+            }
+        }
+    }
+
+    /// None for kinds this version doesn't know
+    pub fn from_saved(kind: &str, value: &str) -> Option<Self> {
+        let rule = match kind {
+            "artist_contains" => Self::ArtistContains(value.to_string()),
+            "genre_is" => Self::GenreIs(value.to_string()),
+            "favorite" => Self::Favorite,
+            "played_at_least" => Self::PlayedAtLeast(value.parse().ok()?),
+            "played_within_days" => Self::PlayedWithinDays(value.parse().ok()?),
+            "not_played_within_days" => Self::NotPlayedWithinDays(value.parse().ok()?),
+            _ => return None,
+        };
+
+        Some(rule)
+    }
+}
+
+pub fn create_smart_playlist(
+    tx: &mut SqliteConnection,
+    name: &str,
+    rules: &[SmartRule],
+) -> Result<SmartPlaylist, DbError> {
+    use super::schema::{smart_playlist_rules, smart_playlists};
+    use diesel::prelude::*;
+
+    let new_row = NewSmartPlaylistRow { name: name.to_string() };
+    let created_row: SmartPlaylistRow = diesel::insert_into(smart_playlists::table)
+        .values(&new_row)
+        .get_result(tx)?;
+
+    let rule_rows: Vec<SmartPlaylistRuleRow> = rules
+        .iter()
+        .enumerate()
+        .map(|(position, rule)| {
+            let (kind, value) = rule.to_saved();
+            SmartPlaylistRuleRow {
+                smart_playlist_id: created_row.id,
+                position: position as i32,
+                kind: kind.to_string(),
+                value,
+            }
+        })
+        .collect();
+    diesel::insert_into(smart_playlist_rules::table)
+        .values(&rule_rows)
+        .execute(tx)?;
+
+    Ok(SmartPlaylist {
+        id: SmartPlaylistId(created_row.id),
+        name: created_row.name,
+        rules: rules.to_vec(),
+    })
+}
+
+/// All smart playlists, by name
+pub fn all_smart_playlists(
+    tx: &mut SqliteConnection,
+) -> Result<Vec<SmartPlaylist>, DbError> {
+    use super::schema::{smart_playlist_rules, smart_playlists};
+    use diesel::prelude::*;
+
+    let playlist_rows: Vec<SmartPlaylistRow> = smart_playlists::table
+        .order(smart_playlists::name)
+        .load(tx)?;
+    let rule_rows: Vec<SmartPlaylistRuleRow> = smart_playlist_rules::table
+        .order((
+            smart_playlist_rules::smart_playlist_id,
+            smart_playlist_rules::position,
+        ))
+        .load(tx)?;
+
+    let playlists = playlist_rows
+        .into_iter()
+        .map(|playlist_row| {
+            // NOTE rules saved by a later version are skipped
+            let rules = rule_rows
+                .iter()
+                .filter(|rule_row| rule_row.smart_playlist_id == playlist_row.id)
+                .filter_map(|rule_row| {
+                    SmartRule::from_saved(&rule_row.kind, &rule_row.value)
+                })
+                .collect();
+
+            SmartPlaylist {
+                id: SmartPlaylistId(playlist_row.id),
+                name: playlist_row.name,
+                rules,
+            }
+        })
+        .collect();
+
+    Ok(playlists)
+}
+
+pub fn delete_smart_playlist(
+    tx: &mut SqliteConnection,
+    SmartPlaylistId(playlist_id): SmartPlaylistId,
+) -> Result<(), DbError> {
+    use super::schema::{smart_playlist_rules, smart_playlists};
+    use diesel::prelude::*;
+
+    diesel::delete(smart_playlist_rules::table)
+        .filter(smart_playlist_rules::smart_playlist_id.eq(playlist_id))
+        .execute(tx)?;
+    diesel::delete(smart_playlists::table)
+        .filter(smart_playlists::id.eq(playlist_id))
+        .execute(tx)?;
+
+    Ok(())
+}
+
+/// The songs matching every rule, in no particular order.
+/// Day-based rules count back from now, a unix timestamp in seconds.
+pub fn smart_playlist_songs(
+    tx: &mut SqliteConnection,
+    rules: &[SmartRule],
+    now: i64,
+) -> Result<Vec<SongId>, DbError> {
+    use super::schema::{plays, songs};
+    use diesel::dsl::count_star;
+    use diesel::prelude::*;
+
+    const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+    let mut query = songs::table
+        .filter(songs::deleted.eq(false))
+        .select(songs::id)
+        .into_boxed();
+
+    for rule in rules {
+        query = match rule {
+            // NOTE sqlite's like ignores case for ascii
+            SmartRule::ArtistContains(text) => {
+                query.filter(songs::artist.like(format!("%{text}%")))
+            }
+
+            SmartRule::GenreIs(genre) => query.filter(songs::genre.like(genre.clone())),
+
+            SmartRule::Favorite => query.filter(songs::favorite.eq(true)),
+
+            SmartRule::PlayedAtLeast(count) => {
+                let played_enough = plays::table
+                    .group_by(plays::song_id)
+                    .having(count_star().ge(*count))
+                    .select(plays::song_id);
+
+                query.filter(songs::id.eq_any(played_enough))
+            }
+
+            SmartRule::PlayedWithinDays(days) => {
+                let played_since = plays::table
+                    .filter(plays::started_at.ge(now - days * SECONDS_PER_DAY))
+                    .select(plays::song_id);
+
+                query.filter(songs::id.eq_any(played_since))
+            }
+
+            SmartRule::NotPlayedWithinDays(days) => {
+                let played_since = plays::table
+                    .filter(plays::started_at.ge(now - days * SECONDS_PER_DAY))
+                    .select(plays::song_id);
+
+                query.filter(songs::id.ne_all(played_since))
+            }
+        };
+    }
+
+    let song_ids: Vec<i32> = query.load(tx)?;
+
+    Ok(song_ids.into_iter().map(SongId).collect())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DbError {
+    #[error(transparent)]
+    Diesel(#[from] DieselError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_pool, run_migrations};
+
+    fn new_album(dir: &str, release_date: Option<&str>, discs: &[i32]) -> NewAlbum {
+        NewAlbum {
+            directory: Utf8PathBuf::from(dir),
+            title: Some("Greatest Hits".to_string()),
+            artist: Some("Artist".to_string()),
+            release_date: release_date.map(String::from),
+            original_art: None,
+            resized_art: None,
+            album_gain: None,
+            album_peak: None,
+            library_root: Some(Utf8PathBuf::from("Music")),
+            disc_numbers: discs.to_vec(),
+        }
+    }
+
+    fn insert_song(
+        tx: &mut SqliteConnection,
+        album: &Album,
+        dir: &str,
+        disc: Option<i32>,
+    ) {
+        let new_song = NewSong {
+            album_id: album.id,
+            file: Utf8Path::new(dir).join("01.flac"),
+            total_seconds: 100,
+            title: None,
+            artist: None,
+            track_number: Some(1),
+            disc_number: disc,
+            track_gain: None,
+            track_peak: None,
+            genre: None,
+            track_total: None,
+            bpm: None,
+            initial_key: None,
+        };
+        find_or_insert_song(tx, new_song).unwrap();
+    }
+
+    #[test]
+    fn each_output_device_has_its_own_equalizer() {
+        let dir = std::env::temp_dir().join(format!("clef-eq-{}", std::process::id()));
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        let pool = create_pool(&dir.join("db.sqlite")).unwrap();
+        run_migrations(&pool).unwrap();
+        let mut conn = pool.get().unwrap();
+
+        save_equalizer(&mut conn, None, &[1.0, 2.0]).unwrap();
+        save_equalizer(&mut conn, Some("Headphones"), &[3.0, 4.0]).unwrap();
+        save_equalizer(&mut conn, Some("Headphones"), &[5.0, 6.0]).unwrap();
+
+        assert_eq!(load_equalizer(&mut conn, None).unwrap(), [1.0, 2.0]);
+        assert_eq!(
+            load_equalizer(&mut conn, Some("Headphones")).unwrap(),
+            [5.0, 6.0]
+        );
+        assert!(load_equalizer(&mut conn, Some("Speakers"))
+            .unwrap()
+            .is_empty());
+
+        drop(conn);
+        drop(pool);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn plays_are_grouped_into_local_days() {
+        let dir = std::env::temp_dir().join(format!("clef-days-{}", std::process::id()));
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        let pool = create_pool(&dir.join("db.sqlite")).unwrap();
+        run_migrations(&pool).unwrap();
+        let mut conn = pool.get().unwrap();
+
+        let (album, _) =
+            find_or_insert_album(&mut conn, new_album("Music/Album", None, &[])).unwrap();
+        insert_song(&mut conn, &album, "Music/Album", Some(1));
+        insert_song(&mut conn, &album, "Music/Album/CD2", Some(2));
+        let songs = all_songs(&mut conn).unwrap();
+        let (first, second) = (songs[0].id, songs[1].id);
+
+        // noon utc, so that it's the same date in most time zones
+        const JULY_1ST: i64 = 1_688_212_800;
+        const DAY: i64 = 24 * 60 * 60;
+        for (song_id, started_at) in [
+            (second, JULY_1ST + 60),
+            (first, JULY_1ST),
+            (first, JULY_1ST + 120),
+            (second, JULY_1ST + DAY),
+        ] {
+            start_play(&mut conn, song_id, started_at, 100.0, 100.0).unwrap();
+        }
+
+        let days = listening_days(&mut conn, 10).unwrap();
+        let day = |day: &str, plays| ListeningDay { day: day.to_string(), plays };
+        assert_eq!(days, [day("2023-07-02", 1), day("2023-07-01", 3)]);
+        assert_eq!(listening_days(&mut conn, 1).unwrap().len(), 1);
+
+        assert_eq!(
+            plays_on_day(&mut conn, "2023-07-01").unwrap(),
+            [first, second, first]
+        );
+        assert!(plays_on_day(&mut conn, "2023-06-30").unwrap().is_empty());
+
+        // a year later, only the song played twice that day counts as played heavily
+        const A_YEAR_LATER: i64 = JULY_1ST + 366 * DAY;
+        assert_eq!(
+            on_this_day(&mut conn, A_YEAR_LATER, 2, 10).unwrap(),
+            [first]
+        );
+        assert_eq!(
+            on_this_day(&mut conn, A_YEAR_LATER, 1, 10).unwrap(),
+            [first, second]
+        );
+        // but not the same year
+        assert!(on_this_day(&mut conn, JULY_1ST + 600, 1, 10)
+            .unwrap()
+            .is_empty());
+
+        drop(conn);
+        drop(pool);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn only_split_discs_are_merged_into_one_album() {
+        let dir =
+            std::env::temp_dir().join(format!("clef-queries-{}", std::process::id()));
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        let pool = create_pool(&dir.join("db.sqlite")).unwrap();
+        run_migrations(&pool).unwrap();
+        let mut conn = pool.get().unwrap();
+        let mut crawl = |dir: &str, release_date: Option<&str>, discs: &[i32]| {
+            let (album, reconciled) =
+                find_or_insert_album(&mut conn, new_album(dir, release_date, discs))
+                    .unwrap();
+            for disc in discs {
+                insert_song(&mut conn, &album, dir, Some(*disc));
+            }
+            (album.id, reconciled)
+        };
+
+        let (hits, _) = crawl("Music/Greatest Hits", None, &[1]);
+
+        // the same title and artist in the same music directory, but other releases
+        let (deluxe, reconciled) = crawl("Music/Greatest Hits (Deluxe)", None, &[1, 2]);
+        assert_ne!(deluxe, hits);
+        assert_eq!(reconciled, Reconciled::Added);
+        let (remaster, _) = crawl("Music/Greatest Hits (2010 Remaster)", None, &[]);
+        assert_ne!(remaster, hits);
+        assert_ne!(remaster, deluxe);
+
+        // a disc the first album doesn't have
+        let (second_disc, _) = crawl("Music/Greatest Hits CD2", None, &[2]);
+        assert_eq!(second_disc, hits);
+
+        // discs in folders inside the album's, with the same disc tag
+        let (live, _) = crawl("Music/Greatest Hits Live/CD1", Some("1995"), &[1]);
+        assert_ne!(live, hits);
+        let (live_second_disc, _) =
+            crawl("Music/Greatest Hits Live/Disc 2", Some("1995"), &[1]);
+        assert_eq!(live_second_disc, live);
+
+        // the same discs, crawled again
+        assert_eq!(
+            crawl("Music/Greatest Hits CD2", None, &[2]),
+            (hits, Reconciled::Unchanged)
+        );
+        assert_eq!(
+            crawl("Music/Greatest Hits Live/Disc 2", Some("1995"), &[1]).0,
+            live
+        );
+
+        drop(conn);
+        drop(pool);
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -9,6 +9,126 @@ diesel::table! {
         release_date -> Nullable<Text>,
         original_art -> Nullable<Text>,
         resized_art -> Nullable<Text>,
+        deleted -> Bool,
+        album_gain -> Nullable<Double>,
+        album_peak -> Nullable<Double>,
+        library_root -> Nullable<Text>,
+        edited_title -> Nullable<Text>,
+        edited_artist -> Nullable<Text>,
+        total_seconds -> BigInt,
+        musicbrainz_release_id -> Nullable<Text>,
+        notes -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    equalizer_bands (device, band) {
+        device -> Text,
+        band -> Integer,
+        gain_db -> Double,
+    }
+}
+
+diesel::table! {
+    playlist_mirrors (file) {
+        file -> Text,
+        playlist_file -> Text,
+        mirrored -> Text,
+        listed -> Text,
+    }
+}
+
+diesel::table! {
+    playlist_songs (playlist_id, position) {
+        playlist_id -> Integer,
+        position -> Integer,
+        song_id -> Integer,
+    }
+}
+
+diesel::table! {
+    playlists (id) {
+        id -> Integer,
+        file -> Text,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    plays (id) {
+        id -> Integer,
+        song_id -> Integer,
+        started_at -> BigInt,
+        completed_at -> Nullable<BigInt>,
+        percent_played -> Double,
+        listened_seconds -> Double,
+    }
+}
+
+diesel::table! {
+    saved_queue_songs (saved_queue_id, position) {
+        saved_queue_id -> Integer,
+        position -> Integer,
+        song_id -> Integer,
+        unshuffled_position -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    saved_queues (id) {
+        id -> Integer,
+        current_index -> Integer,
+        elapsed_seconds -> Double,
+        from_crash -> Bool,
+        playing -> Bool,
+        session_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Integer,
+        name -> Text,
+        active -> Bool,
+        transition -> Text,
+    }
+}
+
+diesel::table! {
+    smart_playlist_rules (smart_playlist_id, position) {
+        smart_playlist_id -> Integer,
+        position -> Integer,
+        kind -> Text,
+        value -> Text,
+    }
+}
+
+diesel::table! {
+    smart_playlists (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    sort_names (kind, name) {
+        kind -> Text,
+        name -> Text,
+        sort_name -> Text,
+    }
+}
+
+diesel::table! {
+    song_lyrics (song_id) {
+        song_id -> Integer,
+        lyrics -> Text,
+    }
+}
+
+diesel::table! {
+    song_waveforms (song_id) {
+        song_id -> Integer,
+        peaks -> Binary,
     }
 }
 
@@ -21,12 +141,44 @@ diesel::table! {
         title -> Nullable<Text>,
         artist -> Nullable<Text>,
         track_number -> Nullable<Integer>,
+        disc_number -> Nullable<Integer>,
+        deleted -> Bool,
+        track_gain -> Nullable<Double>,
+        track_peak -> Nullable<Double>,
+        favorite -> Bool,
+        genre -> Nullable<Text>,
+        track_total -> Nullable<Integer>,
+        bpm -> Nullable<Double>,
+        initial_key -> Nullable<Text>,
+        musicbrainz_recording_id -> Nullable<Text>,
+        unplayable -> Bool,
     }
 }
 
+diesel::joinable!(playlist_songs -> playlists (playlist_id));
+diesel::joinable!(playlist_songs -> songs (song_id));
+diesel::joinable!(plays -> songs (song_id));
+diesel::joinable!(saved_queue_songs -> saved_queues (saved_queue_id));
+diesel::joinable!(saved_queue_songs -> songs (song_id));
+diesel::joinable!(song_lyrics -> songs (song_id));
+diesel::joinable!(song_waveforms -> songs (song_id));
+diesel::joinable!(smart_playlist_rules -> smart_playlists (smart_playlist_id));
 diesel::joinable!(songs -> albums (album_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     albums,
+    equalizer_bands,
+    playlist_mirrors,
+    playlist_songs,
+    playlists,
+    plays,
+    saved_queue_songs,
+    saved_queues,
+    sessions,
+    smart_playlist_rules,
+    smart_playlists,
+    song_lyrics,
+    song_waveforms,
     songs,
+    sort_names,
 );
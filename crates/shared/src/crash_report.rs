@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::panic::PanicHookInfo;
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+/// The reports' directory, in the local data directory
+const REPORTS_DIR_NAME: &str = "crash_reports";
+
+/// Holds the path of the latest report, until it's offered on the next launch
+const UNSEEN_FILE_NAME: &str = "unseen";
+
+/// How many log lines to keep for the next report
+const RECENT_LOG_LINES: usize = 200;
+
+static RECENT_LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// The latest summary from each thread that keeps one, by name
+static STATE_SUMMARIES: Mutex<BTreeMap<&'static str, String>> =
+    Mutex::new(BTreeMap::new());
+
+/// Keeps a log line for the next report, dropping the oldest
+pub fn record_log(line: String) {
+    let mut recent_log = RECENT_LOG.lock().unwrap_or_else(|e| e.into_inner());
+    if recent_log.len() == RECENT_LOG_LINES {
+        recent_log.pop_front();
+    }
+    recent_log.push_back(line);
+}
+
+/// Replaces one thread's part of the state summary in the next report
+pub fn set_state_summary(source: &'static str, summary: String) {
+    let mut summaries = STATE_SUMMARIES.lock().unwrap_or_else(|e| e.into_inner());
+    summaries.insert(source, summary);
+}
+
+/// Writes a report for a panic on any thread, before the default hook's output.
+/// NOTE this prints instead of logging, in case the panic came from the logger
+pub fn install_panic_hook(local_data_directory: Utf8PathBuf) {
+    let default_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        let report = panic_report(info);
+        match write_report(&local_data_directory, &report) {
+            Ok(path) => eprintln!("wrote crash report to {path}"),
+            Err(e) => eprintln!("failed to write crash report: {e}"),
+        }
+
+        default_hook(info);
+    }));
+}
+
+/// The latest report that hasn't been offered yet; it's only returned once
+pub fn take_unseen_report(local_data_directory: &Utf8Path) -> Option<Utf8PathBuf> {
+    let unseen_path = local_data_directory
+        .join(REPORTS_DIR_NAME)
+        .join(UNSEEN_FILE_NAME);
+
+    let contents = std::fs::read_to_string(&unseen_path).ok()?;
+    std::fs::remove_file(&unseen_path).ok()?;
+
+    let report_path = Utf8PathBuf::from(contents.trim_end());
+    report_path.exists().then_some(report_path)
+}
+
+/// Opens a report in the platform's default app for text files
+pub fn open_report(report_path: &Utf8Path) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    let mut command = std::process::Command::new("xdg-open");
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = std::process::Command::new("explorer");
+
+    command.arg(report_path).spawn()?;
+
+    Ok(())
+}
+
+fn write_report(
+    local_data_directory: &Utf8Path,
+    report: &str,
+) -> std::io::Result<Utf8PathBuf> {
+    let reports_directory = local_data_directory.join(REPORTS_DIR_NAME);
+    std::fs::create_dir_all(&reports_directory)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = reports_directory.join(format!("crash-{timestamp}.txt"));
+
+    std::fs::write(&path, report)?;
+    std::fs::write(reports_directory.join(UNSEEN_FILE_NAME), path.as_str())?;
+
+    Ok(path)
+}
+
+fn panic_report(info: &PanicHookInfo<'_>) -> String {
+    let thread = std::thread::current();
+    let thread_name = thread.name().unwrap_or("unnamed");
+
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("(no message)");
+
+    let mut report = String::from("clef crash report\n\n");
+    writeln!(report, "thread '{thread_name}' panicked: {message}").ok();
+    if let Some(location) = info.location() {
+        writeln!(report, "at {location}").ok();
+    }
+
+    match lock_for_report(&STATE_SUMMARIES) {
+        Some(summaries) => {
+            for (source, summary) in summaries.iter() {
+                writeln!(report, "\n== {source} state ==\n{summary}").ok();
+            }
+        }
+        None => {
+            writeln!(report, "\n(the state summaries were unavailable)").ok();
+        }
+    }
+
+    match lock_for_report(&RECENT_LOG) {
+        Some(recent_log) => {
+            writeln!(report, "\n== recent log ==").ok();
+            for line in recent_log.iter() {
+                writeln!(report, "{line}").ok();
+            }
+        }
+        None => {
+            writeln!(report, "\n(the recent log was unavailable)").ok();
+        }
+    }
+
+    let backtrace = std::backtrace::Backtrace::force_capture();
+    writeln!(report, "\n== backtrace ==\n{backtrace}").ok();
+
+    report
+}
+
+/// NOTE the panic may have come from a thread holding the lock;
+/// a report without that part beats a deadlock
+fn lock_for_report<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_reports_are_offered_once() {
+        let dir = std::env::temp_dir()
+            .join(format!("clef-crash-report-{}", std::process::id()));
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+
+        assert_eq!(take_unseen_report(&dir), None);
+
+        let path = write_report(&dir, "a report").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a report");
+        assert_eq!(take_unseen_report(&dir), Some(path));
+        assert_eq!(take_unseen_report(&dir), None);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -5,6 +5,7 @@
 /// NOTE This is used for looking up the window handle on windows.
 pub const WINDOW_TITLE: &str = "Clef";
 
+pub mod crash_report;
 pub mod queue;
 
 #[cfg(target_os = "windows")]
@@ -1,6 +1,10 @@
+use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::fmt::Debug;
 
+use rand::seq::SliceRandom;
+use rand::Rng;
+
 /// A generic zip list for representing a now-playing queue.
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -11,12 +15,95 @@ where
     pub previous: Vec<T>,
     pub current: T,
     pub next: VecDeque<T>,
+    /// The full queue order from before shuffling; None = not shuffled
+    pub unshuffled: Option<Vec<T>>,
 }
 
 impl<T> Queue<T>
 where
     T: Debug + Clone + PartialEq + Eq,
 {
+    pub fn new(previous: Vec<T>, current: T, next: VecDeque<T>) -> Self {
+        Self {
+            previous,
+            current,
+            next,
+            unshuffled: None,
+        }
+    }
+
+    pub fn is_shuffled(&self) -> bool {
+        self.unshuffled.is_some()
+    }
+
+    /// Shuffles the upcoming songs.
+    /// The previous songs are left alone, so that going back
+    /// replays the songs that were actually played.
+    pub fn shuffled<R: Rng + ?Sized>(self, rng: &mut R) -> Self {
+        self.shuffled_by(|_current, next| next.shuffle(rng))
+    }
+
+    /// Like shuffled, with the upcoming songs arranged by a given order,
+    /// which is passed the current song and the upcoming ones
+    pub fn shuffled_by(mut self, arrange: impl FnOnce(&T, &mut [T])) -> Self {
+        if self.unshuffled.is_none() {
+            let mut original = self.previous.clone();
+            original.push(self.current.clone());
+            original.extend(self.next.iter().cloned());
+
+            self.unshuffled = Some(original);
+        }
+
+        arrange(&self.current, self.next.make_contiguous());
+
+        self
+    }
+
+    /// Restores the upcoming songs to the order they had before shuffling.
+    /// The songs already played stay in the past, wherever they were in that order.
+    pub fn unshuffled(mut self) -> Self {
+        let Some(original) = self.unshuffled.take() else {
+            return self;
+        };
+
+        // NOTE each played copy of a duplicate removes one copy from the original order
+        let mut played: Vec<&T> = self.previous.iter().collect();
+        played.push(&self.current);
+
+        let next = original.into_iter().filter(|t| {
+            match played.iter().position(|played| *played == t) {
+                Some(index) => {
+                    played.swap_remove(index);
+                    false
+                }
+                None => true,
+            }
+        });
+        self.next = next.collect();
+
+        self
+    }
+
+    /// Adds an item to the end of the queue
+    pub fn enqueue(&mut self, item: T) {
+        if let Some(original) = &mut self.unshuffled {
+            original.push(item.clone());
+        }
+
+        self.next.push_back(item);
+    }
+
+    /// Adds an item to play after the current one
+    pub fn enqueue_next(&mut self, item: T) {
+        if let Some(original) = &mut self.unshuffled {
+            let position = original.iter().position(|t| t == &self.current);
+            let index = position.map(|p| p + 1).unwrap_or(original.len());
+            original.insert(index, item.clone());
+        }
+
+        self.next.push_front(item);
+    }
+
     pub fn try_forward(mut self) -> Result<Self, Self> {
         match self.next.pop_front() {
             Some(new_current) => {
@@ -27,6 +114,7 @@ where
                     },
                     current: new_current,
                     next: self.next,
+                    unshuffled: self.unshuffled,
                 };
 
                 Ok(new_queue)
@@ -46,6 +134,7 @@ where
                         self.next.push_front(self.current);
                         self.next
                     },
+                    unshuffled: self.unshuffled,
                 };
 
                 Ok(new_queue)
@@ -54,4 +143,224 @@ where
             None => Err(self),
         }
     }
+
+    /// Moves to the item at an index in play order (previous, current, then next)
+    pub fn try_go_to(self, index: usize) -> Result<Self, Self> {
+        if index > self.previous.len() + self.next.len() {
+            return Err(self);
+        }
+
+        let mut queue = self;
+        while queue.previous.len() > index {
+            queue = queue.try_back()?;
+        }
+        while queue.previous.len() < index {
+            queue = queue.try_forward()?;
+        }
+
+        Ok(queue)
+    }
+
+    /// Moves an upcoming item from one index in play order to another,
+    /// without changing the previous or current items
+    pub fn move_upcoming(&mut self, from: usize, to: usize) -> bool {
+        let first_upcoming = self.previous.len() + 1;
+        if from < first_upcoming || to < first_upcoming {
+            return false;
+        }
+
+        let (from, to) = (from - first_upcoming, to - first_upcoming);
+        if to >= self.next.len() {
+            return false;
+        }
+        let Some(item) = self.next.remove(from) else {
+            return false;
+        };
+        self.next.insert(to, item);
+
+        // NOTE the item keeps its place before the one it was moved in front of,
+        // so that unshuffling doesn't undo the move
+        if let Some(original) = &mut self.unshuffled {
+            let item = &self.next[to];
+            if let Some(position) = original.iter().rposition(|t| t == item) {
+                let item = original.remove(position);
+                let index = match self.next.get(to + 1) {
+                    Some(following) => original
+                        .iter()
+                        .rposition(|t| t == following)
+                        .unwrap_or(original.len()),
+                    None => original.len(),
+                };
+                original.insert(index, item);
+            }
+        }
+
+        true
+    }
+
+    /// Removes the item at an index in play order, other than the current one
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let current_index = self.previous.len();
+        let removed = match index.cmp(&current_index) {
+            Ordering::Less => self.previous.remove(index),
+            Ordering::Equal => return None,
+            Ordering::Greater => self.next.remove(index - current_index - 1)?,
+        };
+
+        // NOTE with duplicates, which copy leaves the original order doesn't matter
+        if let Some(original) = &mut self.unshuffled {
+            if let Some(position) = original.iter().position(|t| t == &removed) {
+                original.remove(position);
+            }
+        }
+
+        Some(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn numbered_queue() -> Queue<i32> {
+        Queue::new(vec![1, 2], 3, VecDeque::from([4, 5, 6, 7, 8]))
+    }
+
+    #[test]
+    fn shuffled_keeps_history_and_upcoming_songs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let queue = numbered_queue().shuffled(&mut rng);
+
+        assert_eq!(queue.previous, vec![1, 2]);
+        assert_eq!(queue.current, 3);
+
+        let mut next: Vec<i32> = queue.next.iter().copied().collect();
+        next.sort();
+        assert_eq!(next, vec![4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn back_after_shuffled_forward_returns_to_played_song() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let queue = numbered_queue().shuffled(&mut rng);
+        let played = queue.next[0];
+
+        let queue = queue.try_forward().unwrap();
+        assert_eq!(queue.current, played);
+
+        let queue = queue.try_back().unwrap();
+        assert_eq!(queue.current, 3);
+        assert_eq!(queue.next[0], played);
+    }
+
+    #[test]
+    fn enqueued_items_survive_unshuffling() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut queue = numbered_queue().shuffled(&mut rng);
+
+        queue.enqueue(9);
+        queue.enqueue_next(10);
+        assert_eq!(queue.next.front(), Some(&10));
+        assert_eq!(queue.next.back(), Some(&9));
+
+        let queue = queue.unshuffled();
+        assert_eq!(queue.next, VecDeque::from([10, 4, 5, 6, 7, 8, 9]));
+    }
+
+    #[test]
+    fn go_to_moves_in_both_directions() {
+        let queue = numbered_queue().try_go_to(5).unwrap();
+        assert_eq!(queue.previous, vec![1, 2, 3, 4, 5]);
+        assert_eq!(queue.current, 6);
+        assert_eq!(queue.next, VecDeque::from([7, 8]));
+
+        let queue = queue.try_go_to(0).unwrap();
+        assert_eq!(queue.current, 1);
+        assert_eq!(queue.next, VecDeque::from([2, 3, 4, 5, 6, 7, 8]));
+
+        let queue = queue.try_go_to(8).unwrap_err();
+        assert_eq!(queue.current, 1);
+    }
+
+    #[test]
+    fn remove_skips_the_current_item() {
+        let mut queue = numbered_queue();
+
+        assert_eq!(queue.remove(0), Some(1));
+        assert_eq!(queue.remove(1), None);
+        assert_eq!(queue.remove(2), Some(4));
+        assert_eq!(queue.remove(9), None);
+
+        assert_eq!(queue.previous, vec![2]);
+        assert_eq!(queue.current, 3);
+        assert_eq!(queue.next, VecDeque::from([5, 6, 7, 8]));
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut queue = queue.shuffled(&mut rng);
+        let removed = queue.remove(2).unwrap();
+        let queue = queue.unshuffled();
+        assert!(!queue.next.contains(&removed));
+        assert_eq!(queue.next.len(), 3);
+    }
+
+    #[test]
+    fn move_upcoming_reorders_only_the_next_items() {
+        let mut queue = numbered_queue();
+
+        assert!(queue.move_upcoming(3, 6));
+        assert_eq!(queue.next, VecDeque::from([5, 6, 7, 4, 8]));
+        assert!(queue.move_upcoming(7, 3));
+        assert_eq!(queue.next, VecDeque::from([8, 5, 6, 7, 4]));
+
+        assert!(!queue.move_upcoming(2, 4));
+        assert!(!queue.move_upcoming(4, 1));
+        assert!(!queue.move_upcoming(4, 8));
+        assert_eq!(queue.previous, vec![1, 2]);
+        assert_eq!(queue.current, 3);
+        assert_eq!(queue.next, VecDeque::from([8, 5, 6, 7, 4]));
+    }
+
+    #[test]
+    fn unshuffled_restores_order_after_current() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let queue = numbered_queue().shuffled(&mut rng);
+        let queue = queue.try_forward().unwrap();
+        let current = queue.current;
+
+        let queue = queue.unshuffled();
+
+        assert!(!queue.is_shuffled());
+        let expected: VecDeque<i32> = (4..=8).filter(|n| *n != current).collect();
+        assert_eq!(queue.next, expected);
+    }
+
+    #[test]
+    fn unshuffled_keeps_played_duplicates_in_the_past() {
+        let queue = Queue::new(vec![], 1, VecDeque::from([2, 1, 3]));
+        let queue = queue.shuffled_by(|_current, next| next.reverse());
+        assert_eq!(queue.next, VecDeque::from([3, 1, 2]));
+
+        let queue = queue.try_forward().unwrap().try_forward().unwrap();
+        assert_eq!(queue.current, 1);
+
+        let queue = queue.unshuffled();
+        assert_eq!(queue.previous, vec![1, 3]);
+        assert_eq!(queue.next, VecDeque::from([2]));
+    }
+
+    #[test]
+    fn moves_while_shuffled_survive_unshuffling() {
+        let mut queue = numbered_queue().shuffled_by(|_current, next| next.reverse());
+        assert_eq!(queue.next, VecDeque::from([8, 7, 6, 5, 4]));
+
+        // 4 to the front of the upcoming songs, before 8
+        assert!(queue.move_upcoming(7, 3));
+        assert_eq!(queue.next, VecDeque::from([4, 8, 7, 6, 5]));
+
+        let queue = queue.unshuffled();
+        assert_eq!(queue.next, VecDeque::from([5, 6, 7, 4, 8]));
+    }
 }
@@ -1,43 +1,109 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use flume::{Receiver, Sender};
 use iced::keyboard::KeyCode;
+use iced::mouse::{self, Event as MouseEvent};
+use iced::widget::scrollable::RelativeOffset;
 use iced::widget::{
-    button, column, container, horizontal_space, row, scrollable, slider, text, Column,
-    Container, Image, Row, Space,
+    button, canvas, checkbox, column, container, horizontal_space, image, pick_list,
+    progress_bar, row, scrollable, slider, text, text_input, vertical_slider, Button,
+    Column, Container, Image, Row, Space,
 };
+use iced::window::Event as WindowEvent;
 use iced::{
-    alignment, executor, Alignment, Application, Command, ContentFit, Element, Event,
-    Length, Subscription, Theme,
+    alignment, executor, theme, Alignment, Application, Color, Command, ContentFit,
+    Element, Event, Length, Subscription, Theme,
 };
 use iced_native::keyboard::Event as KeyboardEvent;
-use log::error;
-
-use clef_audio::player::{AudioAction, AudioMessage, PlayerDisplay, ProgressTimes};
+use log::{error, info, warn};
+
+use clef_audio::dsp::equalizer::{EqCurve, EqPreset, BAND_FREQUENCIES, MAX_BAND_GAIN};
+use clef_audio::dsp::sample_tap::SampleRing;
+use clef_audio::dsp::transition::TransitionKind;
+use clef_audio::player::{
+    output_device_names, AbLoop, AudioAction, AudioMessage, OutputTelemetry,
+    PlayerDisplay, ProgressTimes, QueueDisplay, SeekOffset,
+};
+use clef_audio::replay_gain::{ReplayGainMode, ReplayGainSettings};
+use clef_audio::resample_quality::ResampleQuality;
+use clef_audio::shuffle_order::ShuffleOrder;
+use clef_db::integrity::{self, DatabaseDamage};
 use clef_db::queries::*;
 use clef_db::SqlitePool;
 
+mod art_fetcher;
 mod audio_subscription;
+mod context_menu;
+mod conversions;
 pub(crate) mod crawler;
 mod custom_style;
 mod effect;
+mod export;
+mod folders;
 mod hoverable;
 mod icons;
+pub(crate) mod instance;
+mod keymap;
+mod layered;
+mod lyrics;
+mod mqtt;
 mod music_cache;
+mod musicbrainz;
+mod notification;
 mod old_unfold;
+pub(crate) mod playlist_file;
+mod playlist_sync;
+mod power;
 mod resizer;
 mod rgba;
-
+pub(crate) mod settings;
+mod smart_playlist;
+mod state_dump;
+mod stats;
+mod tray;
+mod visualizer;
+mod waveform;
+mod webhook;
+
+use art_fetcher::ArtLookup;
 use audio_subscription::audio_subscription;
+use context_menu::ContextMenu;
+use conversions::ConversionStats;
 use crawler::*;
-use custom_style::no_background;
+use custom_style::{
+    accent_icon, app_theme, hover_tint, menu_panel, no_background, selected_tint,
+    solid_color, Accent, CaptionColors, ThemeColor,
+};
 use effect::Effect;
+use export::{export_subscription, ExportFormat, ExportMessage, ExportRequest};
+use folders::{parent_folder, read_folder, FolderListing};
 use hoverable::*;
+use instance::{instance_subscription, Handoff, SongLink};
+use keymap::{KeyAction, Keymap};
+use layered::Layered;
+use lyrics::Lyrics;
+use mqtt::MqttRequest;
 use music_cache::*;
+use notification::{Notification, Notifications};
+use playlist_file::{PlaylistExport, PlaylistSongs};
+use power::*;
 use resizer::*;
 use rgba::*;
+use settings::SettingsFile;
+use smart_playlist::*;
+use state_dump::*;
+use stats::{
+    format_listening, ListeningClockMap, ListeningStats, LoadedListening, StatsPeriod,
+};
+use tray::{tray_subscription, Tray, TrayAction, TrayState};
+use visualizer::{Visualizer, VisualizerStyle};
+use waveform::WaveformSeekBar;
+use webhook::{unix_timestamp, PlaybackEvent, WebhookRequest, WebhookSong};
 
+use clef_shared::crash_report;
 use clef_shared::WINDOW_TITLE;
 
 #[derive(Debug)]
@@ -48,7 +114,20 @@ pub struct App {
     to_audio: Sender<AudioAction>,
     to_resizer: Sender<ResizeRequest>,
     resizer_inbox: Receiver<ResizeRequest>,
+    to_art_fetcher: Sender<ArtLookup>,
+    to_webhook: Sender<WebhookRequest>,
+    to_mqtt: Sender<MqttRequest>,
+    /// Requests from later launches, while this is the running instance
+    handoffs: Receiver<Handoff>,
+    /// None = the desktop doesn't show status icons
+    tray: Option<Tray>,
+    tray_actions: Receiver<TrayAction>,
+    /// The last state sent to the tray
+    tray_state: TrayState,
     ui: Ui,
+    /// Used for logging startup timings
+    started_at: Instant,
+    logged_first_crawl: bool,
 }
 
 #[derive(Debug)]
@@ -57,7 +136,162 @@ struct Ui {
     current_song: Option<CurrentSong>,
     progress: Option<ProgressDisplay>,
     hovered_song_id: Option<SongId>,
+    /// The row whose right-click menu is open
+    context_menu: Option<MenuTarget>,
     music_cache: MusicCache,
+    /// NOTE this lasts for the session, across queues
+    shuffle: bool,
+    /// A queue saved when the player failed, during this launch or the last one
+    crashed_queue: Option<SavedQueue>,
+    /// A report written when the last launch panicked, until it's opened or dismissed
+    crash_report: Option<Utf8PathBuf>,
+    /// Problems found in the db at launch, until the backup is restored or they're dismissed
+    database_damage: Option<DatabaseDamage>,
+    /// A song that was playing when the app closed last time,
+    /// restored paused, with a prompt to resume it
+    interrupted_song: Option<InterruptedSong>,
+    /// Played once the saved library is loaded, instead of restoring the saved queue
+    play_on_launch: Vec<SongId>,
+    /// Played once the saved library is loaded, with the rest of its directory,
+    /// even if it's outside the library
+    open_on_launch: Option<Utf8PathBuf>,
+    /// Played once the saved library is loaded, from a moment in the song
+    link_on_launch: Option<SongLink>,
+    /// The devices available to choose from; empty = only the default
+    output_devices: Vec<String>,
+    output_device: OutputDevice,
+    /// Narrows the album list to one genre
+    genre_filter: GenreFilter,
+    /// The latest diagnostics from the audio output; None = no output open
+    output_telemetry: Option<OutputTelemetry>,
+    show_output_telemetry: bool,
+    /// Shows only the playing song, its controls, and the progress slider
+    compact: bool,
+    /// The window's size before switching to the mini player, to go back to;
+    /// None = not resized since launch
+    full_window_size: Option<(u32, u32)>,
+    /// How the songs played since launch were converted, for the settings view
+    conversions: ConversionStats,
+    equalizer: EqCurve,
+    show_equalizer: bool,
+    /// The latest samples from the player, for the visualizer
+    sample_ring: SampleRing,
+    library_view: LibraryView,
+    /// The player's whole queue, for the queue view; None = stopped
+    queue: Option<QueueDisplay>,
+    /// The queue row under the mouse, by position in play order
+    hovered_queue_index: Option<usize>,
+    /// An upcoming song being dragged to a new position; None = not dragging
+    dragged_queue_index: Option<usize>,
+    /// How far down the library is scrolled, from 0 to 1
+    library_scroll: f32,
+    play_history: PlayHistory,
+    stats: ListeningStats,
+    /// The song the lyrics were last loaded for, while the lyrics view is open
+    lyrics_song: Option<SongId>,
+    /// None = the song has none, or they're still loading
+    lyrics: Option<Lyrics>,
+    /// The song the waveform was last loaded for
+    waveform_song: Option<SongId>,
+    /// Peaks across the current song, for the seek bar; None = still computing,
+    /// or it couldn't be decoded
+    waveform: Option<Vec<u8>>,
+    /// The open folder in the folders view; None = the list of music directories
+    folder: Option<FolderListing>,
+    /// Playlist files from the music directories, as of the last scan
+    playlists: Vec<Playlist>,
+    smart_playlists: Vec<SmartPlaylist>,
+    smart_playlist_draft: SmartPlaylistDraft,
+    /// Named sessions; the default one isn't included
+    sessions: Vec<Session>,
+    session_name_draft: String,
+    /// A short-lived notice about something the app did on its own
+    toast: Option<Toast>,
+    /// Errors and warnings from the background threads, until they're dismissed
+    notifications: Notifications,
+    /// NOTE the mode lasts for the session, like shuffle
+    power: PowerState,
+    /// The settings as shown in the settings view, including any launch overrides
+    settings: SettingsFile,
+    music_directory_draft: String,
+    sort_name_draft: SortNameDraft,
+    /// What the running crawl has changed so far
+    scan_changes: ScanChanges,
+    /// What the last crawl changed, until it's dismissed; None = nothing changed
+    scan_summary: Option<ScanChanges>,
+    show_scan_details: bool,
+    /// The default key bindings, with any from the settings file
+    keymap: Keymap,
+    /// A linear gain for the output; NOTE this lasts for the session
+    volume: f32,
+    /// Silences the output, keeping the volume to restore
+    muted: bool,
+    webhook_url_draft: String,
+    /// The result of the last test from the settings; None = none sent, or waiting
+    webhook_test: Option<Result<(), String>>,
+    /// Hidden to the tray icon; NOTE only when there is one to show it again
+    window_hidden: bool,
+    tray_available: bool,
+    /// The album title and artist being edited on an album's page
+    album_rename_draft: Option<AlbumRenameDraft>,
+    /// The notes being edited on an album's page
+    album_notes_draft: Option<AlbumNotesDraft>,
+    /// Where and how to export an album, as entered on its page
+    album_export_draft: Option<AlbumExportDraft>,
+    /// The running export; NOTE only one runs at a time
+    album_export: Option<AlbumExport>,
+    /// The last destination exported to, for the next export this session
+    export_destination: String,
+    /// The album being filled in from MusicBrainz; NOTE only one at a time
+    musicbrainz_filling: Option<AlbumId>,
+}
+
+/// A sort name override being entered in the settings view
+#[derive(Debug)]
+struct SortNameDraft {
+    kind: SortKind,
+    name: String,
+    sort_name: String,
+}
+
+impl Default for SortNameDraft {
+    fn default() -> Self {
+        Self {
+            kind: SortKind::Artist,
+            name: String::new(),
+            sort_name: String::new(),
+        }
+    }
+}
+
+/// NOTE blank fields go back to the tags
+#[derive(Debug)]
+struct AlbumRenameDraft {
+    album_id: AlbumId,
+    title: String,
+    artist: String,
+}
+
+/// NOTE blank notes are cleared
+#[derive(Debug)]
+struct AlbumNotesDraft {
+    album_id: AlbumId,
+    notes: String,
+}
+
+#[derive(Debug)]
+struct AlbumExportDraft {
+    album_id: AlbumId,
+    destination: String,
+    format: ExportFormat,
+}
+
+#[derive(Debug)]
+struct AlbumExport {
+    album_id: AlbumId,
+    request: ExportRequest,
+    /// Files written (0) of the total (1)
+    progress: (usize, usize),
 }
 
 impl Ui {
@@ -66,16 +300,235 @@ impl Ui {
             current_song: None,
             progress: None,
             hovered_song_id: None,
+            context_menu: None,
             crawling_music: true,
             music_cache: MusicCache::new(),
+            shuffle: false,
+            crashed_queue: None,
+            crash_report: None,
+            database_damage: None,
+            interrupted_song: None,
+            play_on_launch: Vec::new(),
+            open_on_launch: None,
+            link_on_launch: None,
+            output_devices: Vec::new(),
+            output_device: OutputDevice::Default,
+            genre_filter: GenreFilter::All,
+            output_telemetry: None,
+            show_output_telemetry: false,
+            compact: false,
+            full_window_size: None,
+            conversions: ConversionStats::default(),
+            equalizer: EqCurve::default(),
+            show_equalizer: false,
+            sample_ring: SampleRing::default(),
+            library_view: LibraryView::Albums,
+            queue: None,
+            hovered_queue_index: None,
+            dragged_queue_index: None,
+            library_scroll: 0.0,
+            play_history: PlayHistory::default(),
+            stats: ListeningStats::default(),
+            lyrics_song: None,
+            lyrics: None,
+            waveform_song: None,
+            waveform: None,
+            folder: None,
+            playlists: Vec::new(),
+            smart_playlists: Vec::new(),
+            smart_playlist_draft: SmartPlaylistDraft::default(),
+            sessions: Vec::new(),
+            session_name_draft: String::new(),
+            toast: None,
+            notifications: Notifications::default(),
+            power: PowerState::default(),
+            settings: SettingsFile::default(),
+            music_directory_draft: String::new(),
+            sort_name_draft: SortNameDraft::default(),
+            scan_changes: ScanChanges::default(),
+            scan_summary: None,
+            show_scan_details: false,
+            keymap: Keymap::default(),
+            volume: 1.0,
+            muted: false,
+            webhook_url_draft: String::new(),
+            webhook_test: None,
+            window_hidden: false,
+            tray_available: false,
+            album_rename_draft: None,
+            album_notes_draft: None,
+            album_export_draft: None,
+            musicbrainz_filling: None,
+            album_export: None,
+            export_destination: String::new(),
         }
     }
+
+    /// A damaged db isn't crawled into until the user restores it or carries on
+    fn set_database_damage(&mut self, damage: Option<DatabaseDamage>) {
+        self.crawling_music = damage.is_none();
+        self.database_damage = damage;
+    }
+}
+
+/// What fills the main area, above the bottom row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibraryView {
+    Albums,
+    /// The songs in the queue, in play order
+    Queue,
+    History,
+    /// Listening totals and favorites over a recent span
+    Stats,
+    /// The music directories as folders on disk, regardless of tags
+    Folders,
+    SmartPlaylists,
+    Sessions,
+    Settings,
+    /// The current song's lyrics, following along when they're synced
+    Lyrics,
+    /// A page for one album, opened from its cover
+    Album(AlbumId),
+}
+
+/// A row with a right-click menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuTarget {
+    Song(SongId),
+    Album(AlbumId),
+}
+
+/// Songs from the recorded plays, for the history view
+#[derive(Debug, Default)]
+struct PlayHistory {
+    /// Latest first
+    recently_played: Vec<SongId>,
+    /// Song id and play count, most first
+    most_played: Vec<(SongId, i64)>,
+    /// Latest first, each with a button to rebuild its listening session
+    days: Vec<ListeningDay>,
+}
+
+/// The number of songs in each history list
+const PLAY_HISTORY_LENGTH: usize = 50;
+/// The number of days with plays in the history view
+const LISTENING_DAYS_LENGTH: i64 = 30;
+/// How many times a song was played on a date, over previous years, to be on this day
+const ON_THIS_DAY_MIN_PLAYS: i64 = 2;
+/// The number of songs in the on this day queue
+const ON_THIS_DAY_LENGTH: i64 = 50;
+
+impl PlayHistory {
+    fn new(mut counts: Vec<PlayCount>, days: Vec<ListeningDay>) -> Self {
+        counts.sort_by_key(|count| std::cmp::Reverse(count.last_played_at));
+        let recently_played = counts
+            .iter()
+            .take(PLAY_HISTORY_LENGTH)
+            .map(|count| count.song_id)
+            .collect();
+
+        counts.sort_by_key(|count| std::cmp::Reverse(count.plays));
+        let most_played = counts
+            .iter()
+            .take(PLAY_HISTORY_LENGTH)
+            .map(|count| (count.song_id, count.plays))
+            .collect();
+
+        Self { recently_played, most_played, days }
+    }
+}
+
+#[derive(Debug)]
+struct InterruptedSong {
+    title: String,
+    elapsed_seconds: f64,
+}
+
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct Toast {
+    message: String,
+    shown_at: Instant,
+}
+
+impl Toast {
+    fn new(message: String) -> Self {
+        Self { message, shown_at: Instant::now() }
+    }
+
+    // NOTE there's no timer subscription, so this is checked on audio updates
+    fn expired(&self) -> bool {
+        self.shown_at.elapsed() >= TOAST_DURATION
+    }
 }
 
 impl App {
     fn new(flags: Flags) -> Self {
         let (to_resizer_tx, to_resizer_rx) = flume::unbounded::<ResizeRequest>();
 
+        // NOTE the player starts with the default order, before any queue is shuffled
+        let shuffle_order = flags.config.settings.shuffle_order.unwrap_or_default();
+        flags
+            .to_audio
+            .send(AudioAction::SetShuffleOrder(shuffle_order))
+            .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+        let resample_quality = flags.config.settings.resample_quality;
+        flags
+            .to_audio
+            .send(AudioAction::SetResampleQuality(resample_quality))
+            .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+
+        let mut ui = Ui::new();
+        ui.music_cache.set_art_budget(flags.config.art_cache_bytes);
+        ui.play_on_launch = flags.play_on_launch;
+        ui.open_on_launch = flags.open_on_launch;
+        ui.link_on_launch = flags.link_on_launch;
+        ui.crash_report = flags.crash_report;
+        ui.set_database_damage(flags.database_damage);
+        let (keymap, key_errors) = Keymap::with_overrides(&flags.config.settings.keys);
+        for key_error in key_errors {
+            warn!("ignoring key binding in settings: {key_error}");
+        }
+        ui.keymap = keymap;
+        ui.webhook_url_draft = flags
+            .config
+            .settings
+            .webhook_url
+            .clone()
+            .unwrap_or_default();
+        ui.settings = SettingsFile {
+            music_directories: flags.config.audio_directories.clone(),
+            replaygain: Some(flags.config.replay_gain.mode),
+            replaygain_preamp: Some(flags.config.replay_gain.preamp_db),
+            ..flags.config.settings.clone()
+        };
+
+        let (to_art_fetcher_tx, to_art_fetcher_rx) = flume::unbounded::<ArtLookup>();
+        art_fetcher::spawn_art_fetcher(
+            to_art_fetcher_rx,
+            to_resizer_tx.clone(),
+            flags.config.resized_images_directory.clone(),
+        )
+        .unwrap_or_else(|e| error!("failed to start art fetcher thread: {e}"));
+        let (to_webhook_tx, to_webhook_rx) = flume::unbounded::<WebhookRequest>();
+        webhook::spawn_webhook_sender(to_webhook_rx)
+            .unwrap_or_else(|e| error!("failed to start webhook thread: {e}"));
+        let (to_mqtt_tx, to_mqtt_rx) = flume::unbounded::<MqttRequest>();
+        mqtt::spawn_mqtt_publisher(to_mqtt_rx)
+            .unwrap_or_else(|e| error!("failed to start mqtt thread: {e}"));
+
+        let (to_ui_tx, tray_actions) = flume::unbounded::<TrayAction>();
+        let tray = Tray::spawn(to_ui_tx)
+            .map_err(|e| warn!("failed to show tray icon: {e}"))
+            .ok();
+        ui.tray_available = tray.is_some();
+
+        flags
+            .to_audio
+            .send(AudioAction::SetSampleTap(sample_tap(&ui)))
+            .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+
         Self {
             config: Arc::new(flags.config),
             inbox: flags.inbox,
@@ -83,7 +536,16 @@ impl App {
             db: flags.db_pool,
             to_resizer: to_resizer_tx,
             resizer_inbox: to_resizer_rx,
-            ui: Ui::new(),
+            to_art_fetcher: to_art_fetcher_tx,
+            to_webhook: to_webhook_tx,
+            to_mqtt: to_mqtt_tx,
+            handoffs: flags.handoffs,
+            tray,
+            tray_actions,
+            tray_state: TrayState::default(),
+            ui,
+            started_at: flags.started_at,
+            logged_first_crawl: false,
         }
     }
 
@@ -110,6 +572,479 @@ impl App {
             }
 
             Effect::CloseWindow => iced::window::close(),
+
+            Effect::HideWindow => iced::window::change_mode(iced::window::Mode::Hidden),
+
+            Effect::CopyToClipboard(contents) => iced::clipboard::write(contents),
+
+            Effect::Notify(notification) => {
+                self.ui.notifications.push(notification);
+                Command::none()
+            }
+
+            Effect::ShowInFileManager(directory) => {
+                if let Err(e) = open_in_file_manager(&directory) {
+                    error!("failed to open {directory}: {e}");
+                }
+
+                Command::none()
+            }
+
+            Effect::ResizeWindow(width, height) => iced::window::resize(width, height),
+
+            Effect::FocusWindow => Command::batch([
+                iced::window::change_mode(iced::window::Mode::Windowed),
+                iced::window::minimize(false),
+                iced::window::gain_focus(),
+            ]),
+
+            Effect::SaveEqualizer(device_name, curve) => {
+                self.to_audio
+                    .send(AudioAction::SetEqualizer(curve))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+
+                let saved =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| {
+                                save_equalizer(
+                                    tx,
+                                    device_name.as_deref(),
+                                    &curve.to_saved(),
+                                )
+                            })
+                            .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = saved {
+                    error!("failed to save equalizer: {e}");
+                }
+
+                Command::none()
+            }
+
+            Effect::SwitchOutputDevice(device_name) => {
+                self.to_audio
+                    .send(AudioAction::SetOutputDevice(device_name.clone()))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+
+                Command::perform(
+                    load_equalizer(self.db.clone(), device_name.clone()),
+                    move |curve| Message::LoadedEqualizer(device_name, curve),
+                )
+            }
+
+            Effect::SaveFavorite(song_id, favorite) => {
+                let saved =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| {
+                                set_song_favorite(tx, song_id, favorite)
+                            })
+                            .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = saved {
+                    error!("failed to save favorite: {e}");
+                }
+
+                Command::none()
+            }
+
+            Effect::LoadCrashedQueue if self.to_audio.is_disconnected() => {
+                error!("audio thread is gone, closing");
+                iced::window::close()
+            }
+
+            Effect::LoadCrashedQueue => {
+                Command::perform(take_queue(self.db.clone()), Message::LoadedCrashedQueue)
+            }
+
+            Effect::LoadPlayHistory => {
+                Command::perform(load_play_history(self.db.clone()), |(counts, days)| {
+                    Message::LoadedPlayHistory(counts, days)
+                })
+            }
+
+            Effect::RebuildSession(day) => Command::perform(
+                load_plays_on_day(self.db.clone(), day),
+                Message::LoadedSessionPlays,
+            ),
+
+            Effect::LoadStats(period) => Command::perform(
+                load_stats(self.db.clone(), period),
+                Message::LoadedStats,
+            ),
+
+            Effect::LoadPlaylists => Command::batch([
+                Command::perform(
+                    load_playlists(self.db.clone()),
+                    Message::LoadedPlaylists,
+                ),
+                Command::perform(
+                    load_smart_playlists(self.db.clone()),
+                    Message::LoadedSmartPlaylists,
+                ),
+            ]),
+
+            Effect::ExportPlaylist(export) => Command::perform(
+                export_playlist(self.db.clone(), export),
+                Message::ExportedPlaylist,
+            ),
+
+            Effect::SaveSmartPlaylist(name, rules) => {
+                let saved =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| {
+                                create_smart_playlist(tx, &name, &rules)
+                            })
+                            .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = saved {
+                    error!("failed to save smart playlist: {e}");
+                }
+
+                Command::perform(
+                    load_smart_playlists(self.db.clone()),
+                    Message::LoadedSmartPlaylists,
+                )
+            }
+
+            Effect::DeleteSmartPlaylist(playlist_id) => {
+                let deleted =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| {
+                                delete_smart_playlist(tx, playlist_id)
+                            })
+                            .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = deleted {
+                    error!("failed to delete smart playlist: {e}");
+                }
+
+                Command::perform(
+                    load_smart_playlists(self.db.clone()),
+                    Message::LoadedSmartPlaylists,
+                )
+            }
+
+            Effect::LoadSmartPlaylistSongs(rules) => Command::perform(
+                load_smart_playlist_songs(self.db.clone(), rules),
+                Message::LoadedSmartPlaylistSongs,
+            ),
+
+            Effect::LoadOnThisDay => Command::perform(
+                load_on_this_day(self.db.clone()),
+                Message::LoadedOnThisDay,
+            ),
+
+            Effect::LoadSessions => {
+                Command::perform(load_sessions(self.db.clone()), Message::LoadedSessions)
+            }
+
+            Effect::FillFromMusicBrainz(album) => Command::perform(
+                musicbrainz::fill_album(self.db.clone(), *album),
+                Message::FilledFromMusicBrainz,
+            ),
+
+            Effect::LoadLyrics(song_id) => {
+                Command::perform(load_lyrics(self.db.clone(), song_id), move |lyrics| {
+                    Message::LoadedLyrics(song_id, lyrics)
+                })
+            }
+
+            Effect::LoadWaveform(song_id, path) => Command::perform(
+                load_waveform(self.db.clone(), song_id, path),
+                move |peaks| Message::LoadedWaveform(song_id, peaks),
+            ),
+
+            Effect::CheckPowerSource => {
+                Command::perform(detect_power_source(), Message::CheckedPowerSource)
+            }
+
+            Effect::CreateSession(name) => {
+                let created =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| create_session(tx, &name))
+                                .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = created {
+                    error!("failed to create session: {e}");
+                }
+
+                Command::perform(load_sessions(self.db.clone()), Message::LoadedSessions)
+            }
+
+            Effect::DeleteSession(session_id) => {
+                let deleted =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| {
+                                delete_session(tx, session_id)
+                            })
+                            .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = deleted {
+                    error!("failed to delete session: {e}");
+                }
+
+                Command::perform(load_sessions(self.db.clone()), Message::LoadedSessions)
+            }
+
+            Effect::SwitchSession(session_id, transition, leaving_position) => {
+                self.to_audio
+                    .send(AudioAction::SetTransition(transition))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+
+                Command::perform(
+                    switch_to_session(self.db.clone(), session_id, leaving_position),
+                    Message::SwitchedSession,
+                )
+            }
+
+            Effect::SaveSessionTransition(session_id, transition, active) => {
+                if active {
+                    self.to_audio
+                        .send(AudioAction::SetTransition(transition))
+                        .unwrap_or_else(|e| {
+                            error!("failed to send to audio thread: {e}")
+                        });
+                }
+
+                let saved =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| {
+                                set_session_transition(
+                                    tx,
+                                    session_id,
+                                    transition.to_saved(),
+                                )
+                            })
+                            .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = saved {
+                    error!("failed to save session transition: {e}");
+                }
+
+                Command::none()
+            }
+
+            Effect::SaveSortName(sort_name) => {
+                let saved =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| {
+                                save_sort_name(tx, &sort_name)
+                            })
+                            .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = saved {
+                    error!("failed to save sort name: {e}");
+                }
+
+                Command::none()
+            }
+
+            Effect::DeleteSortName(kind, name) => {
+                let deleted =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| {
+                                delete_sort_name(tx, kind, &name)
+                            })
+                            .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = deleted {
+                    error!("failed to delete sort name: {e}");
+                }
+
+                Command::none()
+            }
+
+            Effect::RenameAlbum(album_id, renamed) => {
+                let saved =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            conn.immediate_transaction(|tx| {
+                                rename_album(tx, album_id, &renamed)
+                            })
+                            .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = saved {
+                    error!("failed to save album rename: {e}");
+                }
+
+                Command::none()
+            }
+
+            Effect::SaveAlbumNotes(album_id, notes) => {
+                let saved =
+                    self.db
+                        .get()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|mut conn| {
+                            save_album_notes(&mut conn, album_id, notes.as_deref())
+                                .map_err(anyhow::Error::from)
+                        });
+                if let Err(e) = saved {
+                    error!("failed to save album notes: {e}");
+                }
+
+                Command::none()
+            }
+
+            Effect::TestWebhook(url) => {
+                Command::perform(webhook::send_test(url), Message::WebhookTested)
+            }
+
+            Effect::FetchArt(lookup) => {
+                self.to_art_fetcher.send(lookup).unwrap_or_else(|e| {
+                    error!("failed to send to art fetcher thread: {e}")
+                });
+
+                Command::none()
+            }
+
+            Effect::OpenFile(file) => Command::perform(
+                read_unsaved_album(file.clone(), self.config.extensions.clone()),
+                move |read| Message::OpenedFile(file, read),
+            ),
+
+            Effect::ReadFolder(path) => Command::perform(
+                read_folder(path, self.config.extensions.clone()),
+                Message::ReadFolder,
+            ),
+
+            Effect::ReadDropped(path, import_to) => Command::perform(
+                read_dropped(path.clone(), import_to, self.config.extensions.clone()),
+                move |read| Message::ReadDropped(path, read),
+            ),
+
+            Effect::SetLowPower(low_power) => {
+                self.to_audio
+                    .send(AudioAction::SetLowPower(low_power))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+                self.to_audio
+                    .send(AudioAction::SetSampleTap(sample_tap(&self.ui)))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+
+                Command::none()
+            }
+
+            Effect::SaveSettings(settings) => {
+                self.to_audio
+                    .send(AudioAction::SetReplayGain(settings.replay_gain()))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+                let shuffle_order = settings.shuffle_order.unwrap_or_default();
+                self.to_audio
+                    .send(AudioAction::SetShuffleOrder(shuffle_order))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+                self.to_audio
+                    .send(AudioAction::SetResampleQuality(settings.resample_quality))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+                self.to_audio
+                    .send(AudioAction::SetSampleTap(sample_tap(&self.ui)))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+
+                if let Err(e) = settings.save(&self.config.settings_path) {
+                    error!("failed to save settings: {e:#}");
+                }
+
+                // NOTE the crawler picks up the new directories when it next starts
+                let mut config = Config::clone(&self.config);
+                config.audio_directories = settings.music_directories.clone();
+                config.replay_gain = settings.replay_gain();
+                config.settings = *settings;
+                self.config = Arc::new(config);
+
+                Command::none()
+            }
+
+            Effect::RestoreDatabase => {
+                match integrity::request_restore(&self.config.db_path) {
+                    Ok(()) => iced::window::close(),
+                    Err(e) => {
+                        error!("failed to request a db restore: {e}");
+                        Command::none()
+                    }
+                }
+            }
+
+            Effect::ContinueWithDamagedDatabase => {
+                if let Err(e) = clef_db::run_migrations(&self.db) {
+                    error!("failed to migrate the damaged db: {e}");
+                }
+
+                self.to_audio
+                    .send(AudioAction::SetDbWrites(true))
+                    .unwrap_or_else(|e| error!("failed to send to audio thread: {e}"));
+
+                Command::perform(
+                    load_saved_library(self.db.clone()),
+                    Message::LoadedSavedLibrary,
+                )
+            }
+
+            Effect::OpenCrashReport(report_path) => {
+                if let Err(e) = crash_report::open_report(&report_path) {
+                    error!("failed to open {report_path}: {e}");
+                }
+
+                Command::none()
+            }
+
+            Effect::WriteStateDump(dump) => {
+                match write_state_dump(&self.config.local_data_directory, &dump) {
+                    Ok(path) => info!("wrote state dump to {path}"),
+                    Err(e) => error!("failed to write state dump: {e:#}"),
+                }
+
+                Command::none()
+            }
+        }
+    }
+
+    fn log_startup_timing(&mut self, message: &Message) {
+        match message {
+            Message::LoadedSavedLibrary(saved) => {
+                let elapsed = self.started_at.elapsed();
+                info!(
+                    "loaded {} saved albums after {elapsed:?}",
+                    saved.albums.len()
+                );
+            }
+
+            Message::FromCrawler(_) if !self.logged_first_crawl => {
+                self.logged_first_crawl = true;
+                info!(
+                    "first crawler message after {:?}",
+                    self.started_at.elapsed()
+                );
+            }
+
+            _ => {}
         }
     }
 }
@@ -123,6 +1058,8 @@ struct CurrentSong {
     artist: Option<String>,
     playing: bool,
     total_seconds: i64,
+    /// A section the player repeats; None = no loop points are marked
+    ab_loop: Option<AbLoop>,
 }
 
 impl CurrentSong {
@@ -135,6 +1072,7 @@ impl CurrentSong {
             artist: song.artist.clone(),
             total_seconds: song.total_seconds,
             playing,
+            ab_loop: None,
         }
     }
 }
@@ -145,6 +1083,46 @@ pub(crate) enum ProgressDisplay {
     FromAudio(ProgressTimes),
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputDevice {
+    Default,
+    Named(String),
+}
+
+impl OutputDevice {
+    /// None for the default output
+    fn name(&self) -> Option<String> {
+        match self {
+            OutputDevice::Default => None,
+            OutputDevice::Named(name) => Some(name.clone()),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputDevice::Default => write!(f, "Default Output"),
+            OutputDevice::Named(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenreFilter {
+    All,
+    Genre(String),
+}
+
+impl std::fmt::Display for GenreFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenreFilter::All => write!(f, "All Genres"),
+            GenreFilter::Genre(genre) => write!(f, "{genre}"),
+        }
+    }
+}
+
 impl ProgressDisplay {
     fn display_proportion(&self) -> f32 {
         match self {
@@ -158,12 +1136,20 @@ impl ProgressDisplay {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Config {
     pub local_data_directory: Utf8PathBuf,
-    pub audio_directory: Utf8PathBuf,
+    /// The music directories to crawl; each holds one level of album directories
+    pub audio_directories: Vec<Utf8PathBuf>,
     pub db_path: Utf8PathBuf,
     pub resized_images_directory: Utf8PathBuf,
+    pub replay_gain: ReplayGainSettings,
+    /// How much decoded album art to keep in memory
+    pub art_cache_bytes: usize,
+    pub extensions: LibraryExtensions,
+    pub settings_path: Utf8PathBuf,
+    /// The settings file as it was read, before the environment overrides
+    pub settings: SettingsFile,
 }
 
 #[derive(Debug)]
@@ -172,25 +1158,175 @@ pub struct Flags {
     pub to_audio: Sender<AudioAction>,
     pub db_pool: SqlitePool,
     pub config: Config,
+    pub started_at: Instant,
+    /// Songs to play in place of the saved queue, ie from 'clef play'
+    pub play_on_launch: Vec<SongId>,
+    /// A song file to play in place of the saved queue, ie from 'clef <file>'
+    pub open_on_launch: Option<Utf8PathBuf>,
+    /// A moment in a song to play in place of the saved queue, ie from a clef:// link
+    pub link_on_launch: Option<SongLink>,
+    /// Requests from later launches; see claim_instance
+    pub handoffs: Receiver<Handoff>,
+    /// A crash report from the last launch, to offer to open
+    pub crash_report: Option<Utf8PathBuf>,
+    /// Problems found in the db at launch, to offer the backup
+    pub database_damage: Option<DatabaseDamage>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     GotHwnd,
+    LoadedSavedLibrary(Box<SavedLibrary>),
+    LoadedOutputDevices(Vec<String>),
+    LoadedCrashedQueue(Option<SavedQueue>),
+    /// The curve saved for an output device (0; None = the default output), if any (1)
+    LoadedEqualizer(Option<String>, Option<EqCurve>),
+    ResumeCrashedQueueClicked,
+    DismissCrashedQueueClicked,
+    OpenCrashReportClicked,
+    DismissCrashReportClicked,
+    RestoreDatabaseClicked,
+    ContinueWithDamagedDatabaseClicked,
+    ResumeInterruptedSongClicked,
+    DismissInterruptedSongClicked,
     FromCrawler(CrawlerMessage),
     FromResizer(ResizerMessage),
     FromAudio(AudioMessage),
+    FromInstance(Handoff),
+    FromTray(TrayAction),
+    /// The file (0) and its directory read as an album, or an error to show (1)
+    OpenedFile(Utf8PathBuf, Result<Box<CrawledAlbum>, String>),
+    /// A file or folder dropped on the window (0), read from outside the library
+    ReadDropped(Utf8PathBuf, Result<DroppedAlbums, String>),
     Native(Event),
     PlayPausedClicked,
     PlaySongClicked(SongId),
+    PlayNextClicked(SongId),
+    AddToQueueClicked(SongId),
+    /// A position in the queue's play order
+    QueueSongClicked(usize),
+    RemoveFromQueueClicked(usize),
+    QueueRowHovered(usize),
+    QueueRowUnhovered(usize),
+    FavoriteClicked(SongId),
+    PlayFavoritesClicked,
     PauseClicked,
     ForwardClicked,
+    LoopPointClicked,
     BackClicked,
     SeekDrag(f32),
     SeekRelease,
     SeekWithoutSong(f32),
+    ShuffleClicked,
+    MuteClicked,
+    RescanClicked,
+    OutputDeviceSelected(OutputDevice),
+    GenreFilterSelected(GenreFilter),
+    EqualizerClicked,
+    CompactClicked,
+    EqBandChanged(usize, f32),
+    EqBandReleased,
+    EqPresetSelected(EqPreset),
+    LibraryViewClicked(LibraryView),
+    LibraryScrolled(RelativeOffset),
+    LoadedPlayHistory(Vec<PlayCount>, Vec<ListeningDay>),
+    /// Plays a local day's (yyyy-mm-dd) songs again, in the order they were played
+    RebuildSessionClicked(String),
+    LoadedSessionPlays(Vec<SongId>),
+    StatsPeriodClicked(StatsPeriod),
+    LoadedStats(Box<LoadedListening>),
+    LoadedLyrics(SongId, Option<Lyrics>),
+    LoadedWaveform(SongId, Option<Vec<u8>>),
+    FolderClicked(Utf8PathBuf),
+    FolderUpClicked,
+    /// The listing, or an error to show
+    ReadFolder(Result<FolderListing, String>),
+    /// Adds a file, or every song under a folder, to the end of the queue
+    EnqueueFolderClicked(Utf8PathBuf),
+    LoadedPlaylists(Vec<Playlist>),
+    PlayPlaylistClicked(PlaylistId),
+    LoadedSmartPlaylists(Vec<SmartPlaylist>),
+    SmartPlaylistNameChanged(String),
+    AddRuleClicked,
+    RuleKindSelected(usize, RuleKind),
+    RuleValueChanged(usize, String),
+    RemoveRuleClicked(usize),
+    SaveSmartPlaylistClicked,
+    DeleteSmartPlaylistClicked(SmartPlaylistId),
+    PlaySmartPlaylistClicked(SmartPlaylistId),
+    LoadedSmartPlaylistSongs(Vec<SongId>),
+    PlayOnThisDayClicked,
+    LoadedOnThisDay(Vec<SongId>),
+    PlaylistExportDestinationChanged(String),
+    ExportPlaylistClicked(String, PlaylistSongs),
+    /// The written file, or an error to show
+    ExportedPlaylist(Result<Utf8PathBuf, String>),
+    LoadedSessions(Vec<Session>),
+    CheckedPowerSource(Option<PowerSource>),
+    PowerModeSelected(PowerMode),
+    MusicDirectoryChanged(String),
+    AddMusicDirectoryClicked,
+    RemoveMusicDirectoryClicked(usize),
+    WebhookUrlChanged(String),
+    SaveWebhookClicked,
+    TestWebhookClicked,
+    WebhookTested(Result<(), String>),
+    ReplayGainModeSelected(ReplayGainMode),
+    ShuffleOrderSelected(ShuffleOrder),
+    ResampleQualitySelected(ResampleQuality),
+    VisualizerSelected(VisualizerStyle),
+    AccentSelected(Accent),
+    DurationBarsToggled(bool),
+    ImportDroppedToggled(bool),
+    FetchMissingArtToggled(bool),
+    CloseToTrayToggled(bool),
+    PreampChanged(f32),
+    PreampReleased,
+    LoadedSortNames(Vec<SortName>),
+    SortKindSelected(SortKind),
+    SortNameChanged(String),
+    SortAsChanged(String),
+    AddSortNameClicked,
+    RemoveSortNameClicked(SortKind, String),
+    RenameAlbumClicked(AlbumId),
+    AlbumTitleDraftChanged(String),
+    AlbumArtistDraftChanged(String),
+    SaveAlbumRenameClicked,
+    /// Goes back to the tagged title and artist
+    ResetAlbumRenameClicked,
+    CancelAlbumRenameClicked,
+    EditAlbumNotesClicked(AlbumId),
+    AlbumNotesDraftChanged(String),
+    SaveAlbumNotesClicked,
+    CancelAlbumNotesClicked,
+    ExportAlbumClicked(AlbumId),
+    FillFromMusicBrainzClicked(AlbumId),
+    /// The album and its songs as saved, or an error to show
+    FilledFromMusicBrainz(Result<(Album, Vec<Song>), String>),
+    ExportDestinationChanged(String),
+    ExportFormatSelected(ExportFormat),
+    StartExportClicked,
+    CancelExportDraftClicked,
+    FromExport(ExportMessage),
+    SessionNameChanged(String),
+    CreateSessionClicked,
+    DeleteSessionClicked(SessionId),
+    /// None = the default session
+    SwitchSessionClicked(Option<SessionId>),
+    SessionTransitionSelected(SessionId, TransitionKind),
+    /// The saved queue of the session that was switched to, if any
+    SwitchedSession(Option<SavedQueue>),
+    DismissToastClicked,
+    DismissNotificationClicked(usize),
+    ScanDetailsClicked,
+    DismissScanSummaryClicked,
     HoveredSong(SongId),
     UnhoveredSong(SongId),
+    MenuOpened(MenuTarget),
+    MenuClosed,
+    AddAlbumToQueueClicked(AlbumId),
+    /// A directory to open
+    ShowInFileManagerClicked(Utf8PathBuf),
 }
 
 impl Application for App {
@@ -201,15 +1337,69 @@ impl Application for App {
 
     fn new(flags: Self::Flags) -> (Self, iced::Command<Self::Message>) {
         let initial_state = Self::new(flags);
+        info!(
+            "created window after {:?}",
+            initial_state.started_at.elapsed()
+        );
+
+        // NOTE This displays the albums from previous crawls immediately,
+        // while the crawler verifies them in the background.
+        // Taking the saved queue writes to the db, so a damaged one waits for the user.
+        let load_saved_library = match initial_state.ui.database_damage {
+            None => Command::perform(
+                load_saved_library(initial_state.db.clone()),
+                Message::LoadedSavedLibrary,
+            ),
+            Some(_) => Command::none(),
+        };
+
+        let load_output_devices = Command::perform(
+            async { output_device_names() },
+            Message::LoadedOutputDevices,
+        );
+
+        let check_power_source =
+            Command::perform(detect_power_source(), Message::CheckedPowerSource);
+
+        let load_equalizer =
+            Command::perform(load_equalizer(initial_state.db.clone(), None), |curve| {
+                Message::LoadedEqualizer(None, curve)
+            });
+
+        let load_sort_names = Command::perform(
+            load_sort_names(initial_state.db.clone()),
+            Message::LoadedSortNames,
+        );
+
+        // NOTE this also applies the active session's transition
+        let load_sessions = Command::perform(
+            load_sessions(initial_state.db.clone()),
+            Message::LoadedSessions,
+        );
 
         #[cfg(not(target_os = "windows"))]
-        let initial_command = Command::none();
+        let initial_command = Command::batch([
+            load_saved_library,
+            load_output_devices,
+            load_equalizer,
+            load_sort_names,
+            load_sessions,
+            check_power_source,
+        ]);
 
         #[cfg(target_os = "windows")]
-        let initial_command = Command::perform(
-            async move { clef_shared::window_handle_hack::set_hwnd() },
-            |_| Message::GotHwnd,
-        );
+        let initial_command = Command::batch([
+            load_saved_library,
+            load_output_devices,
+            load_equalizer,
+            load_sort_names,
+            load_sessions,
+            check_power_source,
+            Command::perform(
+                async move { clef_shared::window_handle_hack::set_hwnd() },
+                |_| Message::GotHwnd,
+            ),
+        ]);
 
         (initial_state, initial_command)
     }
@@ -221,21 +1411,80 @@ impl Application for App {
     }
 
     fn theme(&self) -> Theme {
-        Theme::Dark
+        app_theme(&self.ui.settings.palette)
     }
 
     fn update(&mut self, message: Self::Message) -> iced::Command<Self::Message> {
+        self.log_startup_timing(&message);
+
+        let playback_before = playback_state(&self.ui);
         let effect = update(&mut self.ui, message);
-        self.execute(effect)
-    }
+        let mut command = self.execute(effect);
 
-    fn subscription(&self) -> Subscription<Self::Message> {
-        let crawler = if self.ui.crawling_music {
-            crawler_subcription(self.config.clone(), self.db.clone())
-                .map(Message::FromCrawler)
-        } else {
-            Subscription::none()
-        };
+        // NOTE whichever message opened the lyrics view or changed the song, this loads them
+        if let Some(song_id) = lyrics_to_load(&mut self.ui) {
+            let load_lyrics = self.execute(Effect::LoadLyrics(song_id));
+            command = Command::batch([command, load_lyrics]);
+        }
+        if let Some((song_id, path)) = waveform_to_load(&mut self.ui) {
+            let load_waveform = self.execute(Effect::LoadWaveform(song_id, path));
+            command = Command::batch([command, load_waveform]);
+        }
+
+        if let Some(event) = playback_event(playback_before, &self.ui) {
+            let timestamp = unix_timestamp();
+            if let Some(url) = &self.ui.settings.webhook_url {
+                let request = WebhookRequest {
+                    url: url.clone(),
+                    event: event.clone(),
+                    timestamp,
+                };
+                self.to_webhook
+                    .send(request)
+                    .unwrap_or_else(|e| error!("failed to send to webhook thread: {e}"));
+            }
+
+            if let Some(settings) = &self.ui.settings.mqtt {
+                let request = MqttRequest {
+                    settings: settings.clone(),
+                    event,
+                    timestamp,
+                };
+                self.to_mqtt
+                    .send(request)
+                    .unwrap_or_else(|e| error!("failed to send to mqtt thread: {e}"));
+            }
+        }
+
+        if let Some(tray) = &self.tray {
+            let tray_state = tray_state(&self.ui);
+            if tray_state != self.tray_state {
+                tray.update(tray_state.clone());
+                self.tray_state = tray_state;
+            }
+        }
+
+        let snapshot = ui_snapshot(&self.ui);
+        crash_report::set_state_summary("ui", format!("{snapshot:#?}"));
+
+        // NOTE whichever message brought albums near the scroll position,
+        // this loads their art
+        for request in show_album_art(&mut self.ui) {
+            self.to_resizer
+                .send(request)
+                .unwrap_or_else(|e| error!("failed to send to resizer thread: {e}"));
+        }
+
+        command
+    }
+
+    fn subscription(&self) -> Subscription<Self::Message> {
+        let crawler = if self.ui.crawling_music {
+            crawler_subcription(self.config.clone(), self.db.clone())
+                .map(Message::FromCrawler)
+        } else {
+            Subscription::none()
+        };
 
         let resizer = resizer_subscription(
             self.config.clone(),
@@ -246,9 +1495,21 @@ impl Application for App {
 
         let audio = audio_subscription(self.inbox.clone()).map(Message::FromAudio);
 
+        let instance =
+            instance_subscription(self.handoffs.clone()).map(Message::FromInstance);
+
         let native = iced_native::subscription::events().map(Message::Native);
 
-        Subscription::batch([crawler, resizer, audio, native])
+        let tray = tray_subscription(self.tray_actions.clone()).map(Message::FromTray);
+
+        let export = match &self.ui.album_export {
+            Some(export) => {
+                export_subscription(export.request.clone()).map(Message::FromExport)
+            }
+            None => Subscription::none(),
+        };
+
+        Subscription::batch([crawler, resizer, audio, instance, native, tray, export])
     }
 
     fn view(&self) -> iced::Element<'_, Self::Message, iced::Renderer<Self::Theme>> {
@@ -256,533 +1517,5890 @@ impl Application for App {
     }
 }
 
+/// The albums and queue saved during previous launches
+#[derive(Debug, Clone)]
+pub struct SavedLibrary {
+    albums: Vec<CrawledAlbum>,
+    queue: Option<SavedQueue>,
+}
+
+/// The queue is loaded along with the albums,
+/// so that its songs are in the music cache when it's restored
+async fn load_saved_library(db: SqlitePool) -> Box<SavedLibrary> {
+    let albums = load_saved_albums(db.clone()).await;
+    let queue = take_queue(db).await;
+
+    Box::new(SavedLibrary { albums, queue })
+}
+
+/// NOTE taking the queue clears it from the db, so a crashed queue is only offered once
+async fn take_queue(db: SqlitePool) -> Option<SavedQueue> {
+    let queue = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        conn.immediate_transaction(take_saved_queue)
+            .map_err(anyhow::Error::from)
+    });
+
+    queue.unwrap_or_else(|e| {
+        error!("failed to load saved queue: {e}");
+        None
+    })
+}
+
+/// The curve saved for an output device during previous launches, if there is one
+async fn load_equalizer(db: SqlitePool, device_name: Option<String>) -> Option<EqCurve> {
+    let saved = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        clef_db::queries::load_equalizer(&mut conn, device_name.as_deref())
+            .map_err(anyhow::Error::from)
+    });
+
+    match saved {
+        Ok(gains) if gains.is_empty() => None,
+
+        Ok(gains) => EqCurve::from_saved(&gains).or_else(|| {
+            error!("unexpected saved equalizer bands: {gains:?}");
+            None
+        }),
+
+        Err(e) => {
+            error!("failed to load equalizer: {e}");
+            None
+        }
+    }
+}
+
+async fn load_play_history(db: SqlitePool) -> (Vec<PlayCount>, Vec<ListeningDay>) {
+    let history = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        conn.immediate_transaction(|tx| {
+            Ok::<_, DbError>((
+                play_counts(tx)?,
+                listening_days(tx, LISTENING_DAYS_LENGTH)?,
+            ))
+        })
+        .map_err(anyhow::Error::from)
+    });
+
+    history.unwrap_or_else(|e| {
+        error!("failed to load play history: {e}");
+        (Vec::new(), Vec::new())
+    })
+}
+
+async fn load_plays_on_day(db: SqlitePool, day: String) -> Vec<SongId> {
+    let plays = db
+        .get()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut conn| plays_on_day(&mut conn, &day).map_err(anyhow::Error::from));
+
+    plays.unwrap_or_else(|e| {
+        error!("failed to load the plays on {day}: {e}");
+        Vec::new()
+    })
+}
+
+async fn load_stats(db: SqlitePool, period: StatsPeriod) -> Box<LoadedListening> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or_default();
+    let since = period.since(now);
+
+    let loaded = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        let songs = song_listening_since(&mut conn, since)?;
+        let clock = listening_clock(&mut conn, since)?;
+        Ok(LoadedListening { period, songs, clock })
+    });
+
+    let loaded = loaded.unwrap_or_else(|e| {
+        error!("failed to load listening stats: {e}");
+        LoadedListening {
+            period,
+            songs: Vec::new(),
+            clock: [[0.0; 24]; 7],
+        }
+    });
+
+    Box::new(loaded)
+}
+
+async fn load_lyrics(db: SqlitePool, song_id: SongId) -> Option<Lyrics> {
+    let lyrics = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        song_lyrics(&mut conn, song_id).map_err(anyhow::Error::from)
+    });
+
+    match lyrics {
+        Ok(lyrics) => lyrics.as_deref().map(Lyrics::parse),
+        Err(e) => {
+            error!("failed to load lyrics: {e}");
+            None
+        }
+    }
+}
+
+/// The song's saved waveform, or else one computed and saved for next time
+async fn load_waveform(
+    db: SqlitePool,
+    song_id: SongId,
+    path: Utf8PathBuf,
+) -> Option<Vec<u8>> {
+    // NOTE songs from outside the library aren't in the db, so theirs aren't kept
+    let saved = song_id.is_saved();
+
+    if saved {
+        let loaded = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+            song_waveform(&mut conn, song_id).map_err(anyhow::Error::from)
+        });
+        match loaded {
+            Ok(Some(peaks)) => return Some(peaks),
+            Ok(None) => {}
+            Err(e) => error!("failed to load waveform: {e}"),
+        }
+    }
+
+    let peaks = clef_audio::waveform::peaks(&path)?;
+
+    if saved {
+        let saved = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+            save_song_waveform(&mut conn, song_id, &peaks).map_err(anyhow::Error::from)
+        });
+        if let Err(e) = saved {
+            error!("failed to save waveform: {e}");
+        }
+    }
+
+    Some(peaks)
+}
+
+async fn load_playlists(db: SqlitePool) -> Vec<Playlist> {
+    let playlists = db
+        .get()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut conn| all_playlists(&mut conn).map_err(anyhow::Error::from));
+
+    playlists.unwrap_or_else(|e| {
+        error!("failed to load playlists: {e}");
+        Vec::new()
+    })
+}
+
+/// Writes the playlist's songs that are still in the library; the error is for display
+async fn export_playlist(
+    db: SqlitePool,
+    export: PlaylistExport,
+) -> Result<Utf8PathBuf, String> {
+    if !export.destination.is_dir() {
+        return Err(format!("{} isn't a folder.", export.destination));
+    }
+
+    let matching = match &export.songs {
+        PlaylistSongs::Listed(_) => Vec::new(),
+        PlaylistSongs::Matching(rules) => {
+            load_smart_playlist_songs(db.clone(), rules.clone()).await
+        }
+    };
+
+    let mut conn = db.get().map_err(|e| e.to_string())?;
+    let songs: HashMap<SongId, Song> = all_songs(&mut conn)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|song| (song.id, song))
+        .collect();
+
+    export
+        .write(&matching, &songs)
+        .map_err(|e| format!("Couldn't write {}: {e}", export.file()))
+}
+
+async fn load_smart_playlists(db: SqlitePool) -> Vec<SmartPlaylist> {
+    let playlists = db
+        .get()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut conn| all_smart_playlists(&mut conn).map_err(anyhow::Error::from));
+
+    playlists.unwrap_or_else(|e| {
+        error!("failed to load smart playlists: {e}");
+        Vec::new()
+    })
+}
+
+async fn load_smart_playlist_songs(db: SqlitePool, rules: Vec<SmartRule>) -> Vec<SongId> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or_default();
+
+    let song_ids = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        smart_playlist_songs(&mut conn, &rules, now).map_err(anyhow::Error::from)
+    });
+
+    song_ids.unwrap_or_else(|e| {
+        error!("failed to find smart playlist songs: {e}");
+        Vec::new()
+    })
+}
+
+async fn load_on_this_day(db: SqlitePool) -> Vec<SongId> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or_default();
+
+    let song_ids = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        on_this_day(&mut conn, now, ON_THIS_DAY_MIN_PLAYS, ON_THIS_DAY_LENGTH)
+            .map_err(anyhow::Error::from)
+    });
+
+    song_ids.unwrap_or_else(|e| {
+        error!("failed to find songs played on this day: {e}");
+        Vec::new()
+    })
+}
+
+async fn load_sort_names(db: SqlitePool) -> Vec<SortName> {
+    let sort_names = db
+        .get()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut conn| all_sort_names(&mut conn).map_err(anyhow::Error::from));
+
+    sort_names.unwrap_or_else(|e| {
+        error!("failed to load sort names: {e}");
+        Vec::new()
+    })
+}
+
+async fn load_sessions(db: SqlitePool) -> Vec<Session> {
+    let sessions = db
+        .get()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut conn| all_sessions(&mut conn).map_err(anyhow::Error::from));
+
+    sessions.unwrap_or_else(|e| {
+        error!("failed to load sessions: {e}");
+        Vec::new()
+    })
+}
+
+/// The default session always cuts straight to the next song
+fn active_transition(sessions: &[Session]) -> TransitionKind {
+    sessions
+        .iter()
+        .find(|session| session.active)
+        .and_then(|session| TransitionKind::from_saved(&session.transition))
+        .unwrap_or_default()
+}
+
+async fn switch_to_session(
+    db: SqlitePool,
+    session_id: Option<SessionId>,
+    leaving_position: Option<(SongId, f64)>,
+) -> Option<SavedQueue> {
+    let queue = db.get().map_err(anyhow::Error::from).and_then(|mut conn| {
+        conn.immediate_transaction(|tx| switch_session(tx, session_id, leaving_position))
+            .map_err(anyhow::Error::from)
+    });
+
+    queue.unwrap_or_else(|e| {
+        error!("failed to switch sessions: {e}");
+        None
+    })
+}
+
 // Update
 
 fn update(ui: &mut Ui, message: Message) -> Effect<Message> {
     match message {
         Message::GotHwnd => Effect::none(),
 
-        Message::FromCrawler(CrawlerMessage::NoAudioDirectory) => {
-            error!("failed to crawl audio directory");
-            ui.crawling_music = false;
-            Effect::none()
-        }
-        Message::FromCrawler(CrawlerMessage::DbError) => {
-            error!("crawler database error");
-            ui.crawling_music = false;
-            Effect::none()
-        }
-        Message::FromCrawler(CrawlerMessage::Done) => {
-            ui.crawling_music = false;
-            Effect::none()
-        }
-        Message::FromCrawler(CrawlerMessage::CrawledAlbum(crawled)) => {
-            let resize =
-                if crawled.cached_art.is_none() {
-                    crawled.album.original_art.as_ref().map(|original_art| {
-                        ResizeRequest {
-                            album_id: crawled.album.id,
-                            album_title: crawled
-                                .album
-                                .display_title()
-                                .unwrap_or_default()
-                                .to_string(),
-                            source_path: original_art.clone(),
-                        }
-                    })
-                } else {
-                    None
-                };
+        Message::LoadedSavedLibrary(saved) => {
+            let SavedLibrary { albums, queue } = *saved;
 
-            ui.music_cache.add_crawled_album(*crawled);
+            // NOTE missing resized art is requested when the crawler reaches the album
+            for album in albums {
+                ui.music_cache.add_crawled_album(album);
+            }
+
+            if let Some(file) = ui.open_on_launch.take() {
+                return open_file(ui, file);
+            }
+            if let Some(link) = ui.link_on_launch.take() {
+                return play_link(ui, link);
+            }
+
+            let play_on_launch = std::mem::take(&mut ui.play_on_launch);
+            if let Some(queue) = ui.music_cache.get_songs_queue(&play_on_launch) {
+                return AudioAction::PlayQueue(Box::new(queue)).into();
+            }
+
+            match queue {
+                Some(saved_queue) if saved_queue.from_crash => {
+                    ui.crashed_queue = Some(saved_queue);
+                    Effect::none()
+                }
+
+                Some(saved_queue) => {
+                    let Some(queue) = ui.music_cache.get_saved_queue(&saved_queue) else {
+                        error!("unable to rebuild saved queue");
+                        return Effect::none();
+                    };
+
+                    if saved_queue.playing {
+                        ui.interrupted_song = Some(InterruptedSong {
+                            title: queue.current.title.clone().unwrap_or_default(),
+                            elapsed_seconds: saved_queue.elapsed_seconds,
+                        });
+                    }
+
+                    let seconds = saved_queue.elapsed_seconds as f32;
+                    AudioAction::RestoreState(Box::new(queue), seconds).into()
+                }
 
-            resize.into()
+                None => Effect::none(),
+            }
         }
 
-        Message::FromResizer(ResizerMessage::ResizedImage(resized)) => {
-            ui.music_cache
-                .load_album_art(resized.album_id, resized.bytes);
+        Message::LoadedOutputDevices(names) => {
+            ui.output_devices = names;
             Effect::none()
         }
 
-        Message::Native(Event::Keyboard(KeyboardEvent::KeyReleased {
-            key_code: KeyCode::Space,
-            ..
-        })) => toggle(ui),
-
-        Message::Native(_) => Effect::none(),
+        // NOTE a device without a saved curve keeps the last one, until it's changed
+        Message::LoadedEqualizer(device_name, curve) => match curve {
+            Some(curve) if device_name == ui.output_device.name() => {
+                ui.equalizer = curve;
+                AudioAction::SetEqualizer(curve).into()
+            }
+            _ => Effect::none(),
+        },
 
-        Message::PlayPausedClicked => AudioAction::PlayPaused.into(),
+        Message::CompactClicked => toggle_compact(ui),
 
-        Message::PlaySongClicked(song_id) => {
-            let Some(current) = get_current_song(&ui.music_cache, song_id, true) else {
-                return Effect::none();
-            };
+        Message::EqualizerClicked => {
+            ui.show_equalizer = !ui.show_equalizer;
+            Effect::none()
+        }
 
-            let queue = ui.music_cache.get_album_queue(current.id, current.album_id);
-            let Some(queue) = queue else {
-                error!("unable to build album queue");
+        // NOTE these reload on every open, to include plays since the last one
+        Message::LibraryViewClicked(library_view) => {
+            if ui.library_view == library_view {
+                ui.library_view = LibraryView::Albums;
                 return Effect::none();
-            };
+            }
 
-            AudioAction::PlayQueue(Box::new(queue)).into()
+            ui.library_view = library_view;
+            ui.album_rename_draft = None;
+            ui.album_notes_draft = None;
+            ui.album_export_draft = None;
+            match library_view {
+                LibraryView::Albums => Effect::none(),
+                LibraryView::History => Effect::LoadPlayHistory,
+                LibraryView::Stats => Effect::LoadStats(ui.stats.period),
+                LibraryView::SmartPlaylists => Effect::LoadPlaylists,
+                LibraryView::Sessions => Effect::LoadSessions,
+                // NOTE the open folder is read again, in case its files changed
+                LibraryView::Folders => match &ui.folder {
+                    Some(folder) => Effect::ReadFolder(folder.path.clone()),
+                    None => Effect::none(),
+                },
+                // NOTE lyrics load after every update; see lyrics_to_load
+                LibraryView::Queue
+                | LibraryView::Settings
+                | LibraryView::Lyrics
+                | LibraryView::Album(_) => Effect::none(),
+            }
         }
 
-        Message::PauseClicked => AudioAction::Pause.into(),
-        Message::ForwardClicked => AudioAction::Forward.into(),
-        Message::BackClicked => AudioAction::Back.into(),
-
-        Message::SeekDrag(proportion) => {
-            ui.progress = Some(ProgressDisplay::Dragging(proportion));
+        Message::LoadedPlayHistory(counts, days) => {
+            ui.play_history = PlayHistory::new(counts, days);
             Effect::none()
         }
 
-        Message::SeekRelease => {
-            let proportion = match &ui.progress {
-                Some(ProgressDisplay::Dragging(proportion)) => *proportion,
-                _ => {
-                    error!("seek release without drag");
-                    return Effect::none();
-                }
+        Message::RebuildSessionClicked(day) => Effect::RebuildSession(day),
+        Message::LoadedSessionPlays(song_ids) => {
+            // NOTE songs removed from the library since are skipped, like in the view
+            let saved_queue = SavedQueue {
+                song_ids: song_ids
+                    .into_iter()
+                    .filter(|song_id| ui.music_cache.get_song(song_id).is_some())
+                    .collect(),
+                current_index: 0,
+                elapsed_seconds: 0.0,
+                from_crash: false,
+                playing: false,
+                unshuffled_song_ids: None,
+            };
+            let Some(queue) = ui.music_cache.get_saved_queue(&saved_queue) else {
+                let message = "None of that day's songs are in the library.";
+                ui.toast = Some(Toast::new(message.to_string()));
+                return Effect::none();
             };
 
-            AudioAction::Seek(proportion).into()
+            AudioAction::PlayQueue(Box::new(queue)).into()
         }
 
-        Message::SeekWithoutSong(_) => Effect::none(),
+        Message::StatsPeriodClicked(period) => Effect::LoadStats(period),
+        Message::LoadedStats(loaded) => {
+            ui.stats = ListeningStats::new(*loaded, &ui.music_cache);
+            Effect::none()
+        }
 
-        Message::HoveredSong(song_id) => {
-            ui.hovered_song_id = Some(song_id);
+        // NOTE the song may have changed again while these loaded
+        Message::LoadedLyrics(song_id, lyrics) => {
+            if ui.lyrics_song == Some(song_id) {
+                ui.lyrics = lyrics;
+            }
             Effect::none()
         }
-        Message::UnhoveredSong(song_id) => {
-            if ui.hovered_song_id == Some(song_id) {
-                ui.hovered_song_id = None;
+
+        Message::LoadedWaveform(song_id, peaks) => {
+            if ui.waveform_song == Some(song_id) {
+                ui.waveform = peaks;
             }
             Effect::none()
         }
 
-        Message::FromAudio(AudioMessage::DisplayUpdate(Some(display))) => {
-            update_current_song(ui, &display);
+        Message::FolderClicked(path) => Effect::ReadFolder(path),
 
-            match &ui.progress {
-                Some(ProgressDisplay::Dragging(_)) => {
-                    // ignore update to preserve draging slider state
-                }
+        Message::FolderUpClicked => {
+            let Some(folder) = &ui.folder else {
+                return Effect::none();
+            };
 
-                Some(ProgressDisplay::FromAudio(_)) | None => {
-                    ui.progress = Some(ProgressDisplay::FromAudio(display.times));
+            match parent_folder(&folder.path, &ui.settings.music_directories) {
+                Some(parent) => Effect::ReadFolder(parent),
+                None => {
+                    ui.folder = None;
+                    Effect::none()
                 }
             }
+        }
 
+        Message::ReadFolder(Ok(listing)) => {
+            ui.folder = Some(listing);
             Effect::none()
         }
 
-        Message::FromAudio(AudioMessage::SeekComplete(display)) => {
-            update_current_song(ui, &display);
+        Message::ReadFolder(Err(message)) => {
+            ui.toast = Some(Toast::new(message));
+            Effect::none()
+        }
 
-            // deliberately overwrite the dragging state
-            ui.progress = Some(ProgressDisplay::FromAudio(display.times));
+        Message::EnqueueFolderClicked(path) => enqueue_folder(ui, path),
 
+        Message::LoadedPlaylists(playlists)
+        | Message::FromCrawler(CrawlerMessage::Playlists(playlists)) => {
+            ui.playlists = playlists;
             Effect::none()
         }
 
-        Message::FromAudio(AudioMessage::DisplayUpdate(None)) => {
-            ui.current_song = None;
-            ui.progress = None;
+        Message::PlayPlaylistClicked(playlist_id) => {
+            let Some(playlist) = ui.playlists.iter().find(|p| p.id == playlist_id) else {
+                error!("unknown playlist: {playlist_id:?}");
+                return Effect::none();
+            };
+            let Some(queue) = ui.music_cache.get_listed_queue(&playlist.songs) else {
+                let message = "None of that playlist's songs are in the library.";
+                ui.toast = Some(Toast::new(message.to_string()));
+                return Effect::none();
+            };
+
+            AudioAction::PlayQueue(Box::new(queue)).into()
+        }
+
+        Message::LoadedSmartPlaylists(playlists) => {
+            ui.smart_playlists = playlists;
             Effect::none()
         }
 
-        Message::FromAudio(AudioMessage::AudioDied) => Effect::CloseWindow,
-    }
-}
+        Message::SmartPlaylistNameChanged(name) => {
+            ui.smart_playlist_draft.name = name;
+            Effect::none()
+        }
 
-fn toggle(ui: &Ui) -> Effect<Message> {
-    let playing = ui.current_song.as_ref().map(|c| c.playing);
+        Message::AddRuleClicked => {
+            ui.smart_playlist_draft.rules.push(RuleDraft::default());
+            Effect::none()
+        }
 
-    match playing {
-        Some(true) => AudioAction::Pause.into(),
-        Some(false) => AudioAction::PlayPaused.into(),
-        None => Effect::none(),
-    }
-}
+        Message::RuleKindSelected(index, kind) => {
+            if let Some(rule) = ui.smart_playlist_draft.rules.get_mut(index) {
+                rule.kind = kind;
+            }
+            Effect::none()
+        }
 
-fn update_current_song(ui: &mut Ui, display: &PlayerDisplay) {
-    match &mut ui.current_song {
-        Some(current_song) if current_song.id == display.song_id => {
-            current_song.playing = display.playing;
+        Message::RuleValueChanged(index, value) => {
+            if let Some(rule) = ui.smart_playlist_draft.rules.get_mut(index) {
+                rule.value = value;
+            }
+            Effect::none()
         }
 
-        _ => {
-            if let Some(current_song) =
-                get_current_song(&ui.music_cache, display.song_id, display.playing)
-            {
-                ui.current_song = Some(current_song);
+        Message::RemoveRuleClicked(index) => {
+            if index < ui.smart_playlist_draft.rules.len() {
+                ui.smart_playlist_draft.rules.remove(index);
             }
+            Effect::none()
         }
-    };
-}
 
-fn get_current_song(
-    music_cache: &MusicCache,
-    song_id: SongId,
-    playing: bool,
-) -> Option<CurrentSong> {
+        Message::SaveSmartPlaylistClicked => {
+            let Some(rules) = ui.smart_playlist_draft.to_rules() else {
+                error!("tried to save an incomplete smart playlist");
+                return Effect::none();
+            };
+
+            let draft = std::mem::take(&mut ui.smart_playlist_draft);
+            Effect::SaveSmartPlaylist(draft.name.trim().to_string(), rules)
+        }
+
+        Message::DeleteSmartPlaylistClicked(playlist_id) => {
+            Effect::DeleteSmartPlaylist(playlist_id)
+        }
+
+        Message::PlaySmartPlaylistClicked(playlist_id)
+        | Message::FromAudio(AudioMessage::PlaylistActivated(playlist_id)) => {
+            let playlist = ui.smart_playlists.iter().find(|p| p.id == playlist_id);
+            let Some(playlist) = playlist else {
+                error!("unknown smart playlist: {playlist_id:?}");
+                return Effect::none();
+            };
+
+            Effect::LoadSmartPlaylistSongs(playlist.rules.clone())
+        }
+
+        Message::LoadedSmartPlaylistSongs(song_ids) => {
+            let Some(queue) = ui.music_cache.get_songs_queue(&song_ids) else {
+                ui.toast = Some(Toast::new("No songs match that playlist.".to_string()));
+                return Effect::none();
+            };
+
+            AudioAction::PlayQueue(Box::new(queue)).into()
+        }
+
+        Message::PlayOnThisDayClicked => Effect::LoadOnThisDay,
+        // NOTE most played first, rather than album order like the smart playlists
+        Message::LoadedOnThisDay(song_ids) => {
+            let Some(queue) = ui.music_cache.get_listed_queue(&song_ids) else {
+                let message = "Nothing was played much on this day in previous years.";
+                ui.toast = Some(Toast::new(message.to_string()));
+                return Effect::none();
+            };
+
+            AudioAction::PlayQueue(Box::new(queue)).into()
+        }
+
+        Message::PlaylistExportDestinationChanged(destination) => {
+            ui.export_destination = destination;
+            Effect::none()
+        }
+
+        Message::ExportPlaylistClicked(name, songs) => {
+            let destination = ui.export_destination.trim();
+            if destination.is_empty() {
+                return Effect::none();
+            }
+
+            Effect::ExportPlaylist(PlaylistExport {
+                name,
+                songs,
+                destination: destination.into(),
+            })
+        }
+
+        Message::ExportedPlaylist(exported) => {
+            let message = match exported {
+                Ok(file) => format!("Exported {file}"),
+                Err(e) => e,
+            };
+            ui.toast = Some(Toast::new(message));
+            Effect::none()
+        }
+
+        Message::LoadedSessions(sessions) => {
+            ui.sessions = sessions;
+            AudioAction::SetTransition(active_transition(&ui.sessions)).into()
+        }
+
+        Message::LoadedSortNames(sort_names) => {
+            ui.music_cache.set_sort_names(sort_names);
+            Effect::none()
+        }
+
+        Message::SortKindSelected(kind) => {
+            ui.sort_name_draft.kind = kind;
+            Effect::none()
+        }
+
+        Message::SortNameChanged(name) => {
+            ui.sort_name_draft.name = name;
+            Effect::none()
+        }
+
+        Message::SortAsChanged(sort_name) => {
+            ui.sort_name_draft.sort_name = sort_name;
+            Effect::none()
+        }
+
+        Message::AddSortNameClicked => {
+            let draft = &ui.sort_name_draft;
+            let (name, sort_name) = (draft.name.trim(), draft.sort_name.trim());
+            if name.is_empty() || sort_name.is_empty() {
+                return Effect::none();
+            }
+
+            let sort_name = SortName {
+                kind: draft.kind,
+                name: name.to_string(),
+                sort_name: sort_name.to_string(),
+            };
+            ui.sort_name_draft = SortNameDraft {
+                kind: draft.kind,
+                ..Default::default()
+            };
+            ui.music_cache.set_sort_name(sort_name.clone());
+            Effect::SaveSortName(sort_name)
+        }
+
+        Message::RemoveSortNameClicked(kind, name) => {
+            ui.music_cache.remove_sort_name(kind, &name);
+            Effect::DeleteSortName(kind, name)
+        }
+
+        Message::RenameAlbumClicked(album_id) => {
+            let Some(album) = ui.music_cache.get_album(&album_id) else {
+                return Effect::none();
+            };
+
+            ui.album_rename_draft = Some(AlbumRenameDraft {
+                album_id,
+                title: album.display_title().unwrap_or_default().to_string(),
+                artist: album.artist.clone().unwrap_or_default(),
+            });
+            // NOTE the rename form is on the album's page, ie from a right-click menu
+            ui.library_view = LibraryView::Album(album_id);
+            Effect::none()
+        }
+
+        Message::AlbumTitleDraftChanged(title) => {
+            if let Some(draft) = &mut ui.album_rename_draft {
+                draft.title = title;
+            }
+            Effect::none()
+        }
+
+        Message::AlbumArtistDraftChanged(artist) => {
+            if let Some(draft) = &mut ui.album_rename_draft {
+                draft.artist = artist;
+            }
+            Effect::none()
+        }
+
+        Message::SaveAlbumRenameClicked => {
+            let Some(draft) = ui.album_rename_draft.take() else {
+                return Effect::none();
+            };
+            let Some(album) = ui.music_cache.get_album(&draft.album_id) else {
+                return Effect::none();
+            };
+
+            // NOTE only what differs from the tags is saved,
+            // so that a later retag still shows for the other field
+            let edited = |draft: &str, tagged: Option<&str>| {
+                let draft = draft.trim();
+                (!draft.is_empty() && Some(draft) != tagged).then(|| draft.to_string())
+            };
+            let renamed = AlbumRename {
+                title: edited(&draft.title, album.tagged_title.as_deref()),
+                artist: edited(&draft.artist, album.tagged_artist.as_deref()),
+            };
+
+            ui.music_cache.rename_album(draft.album_id, &renamed);
+            Effect::RenameAlbum(draft.album_id, renamed)
+        }
+
+        Message::ResetAlbumRenameClicked => match ui.album_rename_draft.take() {
+            Some(draft) => {
+                let renamed = AlbumRename::default();
+                ui.music_cache.rename_album(draft.album_id, &renamed);
+                Effect::RenameAlbum(draft.album_id, renamed)
+            }
+            None => Effect::none(),
+        },
+
+        Message::CancelAlbumRenameClicked => {
+            ui.album_rename_draft = None;
+            Effect::none()
+        }
+
+        Message::EditAlbumNotesClicked(album_id) => {
+            let Some(album) = ui.music_cache.get_album(&album_id) else {
+                return Effect::none();
+            };
+
+            ui.album_notes_draft = Some(AlbumNotesDraft {
+                album_id,
+                notes: album.notes.clone().unwrap_or_default(),
+            });
+            Effect::none()
+        }
+
+        Message::AlbumNotesDraftChanged(notes) => {
+            if let Some(draft) = &mut ui.album_notes_draft {
+                draft.notes = notes;
+            }
+            Effect::none()
+        }
+
+        Message::SaveAlbumNotesClicked => {
+            let Some(draft) = ui.album_notes_draft.take() else {
+                return Effect::none();
+            };
+
+            let notes = draft.notes.trim();
+            let notes = (!notes.is_empty()).then(|| notes.to_string());
+            ui.music_cache
+                .set_album_notes(draft.album_id, notes.clone());
+            Effect::SaveAlbumNotes(draft.album_id, notes)
+        }
+
+        Message::CancelAlbumNotesClicked => {
+            ui.album_notes_draft = None;
+            Effect::none()
+        }
+
+        Message::FillFromMusicBrainzClicked(album_id) => {
+            if ui.musicbrainz_filling.is_some() {
+                return Effect::none();
+            }
+            let Some(album) = ui.music_cache.get_album(&album_id) else {
+                return Effect::none();
+            };
+
+            ui.musicbrainz_filling = Some(album_id);
+            Effect::FillFromMusicBrainz(Box::new(album.clone()))
+        }
+
+        Message::FilledFromMusicBrainz(filled) => {
+            ui.musicbrainz_filling = None;
+            let message = match filled {
+                Ok((album, songs)) => {
+                    ui.music_cache.fill_album(album, songs);
+                    "Filled in the missing tags from MusicBrainz.".to_string()
+                }
+                Err(e) => e,
+            };
+            ui.toast = Some(Toast::new(message));
+
+            Effect::none()
+        }
+
+        Message::ExportAlbumClicked(album_id) => {
+            ui.album_export_draft = Some(AlbumExportDraft {
+                album_id,
+                destination: ui.export_destination.clone(),
+                format: ExportFormat::Folder,
+            });
+            Effect::none()
+        }
+
+        Message::ExportDestinationChanged(destination) => {
+            if let Some(draft) = &mut ui.album_export_draft {
+                draft.destination = destination;
+            }
+            Effect::none()
+        }
+
+        Message::ExportFormatSelected(format) => {
+            if let Some(draft) = &mut ui.album_export_draft {
+                draft.format = format;
+            }
+            Effect::none()
+        }
+
+        Message::StartExportClicked => {
+            if ui.album_export.is_some() {
+                return Effect::none();
+            }
+            let Some(draft) = ui.album_export_draft.take() else {
+                return Effect::none();
+            };
+            let Some(album) = ui.music_cache.get_cached_album(&draft.album_id) else {
+                return Effect::none();
+            };
+
+            let destination = Utf8PathBuf::from(draft.destination.trim());
+            let mut files: Vec<_> =
+                album.songs.iter().map(|song| song.file.clone()).collect();
+            files.extend(album.album.original_art.clone());
+
+            let name = match (&album.album.artist, album.album.display_title()) {
+                (Some(artist), Some(title)) => format!("{artist} - {title}"),
+                (None, Some(title)) => title.to_string(),
+                _ => album
+                    .album
+                    .directory
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string(),
+            };
+            let request = ExportRequest::new(
+                &name,
+                &album.album.directory,
+                files,
+                destination,
+                draft.format,
+            );
+
+            ui.export_destination = draft.destination;
+            ui.album_export = Some(AlbumExport {
+                album_id: draft.album_id,
+                progress: (0, request.files.len()),
+                request,
+            });
+            Effect::none()
+        }
+
+        Message::CancelExportDraftClicked => {
+            ui.album_export_draft = None;
+            Effect::none()
+        }
+
+        Message::FromExport(ExportMessage::Progress(written, total)) => {
+            if let Some(export) = &mut ui.album_export {
+                export.progress = (written, total);
+            }
+            Effect::none()
+        }
+
+        Message::FromExport(ExportMessage::Finished(result)) => {
+            let message = match result {
+                Ok(path) => format!("Exported the album to {path}."),
+                Err(e) => {
+                    error!("failed to export album: {e}");
+                    format!("Couldn't export the album: {e}")
+                }
+            };
+            ui.album_export = None;
+            ui.toast = Some(Toast::new(message));
+            Effect::none()
+        }
+
+        Message::WebhookUrlChanged(url) => {
+            ui.webhook_url_draft = url;
+            Effect::none()
+        }
+
+        // NOTE saving an empty url turns the webhook off
+        Message::SaveWebhookClicked => {
+            let url = ui.webhook_url_draft.trim();
+            ui.settings.webhook_url = (!url.is_empty()).then(|| url.to_string());
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::TestWebhookClicked => {
+            let url = ui.webhook_url_draft.trim();
+            if url.is_empty() {
+                return Effect::none();
+            }
+
+            ui.webhook_test = None;
+            Effect::TestWebhook(url.to_string())
+        }
+
+        Message::WebhookTested(result) => {
+            ui.webhook_test = Some(result);
+            Effect::none()
+        }
+
+        Message::MusicDirectoryChanged(directory) => {
+            ui.music_directory_draft = directory;
+            Effect::none()
+        }
+
+        Message::AddMusicDirectoryClicked => {
+            let directory = Utf8PathBuf::from(ui.music_directory_draft.trim());
+            if directory.as_str().is_empty()
+                || ui.settings.music_directories.contains(&directory)
+            {
+                return Effect::none();
+            }
+
+            if !directory.is_dir() {
+                ui.toast = Some(Toast::new(format!("{directory} isn't a directory.")));
+                return Effect::none();
+            }
+
+            ui.music_directory_draft.clear();
+            ui.settings.music_directories.push(directory);
+            // NOTE like a rescan, unless one is running already
+            start_crawl(ui);
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::RemoveMusicDirectoryClicked(index) => {
+            if ui.settings.music_directories.len() <= 1 {
+                return Effect::none();
+            }
+
+            ui.settings.music_directories.remove(index);
+            start_crawl(ui);
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::ReplayGainModeSelected(mode) => {
+            ui.settings.replaygain = Some(mode);
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::ShuffleOrderSelected(order) => {
+            ui.settings.shuffle_order = Some(order);
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::ResampleQualitySelected(quality) => {
+            ui.settings.resample_quality = quality;
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::VisualizerSelected(visualizer) => {
+            ui.settings.visualizer = visualizer;
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::AccentSelected(accent) => {
+            ui.settings.palette.accent = Some(ThemeColor::Named(accent));
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::DurationBarsToggled(duration_bars) => {
+            ui.settings.duration_bars = duration_bars;
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::ImportDroppedToggled(import_dropped) => {
+            ui.settings.import_dropped = import_dropped;
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::FetchMissingArtToggled(fetch_missing_art) => {
+            ui.settings.fetch_missing_art = fetch_missing_art;
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::CloseToTrayToggled(close_to_tray) => {
+            ui.settings.close_to_tray = close_to_tray;
+            Effect::SaveSettings(Box::new(ui.settings.clone()))
+        }
+
+        Message::PreampChanged(preamp_db) => {
+            ui.settings.replaygain_preamp = Some(preamp_db);
+            AudioAction::SetReplayGain(ui.settings.replay_gain()).into()
+        }
+
+        Message::PreampReleased => Effect::SaveSettings(Box::new(ui.settings.clone())),
+
+        Message::SessionNameChanged(name) => {
+            ui.session_name_draft = name;
+            Effect::none()
+        }
+
+        Message::CreateSessionClicked => {
+            let name = ui.session_name_draft.trim();
+            if name.is_empty() || ui.sessions.iter().any(|s| s.name == name) {
+                return Effect::none();
+            }
+
+            let name = name.to_string();
+            ui.session_name_draft.clear();
+            Effect::CreateSession(name)
+        }
+
+        Message::DeleteSessionClicked(session_id) => Effect::DeleteSession(session_id),
+
+        Message::SwitchSessionClicked(session_id) => {
+            let active_id = ui.sessions.iter().find(|s| s.active).map(|s| s.id);
+            if active_id == session_id {
+                return Effect::none();
+            }
+
+            for session in &mut ui.sessions {
+                session.active = Some(session.id) == session_id;
+            }
+            let transition = active_transition(&ui.sessions);
+
+            let leaving_position = match (&ui.current_song, &ui.progress) {
+                (Some(current_song), Some(ProgressDisplay::FromAudio(times)))
+                    if current_song.id.is_saved() =>
+                {
+                    let elapsed = times.elapsed.seconds as f64 + times.elapsed.frac;
+                    Some((current_song.id, elapsed))
+                }
+                _ => None,
+            };
+
+            Effect::SwitchSession(session_id, transition, leaving_position)
+        }
+
+        Message::SessionTransitionSelected(session_id, transition) => {
+            let Some(session) = ui.sessions.iter_mut().find(|s| s.id == session_id)
+            else {
+                return Effect::none();
+            };
+            session.transition = transition.to_saved().to_string();
+
+            Effect::SaveSessionTransition(session_id, transition, session.active)
+        }
+
+        // NOTE the banners are about the session that was left
+        Message::SwitchedSession(saved_queue) => {
+            ui.crashed_queue = None;
+            ui.interrupted_song = None;
+
+            let queue = saved_queue.as_ref().and_then(|saved_queue| {
+                let queue = ui.music_cache.get_saved_queue(saved_queue)?;
+                Some((queue, saved_queue.elapsed_seconds as f32))
+            });
+            let Some((queue, seconds)) = queue else {
+                return AudioAction::Stop.into();
+            };
+
+            // keep playing if something was, in the new session's queue
+            let playing = ui.current_song.as_ref().is_some_and(|song| song.playing);
+            if playing {
+                AudioAction::ResumeQueue(Box::new(queue), seconds).into()
+            } else {
+                AudioAction::RestoreState(Box::new(queue), seconds).into()
+            }
+        }
+
+        // NOTE this is saved on release, rather than for every step of a drag
+        Message::EqBandChanged(band, gain_db) => {
+            ui.equalizer.0[band] = gain_db;
+            AudioAction::SetEqualizer(ui.equalizer).into()
+        }
+
+        Message::EqBandReleased => {
+            Effect::SaveEqualizer(ui.output_device.name(), ui.equalizer)
+        }
+
+        Message::EqPresetSelected(preset) => {
+            ui.equalizer = preset.curve();
+            Effect::SaveEqualizer(ui.output_device.name(), ui.equalizer)
+        }
+
+        Message::OutputDeviceSelected(output_device) => {
+            let device_name = output_device.name();
+            ui.output_device = output_device;
+
+            Effect::SwitchOutputDevice(device_name)
+        }
+
+        Message::LibraryScrolled(offset) => {
+            ui.library_scroll = offset.y;
+            Effect::none()
+        }
+
+        Message::GenreFilterSelected(genre_filter) => {
+            ui.genre_filter = genre_filter;
+            Effect::none()
+        }
+
+        Message::ResumeCrashedQueueClicked => {
+            let Some(crashed_queue) = ui.crashed_queue.take() else {
+                return Effect::none();
+            };
+
+            let Some(queue) = ui.music_cache.get_saved_queue(&crashed_queue) else {
+                error!("unable to rebuild crashed queue");
+                return Effect::none();
+            };
+
+            let seconds = crashed_queue.elapsed_seconds as f32;
+            AudioAction::ResumeQueue(Box::new(queue), seconds).into()
+        }
+
+        Message::DismissCrashedQueueClicked => {
+            ui.crashed_queue = None;
+            Effect::none()
+        }
+
+        Message::OpenCrashReportClicked => match ui.crash_report.take() {
+            Some(report_path) => Effect::OpenCrashReport(report_path),
+            None => Effect::none(),
+        },
+
+        Message::DismissCrashReportClicked => {
+            ui.crash_report = None;
+            Effect::none()
+        }
+
+        Message::RestoreDatabaseClicked => match ui.database_damage.take() {
+            Some(damage) if damage.backup_time.is_some() => Effect::RestoreDatabase,
+            _ => Effect::none(),
+        },
+
+        Message::ContinueWithDamagedDatabaseClicked => {
+            if ui.database_damage.take().is_none() {
+                return Effect::none();
+            }
+
+            ui.crawling_music = true;
+            Effect::ContinueWithDamagedDatabase
+        }
+
+        // NOTE the queue was already restored paused, at the same position
+        Message::ResumeInterruptedSongClicked => {
+            ui.interrupted_song = None;
+            AudioAction::PlayPaused.into()
+        }
+
+        Message::DismissInterruptedSongClicked => {
+            ui.interrupted_song = None;
+            Effect::none()
+        }
+
+        Message::FromCrawler(CrawlerMessage::NoAudioDirectory) => {
+            error!("failed to crawl audio directory");
+            ui.crawling_music = false;
+            Effect::Notify(Notification::error("Couldn't read any music directory."))
+        }
+        // NOTE a failed album doesn't stop the scan, but the rest usually fail too
+        Message::FromCrawler(CrawlerMessage::DbError(reason)) => {
+            error!("crawler database error: {reason}");
+            ui.crawling_music = false;
+            let message = format!("Couldn't save the library: {reason}.");
+            Effect::Notify(Notification::error(message))
+        }
+        Message::FromCrawler(CrawlerMessage::Removed(removed)) => {
+            let removed_titles = removed
+                .albums
+                .iter()
+                .filter_map(|album_id| ui.music_cache.get_album(album_id))
+                .map(|album| album.display_title().unwrap_or_default().to_string())
+                .collect();
+            ui.scan_changes
+                .record_removed(removed_titles, removed.songs.len());
+
+            ui.music_cache.remove(&removed);
+
+            if let LibraryView::Album(album_id) = ui.library_view {
+                if ui.music_cache.get_album(&album_id).is_none() {
+                    ui.library_view = LibraryView::Albums;
+                }
+            }
+
+            Effect::none()
+        }
+        Message::FromCrawler(CrawlerMessage::PlaylistConflicts(conflicts)) => {
+            let kept = conflicts
+                .iter()
+                .map(|conflict| conflict.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let message = format!(
+                "Playlists were changed both here and in the playlist folder; \
+                 the folder's versions were kept as {kept}."
+            );
+            Effect::Notify(Notification::warning(message))
+        }
+        Message::FromCrawler(CrawlerMessage::Done) => {
+            ui.crawling_music = false;
+
+            // NOTE changes from a crawl that was restarted carry over to this one
+            let changes = std::mem::take(&mut ui.scan_changes);
+            if !changes.is_empty() {
+                ui.scan_summary = Some(changes);
+                ui.show_scan_details = false;
+            }
+
+            Effect::none()
+        }
+        Message::FromCrawler(CrawlerMessage::CrawledAlbum(crawled)) => {
+            // NOTE previously resized art is loaded once it's shown
+            let resize =
+                if crawled.cached_art.is_none() && crawled.album.resized_art.is_none() {
+                    crawled.album.original_art.as_ref().map(|original_art| {
+                        ResizeRequest {
+                            album_id: crawled.album.id,
+                            album_title: crawled
+                                .album
+                                .display_title()
+                                .unwrap_or_default()
+                                .to_string(),
+                            source_path: original_art.clone(),
+                            already_resized: false,
+                        }
+                    })
+                } else {
+                    None
+                };
+            let lookup = art_lookup(ui, &crawled);
+
+            let title = crawled.album.display_title().unwrap_or_default();
+            ui.scan_changes.record_crawled(title, &crawled.changes);
+            ui.music_cache.add_crawled_album(*crawled);
+
+            match (resize, lookup) {
+                (None, Some(lookup)) => Effect::FetchArt(lookup),
+                (resize, _) => resize.into(),
+            }
+        }
+
+        Message::FromResizer(ResizerMessage::ResizedImage(resized)) => {
+            ui.music_cache.load_album_art(resized);
+            Effect::none()
+        }
+
+        Message::FromResizer(ResizerMessage::Failed(album_id, album_title, reason)) => {
+            ui.music_cache.album_art_failed(album_id);
+            let message = format!("Couldn't load the art for {album_title}: {reason}.");
+            Effect::Notify(Notification::warning(message))
+        }
+
+        // NOTE this is deliberately undocumented; it's for bug reports
+        Message::Native(Event::Keyboard(KeyboardEvent::KeyPressed {
+            key_code: KeyCode::D,
+            modifiers,
+        })) if modifiers.control() && modifiers.shift() => AudioAction::DumpState.into(),
+
+        // NOTE this is also undocumented; it's for diagnosing crackling
+        Message::Native(Event::Keyboard(KeyboardEvent::KeyPressed {
+            key_code: KeyCode::T,
+            modifiers,
+        })) if modifiers.control() && modifiers.shift() => {
+            ui.show_output_telemetry = !ui.show_output_telemetry;
+            Effect::none()
+        }
+
+        Message::Native(Event::Keyboard(KeyboardEvent::KeyPressed {
+            key_code,
+            modifiers,
+        })) => match ui.keymap.action(key_code, modifiers) {
+            Some(action) if action.repeats() => key_action(ui, action),
+            _ => Effect::none(),
+        },
+
+        Message::Native(Event::Keyboard(KeyboardEvent::KeyReleased {
+            key_code,
+            modifiers,
+        })) => match ui.keymap.action(key_code, modifiers) {
+            Some(action) if !action.repeats() => key_action(ui, action),
+            _ => Effect::none(),
+        },
+
+        // NOTE buttons capture their presses, so these only come from the rest of a row
+        Message::Native(Event::Mouse(MouseEvent::ButtonPressed(mouse::Button::Left)))
+            if ui.library_view == LibraryView::Queue =>
+        {
+            ui.dragged_queue_index = ui
+                .hovered_queue_index
+                .filter(|&index| is_upcoming(ui, index));
+            Effect::none()
+        }
+
+        Message::Native(Event::Mouse(MouseEvent::ButtonReleased(
+            mouse::Button::Left,
+        ))) => {
+            let Some(from) = ui.dragged_queue_index.take() else {
+                return Effect::none();
+            };
+
+            match ui.hovered_queue_index {
+                Some(to) if to != from && is_upcoming(ui, to) => {
+                    AudioAction::MoveInQueue(from, to).into()
+                }
+                _ => Effect::none(),
+            }
+        }
+
+        // NOTE the mini player's size isn't kept; it's always the same
+        Message::Native(Event::Window(WindowEvent::Resized { width, height }))
+            if !ui.compact =>
+        {
+            ui.full_window_size = Some((width, height));
+            Effect::none()
+        }
+
+        // NOTE without a tray icon, a hidden window couldn't be shown again
+        Message::Native(Event::Window(WindowEvent::CloseRequested)) => {
+            if ui.settings.close_to_tray && ui.tray_available {
+                set_window_hidden(ui, true)
+            } else {
+                Effect::CloseWindow
+            }
+        }
+
+        Message::Native(Event::Window(WindowEvent::FileDropped(path))) => {
+            match Utf8PathBuf::try_from(path) {
+                Ok(path) => enqueue_dropped(ui, path),
+                Err(e) => {
+                    let path = e.into_path_buf();
+                    let message = format!("Unable to read {}.", path.display());
+                    ui.toast = Some(Toast::new(message));
+                    Effect::none()
+                }
+            }
+        }
+
+        Message::Native(_) => Effect::none(),
+
+        Message::PlayPausedClicked => AudioAction::PlayPaused.into(),
+
+        Message::PlaySongClicked(song_id) => {
+            let Some(current) = get_current_song(&ui.music_cache, song_id, true) else {
+                return Effect::none();
+            };
+
+            let queue = ui.music_cache.get_album_queue(current.id, current.album_id);
+            let Some(queue) = queue else {
+                error!("unable to build album queue");
+                return Effect::none();
+            };
+
+            AudioAction::PlayQueue(Box::new(queue)).into()
+        }
+
+        Message::PlayNextClicked(song_id) => {
+            let Some(queued) = ui.music_cache.get_queued_song(song_id) else {
+                error!("unable to find song to queue: {song_id:?}");
+                return Effect::none();
+            };
+
+            AudioAction::EnqueueNext(Box::new(queued)).into()
+        }
+
+        Message::AddToQueueClicked(song_id) => {
+            let Some(queued) = ui.music_cache.get_queued_song(song_id) else {
+                error!("unable to find song to queue: {song_id:?}");
+                return Effect::none();
+            };
+
+            AudioAction::Enqueue(Box::new(queued)).into()
+        }
+
+        Message::QueueSongClicked(index) => AudioAction::GoTo(index).into(),
+
+        Message::RemoveFromQueueClicked(index) => {
+            AudioAction::RemoveFromQueue(index).into()
+        }
+
+        Message::QueueRowHovered(index) => {
+            ui.hovered_queue_index = Some(index);
+            Effect::none()
+        }
+        Message::QueueRowUnhovered(index) => {
+            if ui.hovered_queue_index == Some(index) {
+                ui.hovered_queue_index = None;
+            }
+            Effect::none()
+        }
+
+        Message::FavoriteClicked(song_id) => {
+            if !song_id.is_saved() {
+                ui.toast = Some(Toast::new(
+                    "Only songs in the library can be favorites.".to_string(),
+                ));
+                return Effect::none();
+            }
+
+            let Some(song) = ui.music_cache.get_song(&song_id) else {
+                error!("unable to find song to favorite: {song_id:?}");
+                return Effect::none();
+            };
+            let favorite = !song.favorite;
+
+            ui.music_cache.set_favorite(song_id, favorite);
+            Effect::SaveFavorite(song_id, favorite)
+        }
+
+        Message::PlayFavoritesClicked => {
+            let Some(queue) = ui.music_cache.get_favorites_queue() else {
+                error!("no favorites to play");
+                return Effect::none();
+            };
+
+            AudioAction::PlayQueue(Box::new(queue)).into()
+        }
+
+        Message::PauseClicked => AudioAction::Pause.into(),
+        Message::ForwardClicked => AudioAction::Forward.into(),
+        Message::LoopPointClicked => AudioAction::MarkLoopPoint.into(),
+        Message::BackClicked => AudioAction::Back.into(),
+
+        Message::SeekDrag(proportion) => {
+            ui.progress = Some(ProgressDisplay::Dragging(proportion));
+            Effect::none()
+        }
+
+        Message::SeekRelease => {
+            let proportion = match &ui.progress {
+                Some(ProgressDisplay::Dragging(proportion)) => *proportion,
+                _ => {
+                    error!("seek release without drag");
+                    return Effect::none();
+                }
+            };
+
+            AudioAction::Seek(proportion).into()
+        }
+
+        Message::SeekWithoutSong(_) => Effect::none(),
+
+        Message::RescanClicked => {
+            // NOTE this restarts the crawler subscription from the beginning
+            start_crawl(ui);
+            Effect::none()
+        }
+
+        Message::ShuffleClicked => {
+            ui.shuffle = !ui.shuffle;
+            AudioAction::SetShuffle(ui.shuffle).into()
+        }
+
+        Message::MuteClicked => toggle_mute(ui),
+
+        Message::HoveredSong(song_id) => {
+            ui.hovered_song_id = Some(song_id);
+            Effect::none()
+        }
+        Message::UnhoveredSong(song_id) => {
+            if ui.hovered_song_id == Some(song_id) {
+                ui.hovered_song_id = None;
+            }
+            Effect::none()
+        }
+
+        Message::MenuOpened(target) => {
+            ui.context_menu = Some(target);
+            Effect::none()
+        }
+        Message::MenuClosed => {
+            ui.context_menu = None;
+            Effect::none()
+        }
+
+        Message::AddAlbumToQueueClicked(album_id) => {
+            let Some(album) = ui.music_cache.get_cached_album(&album_id) else {
+                return Effect::none();
+            };
+
+            let songs = album
+                .songs
+                .iter()
+                .filter_map(|song| ui.music_cache.get_queued_song(song.id))
+                .collect();
+            AudioAction::EnqueueAll(songs).into()
+        }
+
+        Message::ShowInFileManagerClicked(directory) => {
+            Effect::ShowInFileManager(directory)
+        }
+
+        Message::FromAudio(AudioMessage::DisplayUpdate(Some(display))) => {
+            update_current_song(ui, &display);
+            ui.output_telemetry = display.output;
+            ui.conversions.record(&display);
+            if ui.toast.as_ref().is_some_and(Toast::expired) {
+                ui.toast = None;
+            }
+
+            match &ui.progress {
+                Some(ProgressDisplay::Dragging(_)) => {
+                    // ignore update to preserve draging slider state
+                }
+
+                Some(ProgressDisplay::FromAudio(_)) | None => {
+                    ui.progress = Some(ProgressDisplay::FromAudio(display.times));
+                }
+            }
+
+            // NOTE there's no timer subscription, so this is also checked on audio updates
+            if ui.power.check_due() {
+                Effect::CheckPowerSource
+            } else {
+                Effect::none()
+            }
+        }
+
+        Message::CheckedPowerSource(source) => {
+            let was_low_power = ui.power.low_power();
+            ui.power.source = source;
+
+            if ui.power.low_power() == was_low_power {
+                return Effect::none();
+            }
+            Effect::SetLowPower(ui.power.low_power())
+        }
+
+        Message::PowerModeSelected(mode) => {
+            ui.power.mode = mode;
+            Effect::SetLowPower(ui.power.low_power())
+        }
+
+        Message::FromAudio(AudioMessage::SeekComplete(display)) => {
+            update_current_song(ui, &display);
+            ui.output_telemetry = display.output;
+            ui.conversions.record(&display);
+
+            // deliberately overwrite the dragging state
+            ui.progress = Some(ProgressDisplay::FromAudio(display.times));
+
+            Effect::none()
+        }
+
+        Message::FromAudio(AudioMessage::DisplayUpdate(None)) => {
+            ui.current_song = None;
+            ui.progress = None;
+            ui.output_telemetry = None;
+            Effect::none()
+        }
+
+        Message::FromAudio(AudioMessage::QueueUpdate(queue)) => {
+            ui.queue = queue.map(|queue| *queue);
+            Effect::none()
+        }
+
+        // NOTE the player was replaced with a stopped one
+        Message::FromAudio(AudioMessage::AudioDied) => {
+            ui.current_song = None;
+            ui.progress = None;
+            ui.output_telemetry = None;
+            ui.queue = None;
+            Effect::LoadCrashedQueue
+        }
+
+        Message::LoadedCrashedQueue(queue) => {
+            if let Some(queue) = queue.filter(|queue| queue.from_crash) {
+                ui.interrupted_song = None;
+                ui.crashed_queue = Some(queue);
+            }
+            Effect::none()
+        }
+
+        Message::FromInstance(Handoff::Show) => set_window_hidden(ui, false),
+
+        Message::FromTray(TrayAction::TogglePlayback) => toggle(ui),
+        Message::FromTray(TrayAction::Next) => AudioAction::Forward.into(),
+        Message::FromTray(TrayAction::Previous) => AudioAction::Back.into(),
+        Message::FromTray(TrayAction::ToggleWindow) => {
+            set_window_hidden(ui, !ui.window_hidden)
+        }
+        Message::FromTray(TrayAction::Quit) => Effect::CloseWindow,
+
+        Message::FromInstance(Handoff::Open(file)) => open_file(ui, file),
+
+        Message::FromInstance(Handoff::PlayLink(link)) => play_link(ui, link),
+
+        Message::OpenedFile(file, Ok(album)) => {
+            ui.music_cache.add_crawled_album(*album);
+            open_file(ui, file)
+        }
+
+        Message::ReadDropped(path, Ok(dropped)) => {
+            let DroppedAlbums { albums, imported } = dropped;
+
+            // NOTE a dropped file is read with its album, but only the file is queued
+            let dropped_file = path.is_file();
+            let song_ids: Vec<SongId> = albums
+                .iter()
+                .flat_map(|album| album.songs.iter())
+                .filter(|song| !dropped_file || song.file == path)
+                .map(|song| song.id)
+                .collect();
+            for album in albums {
+                ui.music_cache.add_crawled_album(album);
+            }
+
+            if imported > 0 {
+                let message = match imported {
+                    1 => "Imported 1 album; it'll be in the library after this scan.",
+                    _ => {
+                        "Imported the albums; they'll be in the library after this scan."
+                    }
+                };
+                ui.toast = Some(Toast::new(message.to_string()));
+                start_crawl(ui);
+            }
+
+            let songs = song_ids
+                .into_iter()
+                .filter_map(|song_id| ui.music_cache.get_queued_song(song_id))
+                .collect();
+            AudioAction::EnqueueAll(songs).into()
+        }
+
+        Message::ReadDropped(_path, Err(message)) => {
+            Effect::Notify(Notification::warning(message))
+        }
+
+        Message::OpenedFile(_file, Err(message)) => {
+            Effect::Notify(Notification::warning(message))
+        }
+
+        Message::FromInstance(Handoff::Play(path)) => {
+            match ui.music_cache.get_path_queue(&path) {
+                Some(queue) => AudioAction::PlayQueue(Box::new(queue)).into(),
+                None => {
+                    ui.toast =
+                        Some(Toast::new(format!("No songs in the library at {path}.")));
+                    Effect::none()
+                }
+            }
+        }
+
+        // NOTE the os media controls can change the volume too
+        Message::FromAudio(AudioMessage::VolumeChanged { volume, muted }) => {
+            ui.volume = volume;
+            ui.muted = muted;
+            Effect::none()
+        }
+
+        Message::FromAudio(AudioMessage::OutputBufferGrown(buffer_ms)) => {
+            let message =
+                format!("Audio was stuttering, so the buffer grew to {buffer_ms} ms.");
+            ui.toast = Some(Toast::new(message));
+            Effect::none()
+        }
+
+        // NOTE the player already moved past it, and marked it in the db
+        Message::FromAudio(AudioMessage::SongUnplayable(song_id, reason)) => {
+            let title = ui
+                .music_cache
+                .get_song(&song_id)
+                .and_then(Song::display_title)
+                .unwrap_or("a song")
+                .to_string();
+            ui.music_cache.set_unplayable(song_id);
+
+            let message = format!("Skipped {title}, which couldn't be played: {reason}.");
+            Effect::Notify(Notification::warning(message))
+        }
+
+        // NOTE the display update for the pause isn't sent, so it's shown here
+        Message::FromAudio(AudioMessage::NoOutputDevice) => {
+            if let Some(current_song) = &mut ui.current_song {
+                current_song.playing = false;
+            }
+            let message = "No audio device is available, so playback is paused.";
+            Effect::Notify(Notification::error(message))
+        }
+
+        Message::DismissToastClicked => {
+            ui.toast = None;
+            Effect::none()
+        }
+
+        Message::DismissNotificationClicked(index) => {
+            ui.notifications.dismiss(index);
+            Effect::none()
+        }
+
+        Message::ScanDetailsClicked => {
+            ui.show_scan_details = !ui.show_scan_details;
+            Effect::none()
+        }
+
+        Message::DismissScanSummaryClicked => {
+            ui.scan_summary = None;
+            ui.show_scan_details = false;
+            Effect::none()
+        }
+
+        Message::FromAudio(AudioMessage::StateDump(player)) => {
+            let dump = StateDump {
+                player: *player,
+                ui: ui_snapshot(ui),
+            };
+
+            Effect::WriteStateDump(Box::new(dump))
+        }
+    }
+}
+
+/// Plays a file's album from the library,
+/// or reads its directory first when it's from outside the library
+fn open_file(ui: &Ui, file: Utf8PathBuf) -> Effect<Message> {
+    let Some(song) = ui.music_cache.get_song_by_file(&file) else {
+        return Effect::OpenFile(file);
+    };
+
+    let Some(queue) = ui.music_cache.get_album_queue(song.id, song.album_id) else {
+        error!("unable to build album queue");
+        return Effect::none();
+    };
+
+    AudioAction::PlayQueue(Box::new(queue)).into()
+}
+
+/// Plays a linked song's album, starting from the linked moment
+fn play_link(ui: &mut Ui, link: SongLink) -> Effect<Message> {
+    let Some(song) = ui.music_cache.get_song_by_unpacked_id(link.song) else {
+        let message = format!("The linked song ({}) isn't in the library.", link.song);
+        ui.toast = Some(Toast::new(message));
+        return Effect::none();
+    };
+
+    let Some(queue) = ui.music_cache.get_album_queue(song.id, song.album_id) else {
+        error!("unable to build album queue");
+        return Effect::none();
+    };
+
+    let seconds = link.seconds.min(song.total_seconds as f32);
+    AudioAction::ResumeQueue(Box::new(queue), seconds).into()
+}
+
+/// Adds a dropped file or folder to the end of the queue;
+/// songs from outside the library are read first
+fn enqueue_dropped(ui: &Ui, path: Utf8PathBuf) -> Effect<Message> {
+    if let Some(queue) = ui.music_cache.get_path_queue(&path) {
+        let songs = std::iter::once(queue.current).chain(queue.next).collect();
+        return AudioAction::EnqueueAll(songs).into();
+    }
+
+    // NOTE folders already under a music directory are left for the scan to find
+    let in_library = ui
+        .settings
+        .music_directories
+        .iter()
+        .any(|directory| path.starts_with(directory));
+    let import_to = match ui.settings.music_directories.first() {
+        Some(directory) if ui.settings.import_dropped && !in_library => {
+            Some(directory.clone())
+        }
+        _ => None,
+    };
+
+    Effect::ReadDropped(path, import_to)
+}
+
+/// Adds a file or folder to the end of the queue in path order, rather than album order;
+/// folders the crawler didn't save, ie nested ones, are read from disk first
+fn enqueue_folder(ui: &Ui, path: Utf8PathBuf) -> Effect<Message> {
+    let songs = ui.music_cache.get_folder_songs(&path);
+    if songs.is_empty() {
+        return Effect::ReadDropped(path, None);
+    }
+
+    AudioAction::EnqueueAll(songs).into()
+}
+
+/// An online lookup for a crawled album with no art of any kind, if they're turned on;
+/// untitled albums are skipped, since there's nothing to search for
+fn art_lookup(ui: &Ui, crawled: &CrawledAlbum) -> Option<ArtLookup> {
+    let album = &crawled.album;
+    let no_art = crawled.cached_art.is_none()
+        && album.resized_art.is_none()
+        && album.original_art.is_none();
+    if !ui.settings.fetch_missing_art || !no_art {
+        return None;
+    }
+
+    Some(ArtLookup {
+        album_id: album.id,
+        album_title: album.display_title()?.to_string(),
+        artist: album.artist.clone(),
+        directory: album.directory.clone(),
+    })
+}
+
+/// NOTE like crash_report::open_report, this leaves the choice of app to the desktop
+fn open_in_file_manager(directory: &Utf8Path) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    let mut command = std::process::Command::new("xdg-open");
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = std::process::Command::new("explorer");
+
+    command.arg(directory).spawn()?;
+
+    Ok(())
+}
+
+/// The playing song, and whether it's playing rather than paused
+fn playback_state(ui: &Ui) -> Option<(SongId, bool)> {
+    ui.current_song
+        .as_ref()
+        .map(|current| (current.id, current.playing))
+}
+
+/// What to tell the webhook and mqtt broker about a change in playback, if anything.
+/// NOTE a song restored paused at launch isn't an event
+fn playback_event(before: Option<(SongId, bool)>, ui: &Ui) -> Option<PlaybackEvent> {
+    let after = playback_state(ui);
+    if after == before {
+        return None;
+    }
+
+    let Some(current) = &ui.current_song else {
+        return Some(PlaybackEvent::Stopped);
+    };
+    let song = WebhookSong {
+        title: current.title.clone(),
+        artist: current.artist.clone(),
+        album: current.album.clone(),
+        duration_seconds: current.total_seconds,
+    };
+
+    match (before, current.playing) {
+        (_, true) => Some(PlaybackEvent::NowPlaying { song }),
+        (Some((song_id, true)), false) if song_id == current.id => {
+            Some(PlaybackEvent::Paused { song })
+        }
+        (_, false) => None,
+    }
+}
+
+fn ui_snapshot(ui: &Ui) -> UiSnapshot {
+    let current_song = ui.current_song.as_ref().map(|current| CurrentSongSnapshot {
+        id: current.id,
+        album_id: current.album_id,
+        playing: current.playing,
+        total_seconds: current.total_seconds,
+    });
+
+    let progress = ui.progress.as_ref().map(|progress| match progress {
+        ProgressDisplay::Dragging(proportion) => ProgressSnapshot::Dragging(*proportion),
+        ProgressDisplay::FromAudio(times) => ProgressSnapshot::FromAudio {
+            elapsed_seconds: times.elapsed.seconds as f64 + times.elapsed.frac,
+            total_seconds: times.total.seconds as f64 + times.total.frac,
+        },
+    });
+
+    UiSnapshot {
+        crawling_music: ui.crawling_music,
+        current_song,
+        progress,
+        hovered_song_id: ui.hovered_song_id,
+        shuffle: ui.shuffle,
+        crashed_queue: ui.crashed_queue.as_ref().map(SavedQueueSnapshot::from),
+        cached_albums: ui.music_cache.albums().len(),
+    }
+}
+
+/// NOTE showing the window also raises it
+fn set_window_hidden(ui: &mut Ui, hidden: bool) -> Effect<Message> {
+    ui.window_hidden = hidden;
+
+    match hidden {
+        true => Effect::HideWindow,
+        false => Effect::FocusWindow,
+    }
+}
+
+fn tray_state(ui: &Ui) -> TrayState {
+    let song = ui
+        .current_song
+        .as_ref()
+        .map(|current| match &current.artist {
+            Some(artist) => format!("{} - {artist}", current.title),
+            None => current.title.clone(),
+        });
+
+    TrayState {
+        song,
+        playing: ui
+            .current_song
+            .as_ref()
+            .is_some_and(|current| current.playing),
+        window_hidden: ui.window_hidden,
+    }
+}
+
+fn toggle(ui: &Ui) -> Effect<Message> {
+    let playing = ui.current_song.as_ref().map(|c| c.playing);
+
+    match playing {
+        Some(true) => AudioAction::Pause.into(),
+        Some(false) => AudioAction::PlayPaused.into(),
+        None => Effect::none(),
+    }
+}
+
+/// How much each volume key changes the linear gain
+const VOLUME_STEP: f32 = 0.1;
+
+fn key_action(ui: &mut Ui, action: KeyAction) -> Effect<Message> {
+    if let Some(offset) = seek_offset(action) {
+        return AudioAction::SeekBy(offset).into();
+    }
+
+    match action {
+        KeyAction::TogglePlayback => toggle(ui),
+        KeyAction::Next => AudioAction::Forward.into(),
+        KeyAction::Previous => AudioAction::Back.into(),
+
+        KeyAction::VolumeUp | KeyAction::VolumeDown => {
+            let step = if action == KeyAction::VolumeUp {
+                VOLUME_STEP
+            } else {
+                -VOLUME_STEP
+            };
+            // NOTE rounded, so that steps land back on 100%
+            let volume = ((ui.volume + step) / VOLUME_STEP).round() * VOLUME_STEP;
+            ui.volume = volume.clamp(0.0, 1.0);
+            ui.muted = false;
+            AudioAction::SetVolume(ui.volume).into()
+        }
+
+        KeyAction::Mute => toggle_mute(ui),
+
+        KeyAction::JumpToCurrent => match &ui.current_song {
+            Some(current) => {
+                ui.library_view = LibraryView::Album(current.album_id);
+                Effect::none()
+            }
+            None => Effect::none(),
+        },
+
+        // NOTE songs from outside the library can't be linked to
+        KeyAction::CopyLink => match (&ui.current_song, &ui.progress) {
+            (Some(current), Some(ProgressDisplay::FromAudio(times)))
+                if current.id.is_saved() =>
+            {
+                let link = SongLink {
+                    song: current.id.unpack(),
+                    seconds: times.elapsed.seconds as f32,
+                };
+                ui.toast = Some(Toast::new("Copied a link to this moment.".to_string()));
+                Effect::CopyToClipboard(link.to_string())
+            }
+            _ => Effect::none(),
+        },
+
+        KeyAction::ToggleCompact => toggle_compact(ui),
+        KeyAction::MarkLoopPoint => AudioAction::MarkLoopPoint.into(),
+
+        KeyAction::SeekForward
+        | KeyAction::SeekBack
+        | KeyAction::SeekForwardLong
+        | KeyAction::SeekBackLong
+        | KeyAction::SeekForwardTenth
+        | KeyAction::SeekBackTenth => Effect::none(),
+    }
+}
+
+fn toggle_mute(ui: &mut Ui) -> Effect<Message> {
+    ui.muted = !ui.muted;
+    AudioAction::SetMuted(ui.muted).into()
+}
+
+/// Starts a crawl, unless a damaged db is waiting for the user to restore it or carry on
+fn start_crawl(ui: &mut Ui) {
+    if ui.database_damage.is_none() {
+        ui.crawling_music = true;
+    }
+}
+
+fn toggle_compact(ui: &mut Ui) -> Effect<Message> {
+    ui.compact = !ui.compact;
+
+    let (width, height) = if ui.compact {
+        COMPACT_WINDOW_SIZE
+    } else {
+        ui.full_window_size.unwrap_or(DEFAULT_WINDOW_SIZE)
+    };
+
+    Effect::ResizeWindow(width, height)
+}
+
+fn seek_offset(action: KeyAction) -> Option<SeekOffset> {
+    let offset = match action {
+        KeyAction::SeekForward => SeekOffset::Seconds(5.0),
+        KeyAction::SeekBack => SeekOffset::Seconds(-5.0),
+        KeyAction::SeekForwardLong => SeekOffset::Seconds(30.0),
+        KeyAction::SeekBackLong => SeekOffset::Seconds(-30.0),
+        KeyAction::SeekForwardTenth => SeekOffset::Proportion(0.1),
+        KeyAction::SeekBackTenth => SeekOffset::Proportion(-0.1),
+        _ => return None,
+    };
+
+    Some(offset)
+}
+
+/// Shown beside the pickers while the volume is turned down
+fn volume_label(ui: &Ui) -> Option<String> {
+    if ui.muted {
+        return Some("Muted".to_string());
+    }
+
+    (ui.volume < 1.0).then(|| format!("Volume {:.0}%", ui.volume * 100.0))
+}
+
+fn update_current_song(ui: &mut Ui, display: &PlayerDisplay) {
+    // playing anything answers the prompt
+    if display.playing {
+        ui.interrupted_song = None;
+    }
+
+    match &mut ui.current_song {
+        Some(current_song) if current_song.id == display.song_id => {
+            current_song.playing = display.playing;
+            current_song.ab_loop = display.ab_loop;
+        }
+
+        _ => {
+            if let Some(mut current_song) =
+                get_current_song(&ui.music_cache, display.song_id, display.playing)
+            {
+                current_song.ab_loop = display.ab_loop;
+                ui.current_song = Some(current_song);
+            }
+        }
+    };
+}
+
+fn get_current_song(
+    music_cache: &MusicCache,
+    song_id: SongId,
+    playing: bool,
+) -> Option<CurrentSong> {
     let Some(song) = music_cache.get_song(&song_id) else {
         error!("unexpected song id: {song_id:?}");
         return None;
     };
-    let Some(album) = music_cache.get_album(&song.album_id) else {
-        error!("unexpected album id: {:?}", &song.album_id);
+    let Some(album) = music_cache.get_album(&song.album_id) else {
+        error!("unexpected album id: {:?}", &song.album_id);
+        return None;
+    };
+
+    Some(CurrentSong::new(song, album, playing))
+}
+
+/// Row heights in the album list, estimated from song counts;
+/// the list doesn't report its layout
+const ESTIMATED_SONG_ROW_HEIGHT: f32 = 32.0;
+/// How far above and below the scroll position to keep art loaded;
+/// generous, since the heights are estimates and the window size isn't known
+const ART_LOOKAROUND: f32 = 3000.0;
+
+/// The playing song and its file, when its waveform hasn't been loaded yet
+fn waveform_to_load(ui: &mut Ui) -> Option<(SongId, Utf8PathBuf)> {
+    let Some(song_id) = ui.current_song.as_ref().map(|song| song.id) else {
+        ui.waveform_song = None;
+        ui.waveform = None;
+        return None;
+    };
+    if ui.waveform_song == Some(song_id) {
+        return None;
+    }
+
+    // NOTE the queue can follow the song's first display update
+    let queue = ui.queue.as_ref()?;
+    let song = queue
+        .songs
+        .get(queue.current_index)
+        .filter(|song| song.id == song_id)?;
+
+    ui.waveform_song = Some(song_id);
+    ui.waveform = None;
+    Some((song_id, song.path.clone()))
+}
+
+/// The ring for the player to copy samples into;
+/// None when there's no visualizer, so that it doesn't bother
+fn sample_tap(ui: &Ui) -> Option<SampleRing> {
+    visualizer_on(ui).then(|| ui.sample_ring.clone())
+}
+
+/// NOTE the visualizer redraws every frame, so it's off to save power
+fn visualizer_on(ui: &Ui) -> bool {
+    ui.settings.visualizer != VisualizerStyle::Off && !ui.power.low_power()
+}
+
+/// The song to load lyrics for, when the lyrics view is open and hasn't loaded them yet
+fn lyrics_to_load(ui: &mut Ui) -> Option<SongId> {
+    if ui.library_view != LibraryView::Lyrics {
+        ui.lyrics_song = None;
+        ui.lyrics = None;
+        return None;
+    }
+
+    let song_id = ui.current_song.as_ref()?.id;
+    if ui.lyrics_song == Some(song_id) {
+        return None;
+    }
+
+    ui.lyrics_song = Some(song_id);
+    ui.lyrics = None;
+    Some(song_id)
+}
+
+/// Marks the albums on screen (or close to it), and returns requests for their art
+fn show_album_art(ui: &mut Ui) -> Vec<ResizeRequest> {
+    let shown = albums_near_scroll(ui);
+    ui.music_cache.show_albums(&shown)
+}
+
+fn albums_near_scroll(ui: &Ui) -> Vec<AlbumId> {
+    match ui.library_view {
+        LibraryView::Albums => {}
+        LibraryView::Album(album_id) => return vec![album_id],
+        LibraryView::Queue
+        | LibraryView::History
+        | LibraryView::Stats
+        | LibraryView::Folders
+        | LibraryView::SmartPlaylists
+        | LibraryView::Sessions
+        | LibraryView::Settings
+        | LibraryView::Lyrics => return Vec::new(),
+    }
+
+    let albums = match &ui.genre_filter {
+        GenreFilter::All => ui.music_cache.albums(),
+        GenreFilter::Genre(genre) => ui.music_cache.albums_in_genre(genre),
+    };
+    let row_height = |album: &CachedAlbum| {
+        (album.songs.len() as f32 * ESTIMATED_SONG_ROW_HEIGHT).max(IMAGE_SIZE as f32)
+    };
+
+    let total_height: f32 = albums.iter().map(|album| row_height(album)).sum();
+    let scrolled_to = total_height * ui.library_scroll;
+    let near = (scrolled_to - ART_LOOKAROUND)..(scrolled_to + ART_LOOKAROUND);
+
+    let mut near_albums = Vec::new();
+    let mut row_top = 0.0;
+    for album in albums {
+        let row_bottom = row_top + row_height(album);
+        if row_bottom >= near.start && row_top <= near.end {
+            near_albums.push(album.album.id);
+        }
+        row_top = row_bottom;
+    }
+
+    near_albums
+}
+
+// View
+
+fn view(ui: &Ui) -> Element<'_, Message> {
+    const MAX: f32 = 1.0;
+    const STEP: f32 = 0.01;
+
+    let progress_slider: Element<'_, Message> = match (&ui.progress, &ui.waveform) {
+        (Some(progress), Some(peaks)) => {
+            let seek_bar = WaveformSeekBar {
+                peaks,
+                proportion: progress.display_proportion(),
+                on_drag: Message::SeekDrag,
+                on_release: Message::SeekRelease,
+            };
+
+            canvas(seek_bar)
+                .width(Length::Fill)
+                .height(Length::Fixed(WAVEFORM_HEIGHT))
+                .into()
+        }
+
+        // NOTE this is also shown while the waveform is computed
+        (Some(progress), None) => {
+            let proportion = progress.display_proportion();
+
+            slider(0.0..=MAX, proportion, Message::SeekDrag)
+                .step(STEP)
+                .on_release(Message::SeekRelease)
+                .into()
+        }
+
+        // disabled
+        (None, _) => slider(0.0..=MAX, 0.0, Message::SeekWithoutSong)
+            .step(STEP)
+            .into(),
+    };
+
+    if ui.compact {
+        return view_compact(ui, progress_slider);
+    }
+
+    let content = match ui.library_view {
+        LibraryView::Albums => view_album_list(
+            &ui.music_cache,
+            &ui.genre_filter,
+            ui.hovered_song_id,
+            ui.context_menu,
+            &ui.current_song,
+            ui.settings.duration_bars,
+        ),
+        LibraryView::Queue => view_queue(
+            ui.queue.as_ref(),
+            &ui.current_song,
+            ui.hovered_queue_index,
+            ui.dragged_queue_index,
+        ),
+        LibraryView::History => view_history(&ui.music_cache, &ui.play_history),
+        LibraryView::Stats => view_stats(&ui.music_cache, &ui.stats),
+        LibraryView::Folders => {
+            view_folders(&ui.settings.music_directories, ui.folder.as_ref())
+        }
+        LibraryView::Lyrics => view_lyrics(&ui.current_song, &ui.progress, &ui.lyrics),
+        LibraryView::SmartPlaylists => view_playlists(
+            &ui.playlists,
+            &ui.smart_playlists,
+            &ui.smart_playlist_draft,
+            &ui.export_destination,
+        ),
+        LibraryView::Sessions => view_sessions(&ui.sessions, &ui.session_name_draft),
+        LibraryView::Settings => view_settings(
+            &ui.settings,
+            &ui.music_directory_draft,
+            ui.music_cache.sort_names(),
+            &ui.sort_name_draft,
+            &ui.conversions,
+            &ui.keymap,
+            ui.tray_available,
+        )
+        .push(view_webhook_settings(
+            &ui.webhook_url_draft,
+            ui.settings.webhook_url.as_deref(),
+            &ui.webhook_test,
+        )),
+        LibraryView::Album(album_id) => {
+            match ui.music_cache.get_cached_album(&album_id) {
+                Some(album) => view_album_page(
+                    album,
+                    ui.hovered_song_id,
+                    ui.context_menu,
+                    &ui.current_song,
+                    ui.settings.duration_bars,
+                    ui.album_rename_draft.as_ref(),
+                    column![
+                        view_album_notes(&album.album, ui.album_notes_draft.as_ref()),
+                        view_album_export(
+                            album.album.id,
+                            ui.album_export_draft.as_ref(),
+                            ui.album_export.as_ref(),
+                        ),
+                        view_musicbrainz_button(album.album.id, ui.musicbrainz_filling),
+                    ]
+                    .into(),
+                ),
+                None => column![text("This album is no longer in the library.")],
+            }
+        }
+    };
+
+    let content = fill_container(scrollable(content).on_scroll(Message::LibraryScrolled));
+    let content: Element<'_, Message> = match (&ui.crashed_queue, &ui.interrupted_song) {
+        (Some(_), _) => column![view_crashed_queue_banner(), content]
+            .spacing(10)
+            .into(),
+        (None, Some(interrupted)) => {
+            column![view_interrupted_song_banner(interrupted), content]
+                .spacing(10)
+                .into()
+        }
+        (None, None) => content.into(),
+    };
+    let content: Element<'_, Message> = match &ui.scan_summary {
+        Some(changes) => {
+            column![view_scan_summary(changes, ui.show_scan_details), content]
+                .spacing(10)
+                .into()
+        }
+        None => content,
+    };
+    let content: Element<'_, Message> = match &ui.crash_report {
+        Some(_) => column![view_crash_report_banner(), content]
+            .spacing(10)
+            .into(),
+        None => content,
+    };
+    let content: Element<'_, Message> = match &ui.database_damage {
+        Some(damage) => column![view_database_damage_banner(damage), content]
+            .spacing(10)
+            .into(),
+        None => content,
+    };
+    let bottom_row = view_bottom_row(
+        &ui.current_song,
+        &ui.progress,
+        ui.shuffle,
+        ui.show_equalizer,
+        ui.library_view,
+        ui.crawling_music,
+        view_pickers(ui),
+    );
+
+    let mut main_column = column![content];
+    if ui.show_equalizer {
+        main_column = main_column.push(view_equalizer(&ui.equalizer));
+    }
+    let playing = ui.current_song.as_ref().is_some_and(|song| song.playing);
+    if visualizer_on(ui) && playing {
+        let visualizer = Visualizer {
+            ring: &ui.sample_ring,
+            style: ui.settings.visualizer,
+        };
+        main_column = main_column.push(
+            canvas(visualizer)
+                .width(Length::Fill)
+                .height(Length::Fixed(VISUALIZER_HEIGHT)),
+        );
+    }
+    if ui.show_output_telemetry {
+        main_column =
+            main_column.push(text(format_output_telemetry(&ui.output_telemetry)));
+    }
+    if !ui.notifications.is_empty() {
+        main_column = main_column.push(view_notifications(&ui.notifications));
+    }
+    if let Some(toast) = &ui.toast {
+        main_column = main_column.push(view_toast(toast));
+    }
+
+    let main_column = main_column
+        .push(bottom_row)
+        .push(progress_slider)
+        .spacing(10)
+        .padding(20)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .align_items(Alignment::Center);
+
+    main_column.into()
+}
+
+fn view_crash_report_banner<'a>() -> Element<'a, Message> {
+    row![
+        text("Clef crashed last time, and saved a report.").width(Length::Fill),
+        button("Open report").on_press(Message::OpenCrashReportClicked),
+        button("Dismiss")
+            .on_press(Message::DismissCrashReportClicked)
+            .style(no_background()),
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center)
+    .into()
+}
+
+fn view_database_damage_banner<'a>(damage: &DatabaseDamage) -> Element<'a, Message> {
+    let problems = match damage.problems.len() {
+        1 => "a problem".to_string(),
+        count => format!("{count} problems"),
+    };
+
+    let mut banner = row![].spacing(10).align_items(Alignment::Center);
+    match damage.backup_time.and_then(|time| time.elapsed().ok()) {
+        Some(age) => {
+            banner = banner
+                .push(
+                    text(format!(
+                        "The library database has {problems}. \
+                         Clef can restore the backup from {}, \
+                         losing any changes since then.",
+                        format_backup_age(age)
+                    ))
+                    .width(Length::Fill),
+                )
+                .push(
+                    button("Restore and quit").on_press(Message::RestoreDatabaseClicked),
+                );
+        }
+        None => {
+            banner = banner.push(
+                text(format!(
+                    "The library database has {problems}, and there's no backup \
+                     to restore; see the log for details."
+                ))
+                .width(Length::Fill),
+            );
+        }
+    }
+
+    banner
+        .push(
+            button("Continue")
+                .on_press(Message::ContinueWithDamagedDatabaseClicked)
+                .style(no_background()),
+        )
+        .into()
+}
+
+fn format_backup_age(age: Duration) -> String {
+    let days = age.as_secs() / (24 * 60 * 60);
+    match days {
+        0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        days => format!("{days} days ago"),
+    }
+}
+
+fn view_crashed_queue_banner<'a>() -> Element<'a, Message> {
+    row![
+        text("Playback stopped unexpectedly.").width(Length::Fill),
+        button("Resume").on_press(Message::ResumeCrashedQueueClicked),
+        button("Dismiss")
+            .on_press(Message::DismissCrashedQueueClicked)
+            .style(no_background()),
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center)
+    .into()
+}
+
+fn view_interrupted_song_banner(interrupted: &InterruptedSong) -> Element<'_, Message> {
+    let elapsed = format_seconds(interrupted.elapsed_seconds);
+
+    row![
+        text(format!("Resume '{}' at {elapsed}?", interrupted.title)).width(Length::Fill),
+        button("Resume").on_press(Message::ResumeInterruptedSongClicked),
+        button("Dismiss")
+            .on_press(Message::DismissInterruptedSongClicked)
+            .style(no_background()),
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center)
+    .into()
+}
+
+fn view_scan_summary(changes: &ScanChanges, show_details: bool) -> Element<'_, Message> {
+    /// The album list scrolls past this, rather than pushing the library down
+    const DETAILS_HEIGHT: f32 = 150.0;
+
+    let mut summary = row![
+        text(format!("Rescan finished: {}.", changes.summary())).width(Length::Fill)
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center);
+
+    if !changes.albums.is_empty() {
+        let label = if show_details {
+            "Hide details"
+        } else {
+            "Details"
+        };
+        summary = summary.push(
+            button(label)
+                .on_press(Message::ScanDetailsClicked)
+                .style(no_background()),
+        );
+    }
+
+    let summary = summary.push(
+        button("Dismiss")
+            .on_press(Message::DismissScanSummaryClicked)
+            .style(no_background()),
+    );
+
+    if !show_details || changes.albums.is_empty() {
+        return summary.into();
+    }
+
+    let album_rows: Vec<_> = changes
+        .albums
+        .iter()
+        .map(|(change, title)| text(format!("{change}: {title}")).into())
+        .collect();
+    let details = scrollable(Column::with_children(album_rows).spacing(5))
+        .height(Length::Fixed(DETAILS_HEIGHT));
+
+    column![summary, details].spacing(10).into()
+}
+
+fn view_toast(toast: &Toast) -> Element<'_, Message> {
+    row![
+        text(&toast.message).width(Length::Fill),
+        button("Dismiss")
+            .on_press(Message::DismissToastClicked)
+            .style(no_background()),
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center)
+    .into()
+}
+
+fn view_notifications(notifications: &Notifications) -> Element<'_, Message> {
+    let rows = notifications.iter().enumerate().map(|(index, shown)| {
+        let notification = &shown.notification;
+        let message = match shown.count {
+            1 => notification.message.clone(),
+            count => format!("{} ({count} times)", notification.message),
+        };
+
+        row![
+            text(notification.severity).width(Length::Fixed(70.0)),
+            text(message).width(Length::Fill),
+            button("Dismiss")
+                .on_press(Message::DismissNotificationClicked(index))
+                .style(no_background()),
+        ]
+        .spacing(10)
+        .align_items(Alignment::Center)
+        .into()
+    });
+
+    Column::with_children(rows.collect()).spacing(5).into()
+}
+
+fn fill_container<'a>(
+    content: impl Into<Element<'a, Message>>,
+) -> Container<'a, Message> {
+    container(content)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .center_x()
+        .center_y()
+}
+
+fn view_album_list<'a>(
+    music: &'a MusicCache,
+    genre_filter: &GenreFilter,
+    hovered_song_id: Option<SongId>,
+    context_menu: Option<MenuTarget>,
+    current_song: &'a Option<CurrentSong>,
+    duration_bars: bool,
+) -> Column<'a, Message> {
+    let (albums, favorites) = match genre_filter {
+        GenreFilter::All => (music.albums(), music.favorites()),
+        GenreFilter::Genre(genre) => {
+            let mut favorites = music.favorites();
+            favorites.retain(|song| song.genre.as_ref() == Some(genre));
+            (music.albums_in_genre(genre), favorites)
+        }
+    };
+
+    let mut rows: Vec<_> = albums
+        .iter()
+        .map(|album| {
+            view_album(
+                album,
+                hovered_song_id,
+                context_menu,
+                current_song,
+                duration_bars,
+            )
+        })
+        .collect();
+
+    if !favorites.is_empty() {
+        rows.insert(0, view_favorites(favorites));
+    }
+    if let Some(genre_picker) = view_genre_picker(music.genres(), genre_filter) {
+        rows.insert(0, genre_picker);
+    }
+
+    Column::with_children(rows)
+        .spacing(10)
+        .width(Length::Fill)
+        .align_items(Alignment::Center)
+}
+
+/// A virtual album of every favorited song, played as one queue
+fn view_favorites(favorites: Vec<&Song>) -> Element<'_, Message> {
+    let length = Length::Fixed(IMAGE_SIZE as f32);
+    let play_button = button(icons::play().style(accent_icon()))
+        .on_press(Message::PlayFavoritesClicked)
+        .style(no_background());
+
+    let song_count = match favorites.len() {
+        1 => "1 song".to_string(),
+        n => format!("{n} songs"),
+    };
+    let favorites_info = column![text("Favorites"), text(song_count), play_button,]
+        .width(Length::FillPortion(1));
+
+    let song_rows: Vec<_> = favorites
+        .into_iter()
+        .map(|song| {
+            row![
+                text(song.display_title().unwrap_or_default()).width(Length::Fill),
+                text(song.artist.as_deref().unwrap_or_default()),
+                view_favorite_button(song),
+                horizontal_space(Length::Fixed(10f32))
+            ]
+            .width(Length::Fill)
+            .align_items(Alignment::Center)
+            .spacing(10)
+            .padding(2)
+            .into()
+        })
+        .collect();
+    let songs_list = Column::with_children(song_rows).width(Length::FillPortion(2));
+
+    row![Space::new(length, length), favorites_info, songs_list]
+        .spacing(10)
+        .into()
+}
+
+/// Recently and most played songs and the days they were played, side by side
+fn view_history<'a>(
+    music: &'a MusicCache,
+    history: &'a PlayHistory,
+) -> Column<'a, Message> {
+    // NOTE songs removed from the library since they were played are skipped
+    let recent_rows: Vec<_> = history
+        .recently_played
+        .iter()
+        .filter_map(|song_id| music.get_song(song_id))
+        .map(|song| view_history_row(song, None))
+        .collect();
+    let most_played_rows: Vec<_> = history
+        .most_played
+        .iter()
+        .filter_map(|(song_id, plays)| Some((music.get_song(song_id)?, *plays)))
+        .map(|(song, plays)| view_history_row(song, Some(plays)))
+        .collect();
+
+    let recently_played = column![
+        text("Recently played"),
+        Column::with_children(recent_rows).spacing(5)
+    ]
+    .spacing(10)
+    .width(Length::FillPortion(1));
+    let most_played = column![
+        text("Most played"),
+        Column::with_children(most_played_rows).spacing(5)
+    ]
+    .spacing(10)
+    .width(Length::FillPortion(1));
+    let day_rows: Vec<_> = history.days.iter().map(view_listening_day_row).collect();
+    let days = column![text("Sessions"), Column::with_children(day_rows).spacing(5)]
+        .spacing(10)
+        .width(Length::Shrink);
+
+    column![row![recently_played, most_played, days].spacing(20)].width(Length::Fill)
+}
+
+/// The open folder's subfolders and songs, or the music directories to start from
+fn view_folders<'a>(
+    music_directories: &'a [Utf8PathBuf],
+    folder: Option<&'a FolderListing>,
+) -> Column<'a, Message> {
+    let enqueue_button = |path: &Utf8Path| {
+        button("Add to queue")
+            .on_press(Message::EnqueueFolderClicked(path.to_owned()))
+            .style(no_background())
+    };
+    let folder_row = |path: &'a Utf8PathBuf, name: &'a str| -> Element<'a, Message> {
+        row![
+            button(text(name))
+                .on_press(Message::FolderClicked(path.clone()))
+                .style(no_background())
+                .width(Length::Fill),
+            enqueue_button(path),
+        ]
+        .align_items(Alignment::Center)
+        .spacing(10)
+        .into()
+    };
+
+    let Some(folder) = folder else {
+        let rows: Vec<_> = music_directories
+            .iter()
+            .map(|directory| folder_row(directory, directory.as_str()))
+            .collect();
+
+        return column![
+            text("Music folders"),
+            Column::with_children(rows).spacing(5)
+        ]
+        .spacing(10)
+        .width(Length::Fill);
+    };
+
+    let folder_rows = folder
+        .folders
+        .iter()
+        .map(|path| folder_row(path, path.file_name().unwrap_or(path.as_str())));
+    let song_rows = folder.songs.iter().map(|path| {
+        row![
+            text(path.file_name().unwrap_or(path.as_str())).width(Length::Fill),
+            enqueue_button(path),
+        ]
+        .align_items(Alignment::Center)
+        .spacing(10)
+        .into()
+    });
+    let rows: Vec<_> = folder_rows.chain(song_rows).collect();
+
+    let header = row![
+        button("Up")
+            .on_press(Message::FolderUpClicked)
+            .style(no_background()),
+        text(&folder.path).width(Length::Fill),
+        enqueue_button(&folder.path),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    let contents: Element<'_, Message> = if rows.is_empty() {
+        text("There are no folders or songs here.").into()
+    } else {
+        Column::with_children(rows).spacing(5).into()
+    };
+
+    column![header, contents].spacing(10).width(Length::Fill)
+}
+
+/// The listening total, top lists side by side, and the listening clock
+fn view_stats<'a>(
+    music: &'a MusicCache,
+    stats: &'a ListeningStats,
+) -> Column<'a, Message> {
+    let period_buttons: Vec<_> = StatsPeriod::ALL
+        .iter()
+        .map(|&period| {
+            let style = if period == stats.period {
+                theme::Button::Primary
+            } else {
+                no_background()
+            };
+
+            button(text(period))
+                .on_press(Message::StatsPeriodClicked(period))
+                .style(style)
+                .into()
+        })
+        .collect();
+
+    let top_list = |title, rows: Vec<(String, f64)>| {
+        let rows: Vec<_> = rows
+            .into_iter()
+            .map(|(name, seconds)| {
+                row![
+                    text(name).width(Length::Fill),
+                    text(format_listening(seconds))
+                ]
+                .spacing(10)
+                .into()
+            })
+            .collect();
+
+        column![text(title), Column::with_children(rows).spacing(5)]
+            .spacing(10)
+            .width(Length::FillPortion(1))
+    };
+
+    // NOTE the top lists skip songs and albums removed since they were played
+    let songs = stats
+        .top_songs
+        .iter()
+        .filter_map(|(song_id, seconds)| {
+            let title = music.get_song(song_id)?.display_title()?;
+            Some((title.to_string(), *seconds))
+        })
+        .collect();
+    let albums = stats
+        .top_albums
+        .iter()
+        .filter_map(|(album_id, seconds)| {
+            let title = music.get_album(album_id)?.display_title()?;
+            Some((title.to_string(), *seconds))
+        })
+        .collect();
+
+    column![
+        Row::with_children(period_buttons).spacing(10),
+        text(format!(
+            "{} listened to in the {}",
+            format_listening(stats.total_seconds),
+            stats.period.to_string().to_lowercase()
+        ))
+        .size(24),
+        row![
+            top_list("Top songs", songs),
+            top_list("Top albums", albums),
+            top_list("Top artists", stats.top_artists.clone()),
+        ]
+        .spacing(20),
+        text("When you listen"),
+        canvas(ListeningClockMap { clock: &stats.clock })
+            .width(Length::Fill)
+            .height(Length::Fixed(LISTENING_CLOCK_HEIGHT)),
+    ]
+    .spacing(20)
+    .width(Length::Fill)
+}
+
+/// The current song's lyrics, with the line being sung highlighted when they're synced
+fn view_lyrics<'a>(
+    current_song: &Option<CurrentSong>,
+    progress: &Option<ProgressDisplay>,
+    lyrics: &'a Option<Lyrics>,
+) -> Column<'a, Message> {
+    let Some(current_song) = current_song else {
+        return column![text("Nothing is playing.")];
+    };
+    let title = text(&current_song.title).size(24);
+
+    let lines: Vec<Element<'_, Message>> = match lyrics {
+        None => vec![text("There are no lyrics for this song.").into()],
+        Some(Lyrics::Plain(lines)) => {
+            lines.iter().map(|line| text(line).into()).collect()
+        }
+        Some(synced @ Lyrics::Synced(lines)) => {
+            let elapsed = match progress {
+                Some(ProgressDisplay::Dragging(proportion)) => {
+                    f64::from(*proportion) * current_song.total_seconds as f64
+                }
+                Some(ProgressDisplay::FromAudio(times)) => {
+                    times.elapsed.seconds as f64 + times.elapsed.frac
+                }
+                None => 0.0,
+            };
+            let current_line = synced.current_line(elapsed);
+
+            lines
+                .iter()
+                .enumerate()
+                .map(|(index, line)| {
+                    let style = if current_line == Some(index) {
+                        selected_tint()
+                    } else {
+                        theme::Container::Transparent
+                    };
+
+                    container(text(&line.text))
+                        .padding([0, 5])
+                        .style(style)
+                        .into()
+                })
+                .collect()
+        }
+    };
+
+    column![title, Column::with_children(lines).spacing(5)]
+        .spacing(10)
+        .width(Length::Fill)
+}
+
+/// Every song in the queue, with the current one highlighted
+fn view_queue<'a>(
+    queue: Option<&'a QueueDisplay>,
+    current_song: &Option<CurrentSong>,
+    hovered_index: Option<usize>,
+    dragged_index: Option<usize>,
+) -> Column<'a, Message> {
+    let Some(queue) = queue else {
+        return column![text("Nothing is queued.")];
+    };
+    let playing = current_song.as_ref().is_some_and(|song| song.playing);
+
+    let rows: Vec<_> = queue
+        .songs
+        .iter()
+        .enumerate()
+        .map(|(index, song)| {
+            let is_current = index == queue.current_index;
+
+            let play_button = match (is_current, playing) {
+                (true, true) => button(icons::pause().style(accent_icon()))
+                    .on_press(Message::PauseClicked),
+                (true, false) => button(icons::play().style(accent_icon()))
+                    .on_press(Message::PlayPausedClicked),
+                (false, _) => button(icons::play().style(accent_icon()))
+                    .on_press(Message::QueueSongClicked(index)),
+            };
+
+            // NOTE the current song can't be removed; skip past it instead
+            let remove_button: Element<'_, Message> = if is_current {
+                Space::new(Length::Shrink, Length::Shrink).into()
+            } else {
+                button("Remove")
+                    .on_press(Message::RemoveFromQueueClicked(index))
+                    .style(no_background())
+                    .into()
+            };
+
+            let title = song
+                .title
+                .as_deref()
+                .unwrap_or_else(|| song.path.file_name().unwrap_or_default());
+            let duration = song
+                .duration
+                .map(|duration| format_seconds(duration.as_secs_f64()))
+                .unwrap_or_default();
+
+            let row = row![
+                play_button.style(no_background()),
+                text(title).width(Length::FillPortion(2)),
+                text(song.artist.as_deref().unwrap_or_default())
+                    .width(Length::FillPortion(1)),
+                text(song.album_title.as_deref().unwrap_or_default())
+                    .width(Length::FillPortion(1)),
+                text(duration),
+                remove_button,
+                horizontal_space(Length::Fixed(10f32))
+            ]
+            .align_items(Alignment::Center)
+            .spacing(10);
+
+            let is_drop_target = dragged_index.is_some_and(|dragged| dragged != index)
+                && hovered_index == Some(index)
+                && index > queue.current_index;
+            let style = if is_current || is_drop_target {
+                selected_tint()
+            } else if hovered_index == Some(index) {
+                hover_tint()
+            } else {
+                theme::Container::Transparent
+            };
+
+            Hoverable::new(
+                container(row).style(style).width(Length::Fill).into(),
+                Message::QueueRowHovered(index),
+                Message::QueueRowUnhovered(index),
+            )
+            .into()
+        })
+        .collect();
+
+    column![
+        text("Drag upcoming songs to reorder them."),
+        Column::with_children(rows).spacing(5)
+    ]
+    .spacing(10)
+    .width(Length::Fill)
+}
+
+/// Whether a position in the queue's play order is after the current song
+fn is_upcoming(ui: &Ui, index: usize) -> bool {
+    ui.queue
+        .as_ref()
+        .is_some_and(|queue| index > queue.current_index && index < queue.songs.len())
+}
+
+/// The playlist files from the music directories, the saved smart playlists,
+/// and a rule builder for a new one
+fn view_playlists<'a>(
+    playlists: &'a [Playlist],
+    smart_playlists: &'a [SmartPlaylist],
+    draft: &'a SmartPlaylistDraft,
+    export_destination: &'a str,
+) -> Column<'a, Message> {
+    // disabled until there's somewhere to export to
+    let export_button = |name: &str, songs: PlaylistSongs| {
+        let mut export_button = button("Export").style(no_background());
+        if !export_destination.trim().is_empty() {
+            export_button = export_button
+                .on_press(Message::ExportPlaylistClicked(name.to_string(), songs));
+        }
+
+        export_button
+    };
+
+    let file_rows: Vec<_> = playlists
+        .iter()
+        .map(|playlist| {
+            row![
+                button(icons::play().style(accent_icon()))
+                    .on_press(Message::PlayPlaylistClicked(playlist.id))
+                    .style(no_background()),
+                text(&playlist.name).width(Length::FillPortion(1)),
+                text(format!("{} songs", playlist.songs.len()))
+                    .width(Length::FillPortion(2)),
+                export_button(
+                    &playlist.name,
+                    PlaylistSongs::Listed(playlist.songs.clone())
+                ),
+            ]
+            .align_items(Alignment::Center)
+            .spacing(10)
+            .into()
+        })
+        .collect();
+    let file_playlists: Element<'_, Message> = if file_rows.is_empty() {
+        text("Playlist files (.m3u, .m3u8, or .pls) in the music folders show up here.")
+            .into()
+    } else {
+        Column::with_children(file_rows).spacing(5).into()
+    };
+
+    let on_this_day = row![
+        button(icons::play().style(accent_icon()))
+            .on_press(Message::PlayOnThisDayClicked)
+            .style(no_background()),
+        text("On this day").width(Length::FillPortion(1)),
+        text("played most on today's date in previous years")
+            .width(Length::FillPortion(2)),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10)
+    .into();
+    let playlist_rows: Vec<_> = std::iter::once(on_this_day)
+        .chain(smart_playlists.iter().map(|playlist| {
+            let rules: Vec<String> = playlist.rules.iter().map(describe_rule).collect();
+
+            row![
+                button(icons::play().style(accent_icon()))
+                    .on_press(Message::PlaySmartPlaylistClicked(playlist.id))
+                    .style(no_background()),
+                text(&playlist.name).width(Length::FillPortion(1)),
+                text(rules.join(" and ")).width(Length::FillPortion(2)),
+                export_button(
+                    &playlist.name,
+                    PlaylistSongs::Matching(playlist.rules.clone())
+                ),
+                button("Delete")
+                    .on_press(Message::DeleteSmartPlaylistClicked(playlist.id))
+                    .style(no_background()),
+            ]
+            .align_items(Alignment::Center)
+            .spacing(10)
+            .into()
+        }))
+        .collect();
+
+    let rule_rows: Vec<_> = draft
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            let kind_picker =
+                pick_list(&RuleKind::ALL[..], Some(rule.kind), move |kind| {
+                    Message::RuleKindSelected(index, kind)
+                });
+            let value: Element<'_, Message> = if rule.kind.takes_value() {
+                text_input("", &rule.value)
+                    .on_input(move |value| Message::RuleValueChanged(index, value))
+                    .width(Length::Fixed(200.0))
+                    .into()
+            } else {
+                Space::new(Length::Fixed(200.0), Length::Shrink).into()
+            };
+
+            row![
+                kind_picker,
+                value,
+                button("Remove")
+                    .on_press(Message::RemoveRuleClicked(index))
+                    .style(no_background()),
+            ]
+            .align_items(Alignment::Center)
+            .spacing(10)
+            .into()
+        })
+        .collect();
+
+    // disabled until every rule is valid
+    let mut save_button = button("Save");
+    if draft.to_rules().is_some() {
+        save_button = save_button.on_press(Message::SaveSmartPlaylistClicked);
+    }
+
+    let new_playlist = column![
+        text("New smart playlist"),
+        text_input("Name", &draft.name)
+            .on_input(Message::SmartPlaylistNameChanged)
+            .width(Length::Fixed(300.0)),
+        text("Songs matching all of:"),
+        Column::with_children(rule_rows).spacing(5),
+        row![
+            button("Add rule")
+                .on_press(Message::AddRuleClicked)
+                .style(no_background()),
+            save_button,
+        ]
+        .spacing(10),
+    ]
+    .spacing(10);
+
+    let export_to = row![
+        text("Export playlists to"),
+        text_input("Folder", export_destination)
+            .on_input(Message::PlaylistExportDestinationChanged)
+            .width(Length::Fixed(300.0)),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    column![
+        text("Playlists"),
+        file_playlists,
+        text("Smart playlists"),
+        Column::with_children(playlist_rows).spacing(5),
+        export_to,
+        new_playlist,
+    ]
+    .spacing(20)
+    .width(Length::Fill)
+}
+
+fn view_sessions<'a>(
+    sessions: &'a [Session],
+    name_draft: &'a str,
+) -> Column<'a, Message> {
+    let session_row = |session: Option<&'a Session>, active| -> Element<'a, Message> {
+        let name = session.map(|s| s.name.as_str()).unwrap_or("Default");
+        let session_id = session.map(|s| s.id);
+
+        let transition: Element<'a, Message> = match session {
+            Some(session) => {
+                let selected = TransitionKind::from_saved(&session.transition);
+                pick_list(&TransitionKind::ALL[..], selected, |transition| {
+                    Message::SessionTransitionSelected(session.id, transition)
+                })
+                .into()
+            }
+            None => text(TransitionKind::Cut).into(),
+        };
+
+        let mut session_row = row![text(name).width(Length::Fill), transition]
+            .align_items(Alignment::Center)
+            .spacing(10);
+
+        if active {
+            session_row = session_row.push(text("Current"));
+        } else {
+            session_row = session_row.push(
+                button("Switch").on_press(Message::SwitchSessionClicked(session_id)),
+            );
+        }
+
+        // the default session can't be deleted, and the current one is playing
+        if let (Some(session_id), false) = (session_id, active) {
+            session_row = session_row.push(
+                button("Delete")
+                    .on_press(Message::DeleteSessionClicked(session_id))
+                    .style(no_background()),
+            );
+        }
+
+        session_row.into()
+    };
+
+    let default_active = !sessions.iter().any(|s| s.active);
+    let mut session_rows = vec![session_row(None, default_active)];
+    session_rows.extend(
+        sessions
+            .iter()
+            .map(|session| session_row(Some(session), session.active)),
+    );
+
+    let new_session = row![
+        text_input("Name", name_draft)
+            .on_input(Message::SessionNameChanged)
+            .on_submit(Message::CreateSessionClicked)
+            .width(Length::Fixed(300.0)),
+        button("Add").on_press(Message::CreateSessionClicked),
+    ]
+    .spacing(10);
+
+    column![
+        text("Sessions"),
+        text("Each session keeps its own queue, position, and transition between songs."),
+        Column::with_children(session_rows).spacing(5),
+        text("New session"),
+        new_session,
+    ]
+    .spacing(20)
+    .width(Length::Fill)
+}
+
+/// The furthest the replaygain pre-amp goes either way, in dB
+const MAX_PREAMP: f32 = 12.0;
+
+fn view_settings<'a>(
+    settings: &'a SettingsFile,
+    directory_draft: &'a str,
+    sort_names: Vec<SortName>,
+    sort_name_draft: &'a SortNameDraft,
+    conversions: &ConversionStats,
+    keymap: &Keymap,
+    tray_available: bool,
+) -> Column<'a, Message> {
+    // there's always at least one directory to crawl
+    let removable = settings.music_directories.len() > 1;
+    let directory_rows = settings
+        .music_directories
+        .iter()
+        .enumerate()
+        .map(|(index, directory)| {
+            let mut remove_button = button("Remove").style(no_background());
+            if removable {
+                remove_button =
+                    remove_button.on_press(Message::RemoveMusicDirectoryClicked(index));
+            }
+
+            row![text(directory).width(Length::Fill), remove_button]
+                .align_items(Alignment::Center)
+                .spacing(10)
+                .into()
+        })
+        .collect();
+
+    let new_directory = row![
+        text_input("Path", directory_draft)
+            .on_input(Message::MusicDirectoryChanged)
+            .on_submit(Message::AddMusicDirectoryClicked)
+            .width(Length::Fixed(300.0)),
+        button("Add").on_press(Message::AddMusicDirectoryClicked),
+    ]
+    .spacing(10);
+
+    let replay_gain = settings.replay_gain();
+    let replay_gain_mode = row![
+        text("ReplayGain").width(Length::Fixed(150.0)),
+        pick_list(
+            &ReplayGainMode::ALL[..],
+            Some(replay_gain.mode),
+            Message::ReplayGainModeSelected
+        ),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    let shuffle_order = row![
+        text("Shuffle order").width(Length::Fixed(150.0)),
+        pick_list(
+            &ShuffleOrder::ALL[..],
+            Some(settings.shuffle_order.unwrap_or_default()),
+            Message::ShuffleOrderSelected
+        ),
+        text("By tempo and key uses the songs' bpm and key tags, where they have them."),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    let resample_quality = row![
+        text("Resampling").width(Length::Fixed(150.0)),
+        pick_list(
+            &ResampleQuality::ALL[..],
+            Some(settings.resample_quality),
+            Message::ResampleQualitySelected
+        ),
+        text(
+            "Higher quality filters more cleanly, for more CPU and a longer buffer; \
+             fast suits older laptops. Changing it restarts the output."
+        ),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    let visualizer = row![
+        text("Visualizer").width(Length::Fixed(150.0)),
+        pick_list(
+            &VisualizerStyle::ALL[..],
+            Some(settings.visualizer),
+            Message::VisualizerSelected
+        ),
+        text("Shown above the playback controls while music plays."),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    // NOTE a hex accent from clef.toml isn't one of the choices
+    let selected_accent = match settings.palette.accent {
+        Some(ThemeColor::Named(accent)) => Some(accent),
+        Some(ThemeColor::Hex(_)) => None,
+        None => Some(Accent::Blue),
+    };
+    let accent = row![
+        text("Accent color").width(Length::Fixed(150.0)),
+        pick_list(&Accent::ALL[..], selected_accent, Message::AccentSelected),
+        text(
+            "Any '#rrggbb' works too, as palette.accent in clef.toml, \
+              along with palette.background and palette.text."
+        ),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    let preamp = row![
+        text("Pre-amp").width(Length::Fixed(150.0)),
+        slider(
+            -MAX_PREAMP..=MAX_PREAMP,
+            replay_gain.preamp_db,
+            Message::PreampChanged
+        )
+        .step(0.5)
+        .on_release(Message::PreampReleased)
+        .width(Length::Fixed(300.0)),
+        text(format!("{:+.1} dB", replay_gain.preamp_db)),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    let sort_name_rows = sort_names
+        .into_iter()
+        .map(|SortName { kind, name, sort_name }| {
+            row![
+                text(kind).width(Length::Fixed(150.0)),
+                text(&name).width(Length::Fill),
+                text(sort_name).width(Length::Fill),
+                button("Remove")
+                    .on_press(Message::RemoveSortNameClicked(kind, name))
+                    .style(no_background()),
+            ]
+            .align_items(Alignment::Center)
+            .spacing(10)
+            .into()
+        })
+        .collect();
+
+    let new_sort_name = row![
+        pick_list(
+            &SortKind::ALL[..],
+            Some(sort_name_draft.kind),
+            Message::SortKindSelected
+        )
+        .width(Length::Fixed(150.0)),
+        text_input("Name", &sort_name_draft.name)
+            .on_input(Message::SortNameChanged)
+            .width(Length::Fixed(200.0)),
+        text_input("Sort as", &sort_name_draft.sort_name)
+            .on_input(Message::SortAsChanged)
+            .on_submit(Message::AddSortNameClicked)
+            .width(Length::Fixed(200.0)),
+        button("Add").on_press(Message::AddSortNameClicked),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    let conversion_rows = format_conversions(conversions)
+        .into_iter()
+        .map(|line| text(line).into())
+        .collect();
+
+    let key_rows = keymap
+        .bindings()
+        .map(|(action, binding)| {
+            let binding = binding.map(|b| b.to_string());
+            row![
+                text(action.description()).width(Length::Fixed(250.0)),
+                text(binding.as_deref().unwrap_or("Unbound")).width(Length::Fixed(150.0)),
+                text(action.name()),
+            ]
+            .spacing(10)
+            .into()
+        })
+        .collect();
+
+    let mut playback = column![
+        replay_gain_mode,
+        preamp,
+        shuffle_order,
+        resample_quality,
+        visualizer
+    ]
+    .spacing(20);
+    // NOTE only linux has a tray icon, and only with a desktop that shows them
+    if tray_available {
+        playback = playback.push(checkbox(
+            "Close to the tray icon, keeping music playing",
+            settings.close_to_tray,
+            Message::CloseToTrayToggled,
+        ));
+    }
+
+    column![
+        text("Settings"),
+        text("Music directories"),
+        text("Each one holds a folder per album. Changing them rescans the library."),
+        Column::with_children(directory_rows).spacing(5),
+        new_directory,
+        text("Playback"),
+        playback,
+        text("Appearance"),
+        accent,
+        text("Library"),
+        checkbox(
+            "Show a bar behind each duration in track lists, scaled to the song's length",
+            settings.duration_bars,
+            Message::DurationBarsToggled
+        ),
+        checkbox(
+            "Copy folders dropped on the window into the first music directory",
+            settings.import_dropped,
+            Message::ImportDroppedToggled
+        ),
+        checkbox(
+            "Look up missing album art on MusicBrainz during scans, saving it as cover.jpg",
+            settings.fetch_missing_art,
+            Message::FetchMissingArtToggled
+        ),
+        text("Conversions since launch"),
+        text(
+            "Songs that don't match the device's sample rate are resampled, \
+             using the quality chosen above; \
+             exclusive mode or a different device default can avoid it."
+        ),
+        Column::with_children(conversion_rows).spacing(5),
+        text("Keyboard shortcuts"),
+        text("Change these in the [keys] table of clef.toml, ie mute = \"ctrl+m\"."),
+        Column::with_children(key_rows).spacing(5),
+        text("Sort names"),
+        text("Sorts an artist or album title as tagged under another name, ie The Beatles as Beatles."),
+        Column::with_children(sort_name_rows).spacing(5),
+        new_sort_name,
+    ]
+    .spacing(20)
+    .width(Length::Fill)
+}
+
+fn view_webhook_settings<'a>(
+    url_draft: &'a str,
+    saved_url: Option<&str>,
+    test: &Option<Result<(), String>>,
+) -> Column<'a, Message> {
+    let mut save_button = button("Save");
+    if saved_url.unwrap_or_default() != url_draft.trim() {
+        save_button = save_button.on_press(Message::SaveWebhookClicked);
+    }
+
+    let url_row = row![
+        text_input("http://...", url_draft)
+            .on_input(Message::WebhookUrlChanged)
+            .on_submit(Message::SaveWebhookClicked)
+            .width(Length::Fixed(300.0)),
+        save_button,
+        button("Send test")
+            .on_press(Message::TestWebhookClicked)
+            .style(no_background()),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10);
+
+    let status = match test {
+        Some(Ok(())) => "The test was sent.".to_string(),
+        Some(Err(e)) => format!("The test failed: {e}"),
+        None => String::new(),
+    };
+
+    column![
+        text("Webhook"),
+        text(
+            "Posts now playing, paused and stopped events as json to this url, \
+             ie for home automation. Failed posts are retried twice."
+        ),
+        url_row,
+        text(status),
+    ]
+    .spacing(20)
+}
+
+fn view_listening_day_row(day: &ListeningDay) -> Element<'_, Message> {
+    let plays = match day.plays {
+        1 => "1 play".to_string(),
+        plays => format!("{plays} plays"),
+    };
+
+    row![
+        text(&day.day),
+        text(plays),
+        button("Rebuild")
+            .on_press(Message::RebuildSessionClicked(day.day.clone()))
+            .style(no_background()),
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10)
+    .into()
+}
+
+fn view_history_row(song: &Song, plays: Option<i64>) -> Element<'_, Message> {
+    let plays = match plays {
+        Some(1) => "1 play".to_string(),
+        Some(plays) => format!("{plays} plays"),
+        None => String::new(),
+    };
+
+    row![
+        button(icons::play().style(accent_icon()))
+            .on_press(Message::PlaySongClicked(song.id))
+            .style(no_background()),
+        text(song.display_title().unwrap_or_default()).width(Length::Fill),
+        text(song.artist.as_deref().unwrap_or_default()),
+        text(plays),
+        horizontal_space(Length::Fixed(10f32))
+    ]
+    .align_items(Alignment::Center)
+    .spacing(10)
+    .into()
+}
+
+fn view_album<'a>(
+    album: &'a CachedAlbum,
+    hovered_song_id: Option<SongId>,
+    context_menu: Option<MenuTarget>,
+    current_song: &'a Option<CurrentSong>,
+    duration_bars: bool,
+) -> Element<'a, Message> {
+    // NOTE the placeholder stands in for the art until it's resized
+    let art_luminance = match (&album.art, album.placeholder_color) {
+        (Some(art), _) => Some(art.caption_luminance()),
+        (None, Some(placeholder_color)) => Some(relative_luminance(placeholder_color)),
+        (None, None) => None,
+    };
+    let album_image = button(Layered::new(
+        view_album_image(album.art.as_ref(), album.placeholder_color),
+        view_art_caption(
+            album.album.display_title().unwrap_or_default(),
+            art_luminance,
+        ),
+    ))
+    .on_press(Message::LibraryViewClicked(LibraryView::Album(
+        album.album.id,
+    )))
+    .padding(0)
+    .style(no_background());
+
+    let mut album_info = column![
+        text(album.album.artist.as_deref().unwrap_or_default()),
+        text(album.album.release_date.as_deref().unwrap_or_default()),
+        text(album_runtime(album)),
+    ]
+    .width(Length::FillPortion(1));
+    if let Some(missing) = view_missing_tracks(album) {
+        album_info = album_info.push(missing);
+    }
+
+    let song_rows: Vec<_> = album
+        .songs
+        .iter()
+        .map(|song| {
+            let status = song_row_status(current_song, hovered_song_id, song.id);
+            let hovered = hovered_song_id == Some(song.id);
+            let menu_open = context_menu == Some(MenuTarget::Song(song.id));
+            let album_artist = album.album.artist.as_deref();
+            view_song_row(
+                song,
+                album_artist,
+                status,
+                hovered,
+                menu_open,
+                duration_bars,
+            )
+        })
+        .collect();
+    let songs_list = Column::with_children(song_rows).width(Length::FillPortion(2));
+
+    let row = row![album_image, album_info, songs_list].spacing(10);
+
+    let album_id = album.album.id;
+    let menu = (context_menu == Some(MenuTarget::Album(album_id))).then(|| {
+        let mut items = Vec::new();
+        if let Some(first_song) = album.songs.first() {
+            items.push(("Play", Message::PlaySongClicked(first_song.id)));
+        }
+        items.extend([
+            ("Add to queue", Message::AddAlbumToQueueClicked(album_id)),
+            ("Rename", Message::RenameAlbumClicked(album_id)),
+            (
+                "Fill in from MusicBrainz",
+                Message::FillFromMusicBrainzClicked(album_id),
+            ),
+            (
+                "Show in file manager",
+                Message::ShowInFileManagerClicked(album.album.directory.clone()),
+            ),
+        ]);
+
+        view_menu(items)
+    });
+
+    ContextMenu::new(
+        row,
+        menu,
+        Message::MenuOpened(MenuTarget::Album(album_id)),
+        Message::MenuClosed,
+    )
+    .into()
+}
+
+/// A right-click menu's items, as a column of text buttons
+fn view_menu<'a>(items: Vec<(&'a str, Message)>) -> Element<'a, Message> {
+    let buttons: Vec<_> = items
+        .into_iter()
+        .map(|(label, message)| {
+            button(text(label))
+                .on_press(message)
+                .width(Length::Fill)
+                .style(no_background())
+                .into()
+        })
+        .collect();
+
+    container(Column::with_children(buttons))
+        .width(Length::Fixed(MENU_WIDTH))
+        .padding(4)
+        .style(menu_panel())
+        .into()
+}
+
+/// ie '12 tracks · 47 min', from the length cached during the crawl
+fn album_runtime(album: &CachedAlbum) -> String {
+    let tracks = match album.songs.len() {
+        1 => "1 track".to_string(),
+        n => format!("{n} tracks"),
+    };
+
+    let minutes = (album.album.total_seconds as f64 / 60.0).round() as i64;
+    let length = if minutes < 60 {
+        format!("{minutes} min")
+    } else {
+        format!("{} hr {} min", minutes / 60, minutes % 60)
+    };
+
+    format!("{tracks} · {length}")
+}
+
+/// Flags an incomplete rip, going by the track total tags
+fn view_missing_tracks(album: &CachedAlbum) -> Option<Element<'_, Message>> {
+    let (present, expected) = album.missing_tracks()?;
+
+    Some(text(format!("{present} of {expected} tracks")).into())
+}
+
+/// The full-size art, album details, and every track
+fn view_album_page<'a>(
+    album: &'a CachedAlbum,
+    hovered_song_id: Option<SongId>,
+    context_menu: Option<MenuTarget>,
+    current_song: &'a Option<CurrentSong>,
+    duration_bars: bool,
+    rename_draft: Option<&'a AlbumRenameDraft>,
+    actions: Element<'a, Message>,
+) -> Column<'a, Message> {
+    let back_button = button("Back")
+        .on_press(Message::LibraryViewClicked(LibraryView::Albums))
+        .style(no_background());
+
+    // NOTE the cached art is only thumbnail-sized
+    let album_art: Element<'_, Message> = match &album.album.original_art {
+        Some(original_art) => Image::new(image::Handle::from_path(original_art))
+            .width(Length::Fill)
+            .content_fit(ContentFit::Contain)
+            .into(),
+        None => view_album_image(album.art.as_ref(), album.placeholder_color),
+    };
+
+    let total_seconds: i64 = album.songs.iter().map(|song| song.total_seconds).sum();
+    let song_count = match album.songs.len() {
+        1 => "1 song".to_string(),
+        n => format!("{n} songs"),
+    };
+    let runtime = format!("{song_count}, {}", format_seconds(total_seconds as f64));
+
+    let names = match rename_draft {
+        Some(draft) if draft.album_id == album.album.id => {
+            view_album_rename(draft, album.album.is_renamed())
+        }
+        _ => view_album_names(&album.album),
+    };
+    let mut album_info = column![
+        names,
+        text(album.album.release_date.as_deref().unwrap_or_default()),
+        text(runtime),
+    ]
+    .spacing(10)
+    .width(Length::FillPortion(1));
+    if let Some(missing) = view_missing_tracks(album) {
+        album_info = album_info.push(missing);
+    }
+    if let Some(first_song) = album.songs.first() {
+        album_info = album_info.push(
+            button(icons::play().style(accent_icon()))
+                .on_press(Message::PlaySongClicked(first_song.id))
+                .style(no_background()),
+        );
+    }
+    album_info = album_info.push(actions);
+
+    let song_rows: Vec<_> = album
+        .songs
+        .iter()
+        .map(|song| {
+            let status = song_row_status(current_song, hovered_song_id, song.id);
+            let hovered = hovered_song_id == Some(song.id);
+            let menu_open = context_menu == Some(MenuTarget::Song(song.id));
+            let album_artist = album.album.artist.as_deref();
+            view_song_row(
+                song,
+                album_artist,
+                status,
+                hovered,
+                menu_open,
+                duration_bars,
+            )
+        })
+        .collect();
+
+    column![
+        back_button,
+        row![
+            container(album_art).width(Length::FillPortion(1)),
+            album_info
+        ]
+        .spacing(20),
+        Column::with_children(song_rows),
+    ]
+    .spacing(20)
+    .width(Length::Fill)
+}
+
+fn view_album_names(album: &Album) -> Element<'_, Message> {
+    let title = row![
+        text(album.display_title().unwrap_or_default()).size(30),
+        button(icons::edit())
+            .on_press(Message::RenameAlbumClicked(album.id))
+            .style(no_background()),
+    ]
+    .spacing(10)
+    .align_items(Alignment::Center);
+
+    column![title, text(album.artist.as_deref().unwrap_or_default())]
+        .spacing(10)
+        .into()
+}
+
+fn view_album_rename(draft: &AlbumRenameDraft, renamed: bool) -> Element<'_, Message> {
+    let mut buttons = row![
+        button("Save").on_press(Message::SaveAlbumRenameClicked),
+        button("Cancel")
+            .on_press(Message::CancelAlbumRenameClicked)
+            .style(no_background()),
+    ]
+    .spacing(10);
+    if renamed {
+        buttons = buttons.push(
+            button("Use tags")
+                .on_press(Message::ResetAlbumRenameClicked)
+                .style(no_background()),
+        );
+    }
+
+    column![
+        text_input("Title", &draft.title)
+            .on_input(Message::AlbumTitleDraftChanged)
+            .on_submit(Message::SaveAlbumRenameClicked)
+            .size(30),
+        text_input("Artist", &draft.artist)
+            .on_input(Message::AlbumArtistDraftChanged)
+            .on_submit(Message::SaveAlbumRenameClicked),
+        text("Blank fields use the tags. The files aren't changed."),
+        buttons,
+    ]
+    .spacing(10)
+    .into()
+}
+
+fn view_album_notes<'a>(
+    album: &'a Album,
+    draft: Option<&'a AlbumNotesDraft>,
+) -> Element<'a, Message> {
+    if let Some(draft) = draft.filter(|draft| draft.album_id == album.id) {
+        return column![
+            text_input("Notes", &draft.notes)
+                .on_input(Message::AlbumNotesDraftChanged)
+                .on_submit(Message::SaveAlbumNotesClicked),
+            row![
+                button("Save").on_press(Message::SaveAlbumNotesClicked),
+                button("Cancel")
+                    .on_press(Message::CancelAlbumNotesClicked)
+                    .style(no_background()),
+            ]
+            .spacing(10),
+        ]
+        .spacing(10)
+        .into();
+    }
+
+    let edit_label = if album.notes.is_some() {
+        "Edit notes"
+    } else {
+        "Add notes"
+    };
+    let edit_button = button(edit_label)
+        .on_press(Message::EditAlbumNotesClicked(album.id))
+        .style(no_background());
+    match &album.notes {
+        Some(notes) => column![text(notes), edit_button].spacing(10).into(),
+        None => edit_button.into(),
+    }
+}
+
+/// Disabled while any album is being looked up
+fn view_musicbrainz_button<'a>(
+    album_id: AlbumId,
+    filling: Option<AlbumId>,
+) -> Element<'a, Message> {
+    let label = if filling == Some(album_id) {
+        "Looking up on MusicBrainz…"
+    } else {
+        "Fill in missing tags from MusicBrainz"
+    };
+
+    let mut fill_button = button(label).style(no_background());
+    if filling.is_none() {
+        fill_button = fill_button.on_press(Message::FillFromMusicBrainzClicked(album_id));
+    }
+
+    fill_button.into()
+}
+
+/// The export button, its form, or the running export's progress
+fn view_album_export<'a>(
+    album_id: AlbumId,
+    draft: Option<&'a AlbumExportDraft>,
+    export: Option<&'a AlbumExport>,
+) -> Element<'a, Message> {
+    if let Some(draft) = draft.filter(|draft| draft.album_id == album_id) {
+        let mut start_button = button("Export");
+        if export.is_none() && !draft.destination.trim().is_empty() {
+            start_button = start_button.on_press(Message::StartExportClicked);
+        }
+
+        return column![
+            row![
+                text_input("Destination folder", &draft.destination)
+                    .on_input(Message::ExportDestinationChanged)
+                    .on_submit(Message::StartExportClicked),
+                pick_list(
+                    &ExportFormat::ALL[..],
+                    Some(draft.format),
+                    Message::ExportFormatSelected
+                ),
+            ]
+            .spacing(10),
+            row![
+                start_button,
+                button("Cancel")
+                    .on_press(Message::CancelExportDraftClicked)
+                    .style(no_background()),
+            ]
+            .spacing(10),
+        ]
+        .spacing(10)
+        .into();
+    }
+
+    match export {
+        Some(export) if export.album_id == album_id => {
+            let (written, total) = export.progress;
+            column![
+                text(format!("Exporting: {written} of {total} files")),
+                progress_bar(0.0..=total.max(1) as f32, written as f32).height(10),
+            ]
+            .spacing(5)
+            .into()
+        }
+        _ => {
+            let mut export_button = button("Export…").style(no_background());
+            if export.is_none() {
+                export_button =
+                    export_button.on_press(Message::ExportAlbumClicked(album_id));
+            }
+            export_button.into()
+        }
+    }
+}
+
+fn view_album_image(
+    image_bytes: Option<&RgbaBytes>,
+    placeholder_color: Option<Color>,
+) -> Element<'_, Message> {
+    let length = Length::Fixed(IMAGE_SIZE as f32);
+
+    let Some(image_bytes) = image_bytes else {
+        return match placeholder_color {
+            Some(color) => container(Space::new(length, length))
+                .style(solid_color(color))
+                .into(),
+            None => Space::new(length, length).into(),
+        };
+    };
+
+    Image::new(image_bytes)
+        .width(length)
+        .height(length)
+        .content_fit(ContentFit::ScaleDown)
+        .into()
+}
+
+/// A title to draw over album art, with a scrim chosen to stay readable on it;
+/// without art there's nothing to contrast with, so it uses the theme's text
+fn view_art_caption(title: &str, art_luminance: Option<f32>) -> Element<'_, Message> {
+    let Some(art_luminance) = art_luminance else {
+        return container(text(title)).width(Length::Fill).padding(8).into();
+    };
+
+    let colors = CaptionColors::for_luminance(art_luminance);
+    container(text(title).style(colors.text))
+        .width(Length::Fill)
+        .padding(8)
+        .style(solid_color(colors.scrim))
+        .into()
+}
+
+/// A song's length, optionally over a bar scaled to it,
+/// so that interludes and long songs stand out in a track list
+fn view_duration<'a>(total_seconds: i64, show_bar: bool) -> Element<'a, Message> {
+    let duration = format_seconds(total_seconds as f64);
+    if !show_bar {
+        return text(duration).into();
+    }
+
+    let bar_width = DURATION_BAR_WIDTH * duration_bar_fraction(total_seconds);
+    let bar = row![container(Space::new(
+        Length::Fixed(bar_width),
+        Length::Fixed(DURATION_BAR_HEIGHT)
+    ))
+    .style(solid_color(DURATION_BAR_COLOR))]
+    .width(Length::Fixed(DURATION_BAR_WIDTH));
+
+    let label = container(text(duration))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .center_y()
+        .align_x(alignment::Horizontal::Right)
+        .padding([0, 4]);
+
+    Layered::new(bar, label).into()
+}
+
+/// The proportion of a full bar; songs at or over the cap fill it
+fn duration_bar_fraction(total_seconds: i64) -> f32 {
+    (total_seconds as f32 / DURATION_BAR_FULL_SECONDS).clamp(0.0, 1.0)
+}
+
+fn song_row_status(
+    current_song: &Option<CurrentSong>,
+    hovered_song_id: Option<SongId>,
+    song_row_id: SongId,
+) -> SongRowStatus {
+    match current_song {
+        Some(song) if song.id == song_row_id && song.playing => SongRowStatus::Playing,
+        Some(song) if song.id == song_row_id => SongRowStatus::Paused,
+        _ if hovered_song_id == Some(song_row_id) => SongRowStatus::Hovered,
+        _ => SongRowStatus::Blank,
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum SongRowStatus {
+    /// currently playing - show pause button
+    Playing,
+    /// currently paused - show play_paused button
+    Paused,
+    /// Both hovered and not currently playing - show play from start button
+    Hovered,
+    /// Both not playing and not hovered - show nothing
+    Blank,
+}
+
+/// A song in the album table
+/// Shows the track's artist too, where it isn't the album's, ie on compilations
+fn view_song_row<'a>(
+    song: &'a Song,
+    album_artist: Option<&str>,
+    status: SongRowStatus,
+    hovered: bool,
+    menu_open: bool,
+    duration_bar: bool,
+) -> Element<'a, Message> {
+    let button_slot: Element<'a, Message> = match status {
+        SongRowStatus::Playing => button(icons::pause().style(accent_icon()))
+            .on_press(Message::PauseClicked)
+            .style(no_background())
+            .into(),
+
+        SongRowStatus::Paused => button(icons::play().style(accent_icon()))
+            .on_press(Message::PlayPausedClicked)
+            .style(no_background())
+            .into(),
+
+        SongRowStatus::Hovered => button(icons::play().style(accent_icon()))
+            .on_press(Message::PlaySongClicked(song.id))
+            .style(no_background())
+            .into(),
+
+        SongRowStatus::Blank => {
+            text(song.track_number.map(|n| n.to_string()).unwrap_or_default())
+                .width(MAGIC_SVG_SIZE)
+                .height(MAGIC_SVG_SIZE)
+                .horizontal_alignment(alignment::Horizontal::Center)
+                .vertical_alignment(alignment::Vertical::Center)
+                .into()
+        }
+    };
+
+    // NOTE queueing is in the right-click menu
+    let favorite_button: Element<'a, Message> = if hovered || song.favorite {
+        view_favorite_button(song).into()
+    } else {
+        Space::new(Length::Shrink, MAGIC_SVG_SIZE).into()
+    };
+
+    let duration = view_duration(song.total_seconds, duration_bar);
+
+    let track_artist = match song.artist.as_deref() {
+        Some(artist) if Some(artist) != album_artist => artist,
+        _ => "",
+    };
+
+    let title = song.display_title().unwrap_or_default();
+    let title = if song.unplayable {
+        format!("{title} (can't play)")
+    } else {
+        title.to_string()
+    };
+
+    let row = row![
+        button_slot,
+        text(title).width(Length::Fill),
+        text(track_artist),
+        favorite_button,
+        duration,
+        horizontal_space(Length::Fixed(10f32))
+    ]
+    .width(Length::Fill)
+    .align_items(Alignment::Center)
+    .spacing(10);
+    let style = if hovered {
+        hover_tint()
+    } else {
+        theme::Container::Transparent
+    };
+
+    let hoverable = Hoverable::new(
+        container(row).style(style).width(Length::Fill).into(),
+        Message::HoveredSong(song.id),
+        Message::UnhoveredSong(song.id),
+    )
+    .padding(2);
+
+    let menu = menu_open.then(|| {
+        let favorite = if song.favorite {
+            "Unfavorite"
+        } else {
+            "Favorite"
+        };
+        let directory = song.file.parent().map(|parent| parent.to_path_buf());
+        let mut items = vec![
+            ("Play", Message::PlaySongClicked(song.id)),
+            ("Play next", Message::PlayNextClicked(song.id)),
+            ("Add to queue", Message::AddToQueueClicked(song.id)),
+            (favorite, Message::FavoriteClicked(song.id)),
+        ];
+        if let Some(directory) = directory {
+            items.push((
+                "Show in file manager",
+                Message::ShowInFileManagerClicked(directory),
+            ));
+        }
+
+        view_menu(items)
+    });
+
+    ContextMenu::new(
+        hoverable,
+        menu,
+        Message::MenuOpened(MenuTarget::Song(song.id)),
+        Message::MenuClosed,
+    )
+    .into()
+}
+
+fn view_favorite_button(song: &Song) -> Button<'_, Message> {
+    let icon = if song.favorite {
+        icons::heart_filled()
+    } else {
+        icons::heart()
+    };
+
+    button(icon)
+        .on_press(Message::FavoriteClicked(song.id))
+        .style(no_background())
+}
+
+/// Iced's default window size, for leaving the mini player before any resize
+const DEFAULT_WINDOW_SIZE: (u32, u32) = (1024, 768);
+const COMPACT_WINDOW_SIZE: (u32, u32) = (480, 180);
+const COMPACT_ART_SIZE: f32 = 100.0;
+const VISUALIZER_HEIGHT: f32 = 80.0;
+const LISTENING_CLOCK_HEIGHT: f32 = 200.0;
+const MENU_WIDTH: f32 = 200.0;
+const WAVEFORM_HEIGHT: f32 = 40.0;
+
+// 24 (svg) + 5 + 5 (default button padding)
+const MAGIC_SVG_SIZE: Length = Length::Fixed(34f32);
+
+/// The song length that fills a duration bar, in seconds
+const DURATION_BAR_FULL_SECONDS: f32 = 600.0;
+const DURATION_BAR_WIDTH: f32 = 64.0;
+const DURATION_BAR_HEIGHT: f32 = 24.0;
+/// Translucent, to stay subtle on light and dark themes
+const DURATION_BAR_COLOR: Color = Color::from_rgba(0.5, 0.5, 0.5, 0.25);
+
+/// The bottom row with the play/pause button and current song info
+fn view_bottom_row<'a>(
+    current_song: &'a Option<CurrentSong>,
+    progress: &'a Option<ProgressDisplay>,
+    shuffle: bool,
+    show_equalizer: bool,
+    library_view: LibraryView,
+    crawling_music: bool,
+    pickers: Row<'a, Message>,
+) -> Element<'a, Message> {
+    let shuffle_style = if shuffle {
+        theme::Button::Primary
+    } else {
+        no_background()
+    };
+    let shuffle_button = button(icons::shuffle())
+        .on_press(Message::ShuffleClicked)
+        .style(shuffle_style);
+
+    let equalizer_style = if show_equalizer {
+        theme::Button::Primary
+    } else {
+        no_background()
+    };
+    let equalizer_button = button("EQ")
+        .on_press(Message::EqualizerClicked)
+        .style(equalizer_style);
+
+    let library_view_button = |label, view| {
+        let style = if library_view == view {
+            theme::Button::Primary
+        } else {
+            no_background()
+        };
+
+        button(label)
+            .on_press(Message::LibraryViewClicked(view))
+            .style(style)
+    };
+    let queue_button = library_view_button("Queue", LibraryView::Queue);
+    let history_button = library_view_button("History", LibraryView::History);
+    let stats_button = library_view_button("Stats", LibraryView::Stats);
+    let folders_button = library_view_button("Folders", LibraryView::Folders);
+    let playlists_button = library_view_button("Playlists", LibraryView::SmartPlaylists);
+    let sessions_button = library_view_button("Sessions", LibraryView::Sessions);
+    let settings_button = library_view_button("Settings", LibraryView::Settings);
+    let lyrics_button = library_view_button("Lyrics", LibraryView::Lyrics);
+    let compact_button = button("Mini")
+        .on_press(Message::CompactClicked)
+        .style(no_background());
+
+    // disabled while a crawl is already running
+    let mut rescan_button = button(icons::rescan()).style(no_background());
+    if !crawling_music {
+        rescan_button = rescan_button.on_press(Message::RescanClicked);
+    }
+
+    let row_content = match (current_song, progress) {
+        (Some(current_song), Some(progress)) => {
+            let play_pause_button = if current_song.playing {
+                button(icons::pause().style(accent_icon()))
+                    .on_press(Message::PauseClicked)
+                    .style(no_background())
+            } else {
+                button(icons::play().style(accent_icon()))
+                    .on_press(Message::PlayPausedClicked)
+                    .style(no_background())
+            };
+
+            let elapsed = match progress {
+                ProgressDisplay::Dragging(proportion) => {
+                    f64::from(*proportion) * current_song.total_seconds as f64
+                }
+
+                ProgressDisplay::FromAudio(times) => times.elapsed.seconds as f64,
+            };
+
+            let elapsed = format_seconds(elapsed);
+            let total = format_seconds(current_song.total_seconds as f64);
+            let duration = format!("{elapsed} / {total}");
+
+            let (loop_label, loop_style) = match current_song.ab_loop {
+                None => ("A-B".to_string(), no_background()),
+                Some(AbLoop { start, end: None }) => (
+                    format!("A {} - B?", format_seconds(start)),
+                    theme::Button::Primary,
+                ),
+                Some(AbLoop { start, end: Some(end) }) => (
+                    format!("A {} - B {}", format_seconds(start), format_seconds(end)),
+                    theme::Button::Primary,
+                ),
+            };
+            let loop_button = button(text(loop_label))
+                .on_press(Message::LoopPointClicked)
+                .style(loop_style);
+
+            let left_side = row![
+                text(&current_song.title)
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .horizontal_alignment(alignment::Horizontal::Center)
+                    .vertical_alignment(alignment::Vertical::Center),
+                button(icons::back())
+                    .on_press(Message::BackClicked)
+                    .style(no_background()),
+            ]
+            .height(MAGIC_SVG_SIZE)
+            .width(Length::FillPortion(1));
+
+            let right_side = row![
+                button(icons::forward())
+                    .on_press(Message::ForwardClicked)
+                    .style(no_background()),
+                view_current_album_artist(current_song)
+                    .width(Length::Fill)
+                    .height(Length::Fill)
+                    .align_items(Alignment::Center),
+                text(duration)
+                    .height(Length::Fill)
+                    .horizontal_alignment(alignment::Horizontal::Center)
+                    .vertical_alignment(alignment::Vertical::Center),
+                loop_button,
+                shuffle_button,
+                equalizer_button,
+                queue_button,
+                history_button,
+                stats_button,
+                folders_button,
+                lyrics_button,
+                playlists_button,
+                sessions_button,
+                settings_button,
+                compact_button,
+                rescan_button,
+            ]
+            .height(MAGIC_SVG_SIZE)
+            .width(Length::FillPortion(1));
+
+            row![left_side, play_pause_button, right_side,].height(MAGIC_SVG_SIZE)
+        }
+
+        _ => row![
+            Space::new(Length::Fill, MAGIC_SVG_SIZE),
+            button(icons::play().style(accent_icon())).style(no_background()),
+            Space::new(Length::Fill, MAGIC_SVG_SIZE),
+            shuffle_button,
+            equalizer_button,
+            queue_button,
+            history_button,
+            stats_button,
+            folders_button,
+            lyrics_button,
+            playlists_button,
+            sessions_button,
+            settings_button,
+            compact_button,
+            rescan_button,
+        ]
+        .height(MAGIC_SVG_SIZE),
+    };
+
+    let bottom_row = row_content.width(Length::Fill).spacing(10).push(pickers);
+
+    Element::from(bottom_row)
+}
+
+/// The mini player: the playing song's art, names and controls, and the progress slider
+fn view_compact<'a>(
+    ui: &'a Ui,
+    progress_slider: Element<'a, Message>,
+) -> Element<'a, Message> {
+    let art_length = Length::Fixed(COMPACT_ART_SIZE);
+    let expand_button = button("Expand")
+        .on_press(Message::CompactClicked)
+        .style(no_background());
+
+    let Some(current_song) = &ui.current_song else {
+        return column![
+            row![text("Nothing playing").width(Length::Fill), expand_button]
+                .align_items(Alignment::Center),
+            progress_slider
+        ]
+        .spacing(10)
+        .padding(10)
+        .into();
+    };
+
+    let cached_album = ui.music_cache.get_cached_album(&current_song.album_id);
+    let art: Element<'_, Message> =
+        match cached_album.and_then(|album| album.art.as_ref()) {
+            Some(art) => Image::new(art)
+                .width(art_length)
+                .height(art_length)
+                .content_fit(ContentFit::ScaleDown)
+                .into(),
+            None => match cached_album.and_then(|album| album.placeholder_color) {
+                Some(color) => container(Space::new(art_length, art_length))
+                    .style(solid_color(color))
+                    .into(),
+                None => Space::new(art_length, art_length).into(),
+            },
+        };
+
+    let play_pause_button = if current_song.playing {
+        button(icons::pause().style(accent_icon())).on_press(Message::PauseClicked)
+    } else {
+        button(icons::play().style(accent_icon())).on_press(Message::PlayPausedClicked)
+    };
+    let controls = row![
+        button(icons::back())
+            .on_press(Message::BackClicked)
+            .style(no_background()),
+        play_pause_button.style(no_background()),
+        button(icons::forward())
+            .on_press(Message::ForwardClicked)
+            .style(no_background()),
+        horizontal_space(Length::Fill),
+        expand_button,
+    ]
+    .align_items(Alignment::Center);
+
+    let details = column![
+        text(&current_song.title).size(20),
+        text(current_song.artist.as_deref().unwrap_or_default()),
+        controls,
+    ]
+    .spacing(5)
+    .width(Length::Fill);
+
+    column![
+        row![art, details]
+            .spacing(10)
+            .align_items(Alignment::Center),
+        progress_slider
+    ]
+    .spacing(10)
+    .padding(10)
+    .into()
+}
+
+/// A slider for each band, and a preset picker
+fn view_equalizer<'a>(curve: &EqCurve) -> Element<'a, Message> {
+    let presets = pick_list(
+        &EqPreset::ALL[..],
+        curve.preset(),
+        Message::EqPresetSelected,
+    );
+
+    let mut bands = row![].spacing(10).align_items(Alignment::Center);
+    let gains = BAND_FREQUENCIES.iter().zip(curve.0);
+    for (band, (frequency, gain_db)) in gains.enumerate() {
+        let band_slider =
+            vertical_slider(-MAX_BAND_GAIN..=MAX_BAND_GAIN, gain_db, move |gain_db| {
+                Message::EqBandChanged(band, gain_db)
+            })
+            .step(0.5)
+            .height(Length::Fixed(100.0))
+            .on_release(Message::EqBandReleased);
+
+        let label = text(format_frequency(*frequency)).size(12);
+
+        bands = bands.push(
+            column![band_slider, label]
+                .spacing(5)
+                .align_items(Alignment::Center),
+        );
+    }
+
+    row![presets, bands]
+        .spacing(20)
+        .align_items(Alignment::Center)
+        .into()
+}
+
+fn format_frequency(hz: f32) -> String {
+    if hz >= 1000.0 {
+        format!("{}k", hz / 1000.0)
+    } else {
+        format!("{hz}")
+    }
+}
+
+/// The settings at the end of the bottom row
+fn view_pickers(ui: &Ui) -> Row<'_, Message> {
+    let mut pickers = row![].spacing(10).align_items(Alignment::Center);
+    if let Some(output_device_picker) =
+        view_output_device_picker(&ui.output_devices, &ui.output_device)
+    {
+        pickers = pickers.push(output_device_picker);
+    }
+
+    let mute_icon = if ui.muted {
+        icons::muted()
+    } else {
+        icons::volume()
+    };
+    pickers = pickers.push(
+        button(mute_icon)
+            .on_press(Message::MuteClicked)
+            .style(no_background()),
+    );
+    if let Some(volume_label) = volume_label(ui) {
+        pickers = pickers.push(text(volume_label));
+    }
+    if ui.power.low_power() {
+        pickers = pickers.push(text("Low power"));
+    }
+    pickers.push(pick_list(
+        &PowerMode::ALL[..],
+        Some(ui.power.mode),
+        Message::PowerModeSelected,
+    ))
+}
+
+/// None when there's nothing to choose besides the default device
+fn view_output_device_picker<'a>(
+    output_devices: &[String],
+    selected: &OutputDevice,
+) -> Option<Element<'a, Message>> {
+    if output_devices.is_empty() {
+        return None;
+    }
+
+    let mut options = vec![OutputDevice::Default];
+    options.extend(output_devices.iter().cloned().map(OutputDevice::Named));
+
+    let picker = pick_list(
+        options,
+        Some(selected.clone()),
+        Message::OutputDeviceSelected,
+    );
+
+    Some(picker.into())
+}
+
+/// None when no songs have a genre tag
+fn view_genre_picker<'a>(
+    genres: Vec<&str>,
+    selected: &GenreFilter,
+) -> Option<Element<'a, Message>> {
+    if genres.is_empty() {
         return None;
+    }
+
+    let mut options = vec![GenreFilter::All];
+    options.extend(
+        genres
+            .into_iter()
+            .map(|g| GenreFilter::Genre(g.to_string())),
+    );
+
+    let picker = pick_list(
+        options,
+        Some(selected.clone()),
+        Message::GenreFilterSelected,
+    );
+
+    Some(picker.into())
+}
+
+fn view_current_album_artist(current: &CurrentSong) -> Row<'_, Message> {
+    let mut children: Vec<Element<'_, Message>> = Vec::new();
+
+    children.push(horizontal_space(Length::Fill).into());
+
+    let mut label = String::new();
+    if let Some(album) = &current.album {
+        label.push_str(album);
+    }
+    if current.album.is_some() && current.artist.is_some() {
+        label.push_str(" - ");
+    }
+    if let Some(artist) = &current.artist {
+        label.push_str(artist);
+    }
+    children.push(text(label).into());
+
+    children.push(horizontal_space(Length::Fill).into());
+
+    Row::with_children(children).spacing(10)
+}
+
+fn format_seconds(seconds: f64) -> String {
+    let minutes = seconds / 60.0;
+    let whole_minutes = minutes.floor();
+    let seconds = (minutes - whole_minutes) * 60.0;
+    let seconds = seconds.round();
+
+    format!("{whole_minutes}:{seconds:02}")
+}
+
+fn format_conversions(conversions: &ConversionStats) -> Vec<String> {
+    let songs = match conversions.songs {
+        1 => "1 song".to_string(),
+        songs => format!("{songs} songs"),
+    };
+    let mut lines = vec![format!(
+        "{songs} played, {} resampled",
+        conversions.resampled_songs()
+    )];
+
+    for ((from, to), songs) in &conversions.resampled {
+        lines.push(format!("{from} Hz -> {to} Hz: {songs}"));
+    }
+    for ((bits, sample_format), songs) in &conversions.bit_depths {
+        lines.push(format!("{bits}-bit -> {sample_format}: {songs}"));
+    }
+
+    lines
+}
+
+fn format_output_telemetry(telemetry: &Option<OutputTelemetry>) -> String {
+    let Some(telemetry) = telemetry else {
+        return String::from("output: not open");
+    };
+
+    let resampling = match telemetry.resampling {
+        Some((from, to)) => format!("{from} Hz -> {to} Hz"),
+        None => String::from("none"),
+    };
+    let buffer = match (telemetry.buffer_fill, telemetry.buffer_ms) {
+        (Some(fill), Some(buffer_ms)) => {
+            format!("{:.0}% of {buffer_ms} ms", fill * 100.0)
+        }
+        (Some(fill), None) => format!("{:.0}%", fill * 100.0),
+        (None, _) => String::from("server-side"),
+    };
+    let underruns = match telemetry.underruns {
+        Some(underruns) => underruns.to_string(),
+        None => String::from("unknown"),
     };
 
-    Some(CurrentSong::new(song, album, playing))
-}
+    format!(
+        "output: {} | resampling: {resampling} | buffer: {buffer} | underruns: {underruns}",
+        telemetry.sample_format
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{assert_eq, str::FromStr};
+
+    use camino::Utf8PathBuf;
+    use clef_audio::player::PlayerSnapshot;
+    use iced::keyboard::Modifiers;
+
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn crawled_album_with_no_original_art_sends_no_resize_request() {
+        let mut ui = Ui::new();
+
+        let mut crawled = fake_album();
+        crawled.cached_art = None;
+        crawled.album.original_art = None;
+
+        let message = crawled_album_message(&crawled);
+
+        let effect = update(&mut ui, message);
+
+        assert!(matches!(effect, Effect::None))
+    }
+
+    #[test]
+    fn crawled_album_with_cached_resized_art_sends_no_resize_request() {
+        let mut ui = Ui::new();
+
+        let mut crawled = fake_album();
+        crawled.cached_art = Some(RgbaBytes::empty());
+        crawled.album.original_art = Some(Utf8PathBuf::from_str("original").unwrap());
+
+        let message = crawled_album_message(&crawled);
+
+        let effect = update(&mut ui, message);
+
+        assert!(matches!(effect, Effect::None))
+    }
+
+    #[test]
+    fn crawled_album_with_previously_resized_art_sends_no_resize_request() {
+        let mut ui = Ui::new();
+        let mut crawled = fake_album();
+        crawled.cached_art = None;
+        crawled.album.original_art = Some(Utf8PathBuf::from_str("original").unwrap());
+        crawled.album.resized_art = Some(Utf8PathBuf::from_str("resized").unwrap());
+
+        let message = crawled_album_message(&crawled);
+
+        let effect = update(&mut ui, message);
+
+        assert!(matches!(effect, Effect::None))
+    }
+
+    #[test]
+    fn crawled_album_with_no_cached_resized_art_sends_resize_request() {
+        let mut ui = Ui::new();
+        let mut crawled = fake_album();
+        crawled.cached_art = None;
+        crawled.album.original_art = Some(Utf8PathBuf::from_str("original").unwrap());
+
+        let message = crawled_album_message(&crawled);
+
+        let effect = update(&mut ui, message);
+
+        match effect {
+            Effect::ToResizer(ResizeRequest { album_id, source_path, .. }) => {
+                assert_eq!(album_id, crawled.album.id);
+                assert_eq!(source_path, crawled.album.original_art.unwrap());
+            }
+            _ => panic!("expected resize request"),
+        }
+    }
 
-// View
+    #[test]
+    fn crawled_album_with_no_art_is_looked_up_when_turned_on() {
+        let mut ui = Ui::new();
+        let mut crawled = fake_album();
+        crawled.cached_art = None;
+        crawled.album.original_art = None;
 
-fn view(ui: &Ui) -> Element<'_, Message> {
-    const MAX: f32 = 1.0;
-    const STEP: f32 = 0.01;
+        let effect = update(&mut ui, crawled_album_message(&crawled));
+        assert!(matches!(effect, Effect::None));
 
-    let progress_slider = match &ui.progress {
-        Some(progress) => {
-            let proportion = progress.display_proportion();
+        ui.settings.fetch_missing_art = true;
+        let effect = update(&mut ui, crawled_album_message(&crawled));
+        match effect {
+            Effect::FetchArt(lookup) => {
+                assert_eq!(lookup.album_id, crawled.album.id);
+                assert_eq!(lookup.directory, crawled.album.directory);
+            }
+            _ => panic!("expected art lookup"),
+        }
 
-            slider(0.0..=MAX, proportion, Message::SeekDrag)
-                .step(STEP)
-                .on_release(Message::SeekRelease)
+        crawled.album.original_art = Some(Utf8PathBuf::from_str("original").unwrap());
+        let effect = update(&mut ui, crawled_album_message(&crawled));
+        assert!(matches!(effect, Effect::ToResizer(_)));
+    }
+
+    #[test]
+    fn crawling_a_saved_album_does_not_duplicate_it() {
+        let mut ui = Ui::new();
+        let crawled = fake_album();
+
+        let saved = SavedLibrary {
+            albums: vec![crawled.clone()],
+            queue: None,
+        };
+        update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+        update(&mut ui, crawled_album_message(&crawled));
+
+        let albums = ui.music_cache.albums();
+        assert_eq!(albums.len(), 1);
+        assert_eq!(albums[0].songs.len(), crawled.songs.len());
+    }
+
+    #[test]
+    fn file_opened_on_launch_plays_its_directory() {
+        let mut ui = Ui::new();
+        ui.open_on_launch = Some("Third".into());
+
+        let saved = SavedLibrary { albums: Vec::new(), queue: None };
+        let effect = update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+        let Effect::OpenFile(file) = effect else {
+            panic!("expected to read a file from outside the library");
+        };
+
+        let read = Ok(Box::new(fake_album()));
+        let effect = update(&mut ui, Message::OpenedFile(file, read));
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::PlayQueue(queue))
+                if queue.current.id == SongId::new(3) && queue.previous.len() == 2
+        ));
+        assert!(ui.open_on_launch.is_none());
+    }
+
+    #[test]
+    fn song_links_play_their_album_from_the_linked_moment() {
+        let mut ui = Ui::new();
+        ui.link_on_launch = Some(SongLink { song: 2, seconds: 30.0 });
+
+        let saved = SavedLibrary {
+            albums: vec![fake_album()],
+            queue: None,
+        };
+        let effect = update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::ResumeQueue(queue, seconds))
+                if queue.current.id == SongId::new(2) && seconds == 30.0
+        ));
+        assert!(ui.link_on_launch.is_none());
+
+        let missing = Handoff::PlayLink(SongLink { song: 99, seconds: 0.0 });
+        let effect = update(&mut ui, Message::FromInstance(missing));
+        assert!(matches!(effect, Effect::None));
+        assert!(ui.toast.is_some());
+    }
+
+    #[test]
+    fn dropped_paths_are_enqueued_or_read_first() {
+        let mut ui = Ui::new();
+        let saved = SavedLibrary {
+            albums: vec![fake_album()],
+            queue: None,
+        };
+        update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+        let dropped = |path: &str| {
+            Message::Native(Event::Window(WindowEvent::FileDropped(path.into())))
+        };
+
+        let effect = update(&mut ui, dropped("Second"));
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::EnqueueAll(songs))
+                if songs.len() == 1 && songs[0].id == SongId::new(2)
+        ));
+
+        let effect = update(&mut ui, dropped("/downloads/Some Album"));
+        assert!(matches!(effect, Effect::ReadDropped(_, None)));
+
+        ui.settings.import_dropped = true;
+        ui.settings.music_directories = vec!["/music".into()];
+        let effect = update(&mut ui, dropped("/downloads/Some Album"));
+        assert!(matches!(effect, Effect::ReadDropped(_, Some(dir)) if dir == "/music"));
+        let effect = update(&mut ui, dropped("/music/Not Scanned Yet"));
+        assert!(matches!(effect, Effect::ReadDropped(_, None)));
+    }
+
+    #[test]
+    fn queue_saved_while_playing_offers_to_resume() {
+        let mut ui = Ui::new();
+        let crawled = fake_album();
+
+        let saved = SavedLibrary {
+            albums: vec![crawled],
+            queue: Some(SavedQueue {
+                song_ids: vec![SongId::new(1), SongId::new(2)],
+                current_index: 1,
+                elapsed_seconds: 161.0,
+                from_crash: false,
+                playing: true,
+                unshuffled_song_ids: None,
+            }),
+        };
+        let effect = update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::RestoreState(_, seconds)) if seconds == 161.0
+        ));
+        let interrupted = ui.interrupted_song.as_ref().unwrap();
+        assert_eq!(interrupted.title, "Second");
+        assert_eq!(format_seconds(interrupted.elapsed_seconds), "2:41");
+
+        let effect = update(&mut ui, Message::ResumeInterruptedSongClicked);
+        assert!(matches!(effect, Effect::ToAudio(AudioAction::PlayPaused)));
+        assert!(ui.interrupted_song.is_none());
+    }
+
+    #[test]
+    fn a_failed_player_offers_to_resume_its_queue() {
+        let mut ui = Ui::new();
+        let saved = SavedLibrary {
+            albums: vec![fake_album()],
+            queue: None,
+        };
+        update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+
+        let effect = update(&mut ui, Message::FromAudio(AudioMessage::AudioDied));
+        assert!(matches!(effect, Effect::LoadCrashedQueue));
+        assert!(ui.current_song.is_none());
+
+        let crashed = SavedQueue {
+            song_ids: vec![SongId::new(1), SongId::new(2)],
+            current_index: 1,
+            elapsed_seconds: 30.0,
+            from_crash: true,
+            playing: true,
+            unshuffled_song_ids: None,
+        };
+        update(&mut ui, Message::LoadedCrashedQueue(Some(crashed)));
+        assert!(ui.crashed_queue.is_some());
+
+        let effect = update(&mut ui, Message::ResumeCrashedQueueClicked);
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::ResumeQueue(queue, seconds))
+                if queue.current.id == SongId::new(2) && seconds == 30.0
+        ));
+        assert!(ui.crashed_queue.is_none());
+    }
+
+    #[test]
+    fn songs_to_play_on_launch_replace_the_saved_queue() {
+        let mut ui = Ui::new();
+        ui.play_on_launch = vec![SongId::new(4), SongId::new(3)];
+
+        let saved = SavedLibrary {
+            albums: vec![fake_album()],
+            queue: Some(SavedQueue {
+                song_ids: vec![SongId::new(1)],
+                current_index: 0,
+                elapsed_seconds: 12.0,
+                from_crash: false,
+                playing: true,
+                unshuffled_song_ids: None,
+            }),
+        };
+        let effect = update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::PlayQueue(queue)) if queue.current.id == SongId::new(3)
+        ));
+        assert!(ui.interrupted_song.is_none());
+        assert!(ui.play_on_launch.is_empty());
+    }
+
+    #[test]
+    fn arrow_keys_seek_with_modifiers() {
+        let keymap = Keymap::default();
+        let cases = [
+            (KeyCode::Right, Modifiers::empty(), SeekOffset::Seconds(5.0)),
+            (KeyCode::Left, Modifiers::SHIFT, SeekOffset::Seconds(-30.0)),
+            (KeyCode::Right, Modifiers::ALT, SeekOffset::Proportion(0.1)),
+        ];
+
+        for (key_code, modifiers, expected) in cases {
+            let action = keymap.action(key_code, modifiers);
+            assert_eq!(action.and_then(seek_offset), Some(expected));
         }
 
-        // disabled
-        None => slider(0.0..=MAX, 0.0, Message::SeekWithoutSong).step(STEP),
-    };
+        let next = keymap.action(KeyCode::Right, Modifiers::CTRL);
+        assert_eq!(next, Some(KeyAction::Next));
+        assert_eq!(next.and_then(seek_offset), None);
+    }
 
-    let content = view_album_list(&ui.music_cache, ui.hovered_song_id, &ui.current_song);
+    #[test]
+    fn webhook_events_follow_playback_changes() {
+        let mut ui = Ui::new();
+        let song = |id, playing| CurrentSong {
+            id: SongId::new(id),
+            album_id: AlbumId::new(1),
+            title: format!("Song {id}"),
+            album: None,
+            artist: None,
+            playing,
+            total_seconds: 200,
+            ab_loop: None,
+        };
 
-    let content = fill_container(scrollable(content));
-    let bottom_row = view_bottom_row(&ui.current_song, &ui.progress);
+        ui.current_song = Some(song(1, false));
+        assert_eq!(playback_event(None, &ui), None);
 
-    let main_column = column![content, bottom_row, progress_slider]
-        .spacing(10)
-        .padding(20)
-        .width(Length::Fill)
-        .height(Length::Fill)
-        .align_items(Alignment::Center);
+        ui.current_song = Some(song(1, true));
+        let event = playback_event(Some((SongId::new(1), false)), &ui);
+        assert!(matches!(event, Some(PlaybackEvent::NowPlaying { .. })));
+        assert_eq!(playback_event(Some((SongId::new(1), true)), &ui), None);
 
-    main_column.into()
-}
+        ui.current_song = Some(song(1, false));
+        let event = playback_event(Some((SongId::new(1), true)), &ui);
+        assert!(matches!(event, Some(PlaybackEvent::Paused { .. })));
 
-fn fill_container<'a>(
-    content: impl Into<Element<'a, Message>>,
-) -> Container<'a, Message> {
-    container(content)
-        .width(Length::Fill)
-        .height(Length::Fill)
-        .center_x()
-        .center_y()
-}
+        ui.current_song = None;
+        let event = playback_event(Some((SongId::new(1), false)), &ui);
+        assert_eq!(event, Some(PlaybackEvent::Stopped));
+    }
 
-fn view_album_list<'a>(
-    music: &'a MusicCache,
-    hovered_song_id: Option<SongId>,
-    current_song: &'a Option<CurrentSong>,
-) -> Column<'a, Message> {
-    let rows: Vec<_> = music
-        .albums()
-        .iter()
-        .map(|a| view_album(a, hovered_song_id, current_song))
-        .collect();
+    #[test]
+    fn volume_keys_step_and_mute() {
+        let mut ui = Ui::new();
 
-    Column::with_children(rows)
-        .spacing(10)
-        .width(Length::Fill)
-        .align_items(Alignment::Center)
-}
+        key_action(&mut ui, KeyAction::VolumeDown);
+        let effect = key_action(&mut ui, KeyAction::VolumeDown);
+        assert!(matches!(effect, Effect::ToAudio(AudioAction::SetVolume(v)) if v == 0.8));
+        assert_eq!(volume_label(&ui).as_deref(), Some("Volume 80%"));
+
+        let effect = key_action(&mut ui, KeyAction::Mute);
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::SetMuted(true))
+        ));
+        assert_eq!(volume_label(&ui).as_deref(), Some("Muted"));
+
+        let effect = update(&mut ui, Message::MuteClicked);
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::SetMuted(false))
+        ));
+        assert_eq!(volume_label(&ui).as_deref(), Some("Volume 80%"));
+
+        key_action(&mut ui, KeyAction::Mute);
+        key_action(&mut ui, KeyAction::VolumeUp);
+        let effect = key_action(&mut ui, KeyAction::VolumeUp);
+        assert!(matches!(effect, Effect::ToAudio(AudioAction::SetVolume(v)) if v == 1.0));
+        assert_eq!(volume_label(&ui), None);
+
+        // ie from the os media controls
+        let message = AudioMessage::VolumeChanged { volume: 0.5, muted: true };
+        update(&mut ui, Message::FromAudio(message));
+        assert_eq!(volume_label(&ui).as_deref(), Some("Muted"));
+        key_action(&mut ui, KeyAction::Mute);
+        assert_eq!(volume_label(&ui).as_deref(), Some("Volume 50%"));
+    }
 
-fn view_album<'a>(
-    album: &'a CachedAlbum,
-    hovered_song_id: Option<SongId>,
-    current_song: &'a Option<CurrentSong>,
-) -> Element<'a, Message> {
-    let album_image = view_album_image(album.art.as_ref());
+    #[test]
+    fn queue_view_follows_the_player_and_edits_the_queue() {
+        let mut ui = Ui::new();
+        let saved = SavedLibrary {
+            albums: vec![fake_album()],
+            queue: None,
+        };
+        update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+
+        let songs: Vec<_> = (1..=4)
+            .filter_map(|id| ui.music_cache.get_queued_song(SongId::new(id)))
+            .collect();
+        let queue = QueueDisplay { songs, current_index: 1 };
+        let message = AudioMessage::QueueUpdate(Some(Box::new(queue.clone())));
+        update(&mut ui, Message::FromAudio(message));
+        assert_eq!(ui.queue, Some(queue));
+
+        let effect = update(&mut ui, Message::QueueSongClicked(2));
+        assert!(matches!(effect, Effect::ToAudio(AudioAction::GoTo(2))));
+        let effect = update(&mut ui, Message::RemoveFromQueueClicked(0));
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::RemoveFromQueue(0))
+        ));
+
+        ui.library_view = LibraryView::Queue;
+        let pressed =
+            Message::Native(Event::Mouse(MouseEvent::ButtonPressed(mouse::Button::Left)));
+        let released = Message::Native(Event::Mouse(MouseEvent::ButtonReleased(
+            mouse::Button::Left,
+        )));
+
+        update(&mut ui, Message::QueueRowHovered(3));
+        update(&mut ui, pressed.clone());
+        assert_eq!(ui.dragged_queue_index, Some(3));
+        update(&mut ui, Message::QueueRowUnhovered(3));
+        update(&mut ui, Message::QueueRowHovered(2));
+        let effect = update(&mut ui, released.clone());
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::MoveInQueue(3, 2))
+        ));
+        assert_eq!(ui.dragged_queue_index, None);
+
+        // dropping on the current song does nothing
+        update(&mut ui, pressed.clone());
+        update(&mut ui, Message::QueueRowUnhovered(2));
+        update(&mut ui, Message::QueueRowHovered(1));
+        assert!(matches!(update(&mut ui, released), Effect::None));
+
+        // and it can't be dragged
+        update(&mut ui, pressed);
+        assert_eq!(ui.dragged_queue_index, None);
+
+        update(&mut ui, Message::FromAudio(AudioMessage::QueueUpdate(None)));
+        assert_eq!(ui.queue, None);
+    }
 
-    let album_info = column![
-        text(album.album.display_title().unwrap_or_default()),
-        text(album.album.artist.as_deref().unwrap_or_default()),
-        text(album.album.release_date.as_deref().unwrap_or_default()),
-    ]
-    .width(Length::FillPortion(1));
+    #[test]
+    fn state_dump_from_audio_includes_ui_state() {
+        let mut ui = Ui::new();
+        ui.shuffle = true;
+        ui.progress = Some(ProgressDisplay::Dragging(0.5));
 
-    let song_rows: Vec<_> = album
-        .songs
-        .iter()
-        .map(|song| {
-            let status = song_row_status(current_song, hovered_song_id, song.id);
-            view_song_row(song, status)
-        })
-        .collect();
-    let songs_list = Column::with_children(song_rows).width(Length::FillPortion(2));
+        let player = PlayerSnapshot { shuffle: true, state: None };
+        let message = Message::FromAudio(AudioMessage::StateDump(Box::new(player)));
+
+        let effect = update(&mut ui, message);
+
+        let Effect::WriteStateDump(dump) = effect else {
+            panic!("expected state dump");
+        };
+        assert!(dump.ui.shuffle);
+
+        let json = serde_json::to_value(&dump).unwrap();
+        assert_eq!(json["ui"]["progress"]["Dragging"], 0.5);
+        assert!(json["player"]["state"].is_null());
+    }
+
+    #[test]
+    fn output_telemetry_formats_unknown_values() {
+        let telemetry = OutputTelemetry {
+            sample_format: "i16",
+            resampling: Some((44100, 48000)),
+            buffer_fill: Some(0.5),
+            buffer_ms: Some(400),
+            underruns: None,
+        };
+
+        assert_eq!(
+            format_output_telemetry(&Some(telemetry)),
+            "output: i16 | resampling: 44100 Hz -> 48000 Hz | buffer: 50% of 400 ms | underruns: unknown"
+        );
+        assert_eq!(format_output_telemetry(&None), "output: not open");
+    }
+
+    #[test]
+    fn duration_bars_scale_to_a_capped_length() {
+        assert_eq!(duration_bar_fraction(0), 0.0);
+        assert_eq!(duration_bar_fraction(150), 0.25);
+        assert_eq!(duration_bar_fraction(600), 1.0);
+        assert_eq!(duration_bar_fraction(1_800), 1.0);
+    }
+
+    #[test]
+    fn selecting_an_eq_preset_saves_its_curve() {
+        let mut ui = Ui::new();
+
+        let effect = update(&mut ui, Message::EqPresetSelected(EqPreset::BassBoost));
+
+        let Effect::SaveEqualizer(None, curve) = effect else {
+            panic!("expected to save the equalizer");
+        };
+        assert_eq!(curve, EqPreset::BassBoost.curve());
+        assert_eq!(ui.equalizer.preset(), Some(EqPreset::BassBoost));
+    }
+
+    #[test]
+    fn each_output_device_keeps_its_own_curve() {
+        let mut ui = Ui::new();
+        let headphones = || Some("Headphones".to_string());
+
+        let effect = update(
+            &mut ui,
+            Message::OutputDeviceSelected(OutputDevice::Named("Headphones".to_string())),
+        );
+        assert!(
+            matches!(effect, Effect::SwitchOutputDevice(name) if name == headphones())
+        );
+
+        // a device without a saved curve keeps the one from the last device
+        update(&mut ui, Message::LoadedEqualizer(headphones(), None));
+        assert_eq!(ui.equalizer, EqCurve::default());
+        let effect = update(&mut ui, Message::EqPresetSelected(EqPreset::BassBoost));
+        assert!(matches!(effect, Effect::SaveEqualizer(name, _) if name == headphones()));
+
+        let effect = update(
+            &mut ui,
+            Message::OutputDeviceSelected(OutputDevice::Default),
+        );
+        assert!(matches!(effect, Effect::SwitchOutputDevice(None)));
+        let flat = EqCurve::default();
+        let effect = update(&mut ui, Message::LoadedEqualizer(None, Some(flat)));
+        assert!(
+            matches!(effect, Effect::ToAudio(AudioAction::SetEqualizer(curve)) if curve == flat)
+        );
+        assert_eq!(ui.equalizer, flat);
+
+        // a curve that loads after switching away again is for another device
+        let bass = EqPreset::BassBoost.curve();
+        update(&mut ui, Message::LoadedEqualizer(headphones(), Some(bass)));
+        assert_eq!(ui.equalizer, flat);
+    }
+
+    #[test]
+    fn favorite_clicked_toggles_and_saves() {
+        let mut ui = Ui::new();
+        let album = fake_album();
+        update(&mut ui, crawled_album_message(&album));
+        let song_id = album.songs[1].id;
+
+        let effect = update(&mut ui, Message::FavoriteClicked(song_id));
+        assert!(matches!(effect, Effect::SaveFavorite(id, true) if id == song_id));
+        assert_eq!(ui.music_cache.favorites().len(), 1);
+
+        let effect = update(&mut ui, Message::FavoriteClicked(song_id));
+        assert!(matches!(effect, Effect::SaveFavorite(id, false) if id == song_id));
+        assert!(ui.music_cache.favorites().is_empty());
+    }
+
+    #[test]
+    fn play_history_sorts_by_recency_and_count() {
+        let count = |id, plays, last_played_at| PlayCount {
+            song_id: SongId::new(id),
+            plays,
+            last_played_at,
+        };
+
+        let history = PlayHistory::new(
+            vec![count(1, 5, 100), count(2, 1, 300), count(3, 3, 200)],
+            Vec::new(),
+        );
+
+        let ids = |ids: &[i32]| -> Vec<SongId> {
+            ids.iter().copied().map(SongId::new).collect()
+        };
+        assert_eq!(history.recently_played, ids(&[2, 3, 1]));
+        let most_played: Vec<SongId> =
+            history.most_played.iter().map(|(id, _)| *id).collect();
+        assert_eq!(most_played, ids(&[1, 3, 2]));
+    }
+
+    #[test]
+    fn rebuilt_sessions_play_a_days_songs_in_order() {
+        let mut ui = Ui::new();
+        update(&mut ui, crawled_album_message(&fake_album()));
+
+        // a song removed since, and a song played twice
+        let played = [99, 3, 1, 3].into_iter().map(SongId::new).collect();
+        let effect = update(&mut ui, Message::LoadedSessionPlays(played));
+
+        let Effect::ToAudio(AudioAction::PlayQueue(queue)) = effect else {
+            panic!("expected a queue to play, got {effect:?}");
+        };
+        assert_eq!(queue.current.id, SongId::new(3));
+        let next: Vec<SongId> = queue.next.iter().map(|song| song.id).collect();
+        assert_eq!(next, [SongId::new(1), SongId::new(3)]);
+
+        let effect = update(&mut ui, Message::LoadedSessionPlays(vec![SongId::new(99)]));
+        assert!(matches!(effect, Effect::None));
+        assert!(ui.toast.is_some());
+    }
+
+    #[test]
+    fn on_this_day_plays_the_most_played_first() {
+        let mut ui = Ui::new();
+        update(&mut ui, crawled_album_message(&fake_album()));
+
+        let most_played = vec![SongId::new(4), SongId::new(1)];
+        let effect = update(&mut ui, Message::LoadedOnThisDay(most_played));
+
+        let Effect::ToAudio(AudioAction::PlayQueue(queue)) = effect else {
+            panic!("expected a queue to play, got {effect:?}");
+        };
+        assert_eq!(queue.current.id, SongId::new(4));
+        let next: Vec<SongId> = queue.next.iter().map(|song| song.id).collect();
+        assert_eq!(next, [SongId::new(1)]);
+
+        let effect = update(&mut ui, Message::LoadedOnThisDay(Vec::new()));
+        assert!(matches!(effect, Effect::None));
+        assert!(ui.toast.is_some());
+    }
+
+    #[test]
+    fn switching_sessions_restores_or_stops() {
+        let mut ui = Ui::new();
+        let album = fake_album();
+        update(&mut ui, crawled_album_message(&album));
+        ui.sessions = vec![Session {
+            id: SessionId::new(1),
+            name: "Work".to_string(),
+            active: false,
+            transition: "gap".to_string(),
+        }];
+
+        let effect = update(
+            &mut ui,
+            Message::SwitchSessionClicked(Some(SessionId::new(1))),
+        );
+        assert!(matches!(
+            effect,
+            Effect::SwitchSession(Some(id), TransitionKind::Gap, None)
+                if id == SessionId::new(1)
+        ));
+        assert!(ui.sessions[0].active);
+
+        let effect = update(&mut ui, Message::SwitchedSession(None));
+        assert!(matches!(effect, Effect::ToAudio(AudioAction::Stop)));
+
+        let saved = SavedQueue {
+            song_ids: vec![SongId::new(3), SongId::new(4)],
+            current_index: 1,
+            elapsed_seconds: 30.0,
+            from_crash: false,
+            playing: false,
+            unshuffled_song_ids: None,
+        };
+        let effect = update(&mut ui, Message::SwitchedSession(Some(saved)));
+        let Effect::ToAudio(AudioAction::RestoreState(queue, seconds)) = effect else {
+            panic!("expected to restore the session's queue");
+        };
+        assert_eq!(queue.current.id, SongId::new(4));
+        assert_eq!(seconds, 30.0);
+    }
+
+    #[test]
+    fn album_page_closes_when_its_album_is_removed() {
+        let mut ui = Ui::new();
+        let album = fake_album();
+        let album_id = album.album.id;
+        update(&mut ui, crawled_album_message(&album));
+
+        let album_page = LibraryView::Album(album_id);
+        update(&mut ui, Message::LibraryViewClicked(album_page));
+        assert_eq!(ui.library_view, album_page);
+
+        let removed = RemovedFromLibrary {
+            albums: vec![album_id],
+            songs: album.songs.iter().map(|song| song.id).collect(),
+        };
+        update(
+            &mut ui,
+            Message::FromCrawler(CrawlerMessage::Removed(removed)),
+        );
+        assert_eq!(ui.library_view, LibraryView::Albums);
+    }
+
+    #[test]
+    fn art_loads_for_albums_near_the_scroll_position() {
+        let mut ui = Ui::new();
+        for number in 1..=30 {
+            let mut album = fake_album();
+            album.album.id = AlbumId::new(number);
+            album.album.directory = format!("Album Dir {number:02}").into();
+            album.album.title = Some(format!("Album {number:02}"));
+            album.album.resized_art = Some(format!("resized_{number}.bmp").into());
+            album.songs.clear();
+            ui.music_cache.add_crawled_album(album);
+        }
+
+        let requested = |ui: &mut Ui| -> Vec<AlbumId> {
+            show_album_art(ui).iter().map(|r| r.album_id).collect()
+        };
+
+        let at_top = requested(&mut ui);
+        assert!(at_top.contains(&AlbumId::new(1)));
+        assert!(!at_top.contains(&AlbumId::new(30)));
+
+        let bottom = RelativeOffset { x: 0.0, y: 1.0 };
+        update(&mut ui, Message::LibraryScrolled(bottom));
+        let at_bottom = requested(&mut ui);
+        assert!(at_bottom.contains(&AlbumId::new(30)));
+        assert!(!at_bottom.contains(&AlbumId::new(1)));
+    }
+
+    #[test]
+    fn low_power_follows_the_battery_unless_overridden() {
+        let mut ui = Ui::new();
+
+        let effect = update(
+            &mut ui,
+            Message::CheckedPowerSource(Some(PowerSource::Battery)),
+        );
+        assert!(matches!(effect, Effect::SetLowPower(true)));
+
+        let effect = update(
+            &mut ui,
+            Message::CheckedPowerSource(Some(PowerSource::Battery)),
+        );
+        assert!(matches!(effect, Effect::None));
+
+        let effect = update(&mut ui, Message::PowerModeSelected(PowerMode::Full));
+        assert!(matches!(effect, Effect::SetLowPower(false)));
+
+        let effect = update(&mut ui, Message::CheckedPowerSource(None));
+        assert!(matches!(effect, Effect::None));
+    }
+
+    #[test]
+    fn changing_music_directories_saves_and_rescans() {
+        let mut ui = Ui::new();
+        ui.crawling_music = false;
+        ui.settings.music_directories = vec!["Music".into()];
+
+        let effect = update(&mut ui, Message::RemoveMusicDirectoryClicked(0));
+        assert!(matches!(effect, Effect::None));
+
+        let temp_dir = Utf8PathBuf::try_from(std::env::temp_dir()).unwrap();
+        update(
+            &mut ui,
+            Message::MusicDirectoryChanged(temp_dir.to_string()),
+        );
+        let effect = update(&mut ui, Message::AddMusicDirectoryClicked);
+        assert!(matches!(
+            effect,
+            Effect::SaveSettings(settings) if settings.music_directories.len() == 2
+        ));
+        assert!(ui.crawling_music);
+        assert!(ui.music_directory_draft.is_empty());
+
+        update(
+            &mut ui,
+            Message::MusicDirectoryChanged("Not A Directory".into()),
+        );
+        let effect = update(&mut ui, Message::AddMusicDirectoryClicked);
+        assert!(matches!(effect, Effect::None));
+        assert!(ui.toast.is_some());
+    }
+
+    #[test]
+    fn a_finished_crawl_summarizes_its_changes() {
+        let mut ui = Ui::new();
+        let mut crawled = fake_album();
+        crawled.changes = AlbumChanges {
+            album: Reconciled::Added,
+            songs_added: crawled.songs.len(),
+            songs_updated: 0,
+        };
+
+        update(&mut ui, crawled_album_message(&crawled));
+        update(&mut ui, Message::FromCrawler(CrawlerMessage::Done));
+
+        let summary = ui.scan_summary.as_ref().unwrap();
+        assert_eq!(
+            summary.albums,
+            vec![(AlbumChange::Added, "Album Title".to_string())]
+        );
+        assert!(ui.scan_changes.is_empty());
+
+        // an unchanged crawl keeps the last summary
+        ui.crawling_music = true;
+        update(&mut ui, Message::FromCrawler(CrawlerMessage::Done));
+        assert!(ui.scan_summary.is_some());
+
+        update(&mut ui, Message::DismissScanSummaryClicked);
+        assert!(ui.scan_summary.is_none());
+    }
+
+    #[test]
+    fn background_errors_are_notified_until_dismissed() {
+        let mut ui = Ui::new();
+        ui.crawling_music = true;
+
+        let locked = CrawlerMessage::DbError("database is locked".to_string());
+        let effect = update(&mut ui, Message::FromCrawler(locked));
+        assert!(!ui.crawling_music);
+        let Effect::Notify(notification) = effect else {
+            panic!("expected a notification, got {effect:?}");
+        };
+        assert_eq!(notification.severity, notification::Severity::Error);
+        assert!(notification.message.contains("database is locked"));
+
+        ui.current_song = Some(CurrentSong {
+            id: SongId::new(1),
+            album_id: AlbumId::new(1),
+            title: "Song".to_string(),
+            album: None,
+            artist: None,
+            playing: true,
+            total_seconds: 200,
+            ab_loop: None,
+        });
+        let effect = update(&mut ui, Message::FromAudio(AudioMessage::NoOutputDevice));
+        assert!(matches!(effect, Effect::Notify(_)));
+        assert!(!ui.current_song.as_ref().unwrap().playing);
+
+        ui.notifications.push(notification);
+        update(&mut ui, Message::DismissNotificationClicked(0));
+        assert!(ui.notifications.is_empty());
+    }
 
-    let row = row![album_image, album_info, songs_list].spacing(10);
+    #[test]
+    fn unplayable_songs_are_marked_and_notified() {
+        let mut ui = Ui::new();
+        let crawled = fake_album();
+        let album_id = crawled.album.id;
+        ui.music_cache.add_crawled_album(crawled);
 
-    Element::from(row)
-}
+        let reason = "file not found: Second".to_string();
+        let message = AudioMessage::SongUnplayable(SongId::new(2), reason);
+        let effect = update(&mut ui, Message::FromAudio(message));
 
-fn view_album_image(image_bytes: Option<&RgbaBytes>) -> Element<'_, Message> {
-    let length = Length::Fixed(IMAGE_SIZE as f32);
+        let Effect::Notify(notification) = effect else {
+            panic!("expected a notification, got {effect:?}");
+        };
+        assert!(notification.message.contains("Second"));
+        assert!(ui.music_cache.get_song(&SongId::new(2)).unwrap().unplayable);
+        let cached = ui.music_cache.get_cached_album(&album_id).unwrap();
+        assert!(cached.songs[1].unplayable);
+        assert!(!cached.songs[0].unplayable);
+    }
 
-    let Some(image_bytes) = image_bytes else {
-        return Space::new(length, length).into();
-    };
+    #[test]
+    fn a_crash_report_is_offered_until_opened() {
+        let mut ui = Ui::new();
+        let report_path = Utf8PathBuf::from("/data/crash_reports/crash-1.txt");
+        ui.crash_report = Some(report_path.clone());
 
-    Image::new(image_bytes)
-        .width(length)
-        .height(length)
-        .content_fit(ContentFit::ScaleDown)
-        .into()
-}
+        let effect = update(&mut ui, Message::OpenCrashReportClicked);
+        assert!(matches!(effect, Effect::OpenCrashReport(path) if path == report_path));
+        assert!(ui.crash_report.is_none());
 
-fn song_row_status(
-    current_song: &Option<CurrentSong>,
-    hovered_song_id: Option<SongId>,
-    song_row_id: SongId,
-) -> SongRowStatus {
-    match current_song {
-        Some(song) if song.id == song_row_id && song.playing => SongRowStatus::Playing,
-        Some(song) if song.id == song_row_id => SongRowStatus::Paused,
-        _ if hovered_song_id == Some(song_row_id) => SongRowStatus::Hovered,
-        _ => SongRowStatus::Blank,
+        let effect = update(&mut ui, Message::OpenCrashReportClicked);
+        assert!(matches!(effect, Effect::None));
     }
-}
 
-#[derive(Debug, Eq, PartialEq)]
-enum SongRowStatus {
-    /// currently playing - show pause button
-    Playing,
-    /// currently paused - show play_paused button
-    Paused,
-    /// Both hovered and not currently playing - show play from start button
-    Hovered,
-    /// Both not playing and not hovered - show nothing
-    Blank,
-}
+    #[test]
+    fn a_damaged_database_is_not_crawled_until_the_user_continues() {
+        let mut ui = Ui::new();
+        ui.set_database_damage(Some(DatabaseDamage {
+            problems: vec!["row 3 missing from index".to_string()],
+            backup_time: Some(SystemTime::now()),
+        }));
+        assert!(!ui.crawling_music);
+
+        update(&mut ui, Message::RescanClicked);
+        assert!(!ui.crawling_music);
+
+        ui.settings.music_directories = vec!["Music".into(), "Podcasts".into()];
+        update(&mut ui, Message::RemoveMusicDirectoryClicked(0));
+        assert!(!ui.crawling_music);
+
+        let effect = update(&mut ui, Message::ContinueWithDamagedDatabaseClicked);
+        assert!(matches!(effect, Effect::ContinueWithDamagedDatabase));
+        assert!(ui.database_damage.is_none());
+        assert!(ui.crawling_music);
+
+        let effect = update(&mut ui, Message::ContinueWithDamagedDatabaseClicked);
+        assert!(matches!(effect, Effect::None));
+    }
 
-/// A song in the album table
-fn view_song_row(song: &Song, status: SongRowStatus) -> Element<'_, Message> {
-    let button_slot: Element<'_, Message> = match status {
-        SongRowStatus::Playing => button(icons::pause())
-            .on_press(Message::PauseClicked)
-            .style(no_background())
-            .into(),
+    #[test]
+    fn a_damaged_database_is_restored_only_from_a_backup() {
+        let mut ui = Ui::new();
+        ui.database_damage = Some(DatabaseDamage {
+            problems: vec!["row 3 missing from index".to_string()],
+            backup_time: None,
+        });
+
+        let effect = update(&mut ui, Message::RestoreDatabaseClicked);
+        assert!(matches!(effect, Effect::None));
+
+        ui.database_damage = Some(DatabaseDamage {
+            problems: vec!["row 3 missing from index".to_string()],
+            backup_time: Some(SystemTime::now()),
+        });
+
+        let effect = update(&mut ui, Message::RestoreDatabaseClicked);
+        assert!(matches!(effect, Effect::RestoreDatabase));
+        assert!(ui.database_damage.is_none());
+
+        assert_eq!(format_backup_age(Duration::from_secs(60)), "today");
+        assert_eq!(
+            format_backup_age(Duration::from_secs(3 * 24 * 60 * 60)),
+            "3 days ago"
+        );
+    }
 
-        SongRowStatus::Paused => button(icons::play())
-            .on_press(Message::PlayPausedClicked)
-            .style(no_background())
-            .into(),
+    #[test]
+    fn album_notes_are_saved_and_cleared() {
+        let mut ui = Ui::new();
+        let crawled = fake_album();
+        let album_id = crawled.album.id;
+        update(&mut ui, crawled_album_message(&crawled));
+
+        update(&mut ui, Message::EditAlbumNotesClicked(album_id));
+        assert_eq!(ui.album_notes_draft.as_ref().unwrap().notes, "");
+        update(
+            &mut ui,
+            Message::AlbumNotesDraftChanged(
+                " Ripped from the 1978 pressing ".to_string(),
+            ),
+        );
+        let effect = update(&mut ui, Message::SaveAlbumNotesClicked);
+        let expected = "Ripped from the 1978 pressing";
+        assert!(matches!(effect, Effect::SaveAlbumNotes(id, Some(notes))
+            if id == album_id && notes == expected));
+        assert!(ui.album_notes_draft.is_none());
+        let album = ui.music_cache.get_album(&album_id).unwrap();
+        assert_eq!(album.notes.as_deref(), Some(expected));
+
+        // the draft starts from the saved notes, and blank clears them
+        update(&mut ui, Message::EditAlbumNotesClicked(album_id));
+        assert_eq!(ui.album_notes_draft.as_ref().unwrap().notes, expected);
+        update(&mut ui, Message::AlbumNotesDraftChanged("  ".to_string()));
+        let effect = update(&mut ui, Message::SaveAlbumNotesClicked);
+        assert!(matches!(effect, Effect::SaveAlbumNotes(_, None)));
+        assert!(ui.music_cache.get_album(&album_id).unwrap().notes.is_none());
+    }
 
-        SongRowStatus::Hovered => button(icons::play())
-            .on_press(Message::PlaySongClicked(song.id))
-            .style(no_background())
-            .into(),
+    #[test]
+    fn renamed_albums_keep_their_tags_to_go_back_to() {
+        let mut ui = Ui::new();
+        let crawled = fake_album();
+        let album_id = crawled.album.id;
+        update(&mut ui, crawled_album_message(&crawled));
+
+        update(&mut ui, Message::RenameAlbumClicked(album_id));
+        let draft = ui.album_rename_draft.as_ref().unwrap();
+        assert_eq!(
+            (draft.title.as_str(), draft.artist.as_str()),
+            ("Album Title", "Fake Artist")
+        );
 
-        SongRowStatus::Blank => {
-            text(song.track_number.map(|n| n.to_string()).unwrap_or_default())
-                .width(MAGIC_SVG_SIZE)
-                .height(MAGIC_SVG_SIZE)
-                .horizontal_alignment(alignment::Horizontal::Center)
-                .vertical_alignment(alignment::Vertical::Center)
-                .into()
-        }
-    };
+        update(
+            &mut ui,
+            Message::AlbumTitleDraftChanged(" Better Title ".to_string()),
+        );
+        let effect = update(&mut ui, Message::SaveAlbumRenameClicked);
+        let expected = AlbumRename {
+            title: Some("Better Title".to_string()),
+            artist: None,
+        };
+        assert!(matches!(effect, Effect::RenameAlbum(id, renamed)
+            if id == album_id && renamed == expected));
+        assert!(ui.album_rename_draft.is_none());
+
+        let album = ui.music_cache.get_album(&album_id).unwrap();
+        assert_eq!(album.display_title(), Some("Better Title"));
+        assert_eq!(album.artist.as_deref(), Some("Fake Artist"));
+        assert!(album.is_renamed());
+
+        update(&mut ui, Message::RenameAlbumClicked(album_id));
+        let effect = update(&mut ui, Message::ResetAlbumRenameClicked);
+        assert!(matches!(effect, Effect::RenameAlbum(_, renamed)
+            if renamed == AlbumRename::default()));
+        let album = ui.music_cache.get_album(&album_id).unwrap();
+        assert_eq!(album.display_title(), Some("Album Title"));
+        assert!(!album.is_renamed());
+    }
 
-    let duration = format_seconds(song.total_seconds as f64);
+    #[test]
+    fn an_album_export_runs_one_at_a_time_and_remembers_its_destination() {
+        let mut ui = Ui::new();
+        let crawled = fake_album();
+        let album_id = crawled.album.id;
+        update(&mut ui, crawled_album_message(&crawled));
+
+        update(&mut ui, Message::ExportAlbumClicked(album_id));
+        update(
+            &mut ui,
+            Message::ExportDestinationChanged(" /media/usb ".to_string()),
+        );
+        update(&mut ui, Message::ExportFormatSelected(ExportFormat::Zip));
+        update(&mut ui, Message::StartExportClicked);
+        assert!(ui.album_export_draft.is_none());
+
+        let export = ui.album_export.as_ref().unwrap();
+        assert_eq!(export.progress, (0, 5));
+        assert_eq!(export.request.name, "Fake Artist - Album Title");
+        assert_eq!(export.request.destination, "/media/usb");
+        assert_eq!(export.request.format, ExportFormat::Zip);
+
+        // NOTE a second export waits for the first
+        update(&mut ui, Message::ExportAlbumClicked(album_id));
+        update(&mut ui, Message::StartExportClicked);
+        assert_eq!(
+            ui.album_export.as_ref().unwrap().request.format,
+            ExportFormat::Zip
+        );
 
-    let hoverable = Hoverable::new(
-        row![
-            button_slot,
-            text(song.display_title().unwrap_or_default()).width(Length::Fill),
-            text(duration),
-            horizontal_space(Length::Fixed(10f32))
-        ]
-        .width(Length::Fill)
-        .align_items(Alignment::Center)
-        .spacing(10)
-        .into(),
-        Message::HoveredSong(song.id),
-        Message::UnhoveredSong(song.id),
-    )
-    .padding(2);
+        update(&mut ui, Message::FromExport(ExportMessage::Progress(3, 5)));
+        assert_eq!(ui.album_export.as_ref().unwrap().progress, (3, 5));
 
-    Element::from(hoverable)
-}
+        let finished = Ok(Utf8PathBuf::from(
+            "/media/usb/Fake Artist - Album Title.zip",
+        ));
+        update(
+            &mut ui,
+            Message::FromExport(ExportMessage::Finished(finished)),
+        );
+        assert!(ui.album_export.is_none());
+        assert!(ui.toast.is_some());
 
-// 24 (svg) + 5 + 5 (default button padding)
-const MAGIC_SVG_SIZE: Length = Length::Fixed(34f32);
+        update(&mut ui, Message::ExportAlbumClicked(album_id));
+        let draft = ui.album_export_draft.as_ref().unwrap();
+        assert_eq!(draft.destination, " /media/usb ");
+    }
 
-/// The bottom row with the play/pause button and current song info
-fn view_bottom_row<'a>(
-    current_song: &'a Option<CurrentSong>,
-    progress: &'a Option<ProgressDisplay>,
-) -> Element<'a, Message> {
-    let row_content = match (current_song, progress) {
-        (Some(current_song), Some(progress)) => {
-            let play_pause_button = if current_song.playing {
-                button(icons::pause())
-                    .on_press(Message::PauseClicked)
-                    .style(no_background())
-            } else {
-                button(icons::play())
-                    .on_press(Message::PlayPausedClicked)
-                    .style(no_background())
-            };
+    #[test]
+    fn closing_hides_to_the_tray_only_when_there_is_one() {
+        let mut ui = Ui::new();
+        ui.settings.close_to_tray = true;
+        let close = || Message::Native(Event::Window(WindowEvent::CloseRequested));
 
-            let elapsed = match progress {
-                ProgressDisplay::Dragging(proportion) => {
-                    f64::from(*proportion) * current_song.total_seconds as f64
-                }
+        let effect = update(&mut ui, close());
+        assert!(matches!(effect, Effect::CloseWindow));
 
-                ProgressDisplay::FromAudio(times) => times.elapsed.seconds as f64,
-            };
+        ui.tray_available = true;
+        let effect = update(&mut ui, close());
+        assert!(matches!(effect, Effect::HideWindow));
+        assert!(tray_state(&ui).window_hidden);
 
-            let elapsed = format_seconds(elapsed);
-            let total = format_seconds(current_song.total_seconds as f64);
-            let duration = format!("{elapsed} / {total}");
+        let effect = update(&mut ui, Message::FromTray(TrayAction::ToggleWindow));
+        assert!(matches!(effect, Effect::FocusWindow));
+        assert!(!ui.window_hidden);
 
-            let left_side = row![
-                text(&current_song.title)
-                    .width(Length::Fill)
-                    .height(Length::Fill)
-                    .horizontal_alignment(alignment::Horizontal::Center)
-                    .vertical_alignment(alignment::Vertical::Center),
-                button(icons::back())
-                    .on_press(Message::BackClicked)
-                    .style(no_background()),
-            ]
-            .height(MAGIC_SVG_SIZE)
-            .width(Length::FillPortion(1));
+        update(&mut ui, close());
+        let effect = update(&mut ui, Message::FromInstance(Handoff::Show));
+        assert!(matches!(effect, Effect::FocusWindow));
+        assert!(!ui.window_hidden);
 
-            let right_side = row![
-                button(icons::forward())
-                    .on_press(Message::ForwardClicked)
-                    .style(no_background()),
-                view_current_album_artist(current_song)
-                    .width(Length::Fill)
-                    .height(Length::Fill)
-                    .align_items(Alignment::Center),
-                text(duration)
-                    .height(Length::Fill)
-                    .horizontal_alignment(alignment::Horizontal::Center)
-                    .vertical_alignment(alignment::Vertical::Center),
-            ]
-            .height(MAGIC_SVG_SIZE)
-            .width(Length::FillPortion(1));
+        ui.settings.close_to_tray = false;
+        let effect = update(&mut ui, close());
+        assert!(matches!(effect, Effect::CloseWindow));
+    }
 
-            row![left_side, play_pause_button, right_side,].height(MAGIC_SVG_SIZE)
-        }
+    #[test]
+    fn lyrics_load_for_each_song_while_their_view_is_open() {
+        let mut ui = Ui::new();
+        let song = |id| CurrentSong {
+            id: SongId::new(id),
+            album_id: AlbumId::new(1),
+            title: format!("Song {id}"),
+            album: None,
+            artist: None,
+            playing: true,
+            total_seconds: 200,
+            ab_loop: None,
+        };
+        ui.current_song = Some(song(1));
+        assert_eq!(lyrics_to_load(&mut ui), None);
+
+        update(&mut ui, Message::LibraryViewClicked(LibraryView::Lyrics));
+        assert_eq!(lyrics_to_load(&mut ui), Some(SongId::new(1)));
+        assert_eq!(lyrics_to_load(&mut ui), None);
+
+        // NOTE these arrive after the song changed, so they're for the wrong one
+        ui.current_song = Some(song(2));
+        assert_eq!(lyrics_to_load(&mut ui), Some(SongId::new(2)));
+        let lyrics = Some(Lyrics::parse("[00:01.00]Wrong song"));
+        update(&mut ui, Message::LoadedLyrics(SongId::new(1), lyrics));
+        assert_eq!(ui.lyrics, None);
+
+        let lyrics = Some(Lyrics::parse("Right song"));
+        update(
+            &mut ui,
+            Message::LoadedLyrics(SongId::new(2), lyrics.clone()),
+        );
+        assert_eq!(ui.lyrics, lyrics);
 
-        _ => row![
-            Space::new(Length::Fill, MAGIC_SVG_SIZE),
-            button(icons::play()).style(no_background()),
-            Space::new(Length::Fill, MAGIC_SVG_SIZE),
-        ]
-        .height(MAGIC_SVG_SIZE),
-    };
+        update(&mut ui, Message::LibraryViewClicked(LibraryView::Lyrics));
+        assert_eq!(lyrics_to_load(&mut ui), None);
+        assert_eq!(ui.lyrics, None);
+    }
 
-    let bottom_row = row_content.width(Length::Fill).spacing(10);
+    #[test]
+    fn the_player_only_fills_the_sample_ring_while_a_visualizer_is_chosen() {
+        let mut ui = Ui::new();
+        assert_eq!(sample_tap(&ui), None);
 
-    Element::from(bottom_row)
-}
+        let effect = update(&mut ui, Message::VisualizerSelected(VisualizerStyle::Bars));
+        assert!(matches!(effect, Effect::SaveSettings(settings)
+            if settings.visualizer == VisualizerStyle::Bars));
+        assert_eq!(sample_tap(&ui), Some(ui.sample_ring.clone()));
 
-fn view_current_album_artist(current: &CurrentSong) -> Row<'_, Message> {
-    let mut children: Vec<Element<'_, Message>> = Vec::new();
+        update(&mut ui, Message::VisualizerSelected(VisualizerStyle::Off));
+        assert_eq!(sample_tap(&ui), None);
+    }
 
-    children.push(horizontal_space(Length::Fill).into());
+    #[test]
+    fn low_power_turns_off_the_visualizer() {
+        let mut ui = Ui::new();
+        update(&mut ui, Message::VisualizerSelected(VisualizerStyle::Bars));
+        assert!(visualizer_on(&ui));
 
-    let mut label = String::new();
-    if let Some(album) = &current.album {
-        label.push_str(album);
-    }
-    if current.album.is_some() && current.artist.is_some() {
-        label.push_str(" - ");
-    }
-    if let Some(artist) = &current.artist {
-        label.push_str(artist);
+        let effect = update(&mut ui, Message::PowerModeSelected(PowerMode::Saver));
+        assert!(matches!(effect, Effect::SetLowPower(true)));
+        assert!(!visualizer_on(&ui));
+        assert_eq!(sample_tap(&ui), None);
+
+        update(&mut ui, Message::PowerModeSelected(PowerMode::Full));
+        assert_eq!(sample_tap(&ui), Some(ui.sample_ring.clone()));
     }
-    children.push(text(label).into());
 
-    children.push(horizontal_space(Length::Fill).into());
+    #[test]
+    fn waveforms_load_once_the_queue_has_the_playing_song() {
+        let mut ui = Ui::new();
+        let saved = SavedLibrary {
+            albums: vec![fake_album()],
+            queue: None,
+        };
+        update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+        let song = ui.music_cache.get_queued_song(SongId::new(2)).unwrap();
+        let song_path = song.path.clone();
+
+        ui.current_song = Some(CurrentSong {
+            id: SongId::new(2),
+            album_id: AlbumId::new(1),
+            title: "Song 2".to_string(),
+            album: None,
+            artist: None,
+            playing: true,
+            total_seconds: 200,
+            ab_loop: None,
+        });
+        assert_eq!(waveform_to_load(&mut ui), None);
+
+        let queue = QueueDisplay { songs: vec![song], current_index: 0 };
+        let message = AudioMessage::QueueUpdate(Some(Box::new(queue)));
+        update(&mut ui, Message::FromAudio(message));
+        assert_eq!(waveform_to_load(&mut ui), Some((SongId::new(2), song_path)));
+        assert_eq!(waveform_to_load(&mut ui), None);
+
+        update(
+            &mut ui,
+            Message::LoadedWaveform(SongId::new(1), Some(vec![1; 4])),
+        );
+        assert_eq!(ui.waveform, None);
+        update(
+            &mut ui,
+            Message::LoadedWaveform(SongId::new(2), Some(vec![2; 4])),
+        );
+        assert_eq!(ui.waveform, Some(vec![2; 4]));
 
-    Row::with_children(children).spacing(10)
-}
+        ui.current_song = None;
+        assert_eq!(waveform_to_load(&mut ui), None);
+        assert_eq!(ui.waveform, None);
+    }
 
-fn format_seconds(seconds: f64) -> String {
-    let minutes = seconds / 60.0;
-    let whole_minutes = minutes.floor();
-    let seconds = (minutes - whole_minutes) * 60.0;
-    let seconds = seconds.round();
+    #[test]
+    fn album_runtimes_round_to_minutes() {
+        let crawled = fake_album();
+        let mut album = CachedAlbum {
+            album: crawled.album,
+            songs: crawled.songs,
+            art: None,
+            placeholder_color: None,
+        };
+        assert_eq!(album_runtime(&album), "5 tracks · 8 min");
 
-    format!("{whole_minutes}:{seconds:02}")
-}
+        album.songs.truncate(1);
+        album.album.total_seconds = 2 * 3600 + 5 * 60 + 40;
+        assert_eq!(album_runtime(&album), "1 track · 2 hr 6 min");
+    }
 
-#[cfg(test)]
-mod tests {
-    use std::{assert_eq, str::FromStr};
+    #[test]
+    fn musicbrainz_lookups_run_one_at_a_time_and_fill_the_cache() {
+        let mut ui = Ui::new();
+        let crawled = fake_album();
+        let album_id = crawled.album.id;
+        update(&mut ui, crawled_album_message(&crawled));
 
-    use camino::Utf8PathBuf;
+        let effect = update(&mut ui, Message::FillFromMusicBrainzClicked(album_id));
+        assert!(
+            matches!(effect, Effect::FillFromMusicBrainz(album) if album.id == album_id)
+        );
+        let effect = update(&mut ui, Message::FillFromMusicBrainzClicked(album_id));
+        assert!(matches!(effect, Effect::None));
+
+        let mut album = crawled.album.clone();
+        album.release_date = Some("1994-08-22".to_string());
+        let mut song = crawled.songs[0].clone();
+        song.title = Some("Mysterons".to_string());
+        update(
+            &mut ui,
+            Message::FilledFromMusicBrainz(Ok((album, vec![song]))),
+        );
 
-    use super::*;
-    use crate::test_util::*;
+        assert_eq!(ui.musicbrainz_filling, None);
+        let cached = ui.music_cache.get_cached_album(&album_id).unwrap();
+        assert_eq!(cached.album.release_date.as_deref(), Some("1994-08-22"));
+        assert_eq!(cached.songs[0].title.as_deref(), Some("Mysterons"));
+        assert_eq!(cached.songs[1].title.as_deref(), Some("Second"));
+
+        let failed = Err("No close match for Album Title on MusicBrainz.".to_string());
+        update(&mut ui, Message::FillFromMusicBrainzClicked(album_id));
+        update(&mut ui, Message::FilledFromMusicBrainz(failed));
+        assert_eq!(ui.musicbrainz_filling, None);
+        assert!(ui.toast.is_some());
+    }
 
     #[test]
-    fn crawled_album_with_no_original_art_sends_no_resize_request() {
+    fn right_click_menus_queue_albums_and_show_folders() {
         let mut ui = Ui::new();
+        let album = fake_album();
+        let album_id = album.album.id;
+        let saved = SavedLibrary { albums: vec![album], queue: None };
+        update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
+
+        let target = MenuTarget::Album(album_id);
+        update(&mut ui, Message::MenuOpened(target));
+        assert_eq!(ui.context_menu, Some(target));
+
+        let effect = update(&mut ui, Message::AddAlbumToQueueClicked(album_id));
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::EnqueueAll(songs)) if songs.len() == 5
+        ));
+        update(&mut ui, Message::MenuClosed);
+        assert_eq!(ui.context_menu, None);
+
+        let effect = update(&mut ui, Message::RenameAlbumClicked(album_id));
+        assert!(matches!(effect, Effect::None));
+        assert!(ui.album_rename_draft.is_some());
+        assert!(matches!(ui.library_view, LibraryView::Album(id) if id == album_id));
+
+        let effect = update(&mut ui, Message::ShowInFileManagerClicked("/music".into()));
+        assert!(matches!(effect, Effect::ShowInFileManager(dir) if dir == "/music"));
+    }
 
+    #[test]
+    fn folders_go_up_to_the_music_directories_and_enqueue_in_path_order() {
+        let mut ui = Ui::new();
+        ui.settings.music_directories = vec!["/music".into()];
         let mut crawled = fake_album();
-        crawled.cached_art = None;
-        crawled.album.original_art = None;
+        for song in &mut crawled.songs {
+            song.file = format!("/music/Artist/Album/{}.flac", song.file).into();
+        }
+        let saved = SavedLibrary { albums: vec![crawled], queue: None };
+        update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
 
-        let message = crawled_album_message(&crawled);
+        let listing = |path: &str| FolderListing {
+            path: path.into(),
+            folders: Vec::new(),
+            songs: Vec::new(),
+        };
+        update(&mut ui, Message::ReadFolder(Ok(listing("/music/Artist"))));
+        let effect = update(&mut ui, Message::FolderUpClicked);
+        assert!(matches!(effect, Effect::ReadFolder(path) if path == "/music"));
 
-        let effect = update(&mut ui, message);
+        update(&mut ui, Message::ReadFolder(Ok(listing("/music"))));
+        update(&mut ui, Message::FolderUpClicked);
+        assert_eq!(ui.folder, None);
 
-        assert!(matches!(effect, Effect::None))
+        let effect = update(
+            &mut ui,
+            Message::EnqueueFolderClicked("/music/Artist".into()),
+        );
+        let Effect::ToAudio(AudioAction::EnqueueAll(songs)) = effect else {
+            panic!("expected the folder's songs to be enqueued");
+        };
+        let song_ids: Vec<i32> = songs.iter().map(|song| song.id.unpack()).collect();
+        assert_eq!(song_ids, [5, 1, 4, 2, 3]);
+
+        let effect = update(&mut ui, Message::EnqueueFolderClicked("/mnt/Mixes".into()));
+        assert!(
+            matches!(effect, Effect::ReadDropped(path, None) if path == "/mnt/Mixes")
+        );
     }
 
     #[test]
-    fn crawled_album_with_cached_resized_art_sends_no_resize_request() {
+    fn playlist_files_from_the_crawler_play_and_export() {
         let mut ui = Ui::new();
+        let saved = SavedLibrary {
+            albums: vec![fake_album()],
+            queue: None,
+        };
+        update(&mut ui, Message::LoadedSavedLibrary(Box::new(saved)));
 
-        let mut crawled = fake_album();
-        crawled.cached_art = Some(RgbaBytes::empty());
-        crawled.album.original_art = Some(Utf8PathBuf::from_str("original").unwrap());
-
-        let message = crawled_album_message(&crawled);
-
-        let effect = update(&mut ui, message);
-
-        assert!(matches!(effect, Effect::None))
+        let playlist = Playlist {
+            id: PlaylistId::new(1),
+            file: "/music/Mix.m3u".into(),
+            name: "Mix".to_string(),
+            songs: vec![SongId::new(3), SongId::new(1)],
+        };
+        let playlists = CrawlerMessage::Playlists(vec![playlist.clone()]);
+        update(&mut ui, Message::FromCrawler(playlists));
+
+        let effect = update(&mut ui, Message::PlayPlaylistClicked(playlist.id));
+        assert!(matches!(
+            effect,
+            Effect::ToAudio(AudioAction::PlayQueue(queue))
+                if queue.current.id == SongId::new(3) && queue.next[0].id == SongId::new(1)
+        ));
+
+        let songs = PlaylistSongs::Listed(playlist.songs.clone());
+        let export = Message::ExportPlaylistClicked("Mix".to_string(), songs);
+        assert!(matches!(update(&mut ui, export.clone()), Effect::None));
+
+        update(
+            &mut ui,
+            Message::PlaylistExportDestinationChanged("/media/usb".to_string()),
+        );
+        let effect = update(&mut ui, export);
+        assert!(matches!(
+            effect,
+            Effect::ExportPlaylist(export) if export.file() == "/media/usb/Mix.m3u8"
+        ));
     }
 
     #[test]
-    fn crawled_album_with_no_cached_resized_art_sends_resize_request() {
+    fn the_mini_player_goes_back_to_the_last_full_size() {
         let mut ui = Ui::new();
-        let mut crawled = fake_album();
-        crawled.cached_art = None;
-        crawled.album.original_art = Some(Utf8PathBuf::from_str("original").unwrap());
+        let resized = |width, height| {
+            Message::Native(Event::Window(WindowEvent::Resized { width, height }))
+        };
 
-        let message = crawled_album_message(&crawled);
+        let effect = update(&mut ui, Message::CompactClicked);
+        assert!(ui.compact);
+        assert!(matches!(effect, Effect::ResizeWindow(480, 180)));
 
-        let effect = update(&mut ui, message);
+        let effect = update(&mut ui, Message::CompactClicked);
+        assert!(!ui.compact);
+        assert!(matches!(effect, Effect::ResizeWindow(1024, 768)));
 
-        match effect {
-            Effect::ToResizer(ResizeRequest { album_id, source_path, .. }) => {
-                assert_eq!(album_id, crawled.album.id);
-                assert_eq!(source_path, crawled.album.original_art.unwrap());
-            }
-            _ => panic!("expected resize request"),
-        }
+        update(&mut ui, resized(1280, 900));
+        let effect = key_action(&mut ui, KeyAction::ToggleCompact);
+        assert!(matches!(effect, Effect::ResizeWindow(480, 180)));
+
+        // NOTE the compact size itself isn't remembered
+        update(&mut ui, resized(480, 180));
+        let effect = key_action(&mut ui, KeyAction::ToggleCompact);
+        assert!(matches!(effect, Effect::ResizeWindow(1280, 900)));
     }
 
     fn crawled_album_message(crawled: &CrawledAlbum) -> Message {
@@ -0,0 +1,157 @@
+//! Looks up art online for albums that have none, from MusicBrainz and the Cover Art Archive.
+//! Downloads go to the resizer like any other original art
+
+use std::collections::HashSet;
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use flume::{Receiver, Sender};
+use log::{error, info};
+
+use crate::app::musicbrainz::{search_releases, USER_AGENT};
+use crate::app::resizer::ResizeRequest;
+use clef_db::queries::AlbumId;
+
+const COVER_ART_URL: &str = "https://coverartarchive.org/release";
+/// MusicBrainz allows about one request a second
+const LOOKUP_DELAY: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// Larger images are skipped rather than read into memory
+const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+/// The crawler picks this up as folder art during later scans
+const COVER_FILE_NAME: &str = "cover.jpg";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtLookup {
+    pub album_id: AlbumId,
+    pub album_title: String,
+    pub artist: Option<String>,
+    pub directory: Utf8PathBuf,
+}
+
+/// Looks up albums in order on their own thread, keeping to the rate limit.
+/// Each album is only looked up once per launch, so that a rescan doesn't repeat misses
+pub fn spawn_art_fetcher(
+    inbox: Receiver<ArtLookup>,
+    to_resizer: Sender<ResizeRequest>,
+    images_directory: Utf8PathBuf,
+) -> std::io::Result<()> {
+    thread::Builder::new()
+        .name("art fetcher".to_string())
+        .spawn(move || {
+            let mut looked_up = HashSet::new();
+
+            for lookup in inbox.iter() {
+                if !looked_up.insert(lookup.album_id) {
+                    continue;
+                }
+
+                match fetch_art(&lookup, &images_directory) {
+                    Ok(Some(source_path)) => {
+                        let request = ResizeRequest {
+                            album_id: lookup.album_id,
+                            album_title: lookup.album_title,
+                            source_path,
+                            already_resized: false,
+                        };
+                        to_resizer.send(request).unwrap_or_else(|e| {
+                            error!("failed to send to resizer thread: {e}")
+                        });
+                    }
+                    Ok(None) => info!("no art found online for {}", lookup.directory),
+                    Err(e) => {
+                        info!("failed to look up art for {}: {e}", lookup.directory)
+                    }
+                }
+
+                thread::sleep(LOOKUP_DELAY);
+            }
+        })?;
+
+    Ok(())
+}
+
+/// The downloaded file, or None when there's no confident match with front art
+fn fetch_art(
+    lookup: &ArtLookup,
+    images_directory: &Utf8Path,
+) -> anyhow::Result<Option<Utf8PathBuf>> {
+    let candidates = search_releases(&lookup.album_title, lookup.artist.as_deref())?;
+
+    let mut image = None;
+    for release_id in &candidates {
+        if let Some(front) = fetch_front(release_id)? {
+            image = Some(front);
+            break;
+        }
+    }
+    let Some(image) = image else {
+        return Ok(None);
+    };
+
+    // NOTE read-only or network music folders fall back to the resized cache
+    let beside_songs = lookup.directory.join(COVER_FILE_NAME);
+    if !beside_songs.exists() && std::fs::write(&beside_songs, &image).is_ok() {
+        return Ok(Some(beside_songs));
+    }
+    let cached = images_directory.join(format!(
+        "download_{}_{COVER_FILE_NAME}",
+        lookup.album_id.unpack()
+    ));
+    std::fs::write(&cached, &image)?;
+
+    Ok(Some(cached))
+}
+
+/// The release's front art, or None when the archive has none
+fn fetch_front(release_id: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    // NOTE the archive redirects to the image itself, which ureq follows
+    let response = ureq::get(&format!("{COVER_ART_URL}/{release_id}/front-500"))
+        .timeout(REQUEST_TIMEOUT)
+        .set("User-Agent", USER_AGENT)
+        .call();
+    let response = match response {
+        Ok(response) => response,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(Some(read_image(response.into_reader())?))
+}
+
+/// NOTE the image is saved beside the songs and crawled as folder art from then on,
+/// so a cut off or otherwise broken download is an error rather than a file
+fn read_image(reader: impl Read) -> anyhow::Result<Vec<u8>> {
+    let mut image = Vec::new();
+    reader.take(MAX_IMAGE_BYTES + 1).read_to_end(&mut image)?;
+    if image.len() as u64 > MAX_IMAGE_BYTES {
+        anyhow::bail!("the front art is over {MAX_IMAGE_BYTES} bytes");
+    }
+
+    image_rs::load_from_memory(&image).context("the front art doesn't decode")?;
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn only_whole_images_are_read() {
+        let mut png = Cursor::new(Vec::new());
+        image_rs::DynamicImage::new_rgb8(2, 2)
+            .write_to(&mut png, image_rs::ImageOutputFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+        assert_eq!(read_image(png.as_slice()).unwrap(), png);
+
+        assert!(read_image(&png[..png.len() / 2]).is_err());
+        assert!(read_image(b"<html>not found</html>".as_slice()).is_err());
+        assert!(read_image(std::io::repeat(0).take(MAX_IMAGE_BYTES + 1)).is_err());
+    }
+}
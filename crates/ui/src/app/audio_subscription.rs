@@ -1,4 +1,4 @@
-use flume::{Receiver, TryRecvError};
+use flume::{Receiver, RecvError};
 
 use crate::app::old_unfold::old_unfold;
 use clef_audio::player::AudioMessage;
@@ -21,21 +21,52 @@ pub fn audio_subscription(
     )
 }
 
+/// Waits for the next message, so the ui task sleeps while the player is idle
 async fn listen(
     state: AudioSubState,
     inbox: Receiver<AudioMessage>,
 ) -> (Option<AudioMessage>, AudioSubState) {
     if state == AudioSubState::Disconnected {
-        return (None, AudioSubState::Disconnected);
+        // NOTE AudioDied was already sent, and nothing else can arrive
+        return iced::futures::future::pending().await;
     }
 
-    match inbox.try_recv() {
+    match inbox.recv_async().await {
         Ok(msg) => (Some(msg), AudioSubState::Ready),
 
-        Err(TryRecvError::Empty) => (None, AudioSubState::Ready),
-
-        Err(TryRecvError::Disconnected) => {
+        Err(RecvError::Disconnected) => {
             (Some(AudioMessage::AudioDied), AudioSubState::Disconnected)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iced::futures::executor::block_on;
+    use iced::futures::FutureExt;
+
+    #[test]
+    fn a_disconnected_player_is_reported_once() {
+        let (to_ui, inbox) = flume::unbounded();
+        to_ui.send(AudioMessage::AudioDied).unwrap();
+
+        let (msg, state) = block_on(listen(AudioSubState::Ready, inbox.clone()));
+        assert!(matches!(msg, Some(AudioMessage::AudioDied)));
+        assert_eq!(state, AudioSubState::Ready);
+
+        // NOTE nothing is sent, so this waits rather than returning an empty message
+        assert!(listen(AudioSubState::Ready, inbox.clone())
+            .now_or_never()
+            .is_none());
+
+        drop(to_ui);
+        let (msg, state) = block_on(listen(AudioSubState::Ready, inbox.clone()));
+        assert!(matches!(msg, Some(AudioMessage::AudioDied)));
+        assert_eq!(state, AudioSubState::Disconnected);
+
+        assert!(listen(AudioSubState::Disconnected, inbox)
+            .now_or_never()
+            .is_none());
+    }
+}
@@ -0,0 +1,325 @@
+use iced::{keyboard, mouse};
+use iced_native::event::{self, Event};
+use iced_native::layout;
+use iced_native::overlay;
+use iced_native::renderer;
+use iced_native::widget::tree::{self, Tree};
+use iced_native::{
+    Clipboard, Element, Layout, Length, Point, Rectangle, Shell, Size, Vector, Widget,
+};
+
+/// Opens a menu where its content is right-clicked.
+/// The menu is only passed in while it's open, from the ui state;
+/// clicking anywhere or pressing escape closes it
+#[allow(missing_debug_implementations)]
+pub struct ContextMenu<'a, Message, Renderer> {
+    content: Element<'a, Message, Renderer>,
+    /// None = closed
+    menu: Option<Element<'a, Message, Renderer>>,
+    on_open: Message,
+    on_close: Message,
+}
+
+impl<'a, Message, Renderer> ContextMenu<'a, Message, Renderer>
+where
+    Renderer: iced_native::Renderer,
+{
+    pub fn new(
+        content: impl Into<Element<'a, Message, Renderer>>,
+        menu: Option<Element<'a, Message, Renderer>>,
+        on_open: Message,
+        on_close: Message,
+    ) -> Self {
+        Self {
+            content: content.into(),
+            menu,
+            on_open,
+            on_close,
+        }
+    }
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer>
+    for ContextMenu<'a, Message, Renderer>
+where
+    Message: 'a + Clone,
+    Renderer: iced_native::Renderer + 'a,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State::default())
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        let mut children = vec![Tree::new(&self.content)];
+        if let Some(menu) = &self.menu {
+            children.push(Tree::new(menu));
+        }
+
+        children
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        match &self.menu {
+            Some(menu) => tree.diff_children(&[&self.content, menu]),
+            None => tree.diff_children(std::slice::from_ref(&self.content)),
+        }
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) -> event::Status {
+        // NOTE a menu nested in the content takes the click first
+        if let event::Status::Captured = self.content.as_widget_mut().on_event(
+            &mut tree.children[0],
+            event.clone(),
+            layout,
+            cursor_position,
+            renderer,
+            clipboard,
+            shell,
+        ) {
+            return event::Status::Captured;
+        }
+
+        let bounds = layout.bounds();
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right))
+                if bounds.contains(cursor_position) =>
+            {
+                let state = tree.state.downcast_mut::<State>();
+                state.opened_at = cursor_position - bounds.position();
+                shell.publish(self.on_open.clone());
+
+                event::Status::Captured
+            }
+
+            _ => event::Status::Ignored,
+        }
+    }
+
+    fn layout(&self, renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        self.content.as_widget().layout(renderer, limits)
+    }
+
+    fn width(&self) -> Length {
+        self.content.as_widget().width()
+    }
+
+    fn height(&self) -> Length {
+        self.content.as_widget().height()
+    }
+
+    fn draw(
+        &self,
+        state: &Tree,
+        renderer: &mut Renderer,
+        theme: &<Renderer as iced_native::Renderer>::Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        viewport: &Rectangle,
+    ) {
+        self.content.as_widget().draw(
+            &state.children[0],
+            renderer,
+            theme,
+            style,
+            layout,
+            cursor_position,
+            viewport,
+        );
+    }
+
+    fn mouse_interaction(
+        &self,
+        state: &Tree,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.content.as_widget().mouse_interaction(
+            &state.children[0],
+            layout,
+            cursor_position,
+            viewport,
+            renderer,
+        )
+    }
+
+    fn overlay<'b>(
+        &'b mut self,
+        tree: &'b mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+    ) -> Option<overlay::Element<'b, Message, Renderer>> {
+        let opened_at = tree.state.downcast_ref::<State>().opened_at;
+        let (content_tree, menu_tree) = match tree.children.as_mut_slice() {
+            [content_tree, menu_tree] => (content_tree, Some(menu_tree)),
+            [content_tree] => (content_tree, None),
+            _ => return None,
+        };
+
+        match (&mut self.menu, menu_tree) {
+            (Some(menu), Some(menu_tree)) => {
+                let position = layout.position() + opened_at;
+                let menu_overlay = MenuOverlay {
+                    menu,
+                    tree: menu_tree,
+                    on_close: self.on_close.clone(),
+                };
+
+                Some(overlay::Element::new(position, Box::new(menu_overlay)))
+            }
+
+            _ => self
+                .content
+                .as_widget_mut()
+                .overlay(content_tree, layout, renderer),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct State {
+    /// Where the content was last right-clicked, from its top left
+    opened_at: Vector,
+}
+
+/// The open menu, drawn over everything else at the click
+struct MenuOverlay<'a, 'b, Message, Renderer> {
+    menu: &'b mut Element<'a, Message, Renderer>,
+    tree: &'b mut Tree,
+    on_close: Message,
+}
+
+impl<'a, 'b, Message, Renderer> overlay::Overlay<Message, Renderer>
+    for MenuOverlay<'a, 'b, Message, Renderer>
+where
+    Message: Clone,
+    Renderer: iced_native::Renderer,
+{
+    fn layout(&self, renderer: &Renderer, bounds: Size, position: Point) -> layout::Node {
+        let limits = layout::Limits::new(Size::ZERO, bounds);
+        let mut node = self.menu.as_widget().layout(renderer, &limits);
+
+        // NOTE near the window's right or bottom edge, the menu opens up or left instead
+        let size = node.size();
+        let x = if position.x + size.width > bounds.width {
+            position.x - size.width
+        } else {
+            position.x
+        };
+        let y = if position.y + size.height > bounds.height {
+            position.y - size.height
+        } else {
+            position.y
+        };
+        node.move_to(Point::new(x.max(0.0), y.max(0.0)));
+
+        node
+    }
+
+    fn draw(
+        &self,
+        renderer: &mut Renderer,
+        theme: &<Renderer as iced_native::Renderer>::Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor_position: Point,
+    ) {
+        self.menu.as_widget().draw(
+            self.tree,
+            renderer,
+            theme,
+            style,
+            layout,
+            cursor_position,
+            &layout.bounds(),
+        );
+    }
+
+    fn on_event(
+        &mut self,
+        event: Event,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) -> event::Status {
+        let status = self.menu.as_widget_mut().on_event(
+            self.tree,
+            event.clone(),
+            layout,
+            cursor_position,
+            renderer,
+            clipboard,
+            shell,
+        );
+
+        let over_menu = layout.bounds().contains(cursor_position);
+        match event {
+            // NOTE this follows the menu item's own message, which it sends on release
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
+                if over_menu =>
+            {
+                shell.publish(self.on_close.clone());
+                event::Status::Captured
+            }
+
+            Event::Mouse(mouse::Event::ButtonPressed(_)) if !over_menu => {
+                shell.publish(self.on_close.clone());
+                event::Status::Captured
+            }
+
+            Event::Keyboard(keyboard::Event::KeyPressed {
+                key_code: keyboard::KeyCode::Escape,
+                ..
+            }) => {
+                shell.publish(self.on_close.clone());
+                event::Status::Captured
+            }
+
+            _ => status,
+        }
+    }
+
+    fn mouse_interaction(
+        &self,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.menu.as_widget().mouse_interaction(
+            self.tree,
+            layout,
+            cursor_position,
+            viewport,
+            renderer,
+        )
+    }
+}
+
+impl<'a, Message, Renderer> From<ContextMenu<'a, Message, Renderer>>
+    for Element<'a, Message, Renderer>
+where
+    Message: Clone + 'a,
+    Renderer: iced_native::Renderer + 'a,
+{
+    fn from(context_menu: ContextMenu<'a, Message, Renderer>) -> Self {
+        Self::new(context_menu)
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use clef_audio::player::PlayerDisplay;
+use clef_db::queries::SongId;
+
+/// How the songs played since launch were converted for the output,
+/// for deciding whether to change the device's defaults
+#[derive(Debug, Default)]
+pub struct ConversionStats {
+    /// The song that was counted last; a song is counted once its output is open
+    last_counted: Option<SongId>,
+    pub songs: usize,
+    /// Songs resampled by clef, by (from, to) rate in Hz.
+    /// NOTE outputs that hand resampling to a sound server don't report it
+    pub resampled: BTreeMap<(u32, u32), usize>,
+    /// Songs by (source bit depth, output sample format), for sources that store one
+    pub bit_depths: BTreeMap<(u32, &'static str), usize>,
+}
+
+impl ConversionStats {
+    /// Counts the song, if it's new since the last update
+    pub fn record(&mut self, display: &PlayerDisplay) {
+        let Some(output) = &display.output else {
+            return;
+        };
+        if self.last_counted == Some(display.song_id) {
+            return;
+        }
+        self.last_counted = Some(display.song_id);
+        self.songs += 1;
+
+        if let Some(rates) = output.resampling {
+            *self.resampled.entry(rates).or_default() += 1;
+        }
+
+        if let Some(bits) = display.bits_per_sample {
+            *self
+                .bit_depths
+                .entry((bits, output.sample_format))
+                .or_default() += 1;
+        }
+    }
+
+    pub fn resampled_songs(&self) -> usize {
+        self.resampled.values().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clef_audio::player::{OutputTelemetry, ProgressTimes};
+
+    #[test]
+    fn each_song_is_counted_once_its_output_is_open() {
+        let display = |song_id, output| PlayerDisplay {
+            song_id: SongId::new(song_id),
+            playing: true,
+            times: ProgressTimes::ZERO,
+            output,
+            bits_per_sample: Some(24),
+            ab_loop: None,
+        };
+        let resampled = OutputTelemetry {
+            sample_format: "f32",
+            resampling: Some((44_100, 48_000)),
+            buffer_fill: None,
+            buffer_ms: None,
+            underruns: None,
+        };
+
+        let mut stats = ConversionStats::default();
+        stats.record(&display(1, None));
+        stats.record(&display(1, Some(resampled)));
+        stats.record(&display(1, Some(resampled)));
+        stats.record(&display(2, Some(resampled)));
+
+        assert_eq!(stats.songs, 2);
+        assert_eq!(stats.resampled_songs(), 2);
+        assert_eq!(stats.resampled.get(&(44_100, 48_000)), Some(&2));
+        assert_eq!(stats.bit_depths.get(&(24, "f32")), Some(&2));
+    }
+}
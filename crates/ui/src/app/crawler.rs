@@ -1,32 +1,190 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::Arc;
 
+use anyhow::Context;
 use camino::{Utf8Path, Utf8PathBuf};
 use clef_db::queries::DbError;
+use flume::{Receiver, Sender};
 use log::{error, info};
 
-use super::rgba::{load_cached_rgba_bmp, RgbaBytes};
+use iced::Color;
+
+use super::resizer::save_resized_image;
+use super::rgba::{load_rgba_from_memory, sample_average_color, RgbaBytes};
 use super::Config;
 use crate::app::old_unfold::old_unfold;
-use clef_audio::metadata::{decode_metadata, TagKey};
+use crate::app::playlist_file::{is_playlist_file, playlist_name, read_playlist};
+use crate::app::playlist_sync::sync_playlist_folder;
+use clef_audio::metadata::{
+    decode_metadata, is_supported_audio_extension, TagKey, AUDIO_EXTENSIONS,
+};
+use clef_audio::replay_gain::{parse_gain, parse_peak};
 use clef_db::{
-    queries::{self, Album, NewAlbum, NewSong, Song},
+    queries::{
+        self, Album, AlbumId, NewAlbum, NewSong, Playlist, PlaylistId, Reconciled, Song,
+        SongId,
+    },
     SqlitePool, SqlitePoolConn,
 };
 
 #[derive(Clone, Debug)]
 pub enum CrawlerMessage {
     NoAudioDirectory,
-    DbError,
+    /// Saving to the db failed, with the reason, ie that it's locked
+    DbError(String),
     CrawledAlbum(Box<CrawledAlbum>),
+    /// Songs whose files are gone, and albums left with no songs
+    Removed(RemovedFromLibrary),
+    /// Every playlist file in the music directories, after the songs are saved
+    Playlists(Vec<Playlist>),
+    /// Where the playlist folder's copies were kept, for playlists that
+    /// were also changed since the last sync; see sync_playlist_folder
+    PlaylistConflicts(Vec<Utf8PathBuf>),
     Done,
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct RemovedFromLibrary {
+    pub albums: Vec<AlbumId>,
+    pub songs: Vec<SongId>,
+}
+
 #[derive(Clone, Debug)]
 pub struct CrawledAlbum {
     pub album: Album,
     pub songs: Vec<Song>,
+    /// Art that was just resized; previously resized art is loaded when it's shown
     pub cached_art: Option<RgbaBytes>,
+    /// The average color of the original art, shown until the resized art is ready
+    pub placeholder_color: Option<Color>,
+    /// What this crawl changed in the db; always unchanged for saved or unsaved albums
+    pub changes: AlbumChanges,
+}
+
+/// What a crawl changed about one album, from the db's reconciliation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlbumChanges {
+    pub album: Reconciled,
+    pub songs_added: usize,
+    pub songs_updated: usize,
+}
+
+impl AlbumChanges {
+    pub const UNCHANGED: Self = Self {
+        album: Reconciled::Unchanged,
+        songs_added: 0,
+        songs_updated: 0,
+    };
+}
+
+/// How an album changed, in the list after a rescan
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlbumChange {
+    Added,
+    Updated,
+    Removed,
+}
+
+impl std::fmt::Display for AlbumChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Added => "Added",
+            Self::Updated => "Updated",
+            Self::Removed => "Removed",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// Everything a crawl changed in the library, for the summary after a rescan
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScanChanges {
+    /// Album titles, in crawl order
+    pub albums: Vec<(AlbumChange, String)>,
+    pub songs_added: usize,
+    pub songs_updated: usize,
+    pub songs_removed: usize,
+}
+
+impl ScanChanges {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn record_crawled(&mut self, title: &str, changes: &AlbumChanges) {
+        self.songs_added += changes.songs_added;
+        self.songs_updated += changes.songs_updated;
+
+        let album_change = match changes.album {
+            Reconciled::Added => AlbumChange::Added,
+            Reconciled::Updated => AlbumChange::Updated,
+            Reconciled::Unchanged if changes.songs_added + changes.songs_updated > 0 => {
+                AlbumChange::Updated
+            }
+            Reconciled::Unchanged => return,
+        };
+
+        self.albums.push((album_change, title.to_string()));
+    }
+
+    pub fn record_removed(&mut self, album_titles: Vec<String>, songs: usize) {
+        self.songs_removed += songs;
+        let removed = album_titles
+            .into_iter()
+            .map(|title| (AlbumChange::Removed, title));
+        self.albums.extend(removed);
+    }
+
+    /// ie '12 albums added, 3 removed; 48 songs updated'
+    pub fn summary(&self) -> String {
+        let album_count =
+            |change| self.albums.iter().filter(|(c, _)| *c == change).count();
+        let album_parts = counted_parts(
+            "album",
+            &[
+                (album_count(AlbumChange::Added), "added"),
+                (album_count(AlbumChange::Updated), "updated"),
+                (album_count(AlbumChange::Removed), "removed"),
+            ],
+        );
+        let song_parts = counted_parts(
+            "song",
+            &[
+                (self.songs_added, "added"),
+                (self.songs_updated, "updated"),
+                (self.songs_removed, "removed"),
+            ],
+        );
+
+        let summary: Vec<String> = [album_parts, song_parts]
+            .into_iter()
+            .filter(|parts| !parts.is_empty())
+            .collect();
+
+        if summary.is_empty() {
+            return "No changes".to_string();
+        }
+
+        summary.join("; ")
+    }
+}
+
+/// The non-zero counts, with the noun on the first one
+fn counted_parts(noun: &str, counts: &[(usize, &str)]) -> String {
+    let parts: Vec<String> = counts
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .enumerate()
+        .map(|(index, (count, verb))| match (index, count) {
+            (0, 1) => format!("1 {noun} {verb}"),
+            (0, count) => format!("{count} {noun}s {verb}"),
+            (_, count) => format!("{count} {verb}"),
+        })
+        .collect();
+
+    parts.join(", ")
 }
 
 #[derive(Clone, Debug)]
@@ -34,6 +192,8 @@ pub struct CrawledSong {
     pub path: Utf8PathBuf,
     pub tags: HashMap<TagKey, String>,
     pub total_seconds: u64,
+    /// See read_lyrics
+    pub lyrics: Option<String>,
 }
 
 pub fn crawler_subcription(
@@ -51,66 +211,414 @@ pub fn crawler_subcription(
 
 enum CrawlerState {
     Initial,
-    AlbumDirectories(Vec<Utf8PathBuf>, SqlitePoolConn),
+    AlbumDirectories(Box<CrawlWorkers>, SqlitePoolConn),
+    Pruned(SqlitePoolConn),
+    /// With the conflicts from syncing the playlist folder
+    PlaylistsSaved(Vec<Utf8PathBuf>),
     Final,
 }
 
+/// The most threads to read album directories with
+const MAX_CRAWL_WORKERS: usize = 4;
+
+/// How many directories the workers can read past the next one to save;
+/// this bounds the embedded art held while a slow directory is read
+const SCAN_AHEAD: usize = 2 * MAX_CRAWL_WORKERS;
+
+/// Reads album directories on worker threads, since decoding every song's tags
+/// is most of a crawl. Saving stays on the subscription, in directory order.
+struct CrawlWorkers {
+    results: Receiver<(usize, Option<ScannedAlbum>)>,
+    /// Hands directories to the workers; None once they all have been
+    to_workers: Option<Sender<(usize, AlbumDir)>>,
+    unsent: VecDeque<AlbumDir>,
+    /// Directories that were read ahead of an earlier one
+    finished: BTreeMap<usize, Option<ScannedAlbum>>,
+    next: usize,
+    sent: usize,
+    total: usize,
+}
+
+enum NextScan {
+    Scanned(ScannedAlbum),
+    /// A directory without songs, or that couldn't be read
+    Skipped,
+    Done,
+}
+
+impl CrawlWorkers {
+    fn spawn(album_dirs: Vec<AlbumDir>, extensions: &LibraryExtensions) -> Self {
+        let total = album_dirs.len();
+        let (to_workers, work) = flume::unbounded();
+        let (to_crawler, results) = flume::bounded(SCAN_AHEAD);
+        let worker_count = std::thread::available_parallelism()
+            .map(usize::from)
+            .unwrap_or(1)
+            .min(MAX_CRAWL_WORKERS);
+
+        for worker in 0..worker_count {
+            let work = work.clone();
+            let to_crawler = to_crawler.clone();
+            let extensions = extensions.clone();
+
+            let spawned = std::thread::Builder::new()
+                .name(format!("ClefCrawler{worker}"))
+                .spawn(move || {
+                    // NOTE this ends once every directory was sent, or the crawl was dropped
+                    for (index, album_dir) in work.iter() {
+                        let scanned = scan_album_dir(&album_dir, &extensions);
+
+                        // NOTE this fails when the crawl was dropped, ie by a rescan
+                        if to_crawler.send((index, scanned)).is_err() {
+                            return;
+                        }
+                    }
+                });
+
+            if let Err(e) = spawned {
+                error!("failed to spawn crawler worker: {e}");
+            }
+        }
+
+        let mut workers = Self {
+            results,
+            to_workers: Some(to_workers),
+            unsent: album_dirs.into(),
+            finished: BTreeMap::new(),
+            next: 0,
+            sent: 0,
+            total,
+        };
+        workers.send_ahead();
+
+        workers
+    }
+
+    /// Hands the workers directories up to SCAN_AHEAD past the next one
+    fn send_ahead(&mut self) {
+        while self.sent < self.next + SCAN_AHEAD {
+            let (Some(to_workers), Some(album_dir)) =
+                (&self.to_workers, self.unsent.pop_front())
+            else {
+                self.to_workers = None;
+                return;
+            };
+
+            to_workers.send((self.sent, album_dir)).ok();
+            self.sent += 1;
+        }
+    }
+
+    /// The next directory in order, waiting for the workers if necessary
+    async fn next(&mut self) -> NextScan {
+        while self.next < self.total {
+            self.send_ahead();
+
+            if let Some(scanned) = self.finished.remove(&self.next) {
+                self.next += 1;
+
+                return match scanned {
+                    Some(scanned) => NextScan::Scanned(scanned),
+                    None => NextScan::Skipped,
+                };
+            }
+
+            match self.results.recv_async().await {
+                Ok((index, scanned)) => {
+                    self.finished.insert(index, scanned);
+                }
+                Err(_) => {
+                    error!("crawler workers stopped early");
+                    break;
+                }
+            }
+        }
+
+        NextScan::Done
+    }
+}
+
 async fn step(
     state: CrawlerState,
     config: Arc<Config>,
     db: SqlitePool,
 ) -> (Option<CrawlerMessage>, CrawlerState) {
     match state {
-        CrawlerState::Initial => match collect_album_dirs(&config.audio_directory) {
+        CrawlerState::Initial => match collect_album_dirs(&config.audio_directories) {
             Err(message) => (Some(message), CrawlerState::Final),
             Ok(mut album_dirs) => {
                 let conn = match db.get() {
                     Ok(conn) => conn,
                     Err(e) => {
                         error!("failed to check out db connection: {e}");
-                        return (Some(CrawlerMessage::DbError), CrawlerState::Final);
+                        let message = CrawlerMessage::DbError(e.to_string());
+                        return (Some(message), CrawlerState::Final);
                     }
                 };
 
-                album_dirs.sort_by_key(|d| d.components().last().unwrap().to_string());
-                album_dirs.reverse();
+                sort_newest_first(&mut album_dirs);
+                let workers =
+                    Box::new(CrawlWorkers::spawn(album_dirs, &config.extensions));
 
-                (None, CrawlerState::AlbumDirectories(album_dirs, conn))
+                (None, CrawlerState::AlbumDirectories(workers, conn))
             }
         },
 
-        CrawlerState::AlbumDirectories(mut directories, mut conn) => {
-            let Some(album_dir) = directories.pop() else {
-                return (Some(CrawlerMessage::Done), CrawlerState::Final);
+        CrawlerState::AlbumDirectories(mut workers, mut conn) => {
+            let scanned = match workers.next().await {
+                NextScan::Scanned(scanned) => scanned,
+                NextScan::Skipped => {
+                    return (None, CrawlerState::AlbumDirectories(workers, conn));
+                }
+                NextScan::Done => {
+                    return match prune_missing(&mut conn, &config.audio_directories) {
+                        Ok(removed) => (
+                            Some(CrawlerMessage::Removed(removed)),
+                            CrawlerState::Pruned(conn),
+                        ),
+                        Err(e) => {
+                            error!("failed to remove missing songs: {e}");
+                            let message = CrawlerMessage::DbError(e.to_string());
+                            (Some(message), CrawlerState::Final)
+                        }
+                    };
+                }
             };
 
-            let crawled_album = match collect_single_album(&album_dir, &mut conn) {
+            let images_dir = &config.resized_images_directory;
+            let crawled_album = match save_scanned_album(scanned, images_dir, &mut conn) {
                 Ok(crawled_album) => Box::new(crawled_album),
                 Err(maybe_message) => {
                     return (
                         maybe_message,
-                        CrawlerState::AlbumDirectories(directories, conn),
+                        CrawlerState::AlbumDirectories(workers, conn),
                     );
                 }
             };
 
             (
                 Some(CrawlerMessage::CrawledAlbum(crawled_album)),
-                CrawlerState::AlbumDirectories(directories, conn),
+                CrawlerState::AlbumDirectories(workers, conn),
             )
         }
 
+        CrawlerState::Pruned(mut conn) => {
+            let playlist_folder = config.settings.playlist_folder.as_deref();
+            match save_playlists(&mut conn, &config.audio_directories, playlist_folder) {
+                Ok((playlists, conflicts)) => (
+                    Some(CrawlerMessage::Playlists(playlists)),
+                    CrawlerState::PlaylistsSaved(conflicts),
+                ),
+                Err(e) => {
+                    error!("failed to save playlists: {e}");
+                    (None, CrawlerState::PlaylistsSaved(Vec::new()))
+                }
+            }
+        }
+
+        CrawlerState::PlaylistsSaved(conflicts) if !conflicts.is_empty() => (
+            Some(CrawlerMessage::PlaylistConflicts(conflicts)),
+            CrawlerState::PlaylistsSaved(Vec::new()),
+        ),
+
+        CrawlerState::PlaylistsSaved(_) => {
+            (Some(CrawlerMessage::Done), CrawlerState::Final)
+        }
+
         CrawlerState::Final => (None, CrawlerState::Final),
     }
 }
 
-fn collect_album_dirs(audio_dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>, CrawlerMessage> {
-    let mut album_dirs = Vec::new();
-    let entries = audio_dir.read_dir().map_err(|e| {
-        error!("error reading audio directory entries: {e}");
-        CrawlerMessage::NoAudioDirectory
+#[derive(Debug, Default)]
+pub struct ScanSummary {
+    pub albums: usize,
+    pub songs: usize,
+    pub skipped_directories: usize,
+    pub removed: RemovedFromLibrary,
+    pub playlists: usize,
+    /// See CrawlerMessage::PlaylistConflicts
+    pub playlist_conflicts: Vec<Utf8PathBuf>,
+}
+
+/// Crawls every music directory on the current thread, without the ui.
+/// Folder art isn't resized, since that's done by the ui's resizer.
+pub fn scan_library(config: &Config, db: &SqlitePool) -> anyhow::Result<ScanSummary> {
+    let mut album_dirs = collect_album_dirs(&config.audio_directories)
+        .map_err(|_| anyhow::anyhow!("failed to read any music directory"))?;
+    sort_newest_first(&mut album_dirs);
+    let mut conn = db.get()?;
+
+    let mut summary = ScanSummary::default();
+    for album_dir in album_dirs {
+        match collect_single_album(
+            &album_dir,
+            &config.extensions,
+            &config.resized_images_directory,
+            &mut conn,
+        ) {
+            Ok(crawled) => {
+                summary.albums += 1;
+                summary.songs += crawled.songs.len();
+            }
+            Err(Some(CrawlerMessage::DbError(e))) => {
+                anyhow::bail!("crawler database error: {e}")
+            }
+            Err(_) => summary.skipped_directories += 1,
+        }
+    }
+
+    summary.removed = prune_missing(&mut conn, &config.audio_directories)?;
+    let playlist_folder = config.settings.playlist_folder.as_deref();
+    let (playlists, conflicts) =
+        save_playlists(&mut conn, &config.audio_directories, playlist_folder)?;
+    summary.playlists = playlists.len();
+    summary.playlist_conflicts = conflicts;
+
+    Ok(summary)
+}
+
+/// Soft-deletes songs whose files no longer exist, and any albums left empty.
+/// Renamed or moved files are picked up as new songs by the crawl before this.
+/// Songs under a music directory that's gone entirely are kept, ie for an unplugged drive,
+/// but songs outside of every music directory are removed.
+fn prune_missing(
+    conn: &mut SqlitePoolConn,
+    library_roots: &[Utf8PathBuf],
+) -> Result<RemovedFromLibrary, DbError> {
+    conn.immediate_transaction(|tx| {
+        let missing_songs: Vec<SongId> = queries::all_songs(tx)?
+            .into_iter()
+            .filter(|song| is_missing(&song.file, library_roots))
+            .map(|song| song.id)
+            .collect();
+
+        queries::soft_delete_songs(tx, &missing_songs)?;
+        let empty_albums = queries::soft_delete_empty_albums(tx)?;
+
+        Ok(RemovedFromLibrary {
+            albums: empty_albums,
+            songs: missing_songs,
+        })
+    })
+}
+
+/// See prune_missing
+fn is_missing(file: &Utf8Path, library_roots: &[Utf8PathBuf]) -> bool {
+    match library_roots.iter().find(|root| file.starts_with(root)) {
+        Some(root) => root.is_dir() && !file.is_file(),
+        None => true,
+    }
+}
+
+/// Saves every playlist file under the music directories, with the listed songs
+/// that are in the library, and forgets playlists whose files are gone like prune_missing.
+/// Then syncs them with the playlist folder, if there is one, returning the conflicts.
+/// NOTE this runs after the crawl and prune, so that the songs it lists are saved
+fn save_playlists(
+    conn: &mut SqlitePoolConn,
+    library_roots: &[Utf8PathBuf],
+    playlist_folder: Option<&Utf8Path>,
+) -> Result<(Vec<Playlist>, Vec<Utf8PathBuf>), DbError> {
+    let mut playlist_files = Vec::new();
+    for library_root in library_roots.iter().filter(|root| root.is_dir()) {
+        let mut directories = Vec::new();
+        collect_nested_dirs(library_root, &mut directories);
+
+        for directory in directories {
+            let Ok(entries) = directory.read_dir_utf8() else {
+                continue;
+            };
+            playlist_files.extend(
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.into_path())
+                    .filter(|path| is_playlist_file(path) && path.is_file()),
+            );
+        }
+    }
+
+    conn.immediate_transaction(|tx| {
+        let songs = queries::all_songs(tx)?;
+        let songs_by_path: HashMap<&Utf8Path, SongId> = songs
+            .iter()
+            .map(|song| (song.file.as_path(), song.id))
+            .collect();
+
+        let gone: Vec<PlaylistId> = queries::all_playlists(tx)?
+            .into_iter()
+            .filter(|playlist| is_missing(&playlist.file, library_roots))
+            .map(|playlist| playlist.id)
+            .collect();
+        queries::delete_playlists(tx, &gone)?;
+
+        for playlist_file in &playlist_files {
+            let entries = match read_playlist(playlist_file) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    info!("skipping unreadable playlist: {playlist_file} {e}");
+                    continue;
+                }
+            };
+            let song_ids: Vec<SongId> = entries
+                .iter()
+                .filter_map(|entry| songs_by_path.get(entry.path.as_path()).copied())
+                .collect();
+
+            queries::save_playlist(
+                tx,
+                playlist_file,
+                &playlist_name(playlist_file),
+                &song_ids,
+            )?;
+        }
+
+        Ok::<_, DbError>(())
     })?;
 
+    let conflicts = match playlist_folder {
+        Some(folder) => sync_playlist_folder(conn, folder)?,
+        None => Vec::new(),
+    };
+    let playlists = conn.immediate_transaction(queries::all_playlists)?;
+
+    Ok((playlists, conflicts))
+}
+
+/// An album directory, and the music directory it's in
+struct AlbumDir {
+    path: Utf8PathBuf,
+    library_root: Utf8PathBuf,
+}
+
+/// Fails only if none of the music directories could be read
+fn collect_album_dirs(
+    library_roots: &[Utf8PathBuf],
+) -> Result<Vec<AlbumDir>, CrawlerMessage> {
+    let mut album_dirs = Vec::new();
+    let mut any_read = false;
+
+    for library_root in library_roots {
+        match library_root.read_dir() {
+            Ok(entries) => {
+                any_read = true;
+                collect_root_album_dirs(library_root, entries, &mut album_dirs);
+            }
+            Err(e) => error!("error reading music directory entries: {library_root} {e}"),
+        }
+    }
+
+    if !any_read {
+        return Err(CrawlerMessage::NoAudioDirectory);
+    }
+
+    Ok(album_dirs)
+}
+
+fn collect_root_album_dirs(
+    library_root: &Utf8Path,
+    entries: std::fs::ReadDir,
+    album_dirs: &mut Vec<AlbumDir>,
+) {
     for entry in entries {
         let Ok(entry) = entry else {
             continue;
@@ -123,20 +631,77 @@ fn collect_album_dirs(audio_dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>, CrawlerM
         };
 
         if path.is_dir() {
-            album_dirs.push(path);
+            collect_disc_dirs(library_root, &path, album_dirs);
+            album_dirs.push(AlbumDir {
+                path,
+                library_root: library_root.to_owned(),
+            });
         }
     }
+}
 
-    Ok(album_dirs)
+/// Discs in folders of their own inside an album's, ie 'Album/CD1' and 'Album/CD2';
+/// find_or_insert_album merges them back into one album
+fn collect_disc_dirs(
+    library_root: &Utf8Path,
+    album_dir: &Utf8Path,
+    album_dirs: &mut Vec<AlbumDir>,
+) {
+    let Ok(entries) = album_dir.read_dir_utf8() else {
+        return;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.into_path();
+        if path.is_dir() && disc_number_from_directory(&path).is_some() {
+            album_dirs.push(AlbumDir {
+                path,
+                library_root: library_root.to_owned(),
+            });
+        }
+    }
+}
+
+/// Most recently modified first, so albums added since the last launch show up
+/// before the rest of the library is verified; adding files updates a directory's time.
+/// Ties and unreadable times fall back to the directory name.
+fn sort_newest_first(album_dirs: &mut [AlbumDir]) {
+    album_dirs.sort_by_cached_key(|dir| {
+        let modified = dir.path.metadata().and_then(|meta| meta.modified()).ok();
+        (Reverse(modified), dir.path.file_name().map(str::to_string))
+    });
 }
 
 fn collect_single_album(
-    album_dir: &Utf8Path,
+    album_dir: &AlbumDir,
+    extensions: &LibraryExtensions,
+    images_dir: &Utf8Path,
     conn: &mut SqlitePoolConn,
 ) -> Result<CrawledAlbum, Option<CrawlerMessage>> {
+    let scanned = scan_album_dir(album_dir, extensions).ok_or(None)?;
+
+    save_scanned_album(scanned, images_dir, conn)
+}
+
+/// An album directory's songs and art, as read from its files
+struct ScannedAlbum {
+    directory: Utf8PathBuf,
+    library_root: Utf8PathBuf,
+    songs: Vec<CrawledSong>,
+    original_art: Option<Utf8PathBuf>,
+    embedded_art: Option<Box<[u8]>>,
+}
+
+/// Reads an album directory, without touching the db;
+/// None for directories without songs, or that can't be read
+fn scan_album_dir(
+    album_dir: &AlbumDir,
+    extensions: &LibraryExtensions,
+) -> Option<ScannedAlbum> {
     let mut songs = Vec::new();
     let mut covers = Vec::new();
-    let entries = album_dir.read_dir().map_err(|_| None)?;
+    let mut embedded_art = None;
+    let entries = album_dir.path.read_dir().ok()?;
 
     for entry in entries {
         let Ok(entry) = entry else {
@@ -151,18 +716,24 @@ fn collect_single_album(
             }
         };
 
-        if is_music(&path) {
+        if extensions.is_music(&path) {
             if let Some(decoded) = decode_metadata(&path) {
+                if embedded_art.is_none() {
+                    embedded_art = decoded.embedded_art;
+                }
+
+                let lyrics = read_lyrics(&path, &decoded.tags);
                 songs.push(CrawledSong {
                     path,
                     tags: decoded.tags,
                     total_seconds: decoded.total_seconds,
+                    lyrics,
                 });
             } else {
                 info!("skipping file with invalid music metadata: {path}");
                 continue;
             }
-        } else if is_cover_art(&path) {
+        } else if extensions.is_cover_art(&path) {
             let file_meta = match entry.metadata() {
                 Ok(meta) => meta,
                 Err(e) => {
@@ -174,90 +745,798 @@ fn collect_single_album(
         }
     }
 
+    // NOTE albums without songs would be removed again by the prune pass
+    if songs.is_empty() {
+        return None;
+    }
+
     covers.sort_by_key(|(_path, file_size)| *file_size);
     let original_art = covers.last().map(|(path, _file_size)| path).cloned();
 
-    let (saved_album, mut saved_songs) = conn
+    Some(ScannedAlbum {
+        directory: album_dir.path.clone(),
+        library_root: album_dir.library_root.clone(),
+        songs,
+        original_art,
+        embedded_art,
+    })
+}
+
+/// A song's lyrics from an .lrc file with the same name, or else its lyrics tag
+fn read_lyrics(path: &Utf8Path, tags: &HashMap<TagKey, String>) -> Option<String> {
+    let lrc = std::fs::read_to_string(path.with_extension("lrc")).ok();
+    let lyrics = lrc.or_else(|| tags.get(&TagKey::Lyrics).cloned())?;
+    let lyrics = lyrics.trim_start_matches('\u{feff}').trim();
+
+    (!lyrics.is_empty()).then(|| lyrics.to_string())
+}
+
+fn save_scanned_album(
+    scanned: ScannedAlbum,
+    images_dir: &Utf8Path,
+    conn: &mut SqlitePoolConn,
+) -> Result<CrawledAlbum, Option<CrawlerMessage>> {
+    let ScannedAlbum {
+        directory: album_dir,
+        library_root,
+        songs,
+        original_art,
+        embedded_art,
+    } = scanned;
+
+    let (mut saved_album, mut saved_songs, changes) = conn
         .immediate_transaction(|tx| {
-            let saved_album = {
-                let (album_title, album_artist, album_date) = songs
-                    .first()
-                    .map(|s| {
-                        (
-                            s.tags.get(&TagKey::Album),
-                            s.tags.get(&TagKey::Artist),
-                            s.tags.get(&TagKey::Date),
-                        )
-                    })
-                    .unwrap_or_default();
-
-                let new_album = NewAlbum {
-                    directory: album_dir.to_owned(),
-                    title: album_title.cloned(),
-                    artist: album_artist.cloned(),
-                    release_date: album_date.cloned(),
-                    original_art,
-                    resized_art: None,
-                };
+            let new_album =
+                new_album(&album_dir, &songs, original_art, Some(library_root));
+            let (mut saved_album, album_reconciled) =
+                queries::find_or_insert_album(tx, new_album)?;
 
-                queries::find_or_insert_album(tx, new_album)?
-            };
+            let directory_disc_number = disc_number_from_directory(&album_dir);
 
             let mut saved_songs = Vec::new();
+            let mut changes = AlbumChanges {
+                album: album_reconciled,
+                ..AlbumChanges::UNCHANGED
+            };
             for crawled in &songs {
-                let new_song = NewSong {
-                    album_id: saved_album.id,
-                    file: crawled.path.clone(),
-                    total_seconds: crawled.total_seconds as i64,
-                    title: crawled.tags.get(&TagKey::TrackTitle).cloned(),
-                    artist: crawled.tags.get(&TagKey::Artist).cloned(),
-                    track_number: crawled
-                        .tags
-                        .get(&TagKey::TrackNumber)
-                        .and_then(|s| s.parse().ok()),
-                };
-
-                let saved_song = queries::find_or_insert_song(tx, new_song)?;
+                let new_song = new_song(saved_album.id, crawled, directory_disc_number);
+                let (saved_song, reconciled) =
+                    queries::find_or_insert_song(tx, new_song)?;
+                queries::save_song_lyrics(tx, saved_song.id, crawled.lyrics.as_deref())?;
+                match reconciled {
+                    Reconciled::Added => changes.songs_added += 1,
+                    Reconciled::Updated => changes.songs_updated += 1,
+                    Reconciled::Unchanged => {}
+                }
                 saved_songs.push(saved_song);
             }
+            saved_album.total_seconds =
+                queries::update_album_runtime(tx, saved_album.id)?;
 
-            Ok((saved_album, saved_songs))
+            Ok((saved_album, saved_songs, changes))
         })
         .map_err(|e: DbError| {
             error!("failed to insert album: {e}");
-            Some(CrawlerMessage::DbError)
+            Some(CrawlerMessage::DbError(e.to_string()))
         })?;
 
-    saved_songs.sort_by_key(|s| s.track_number);
+    saved_songs.sort_by_key(|s| (s.disc_number, s.track_number));
 
-    let cached_art = saved_album.resized_art.as_ref().and_then(|path| {
-        load_cached_rgba_bmp(path)
-            .map_err(|e| {
-                info!("error loading cached resized image: {e}");
-                e
-            })
-            .ok()
-    });
+    // NOTE this only checks that previously resized art is still there;
+    // it's loaded later, when the album is shown
+    if let Some(resized_art) = &saved_album.resized_art {
+        if !resized_art.exists() {
+            saved_album.resized_art = None;
+        }
+    }
+
+    // NOTE embedded art takes priority over folder images,
+    // which are only sent to the resizer when there's no resized art
+    let mut cached_art = None;
+    if let (None, Some(embedded_art)) = (&saved_album.resized_art, embedded_art) {
+        match save_embedded_art(&saved_album, &embedded_art, images_dir, conn) {
+            Ok((path, image_bytes)) => {
+                saved_album.resized_art = Some(path);
+                cached_art = Some(image_bytes);
+            }
+            Err(e) => info!("error saving embedded art: {e}"),
+        }
+    }
+
+    let placeholder_color = match (&saved_album.resized_art, &saved_album.original_art) {
+        (None, Some(original_art)) => sample_average_color(original_art)
+            .map_err(|e| info!("error sampling original art color: {e}"))
+            .ok(),
+        _ => None,
+    };
 
     Ok(CrawledAlbum {
         album: saved_album,
         songs: saved_songs,
         cached_art,
+        placeholder_color,
+        changes,
     })
 }
 
-const AUDIO_EXTENSIONS: [&str; 2] = ["mp3", "flac"];
+/// Reads the directory around a file as an album, without touching the db,
+/// so that files outside the library can be played. Errors are for display.
+pub async fn read_unsaved_album(
+    file: Utf8PathBuf,
+    extensions: LibraryExtensions,
+) -> Result<Box<CrawledAlbum>, String> {
+    let Some(album_dir) = file.parent() else {
+        return Err(format!("{file} isn't in a directory."));
+    };
+    if !extensions.is_music(&file) {
+        return Err(format!("{file} isn't a supported audio file."));
+    }
+
+    let album_dir = AlbumDir {
+        path: album_dir.to_owned(),
+        library_root: album_dir.to_owned(),
+    };
+    let Some(scanned) = scan_album_dir(&album_dir, &extensions) else {
+        return Err(format!("Unable to read {file}."));
+    };
+    if !scanned.songs.iter().any(|song| song.path == file) {
+        return Err(format!("Unable to read {file}."));
+    }
+
+    Ok(Box::new(unsaved_album(scanned)))
+}
+
+/// What was read from files or folders dropped on the window
+#[derive(Debug, Clone)]
+pub struct DroppedAlbums {
+    pub albums: Vec<CrawledAlbum>,
+    /// The album folders copied into the library; see import_album_dir
+    pub imported: usize,
+}
+
+/// Reads a dropped file with the rest of its directory, or a dropped folder
+/// with every folder under it, without touching the db. With a music directory (1),
+/// the album folders are also copied into it, for the next scan. Errors are for display.
+pub async fn read_dropped(
+    path: Utf8PathBuf,
+    import_to: Option<Utf8PathBuf>,
+    extensions: LibraryExtensions,
+) -> Result<DroppedAlbums, String> {
+    let albums = if path.is_file() {
+        vec![*read_unsaved_album(path.clone(), extensions).await?]
+    } else {
+        let mut directories = Vec::new();
+        collect_nested_dirs(&path, &mut directories);
+
+        directories
+            .into_iter()
+            .filter_map(|directory| {
+                let album_dir = AlbumDir {
+                    path: directory.clone(),
+                    library_root: directory,
+                };
+                scan_album_dir(&album_dir, &extensions)
+            })
+            .map(unsaved_album)
+            .collect()
+    };
+
+    if albums.is_empty() {
+        return Err(format!("There are no songs to play in {path}."));
+    }
+
+    let imported = match import_to {
+        Some(music_directory) => albums
+            .iter()
+            .filter(|album| {
+                import_album_dir(&album.album.directory, &music_directory)
+                    .map_err(|e| error!("failed to import dropped album: {e:#}"))
+                    .is_ok()
+            })
+            .count(),
+        None => 0,
+    };
+
+    Ok(DroppedAlbums { albums, imported })
+}
+
+/// A directory and every one under it, in path order.
+/// NOTE symlinked directories aren't followed, in case they loop
+fn collect_nested_dirs(directory: &Utf8Path, directories: &mut Vec<Utf8PathBuf>) {
+    directories.push(directory.to_owned());
+
+    let Ok(entries) = directory.read_dir_utf8() else {
+        return;
+    };
+    let mut children: Vec<Utf8PathBuf> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.into_path())
+        .collect();
+    children.sort();
+
+    for child in children {
+        collect_nested_dirs(&child, directories);
+    }
+}
+
+/// Copies an album folder's files into a music directory, under the same folder name;
+/// an existing folder there is left alone
+fn import_album_dir(
+    album_dir: &Utf8Path,
+    music_directory: &Utf8Path,
+) -> anyhow::Result<()> {
+    let name = album_dir
+        .file_name()
+        .with_context(|| format!("{album_dir} has no folder name"))?;
+    let target = music_directory.join(name);
+    if target.exists() {
+        anyhow::bail!("{target} already exists");
+    }
+
+    std::fs::create_dir_all(&target).with_context(|| format!("creating {target}"))?;
+    for entry in album_dir.read_dir_utf8()? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            std::fs::copy(entry.path(), target.join(entry.file_name()))
+                .with_context(|| format!("copying {}", entry.path()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// An album for songs from outside the library, which are never saved
+fn unsaved_album(scanned: ScannedAlbum) -> CrawledAlbum {
+    let placeholder_color = scanned.original_art.as_ref().and_then(|original_art| {
+        sample_average_color(original_art)
+            .map_err(|e| info!("error sampling original art color: {e}"))
+            .ok()
+    });
+
+    let mut album = new_album(
+        &scanned.directory,
+        &scanned.songs,
+        scanned.original_art,
+        None,
+    )
+    .into_unsaved();
+    let directory_disc_number = disc_number_from_directory(&scanned.directory);
+    let mut songs: Vec<Song> = scanned
+        .songs
+        .iter()
+        .map(|crawled| new_song(album.id, crawled, directory_disc_number).into_unsaved())
+        .collect();
+    songs.sort_by_key(|s| (s.disc_number, s.track_number));
+    album.total_seconds = songs.iter().map(|s| s.total_seconds).sum();
+
+    CrawledAlbum {
+        album,
+        songs,
+        cached_art: None,
+        placeholder_color,
+        changes: AlbumChanges::UNCHANGED,
+    }
+}
+
+/// The album artist for compilations without an album artist tag
+const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// The album as tagged in its first song, except for the artist; see album_artist
+fn new_album(
+    album_dir: &Utf8Path,
+    songs: &[CrawledSong],
+    original_art: Option<Utf8PathBuf>,
+    library_root: Option<Utf8PathBuf>,
+) -> NewAlbum {
+    let (album_title, album_date) = songs
+        .first()
+        .map(|s| (s.tags.get(&TagKey::Album), s.tags.get(&TagKey::Date)))
+        .unwrap_or_default();
+    let first_tags = songs.first().map(|s| &s.tags);
+
+    let directory_disc_number = disc_number_from_directory(album_dir);
+    let mut disc_numbers: Vec<i32> = songs
+        .iter()
+        .filter_map(|song| {
+            song.tags
+                .get(&TagKey::DiscNumber)
+                .and_then(|s| parse_tag_number(s))
+                .or(directory_disc_number)
+        })
+        .collect();
+    disc_numbers.sort_unstable();
+    disc_numbers.dedup();
 
-fn is_music(path: &Utf8Path) -> bool {
-    path.extension()
-        .map(|ext| AUDIO_EXTENSIONS.contains(&ext))
-        .unwrap_or_default()
+    NewAlbum {
+        directory: album_dir.to_owned(),
+        title: album_title.cloned(),
+        artist: album_artist(songs),
+        release_date: album_date.cloned(),
+        original_art,
+        resized_art: None,
+        album_gain: first_tags
+            .and_then(|tags| tags.get(&TagKey::ReplayGainAlbumGain))
+            .and_then(|s| parse_gain(s))
+            .map(f64::from),
+        album_peak: first_tags
+            .and_then(|tags| tags.get(&TagKey::ReplayGainAlbumPeak))
+            .and_then(|s| parse_peak(s))
+            .map(f64::from),
+        library_root,
+        disc_numbers,
+    }
+}
+
+/// The album artist tag from any song; otherwise the track artist of at least half
+/// the songs, or Various Artists for compilations and albums with no such artist
+fn album_artist(songs: &[CrawledSong]) -> Option<String> {
+    if let Some(tagged) = songs.iter().find_map(|s| s.tags.get(&TagKey::AlbumArtist)) {
+        return Some(tagged.clone());
+    }
+
+    let compilation = songs
+        .iter()
+        .filter_map(|s| s.tags.get(&TagKey::Compilation))
+        .any(|flag| is_compilation_flag(flag));
+    if compilation {
+        return Some(VARIOUS_ARTISTS.to_string());
+    }
+
+    let mut artist_counts: HashMap<&str, usize> = HashMap::new();
+    for artist in songs.iter().filter_map(|s| s.tags.get(&TagKey::Artist)) {
+        *artist_counts.entry(artist.trim()).or_default() += 1;
+    }
+
+    // NOTE a few tracks 'featuring' someone shouldn't make an album a compilation
+    let (most_common, count) = artist_counts
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))?;
+    if count * 2 >= songs.len() {
+        Some(most_common.to_string())
+    } else {
+        Some(VARIOUS_ARTISTS.to_string())
+    }
+}
+
+/// The id3 TCMP frame and the vorbis comment are '1'; some taggers write 'true'
+fn is_compilation_flag(flag: &str) -> bool {
+    let flag = flag.trim();
+    flag == "1" || flag.eq_ignore_ascii_case("true")
+}
+
+fn new_song(
+    album_id: AlbumId,
+    crawled: &CrawledSong,
+    directory_disc_number: Option<i32>,
+) -> NewSong {
+    NewSong {
+        album_id,
+        file: crawled.path.clone(),
+        total_seconds: crawled.total_seconds as i64,
+        title: crawled.tags.get(&TagKey::TrackTitle).cloned(),
+        artist: crawled.tags.get(&TagKey::Artist).cloned(),
+        track_number: crawled
+            .tags
+            .get(&TagKey::TrackNumber)
+            .and_then(|s| parse_tag_number(s)),
+        disc_number: crawled
+            .tags
+            .get(&TagKey::DiscNumber)
+            .and_then(|s| parse_tag_number(s))
+            .or(directory_disc_number),
+        track_gain: crawled
+            .tags
+            .get(&TagKey::ReplayGainTrackGain)
+            .and_then(|s| parse_gain(s))
+            .map(f64::from),
+        track_peak: crawled
+            .tags
+            .get(&TagKey::ReplayGainTrackPeak)
+            .and_then(|s| parse_peak(s))
+            .map(f64::from),
+        genre: crawled
+            .tags
+            .get(&TagKey::Genre)
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty()),
+        track_total: crawled
+            .tags
+            .get(&TagKey::TrackTotal)
+            .and_then(|s| parse_tag_number(s))
+            .or_else(|| {
+                crawled
+                    .tags
+                    .get(&TagKey::TrackNumber)
+                    .and_then(|s| parse_tag_total(s))
+            }),
+        bpm: crawled.tags.get(&TagKey::Bpm).and_then(|s| parse_bpm(s)),
+        initial_key: crawled
+            .tags
+            .get(&TagKey::InitialKey)
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty()),
+    }
+}
+
+/// Loads the albums saved by previous crawls, so they can be displayed
+/// before the crawler has verified them against the filesystem
+pub async fn load_saved_albums(db: SqlitePool) -> Vec<CrawledAlbum> {
+    let mut conn = match db.get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("failed to check out db connection: {e}");
+            return Vec::new();
+        }
+    };
+
+    let saved = queries::all_albums(&mut conn)
+        .and_then(|albums| Ok((albums, queries::all_songs(&mut conn)?)));
+
+    let (albums, songs) = match saved {
+        Ok(saved) => saved,
+        Err(e) => {
+            error!("failed to load saved albums: {e}");
+            return Vec::new();
+        }
+    };
+
+    let mut songs_by_album: HashMap<_, Vec<Song>> = HashMap::new();
+    for song in songs {
+        songs_by_album.entry(song.album_id).or_default().push(song);
+    }
+
+    albums
+        .into_iter()
+        .map(|album| {
+            let mut songs = songs_by_album.remove(&album.id).unwrap_or_default();
+            songs.sort_by_key(|s| (s.disc_number, s.track_number));
+
+            CrawledAlbum {
+                album,
+                songs,
+                cached_art: None,
+                placeholder_color: None,
+                changes: AlbumChanges::UNCHANGED,
+            }
+        })
+        .collect()
+}
+
+fn save_embedded_art(
+    album: &Album,
+    embedded_art: &[u8],
+    images_dir: &Utf8Path,
+    conn: &mut SqlitePoolConn,
+) -> anyhow::Result<(Utf8PathBuf, RgbaBytes)> {
+    let image_bytes = load_rgba_from_memory(embedded_art)?;
+    let album_title = album.display_title().unwrap_or_default();
+    let path = save_resized_image(album.id, album_title, &image_bytes, images_dir, conn)?;
+
+    Ok((path, image_bytes))
+}
+
+/// Parses track and disc number tags, which are sometimes formatted like '3/12'
+fn parse_tag_number(tag: &str) -> Option<i32> {
+    let number = tag.split('/').next().unwrap_or(tag);
+
+    number.trim().parse().ok()
+}
+
+/// Tempo tags are usually whole numbers, but some taggers write decimals
+fn parse_bpm(tag: &str) -> Option<f64> {
+    let bpm: f64 = tag.trim().parse().ok()?;
+
+    (bpm.is_finite() && bpm > 0.0).then_some(bpm)
+}
+
+/// The total from a 'number/total' tag, like '3/12'
+fn parse_tag_total(tag: &str) -> Option<i32> {
+    let (_number, total) = tag.split_once('/')?;
+
+    total.trim().parse().ok()
+}
+
+/// Looks for a disc number at the end of a directory name, like 'Album (Disc 2)' or 'CD1'
+fn disc_number_from_directory(album_dir: &Utf8Path) -> Option<i32> {
+    queries::disc_marker(album_dir).map(|(_rest, disc_number)| disc_number)
 }
 
 const IMAGE_EXTENSIONS: [&str; 2] = ["jpg", "png"];
 
-fn is_cover_art(path: &Utf8Path) -> bool {
-    path.extension()
-        .map(|ext| IMAGE_EXTENSIONS.contains(&ext))
-        .unwrap_or_default()
+/// The lowercase file extensions that the crawler picks up as songs and cover art
+#[derive(Debug, Clone)]
+pub struct LibraryExtensions {
+    audio: Vec<String>,
+    image: Vec<String>,
+}
+
+impl Default for LibraryExtensions {
+    fn default() -> Self {
+        Self {
+            audio: AUDIO_EXTENSIONS.map(String::from).to_vec(),
+            image: IMAGE_EXTENSIONS.map(String::from).to_vec(),
+        }
+    }
+}
+
+impl LibraryExtensions {
+    /// Fails for extensions symphonia has no enabled reader for
+    pub fn with_audio(self, audio: Vec<String>) -> anyhow::Result<Self> {
+        let audio = lowercase_extensions(audio, "audio")?;
+        if let Some(unsupported) =
+            audio.iter().find(|ext| !is_supported_audio_extension(ext))
+        {
+            anyhow::bail!("unsupported audio extension: {unsupported}");
+        }
+
+        Ok(Self { audio, ..self })
+    }
+
+    /// Fails for extensions the image crate can't decode
+    pub fn with_image(self, image: Vec<String>) -> anyhow::Result<Self> {
+        let image = lowercase_extensions(image, "image")?;
+        let readable = |ext: &String| {
+            image_rs::ImageFormat::from_extension(ext)
+                .is_some_and(|format| format.can_read())
+        };
+        if let Some(unsupported) = image.iter().find(|ext| !readable(ext)) {
+            anyhow::bail!("unsupported image extension: {unsupported}");
+        }
+
+        Ok(Self { image, ..self })
+    }
+
+    pub fn is_music(&self, path: &Utf8Path) -> bool {
+        Self::has_extension(&self.audio, path)
+    }
+
+    fn is_cover_art(&self, path: &Utf8Path) -> bool {
+        Self::has_extension(&self.image, path)
+    }
+
+    fn has_extension(extensions: &[String], path: &Utf8Path) -> bool {
+        path.extension()
+            .map(|ext| extensions.contains(&ext.to_ascii_lowercase()))
+            .unwrap_or_default()
+    }
+}
+
+fn lowercase_extensions(
+    extensions: Vec<String>,
+    kind: &str,
+) -> anyhow::Result<Vec<String>> {
+    let extensions: Vec<String> = extensions
+        .iter()
+        .map(|ext| ext.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect();
+
+    if extensions.is_empty() {
+        anyhow::bail!("no {kind} extensions given");
+    }
+
+    Ok(extensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disc_number_from_directory_finds_trailing_disc_numbers() {
+        let cases = [
+            ("Music/Album CD1", Some(1)),
+            ("Music/Album (Disc 2)", Some(2)),
+            ("Music/Album - disk_3", Some(3)),
+            ("Music/CD 10", Some(10)),
+            ("Music/Album", None),
+            ("Music/Discography", None),
+            ("Music/ABCD 1999", None),
+        ];
+
+        for (dir, expected) in cases {
+            let dir = Utf8Path::new(dir);
+            assert_eq!(disc_number_from_directory(dir), expected, "{dir}");
+        }
+    }
+
+    #[test]
+    fn scan_changes_summarize_albums_and_songs() {
+        let mut changes = ScanChanges::default();
+        assert!(changes.is_empty());
+        assert_eq!(changes.summary(), "No changes");
+
+        let added = AlbumChanges {
+            album: Reconciled::Added,
+            songs_added: 10,
+            songs_updated: 0,
+        };
+        let retagged = AlbumChanges {
+            songs_updated: 2,
+            ..AlbumChanges::UNCHANGED
+        };
+        changes.record_crawled("New Album", &added);
+        changes.record_crawled("Same Album", &AlbumChanges::UNCHANGED);
+        changes.record_crawled("Retagged Album", &retagged);
+        changes.record_removed(vec!["Old Album".to_string()], 8);
+
+        assert_eq!(
+            changes.albums,
+            vec![
+                (AlbumChange::Added, "New Album".to_string()),
+                (AlbumChange::Updated, "Retagged Album".to_string()),
+                (AlbumChange::Removed, "Old Album".to_string()),
+            ]
+        );
+        assert_eq!(
+            changes.summary(),
+            "1 album added, 1 updated, 1 removed; 10 songs added, 2 updated, 8 removed"
+        );
+    }
+
+    #[test]
+    fn disc_folders_inside_an_album_are_crawled() {
+        let root =
+            std::env::temp_dir().join(format!("clef-crawler-{}", std::process::id()));
+        let root = Utf8PathBuf::try_from(root).unwrap();
+        for dir in ["Album/CD1", "Album/Disc 2", "Album/Scans", "Single"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        let mut album_dirs: Vec<Utf8PathBuf> =
+            collect_album_dirs(std::slice::from_ref(&root))
+                .unwrap()
+                .into_iter()
+                .map(|album_dir| album_dir.path)
+                .collect();
+        album_dirs.sort();
+        let expected: Vec<Utf8PathBuf> = ["Album", "Album/CD1", "Album/Disc 2", "Single"]
+            .iter()
+            .map(|dir| root.join(dir))
+            .collect();
+        assert_eq!(album_dirs, expected);
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn crawl_workers_return_directories_in_order() {
+        let scanned = |directory: &str| ScannedAlbum {
+            directory: directory.into(),
+            library_root: "Music".into(),
+            songs: Vec::new(),
+            original_art: None,
+            embedded_art: None,
+        };
+
+        let (to_crawler, results) = flume::unbounded();
+        let mut workers = CrawlWorkers {
+            results,
+            to_workers: None,
+            unsent: VecDeque::new(),
+            finished: BTreeMap::new(),
+            next: 0,
+            sent: 3,
+            total: 3,
+        };
+        to_crawler.send((2, Some(scanned("C")))).unwrap();
+        to_crawler.send((1, None)).unwrap();
+        to_crawler.send((0, Some(scanned("A")))).unwrap();
+
+        let mut next = || iced::futures::executor::block_on(workers.next());
+        assert!(matches!(next(), NextScan::Scanned(album) if album.directory == "A"));
+        assert!(matches!(next(), NextScan::Skipped));
+        assert!(matches!(next(), NextScan::Scanned(album) if album.directory == "C"));
+        assert!(matches!(next(), NextScan::Done));
+    }
+
+    #[test]
+    fn crawl_workers_only_read_a_few_directories_ahead() {
+        let album_dir = |number: usize| AlbumDir {
+            path: format!("Music/Album {number}").into(),
+            library_root: "Music".into(),
+        };
+
+        let (to_workers, work) = flume::unbounded();
+        let (to_crawler, results) = flume::unbounded();
+        let mut workers = CrawlWorkers {
+            results,
+            to_workers: Some(to_workers),
+            unsent: (0..20).map(album_dir).collect(),
+            finished: BTreeMap::new(),
+            next: 0,
+            sent: 0,
+            total: 20,
+        };
+
+        workers.send_ahead();
+        let sent: Vec<usize> = work.try_iter().map(|(index, _)| index).collect();
+        assert_eq!(sent, (0..SCAN_AHEAD).collect::<Vec<_>>());
+
+        // a slow first directory holds back the rest
+        to_crawler.send((1, None)).unwrap();
+        to_crawler.send((0, None)).unwrap();
+        let mut next = || iced::futures::executor::block_on(workers.next());
+        assert!(matches!(next(), NextScan::Skipped));
+        assert!(matches!(next(), NextScan::Skipped));
+        let sent: Vec<usize> = work.try_iter().map(|(index, _)| index).collect();
+        assert_eq!(sent, [SCAN_AHEAD]);
+    }
+
+    #[test]
+    fn library_extensions_only_accept_readable_formats() {
+        let extensions = |list: &[&str]| list.iter().map(|ext| ext.to_string()).collect();
+
+        let custom = LibraryExtensions::default()
+            .with_audio(extensions(&[".MKA", "webm"]))
+            .unwrap()
+            .with_image(extensions(&["webp"]))
+            .unwrap();
+        assert!(custom.is_music(Utf8Path::new("Album/01.mka")));
+        assert!(!custom.is_music(Utf8Path::new("Album/01.mp3")));
+        assert!(custom.is_cover_art(Utf8Path::new("Album/Cover.WEBP")));
+
+        let default = LibraryExtensions::default;
+        assert!(default().with_audio(extensions(&["opus"])).is_err());
+        assert!(default().with_audio(extensions(&["txt"])).is_err());
+        assert!(default().with_audio(extensions(&[" "])).is_err());
+        assert!(default().with_image(extensions(&["svg"])).is_err());
+    }
+
+    #[test]
+    fn album_artist_prefers_the_tag_then_falls_back_to_various_artists() {
+        let song = |tags: &[(TagKey, &str)]| CrawledSong {
+            path: Utf8PathBuf::from("song.flac"),
+            tags: tags
+                .iter()
+                .map(|(key, value)| (*key, value.to_string()))
+                .collect(),
+            total_seconds: 0,
+            lyrics: None,
+        };
+        let by = |artist| song(&[(TagKey::Artist, artist)]);
+
+        let tagged = [
+            by("Massive Attack"),
+            song(&[(TagKey::Artist, "Tricky"), (TagKey::AlbumArtist, "DJ")]),
+        ];
+        assert_eq!(album_artist(&tagged).as_deref(), Some("DJ"));
+
+        let featuring = [by("Burial"), by("Burial"), by("Burial feat. Four Tet")];
+        assert_eq!(album_artist(&featuring).as_deref(), Some("Burial"));
+
+        let mixed = [by("Burial"), by("Four Tet"), by("Caribou")];
+        assert_eq!(album_artist(&mixed).as_deref(), Some(VARIOUS_ARTISTS));
+
+        let flagged = [
+            song(&[(TagKey::Artist, "Burial"), (TagKey::Compilation, "1")]),
+            by("Burial"),
+        ];
+        assert_eq!(album_artist(&flagged).as_deref(), Some(VARIOUS_ARTISTS));
+
+        assert_eq!(album_artist(&[song(&[])]), None);
+    }
+
+    #[test]
+    fn parse_tag_number_ignores_totals() {
+        assert_eq!(parse_tag_number("3/12"), Some(3));
+        assert_eq!(parse_tag_number("7"), Some(7));
+        assert_eq!(parse_tag_number("side a"), None);
+    }
+
+    #[test]
+    fn parse_bpm_reads_whole_and_decimal_tempos() {
+        assert_eq!(parse_bpm("128"), Some(128.0));
+        assert_eq!(parse_bpm(" 92.5 "), Some(92.5));
+        assert_eq!(parse_bpm("0"), None);
+        assert_eq!(parse_bpm("fast"), None);
+    }
+
+    #[test]
+    fn parse_tag_total_reads_totals() {
+        assert_eq!(parse_tag_total("3/12"), Some(12));
+        assert_eq!(parse_tag_total("3 / 12"), Some(12));
+        assert_eq!(parse_tag_total("3"), None);
+    }
 }
@@ -1,5 +1,158 @@
-use iced::theme::{self, Theme};
-use iced::widget::button;
+use iced::theme::{self, Palette, Theme};
+use iced::widget::{button, container, svg};
+use iced::{Background, Color};
+use serde::{Deserialize, Serialize};
+
+/// How strongly the accent shows behind a hovered row
+const HOVER_TINT_ALPHA: f32 = 0.12;
+/// How strongly the accent shows behind a selected row, ie the current song
+const SELECTED_TINT_ALPHA: f32 = 0.3;
+
+/// Colors over the dark theme's, from the settings file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaletteSettings {
+    /// The slider, buttons, play icons, and row highlights
+    pub accent: Option<ThemeColor>,
+    pub background: Option<ThemeColor>,
+    pub text: Option<ThemeColor>,
+}
+
+impl PaletteSettings {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The app's theme: the dark theme, with any colors from the settings
+pub fn app_theme(palette: &PaletteSettings) -> Theme {
+    if palette.is_default() {
+        return Theme::Dark;
+    }
+
+    let dark = Palette::DARK;
+    Theme::custom(Palette {
+        primary: palette.accent.map_or(dark.primary, ThemeColor::color),
+        background: palette
+            .background
+            .map_or(dark.background, ThemeColor::color),
+        text: palette.text.map_or(dark.text, ThemeColor::color),
+        ..dark
+    })
+}
+
+/// The accents to pick from in the settings view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accent {
+    Blue,
+    Purple,
+    Pink,
+    Red,
+    Orange,
+    Green,
+    Teal,
+}
+
+impl Accent {
+    pub const ALL: [Accent; 7] = [
+        Self::Blue,
+        Self::Purple,
+        Self::Pink,
+        Self::Red,
+        Self::Orange,
+        Self::Green,
+        Self::Teal,
+    ];
+
+    /// NOTE blue is the dark theme's own primary color
+    fn rgb(self) -> [u8; 3] {
+        match self {
+            Self::Blue => [0x5E, 0x7C, 0xE2],
+            Self::Purple => [0x9B, 0x6B, 0xE0],
+            Self::Pink => [0xE0, 0x6B, 0xB0],
+            Self::Red => [0xD9, 0x54, 0x54],
+            Self::Orange => [0xE0, 0x8A, 0x3C],
+            Self::Green => [0x4C, 0xAF, 0x6E],
+            Self::Teal => [0x2F, 0xA8, 0xA8],
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Blue => "blue",
+            Self::Purple => "purple",
+            Self::Pink => "pink",
+            Self::Red => "red",
+            Self::Orange => "orange",
+            Self::Green => "green",
+            Self::Teal => "teal",
+        }
+    }
+}
+
+impl std::fmt::Display for Accent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = self.name();
+        let mut chars = name.chars();
+        let first = chars.next().map(|c| c.to_ascii_uppercase());
+
+        write!(f, "{}{}", first.unwrap_or_default(), chars.as_str())
+    }
+}
+
+/// A color in the settings file, by accent name or as '#rrggbb'
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ThemeColor {
+    Named(Accent),
+    Hex([u8; 3]),
+}
+
+impl ThemeColor {
+    pub fn color(self) -> Color {
+        let [r, g, b] = match self {
+            Self::Named(accent) => accent.rgb(),
+            Self::Hex(rgb) => rgb,
+        };
+
+        Color::from_rgb8(r, g, b)
+    }
+}
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let value = value.trim();
+        let invalid = || format!("expected an accent name or '#rrggbb', got '{value}'");
+
+        if let Some(hex) = value.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(invalid());
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+            return match (channel(0), channel(2), channel(4)) {
+                (Ok(r), Ok(g), Ok(b)) => Ok(Self::Hex([r, g, b])),
+                _ => Err(invalid()),
+            };
+        }
+
+        Accent::ALL
+            .into_iter()
+            .find(|accent| accent.name().eq_ignore_ascii_case(value))
+            .map(Self::Named)
+            .ok_or_else(invalid)
+    }
+}
+
+impl From<ThemeColor> for String {
+    fn from(color: ThemeColor) -> Self {
+        match color {
+            ThemeColor::Named(accent) => accent.name().to_string(),
+            ThemeColor::Hex([r, g, b]) => format!("#{r:02x}{g:02x}{b:02x}"),
+        }
+    }
+}
 
 pub fn no_background() -> theme::Button {
     theme::Button::Custom(Box::new(NoBackgroundStyle))
@@ -17,3 +170,126 @@ impl button::StyleSheet for NoBackgroundStyle {
         appearance
     }
 }
+
+/// Art darker than this gets light text; it's where black and white text
+/// have the same contrast ratio against it
+const DARK_ART_LUMINANCE: f32 = 0.179;
+
+/// Text and a translucent scrim behind it, readable over art of a given luminance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptionColors {
+    pub text: Color,
+    pub scrim: Color,
+}
+
+impl CaptionColors {
+    pub fn for_luminance(luminance: f32) -> Self {
+        if luminance < DARK_ART_LUMINANCE {
+            Self {
+                text: Color::WHITE,
+                scrim: Color::from_rgba(0.0, 0.0, 0.0, 0.5),
+            }
+        } else {
+            Self {
+                text: Color::BLACK,
+                scrim: Color::from_rgba(1.0, 1.0, 1.0, 0.6),
+            }
+        }
+    }
+}
+
+/// The theme's accent, translucent; for hovered rows
+pub fn hover_tint() -> theme::Container {
+    theme::Container::Custom(Box::new(AccentTintStyle(HOVER_TINT_ALPHA)))
+}
+
+/// The theme's accent, translucent; for selected rows and drop targets
+pub fn selected_tint() -> theme::Container {
+    theme::Container::Custom(Box::new(AccentTintStyle(SELECTED_TINT_ALPHA)))
+}
+
+pub struct AccentTintStyle(f32);
+
+impl container::StyleSheet for AccentTintStyle {
+    type Style = Theme;
+
+    fn appearance(&self, theme: &Self::Style) -> container::Appearance {
+        let accent = theme.palette().primary;
+
+        container::Appearance {
+            background: Some(Background::Color(Color { a: self.0, ..accent })),
+            ..Default::default()
+        }
+    }
+}
+
+/// Draws an icon in the theme's accent, ie play buttons
+pub fn accent_icon() -> theme::Svg {
+    theme::Svg::custom_fn(|theme| svg::Appearance {
+        color: Some(theme.palette().primary),
+    })
+}
+
+/// A raised panel for menus, ie right-click menus
+pub fn menu_panel() -> theme::Container {
+    theme::Container::Custom(Box::new(MenuPanelStyle))
+}
+
+pub struct MenuPanelStyle;
+
+impl container::StyleSheet for MenuPanelStyle {
+    type Style = Theme;
+
+    fn appearance(&self, theme: &Self::Style) -> container::Appearance {
+        let palette = theme.extended_palette();
+
+        container::Appearance {
+            background: Some(Background::Color(palette.background.weak.color)),
+            border_radius: 4.0,
+            border_width: 1.0,
+            border_color: Color {
+                a: 0.2,
+                ..palette.background.base.text
+            },
+            ..Default::default()
+        }
+    }
+}
+
+pub fn solid_color(color: Color) -> theme::Container {
+    theme::Container::Custom(Box::new(SolidColorStyle(color)))
+}
+
+pub struct SolidColorStyle(Color);
+
+impl container::StyleSheet for SolidColorStyle {
+    type Style = Theme;
+
+    fn appearance(&self, _theme: &Self::Style) -> container::Appearance {
+        container::Appearance {
+            background: Some(Background::Color(self.0)),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_colors_parse_from_names_and_hex() {
+        let parse = |value: &str| ThemeColor::try_from(value.to_string());
+
+        assert_eq!(parse("purple"), Ok(ThemeColor::Named(Accent::Purple)));
+        assert_eq!(parse(" Teal "), Ok(ThemeColor::Named(Accent::Teal)));
+        assert_eq!(parse("#ff8800"), Ok(ThemeColor::Hex([0xFF, 0x88, 0x00])));
+        assert!(parse("#ff88").is_err());
+        assert!(parse("#gg8800").is_err());
+        assert!(parse("chartreuse").is_err());
+
+        assert_eq!(String::from(ThemeColor::Hex([0xFF, 0x88, 0x00])), "#ff8800");
+        assert_eq!(String::from(ThemeColor::Named(Accent::Pink)), "pink");
+        assert_eq!(Accent::Orange.to_string(), "Orange");
+    }
+}
@@ -1,7 +1,20 @@
+use camino::Utf8PathBuf;
 use iced::Command;
 
+use crate::app::art_fetcher::ArtLookup;
+use crate::app::notification::Notification;
+use crate::app::playlist_file::PlaylistExport;
 use crate::app::resizer::ResizeRequest;
+use crate::app::settings::SettingsFile;
+use crate::app::state_dump::StateDump;
+use crate::app::stats::StatsPeriod;
+use clef_audio::dsp::equalizer::EqCurve;
+use clef_audio::dsp::transition::TransitionKind;
 use clef_audio::player::AudioAction;
+use clef_db::queries::{
+    Album, AlbumId, AlbumRename, SessionId, SmartPlaylistId, SmartRule, SongId, SortKind,
+    SortName,
+};
 
 #[derive(Debug)]
 pub enum Effect<Message> {
@@ -9,8 +22,98 @@ pub enum Effect<Message> {
     #[allow(unused)] // will need this for one-off commands
     Command(Command<Message>),
     ToAudio(AudioAction),
+    /// Tells the player whether to save power, and starts or stops its sample tap to match
+    SetLowPower(bool),
     ToResizer(ResizeRequest),
     CloseWindow,
+    /// Restores and raises the window, ie when another launch hands off to this one
+    FocusWindow,
+    /// Hides the window, ie to the tray icon
+    HideWindow,
+    /// Resizes the window to a width (0) and height (1), ie for the mini player
+    ResizeWindow(u32, u32),
+    WriteStateDump(Box<StateDump>),
+    /// Asks the next launch to restore the db backup, then closes the window
+    RestoreDatabase,
+    /// Migrates a damaged db as it is, then starts saving to it and loads the library
+    ContinueWithDamagedDatabase,
+    /// Takes the queue saved when the player failed, to offer resuming it
+    /// with the new player; closes the window if there's no new player
+    LoadCrashedQueue,
+    /// Opens a crash report from the last launch
+    OpenCrashReport(Utf8PathBuf),
+    /// Applies the curve (1), and saves it for later launches
+    /// with an output device (0; None = the default output)
+    SaveEqualizer(Option<String>, EqCurve),
+    /// Switches to an output device (None = the default output),
+    /// and loads the curve saved for it
+    SwitchOutputDevice(Option<String>),
+    /// Saves whether a song is a favorite
+    SaveFavorite(SongId, bool),
+    /// Loads play counts and the days with plays, for the history view
+    LoadPlayHistory,
+    /// Loads the plays of a local day (yyyy-mm-dd), to play them again as a queue
+    RebuildSession(String),
+    /// Loads listening totals since the start of the period, for the stats view
+    LoadStats(StatsPeriod),
+    /// Loads the playlist files and smart playlists, for the playlists view
+    LoadPlaylists,
+    /// Writes a playlist as an m3u file
+    ExportPlaylist(PlaylistExport),
+    /// Saves a new smart playlist with a name (0) and rules (1),
+    /// then reloads the list
+    SaveSmartPlaylist(String, Vec<SmartRule>),
+    /// Deletes a smart playlist, then reloads the list
+    DeleteSmartPlaylist(SmartPlaylistId),
+    /// Finds the songs matching the rules, to play them
+    LoadSmartPlaylistSongs(Vec<SmartRule>),
+    /// Finds the songs played most on today's date in previous years, to play them
+    LoadOnThisDay,
+    LoadSessions,
+    /// Loads a song's lyrics, for the lyrics view
+    LoadLyrics(SongId),
+    /// Loads the waveform for a song at a path, for the seek bar;
+    /// it's computed and saved the first time
+    LoadWaveform(SongId, Utf8PathBuf),
+    /// Looks at whether the machine is on battery
+    CheckPowerSource,
+    /// Saves a new, empty session with a name, then reloads the list
+    CreateSession(String),
+    /// Deletes a session and its queue, then reloads the list
+    DeleteSession(SessionId),
+    /// Applies a session's transition (1) and makes the session (0) active
+    /// (None = the default session), after saving the latest position (2)
+    /// for the one being left, then loads its queue
+    SwitchSession(Option<SessionId>, TransitionKind, Option<(SongId, f64)>),
+    /// Saves a session's transition (1), and applies it if the session is active (2)
+    SaveSessionTransition(SessionId, TransitionKind, bool),
+    /// Writes the settings file, and applies the settings that can change while running
+    SaveSettings(Box<SettingsFile>),
+    /// Reads the directory around a file from outside the library, to play it
+    OpenFile(Utf8PathBuf),
+    /// Lists a folder's subfolders and songs, for the folders view
+    ReadFolder(Utf8PathBuf),
+    /// Reads a dropped file or folder from outside the library, to enqueue it;
+    /// with a music directory (1), it's also copied there
+    ReadDropped(Utf8PathBuf, Option<Utf8PathBuf>),
+    /// Saves a sort name override, replacing any for the same name
+    SaveSortName(SortName),
+    DeleteSortName(SortKind, String),
+    /// Saves an album's edited title and artist, without touching its files
+    RenameAlbum(AlbumId, AlbumRename),
+    /// Saves an album's notes; None clears them
+    SaveAlbumNotes(AlbumId, Option<String>),
+    /// Sends a test event to a webhook url, to show the result in the settings
+    TestWebhook(String),
+    /// Looks up art online for an album with none, then resizes it
+    FetchArt(ArtLookup),
+    /// Looks up the album on MusicBrainz, and saves the tags it's missing
+    FillFromMusicBrainz(Box<Album>),
+    CopyToClipboard(String),
+    /// Shows an error or warning until it's dismissed
+    Notify(Notification),
+    /// Opens a directory in the platform's file manager
+    ShowInFileManager(Utf8PathBuf),
 }
 
 impl<Message> Effect<Message> {
@@ -0,0 +1,409 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::app::old_unfold::old_unfold;
+
+/// How to write an exported album
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    Folder,
+    /// Uncompressed, since the songs and art already are
+    Zip,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 2] = [Self::Folder, Self::Zip];
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Folder => "Folder",
+            Self::Zip => "Zip file",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// An album's files to copy out of the library, ie onto a usb stick for the car
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExportRequest {
+    /// For the new folder or zip file, in the destination
+    pub name: String,
+    /// Each file, with its path in the export
+    pub files: Vec<(Utf8PathBuf, String)>,
+    /// An existing directory to write the folder or zip file in
+    pub destination: Utf8PathBuf,
+    pub format: ExportFormat,
+}
+
+impl ExportRequest {
+    /// NOTE files inside the album's directory keep their place in it
+    pub fn new(
+        name: &str,
+        directory: &Utf8Path,
+        files: Vec<Utf8PathBuf>,
+        destination: Utf8PathBuf,
+        format: ExportFormat,
+    ) -> Self {
+        let files = files
+            .into_iter()
+            .filter_map(|file| {
+                let entry_name = entry_name(directory, &file)?;
+                Some((file, entry_name))
+            })
+            .collect();
+
+        Self {
+            name: file_safe_name(name),
+            files,
+            destination,
+            format,
+        }
+    }
+
+    fn target_path(&self) -> Utf8PathBuf {
+        match self.format {
+            ExportFormat::Folder => self.destination.join(&self.name),
+            ExportFormat::Zip => self.destination.join(format!("{}.zip", self.name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExportMessage {
+    /// Files written so far (0) of the total (1)
+    Progress(usize, usize),
+    /// Where the album was written, or why it couldn't be
+    Finished(Result<Utf8PathBuf, String>),
+}
+
+/// Writes one file per step, reporting progress after each;
+/// a different request restarts it
+pub fn export_subscription(request: ExportRequest) -> iced::Subscription<ExportMessage> {
+    struct ExportSub;
+
+    old_unfold(
+        (std::any::TypeId::of::<ExportSub>(), request.clone()),
+        ExportState::Initial(request),
+        step,
+    )
+}
+
+enum ExportState {
+    Initial(ExportRequest),
+    Writing {
+        writer: ExportWriter,
+        request: ExportRequest,
+        written: usize,
+    },
+    Finished,
+}
+
+async fn step(state: ExportState) -> (Option<ExportMessage>, ExportState) {
+    match state {
+        ExportState::Initial(request) => match ExportWriter::create(&request) {
+            Ok(writer) => {
+                let progress = ExportMessage::Progress(0, request.files.len());
+                let state = ExportState::Writing { writer, request, written: 0 };
+                (Some(progress), state)
+            }
+            Err(e) => finished(Err(e)),
+        },
+
+        ExportState::Writing { mut writer, request, written } => {
+            let Some((source, entry_name)) = request.files.get(written) else {
+                return finished(writer.finish().map(|()| request.target_path()));
+            };
+
+            if let Err(e) = writer.add(source, entry_name) {
+                return finished(Err(e));
+            }
+
+            let written = written + 1;
+            let progress = ExportMessage::Progress(written, request.files.len());
+            (
+                Some(progress),
+                ExportState::Writing { writer, request, written },
+            )
+        }
+
+        ExportState::Finished => iced::futures::future::pending().await,
+    }
+}
+
+fn finished(result: anyhow::Result<Utf8PathBuf>) -> (Option<ExportMessage>, ExportState) {
+    let result = result.map_err(|e| format!("{e:#}"));
+    (Some(ExportMessage::Finished(result)), ExportState::Finished)
+}
+
+enum ExportWriter {
+    Folder(Utf8PathBuf),
+    Zip(StoredZip),
+}
+
+impl ExportWriter {
+    /// NOTE this won't write over or into an existing export
+    fn create(request: &ExportRequest) -> anyhow::Result<Self> {
+        let target = request.target_path();
+        if !request.destination.is_dir() {
+            anyhow::bail!("{} isn't a folder", request.destination);
+        }
+        if target.exists() {
+            anyhow::bail!("{target} already exists");
+        }
+
+        match request.format {
+            ExportFormat::Folder => {
+                std::fs::create_dir(&target)?;
+                Ok(Self::Folder(target))
+            }
+            ExportFormat::Zip => Ok(Self::Zip(StoredZip::create(&target)?)),
+        }
+    }
+
+    fn add(&mut self, source: &Utf8Path, entry_name: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Folder(folder) => {
+                let target = folder.join(entry_name);
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::copy(source, &target)
+                    .map_err(|e| anyhow::anyhow!("failed to copy {source}: {e}"))?;
+            }
+            Self::Zip(zip) => {
+                let contents = std::fs::read(source)
+                    .map_err(|e| anyhow::anyhow!("failed to read {source}: {e}"))?;
+                zip.add(entry_name, &contents)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        match self {
+            Self::Folder(_) => Ok(()),
+            Self::Zip(zip) => zip.finish(),
+        }
+    }
+}
+
+/// The album's folder names can't be trusted to be valid file names elsewhere,
+/// ie on a FAT formatted usb stick
+fn file_safe_name(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let safe = safe.trim().trim_end_matches('.');
+
+    if safe.is_empty() {
+        "Album".to_string()
+    } else {
+        safe.to_string()
+    }
+}
+
+/// The path within the album's directory (ie 'CD2/01.flac'), or else the file name.
+/// NOTE zip files always separate with '/'
+fn entry_name(directory: &Utf8Path, file: &Utf8Path) -> Option<String> {
+    match file.strip_prefix(directory) {
+        Ok(relative) => {
+            let components: Vec<_> = relative.components().map(|c| c.as_str()).collect();
+            (!components.is_empty()).then(|| components.join("/"))
+        }
+        Err(_) => file.file_name().map(str::to_string),
+    }
+}
+
+/// 1980-01-01, the earliest dos date; entries aren't given a real timestamp
+const DOS_DATE: u16 = (1 << 5) | 1;
+/// The names are utf-8
+const UTF8_FLAG: u16 = 1 << 11;
+/// 2.0, the first version with folders
+const ZIP_VERSION: u16 = 20;
+
+/// A zip file without compression or zip64 extensions
+struct StoredZip {
+    file: BufWriter<File>,
+    offset: u32,
+    entries: Vec<ZipEntry>,
+}
+
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+impl StoredZip {
+    fn create(path: &Utf8Path) -> std::io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+            offset: 0,
+            entries: Vec::new(),
+        })
+    }
+
+    fn add(&mut self, name: &str, contents: &[u8]) -> anyhow::Result<()> {
+        let too_big = || anyhow::anyhow!("the album is too big for a zip file");
+        let size = u32::try_from(contents.len()).map_err(|_| too_big())?;
+        let name_length = u16::try_from(name.len())?;
+
+        let entry = ZipEntry {
+            name: name.to_string(),
+            crc: crc32fast::hash(contents),
+            size,
+            offset: self.offset,
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend(0x04034b50u32.to_le_bytes());
+        header.extend(ZIP_VERSION.to_le_bytes());
+        header.extend(UTF8_FLAG.to_le_bytes());
+        header.extend(0u16.to_le_bytes()); // stored
+        header.extend(0u16.to_le_bytes()); // time
+        header.extend(DOS_DATE.to_le_bytes());
+        header.extend(entry.crc.to_le_bytes());
+        header.extend(size.to_le_bytes()); // compressed
+        header.extend(size.to_le_bytes());
+        header.extend(name_length.to_le_bytes());
+        header.extend(0u16.to_le_bytes()); // extra field
+        header.extend(name.as_bytes());
+
+        self.file.write_all(&header)?;
+        self.file.write_all(contents)?;
+
+        let written = u32::try_from(header.len())? as u64 + size as u64;
+        self.offset =
+            u32::try_from(self.offset as u64 + written).map_err(|_| too_big())?;
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Writes the central directory, which readers start from
+    fn finish(mut self) -> anyhow::Result<()> {
+        let directory_offset = self.offset;
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend(0x02014b50u32.to_le_bytes());
+            directory.extend(ZIP_VERSION.to_le_bytes()); // made by
+            directory.extend(ZIP_VERSION.to_le_bytes()); // needed
+            directory.extend(UTF8_FLAG.to_le_bytes());
+            directory.extend(0u16.to_le_bytes()); // stored
+            directory.extend(0u16.to_le_bytes()); // time
+            directory.extend(DOS_DATE.to_le_bytes());
+            directory.extend(entry.crc.to_le_bytes());
+            directory.extend(entry.size.to_le_bytes()); // compressed
+            directory.extend(entry.size.to_le_bytes());
+            directory.extend((entry.name.len() as u16).to_le_bytes());
+            directory.extend(0u16.to_le_bytes()); // extra field
+            directory.extend(0u16.to_le_bytes()); // comment
+            directory.extend(0u16.to_le_bytes()); // disk
+            directory.extend(0u16.to_le_bytes()); // internal attributes
+            directory.extend(0u32.to_le_bytes()); // external attributes
+            directory.extend(entry.offset.to_le_bytes());
+            directory.extend(entry.name.as_bytes());
+        }
+
+        let entry_count = u16::try_from(self.entries.len())?;
+        let mut end = Vec::with_capacity(22);
+        end.extend(0x06054b50u32.to_le_bytes());
+        end.extend(0u16.to_le_bytes()); // this disk
+        end.extend(0u16.to_le_bytes()); // the directory's disk
+        end.extend(entry_count.to_le_bytes()); // on this disk
+        end.extend(entry_count.to_le_bytes());
+        end.extend(u32::try_from(directory.len())?.to_le_bytes());
+        end.extend(directory_offset.to_le_bytes());
+        end.extend(0u16.to_le_bytes()); // comment
+
+        self.file.write_all(&directory)?;
+        self.file.write_all(&end)?;
+        self.file.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iced::futures::executor::block_on;
+
+    #[test]
+    fn albums_export_to_a_folder_or_zip() {
+        let dir =
+            std::env::temp_dir().join(format!("clef-export-{}", std::process::id()));
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+        let album = dir.join("Album");
+        let (disc_1, disc_2) = (album.join("CD1"), album.join("CD2"));
+        std::fs::create_dir_all(&disc_1).unwrap();
+        std::fs::create_dir_all(&disc_2).unwrap();
+        std::fs::write(disc_1.join("01.flac"), "first").unwrap();
+        std::fs::write(disc_2.join("01.flac"), "second").unwrap();
+        std::fs::write(dir.join("cover.jpg"), "art").unwrap();
+        let files = vec![
+            disc_1.join("01.flac"),
+            disc_2.join("01.flac"),
+            dir.join("cover.jpg"),
+        ];
+
+        let run = |format| {
+            let request = ExportRequest::new(
+                "AC/DC: Live?",
+                &album,
+                files.clone(),
+                dir.clone(),
+                format,
+            );
+            let mut state = ExportState::Initial(request);
+            let mut progress = Vec::new();
+            loop {
+                let (message, next) = block_on(step(state));
+                state = next;
+                match message {
+                    Some(ExportMessage::Progress(written, total)) => {
+                        progress.push((written, total))
+                    }
+                    Some(ExportMessage::Finished(result)) => return (progress, result),
+                    None => {}
+                }
+            }
+        };
+
+        let (progress, result) = run(ExportFormat::Folder);
+        assert_eq!(progress, [(0, 3), (1, 3), (2, 3), (3, 3)]);
+        let folder = result.unwrap();
+        assert_eq!(folder, dir.join("AC_DC_ Live_"));
+        let read = |path: Utf8PathBuf| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(folder.join("CD1/01.flac")), "first");
+        assert_eq!(read(folder.join("CD2/01.flac")), "second");
+        assert_eq!(read(folder.join("cover.jpg")), "art");
+
+        // NOTE an existing export isn't overwritten
+        let (_, result) = run(ExportFormat::Folder);
+        assert!(result.unwrap_err().contains("already exists"));
+
+        let (_, result) = run(ExportFormat::Zip);
+        let zip = std::fs::read(result.unwrap()).unwrap();
+        assert_eq!(&zip[..4], &0x04034b50u32.to_le_bytes());
+        assert_eq!(&zip[30..41], b"CD1/01.flac");
+        assert_eq!(&zip[41..46], b"first");
+        let end = &zip[zip.len() - 22..];
+        assert_eq!(&end[..4], &0x06054b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 3);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,98 @@
+//! The music directories as folders, for libraries organized by folder rather than tags.
+//! Folders are read from disk as they're opened, including ones the crawler skips
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::app::crawler::LibraryExtensions;
+
+/// What's directly inside an opened folder, by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderListing {
+    pub path: Utf8PathBuf,
+    pub folders: Vec<Utf8PathBuf>,
+    /// The audio files; other files aren't shown
+    pub songs: Vec<Utf8PathBuf>,
+}
+
+/// Lists a folder without touching the db; the error is for display.
+/// NOTE hidden folders and files are left out, like a file manager's default
+pub async fn read_folder(
+    path: Utf8PathBuf,
+    extensions: LibraryExtensions,
+) -> Result<FolderListing, String> {
+    let entries = path
+        .read_dir_utf8()
+        .map_err(|e| format!("Couldn't open {path}: {e}"))?;
+
+    let mut folders = Vec::new();
+    let mut songs = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        if entry.file_name().starts_with('.') {
+            continue;
+        }
+
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+        let entry_path = entry.into_path();
+        if is_dir {
+            folders.push(entry_path);
+        } else if extensions.is_music(&entry_path) {
+            songs.push(entry_path);
+        }
+    }
+    folders.sort_by_cached_key(|folder| sort_key(folder));
+    songs.sort_by_cached_key(|song| sort_key(song));
+
+    Ok(FolderListing { path, folders, songs })
+}
+
+/// The folder to go up to, or None from a music directory (or outside all of them),
+/// for the list of music directories
+pub fn parent_folder(
+    path: &Utf8Path,
+    music_directories: &[Utf8PathBuf],
+) -> Option<Utf8PathBuf> {
+    if music_directories.iter().any(|directory| directory == path) {
+        return None;
+    }
+
+    let parent = path.parent()?;
+    music_directories
+        .iter()
+        .any(|directory| parent.starts_with(directory))
+        .then(|| parent.to_owned())
+}
+
+fn sort_key(path: &Utf8Path) -> String {
+    path.file_name().unwrap_or_default().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn going_up_stops_at_the_music_directories() {
+        let music_directories = vec![Utf8PathBuf::from("/music"), "/mnt/usb".into()];
+
+        assert_eq!(
+            parent_folder(Utf8Path::new("/music/Artist/Album"), &music_directories),
+            Some("/music/Artist".into())
+        );
+        assert_eq!(
+            parent_folder(Utf8Path::new("/music/Artist"), &music_directories),
+            Some("/music".into())
+        );
+        assert_eq!(
+            parent_folder(Utf8Path::new("/music"), &music_directories),
+            None
+        );
+        assert_eq!(
+            parent_folder(Utf8Path::new("/mnt/usb"), &music_directories),
+            None
+        );
+        assert_eq!(
+            parent_folder(Utf8Path::new("/home/me/Mix"), &music_directories),
+            None
+        );
+    }
+}
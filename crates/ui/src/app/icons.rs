@@ -33,6 +33,62 @@ where
     svg_icon("skip-back.svg")
 }
 
+pub fn shuffle<Renderer>() -> Svg<Renderer>
+where
+    Renderer: iced_native::svg::Renderer,
+    Renderer::Theme: StyleSheet,
+{
+    svg_icon("shuffle.svg")
+}
+
+pub fn rescan<Renderer>() -> Svg<Renderer>
+where
+    Renderer: iced_native::svg::Renderer,
+    Renderer::Theme: StyleSheet,
+{
+    svg_icon("refresh-cw.svg")
+}
+
+pub fn heart<Renderer>() -> Svg<Renderer>
+where
+    Renderer: iced_native::svg::Renderer,
+    Renderer::Theme: StyleSheet,
+{
+    svg_icon("heart.svg")
+}
+
+pub fn heart_filled<Renderer>() -> Svg<Renderer>
+where
+    Renderer: iced_native::svg::Renderer,
+    Renderer::Theme: StyleSheet,
+{
+    svg_icon("heart-filled.svg")
+}
+
+pub fn edit<Renderer>() -> Svg<Renderer>
+where
+    Renderer: iced_native::svg::Renderer,
+    Renderer::Theme: StyleSheet,
+{
+    svg_icon("edit-2.svg")
+}
+
+pub fn volume<Renderer>() -> Svg<Renderer>
+where
+    Renderer: iced_native::svg::Renderer,
+    Renderer::Theme: StyleSheet,
+{
+    svg_icon("volume-2.svg")
+}
+
+pub fn muted<Renderer>() -> Svg<Renderer>
+where
+    Renderer: iced_native::svg::Renderer,
+    Renderer::Theme: StyleSheet,
+{
+    svg_icon("volume-x.svg")
+}
+
 fn svg_icon<Renderer>(file_name: &str) -> Svg<Renderer>
 where
     Renderer: iced_native::svg::Renderer,
@@ -0,0 +1,424 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use flume::{Receiver, Sender};
+use log::{error, info, warn};
+use rand::Rng;
+
+use crate::app::old_unfold::old_unfold;
+
+/// Holds the running instance's port and token, in the local data directory
+const INSTANCE_FILE_NAME: &str = "instance";
+
+/// Locked by the running instance for as long as it runs;
+/// the os releases it when the process exits, even after a crash
+const LOCK_FILE_NAME: &str = "instance.lock";
+
+/// How long to keep trying a running instance that may still be starting up,
+/// ie that has taken the lock but not yet written the instance file
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(3);
+const HANDOFF_RETRY: Duration = Duration::from_millis(50);
+
+/// How long to wait on an instance that may have exited without cleaning up
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The running instance's reply to a request with the right token
+const HANDOFF_ACK: &str = "ok";
+
+/// The start of a link to a moment in a song; see SongLink
+const SONG_LINK_PREFIX: &str = "clef://play?";
+
+/// A request from a second launch, passed on to the running instance
+#[derive(Debug, Clone, PartialEq)]
+pub enum Handoff {
+    /// Bring the window to the front
+    Show,
+    /// Replace the queue with the songs under an absolute path, ie from 'clef play'
+    Play(Utf8PathBuf),
+    /// Play an absolute file path's album, even from outside the library
+    Open(Utf8PathBuf),
+    /// Play a song's album, starting from a moment in the song
+    PlayLink(SongLink),
+}
+
+/// A moment in a library song, as a 'clef://play?song=<id>&t=<seconds>' uri
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SongLink {
+    /// An unpacked SongId; it's only looked up in the library
+    pub song: i32,
+    /// From the start of the song; 0 when the link has no 't'
+    pub seconds: f32,
+}
+
+impl SongLink {
+    /// Unknown parameters are ignored, for links from newer versions
+    pub fn parse(uri: &str) -> Option<Self> {
+        let query = uri.strip_prefix(SONG_LINK_PREFIX)?;
+
+        let mut song = None;
+        let mut seconds = 0.0;
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match key {
+                "song" => {
+                    let id: i32 = value.parse().ok()?;
+                    song = (id > 0).then_some(id);
+                }
+                "t" => {
+                    let t: f32 = value.parse().ok()?;
+                    if !t.is_finite() || t < 0.0 {
+                        return None;
+                    }
+                    seconds = t;
+                }
+                _ => {}
+            }
+        }
+
+        Some(Self { song: song?, seconds })
+    }
+}
+
+impl std::fmt::Display for SongLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{SONG_LINK_PREFIX}song={}", self.song)?;
+        if self.seconds > 0.0 {
+            write!(f, "&t={}", self.seconds)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Handoff {
+    fn encode(&self) -> String {
+        match self {
+            Self::Show => "show".to_string(),
+            Self::Play(path) => format!("play {path}"),
+            Self::Open(file) => format!("open {file}"),
+            Self::PlayLink(link) => format!("link {link}"),
+        }
+    }
+
+    /// NOTE paths can't hold newlines here; the request is one line
+    fn decode(line: &str) -> Option<Self> {
+        match line.split_once(' ') {
+            None if line == "show" => Some(Self::Show),
+            Some(("play", path)) if !path.is_empty() => Some(Self::Play(path.into())),
+            Some(("open", file)) if !file.is_empty() => Some(Self::Open(file.into())),
+            Some(("link", uri)) => SongLink::parse(uri).map(Self::PlayLink),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum InstanceClaim {
+    /// This is the only instance; hand-offs from later launches arrive here
+    Primary(Receiver<Handoff>),
+    /// Another instance is running, and has been sent the request
+    HandedOff,
+    /// Another instance holds the lock, but didn't take the request
+    Unresponsive,
+}
+
+/// Becomes the running instance, or passes the request to the one that already is.
+/// Whichever launch locks the lock file first is the running instance.
+pub fn claim_instance(
+    local_data_directory: &Utf8Path,
+    handoff: Handoff,
+) -> anyhow::Result<InstanceClaim> {
+    let instance_path = local_data_directory.join(INSTANCE_FILE_NAME);
+    let lock_path = local_data_directory.join(LOCK_FILE_NAME);
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("failed to open {lock_path}"))?;
+    match lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            return Ok(hand_off(&instance_path, &handoff));
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("failed to lock {lock_path}"));
+        }
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .context("failed to bind instance listener")?;
+    let port = listener.local_addr()?.port();
+
+    let token = format!("{:016x}", rand::thread_rng().gen::<u64>());
+    std::fs::write(&instance_path, format!("{port}\n{token}\n"))
+        .with_context(|| format!("failed to write {instance_path}"))?;
+
+    let (tx, rx) = flume::unbounded();
+    std::thread::Builder::new()
+        .name("ClefInstance".to_string())
+        .spawn(move || listen(listener, token, lock, tx))?;
+
+    Ok(InstanceClaim::Primary(rx))
+}
+
+/// Sends the request to the instance holding the lock,
+/// waiting for it to write the instance file if it's just starting
+fn hand_off(instance_path: &Utf8Path, handoff: &Handoff) -> InstanceClaim {
+    let started = Instant::now();
+
+    loop {
+        let sent = match read_instance_file(instance_path) {
+            Some((port, token)) => send_handoff(port, &token, handoff),
+            None => Err(anyhow::anyhow!("no instance file yet")),
+        };
+
+        match sent {
+            Ok(()) => return InstanceClaim::HandedOff,
+            Err(e) if started.elapsed() >= HANDOFF_TIMEOUT => {
+                warn!("the running instance didn't take the request: {e}");
+                return InstanceClaim::Unresponsive;
+            }
+            Err(e) => info!("retrying handoff: {e}"),
+        }
+
+        std::thread::sleep(HANDOFF_RETRY);
+    }
+}
+
+fn read_instance_file(path: &Utf8Path) -> Option<(u16, String)> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let mut lines = contents.lines();
+    let port = lines.next()?.parse().ok()?;
+    let token = lines.next()?.to_string();
+
+    Some((port, token))
+}
+
+fn send_handoff(port: u16, token: &str, handoff: &Handoff) -> anyhow::Result<()> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+    write!(stream, "{token}\n{}\n", handoff.encode())?;
+
+    // NOTE a stale instance file's port can belong to some other process by now
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let mut ack = String::new();
+    BufReader::new(stream)
+        .read_line(&mut ack)
+        .context("no reply from the running instance")?;
+    if ack.trim_end() != HANDOFF_ACK {
+        anyhow::bail!("unexpected reply from the running instance: {ack:?}");
+    }
+
+    Ok(())
+}
+
+/// NOTE this holds the lock file, which stays locked while the thread runs
+fn listen(listener: TcpListener, token: String, _lock: File, tx: Sender<Handoff>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("failed to accept instance connection: {e}");
+                continue;
+            }
+        };
+
+        // NOTE a client that never finishes its request shouldn't block the next one
+        if let Err(e) = stream.set_read_timeout(Some(CONNECT_TIMEOUT)) {
+            warn!("failed to set instance read timeout: {e}");
+            continue;
+        }
+
+        let mut lines = BufReader::new(&stream).lines();
+        let (Some(Ok(sent_token)), Some(Ok(request))) = (lines.next(), lines.next())
+        else {
+            warn!("incomplete instance request");
+            continue;
+        };
+
+        if sent_token != token {
+            warn!("ignoring instance request with the wrong token");
+            continue;
+        }
+
+        // NOTE without an ack, the other launch doesn't exit as if this took it
+        let Some(handoff) = Handoff::decode(&request) else {
+            warn!("unknown instance request: {request}");
+            continue;
+        };
+
+        if let Err(e) = writeln!(&stream, "{HANDOFF_ACK}") {
+            warn!("failed to acknowledge instance request: {e}");
+        }
+
+        if tx.send(handoff).is_err() {
+            error!("ui stopped listening for other instances");
+            return;
+        }
+    }
+}
+
+pub fn instance_subscription(handoffs: Receiver<Handoff>) -> iced::Subscription<Handoff> {
+    struct InstanceSub;
+
+    old_unfold(
+        std::any::TypeId::of::<InstanceSub>(),
+        handoffs,
+        |handoffs| async move {
+            match handoffs.recv_async().await {
+                Ok(handoff) => (Some(handoff), handoffs),
+                // NOTE the listener failed, or this instance isn't the primary
+                Err(_) => iced::futures::future::pending().await,
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handoffs_round_trip() {
+        let play = Handoff::Play("/music/Some Artist/An Album".into());
+        assert_eq!(Handoff::decode(&play.encode()), Some(play));
+        assert_eq!(
+            Handoff::decode(&Handoff::Show.encode()),
+            Some(Handoff::Show)
+        );
+
+        let open = Handoff::Open("/downloads/a song.flac".into());
+        assert_eq!(Handoff::decode(&open.encode()), Some(open));
+
+        let link = Handoff::PlayLink(SongLink { song: 12, seconds: 83.5 });
+        assert_eq!(Handoff::decode(&link.encode()), Some(link));
+
+        assert_eq!(Handoff::decode("play "), None);
+        assert_eq!(Handoff::decode("stop"), None);
+    }
+
+    #[test]
+    fn song_links_parse_with_an_optional_time() {
+        let link = |song, seconds| Some(SongLink { song, seconds });
+
+        assert_eq!(SongLink::parse("clef://play?song=7&t=90"), link(7, 90.0));
+        assert_eq!(SongLink::parse("clef://play?t=1.5&song=7"), link(7, 1.5));
+        assert_eq!(SongLink::parse("clef://play?song=7"), link(7, 0.0));
+        assert_eq!(
+            SongLink::parse("clef://play?song=7&from=notes"),
+            link(7, 0.0)
+        );
+        assert_eq!(
+            SongLink::parse(&SongLink { song: 7, seconds: 90.0 }.to_string()),
+            link(7, 90.0)
+        );
+
+        assert_eq!(SongLink::parse("clef://play?t=90"), None);
+        assert_eq!(SongLink::parse("clef://play?song=-3"), None);
+        assert_eq!(SongLink::parse("clef://play?song=7&t=-1"), None);
+        assert_eq!(SongLink::parse("clef://play?song=7&t=inf"), None);
+        assert_eq!(SongLink::parse("https://play?song=7"), None);
+    }
+
+    #[test]
+    fn a_second_claim_hands_off_to_the_first() {
+        let dir =
+            std::env::temp_dir().join(format!("clef-instance-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+
+        let InstanceClaim::Primary(handoffs) =
+            claim_instance(&dir, Handoff::Show).unwrap()
+        else {
+            panic!("expected the first claim to be primary");
+        };
+
+        let play = Handoff::Play("/music/An Album".into());
+        let second = claim_instance(&dir, play.clone()).unwrap();
+        assert!(matches!(second, InstanceClaim::HandedOff));
+        assert_eq!(handoffs.recv_timeout(Duration::from_secs(5)), Ok(play));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn a_held_lock_is_never_claimed_twice() {
+        let dir = std::env::temp_dir()
+            .join(format!("clef-locked-instance-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+
+        // NOTE like an instance that took the lock, but hasn't started listening
+        let lock = File::create(dir.join(LOCK_FILE_NAME)).unwrap();
+        lock.try_lock().unwrap();
+
+        let claim = claim_instance(&dir, Handoff::Show).unwrap();
+        assert!(matches!(claim, InstanceClaim::Unresponsive));
+
+        drop(lock);
+        let claim = claim_instance(&dir, Handoff::Show).unwrap();
+        assert!(matches!(claim, InstanceClaim::Primary(_)));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn unknown_requests_are_not_acknowledged() {
+        let dir = std::env::temp_dir()
+            .join(format!("clef-unknown-request-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+
+        let InstanceClaim::Primary(_handoffs) =
+            claim_instance(&dir, Handoff::Show).unwrap()
+        else {
+            panic!("expected the first claim to be primary");
+        };
+        let (port, token) = read_instance_file(&dir.join(INSTANCE_FILE_NAME)).unwrap();
+
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{token}\nstop\n").unwrap();
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT)).unwrap();
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).ok();
+        assert_eq!(reply, "");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn a_port_that_never_replies_is_stale() {
+        let dir = std::env::temp_dir()
+            .join(format!("clef-stale-instance-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = Utf8PathBuf::try_from(dir).unwrap();
+
+        // NOTE connecting succeeds, but nothing reads the request
+        let other_process = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = other_process.local_addr().unwrap().port();
+        std::fs::write(dir.join(INSTANCE_FILE_NAME), format!("{port}\nstale\n")).unwrap();
+        let lock = File::create(dir.join(LOCK_FILE_NAME)).unwrap();
+        lock.try_lock().unwrap();
+
+        let claim = claim_instance(&dir, Handoff::Show).unwrap();
+        assert!(matches!(claim, InstanceClaim::Unresponsive));
+
+        let (stream, _) = other_process.accept().unwrap();
+        let mut token = String::new();
+        BufReader::new(stream).read_line(&mut token).unwrap();
+        assert_eq!(token, "stale\n");
+
+        drop(lock);
+        let claim = claim_instance(&dir, Handoff::Show).unwrap();
+        assert!(matches!(claim, InstanceClaim::Primary(_)));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
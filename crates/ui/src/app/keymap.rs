@@ -0,0 +1,423 @@
+use std::collections::BTreeMap;
+
+use iced::keyboard::{KeyCode, Modifiers};
+
+/// Something a key can do, named as in the settings file's [keys] table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyAction {
+    TogglePlayback,
+    Next,
+    Previous,
+    SeekForward,
+    SeekBack,
+    SeekForwardLong,
+    SeekBackLong,
+    /// A tenth of the song
+    SeekForwardTenth,
+    /// A tenth of the song
+    SeekBackTenth,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    /// Opens the playing song's album
+    JumpToCurrent,
+    /// A clef:// link to the playing song's current moment; see SongLink
+    CopyLink,
+    /// Switches between the full window and the mini player
+    ToggleCompact,
+    /// Marks the start of a loop, then its end, then clears it
+    MarkLoopPoint,
+}
+
+impl KeyAction {
+    pub const ALL: [KeyAction; 16] = [
+        Self::TogglePlayback,
+        Self::Next,
+        Self::Previous,
+        Self::SeekForward,
+        Self::SeekBack,
+        Self::SeekForwardLong,
+        Self::SeekBackLong,
+        Self::SeekForwardTenth,
+        Self::SeekBackTenth,
+        Self::VolumeUp,
+        Self::VolumeDown,
+        Self::Mute,
+        Self::JumpToCurrent,
+        Self::CopyLink,
+        Self::ToggleCompact,
+        Self::MarkLoopPoint,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::TogglePlayback => "toggle_playback",
+            Self::Next => "next",
+            Self::Previous => "previous",
+            Self::SeekForward => "seek_forward",
+            Self::SeekBack => "seek_back",
+            Self::SeekForwardLong => "seek_forward_long",
+            Self::SeekBackLong => "seek_back_long",
+            Self::SeekForwardTenth => "seek_forward_tenth",
+            Self::SeekBackTenth => "seek_back_tenth",
+            Self::VolumeUp => "volume_up",
+            Self::VolumeDown => "volume_down",
+            Self::Mute => "mute",
+            Self::JumpToCurrent => "jump_to_current",
+            Self::CopyLink => "copy_link",
+            Self::ToggleCompact => "toggle_compact",
+            Self::MarkLoopPoint => "mark_loop_point",
+        }
+    }
+
+    /// Shown beside the binding in the settings
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::TogglePlayback => "Play/pause",
+            Self::Next => "Next song",
+            Self::Previous => "Previous song",
+            Self::SeekForward => "Forward 5 seconds",
+            Self::SeekBack => "Back 5 seconds",
+            Self::SeekForwardLong => "Forward 30 seconds",
+            Self::SeekBackLong => "Back 30 seconds",
+            Self::SeekForwardTenth => "Forward a tenth of the song",
+            Self::SeekBackTenth => "Back a tenth of the song",
+            Self::VolumeUp => "Volume up",
+            Self::VolumeDown => "Volume down",
+            Self::Mute => "Mute",
+            Self::JumpToCurrent => "Show the playing album",
+            Self::CopyLink => "Copy a link to this moment",
+            Self::ToggleCompact => "Switch to or from the mini player",
+            Self::MarkLoopPoint => "Mark a loop's start, then its end, then clear it",
+        }
+    }
+
+    /// Whether holding the key repeats the action;
+    /// the others happen once, when the key is released
+    pub fn repeats(self) -> bool {
+        matches!(
+            self,
+            Self::SeekForward
+                | Self::SeekBack
+                | Self::SeekForwardLong
+                | Self::SeekBackLong
+                | Self::SeekForwardTenth
+                | Self::SeekBackTenth
+                | Self::VolumeUp
+                | Self::VolumeDown
+        )
+    }
+
+    fn default_binding(self) -> KeyBinding {
+        let (modifiers, key_code) = match self {
+            Self::TogglePlayback => (Modifiers::empty(), KeyCode::Space),
+            Self::Next => (Modifiers::CTRL, KeyCode::Right),
+            Self::Previous => (Modifiers::CTRL, KeyCode::Left),
+            Self::SeekForward => (Modifiers::empty(), KeyCode::Right),
+            Self::SeekBack => (Modifiers::empty(), KeyCode::Left),
+            Self::SeekForwardLong => (Modifiers::SHIFT, KeyCode::Right),
+            Self::SeekBackLong => (Modifiers::SHIFT, KeyCode::Left),
+            Self::SeekForwardTenth => (Modifiers::ALT, KeyCode::Right),
+            Self::SeekBackTenth => (Modifiers::ALT, KeyCode::Left),
+            Self::VolumeUp => (Modifiers::empty(), KeyCode::Up),
+            Self::VolumeDown => (Modifiers::empty(), KeyCode::Down),
+            Self::Mute => (Modifiers::empty(), KeyCode::M),
+            Self::JumpToCurrent => (Modifiers::empty(), KeyCode::L),
+            Self::CopyLink => (Modifiers::CTRL, KeyCode::C),
+            Self::ToggleCompact => (Modifiers::CTRL, KeyCode::M),
+            Self::MarkLoopPoint => (Modifiers::empty(), KeyCode::A),
+        };
+
+        KeyBinding { key_code, modifiers }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// Key names for the settings file, besides single letters and digits
+const NAMED_KEYS: [(&str, KeyCode); 31] = [
+    ("space", KeyCode::Space),
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("slash", KeyCode::Slash),
+    ("comma", KeyCode::Comma),
+    ("period", KeyCode::Period),
+    ("minus", KeyCode::Minus),
+    ("equals", KeyCode::Equals),
+    ("plus", KeyCode::Plus),
+    ("f1", KeyCode::F1),
+    ("f2", KeyCode::F2),
+    ("f3", KeyCode::F3),
+    ("f4", KeyCode::F4),
+    ("f5", KeyCode::F5),
+    ("f6", KeyCode::F6),
+    ("f7", KeyCode::F7),
+    ("f8", KeyCode::F8),
+    ("f9", KeyCode::F9),
+    ("f10", KeyCode::F10),
+    ("f11", KeyCode::F11),
+    ("f12", KeyCode::F12),
+];
+
+const LETTER_KEYS: [KeyCode; 26] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+];
+
+const DIGIT_KEYS: [KeyCode; 10] = [
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// A key with the exact modifiers to hold, ie 'ctrl+shift+right'
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub key_code: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl KeyBinding {
+    /// Modifiers and a key name joined with '+', in any case; None for unknown names
+    pub fn parse(binding: &str) -> Option<Self> {
+        let binding = binding.trim().to_lowercase();
+        let mut parts: Vec<&str> = binding.split('+').map(str::trim).collect();
+
+        // NOTE 'ctrl++' binds the plus key
+        if binding.ends_with("++") {
+            parts.truncate(parts.len().saturating_sub(2));
+            parts.push("plus");
+        }
+
+        let (key_name, modifier_names) = parts.split_last()?;
+
+        let mut modifiers = Modifiers::empty();
+        for name in modifier_names {
+            modifiers |= match *name {
+                "ctrl" | "control" => Modifiers::CTRL,
+                "shift" => Modifiers::SHIFT,
+                "alt" | "option" => Modifiers::ALT,
+                "logo" | "super" | "cmd" | "win" => Modifiers::LOGO,
+                _ => return None,
+            };
+        }
+
+        Some(Self {
+            key_code: key_code(key_name)?,
+            modifiers,
+        })
+    }
+}
+
+impl std::fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let modifier_names = [
+            (Modifiers::CTRL, "Ctrl"),
+            (Modifiers::ALT, "Alt"),
+            (Modifiers::SHIFT, "Shift"),
+            (Modifiers::LOGO, "Logo"),
+        ];
+        for (modifier, name) in modifier_names {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}+")?;
+            }
+        }
+
+        let name = key_name(self.key_code).unwrap_or_else(|| "?".to_string());
+        let mut chars = name.chars();
+        match chars.next() {
+            Some(first) => write!(f, "{}{}", first.to_ascii_uppercase(), chars.as_str()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn key_code(name: &str) -> Option<KeyCode> {
+    if let Some((_, key_code)) = NAMED_KEYS.iter().find(|(named, _)| *named == name) {
+        return Some(*key_code);
+    }
+
+    let mut chars = name.chars();
+    let (Some(single), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+
+    match single {
+        'a'..='z' => Some(LETTER_KEYS[(single as u8 - b'a') as usize]),
+        '0'..='9' => Some(DIGIT_KEYS[(single as u8 - b'0') as usize]),
+        '/' => Some(KeyCode::Slash),
+        _ => None,
+    }
+}
+
+fn key_name(key_code: KeyCode) -> Option<String> {
+    if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, named)| *named == key_code) {
+        return Some(name.to_string());
+    }
+
+    if let Some(index) = LETTER_KEYS.iter().position(|letter| *letter == key_code) {
+        return Some(char::from(b'a' + index as u8).to_string());
+    }
+
+    DIGIT_KEYS
+        .iter()
+        .position(|digit| *digit == key_code)
+        .map(|index| index.to_string())
+}
+
+/// Which action each key does; the defaults, with the settings file's bindings over them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    bindings: BTreeMap<KeyAction, KeyBinding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = KeyAction::ALL
+            .into_iter()
+            .map(|action| (action, action.default_binding()))
+            .collect();
+
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// Overrides from the settings file, by action name;
+    /// the errors are for entries that were ignored
+    pub fn with_overrides(overrides: &BTreeMap<String, String>) -> (Self, Vec<String>) {
+        let mut keymap = Self::default();
+        let mut errors = Vec::new();
+
+        for (action_name, binding) in overrides {
+            let Some(action) = KeyAction::from_name(action_name) else {
+                errors.push(format!("unknown key action: {action_name}"));
+                continue;
+            };
+            let Some(binding) = KeyBinding::parse(binding) else {
+                errors.push(format!("unknown key for {action_name}: {binding}"));
+                continue;
+            };
+
+            // NOTE a key moved to a new action no longer does the old one
+            keymap.bindings.retain(|_, bound| *bound != binding);
+            keymap.bindings.insert(action, binding);
+        }
+
+        (keymap, errors)
+    }
+
+    pub fn action(&self, key_code: KeyCode, modifiers: Modifiers) -> Option<KeyAction> {
+        let pressed = KeyBinding { key_code, modifiers };
+
+        self.bindings
+            .iter()
+            .find(|(_, binding)| **binding == pressed)
+            .map(|(action, _)| *action)
+    }
+
+    /// Every action, with its key if it still has one
+    pub fn bindings(&self) -> impl Iterator<Item = (KeyAction, Option<KeyBinding>)> + '_ {
+        KeyAction::ALL
+            .into_iter()
+            .map(|action| (action, self.bindings.get(&action).copied()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_parse_and_display() {
+        let binding = KeyBinding::parse("Ctrl+Shift+Right").unwrap();
+        assert_eq!(binding.key_code, KeyCode::Right);
+        assert_eq!(binding.modifiers, Modifiers::CTRL | Modifiers::SHIFT);
+        assert_eq!(binding.to_string(), "Ctrl+Shift+Right");
+
+        assert_eq!(KeyBinding::parse("/").unwrap().key_code, KeyCode::Slash);
+        assert_eq!(KeyBinding::parse("q").unwrap().to_string(), "Q");
+        assert_eq!(
+            KeyBinding::parse("ctrl++").unwrap().to_string(),
+            "Ctrl+Plus"
+        );
+
+        assert_eq!(KeyBinding::parse("hyper+x"), None);
+        assert_eq!(KeyBinding::parse("nope"), None);
+        assert_eq!(KeyBinding::parse(""), None);
+    }
+
+    #[test]
+    fn overrides_replace_defaults_and_take_over_their_keys() {
+        let overrides = BTreeMap::from([
+            ("mute".to_string(), "ctrl+m".to_string()),
+            ("jump_to_current".to_string(), "space".to_string()),
+            ("dance".to_string(), "d".to_string()),
+            ("next".to_string(), "hyper+n".to_string()),
+        ]);
+        let (keymap, errors) = Keymap::with_overrides(&overrides);
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            keymap.action(KeyCode::M, Modifiers::CTRL),
+            Some(KeyAction::Mute)
+        );
+        assert_eq!(keymap.action(KeyCode::M, Modifiers::empty()), None);
+        assert_eq!(
+            keymap.action(KeyCode::Space, Modifiers::empty()),
+            Some(KeyAction::JumpToCurrent)
+        );
+        assert_eq!(
+            keymap.action(KeyCode::Right, Modifiers::CTRL),
+            Some(KeyAction::Next)
+        );
+
+        let toggle = keymap
+            .bindings()
+            .find(|(action, _)| *action == KeyAction::TogglePlayback);
+        assert_eq!(toggle, Some((KeyAction::TogglePlayback, None)));
+    }
+}
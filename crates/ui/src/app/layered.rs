@@ -0,0 +1,177 @@
+use iced::overlay;
+use iced_native::event::{self, Event};
+use iced_native::layout;
+use iced_native::renderer;
+use iced_native::widget::tree::Tree;
+use iced_native::{Clipboard, Element, Layout, Length, Point, Rectangle, Shell, Widget};
+
+/// Draws one element on top of another, aligned to its bottom edge.
+/// The base decides the size; the top is limited to it.
+#[allow(missing_debug_implementations)]
+pub struct Layered<'a, Message, Renderer> {
+    base: Element<'a, Message, Renderer>,
+    top: Element<'a, Message, Renderer>,
+}
+
+impl<'a, Message, Renderer> Layered<'a, Message, Renderer>
+where
+    Renderer: iced_native::Renderer,
+{
+    pub fn new(
+        base: impl Into<Element<'a, Message, Renderer>>,
+        top: impl Into<Element<'a, Message, Renderer>>,
+    ) -> Self {
+        Self { base: base.into(), top: top.into() }
+    }
+}
+
+impl<'a, Message, Renderer> Widget<Message, Renderer> for Layered<'a, Message, Renderer>
+where
+    Message: 'a,
+    Renderer: iced_native::Renderer,
+{
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.base), Tree::new(&self.top)]
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(&[&self.base, &self.top]);
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+    ) -> event::Status {
+        let mut children = layout.children();
+        let base_layout = children.next().unwrap();
+        let top_layout = children.next().unwrap();
+
+        if let event::Status::Captured = self.top.as_widget_mut().on_event(
+            &mut tree.children[1],
+            event.clone(),
+            top_layout,
+            cursor_position,
+            renderer,
+            clipboard,
+            shell,
+        ) {
+            return event::Status::Captured;
+        }
+
+        self.base.as_widget_mut().on_event(
+            &mut tree.children[0],
+            event,
+            base_layout,
+            cursor_position,
+            renderer,
+            clipboard,
+            shell,
+        )
+    }
+
+    fn layout(&self, renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        let base_layout = self.base.as_widget().layout(renderer, limits);
+        let size = base_layout.size();
+
+        let top_limits = layout::Limits::new(iced::Size::ZERO, size);
+        let mut top_layout = self.top.as_widget().layout(renderer, &top_limits);
+        top_layout.move_to(Point::new(0.0, size.height - top_layout.size().height));
+
+        layout::Node::with_children(size, vec![base_layout, top_layout])
+    }
+
+    fn width(&self) -> Length {
+        self.base.as_widget().width()
+    }
+
+    fn height(&self) -> Length {
+        self.base.as_widget().height()
+    }
+
+    fn draw(
+        &self,
+        state: &Tree,
+        renderer: &mut Renderer,
+        theme: &<Renderer as iced_native::Renderer>::Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        viewport: &Rectangle,
+    ) {
+        let bounds = layout.bounds();
+        let mut children = layout.children();
+        let base_layout = children.next().unwrap();
+        let top_layout = children.next().unwrap();
+
+        self.base.as_widget().draw(
+            &state.children[0],
+            renderer,
+            theme,
+            style,
+            base_layout,
+            cursor_position,
+            viewport,
+        );
+
+        // NOTE within a layer, the renderer draws images over quads,
+        // so the top needs its own layer to cover an image base
+        renderer.with_layer(bounds, |renderer| {
+            self.top.as_widget().draw(
+                &state.children[1],
+                renderer,
+                theme,
+                style,
+                top_layout,
+                cursor_position,
+                viewport,
+            );
+        });
+    }
+
+    fn mouse_interaction(
+        &self,
+        state: &Tree,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> iced_native::mouse::Interaction {
+        self.base.as_widget().mouse_interaction(
+            &state.children[0],
+            layout.children().next().unwrap(),
+            cursor_position,
+            viewport,
+            renderer,
+        )
+    }
+
+    fn overlay<'b>(
+        &'b mut self,
+        tree: &'b mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+    ) -> Option<overlay::Element<'b, Message, Renderer>> {
+        self.base.as_widget_mut().overlay(
+            &mut tree.children[0],
+            layout.children().next().unwrap(),
+            renderer,
+        )
+    }
+}
+
+impl<'a, Message, Renderer> From<Layered<'a, Message, Renderer>>
+    for Element<'a, Message, Renderer>
+where
+    Message: 'a,
+    Renderer: iced_native::Renderer + 'a,
+{
+    fn from(layered: Layered<'a, Message, Renderer>) -> Self {
+        Self::new(layered)
+    }
+}
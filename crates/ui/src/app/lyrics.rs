@@ -0,0 +1,127 @@
+/// A song's lyrics, ready to show beside the playing song
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lyrics {
+    Plain(Vec<String>),
+    /// From lrc timestamps, ie '[01:02.50]some words'; sorted by time
+    Synced(Vec<SyncedLine>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncedLine {
+    pub seconds: f64,
+    pub text: String,
+}
+
+impl Lyrics {
+    /// Lyrics with at least one timestamped line are synced, and keep only those lines;
+    /// other lrc tags (ie '[ar:Artist]') are dropped, aside from the offset
+    pub fn parse(lyrics: &str) -> Self {
+        let mut synced = Vec::new();
+        let mut offset_seconds = 0.0;
+
+        for line in lyrics.lines() {
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+
+            // NOTE repeated lines can share one text, ie '[00:10.00][01:20.00]chorus'
+            while let Some(tag_end) = rest.strip_prefix('[').and_then(|tag| tag.find(']'))
+            {
+                let tag = &rest[1..=tag_end];
+                match parse_timestamp(tag) {
+                    Some(seconds) => times.push(seconds),
+                    None => {
+                        if let Some(millis) = tag.strip_prefix("offset:") {
+                            // NOTE a positive offset shows the lines sooner
+                            offset_seconds =
+                                millis.trim().parse::<f64>().unwrap_or(0.0) / 1000.0;
+                        }
+                    }
+                }
+                rest = rest[tag_end + 2..].trim_start();
+            }
+
+            for seconds in times {
+                synced.push(SyncedLine { seconds, text: rest.to_string() });
+            }
+        }
+
+        if synced.is_empty() {
+            return Self::Plain(
+                lyrics.lines().map(|l| l.trim_end().to_string()).collect(),
+            );
+        }
+
+        for line in &mut synced {
+            line.seconds = (line.seconds - offset_seconds).max(0.0);
+        }
+        synced.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+
+        Self::Synced(synced)
+    }
+
+    /// The index of the line being sung at this point in the song;
+    /// None for plain lyrics, or before the first line
+    pub fn current_line(&self, elapsed_seconds: f64) -> Option<usize> {
+        let Self::Synced(lines) = self else {
+            return None;
+        };
+
+        lines
+            .partition_point(|line| line.seconds <= elapsed_seconds)
+            .checked_sub(1)
+    }
+}
+
+/// 'mm:ss.xx', 'mm:ss:xx', or 'mm:ss', as seconds
+fn parse_timestamp(tag: &str) -> Option<f64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: u32 = minutes.trim().parse().ok()?;
+    let seconds: f64 = seconds.trim().replacen(':', ".", 1).parse().ok()?;
+
+    if !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+
+    Some(minutes as f64 * 60.0 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamped_lines_are_synced_and_the_rest_are_plain() {
+        let lrc = "[ar:Portishead]\n\
+                   [offset:+500]\n\
+                   [00:12.50]Give me a reason\n\
+                   [00:05.00][01:05.00]Nobody loves me\n\
+                   [00:20:00]\n";
+
+        let line = |seconds, text: &str| SyncedLine { seconds, text: text.to_string() };
+        let lyrics = Lyrics::parse(lrc);
+        assert_eq!(
+            lyrics,
+            Lyrics::Synced(vec![
+                line(4.5, "Nobody loves me"),
+                line(12.0, "Give me a reason"),
+                line(19.5, ""),
+                line(64.5, "Nobody loves me"),
+            ])
+        );
+
+        assert_eq!(lyrics.current_line(1.0), None);
+        assert_eq!(lyrics.current_line(4.5), Some(0));
+        assert_eq!(lyrics.current_line(30.0), Some(2));
+        assert_eq!(lyrics.current_line(300.0), Some(3));
+
+        let plain = Lyrics::parse("Give me a reason\n[not a timestamp]\n");
+        assert_eq!(
+            plain,
+            Lyrics::Plain(vec![
+                "Give me a reason".to_string(),
+                "[not a timestamp]".to_string()
+            ])
+        );
+        assert_eq!(plain.current_line(30.0), None);
+    }
+}
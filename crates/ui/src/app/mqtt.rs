@@ -0,0 +1,433 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flume::{Receiver, RecvTimeoutError};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::app::webhook::{body_json, PlaybackEvent};
+
+/// The default port for unencrypted mqtt
+const DEFAULT_PORT: u16 = 1883;
+/// For connecting and for each write; a missing broker shouldn't back up the events
+const BROKER_TIMEOUT: Duration = Duration::from_secs(5);
+/// The most a packet's four length bytes can hold
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+/// The broker drops the connection after half again this long without a packet,
+/// so the publisher pings it after half this long without an event
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+/// How long the connection can sit unused before a publish checks that it's still open
+const IDLE_BEFORE_PING: Duration = Duration::from_secs(30);
+
+/// Where to publish playback events, from the [mqtt] table in clef.toml
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MqttSettings {
+    /// ie 'mqtt://homeassistant.local:1883'; NOTE tls isn't supported
+    pub broker_url: String,
+    #[serde(default = "default_topic")]
+    pub topic: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+fn default_topic() -> String {
+    "clef/playback".to_string()
+}
+
+#[derive(Debug)]
+pub struct MqttRequest {
+    pub settings: MqttSettings,
+    pub event: PlaybackEvent,
+    /// When the event happened; see WebhookRequest
+    pub timestamp: u64,
+}
+
+/// Publishes events on their own thread, keeping one connection to the broker open.
+/// Each event is retained, so that a dashboard shows the current state when it subscribes
+pub fn spawn_mqtt_publisher(inbox: Receiver<MqttRequest>) -> std::io::Result<()> {
+    thread::Builder::new()
+        .name("mqtt".to_string())
+        .spawn(move || publish_events(inbox, KEEP_ALIVE / 2))?;
+
+    Ok(())
+}
+
+/// Publishes until the app closes, pinging the broker whenever there's been
+/// no event for ping_every, so that it keeps the connection open
+fn publish_events(inbox: Receiver<MqttRequest>, ping_every: Duration) {
+    let mut connection: Option<BrokerConnection> = None;
+
+    loop {
+        match inbox.recv_timeout(ping_every) {
+            Ok(request) => {
+                // NOTE only the latest state is retained, so a newer event replaces this one
+                if !inbox.is_empty() {
+                    continue;
+                }
+
+                let payload = body_json(&request.event, request.timestamp);
+                connection = publish(connection, &request.settings, &payload);
+            }
+
+            // NOTE a closed connection is reopened by the next publish
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(mut open) = connection.take() {
+                    match open.ping() {
+                        Ok(()) => connection = Some(open),
+                        Err(e) => info!("closing idle mqtt connection after error: {e}"),
+                    }
+                }
+            }
+
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+/// Publishes on the open connection if it's to the same broker, or else a new one.
+/// NOTE an at-most-once publish to a closed connection is lost without an error,
+/// so an idle connection is pinged first, and reconnected once if that fails
+fn publish(
+    connection: Option<BrokerConnection>,
+    settings: &MqttSettings,
+    payload: &str,
+) -> Option<BrokerConnection> {
+    let mut connection = connection.filter(|connection| &connection.settings == settings);
+
+    for attempt in 1..=2 {
+        let mut open = match connection.take() {
+            Some(open) => open,
+            None => match BrokerConnection::open(settings) {
+                Ok(open) => open,
+                Err(e) => {
+                    error!(
+                        "failed to connect to mqtt broker {}: {e}",
+                        settings.broker_url
+                    );
+                    return None;
+                }
+            },
+        };
+
+        let published = open
+            .ping_if_idle()
+            .and_then(|()| open.publish(&settings.topic, payload.as_bytes()));
+        match published {
+            Ok(()) => return Some(open),
+            Err(e) if attempt == 1 => {
+                info!("reconnecting to mqtt broker after error: {e}")
+            }
+            Err(e) => error!("failed to publish playback to mqtt: {e}"),
+        }
+    }
+
+    None
+}
+
+struct BrokerConnection {
+    settings: MqttSettings,
+    stream: TcpStream,
+    last_sent: Instant,
+}
+
+impl BrokerConnection {
+    fn open(settings: &MqttSettings) -> anyhow::Result<Self> {
+        let address = broker_address(&settings.broker_url)?;
+        let mut stream = TcpStream::connect_timeout(&address, BROKER_TIMEOUT)?;
+        stream.set_read_timeout(Some(BROKER_TIMEOUT))?;
+        stream.set_write_timeout(Some(BROKER_TIMEOUT))?;
+
+        stream.write_all(&connect_packet(settings))?;
+
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack)?;
+        match connack {
+            [0x20, 0x02, _, 0] => {}
+            [0x20, 0x02, _, 4 | 5] => anyhow::bail!("the broker refused the credentials"),
+            [0x20, 0x02, _, code] => {
+                anyhow::bail!("the broker refused to connect ({code})")
+            }
+            _ => anyhow::bail!("unexpected response from the broker"),
+        }
+
+        Ok(Self {
+            settings: settings.clone(),
+            stream,
+            last_sent: Instant::now(),
+        })
+    }
+
+    fn publish(&mut self, topic: &str, payload: &[u8]) -> anyhow::Result<()> {
+        self.stream.write_all(&publish_packet(topic, payload)?)?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    fn ping_if_idle(&mut self) -> anyhow::Result<()> {
+        if self.last_sent.elapsed() < IDLE_BEFORE_PING {
+            return Ok(());
+        }
+
+        self.ping()
+    }
+
+    /// Round-trips a ping, since only a read notices that the broker closed the connection
+    fn ping(&mut self) -> anyhow::Result<()> {
+        self.stream.write_all(&[0xC0, 0x00])?;
+        let mut pingresp = [0u8; 2];
+        self.stream.read_exact(&mut pingresp)?;
+        if pingresp != [0xD0, 0x00] {
+            anyhow::bail!("unexpected response to a ping from the broker");
+        }
+
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for BrokerConnection {
+    fn drop(&mut self) {
+        // NOTE without this, the broker would treat the close as a lost connection
+        self.stream.write_all(&[0xE0, 0x00]).ok();
+    }
+}
+
+fn broker_address(broker_url: &str) -> anyhow::Result<SocketAddr> {
+    if broker_url.starts_with("mqtts://") || broker_url.starts_with("ssl://") {
+        anyhow::bail!("tls connections to the broker aren't supported");
+    }
+
+    let host = broker_url
+        .trim_start_matches("mqtt://")
+        .trim_start_matches("tcp://")
+        .trim_end_matches('/');
+
+    // NOTE the port is optional
+    let mut addresses = match host.to_socket_addrs() {
+        Ok(addresses) => addresses,
+        Err(_) => (host, DEFAULT_PORT).to_socket_addrs()?,
+    };
+
+    addresses
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("no address found for {host}"))
+}
+
+/// An mqtt 3.1.1 connect, with a clean session
+fn connect_packet(settings: &MqttSettings) -> Vec<u8> {
+    let mut flags = 0x02;
+    let mut payload = Vec::new();
+    // NOTE brokers disconnect an earlier client with the same id
+    write_string(&mut payload, &format!("clef-{}", std::process::id()));
+    if let Some(username) = &settings.username {
+        flags |= 0x80;
+        write_string(&mut payload, username);
+    }
+    if let Some(password) = &settings.password {
+        flags |= 0x40;
+        write_string(&mut payload, password);
+    }
+
+    let mut body = Vec::new();
+    write_string(&mut body, "MQTT");
+    body.push(4); // protocol level
+    body.push(flags);
+    body.extend((KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    body.extend(payload);
+
+    packet(0x10, body)
+}
+
+/// An at-most-once publish, retained
+fn publish_packet(topic: &str, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    write_string(&mut body, topic);
+    body.extend(payload);
+
+    if body.len() > MAX_REMAINING_LENGTH {
+        anyhow::bail!("the playback event is too big to publish");
+    }
+
+    Ok(packet(0x31, body))
+}
+
+/// Prefixes the body with the packet type and its length
+fn packet(packet_type: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut packet = vec![packet_type];
+
+    // NOTE seven bits at a time, with the high bit marking that more follow
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+
+    packet.extend(body);
+    packet
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    let bytes = &value.as_bytes()[..value.len().min(u16::MAX as usize)];
+    buffer.extend((bytes.len() as u16).to_be_bytes());
+    buffer.extend(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn events_are_published_to_the_broker_and_retained() {
+        let broker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let settings = MqttSettings {
+            broker_url: format!("mqtt://{}", broker.local_addr().unwrap()),
+            topic: default_topic(),
+            username: Some("clef".to_string()),
+            password: Some("hunter2".to_string()),
+        };
+
+        let broker_thread = thread::spawn(move || {
+            let (mut stream, _) = broker.accept().unwrap();
+            let mut header = [0u8; 2];
+            stream.read_exact(&mut header).unwrap();
+            let mut connect = vec![0u8; header[1] as usize];
+            stream.read_exact(&mut connect).unwrap();
+            assert_eq!(header[0], 0x10);
+            assert_eq!(&connect[..7], b"\x00\x04MQTT\x04");
+            assert_eq!(connect[7], 0xC2);
+            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+
+            let mut header = [0u8; 2];
+            stream.read_exact(&mut header).unwrap();
+            let mut publish = vec![0u8; header[1] as usize];
+            stream.read_exact(&mut publish).unwrap();
+            (header[0], publish)
+        });
+
+        let connection = publish(None, &settings, "{\"event\":\"stopped\"}");
+        assert!(connection.is_some());
+
+        let (packet_type, publish) = broker_thread.join().unwrap();
+        assert_eq!(packet_type, 0x31);
+        assert_eq!(&publish[..15], b"\x00\x0Dclef/playback");
+        assert_eq!(&publish[15..], b"{\"event\":\"stopped\"}");
+    }
+
+    #[test]
+    fn an_idle_connection_the_broker_closed_is_reopened_before_publishing() {
+        let broker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let settings = MqttSettings {
+            broker_url: format!("mqtt://{}", broker.local_addr().unwrap()),
+            topic: default_topic(),
+            username: None,
+            password: None,
+        };
+
+        let (publishes_tx, publishes_rx) = flume::unbounded();
+        thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = broker.accept().unwrap();
+                let mut header = [0u8; 2];
+                stream.read_exact(&mut header).unwrap();
+                let mut connect = vec![0u8; header[1] as usize];
+                stream.read_exact(&mut connect).unwrap();
+                assert_eq!(&connect[8..10], &KEEP_ALIVE.as_secs().to_be_bytes()[6..]);
+                stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+
+                let mut header = [0u8; 2];
+                stream.read_exact(&mut header).unwrap();
+                let mut publish = vec![0u8; header[1] as usize];
+                stream.read_exact(&mut publish).unwrap();
+                publishes_tx.send(publish[15..].to_vec()).unwrap();
+                // NOTE dropping the stream closes the connection, like an idle timeout
+            }
+        });
+
+        let mut connection = publish(None, &settings, "{\"event\":\"paused\"}").unwrap();
+        connection.last_sent -= IDLE_BEFORE_PING;
+        let connection = publish(Some(connection), &settings, "{\"event\":\"stopped\"}");
+        assert!(connection.is_some());
+
+        // NOTE a timeout, since a publish lost to the closed connection never arrives
+        let publishes: Vec<_> = (0..2)
+            .map(|_| publishes_rx.recv_timeout(BROKER_TIMEOUT).unwrap())
+            .collect();
+        assert_eq!(
+            publishes,
+            [
+                b"{\"event\":\"paused\"}".to_vec(),
+                b"{\"event\":\"stopped\"}".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn the_publisher_pings_the_broker_between_events() {
+        let broker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let settings = MqttSettings {
+            broker_url: format!("mqtt://{}", broker.local_addr().unwrap()),
+            topic: default_topic(),
+            username: None,
+            password: None,
+        };
+
+        let (packets_tx, packets_rx) = flume::unbounded();
+        thread::spawn(move || {
+            let (mut stream, _) = broker.accept().unwrap();
+            let mut header = [0u8; 2];
+            stream.read_exact(&mut header).unwrap();
+            let mut connect = vec![0u8; header[1] as usize];
+            stream.read_exact(&mut connect).unwrap();
+            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+
+            loop {
+                let mut header = [0u8; 2];
+                if stream.read_exact(&mut header).is_err() {
+                    break;
+                }
+                let mut body = vec![0u8; header[1] as usize];
+                stream.read_exact(&mut body).unwrap();
+                if header[0] == 0xC0 {
+                    stream.write_all(&[0xD0, 0x00]).unwrap();
+                }
+                packets_tx.send(header[0]).unwrap();
+            }
+        });
+
+        let (to_publisher, inbox) = flume::unbounded();
+        let publisher =
+            thread::spawn(move || publish_events(inbox, Duration::from_millis(50)));
+        let request = MqttRequest {
+            settings,
+            event: PlaybackEvent::Stopped,
+            timestamp: 0,
+        };
+        to_publisher.send(request).unwrap();
+
+        // NOTE a timeout, so that a missing ping fails rather than hangs
+        let packets: Vec<u8> = (0..3)
+            .map(|_| packets_rx.recv_timeout(BROKER_TIMEOUT).unwrap())
+            .collect();
+        assert_eq!(packets, [0x31, 0xC0, 0xC0]);
+
+        drop(to_publisher);
+        publisher.join().unwrap();
+        assert_eq!(packets_rx.recv_timeout(BROKER_TIMEOUT), Ok(0xE0));
+    }
+
+    #[test]
+    fn lengths_over_a_byte_continue_into_the_next() {
+        assert_eq!(packet(0x31, vec![0; 127])[..2], [0x31, 127]);
+        assert_eq!(packet(0x31, vec![0; 321])[..3], [0x31, 0xC1, 0x02]);
+    }
+}
@@ -1,20 +1,65 @@
 use std::cmp::Ordering;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::time::Duration;
 
+use camino::Utf8Path;
+use iced::Color;
 use log::error;
 
 use clef_audio::player::QueuedSong;
-use clef_db::queries::{Album, AlbumId, Song, SongId};
+use clef_audio::replay_gain::ReplayGain;
+use clef_audio::shuffle_order::CamelotKey;
+use clef_db::queries::{
+    Album, AlbumId, AlbumRename, SavedQueue, Song, SongId, SortKind, SortName,
+};
 use clef_shared::queue::Queue;
 
-use crate::app::{crawler::CrawledAlbum, rgba::RgbaBytes};
+use crate::app::crawler::{CrawledAlbum, RemovedFromLibrary};
+use crate::app::resizer::{ResizeRequest, ResizedImage};
+use crate::app::rgba::RgbaBytes;
 
 #[derive(Default, Debug)]
 pub struct MusicCache {
     album_display_order: Vec<(AlbumId, AlbumSortKey)>,
     songs_by_id: HashMap<SongId, Song>,
     albums_by_id: HashMap<AlbumId, CachedAlbum>,
+    /// Overrides for album_display_order; (kind, name as tagged) => sort name
+    sort_names: HashMap<(SortKind, String), String>,
+    art: ArtUsage,
+}
+
+/// Keeps decoded album art under a memory budget,
+/// by dropping the least recently shown art that isn't on screen
+#[derive(Debug)]
+struct ArtUsage {
+    /// Albums with art loaded, least recently shown first
+    recency: VecDeque<AlbumId>,
+    bytes: usize,
+    budget: usize,
+    /// Albums near the scroll position, which keep their art
+    shown: HashSet<AlbumId>,
+    /// Albums with art requested from the resizer, that hasn't arrived yet
+    pending: HashSet<AlbumId>,
+}
+
+impl Default for ArtUsage {
+    /// Unlimited until the configured budget is set
+    fn default() -> Self {
+        Self {
+            recency: VecDeque::new(),
+            bytes: 0,
+            budget: usize::MAX,
+            shown: HashSet::new(),
+            pending: HashSet::new(),
+        }
+    }
+}
+
+impl ArtUsage {
+    fn touch(&mut self, album_id: AlbumId) {
+        self.recency.retain(|id| *id != album_id);
+        self.recency.push_back(album_id);
+    }
 }
 
 #[derive(Debug)]
@@ -22,9 +67,35 @@ pub struct CachedAlbum {
     pub album: Album,
     pub songs: Vec<Song>,
     pub art: Option<RgbaBytes>,
+    pub placeholder_color: Option<Color>,
+}
+
+impl CachedAlbum {
+    /// The songs present (0) and expected (1), if the track totals say some are missing.
+    /// NOTE totals are per disc, so a whole missing disc isn't noticed
+    pub fn missing_tracks(&self) -> Option<(usize, usize)> {
+        // disc number => songs present, highest tagged total
+        let mut discs: BTreeMap<Option<i32>, (usize, Option<i32>)> = BTreeMap::new();
+        for song in &self.songs {
+            let (present, total) = discs.entry(song.disc_number).or_default();
+            *present += 1;
+            *total = (*total).max(song.track_total);
+        }
+
+        let present = self.songs.len();
+        let expected: usize = discs
+            .values()
+            .map(|(present, total)| match total {
+                Some(total) => (*present).max(*total as usize),
+                None => *present,
+            })
+            .sum();
+
+        (expected > present).then_some((present, expected))
+    }
 }
 
-/// Artist, Display Title
+/// Artist, Display Title; or their sort names
 type AlbumSortKey = (Option<String>, Option<String>);
 
 impl MusicCache {
@@ -49,10 +120,30 @@ impl MusicCache {
             self.songs_by_id.insert(song.id, song.clone());
         }
 
-        let sort_key = (
-            crawled.album.artist.clone(),
-            crawled.album.display_title().map(str::to_string),
-        );
+        // the other directories of an album split across discs
+        if let Some(existing) = self.albums_by_id.get_mut(&crawled.album.id) {
+            for song in crawled.songs {
+                if !existing.songs.iter().any(|s| s.id == song.id) {
+                    existing.songs.push(song);
+                }
+            }
+            existing
+                .songs
+                .sort_by_key(|s| (s.disc_number, s.track_number));
+            // NOTE the db's total already covers the directories crawled before this one
+            existing.album.total_seconds = crawled.album.total_seconds;
+
+            if existing.placeholder_color.is_none() {
+                existing.placeholder_color = crawled.placeholder_color;
+            }
+            if let (None, Some(art)) = (&existing.art, crawled.cached_art) {
+                self.insert_art(crawled.album.id, art);
+            }
+
+            return;
+        }
+
+        let sort_key = self.album_sort_key(&crawled.album);
         self.album_display_order.push((crawled.album.id, sort_key));
         self.album_display_order
             .sort_by(|a, b| artist_then_title_with_nones_last(&a.1, &b.1));
@@ -61,20 +152,373 @@ impl MusicCache {
         let cached_album = CachedAlbum {
             album: crawled.album,
             songs: crawled.songs,
-            art: crawled.cached_art,
+            art: None,
+            placeholder_color: crawled.placeholder_color,
         };
 
         self.albums_by_id.insert(album_id, cached_album);
+        if let Some(art) = crawled.cached_art {
+            self.insert_art(album_id, art);
+        }
+    }
+
+    /// The sort name overrides, by kind and then name
+    pub fn sort_names(&self) -> Vec<SortName> {
+        let mut sort_names: Vec<SortName> = self
+            .sort_names
+            .iter()
+            .map(|((kind, name), sort_name)| SortName {
+                kind: *kind,
+                name: name.clone(),
+                sort_name: sort_name.clone(),
+            })
+            .collect();
+        sort_names.sort_by(|a, b| {
+            (a.kind == SortKind::Album, &a.name)
+                .cmp(&(b.kind == SortKind::Album, &b.name))
+        });
+
+        sort_names
+    }
+
+    pub fn set_sort_names(&mut self, sort_names: Vec<SortName>) {
+        self.sort_names = sort_names
+            .into_iter()
+            .map(|sort_name| ((sort_name.kind, sort_name.name), sort_name.sort_name))
+            .collect();
+        self.resort_albums();
+    }
+
+    pub fn set_sort_name(&mut self, sort_name: SortName) {
+        self.sort_names
+            .insert((sort_name.kind, sort_name.name), sort_name.sort_name);
+        self.resort_albums();
+    }
+
+    pub fn remove_sort_name(&mut self, kind: SortKind, name: &str) {
+        self.sort_names.remove(&(kind, name.to_string()));
+        self.resort_albums();
+    }
+
+    /// Shows the edited title and artist, resorting the albums to match
+    pub fn rename_album(&mut self, album_id: AlbumId, renamed: &AlbumRename) {
+        let Some(cached) = self.albums_by_id.get_mut(&album_id) else {
+            return;
+        };
+        cached.album.rename(renamed);
+        self.resort_albums();
+    }
+
+    pub fn set_album_notes(&mut self, album_id: AlbumId, notes: Option<String>) {
+        if let Some(cached) = self.albums_by_id.get_mut(&album_id) {
+            cached.album.notes = notes;
+        }
+    }
+
+    /// Replaces an album's details and songs with their filled in tags, keeping its art
+    pub fn fill_album(&mut self, album: Album, songs: Vec<Song>) {
+        let Some(cached) = self.albums_by_id.get_mut(&album.id) else {
+            return;
+        };
+
+        for song in songs {
+            if let Some(cached_song) = cached.songs.iter_mut().find(|s| s.id == song.id) {
+                *cached_song = song.clone();
+            }
+            self.songs_by_id.insert(song.id, song);
+        }
+        cached.album = album;
+        self.resort_albums();
+    }
+
+    fn album_sort_key(&self, album: &Album) -> AlbumSortKey {
+        let sort_name = |kind: SortKind, name: Option<&str>| {
+            let name = name?;
+            let sort_name = self.sort_names.get(&(kind, name.to_string()));
+
+            Some(sort_name.map(String::as_str).unwrap_or(name).to_string())
+        };
+
+        (
+            sort_name(SortKind::Artist, album.artist.as_deref()),
+            sort_name(SortKind::Album, album.display_title()),
+        )
+    }
+
+    fn resort_albums(&mut self) {
+        let sort_keys: Vec<(AlbumId, AlbumSortKey)> = self
+            .album_display_order
+            .iter()
+            .filter_map(|(album_id, _old_key)| {
+                let cached = self.albums_by_id.get(album_id)?;
+                Some((*album_id, self.album_sort_key(&cached.album)))
+            })
+            .collect();
+
+        self.album_display_order = sort_keys;
+        self.album_display_order
+            .sort_by(|a, b| artist_then_title_with_nones_last(&a.1, &b.1));
+    }
+
+    pub fn remove(&mut self, removed: &RemovedFromLibrary) {
+        for song_id in &removed.songs {
+            if let Some(song) = self.songs_by_id.remove(song_id) {
+                if let Some(album) = self.albums_by_id.get_mut(&song.album_id) {
+                    album.songs.retain(|s| s.id != song.id);
+                    album.album.total_seconds -= song.total_seconds;
+                }
+            }
+        }
+
+        for album_id in &removed.albums {
+            let removed_art = self
+                .albums_by_id
+                .remove(album_id)
+                .and_then(|album| album.art);
+            if let Some(art) = removed_art {
+                self.art.bytes -= art.byte_len();
+                self.art.recency.retain(|id| id != album_id);
+            }
+        }
+        self.album_display_order
+            .retain(|(album_id, _sort_key)| !removed.albums.contains(album_id));
     }
 
-    pub fn load_album_art(&mut self, album_id: AlbumId, image_bytes: RgbaBytes) {
-        if let Some(album) = self.albums_by_id.get_mut(&album_id) {
-            album.art = Some(image_bytes);
-        } else {
+    pub fn load_album_art(&mut self, resized: ResizedImage) {
+        let album_id = resized.album_id;
+        self.art.pending.remove(&album_id);
+
+        let Some(album) = self.albums_by_id.get_mut(&album_id) else {
             error!("loaded art for unknown album: {album_id:#?}");
+            return;
+        };
+        // NOTE this lets evicted art be loaded again
+        album.album.resized_art = Some(resized.file);
+
+        self.insert_art(album_id, resized.bytes);
+    }
+
+    /// Lets the art be requested again, the next time the album is shown
+    pub fn album_art_failed(&mut self, album_id: AlbumId) {
+        self.art.pending.remove(&album_id);
+    }
+
+    pub fn set_art_budget(&mut self, bytes: usize) {
+        self.art.budget = bytes;
+        self.evict_art();
+    }
+
+    /// Marks the albums near the scroll position, so their art stays loaded.
+    /// Returns requests for the ones whose art was never loaded or was evicted.
+    pub fn show_albums(&mut self, album_ids: &[AlbumId]) -> Vec<ResizeRequest> {
+        self.art.shown = album_ids.iter().copied().collect();
+
+        let mut requests = Vec::new();
+        for album_id in album_ids {
+            let Some(album) = self.albums_by_id.get(album_id) else {
+                continue;
+            };
+
+            if album.art.is_some() {
+                self.art.touch(*album_id);
+                continue;
+            }
+
+            let Some(resized_art) = &album.album.resized_art else {
+                continue;
+            };
+            if self.art.pending.insert(*album_id) {
+                requests.push(ResizeRequest {
+                    album_id: *album_id,
+                    album_title: album
+                        .album
+                        .display_title()
+                        .unwrap_or_default()
+                        .to_string(),
+                    source_path: resized_art.clone(),
+                    already_resized: true,
+                });
+            }
+        }
+
+        self.evict_art();
+
+        requests
+    }
+
+    fn insert_art(&mut self, album_id: AlbumId, art: RgbaBytes) {
+        let Some(album) = self.albums_by_id.get_mut(&album_id) else {
+            return;
+        };
+
+        self.art.bytes += art.byte_len();
+        if let Some(replaced) = album.art.replace(art) {
+            self.art.bytes -= replaced.byte_len();
+        }
+        self.art.touch(album_id);
+
+        self.evict_art();
+    }
+
+    fn evict_art(&mut self) {
+        while self.art.bytes > self.art.budget {
+            let Some(index) = self
+                .art
+                .recency
+                .iter()
+                .position(|id| !self.art.shown.contains(id))
+            else {
+                // everything loaded is on screen
+                return;
+            };
+
+            let album_id = self.art.recency.remove(index).unwrap();
+            let evicted = self
+                .albums_by_id
+                .get_mut(&album_id)
+                .and_then(|album| album.art.take());
+            if let Some(evicted) = evicted {
+                self.art.bytes -= evicted.byte_len();
+            }
+        }
+    }
+
+    pub fn set_favorite(&mut self, song_id: SongId, favorite: bool) {
+        let Some(song) = self.songs_by_id.get_mut(&song_id) else {
+            error!("favorited unknown song: {song_id:?}");
+            return;
+        };
+        song.favorite = favorite;
+
+        if let Some(album) = self.albums_by_id.get_mut(&song.album_id) {
+            for album_song in album.songs.iter_mut().filter(|s| s.id == song_id) {
+                album_song.favorite = favorite;
+            }
+        }
+    }
+
+    /// NOTE songs from outside the library aren't cached, so there's nothing to mark
+    pub fn set_unplayable(&mut self, song_id: SongId) {
+        let Some(song) = self.songs_by_id.get_mut(&song_id) else {
+            return;
+        };
+        song.unplayable = true;
+
+        if let Some(album) = self.albums_by_id.get_mut(&song.album_id) {
+            for album_song in album.songs.iter_mut().filter(|s| s.id == song_id) {
+                album_song.unplayable = true;
+            }
         }
     }
 
+    /// Albums with at least one song in the genre, in display order
+    pub fn albums_in_genre(&self, genre: &str) -> Vec<&CachedAlbum> {
+        self.albums()
+            .into_iter()
+            .filter(|album| {
+                album
+                    .songs
+                    .iter()
+                    .any(|song| song.genre.as_deref() == Some(genre))
+            })
+            .collect()
+    }
+
+    /// Every genre tagged in the library, sorted and without duplicates
+    pub fn genres(&self) -> Vec<&str> {
+        let mut genres: Vec<&str> = self
+            .songs_by_id
+            .values()
+            .filter_map(|song| song.genre.as_deref())
+            .collect();
+        genres.sort_unstable();
+        genres.dedup();
+
+        genres
+    }
+
+    /// Favorited songs, in album display order
+    pub fn favorites(&self) -> Vec<&Song> {
+        self.albums()
+            .into_iter()
+            .flat_map(|album| album.songs.iter())
+            .filter(|song| song.favorite)
+            .collect()
+    }
+
+    /// All the favorites as a queue, starting from the first
+    pub fn get_favorites_queue(&self) -> Option<Queue<QueuedSong>> {
+        self.queue_in_album_order(|song| song.favorite)
+    }
+
+    /// The songs as a queue in album display order, skipping unknown songs
+    pub fn get_songs_queue(&self, song_ids: &[SongId]) -> Option<Queue<QueuedSong>> {
+        let song_ids: HashSet<&SongId> = song_ids.iter().collect();
+        self.queue_in_album_order(|song| song_ids.contains(&song.id))
+    }
+
+    /// The songs as a queue in the given order, ie a playlist file's, skipping unknown songs
+    pub fn get_listed_queue(&self, song_ids: &[SongId]) -> Option<Queue<QueuedSong>> {
+        let mut songs = song_ids.iter().filter_map(|song_id| {
+            let song = self.songs_by_id.get(song_id)?;
+            let album = self.albums_by_id.get(&song.album_id)?;
+            Some(queued_song(&album.album, song))
+        });
+
+        let current = songs.next()?;
+        Some(Queue::new(Vec::new(), current, songs.collect()))
+    }
+
+    /// The songs in a file or under a folder in path order, for folder browsing
+    pub fn get_folder_songs(&self, path: &Utf8Path) -> Vec<QueuedSong> {
+        let mut songs: Vec<&Song> = self
+            .songs_by_id
+            .values()
+            .filter(|song| song.file.starts_with(path))
+            .collect();
+        songs.sort_by(|a, b| a.file.cmp(&b.file));
+
+        songs
+            .into_iter()
+            .filter_map(|song| {
+                let album = self.albums_by_id.get(&song.album_id)?;
+                Some(queued_song(&album.album, song))
+            })
+            .collect()
+    }
+
+    /// The songs in a file or under a directory as a queue, in album display order
+    pub fn get_path_queue(&self, path: &Utf8Path) -> Option<Queue<QueuedSong>> {
+        self.queue_in_album_order(|song| song.file.starts_with(path))
+    }
+
+    fn queue_in_album_order(
+        &self,
+        include: impl Fn(&Song) -> bool,
+    ) -> Option<Queue<QueuedSong>> {
+        let mut songs = self.albums().into_iter().flat_map(|album| {
+            album
+                .songs
+                .iter()
+                .filter(|song| include(song))
+                .map(|song| queued_song(&album.album, song))
+        });
+
+        let current = songs.next()?;
+        Some(Queue::new(Vec::new(), current, songs.collect()))
+    }
+
+    pub fn get_song_by_file(&self, file: &Utf8Path) -> Option<&Song> {
+        self.songs_by_id.values().find(|song| song.file == file)
+    }
+
+    /// For ids from outside the app, ie song links; NOTE this checks every song
+    pub fn get_song_by_unpacked_id(&self, id: i32) -> Option<&Song> {
+        self.songs_by_id
+            .values()
+            .find(|song| song.id.unpack() == id)
+    }
+
     pub fn get_song(&self, song_id: &SongId) -> Option<&Song> {
         self.songs_by_id.get(song_id)
     }
@@ -83,6 +527,10 @@ impl MusicCache {
         self.albums_by_id.get(album_id).map(|ca| &ca.album)
     }
 
+    pub fn get_cached_album(&self, album_id: &AlbumId) -> Option<&CachedAlbum> {
+        self.albums_by_id.get(album_id)
+    }
+
     pub fn get_album_queue(
         &self,
         clicked_song_id: SongId,
@@ -95,17 +543,7 @@ impl MusicCache {
         let mut current = None;
 
         for album_song in &cached_album.songs {
-            let total_seconds: Option<u64> = album_song.total_seconds.try_into().ok();
-
-            let queued_song = QueuedSong {
-                id: album_song.id,
-                path: album_song.file.clone(),
-                title: album_song.title.clone(),
-                artist: album_song.artist.clone(),
-                album_title: cached_album.album.title.clone(),
-                resized_art: cached_album.album.resized_art.clone(),
-                duration: total_seconds.map(Duration::from_secs),
-            };
+            let queued_song = queued_song(&cached_album.album, album_song);
 
             if current.is_none() {
                 if album_song.id == clicked_song_id {
@@ -118,7 +556,66 @@ impl MusicCache {
             }
         }
 
-        current.map(|current| Queue { previous, current, next })
+        current.map(|current| Queue::new(previous, current, next))
+    }
+
+    /// Rebuilds a saved queue, skipping songs that are no longer in the library
+    pub fn get_saved_queue(&self, saved: &SavedQueue) -> Option<Queue<QueuedSong>> {
+        let mut previous = Vec::new();
+        let mut next = VecDeque::new();
+        let mut current = None;
+
+        for (index, song_id) in saved.song_ids.iter().enumerate() {
+            let Some(queued_song) = self.get_queued_song(*song_id) else {
+                continue;
+            };
+
+            match index.cmp(&saved.current_index) {
+                Ordering::Less => previous.push(queued_song),
+                Ordering::Equal => current = Some(queued_song),
+                Ordering::Greater => next.push_back(queued_song),
+            }
+        }
+
+        let mut queue = Queue::new(previous, current?, next);
+        // NOTE missing songs are left out of both orders
+        queue.unshuffled = saved.unshuffled_song_ids.as_ref().map(|unshuffled| {
+            unshuffled
+                .iter()
+                .filter_map(|song_id| self.get_queued_song(*song_id))
+                .collect()
+        });
+
+        Some(queue)
+    }
+
+    pub fn get_queued_song(&self, song_id: SongId) -> Option<QueuedSong> {
+        let song = self.songs_by_id.get(&song_id)?;
+        let cached_album = self.albums_by_id.get(&song.album_id)?;
+
+        Some(queued_song(&cached_album.album, song))
+    }
+}
+
+fn queued_song(album: &Album, song: &Song) -> QueuedSong {
+    let total_seconds: Option<u64> = song.total_seconds.try_into().ok();
+
+    QueuedSong {
+        id: song.id,
+        path: song.file.clone(),
+        title: song.title.clone(),
+        artist: song.artist.clone(),
+        album_title: album.title.clone(),
+        resized_art: album.resized_art.clone(),
+        duration: total_seconds.map(Duration::from_secs),
+        replay_gain: ReplayGain {
+            track_gain: song.track_gain.map(|gain| gain as f32),
+            track_peak: song.track_peak.map(|peak| peak as f32),
+            album_gain: album.album_gain.map(|gain| gain as f32),
+            album_peak: album.album_peak.map(|peak| peak as f32),
+        },
+        bpm: song.bpm,
+        key: song.initial_key.as_deref().and_then(CamelotKey::parse),
     }
 }
 
@@ -168,4 +665,289 @@ mod tests {
             queue.next.into_iter().map(|queued| queued.id).collect();
         assert_eq!(next_ids, vec![SongId::new(4), SongId::new(5)]);
     }
+
+    #[test]
+    fn get_saved_queue_skips_missing_songs() {
+        let mut music_cache = MusicCache::default();
+        music_cache.add_crawled_album(fake_album());
+
+        let saved = SavedQueue {
+            song_ids: vec![
+                SongId::new(1),
+                SongId::new(99),
+                SongId::new(3),
+                SongId::new(4),
+            ],
+            current_index: 2,
+            elapsed_seconds: 12.5,
+            from_crash: true,
+            playing: false,
+            unshuffled_song_ids: None,
+        };
+        let queue = music_cache.get_saved_queue(&saved).unwrap();
+
+        assert_eq!(queue.previous.len(), 1);
+        assert_eq!(queue.current.id, SongId::new(3));
+        assert_eq!(queue.next.len(), 1);
+    }
+
+    #[test]
+    fn get_saved_queue_restores_unshuffled_order() {
+        let mut music_cache = MusicCache::default();
+        music_cache.add_crawled_album(fake_album());
+
+        let saved = SavedQueue {
+            song_ids: vec![SongId::new(3), SongId::new(1), SongId::new(2)],
+            current_index: 0,
+            elapsed_seconds: 0.0,
+            from_crash: false,
+            playing: false,
+            unshuffled_song_ids: Some(vec![
+                SongId::new(1),
+                SongId::new(99),
+                SongId::new(2),
+                SongId::new(3),
+            ]),
+        };
+        let queue = music_cache.get_saved_queue(&saved).unwrap();
+
+        assert!(queue.is_shuffled());
+        let unshuffled_ids: Vec<SongId> = queue
+            .unshuffled
+            .unwrap()
+            .into_iter()
+            .map(|queued| queued.id)
+            .collect();
+        assert_eq!(
+            unshuffled_ids,
+            vec![SongId::new(1), SongId::new(2), SongId::new(3)]
+        );
+    }
+
+    #[test]
+    fn remove_drops_songs_and_empty_albums() {
+        let mut music_cache = MusicCache::default();
+        let album = fake_album();
+        let album_id = album.album.id;
+        music_cache.add_crawled_album(album);
+
+        music_cache.remove(&RemovedFromLibrary {
+            albums: Vec::new(),
+            songs: vec![SongId::new(2)],
+        });
+        assert!(music_cache.get_song(&SongId::new(2)).is_none());
+        assert_eq!(music_cache.albums()[0].songs.len(), 4);
+        assert_eq!(music_cache.albums()[0].album.total_seconds, 400);
+
+        music_cache.remove(&RemovedFromLibrary {
+            albums: vec![album_id],
+            songs: Vec::new(),
+        });
+        assert!(music_cache.albums().is_empty());
+        assert!(music_cache.get_album(&album_id).is_none());
+    }
+
+    #[test]
+    fn add_crawled_album_merges_split_discs() {
+        let mut music_cache = MusicCache::default();
+
+        let mut disc_one = fake_album();
+        disc_one.songs.truncate(2);
+        for song in &mut disc_one.songs {
+            song.disc_number = Some(1);
+        }
+
+        let mut disc_two = fake_album();
+        disc_two.songs = vec![fake_song(6, "Sixth", disc_two.album.id)];
+        disc_two.songs[0].track_number = Some(1);
+        disc_two.songs[0].disc_number = Some(2);
+
+        music_cache.add_crawled_album(disc_two);
+        music_cache.add_crawled_album(disc_one);
+
+        let albums = music_cache.albums();
+        assert_eq!(albums.len(), 1);
+
+        let song_ids: Vec<SongId> = albums[0].songs.iter().map(|s| s.id).collect();
+        assert_eq!(
+            song_ids,
+            vec![SongId::new(1), SongId::new(2), SongId::new(6)]
+        );
+    }
+
+    #[test]
+    fn get_favorites_queue_keeps_album_order() {
+        let mut music_cache = MusicCache::default();
+        music_cache.add_crawled_album(fake_album());
+        assert!(music_cache.get_favorites_queue().is_none());
+
+        music_cache.set_favorite(SongId::new(4), true);
+        music_cache.set_favorite(SongId::new(2), true);
+        music_cache.set_favorite(SongId::new(5), true);
+        music_cache.set_favorite(SongId::new(5), false);
+
+        let queue = music_cache.get_favorites_queue().unwrap();
+        assert_eq!(queue.current.id, SongId::new(2));
+        let next_ids: Vec<SongId> =
+            queue.next.into_iter().map(|queued| queued.id).collect();
+        assert_eq!(next_ids, vec![SongId::new(4)]);
+
+        assert!(music_cache.get_song(&SongId::new(4)).unwrap().favorite);
+    }
+
+    #[test]
+    fn albums_in_genre_match_any_song() {
+        let mut music_cache = MusicCache::default();
+
+        let mut jazz_album = fake_album();
+        jazz_album.songs[3].genre = Some("Jazz".to_string());
+        music_cache.add_crawled_album(jazz_album);
+
+        let mut rock_album = fake_album();
+        let rock_album_id = AlbumId::new(2);
+        rock_album.album.id = rock_album_id;
+        rock_album.album.directory = "Rock Album Dir".into();
+        rock_album.songs = vec![
+            fake_song(6, "Sixth", rock_album_id),
+            fake_song(7, "Seventh", rock_album_id),
+        ];
+        for song in &mut rock_album.songs {
+            song.genre = Some("Rock".to_string());
+        }
+        music_cache.add_crawled_album(rock_album);
+
+        assert_eq!(music_cache.genres(), vec!["Jazz", "Rock"]);
+
+        let jazz_ids: Vec<AlbumId> = music_cache
+            .albums_in_genre("Jazz")
+            .iter()
+            .map(|cached| cached.album.id)
+            .collect();
+        assert_eq!(jazz_ids, vec![AlbumId::new(1)]);
+        assert!(music_cache.albums_in_genre("Polka").is_empty());
+    }
+
+    #[test]
+    fn evicts_the_least_recently_shown_art_offscreen() {
+        let mut music_cache = MusicCache::default();
+        for number in 1..=3 {
+            let mut album = fake_album();
+            album.album.id = AlbumId::new(number);
+            album.album.directory = format!("Album Dir {number}").into();
+            album.songs.clear();
+            music_cache.add_crawled_album(album);
+        }
+
+        let art_bytes = RgbaBytes::blank(4).byte_len();
+        music_cache.set_art_budget(2 * art_bytes);
+        music_cache.show_albums(&[AlbumId::new(1)]);
+
+        for number in 1..=3 {
+            music_cache.load_album_art(ResizedImage {
+                album_id: AlbumId::new(number),
+                file: format!("resized_{number}.bmp").into(),
+                bytes: RgbaBytes::blank(4),
+            });
+        }
+
+        let has_art = |music_cache: &MusicCache, number| {
+            let album = music_cache.get_cached_album(&AlbumId::new(number)).unwrap();
+            album.art.is_some()
+        };
+        assert!(has_art(&music_cache, 1));
+        assert!(!has_art(&music_cache, 2));
+        assert!(has_art(&music_cache, 3));
+
+        let requests = music_cache.show_albums(&[AlbumId::new(2)]);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].source_path.as_str(), "resized_2.bmp");
+        assert!(requests[0].already_resized);
+
+        // already requested
+        assert!(music_cache.show_albums(&[AlbumId::new(2)]).is_empty());
+    }
+
+    #[test]
+    fn failed_art_is_requested_again() {
+        let mut album = fake_album();
+        album.album.resized_art = Some("resized.bmp".into());
+        let album_id = album.album.id;
+        let mut music_cache = MusicCache::default();
+        music_cache.add_crawled_album(album);
+
+        assert_eq!(music_cache.show_albums(&[album_id]).len(), 1);
+        assert!(music_cache.show_albums(&[album_id]).is_empty());
+
+        music_cache.album_art_failed(album_id);
+        assert_eq!(music_cache.show_albums(&[album_id]).len(), 1);
+    }
+
+    #[test]
+    fn missing_tracks_uses_totals_per_disc() {
+        let mut album = fake_album();
+        let cached = |album: &CrawledAlbum| CachedAlbum {
+            album: album.album.clone(),
+            songs: album.songs.clone(),
+            art: None,
+            placeholder_color: None,
+        };
+        assert_eq!(cached(&album).missing_tracks(), None);
+
+        for song in &mut album.songs {
+            song.track_total = Some(6);
+        }
+        assert_eq!(cached(&album).missing_tracks(), Some((5, 6)));
+
+        // two complete discs
+        album.songs[0].track_total = Some(1);
+        album.songs[0].disc_number = Some(1);
+        for song in &mut album.songs[1..] {
+            song.track_total = Some(4);
+            song.disc_number = Some(2);
+        }
+        assert_eq!(cached(&album).missing_tracks(), None);
+    }
+
+    #[test]
+    fn sort_names_override_the_display_order() {
+        let mut music_cache = MusicCache::default();
+        let album = |id: i32, artist: &str| {
+            let mut crawled = fake_album();
+            crawled.album.id = AlbumId::new(id);
+            crawled.album.artist = Some(artist.to_string());
+            crawled.songs.clear();
+            crawled
+        };
+        music_cache.add_crawled_album(album(1, "Cocteau Twins"));
+        music_cache.add_crawled_album(album(2, "The Beatles"));
+
+        let artists = |music_cache: &MusicCache| -> Vec<String> {
+            let albums = music_cache.albums();
+            albums
+                .iter()
+                .filter_map(|a| a.album.artist.clone())
+                .collect()
+        };
+        assert_eq!(artists(&music_cache), ["Cocteau Twins", "The Beatles"]);
+
+        music_cache.set_sort_name(SortName {
+            kind: SortKind::Artist,
+            name: "The Beatles".to_string(),
+            sort_name: "Beatles".to_string(),
+        });
+        assert_eq!(artists(&music_cache), ["The Beatles", "Cocteau Twins"]);
+
+        // new albums are sorted with the override too
+        music_cache.add_crawled_album(album(3, "Can"));
+        assert_eq!(
+            artists(&music_cache),
+            ["The Beatles", "Can", "Cocteau Twins"]
+        );
+
+        music_cache.remove_sort_name(SortKind::Artist, "The Beatles");
+        assert_eq!(
+            artists(&music_cache),
+            ["Can", "Cocteau Twins", "The Beatles"]
+        );
+    }
 }
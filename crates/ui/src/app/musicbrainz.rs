@@ -0,0 +1,260 @@
+//! Searches MusicBrainz by an album's tags, for its art (see art_fetcher)
+//! and to fill in the tags it's missing
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use clef_db::queries::{
+    fill_from_musicbrainz, Album, MusicBrainzRelease, MusicBrainzTrack, Song,
+};
+use clef_db::SqlitePool;
+
+const RELEASE_URL: &str = "https://musicbrainz.org/ws/2/release";
+/// MusicBrainz asks for a descriptive user agent, and blocks requests without one
+pub const USER_AGENT: &str = concat!("clef/", env!("CARGO_PKG_VERSION"));
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+/// Search results scoring lower are too loose a match to trust, out of 100
+const MIN_SCORE: u32 = 90;
+/// Close matches worth checking, ie other pressings of the same album
+const MAX_CANDIDATES: usize = 3;
+
+/// Release ids that closely match the album's tags, closest first
+pub fn search_releases(
+    album_title: &str,
+    artist: Option<&str>,
+) -> anyhow::Result<Vec<String>> {
+    let search = ureq::get(&format!("{RELEASE_URL}/"))
+        .timeout(REQUEST_TIMEOUT)
+        .set("User-Agent", USER_AGENT)
+        .query("query", &release_query(album_title, artist))
+        .query("fmt", "json")
+        .call()?
+        .into_string()?;
+    let search: ReleaseSearch = serde_json::from_str(&search)?;
+
+    Ok(search.candidates().map(str::to_string).collect())
+}
+
+/// Looks up the closest release and saves what the album's tags are missing;
+/// the error is for display
+pub async fn fill_album(
+    db: SqlitePool,
+    album: Album,
+) -> Result<(Album, Vec<Song>), String> {
+    let title = album.display_title().unwrap_or_default();
+    let candidates = search_releases(title, album.artist.as_deref())
+        .map_err(|e| format!("Couldn't search MusicBrainz: {e}"))?;
+    let Some(release_id) = candidates.first() else {
+        return Err(format!("No close match for {title} on MusicBrainz."));
+    };
+
+    let release = lookup_release(release_id)
+        .map_err(|e| format!("Couldn't look up the release on MusicBrainz: {e}"))?;
+
+    let mut conn = db.get().map_err(|e| e.to_string())?;
+    conn.immediate_transaction(|tx| fill_from_musicbrainz(tx, album.id, &release))
+        .map_err(|e| e.to_string())
+}
+
+fn lookup_release(release_id: &str) -> anyhow::Result<MusicBrainzRelease> {
+    let release = ureq::get(&format!("{RELEASE_URL}/{release_id}"))
+        .timeout(REQUEST_TIMEOUT)
+        .set("User-Agent", USER_AGENT)
+        .query("inc", "recordings artist-credits")
+        .query("fmt", "json")
+        .call()?
+        .into_string()?;
+    let release: Release = serde_json::from_str(&release)?;
+
+    Ok(release.into())
+}
+
+/// A lucene query for the release, with the names quoted as phrases
+fn release_query(album_title: &str, artist: Option<&str>) -> String {
+    let phrase = |field: &str, value: &str| {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("{field}:\"{escaped}\"")
+    };
+
+    match artist {
+        Some(artist) => format!(
+            "{} AND {}",
+            phrase("release", album_title),
+            phrase("artist", artist)
+        ),
+        None => phrase("release", album_title),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseSearch {
+    releases: Vec<SearchedRelease>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchedRelease {
+    id: String,
+    score: u32,
+}
+
+impl ReleaseSearch {
+    fn candidates(&self) -> impl Iterator<Item = &str> {
+        let mut releases: Vec<_> = self
+            .releases
+            .iter()
+            .filter(|release| release.score >= MIN_SCORE)
+            .collect();
+        releases.sort_by_key(|release| std::cmp::Reverse(release.score));
+
+        releases
+            .into_iter()
+            .take(MAX_CANDIDATES)
+            .map(|release| release.id.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    id: String,
+    date: Option<String>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(default)]
+    media: Vec<Medium>,
+}
+
+/// One artist of a credit, ie 'Massive Attack' and then ' feat. Tracey Thorn'
+#[derive(Debug, Deserialize)]
+struct ArtistCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Medium {
+    position: i32,
+    #[serde(default)]
+    tracks: Vec<Track>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Track {
+    position: i32,
+    title: String,
+    recording: Recording,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    id: String,
+}
+
+impl From<Release> for MusicBrainzRelease {
+    fn from(release: Release) -> Self {
+        let artist: String = release
+            .artist_credit
+            .iter()
+            .map(|credit| format!("{}{}", credit.name, credit.joinphrase))
+            .collect();
+
+        let tracks = release
+            .media
+            .into_iter()
+            .flat_map(|medium| {
+                medium
+                    .tracks
+                    .into_iter()
+                    .map(move |track| MusicBrainzTrack {
+                        recording_id: track.recording.id,
+                        title: track.title,
+                        disc_number: medium.position,
+                        track_number: track.position,
+                    })
+            })
+            .collect();
+
+        Self {
+            release_id: release.id,
+            artist: (!artist.is_empty()).then_some(artist),
+            release_date: release.date.filter(|date| !date.is_empty()),
+            tracks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_quote_names_and_only_close_matches_are_checked() {
+        assert_eq!(
+            release_query("Dummy", Some("Portishead")),
+            "release:\"Dummy\" AND artist:\"Portishead\""
+        );
+        assert_eq!(
+            release_query("\"Heroes\"", None),
+            "release:\"\\\"Heroes\\\"\""
+        );
+
+        let search: ReleaseSearch = serde_json::from_str(
+            r#"{
+                "count": 5,
+                "releases": [
+                    { "id": "reissue", "score": 95, "title": "Dummy" },
+                    { "id": "loose", "score": 60, "title": "Dummy Run" },
+                    { "id": "original", "score": 100, "title": "Dummy" },
+                    { "id": "deluxe", "score": 91, "title": "Dummy" },
+                    { "id": "promo", "score": 90, "title": "Dummy" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let candidates: Vec<_> = search.candidates().collect();
+        assert_eq!(candidates, ["original", "reissue", "deluxe"]);
+    }
+
+    #[test]
+    fn releases_credit_every_artist_and_number_tracks_by_disc() {
+        let release: Release = serde_json::from_str(
+            r#"{
+                "id": "release",
+                "title": "Protection",
+                "date": "1994-09-26",
+                "artist-credit": [
+                    { "name": "Massive Attack", "joinphrase": " feat. " },
+                    { "name": "Tracey Thorn", "joinphrase": "" }
+                ],
+                "media": [
+                    { "position": 1, "tracks": [
+                        { "position": 1, "number": "A1", "title": "Protection",
+                          "recording": { "id": "protection" } }
+                    ] },
+                    { "position": 2, "tracks": [
+                        { "position": 1, "number": "B1", "title": "Karmacoma",
+                          "recording": { "id": "karmacoma" } }
+                    ] }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let release = MusicBrainzRelease::from(release);
+        assert_eq!(
+            release.artist.as_deref(),
+            Some("Massive Attack feat. Tracey Thorn")
+        );
+        assert_eq!(release.release_date.as_deref(), Some("1994-09-26"));
+        assert_eq!(
+            release.tracks[1],
+            MusicBrainzTrack {
+                recording_id: "karmacoma".to_string(),
+                title: "Karmacoma".to_string(),
+                disc_number: 2,
+                track_number: 1,
+            }
+        );
+    }
+}
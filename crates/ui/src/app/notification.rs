@@ -0,0 +1,131 @@
+//! Errors and warnings from the crawler, resizer, and player, which otherwise
+//! only reach the log; unlike the toast, they're shown until dismissed
+
+/// The most notifications shown at once; older ones are dropped first
+const MAX_SHOWN: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Something was skipped, but everything else still works
+    Warning,
+    /// Something the user asked for didn't happen
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Self::Warning => "Warning",
+            Self::Error => "Error",
+        };
+
+        write!(f, "{label}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Notification {
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+}
+
+/// A shown notification, with how many times it was repeated
+#[derive(Debug, PartialEq, Eq)]
+pub struct Shown {
+    pub notification: Notification,
+    pub count: usize,
+}
+
+/// The shown notifications, oldest first
+#[derive(Debug, Default)]
+pub struct Notifications {
+    shown: Vec<Shown>,
+}
+
+impl Notifications {
+    /// NOTE a repeat, ie the same db error for every album in a scan,
+    /// is counted on the shown notification instead of added again
+    pub fn push(&mut self, notification: Notification) {
+        if let Some(shown) = self
+            .shown
+            .iter_mut()
+            .find(|shown| shown.notification == notification)
+        {
+            shown.count += 1;
+            return;
+        }
+
+        if self.shown.len() == MAX_SHOWN {
+            self.shown.remove(0);
+        }
+        self.shown.push(Shown { notification, count: 1 });
+    }
+
+    pub fn dismiss(&mut self, index: usize) {
+        if index < self.shown.len() {
+            self.shown.remove(index);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Shown> {
+        self.shown.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shown.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_are_counted_and_the_oldest_are_dropped() {
+        let mut notifications = Notifications::default();
+        notifications.push(Notification::error("database is locked"));
+        notifications.push(Notification::error("database is locked"));
+        notifications.push(Notification::warning("database is locked"));
+
+        let counts: Vec<_> = notifications.iter().map(|shown| shown.count).collect();
+        assert_eq!(counts, [2, 1]);
+
+        for n in 0..MAX_SHOWN {
+            notifications.push(Notification::warning(format!("unreadable {n}")));
+        }
+        let messages: Vec<_> = notifications
+            .iter()
+            .map(|shown| shown.notification.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "unreadable 0",
+                "unreadable 1",
+                "unreadable 2",
+                "unreadable 3",
+                "unreadable 4"
+            ]
+        );
+
+        notifications.dismiss(0);
+        notifications.dismiss(MAX_SHOWN);
+        assert_eq!(notifications.iter().count(), MAX_SHOWN - 1);
+    }
+}
@@ -0,0 +1,360 @@
+//! Reading and writing playlist files, ie from other players;
+//! .m3u and .m3u8 are read and written, and .pls is only read
+
+use std::collections::HashMap;
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+
+use clef_db::queries::{SmartRule, Song, SongId};
+
+const PLAYLIST_EXTENSIONS: [&str; 3] = ["m3u", "m3u8", "pls"];
+
+/// A playlist to write as an .m3u8 file in a directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistExport {
+    pub name: String,
+    pub songs: PlaylistSongs,
+    pub destination: Utf8PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaylistSongs {
+    /// A playlist file's songs, in order
+    Listed(Vec<SongId>),
+    /// A smart playlist's songs, as of the export
+    Matching(Vec<SmartRule>),
+}
+
+impl PlaylistExport {
+    pub fn file(&self) -> Utf8PathBuf {
+        m3u8_file(&self.destination, &self.name)
+    }
+
+    /// Writes the songs that are still in the library, given the smart playlist's
+    /// matching songs if it is one, and returns the written file
+    pub fn write(
+        &self,
+        matching: &[SongId],
+        library: &HashMap<SongId, Song>,
+    ) -> std::io::Result<Utf8PathBuf> {
+        let song_ids = match &self.songs {
+            PlaylistSongs::Listed(song_ids) => song_ids.as_slice(),
+            PlaylistSongs::Matching(_) => matching,
+        };
+        let mut listed: Vec<&Song> =
+            song_ids.iter().filter_map(|id| library.get(id)).collect();
+        // NOTE smart playlists match in no particular order
+        if let PlaylistSongs::Matching(_) = self.songs {
+            listed.sort_by(|a, b| a.file.cmp(&b.file));
+        }
+
+        let file = self.file();
+        std::fs::write(&file, to_m3u(listed))?;
+
+        Ok(file)
+    }
+}
+
+/// Where a playlist with the name is written in a directory.
+/// NOTE slashes in the name would write somewhere else
+pub fn m3u8_file(directory: &Utf8Path, name: &str) -> Utf8PathBuf {
+    let name = name.replace(['/', '\\'], "-");
+    directory.join(format!("{name}.m3u8"))
+}
+
+/// One song in a playlist file, with the name shown for it, if any
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub path: Utf8PathBuf,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+pub fn is_playlist_file(path: &Utf8Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        PLAYLIST_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+    })
+}
+
+pub fn is_m3u(path: &Utf8Path) -> bool {
+    is_playlist_file(path) && !is_pls(path)
+}
+
+fn is_pls(path: &Utf8Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pls"))
+}
+
+/// The playlist's songs, in order; relative paths are from the playlist's directory
+pub fn read_playlist(path: &Utf8Path) -> std::io::Result<Vec<PlaylistEntry>> {
+    let contents = decode_playlist(std::fs::read(path)?);
+    let directory = path.parent().unwrap_or(Utf8Path::new(""));
+
+    if is_pls(path) {
+        Ok(parse_pls(&contents, directory))
+    } else {
+        Ok(parse_m3u(&contents, directory))
+    }
+}
+
+/// NOTE .m3u8 files are utf8, but older .m3u and .pls files are usually Latin-1
+fn decode_playlist(bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(contents) => contents,
+        Err(e) => e.into_bytes().into_iter().map(char::from).collect(),
+    }
+}
+
+/// The playlist's name, from its file name
+pub fn playlist_name(path: &Utf8Path) -> String {
+    path.file_stem().unwrap_or(path.as_str()).to_string()
+}
+
+/// Each path line, with the name from the #EXTINF line before it, if any
+pub fn parse_m3u(contents: &str, directory: &Utf8Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut name = None;
+
+    for line in contents
+        .lines()
+        .map(|line| line.trim_matches(['\u{feff}', ' ', '\t']))
+    {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            name = extinf.split_once(',').map(|(_seconds, name)| name.trim());
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let name = name.take();
+        if let Some(path) = resolve(line, directory) {
+            entries.push(entry(path, name));
+        }
+    }
+
+    entries
+}
+
+/// The FileN entries, in order of N, with their TitleN names
+pub fn parse_pls(contents: &str, directory: &Utf8Path) -> Vec<PlaylistEntry> {
+    let mut files = Vec::new();
+    let mut titles = Vec::new();
+
+    for line in contents.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        if let Some(Ok(number)) = key.strip_prefix("file").map(str::parse::<u32>) {
+            files.push((number, value));
+        } else if let Some(Ok(number)) = key.strip_prefix("title").map(str::parse::<u32>)
+        {
+            titles.push((number, value));
+        }
+    }
+    files.sort_by_key(|(number, _file)| *number);
+
+    files
+        .into_iter()
+        .filter_map(|(number, file)| {
+            let path = resolve(file, directory)?;
+            let name = titles
+                .iter()
+                .find(|(title_number, _title)| *title_number == number)
+                .map(|(_number, title)| *title);
+
+            Some(entry(path, name))
+        })
+        .collect()
+}
+
+/// An extended m3u playlist, with absolute paths and each song's length and name
+pub fn to_m3u<'a>(songs: impl IntoIterator<Item = &'a Song>) -> String {
+    let mut m3u = String::from("#EXTM3U\n");
+    for song in songs {
+        let title = song.display_title().unwrap_or_default();
+        let name = match &song.artist {
+            Some(artist) => format!("{artist} - {title}"),
+            None => title.to_string(),
+        };
+        m3u.push_str(&format!(
+            "#EXTINF:{},{name}\n{}\n",
+            song.total_seconds, song.file
+        ));
+    }
+
+    m3u
+}
+
+/// None for streams and other urls, which aren't in the library
+fn resolve(location: &str, directory: &Utf8Path) -> Option<Utf8PathBuf> {
+    let decoded;
+    let location = match location.strip_prefix("file://") {
+        Some(uri_path) => {
+            decoded =
+                percent_decode(uri_path.strip_prefix("localhost").unwrap_or(uri_path))?;
+            // NOTE windows drives are written as 'file:///C:/Music'
+            match decoded.as_bytes() {
+                [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &decoded[1..],
+                _ => &decoded,
+            }
+        }
+        None if location.contains("://") => return None,
+        None => location,
+    };
+
+    // NOTE playlists written on windows separate directories with backslashes
+    let path = Utf8PathBuf::from(location.replace('\\', "/"));
+    let path = if path.is_absolute() {
+        path
+    } else {
+        directory.join(path)
+    };
+
+    Some(normalize(&path))
+}
+
+/// A file uri's path, where ie a space is written as '%20'; None if it isn't utf8
+fn percent_decode(uri_path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(uri_path.len());
+    let mut rest = uri_path.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let escaped = after
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &after[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Removes '.' and '..', without touching the filesystem,
+/// so that paths compare equal to the ones the crawler saved
+fn normalize(path: &Utf8Path) -> Utf8PathBuf {
+    let mut normalized = Utf8PathBuf::new();
+    for component in path.components() {
+        match component {
+            Utf8Component::CurDir => {}
+            Utf8Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
+/// Names are usually 'Artist - Title'
+fn entry(path: Utf8PathBuf, name: Option<&str>) -> PlaylistEntry {
+    let (title, artist) = match name.filter(|name| !name.is_empty()) {
+        Some(name) => match name.split_once(" - ") {
+            Some((artist, title)) => (Some(title.to_string()), Some(artist.to_string())),
+            None => (Some(name.to_string()), None),
+        },
+        None => (None, None),
+    };
+
+    PlaylistEntry { path, title, artist }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn m3u_and_pls_paths_are_resolved_from_the_playlist() {
+        let directory = Utf8Path::new("/music/mixes");
+
+        let m3u = "\u{feff}#EXTM3U\n\
+                   #EXTINF:240,Portishead - Roads\n\
+                   ../Dummy/03 Roads.flac\n\
+                   \n\
+                   http://radio.example.com/stream\n\
+                   /music/Protection/01 Protection.mp3\n\
+                   file:///music/Mezzanine/01%20Angel.mp3\n\
+                   file://localhost/music/Bj%C3%B6rk/01%20Hyperballad.flac\n";
+        assert_eq!(
+            parse_m3u(m3u, directory),
+            vec![
+                PlaylistEntry {
+                    path: "/music/Dummy/03 Roads.flac".into(),
+                    title: Some("Roads".to_string()),
+                    artist: Some("Portishead".to_string()),
+                },
+                PlaylistEntry {
+                    path: "/music/Protection/01 Protection.mp3".into(),
+                    title: None,
+                    artist: None,
+                },
+                PlaylistEntry {
+                    path: "/music/Mezzanine/01 Angel.mp3".into(),
+                    title: None,
+                    artist: None,
+                },
+                PlaylistEntry {
+                    path: "/music/Björk/01 Hyperballad.flac".into(),
+                    title: None,
+                    artist: None,
+                },
+            ]
+        );
+
+        let pls = "[playlist]\n\
+                   File2=Dummy\\04 Sour Times.flac\n\
+                   Title1=Massive Attack - Angel\n\
+                   File1=/music/Mezzanine/01 Angel.mp3\n\
+                   NumberOfEntries=2\n\
+                   Version=2\n";
+        assert_eq!(
+            parse_pls(pls, directory),
+            vec![
+                PlaylistEntry {
+                    path: "/music/Mezzanine/01 Angel.mp3".into(),
+                    title: Some("Angel".to_string()),
+                    artist: Some("Massive Attack".to_string()),
+                },
+                PlaylistEntry {
+                    path: "/music/mixes/Dummy/04 Sour Times.flac".into(),
+                    title: None,
+                    artist: None,
+                },
+            ]
+        );
+
+        assert!(is_playlist_file(Utf8Path::new("/music/Mix.M3U8")));
+        assert!(is_m3u(Utf8Path::new("/music/Mix.m3u")));
+        assert!(!is_m3u(Utf8Path::new("/music/Mix.pls")));
+        assert!(!is_playlist_file(Utf8Path::new("/music/cover.jpg")));
+    }
+
+    #[test]
+    fn legacy_playlists_are_read_as_latin1() {
+        let latin1 = b"#EXTINF:250,Bj\xf6rk - Hyperballad\nBj\xf6rk/01 Hyperballad.mp3\n";
+        let contents = decode_playlist(latin1.to_vec());
+        assert_eq!(
+            parse_m3u(&contents, Utf8Path::new("/music")),
+            vec![PlaylistEntry {
+                path: "/music/Björk/01 Hyperballad.mp3".into(),
+                title: Some("Hyperballad".to_string()),
+                artist: Some("Björk".to_string()),
+            }]
+        );
+
+        let utf8 = "Björk/01 Hyperballad.mp3\n";
+        assert_eq!(decode_playlist(utf8.as_bytes().to_vec()), utf8);
+    }
+}
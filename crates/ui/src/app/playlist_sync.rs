@@ -0,0 +1,304 @@
+//! Mirrors the playlists to .m3u8 files in the playlist folder, ie a share that other
+//! devices read, and brings edits made to those copies back to the playlist.
+//! A playlist and its copy that both changed since the last sync are a conflict;
+//! the copy's version is kept beside it, and the playlist's is mirrored over it
+
+use std::collections::{HashMap, HashSet};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use log::{error, info};
+
+use crate::app::playlist_file::{is_m3u, m3u8_file, parse_m3u, to_m3u};
+use clef_db::queries::{self, DbError, Playlist, PlaylistMirror, Song, SongId};
+use clef_db::SqlitePoolConn;
+
+/// What syncing a playlist changed, to save once every file is written
+#[derive(Debug)]
+struct Synced {
+    mirror: PlaylistMirror,
+    /// The playlist's new songs, from an edited copy
+    pulled: Option<(Playlist, Vec<SongId>)>,
+    /// Where the copy was kept, if it was a conflict
+    conflict: Option<Utf8PathBuf>,
+}
+
+/// Syncs each playlist with its copy in the folder, and removes the copies of playlists
+/// that are gone; returns where the conflicting copies were kept.
+/// NOTE a copy can't be edited into a .pls playlist, since the next scan would read
+/// the .pls file over it, so that's a conflict too
+pub fn sync_playlist_folder(
+    conn: &mut SqlitePoolConn,
+    folder: &Utf8Path,
+) -> Result<Vec<Utf8PathBuf>, DbError> {
+    if let Err(e) = std::fs::create_dir_all(folder) {
+        error!("failed to create playlist folder {folder}: {e}");
+        return Ok(Vec::new());
+    }
+
+    let (songs, playlists, last_syncs) = conn.immediate_transaction(|tx| {
+        Ok::<_, DbError>((
+            queries::all_songs(tx)?,
+            queries::all_playlists(tx)?,
+            queries::all_playlist_mirrors(tx)?,
+        ))
+    })?;
+    let library = Library {
+        by_id: songs.iter().map(|song| (song.id, song)).collect(),
+        by_path: songs
+            .iter()
+            .map(|song| (song.file.as_path(), song.id))
+            .collect(),
+    };
+    let mut last_syncs: HashMap<Utf8PathBuf, PlaylistMirror> = last_syncs
+        .into_iter()
+        .map(|mirror| (mirror.file.clone(), mirror))
+        .collect();
+
+    let mut synced = Vec::new();
+    let mut files = HashSet::new();
+    for playlist in playlists {
+        // NOTE a folder inside a music directory has its copies scanned as playlists
+        if playlist.file.starts_with(folder) {
+            continue;
+        }
+
+        let file = m3u8_file(folder, &playlist.name);
+        if !files.insert(file.clone()) {
+            info!("not mirroring a second playlist named {}", playlist.name);
+            continue;
+        }
+
+        let last_sync = last_syncs.remove(&file);
+        match sync_playlist(playlist, file, last_sync, &library) {
+            Ok(playlist_synced) => synced.extend(playlist_synced),
+            Err(e) => error!("failed to sync playlist: {e:#}"),
+        }
+    }
+
+    // NOTE an edited copy is left for the user, since nothing would sync it anymore
+    let mut forgotten = Vec::new();
+    for (file, last_sync) in last_syncs {
+        match std::fs::read_to_string(&file) {
+            Ok(contents) if contents == last_sync.mirrored => {
+                if let Err(e) = std::fs::remove_file(&file) {
+                    error!("failed to remove playlist copy {file}: {e}");
+                    continue;
+                }
+            }
+            _ => info!("leaving the copy of a removed playlist: {file}"),
+        }
+        forgotten.push(file);
+    }
+
+    conn.immediate_transaction(|tx| {
+        let mut conflicts = Vec::new();
+        for synced in synced {
+            if let Some((playlist, song_ids)) = synced.pulled {
+                queries::save_playlist(tx, &playlist.file, &playlist.name, &song_ids)?;
+            }
+            queries::save_playlist_mirror(tx, &synced.mirror)?;
+            conflicts.extend(synced.conflict);
+        }
+        for file in &forgotten {
+            queries::delete_playlist_mirror(tx, file)?;
+        }
+
+        Ok(conflicts)
+    })
+}
+
+struct Library<'a> {
+    by_id: HashMap<SongId, &'a Song>,
+    by_path: HashMap<&'a Utf8Path, SongId>,
+}
+
+impl Library<'_> {
+    fn to_m3u(&self, song_ids: &[SongId]) -> String {
+        to_m3u(song_ids.iter().filter_map(|id| self.by_id.get(id).copied()))
+    }
+}
+
+/// None if neither the playlist nor its copy changed since the last sync
+fn sync_playlist(
+    playlist: Playlist,
+    file: Utf8PathBuf,
+    last_sync: Option<PlaylistMirror>,
+    library: &Library<'_>,
+) -> anyhow::Result<Option<Synced>> {
+    let listed = library.to_m3u(&playlist.songs);
+    let mirrored = match std::fs::read_to_string(&file) {
+        Ok(mirrored) => Some(mirrored),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(anyhow::anyhow!("failed to read {file}: {e}")),
+    };
+
+    let mut synced = Synced {
+        mirror: PlaylistMirror {
+            file,
+            playlist_file: playlist.file.clone(),
+            mirrored: listed.clone(),
+            listed,
+        },
+        pulled: None,
+        conflict: None,
+    };
+    let mirror = &mut synced.mirror;
+
+    let Some(mirrored) = mirrored else {
+        std::fs::write(&mirror.file, &mirror.listed)?;
+        return Ok(Some(synced));
+    };
+    let (copy_changed, playlist_changed) = match &last_sync {
+        Some(last_sync) => (
+            mirrored != last_sync.mirrored,
+            mirror.listed != last_sync.listed,
+        ),
+        // NOTE the first sync with a copy that's already there
+        None => (mirrored != mirror.listed, mirrored != mirror.listed),
+    };
+
+    match (copy_changed, playlist_changed) {
+        (false, false) if last_sync.is_some() => return Ok(None),
+
+        (false, _) => {
+            std::fs::write(&mirror.file, &mirror.listed)?;
+        }
+
+        (true, false) if is_m3u(&playlist.file) => {
+            let folder = mirror.file.parent().unwrap_or(Utf8Path::new(""));
+            let song_ids: Vec<SongId> = parse_m3u(&mirrored, folder)
+                .iter()
+                .filter_map(|entry| library.by_path.get(entry.path.as_path()).copied())
+                .collect();
+
+            // NOTE songs that aren't in this library stay in the copy, but not the playlist
+            mirror.listed = library.to_m3u(&song_ids);
+            mirror.mirrored = mirrored;
+            std::fs::write(&playlist.file, &mirror.listed)?;
+            synced.pulled = Some((playlist, song_ids));
+        }
+
+        (true, _) => {
+            let conflict = mirror.file.with_file_name(format!(
+                "{} (conflict).m3u8",
+                mirror.file.file_stem().unwrap_or_default()
+            ));
+            std::fs::write(&conflict, &mirrored)?;
+            std::fs::write(&mirror.file, &mirror.listed)?;
+            synced.conflict = Some(conflict);
+        }
+    }
+
+    Ok(Some(synced))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clef_db::queries::{NewAlbum, NewSong};
+    use clef_db::{create_pool, run_migrations};
+
+    #[test]
+    fn playlists_and_their_copies_are_synced() {
+        let root = std::env::temp_dir().join(format!("clef-sync-{}", std::process::id()));
+        let root = Utf8PathBuf::try_from(root).unwrap();
+        let music = root.join("Music");
+        let folder = root.join("Share");
+        std::fs::create_dir_all(&music).unwrap();
+        let pool = create_pool(&root.join("db.sqlite")).unwrap();
+        run_migrations(&pool).unwrap();
+        let mut conn = pool.get().unwrap();
+
+        let source = music.join("Mix.m3u");
+        let (playlist, song_ids) = conn
+            .immediate_transaction(|tx| {
+                let new_album = NewAlbum {
+                    directory: music.join("Album"),
+                    title: Some("Album".to_string()),
+                    artist: None,
+                    release_date: None,
+                    original_art: None,
+                    resized_art: None,
+                    album_gain: None,
+                    album_peak: None,
+                    library_root: Some(music.clone()),
+                    disc_numbers: Vec::new(),
+                };
+                let (album, _) = queries::find_or_insert_album(tx, new_album)?;
+                let mut song_ids = Vec::new();
+                for track in 1..=3 {
+                    let new_song = NewSong {
+                        album_id: album.id,
+                        file: music.join(format!("Album/0{track}.flac")),
+                        total_seconds: 100,
+                        title: Some(format!("Track {track}")),
+                        artist: None,
+                        track_number: Some(track),
+                        disc_number: None,
+                        track_gain: None,
+                        track_peak: None,
+                        genre: None,
+                        track_total: None,
+                        bpm: None,
+                        initial_key: None,
+                    };
+                    song_ids.push(queries::find_or_insert_song(tx, new_song)?.0.id);
+                }
+                let playlist =
+                    queries::save_playlist(tx, &source, "Mix", &song_ids[..2])?;
+                Ok::<_, DbError>((playlist, song_ids))
+            })
+            .unwrap();
+        let songs_in_db = |conn: &mut SqlitePoolConn| {
+            let playlists = conn.immediate_transaction(queries::all_playlists).unwrap();
+            playlists[0].songs.clone()
+        };
+
+        // the first sync writes the copy
+        assert!(sync_playlist_folder(&mut conn, &folder).unwrap().is_empty());
+        let copy = folder.join("Mix.m3u8");
+        let mirrored = std::fs::read_to_string(&copy).unwrap();
+        assert!(mirrored.contains("01.flac") && mirrored.contains("02.flac"));
+
+        // an edited copy is brought back to the playlist and its file
+        let edited = format!("{mirrored}{}\n", music.join("Album/03.flac"));
+        std::fs::write(&copy, &edited).unwrap();
+        assert!(sync_playlist_folder(&mut conn, &folder).unwrap().is_empty());
+        assert_eq!(songs_in_db(&mut conn), song_ids);
+        assert!(std::fs::read_to_string(&source)
+            .unwrap()
+            .contains("03.flac"));
+        assert_eq!(std::fs::read_to_string(&copy).unwrap(), edited);
+
+        // nothing changed since
+        assert!(sync_playlist_folder(&mut conn, &folder).unwrap().is_empty());
+        assert_eq!(songs_in_db(&mut conn), song_ids);
+
+        // both edited: the copy's version is kept beside the playlist's
+        let copy_edit = format!("#EXTM3U\n{}\n", music.join("Album/01.flac"));
+        std::fs::write(&copy, &copy_edit).unwrap();
+        conn.immediate_transaction(|tx| {
+            queries::save_playlist(tx, &source, "Mix", &song_ids[1..])
+        })
+        .unwrap();
+        let conflicts = sync_playlist_folder(&mut conn, &folder).unwrap();
+        assert_eq!(conflicts, [folder.join("Mix (conflict).m3u8")]);
+        assert_eq!(std::fs::read_to_string(&conflicts[0]).unwrap(), copy_edit);
+        assert!(!std::fs::read_to_string(&copy).unwrap().contains("01.flac"));
+        assert_eq!(songs_in_db(&mut conn), &song_ids[1..]);
+
+        // a removed playlist's unchanged copy is removed too
+        conn.immediate_transaction(|tx| queries::delete_playlists(tx, &[playlist.id]))
+            .unwrap();
+        assert!(sync_playlist_folder(&mut conn, &folder).unwrap().is_empty());
+        assert!(!copy.exists());
+        assert!(conn
+            .immediate_transaction(queries::all_playlist_mirrors)
+            .unwrap()
+            .is_empty());
+
+        drop(conn);
+        drop(pool);
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
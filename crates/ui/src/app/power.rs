@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+/// How often to look at the power source; it's checked on audio updates
+const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSource {
+    Battery,
+    /// Plugged in, or a machine without a battery
+    External,
+}
+
+/// Whether to cut back on background work to save battery
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PowerMode {
+    /// Low power while on battery
+    #[default]
+    Auto,
+    Saver,
+    Full,
+}
+
+impl PowerMode {
+    pub const ALL: [PowerMode; 3] = [Self::Auto, Self::Saver, Self::Full];
+}
+
+impl std::fmt::Display for PowerMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Auto => "Power: Auto",
+            Self::Saver => "Power: Saver",
+            Self::Full => "Power: Full",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PowerState {
+    pub mode: PowerMode,
+    /// None = unknown; not checked yet, or not supported on this platform
+    pub source: Option<PowerSource>,
+    checked_at: Option<Instant>,
+}
+
+impl PowerState {
+    pub fn low_power(&self) -> bool {
+        match self.mode {
+            PowerMode::Auto => self.source == Some(PowerSource::Battery),
+            PowerMode::Saver => true,
+            PowerMode::Full => false,
+        }
+    }
+
+    /// True at most once per interval; the caller is expected to check then
+    pub fn check_due(&mut self) -> bool {
+        let due = self
+            .checked_at
+            .is_none_or(|checked_at| checked_at.elapsed() >= POWER_CHECK_INTERVAL);
+        if due {
+            self.checked_at = Some(Instant::now());
+        }
+
+        due
+    }
+}
+
+pub async fn detect_power_source() -> Option<PowerSource> {
+    detect()
+}
+
+#[cfg(target_os = "linux")]
+fn detect() -> Option<PowerSource> {
+    const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+    let read = |path: std::path::PathBuf| {
+        std::fs::read_to_string(path)
+            .map(|contents| contents.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let supplies = std::fs::read_dir(POWER_SUPPLY_DIR).ok()?;
+    let discharging = supplies.flatten().any(|supply| {
+        let path = supply.path();
+        read(path.join("type")) == "Battery" && read(path.join("status")) == "Discharging"
+    });
+
+    if discharging {
+        Some(PowerSource::Battery)
+    } else {
+        Some(PowerSource::External)
+    }
+}
+
+// TODO GetSystemPowerStatus on windows; the power mode can still be set by hand
+#[cfg(not(target_os = "linux"))]
+fn detect() -> Option<PowerSource> {
+    None
+}
@@ -6,15 +6,20 @@ use flume::{Receiver, TryRecvError};
 use log::error;
 
 use crate::app::old_unfold::old_unfold;
-use crate::app::rgba::{load_rgba, save_rgba, RgbaBytes, IMAGE_SIZE};
+use crate::app::rgba::{
+    load_cached_rgba_bmp, load_rgba, save_rgba, RgbaBytes, IMAGE_SIZE,
+};
 use clef_db::queries::{add_resized_image_location, AlbumId};
-use clef_db::SqlitePool;
+use clef_db::{SqlitePool, SqlitePoolConn};
 
 use super::Config;
 
 #[derive(Clone, Debug)]
 pub enum ResizerMessage {
     ResizedImage(ResizedImage),
+    /// An album's art couldn't be resized, with the album (0), its title (1),
+    /// and the reason (2)
+    Failed(AlbumId, String, String),
 }
 
 #[derive(Clone, Debug)]
@@ -29,6 +34,8 @@ pub struct ResizeRequest {
     pub album_id: AlbumId,
     pub album_title: String,
     pub source_path: Utf8PathBuf,
+    /// The source is art resized during an earlier launch, which only needs loading
+    pub already_resized: bool,
 }
 
 pub fn resizer_subscription(
@@ -74,7 +81,11 @@ async fn step(
                 Ok(resized_image) => Some(ResizerMessage::ResizedImage(resized_image)),
                 Err(e) => {
                     error!("error resizing image: {request:#?} {e}");
-                    None
+                    Some(ResizerMessage::Failed(
+                        request.album_id,
+                        request.album_title.clone(),
+                        format!("{e:#}"),
+                    ))
                 }
             };
 
@@ -90,25 +101,27 @@ fn resize(
     images_directory: &Utf8Path,
     db: SqlitePool,
 ) -> anyhow::Result<ResizedImage> {
-    let image_bytes = load_rgba(&request.source_path).context("loading original")?;
-
-    let title: String = request
-        .album_title
-        .chars()
-        .filter(|&c| c != '\\' && c != '/')
-        .collect();
-    let album_id = request.album_id.unpack();
-    let file_name = format!("{title}_{album_id}_{IMAGE_SIZE}.bmp");
-    let file_name: Utf8PathBuf = file_name.try_into()?;
-    let path = images_directory.join(file_name);
+    if request.already_resized {
+        let image_bytes =
+            load_cached_rgba_bmp(&request.source_path).context("loading resized")?;
+
+        return Ok(ResizedImage {
+            album_id: request.album_id,
+            file: request.source_path.clone(),
+            bytes: image_bytes,
+        });
+    }
 
-    save_rgba(&path, &image_bytes)
-        .with_context(|| format!("saving resized bmp: {path}"))?;
+    let image_bytes = load_rgba(&request.source_path).context("loading original")?;
 
     let mut conn = db.get().context("checking out db connection")?;
-    conn.immediate_transaction(|tx| {
-        add_resized_image_location(tx, request.album_id, &path)
-    })?;
+    let path = save_resized_image(
+        request.album_id,
+        &request.album_title,
+        &image_bytes,
+        images_directory,
+        &mut conn,
+    )?;
 
     let resized = ResizedImage {
         album_id: request.album_id,
@@ -118,3 +131,27 @@ fn resize(
 
     Ok(resized)
 }
+
+/// Saves resized album art to the images directory, and records its location
+pub fn save_resized_image(
+    album_id: AlbumId,
+    album_title: &str,
+    image_bytes: &RgbaBytes,
+    images_directory: &Utf8Path,
+    conn: &mut SqlitePoolConn,
+) -> anyhow::Result<Utf8PathBuf> {
+    let title: String = album_title
+        .chars()
+        .filter(|&c| c != '\\' && c != '/')
+        .collect();
+    let file_name = format!("{title}_{}_{IMAGE_SIZE}.bmp", album_id.unpack());
+    let file_name: Utf8PathBuf = file_name.into();
+    let path = images_directory.join(file_name);
+
+    save_rgba(&path, image_bytes)
+        .with_context(|| format!("saving resized bmp: {path}"))?;
+
+    conn.immediate_transaction(|tx| add_resized_image_location(tx, album_id, &path))?;
+
+    Ok(path)
+}
@@ -1,8 +1,13 @@
+use std::fs::File;
+use std::io::BufReader;
+
 use camino::{Utf8Path, Utf8PathBuf};
 use iced::widget::image;
+use iced::Color;
 use iced_native::image::Handle;
+use image_rs::codecs::jpeg::JpegDecoder;
 use image_rs::Rgba;
-use image_rs::{imageops::FilterType, ColorType, ImageBuffer};
+use image_rs::{imageops::FilterType, ColorType, DynamicImage, ImageBuffer};
 
 /// The size that album art gets resized (down) to.
 pub const IMAGE_SIZE: u16 = 256;
@@ -16,23 +21,85 @@ pub const IMAGE_SIZE: u16 = 256;
 #[derive(Clone, Debug)]
 pub struct RgbaBytes {
     handle: Handle,
+    /// The relative luminance of the bottom of the image, where captions go;
+    /// computed along with the conversion for the same reason
+    caption_luminance: f32,
 }
 
 impl RgbaBytes {
     #[cfg(test)]
     pub fn empty() -> Self {
         let handle = Handle::from_pixels(0, 0, vec![]);
-        Self { handle }
+        Self { handle, caption_luminance: 0.0 }
+    }
+
+    /// Blank pixels that take up space, for memory budgets
+    #[cfg(test)]
+    pub fn blank(side: u32) -> Self {
+        Self::from_buffer(ImageBuffer::new(side, side))
     }
 
     fn from_buffer(rgba: ImageBuffer<Rgba<u8>, Vec<u8>>) -> Self {
+        let caption_luminance = caption_luminance(&rgba);
         let width = rgba.width();
         let height = rgba.height();
         let bytes = rgba.into_raw();
         let handle = Handle::from_pixels(width, height, bytes);
 
-        RgbaBytes { handle }
+        RgbaBytes { handle, caption_luminance }
+    }
+
+    pub fn caption_luminance(&self) -> f32 {
+        self.caption_luminance
+    }
+
+    /// The size of the decoded pixels
+    pub fn byte_len(&self) -> usize {
+        use iced_native::image::Data;
+
+        match self.handle.data() {
+            Data::Rgba { pixels, .. } => pixels.len(),
+            Data::Path(_) | Data::Bytes(_) => 0,
+        }
+    }
+}
+
+/// The share of the image's height, from the bottom, that a caption covers
+const CAPTION_BAND: f32 = 0.25;
+
+fn caption_luminance(rgba: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> f32 {
+    let band_start = (rgba.height() as f32 * (1.0 - CAPTION_BAND)) as u32;
+
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (_x, _y, pixel) in rgba
+        .enumerate_pixels()
+        .filter(|(_x, y, _)| *y >= band_start)
+    {
+        let [r, g, b, _a] = pixel.0;
+        sum += relative_luminance(Color::from_rgb8(r, g, b));
+        count += 1;
+    }
+
+    if count == 0 {
+        return 0.0;
     }
+
+    sum / count as f32
+}
+
+/// Relative luminance as defined for WCAG contrast ratios; 0 is black and 1 is white
+/// https://www.w3.org/TR/WCAG21/#dfn-relative-luminance
+pub fn relative_luminance(color: Color) -> f32 {
+    let linear = |channel: f32| {
+        if channel <= 0.03928 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
 }
 
 impl From<&RgbaBytes> for image::Handle {
@@ -45,15 +112,25 @@ impl From<&RgbaBytes> for image::Handle {
 // NOTE this is slow
 pub fn load_rgba(path: &Utf8PathBuf) -> anyhow::Result<RgbaBytes> {
     let img = image_rs::open(path)?;
+
+    Ok(resize_rgba(img))
+}
+
+// NOTE this is slow
+pub fn load_rgba_from_memory(encoded: &[u8]) -> anyhow::Result<RgbaBytes> {
+    let img = image_rs::load_from_memory(encoded)?;
+
+    Ok(resize_rgba(img))
+}
+
+fn resize_rgba(img: DynamicImage) -> RgbaBytes {
     let img = img.resize(
         u32::from(IMAGE_SIZE),
         u32::from(IMAGE_SIZE),
         FilterType::Lanczos3,
     );
 
-    let rgba_bytes = RgbaBytes::from_buffer(img.to_rgba8());
-
-    Ok(rgba_bytes)
+    RgbaBytes::from_buffer(img.to_rgba8())
 }
 
 // NOTE this assumes that the 'conversion' to rgba8
@@ -65,10 +142,48 @@ pub fn load_cached_rgba_bmp(path: &Utf8Path) -> anyhow::Result<RgbaBytes> {
     Ok(rgba_bytes)
 }
 
+/// Samples the average color of an image, for display while it's being resized.
+/// Jpegs are decoded at a reduced scale, which makes this much faster than resizing.
+pub fn sample_average_color(path: &Utf8Path) -> anyhow::Result<Color> {
+    let is_jpeg = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"))
+        .unwrap_or_default();
+
+    let img = if is_jpeg {
+        let file = BufReader::new(File::open(path)?);
+        let mut decoder = JpegDecoder::new(file)?;
+        decoder.scale(COLOR_SAMPLE_SIZE, COLOR_SAMPLE_SIZE)?;
+        DynamicImage::from_decoder(decoder)?
+    } else {
+        image_rs::open(path)?
+    };
+
+    let pixels = img.to_rgb8();
+    let count = u64::from(pixels.width()) * u64::from(pixels.height());
+    if count == 0 {
+        anyhow::bail!("empty image: {path}");
+    }
+
+    let mut sums = [0u64; 3];
+    for pixel in pixels.pixels() {
+        for (sum, channel) in sums.iter_mut().zip(pixel.0) {
+            *sum += u64::from(channel);
+        }
+    }
+
+    let [r, g, b] = sums.map(|sum| (sum / count) as u8);
+
+    Ok(Color::from_rgb8(r, g, b))
+}
+
+/// The smallest size to request from the jpeg decoder when sampling colors
+const COLOR_SAMPLE_SIZE: u16 = 32;
+
 pub fn save_rgba(path: &Utf8PathBuf, rgba: &RgbaBytes) -> anyhow::Result<()> {
     use iced_native::image::Data;
 
-    let RgbaBytes { handle } = rgba;
+    let RgbaBytes { handle, .. } = rgba;
     let (pixels, width, height) = match handle.data() {
         Data::Path(_) | Data::Bytes(_) => unreachable!(),
         Data::Rgba { pixels, width, height } => (pixels, width, height),
@@ -78,3 +193,22 @@ pub fn save_rgba(path: &Utf8PathBuf, rgba: &RgbaBytes) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caption_luminance_only_samples_the_bottom() {
+        let rgba = ImageBuffer::from_fn(4, 4, |_x, y| {
+            if y < 3 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+
+        assert_eq!(caption_luminance(&rgba), 1.0);
+        assert_eq!(relative_luminance(Color::BLACK), 0.0);
+    }
+}
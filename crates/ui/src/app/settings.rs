@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use clef_audio::replay_gain::{ReplayGainMode, ReplayGainSettings};
+use clef_audio::resample_quality::ResampleQuality;
+use clef_audio::shuffle_order::ShuffleOrder;
+
+use crate::app::custom_style::PaletteSettings;
+use crate::app::mqtt::MqttSettings;
+use crate::app::visualizer::VisualizerStyle;
+
+/// The settings file's name, in the platform's config directory
+pub const SETTINGS_FILE_NAME: &str = "clef.toml";
+
+/// The contents of clef.toml; missing settings use their defaults.
+/// NOTE the environment variables read at launch take precedence over the file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SettingsFile {
+    /// Empty = the platform's music folder
+    pub music_directories: Vec<Utf8PathBuf>,
+    pub replaygain: Option<ReplayGainMode>,
+    /// Added to the tagged gain, in dB
+    pub replaygain_preamp: Option<f32>,
+    /// Megabytes of decoded album art to keep in memory
+    pub art_cache_mb: Option<usize>,
+    pub audio_extensions: Option<Vec<String>>,
+    pub image_extensions: Option<Vec<String>>,
+    pub shuffle_order: Option<ShuffleOrder>,
+    /// Used for songs that don't match the output device's sample rate
+    pub resample_quality: ResampleQuality,
+    /// Draws a bar behind each duration in track lists, scaled to the song's length
+    pub duration_bars: bool,
+    /// Copies folders dropped on the window from outside the library
+    /// into the first music directory, then rescans
+    pub import_dropped: bool,
+    /// Looks up art on MusicBrainz for albums with none, during scans
+    pub fetch_missing_art: bool,
+    /// Hides the window to the tray icon on close, so that music keeps playing
+    pub close_to_tray: bool,
+    /// Drawn above the playback controls while music plays
+    pub visualizer: VisualizerStyle,
+    /// Where to POST playback events as json; None = don't send them
+    pub webhook_url: Option<String>,
+    /// Where to mirror the playlists as .m3u8 files after each scan, bringing back
+    /// edits made there; None = don't. NOTE this shouldn't be in a music directory
+    pub playlist_folder: Option<Utf8PathBuf>,
+    /// Where to publish playback events for home automation; None = don't publish them
+    pub mqtt: Option<MqttSettings>,
+    /// Colors over the dark theme's, ie an accent
+    #[serde(skip_serializing_if = "PaletteSettings::is_default")]
+    pub palette: PaletteSettings,
+    /// Key bindings by action name, over the defaults; see keymap
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, String>,
+}
+
+impl SettingsFile {
+    /// A missing file is the same as an empty one
+    pub fn load(path: &Utf8Path) -> anyhow::Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(e).with_context(|| format!("failed to read {path}")),
+        };
+
+        toml::from_str(&contents).with_context(|| format!("invalid settings in {path}"))
+    }
+
+    pub fn save(&self, path: &Utf8Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let contents = toml::to_string_pretty(self)?;
+        std::fs::write(path, contents).with_context(|| format!("failed to write {path}"))
+    }
+
+    pub fn replay_gain(&self) -> ReplayGainSettings {
+        ReplayGainSettings {
+            mode: self.replaygain.unwrap_or_default(),
+            preamp_db: self.replaygain_preamp.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::custom_style::{Accent, ThemeColor};
+
+    #[test]
+    fn settings_file_round_trips_and_allows_missing_keys() {
+        let settings = SettingsFile {
+            music_directories: vec!["/music".into(), "/mnt/share/music".into()],
+            replaygain: Some(ReplayGainMode::Album),
+            replaygain_preamp: Some(-3.5),
+            art_cache_mb: None,
+            audio_extensions: Some(vec!["mka".to_string()]),
+            image_extensions: None,
+            shuffle_order: Some(ShuffleOrder::Smooth),
+            resample_quality: ResampleQuality::High,
+            duration_bars: true,
+            import_dropped: false,
+            fetch_missing_art: true,
+            close_to_tray: true,
+            visualizer: VisualizerStyle::Bars,
+            webhook_url: Some("http://localhost:8123/api/webhook/clef".to_string()),
+            playlist_folder: Some("/mnt/share/playlists".into()),
+            mqtt: Some(MqttSettings {
+                broker_url: "mqtt://homeassistant.local".to_string(),
+                topic: "clef/playback".to_string(),
+                username: Some("clef".to_string()),
+                password: None,
+            }),
+            palette: PaletteSettings {
+                accent: Some(ThemeColor::Named(Accent::Green)),
+                background: Some(ThemeColor::Hex([0x10, 0x10, 0x14])),
+                text: None,
+            },
+            keys: BTreeMap::from([("mute".to_string(), "ctrl+m".to_string())]),
+        };
+
+        let contents = toml::to_string_pretty(&settings).unwrap();
+        assert_eq!(toml::from_str::<SettingsFile>(&contents).unwrap(), settings);
+
+        let partial: SettingsFile = toml::from_str("replaygain = \"off\"").unwrap();
+        assert_eq!(partial.replay_gain().mode, ReplayGainMode::Off);
+        assert!(partial.music_directories.is_empty());
+    }
+}
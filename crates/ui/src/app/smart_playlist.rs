@@ -0,0 +1,150 @@
+use clef_db::queries::SmartRule;
+
+/// The kinds of smart playlist rule, to pick from in the rule builder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleKind {
+    ArtistContains,
+    GenreIs,
+    Favorite,
+    PlayedAtLeast,
+    PlayedWithinDays,
+    NotPlayedWithinDays,
+}
+
+impl RuleKind {
+    pub const ALL: [RuleKind; 6] = [
+        Self::ArtistContains,
+        Self::GenreIs,
+        Self::Favorite,
+        Self::PlayedAtLeast,
+        Self::PlayedWithinDays,
+        Self::NotPlayedWithinDays,
+    ];
+
+    pub fn takes_value(&self) -> bool {
+        !matches!(self, Self::Favorite)
+    }
+}
+
+impl std::fmt::Display for RuleKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::ArtistContains => "Artist contains",
+            Self::GenreIs => "Genre is",
+            Self::Favorite => "Is a favorite",
+            Self::PlayedAtLeast => "Times played, at least",
+            Self::PlayedWithinDays => "Played in the last (days)",
+            Self::NotPlayedWithinDays => "Not played in the last (days)",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// A rule in the rule builder, which may not be valid yet
+#[derive(Debug, Clone)]
+pub struct RuleDraft {
+    pub kind: RuleKind,
+    pub value: String,
+}
+
+impl Default for RuleDraft {
+    fn default() -> Self {
+        Self {
+            kind: RuleKind::ArtistContains,
+            value: String::new(),
+        }
+    }
+}
+
+impl RuleDraft {
+    /// None if the value is missing, or isn't a number for a numeric rule
+    fn to_rule(&self) -> Option<SmartRule> {
+        let value = self.value.trim();
+
+        let rule = match self.kind {
+            RuleKind::ArtistContains if value.is_empty() => return None,
+            RuleKind::ArtistContains => SmartRule::ArtistContains(value.to_string()),
+            RuleKind::GenreIs if value.is_empty() => return None,
+            RuleKind::GenreIs => SmartRule::GenreIs(value.to_string()),
+            RuleKind::Favorite => SmartRule::Favorite,
+            RuleKind::PlayedAtLeast => SmartRule::PlayedAtLeast(value.parse().ok()?),
+            RuleKind::PlayedWithinDays => {
+                SmartRule::PlayedWithinDays(value.parse().ok()?)
+            }
+            RuleKind::NotPlayedWithinDays => {
+                SmartRule::NotPlayedWithinDays(value.parse().ok()?)
+            }
+        };
+
+        Some(rule)
+    }
+}
+
+/// A new smart playlist, being built in the ui
+#[derive(Debug, Default)]
+pub struct SmartPlaylistDraft {
+    pub name: String,
+    pub rules: Vec<RuleDraft>,
+}
+
+impl SmartPlaylistDraft {
+    /// The rules to save; None until there's a name, at least one rule,
+    /// and every rule is valid
+    pub fn to_rules(&self) -> Option<Vec<SmartRule>> {
+        if self.name.trim().is_empty() || self.rules.is_empty() {
+            return None;
+        }
+
+        self.rules.iter().map(RuleDraft::to_rule).collect()
+    }
+}
+
+/// A short description of a rule, for the list of saved playlists
+pub fn describe_rule(rule: &SmartRule) -> String {
+    match rule {
+        SmartRule::ArtistContains(text) => format!("artist contains '{text}'"),
+        SmartRule::GenreIs(genre) => format!("genre is '{genre}'"),
+        SmartRule::Favorite => "a favorite".to_string(),
+        SmartRule::PlayedAtLeast(count) => format!("played {count}+ times"),
+        SmartRule::PlayedWithinDays(days) => format!("played in the last {days} days"),
+        SmartRule::NotPlayedWithinDays(days) => {
+            format!("not played in the last {days} days")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drafts_need_a_name_and_valid_rules() {
+        let mut draft = SmartPlaylistDraft {
+            name: "Old favorites".to_string(),
+            rules: vec![
+                RuleDraft {
+                    kind: RuleKind::Favorite,
+                    value: String::new(),
+                },
+                RuleDraft {
+                    kind: RuleKind::NotPlayedWithinDays,
+                    value: "thirty".to_string(),
+                },
+            ],
+        };
+        assert_eq!(draft.to_rules(), None);
+
+        draft.rules[1].value = " 30 ".to_string();
+        assert_eq!(
+            draft.to_rules(),
+            Some(vec![
+                SmartRule::Favorite,
+                SmartRule::NotPlayedWithinDays(30)
+            ])
+        );
+
+        draft.name = String::new();
+        assert_eq!(draft.to_rules(), None);
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+
+use clef_audio::player::PlayerSnapshot;
+use clef_db::queries::{AlbumId, SavedQueue, SongId};
+
+const DUMPS_DIR_NAME: &str = "state_dumps";
+
+/// The full player and ui state, written to a file for bug reports
+#[derive(Debug, Serialize)]
+pub struct StateDump {
+    pub player: PlayerSnapshot,
+    pub ui: UiSnapshot,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UiSnapshot {
+    pub crawling_music: bool,
+    pub current_song: Option<CurrentSongSnapshot>,
+    pub progress: Option<ProgressSnapshot>,
+    pub hovered_song_id: Option<SongId>,
+    pub shuffle: bool,
+    pub crashed_queue: Option<SavedQueueSnapshot>,
+    pub cached_albums: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CurrentSongSnapshot {
+    pub id: SongId,
+    pub album_id: AlbumId,
+    pub playing: bool,
+    pub total_seconds: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub enum ProgressSnapshot {
+    Dragging(f32),
+    FromAudio {
+        elapsed_seconds: f64,
+        total_seconds: f64,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct SavedQueueSnapshot {
+    pub song_ids: Vec<SongId>,
+    pub current_index: usize,
+    pub elapsed_seconds: f64,
+}
+
+impl From<&SavedQueue> for SavedQueueSnapshot {
+    fn from(saved_queue: &SavedQueue) -> Self {
+        Self {
+            song_ids: saved_queue.song_ids.clone(),
+            current_index: saved_queue.current_index,
+            elapsed_seconds: saved_queue.elapsed_seconds,
+        }
+    }
+}
+
+/// Writes the dump as json to a new file in the local data directory
+pub fn write_state_dump(
+    local_data_directory: &Utf8Path,
+    dump: &StateDump,
+) -> anyhow::Result<Utf8PathBuf> {
+    let dumps_directory = local_data_directory.join(DUMPS_DIR_NAME);
+    std::fs::create_dir_all(&dumps_directory)?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = dumps_directory.join(format!("state-{timestamp}.json"));
+
+    let json = serde_json::to_string_pretty(dump)?;
+    std::fs::write(&path, json).with_context(|| format!("failed to write {path}"))?;
+
+    Ok(path)
+}
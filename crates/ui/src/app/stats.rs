@@ -0,0 +1,246 @@
+//! Listening totals and favorites over a recent span, from the recorded plays;
+//! see clef_db::queries::song_listening_since
+
+use std::collections::HashMap;
+
+use iced::widget::canvas::{self, Cursor, Frame, Geometry, Text};
+use iced::{alignment, Color, Point, Rectangle, Size, Theme};
+
+use clef_db::queries::{AlbumId, ListeningClock, SongId, SongListening};
+
+use crate::app::music_cache::MusicCache;
+
+/// The number of songs, albums, and artists in each top list
+const TOP_LENGTH: usize = 10;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// How far back the stats go, from now
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatsPeriod {
+    #[default]
+    Week,
+    Month,
+    Year,
+}
+
+impl StatsPeriod {
+    pub const ALL: [StatsPeriod; 3] = [Self::Week, Self::Month, Self::Year];
+
+    /// The unix timestamp the period starts at
+    pub fn since(self, now: i64) -> i64 {
+        let days = match self {
+            Self::Week => 7,
+            Self::Month => 30,
+            Self::Year => 365,
+        };
+
+        now - days * SECONDS_PER_DAY
+    }
+}
+
+impl std::fmt::Display for StatsPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Week => "Past week",
+            Self::Month => "Past month",
+            Self::Year => "Past year",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// The db's totals for a period, before they're grouped by album and artist
+#[derive(Debug, Clone)]
+pub struct LoadedListening {
+    pub period: StatsPeriod,
+    pub songs: Vec<SongListening>,
+    pub clock: ListeningClock,
+}
+
+/// Listening seconds, most first
+#[derive(Debug, Default)]
+pub struct ListeningStats {
+    pub period: StatsPeriod,
+    pub total_seconds: f64,
+    pub top_songs: Vec<(SongId, f64)>,
+    pub top_albums: Vec<(AlbumId, f64)>,
+    pub top_artists: Vec<(String, f64)>,
+    pub clock: ListeningClock,
+}
+
+impl ListeningStats {
+    /// NOTE songs removed from the library since they were played
+    /// still count toward the total, but not the top albums or artists
+    pub fn new(loaded: LoadedListening, music: &MusicCache) -> Self {
+        let total_seconds = loaded.songs.iter().map(|song| song.listened_seconds).sum();
+
+        let mut albums: HashMap<AlbumId, f64> = HashMap::new();
+        let mut artists: HashMap<String, f64> = HashMap::new();
+        for listening in &loaded.songs {
+            let Some(song) = music.get_song(&listening.song_id) else {
+                continue;
+            };
+            *albums.entry(song.album_id).or_default() += listening.listened_seconds;
+
+            // NOTE compilations credit each song's artist, rather than the album's
+            let album_artist = music
+                .get_album(&song.album_id)
+                .and_then(|album| album.artist.as_deref());
+            if let Some(artist) = song.artist.as_deref().or(album_artist) {
+                *artists.entry(artist.to_string()).or_default() +=
+                    listening.listened_seconds;
+            }
+        }
+
+        let songs = loaded
+            .songs
+            .iter()
+            .filter(|listening| music.get_song(&listening.song_id).is_some())
+            .map(|listening| (listening.song_id, listening.listened_seconds));
+
+        Self {
+            period: loaded.period,
+            total_seconds,
+            top_songs: top(songs),
+            top_albums: top(albums),
+            top_artists: top(artists),
+            clock: loaded.clock,
+        }
+    }
+}
+
+fn top<K>(totals: impl IntoIterator<Item = (K, f64)>) -> Vec<(K, f64)> {
+    let mut totals: Vec<_> = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1.total_cmp(&a.1));
+    totals.truncate(TOP_LENGTH);
+
+    totals
+}
+
+/// ie '3 hr 20 min' or '45 min'
+pub fn format_listening(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).round() as i64;
+    if minutes < 60 {
+        return format!("{minutes} min");
+    }
+
+    format!("{} hr {} min", minutes / 60, minutes % 60)
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+/// Room for the weekday names, left of the cells
+const LABEL_WIDTH: f32 = 40.0;
+/// Room for the hours, above the cells
+const LABEL_HEIGHT: f32 = 20.0;
+
+/// A canvas program: a row per weekday and a column per hour,
+/// shaded by how much was listened to then
+#[derive(Debug)]
+pub struct ListeningClockMap<'a> {
+    pub clock: &'a ListeningClock,
+}
+
+impl<Message> canvas::Program<Message> for ListeningClockMap<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        theme: &Theme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let palette = theme.palette();
+
+        let most = self.clock.iter().flatten().fold(0f64, |a, &b| a.max(b));
+        let cell_width = (frame.width() - LABEL_WIDTH) / 24.0;
+        let cell_height = (frame.height() - LABEL_HEIGHT) / 7.0;
+
+        for hour in (0..24).step_by(6) {
+            frame.fill_text(Text {
+                content: format!("{hour}:00"),
+                position: Point::new(LABEL_WIDTH + hour as f32 * cell_width, 0.0),
+                color: palette.text,
+                ..Text::default()
+            });
+        }
+
+        for (weekday, hours) in self.clock.iter().enumerate() {
+            let y = LABEL_HEIGHT + weekday as f32 * cell_height;
+            frame.fill_text(Text {
+                content: WEEKDAYS[weekday].to_string(),
+                position: Point::new(0.0, y + cell_height / 2.0),
+                color: palette.text,
+                vertical_alignment: alignment::Vertical::Center,
+                ..Text::default()
+            });
+
+            for (hour, seconds) in hours.iter().enumerate() {
+                // NOTE empty hours still show faintly, so that the grid reads as a week
+                let share = if most > 0.0 { seconds / most } else { 0.0 };
+                let color = Color {
+                    a: 0.08 + 0.92 * share as f32,
+                    ..palette.primary
+                };
+
+                frame.fill_rectangle(
+                    Point::new(LABEL_WIDTH + hour as f32 * cell_width + 1.0, y + 1.0),
+                    Size::new((cell_width - 2.0).max(1.0), (cell_height - 2.0).max(1.0)),
+                    color,
+                );
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fake_album;
+
+    #[test]
+    fn listening_is_totaled_by_song_album_and_artist() {
+        let mut music = MusicCache::new();
+        let mut crawled = fake_album();
+        crawled.songs[1].artist = Some("Guest".to_string());
+        let album_id = crawled.album.id;
+        music.add_crawled_album(crawled);
+
+        let listening = |song_id, listened_seconds| SongListening {
+            song_id: SongId::new(song_id),
+            plays: 1,
+            listened_seconds,
+        };
+        let loaded = LoadedListening {
+            period: StatsPeriod::Month,
+            songs: vec![
+                listening(1, 100.0),
+                listening(2, 300.0),
+                listening(99, 50.0),
+            ],
+            clock: [[0.0; 24]; 7],
+        };
+
+        let stats = ListeningStats::new(loaded, &music);
+        assert_eq!(stats.total_seconds, 450.0);
+        assert_eq!(
+            stats.top_songs,
+            vec![(SongId::new(2), 300.0), (SongId::new(1), 100.0)]
+        );
+        assert_eq!(stats.top_albums, vec![(album_id, 400.0)]);
+        assert_eq!(
+            stats.top_artists,
+            vec![
+                ("Guest".to_string(), 300.0),
+                ("Fake Artist".to_string(), 100.0)
+            ]
+        );
+
+        assert_eq!(format_listening(45.0 * 60.0), "45 min");
+        assert_eq!(format_listening(200.0 * 60.0), "3 hr 20 min");
+    }
+}
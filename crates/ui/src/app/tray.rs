@@ -0,0 +1,162 @@
+//! A status icon with playback controls, so that closing the window can keep music playing.
+//! NOTE this is only on linux for now, as a StatusNotifierItem
+
+use flume::{Receiver, Sender};
+
+use crate::app::old_unfold::old_unfold;
+
+/// A click in the tray's menu, passed on to the ui
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayAction {
+    TogglePlayback,
+    Next,
+    Previous,
+    /// Hides a shown window, or shows a hidden one
+    ToggleWindow,
+    Quit,
+}
+
+/// What the tray shows; it's only updated when this changes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrayState {
+    /// The current song's title and artist, for the tooltip
+    pub song: Option<String>,
+    pub playing: bool,
+    pub window_hidden: bool,
+}
+
+pub fn tray_subscription(
+    actions: Receiver<TrayAction>,
+) -> iced::Subscription<TrayAction> {
+    struct TraySub;
+
+    old_unfold(
+        std::any::TypeId::of::<TraySub>(),
+        actions,
+        |actions| async move {
+            match actions.recv_async().await {
+                Ok(action) => (Some(action), actions),
+                // NOTE the tray failed to start
+                Err(_) => iced::futures::future::pending().await,
+            }
+        },
+    )
+}
+
+pub use platform::Tray;
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use ksni::blocking::{Handle, TrayMethods};
+    use ksni::menu::{MenuItem, StandardItem};
+
+    use super::*;
+
+    pub struct Tray {
+        handle: Handle<StatusItem>,
+    }
+
+    impl std::fmt::Debug for Tray {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Tray").finish_non_exhaustive()
+        }
+    }
+
+    impl Tray {
+        /// Fails when the desktop doesn't show status icons
+        pub fn spawn(to_ui: Sender<TrayAction>) -> anyhow::Result<Self> {
+            let item = StatusItem { to_ui, state: TrayState::default() };
+            let handle = item.spawn()?;
+
+            Ok(Self { handle })
+        }
+
+        pub fn update(&self, state: TrayState) {
+            self.handle.update(|item| item.state = state);
+        }
+    }
+
+    struct StatusItem {
+        to_ui: Sender<TrayAction>,
+        state: TrayState,
+    }
+
+    impl StatusItem {
+        fn item(label: &str, action: TrayAction) -> MenuItem<Self> {
+            StandardItem {
+                label: label.to_string(),
+                activate: Box::new(move |item: &mut Self| item.send(action)),
+                ..Default::default()
+            }
+            .into()
+        }
+
+        fn send(&self, action: TrayAction) {
+            // NOTE the ui is closing
+            self.to_ui.send(action).ok();
+        }
+    }
+
+    impl ksni::Tray for StatusItem {
+        fn id(&self) -> String {
+            "clef".to_string()
+        }
+
+        fn title(&self) -> String {
+            "Clef".to_string()
+        }
+
+        fn icon_name(&self) -> String {
+            "multimedia-audio-player".to_string()
+        }
+
+        fn tool_tip(&self) -> ksni::ToolTip {
+            ksni::ToolTip {
+                title: self
+                    .state
+                    .song
+                    .clone()
+                    .unwrap_or_else(|| "Clef".to_string()),
+                ..Default::default()
+            }
+        }
+
+        fn activate(&mut self, _x: i32, _y: i32) {
+            self.send(TrayAction::ToggleWindow);
+        }
+
+        fn menu(&self) -> Vec<MenuItem<Self>> {
+            let playback = if self.state.playing { "Pause" } else { "Play" };
+            let window = if self.state.window_hidden {
+                "Show window"
+            } else {
+                "Hide window"
+            };
+
+            vec![
+                Self::item(playback, TrayAction::TogglePlayback),
+                Self::item("Next", TrayAction::Next),
+                Self::item("Previous", TrayAction::Previous),
+                MenuItem::Separator,
+                Self::item(window, TrayAction::ToggleWindow),
+                Self::item("Quit", TrayAction::Quit),
+            ]
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::*;
+
+    #[derive(Debug)]
+    pub struct Tray;
+
+    impl Tray {
+        pub fn spawn(_to_ui: Sender<TrayAction>) -> anyhow::Result<Self> {
+            anyhow::bail!("the tray icon isn't supported on this platform yet")
+        }
+
+        pub fn update(&self, _state: TrayState) {}
+    }
+}
@@ -0,0 +1,178 @@
+//! Draws the audio as it plays, from the samples the player copies into a shared ring;
+//! see clef_audio::dsp::sample_tap
+
+use std::f32::consts::PI;
+
+use iced::widget::canvas::{self, Cursor, Frame, Geometry, Path, Stroke};
+use iced::{Point, Rectangle, Size, Theme};
+use serde::{Deserialize, Serialize};
+
+use clef_audio::dsp::sample_tap::SampleRing;
+
+/// The visualizers to choose from in the settings; saved by name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisualizerStyle {
+    #[default]
+    Off,
+    /// The spectrum, as bars from low to high pitches
+    Bars,
+    /// The latest samples, as a line
+    Waveform,
+}
+
+impl VisualizerStyle {
+    pub const ALL: [VisualizerStyle; 3] = [Self::Off, Self::Bars, Self::Waveform];
+}
+
+impl std::fmt::Display for VisualizerStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Off => "Off",
+            Self::Bars => "Spectrum bars",
+            Self::Waveform => "Waveform",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+const BAR_COUNT: usize = 32;
+const LOWEST_FREQUENCY: f32 = 40.0;
+const HIGHEST_FREQUENCY: f32 = 16_000.0;
+/// Bands quieter than this draw as empty bars, in dB below full scale
+const FLOOR_DB: f32 = -60.0;
+
+/// A canvas program; it's redrawn with every progress update from the player
+#[derive(Debug)]
+pub struct Visualizer<'a> {
+    pub ring: &'a SampleRing,
+    pub style: VisualizerStyle,
+}
+
+impl<Message> canvas::Program<Message> for Visualizer<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        theme: &Theme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let color = theme.palette().primary;
+        let (samples, sample_rate) = self.ring.snapshot();
+
+        match self.style {
+            VisualizerStyle::Off => {}
+
+            VisualizerStyle::Bars => {
+                let bar_width = frame.width() / BAR_COUNT as f32;
+                for (index, level) in band_levels(&samples, sample_rate).enumerate() {
+                    let height = level * frame.height();
+                    frame.fill_rectangle(
+                        Point::new(
+                            index as f32 * bar_width + 1.0,
+                            frame.height() - height,
+                        ),
+                        Size::new((bar_width - 2.0).max(1.0), height),
+                        color,
+                    );
+                }
+            }
+
+            VisualizerStyle::Waveform => {
+                let middle = frame.height() / 2.0;
+                let step = frame.width() / samples.len().saturating_sub(1).max(1) as f32;
+                let waveform = Path::new(|path| {
+                    for (index, sample) in samples.iter().enumerate() {
+                        let point = Point::new(
+                            index as f32 * step,
+                            middle - sample.clamp(-1.0, 1.0) * middle,
+                        );
+                        if index == 0 {
+                            path.move_to(point);
+                        } else {
+                            path.line_to(point);
+                        }
+                    }
+                });
+
+                frame.stroke(
+                    &waveform,
+                    Stroke::default().with_color(color).with_width(1.5),
+                );
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
+
+/// The center of a bar, spaced evenly in pitch
+fn bar_frequency(index: usize) -> f32 {
+    let octaves = (HIGHEST_FREQUENCY / LOWEST_FREQUENCY).log2();
+    LOWEST_FREQUENCY * 2f32.powf(octaves * index as f32 / (BAR_COUNT - 1) as f32)
+}
+
+/// Each bar's loudness, from 0 at the floor to 1 at full scale
+fn band_levels(samples: &[f32], sample_rate: u32) -> impl Iterator<Item = f32> {
+    let length = samples.len() as f32;
+    // NOTE a hann window keeps loud bands from smearing into their neighbors
+    let windowed: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(index, sample)| {
+            sample * (0.5 - 0.5 * (2.0 * PI * index as f32 / length).cos())
+        })
+        .collect();
+
+    (0..BAR_COUNT).map(move |index| {
+        let frequency = bar_frequency(index);
+        if frequency >= sample_rate as f32 / 2.0 {
+            return 0.0;
+        }
+
+        // NOTE this is one bin of a fourier transform (the goertzel algorithm),
+        // which is cheaper than a whole transform for this few bars
+        let coefficient = 2.0 * (2.0 * PI * frequency / sample_rate as f32).cos();
+        let (mut previous, mut before_previous) = (0.0, 0.0);
+        for sample in &windowed {
+            let current = sample + coefficient * previous - before_previous;
+            before_previous = previous;
+            previous = current;
+        }
+        let power = previous * previous + before_previous * before_previous
+            - coefficient * previous * before_previous;
+
+        // NOTE the window halves the amplitude, so a full scale sine comes out as 1
+        let amplitude = 4.0 * power.max(0.0).sqrt() / length;
+        let db = 20.0 * amplitude.max(f32::MIN_POSITIVE).log10();
+        ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clef_audio::dsp::sample_tap::TAP_LENGTH;
+
+    #[test]
+    fn a_tone_fills_the_bar_for_its_pitch() {
+        let sample_rate = 44_100;
+        let frequency = bar_frequency(16);
+        let tone: Vec<f32> = (0..TAP_LENGTH)
+            .map(|index| (2.0 * PI * frequency * index as f32 / sample_rate as f32).sin())
+            .collect();
+
+        let levels: Vec<f32> = band_levels(&tone, sample_rate).collect();
+        assert_eq!(levels.len(), BAR_COUNT);
+        assert!(levels[16] > 0.95, "{levels:?}");
+        assert!(levels[..12].iter().all(|&level| level < 0.5), "{levels:?}");
+        assert!(levels[21..].iter().all(|&level| level < 0.5), "{levels:?}");
+
+        let silence = vec![0.0; TAP_LENGTH];
+        assert!(band_levels(&silence, sample_rate).all(|level| level == 0.0));
+    }
+}
@@ -0,0 +1,112 @@
+//! The seek bar drawn as the song's waveform, from peaks computed in the background;
+//! see clef_audio::waveform
+
+use iced::mouse;
+use iced::widget::canvas::{self, event, Cursor, Event, Frame, Geometry};
+use iced::{Color, Point, Rectangle, Size, Theme};
+
+/// A canvas program that seeks like a slider: dragging previews, and releasing seeks
+#[derive(Debug)]
+pub struct WaveformSeekBar<'a, Message> {
+    pub peaks: &'a [u8],
+    /// How much of the song has played, from 0 to 1
+    pub proportion: f32,
+    pub on_drag: fn(f32) -> Message,
+    pub on_release: Message,
+}
+
+/// Whether the mouse was pressed on the bar and hasn't been released yet
+#[derive(Debug, Default)]
+pub struct Dragging(bool);
+
+impl<Message: Clone> canvas::Program<Message> for WaveformSeekBar<'_, Message> {
+    type State = Dragging;
+
+    fn update(
+        &self,
+        dragging: &mut Self::State,
+        event: Event,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> (event::Status, Option<Message>) {
+        // NOTE a drag keeps following the mouse outside the bar, like a slider
+        let proportion =
+            |position: Point| ((position.x - bounds.x) / bounds.width).clamp(0.0, 1.0);
+
+        let Event::Mouse(mouse_event) = event else {
+            return (event::Status::Ignored, None);
+        };
+
+        match mouse_event {
+            mouse::Event::ButtonPressed(mouse::Button::Left)
+                if cursor.is_over(&bounds) =>
+            {
+                let Some(position) = cursor.position() else {
+                    return (event::Status::Ignored, None);
+                };
+                dragging.0 = true;
+
+                (
+                    event::Status::Captured,
+                    Some((self.on_drag)(proportion(position))),
+                )
+            }
+
+            mouse::Event::CursorMoved { position } if dragging.0 => (
+                event::Status::Captured,
+                Some((self.on_drag)(proportion(position))),
+            ),
+
+            mouse::Event::ButtonReleased(mouse::Button::Left) if dragging.0 => {
+                dragging.0 = false;
+
+                (event::Status::Captured, Some(self.on_release.clone()))
+            }
+
+            _ => (event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(
+        &self,
+        _dragging: &Self::State,
+        theme: &Theme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        let palette = theme.palette();
+        let unplayed = Color { a: 0.3, ..palette.text };
+
+        let bar_width = frame.width() / self.peaks.len().max(1) as f32;
+        let middle = frame.height() / 2.0;
+        for (index, peak) in self.peaks.iter().enumerate() {
+            let x = index as f32 * bar_width;
+            // NOTE silent spans still show, so that the bar reads as one line
+            let half_height = (f32::from(*peak) / 255.0 * middle).max(1.0);
+            let played = (x + bar_width / 2.0) / frame.width() <= self.proportion;
+            let color = if played { palette.primary } else { unplayed };
+
+            frame.fill_rectangle(
+                Point::new(x, middle - half_height),
+                Size::new(bar_width.max(1.0), half_height * 2.0),
+                color,
+            );
+        }
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(
+        &self,
+        dragging: &Self::State,
+        bounds: Rectangle,
+        cursor: Cursor,
+    ) -> mouse::Interaction {
+        if dragging.0 || cursor.is_over(&bounds) {
+            mouse::Interaction::Pointer
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
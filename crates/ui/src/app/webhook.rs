@@ -0,0 +1,161 @@
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use flume::Receiver;
+use log::{error, info};
+use serde::Serialize;
+
+/// How many times to send an event before giving up on it
+const ATTEMPTS: u32 = 3;
+/// Doubled after each failed attempt
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// For the whole request; a slow endpoint shouldn't hold up the events behind it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A change in playback, POSTed as json to the configured url
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PlaybackEvent {
+    /// A song started, or resumed after a pause
+    NowPlaying {
+        song: WebhookSong,
+    },
+    Paused {
+        song: WebhookSong,
+    },
+    Stopped,
+    /// Sent from the settings, to check the url
+    Test,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebhookSong {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration_seconds: i64,
+}
+
+#[derive(Debug)]
+pub struct WebhookRequest {
+    pub url: String,
+    pub event: PlaybackEvent,
+    /// When the event happened, from unix_timestamp; a retry or a backed up
+    /// thread sends it later
+    pub timestamp: u64,
+}
+
+/// The json body, with when the event happened rather than when it was sent
+#[derive(Debug, Serialize)]
+struct WebhookBody<'a> {
+    #[serde(flatten)]
+    event: &'a PlaybackEvent,
+    /// Seconds since the unix epoch
+    timestamp: u64,
+}
+
+/// Sends events in order on their own thread, so that retries don't block the ui
+pub fn spawn_webhook_sender(inbox: Receiver<WebhookRequest>) -> std::io::Result<()> {
+    thread::Builder::new()
+        .name("webhook".to_string())
+        .spawn(move || {
+            for request in inbox.iter() {
+                let body = body_json(&request.event, request.timestamp);
+
+                let mut delay = FIRST_RETRY_DELAY;
+                for attempt in 1..=ATTEMPTS {
+                    let result = post(&request.url, &body);
+                    let Err(e) = result else {
+                        break;
+                    };
+
+                    // NOTE a newer event makes this one stale, ie now playing a skipped song
+                    if !e.retryable || attempt == ATTEMPTS || !inbox.is_empty() {
+                        error!("failed to send playback webhook: {}", e.message);
+                        break;
+                    }
+
+                    info!("retrying playback webhook after error: {}", e.message);
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Sends one test event without retrying; the error is for display
+pub async fn send_test(url: String) -> Result<(), String> {
+    let body = body_json(&PlaybackEvent::Test, unix_timestamp());
+    post(&url, &body).map_err(|e| e.message)
+}
+
+/// Seconds since the unix epoch, for a request's timestamp
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub fn body_json(event: &PlaybackEvent, timestamp: u64) -> String {
+    serde_json::to_string(&WebhookBody { event, timestamp })
+        .expect("playback events serialize")
+}
+
+#[derive(Debug)]
+struct PostError {
+    message: String,
+    /// Network errors and server errors might not happen again;
+    /// a client error means the request itself is wrong
+    retryable: bool,
+}
+
+fn post(url: &str, body: &str) -> Result<(), PostError> {
+    let response = ureq::post(url)
+        .timeout(REQUEST_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(body);
+
+    match response {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, _)) => Err(PostError {
+            message: format!("{url} responded with status {status}"),
+            retryable: status >= 500,
+        }),
+        Err(ureq::Error::Transport(e)) => Err(PostError {
+            message: e.to_string(),
+            retryable: true,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_serialize_with_a_tag_and_timestamp() {
+        let event = PlaybackEvent::NowPlaying {
+            song: WebhookSong {
+                title: "Teardrop".to_string(),
+                artist: Some("Massive Attack".to_string()),
+                album: None,
+                duration_seconds: 330,
+            },
+        };
+
+        let timestamp = 1_690_000_000;
+        let json: serde_json::Value =
+            serde_json::from_str(&body_json(&event, timestamp)).unwrap();
+        assert_eq!(json["event"], "now_playing");
+        assert_eq!(json["song"]["title"], "Teardrop");
+        assert_eq!(json["song"]["album"], serde_json::Value::Null);
+        assert_eq!(json["timestamp"], timestamp);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&body_json(&PlaybackEvent::Stopped, timestamp)).unwrap();
+        assert_eq!(json["event"], "stopped");
+    }
+}
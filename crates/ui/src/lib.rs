@@ -6,6 +6,13 @@ pub mod app;
 pub mod icon;
 pub mod setup;
 
+pub use app::crawler::{scan_library, LibraryExtensions, ScanSummary};
+pub use app::instance::{claim_instance, Handoff, InstanceClaim, SongLink};
+pub use app::playlist_file::{
+    is_m3u, is_playlist_file, m3u8_file, read_playlist, to_m3u, PlaylistEntry,
+    PlaylistExport, PlaylistSongs,
+};
+pub use app::settings::{SettingsFile, SETTINGS_FILE_NAME};
 pub use app::Config;
 pub use app::Flags;
 
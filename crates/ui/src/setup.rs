@@ -9,6 +9,8 @@ pub fn launch(flags: Flags) -> anyhow::Result<()> {
         icon: crate::icon::get_icon(),
         ..Default::default()
     };
+    // NOTE the window may close to the tray instead; see WindowEvent::CloseRequested
+    settings.exit_on_close_request = false;
 
     App::run(settings)?;
 
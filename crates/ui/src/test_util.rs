@@ -4,7 +4,7 @@ use camino::Utf8PathBuf;
 
 use clef_db::queries::*;
 
-use crate::app::crawler::CrawledAlbum;
+use crate::app::crawler::{AlbumChanges, CrawledAlbum};
 
 pub fn fake_album() -> CrawledAlbum {
     let album_id = AlbumId::new(1);
@@ -13,9 +13,17 @@ pub fn fake_album() -> CrawledAlbum {
         directory: Utf8PathBuf::from_str("Album Dir").unwrap(),
         title: Some("Album Title".to_string()),
         artist: Some("Fake Artist".to_string()),
+        tagged_title: Some("Album Title".to_string()),
+        tagged_artist: Some("Fake Artist".to_string()),
         release_date: None,
         original_art: None,
         resized_art: None,
+        album_gain: None,
+        album_peak: None,
+        library_root: None,
+        total_seconds: 500,
+        musicbrainz_release_id: None,
+        notes: None,
     };
 
     let songs = vec![
@@ -26,7 +34,13 @@ pub fn fake_album() -> CrawledAlbum {
         fake_song(5, "Fifth", album_id),
     ];
 
-    CrawledAlbum { album, songs, cached_art: None }
+    CrawledAlbum {
+        album,
+        songs,
+        cached_art: None,
+        placeholder_color: None,
+        changes: AlbumChanges::UNCHANGED,
+    }
 }
 
 pub fn fake_song(number: i32, title: &str, album_id: AlbumId) -> Song {
@@ -37,6 +51,15 @@ pub fn fake_song(number: i32, title: &str, album_id: AlbumId) -> Song {
         title: Some(title.to_string()),
         artist: Some("Fake Artist".to_string()),
         track_number: Some(number),
+        disc_number: None,
         total_seconds: 100,
+        track_gain: None,
+        track_peak: None,
+        favorite: false,
+        genre: None,
+        track_total: None,
+        bpm: None,
+        initial_key: None,
+        unplayable: false,
     }
 }
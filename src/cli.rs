@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+
+use clef_audio::gapless::{self, ExpectedGap};
+use clef_db::queries::{self, AlbumId, Song, SongId};
+use clef_db::SqlitePool;
+use clef_ui::{Config, PlaylistExport, PlaylistSongs, SongLink};
+
+use crate::library_data;
+
+/// Library management commands that run without launching the ui
+#[derive(Debug, Clone, PartialEq, Eq, clap::Subcommand)]
+pub enum Subcommand {
+    /// Crawl the music directories into the db
+    Scan,
+    /// Print library totals from the db
+    Stats,
+    /// Check that the files in the db still exist
+    Verify,
+    /// Warn about live albums and dj mixes that won't play gaplessly
+    Gaps,
+    /// Write favorites, play history, and playlists to a file
+    Export { file: Utf8PathBuf },
+    /// Add an exported file's favorites, plays, and playlists to this library
+    Import { file: Utf8PathBuf },
+    /// Write each playlist and smart playlist to an .m3u8 file in a folder
+    ExportPlaylists { folder: Utf8PathBuf },
+}
+
+/// Everything given on the command line
+#[derive(Debug, Default)]
+pub struct Args {
+    /// None when launching the ui
+    pub subcommand: Option<Subcommand>,
+    /// Print debug logs; see logging
+    pub debug: bool,
+    /// Replaces the configured music directories; can be given more than once
+    pub music_dirs: Vec<Utf8PathBuf>,
+    pub db_path: Option<Utf8PathBuf>,
+    /// Scan the library before opening the window
+    pub rescan: bool,
+    /// A song file, or a directory of songs, to play once the window opens
+    pub play: Option<Utf8PathBuf>,
+    /// A song file to play with the rest of its directory, even from outside the library;
+    /// this is how the file manager's 'Open With' launches
+    pub open: Option<Utf8PathBuf>,
+    /// A moment in a song to play, from a clef:// link opened by another app
+    pub link: Option<SongLink>,
+}
+
+/// The command line as clap sees it; see Args for the parsed result
+#[derive(Debug, Parser)]
+#[command(name = "clef", version, about = "A music player for a local library")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// A song file to play with the rest of its directory, or a clef://play link
+    #[arg(value_name = "FILE_OR_LINK", value_parser = LaunchTarget::parse)]
+    target: Option<LaunchTarget>,
+
+    /// Print debug logs
+    #[arg(long, global = true)]
+    debug: bool,
+
+    /// Use this music directory instead of the configured ones; can be given more than once
+    #[arg(long = "music-dir", value_name = "PATH", global = true)]
+    music_dirs: Vec<Utf8PathBuf>,
+
+    /// Use this db instead of the default one
+    #[arg(long = "db", value_name = "PATH", global = true)]
+    db_path: Option<Utf8PathBuf>,
+
+    /// Scan the library before opening the window
+    #[arg(long, global = true)]
+    rescan: bool,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Play a song file, or a directory of songs, once the window opens
+    Play { path: Utf8PathBuf },
+    #[command(flatten)]
+    Library(Subcommand),
+}
+
+#[derive(Debug, Clone)]
+enum LaunchTarget {
+    File(Utf8PathBuf),
+    Link(SongLink),
+}
+
+impl LaunchTarget {
+    fn parse(arg: &str) -> Result<Self, String> {
+        if arg.starts_with("clef://") {
+            return SongLink::parse(arg)
+                .map(Self::Link)
+                .ok_or_else(|| "invalid link".to_string());
+        }
+
+        match Utf8Path::new(arg).is_file() {
+            true => Ok(Self::File(arg.into())),
+            false => Err("not a subcommand, file, or clef:// link".to_string()),
+        }
+    }
+}
+
+/// Exits with a usage error, or prints help or the version, like clap does
+pub fn parse_args() -> Args {
+    parse_args_from(std::env::args_os())
+}
+
+fn parse_args_from<I, T>(command_line: I) -> Args
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let cli = Cli::parse_from(command_line);
+
+    let mut args = Args {
+        debug: cli.debug,
+        music_dirs: cli.music_dirs,
+        db_path: cli.db_path,
+        rescan: cli.rescan,
+        ..Args::default()
+    };
+
+    // NOTE clap can't mark a positional as conflicting with a subcommand
+    // without it also conflicting with the global options
+    match (cli.command, cli.target) {
+        (Some(_), Some(_)) => Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "a file or link can't be given with a subcommand",
+            )
+            .exit(),
+        (Some(Command::Play { path }), None) => args.play = Some(path),
+        (Some(Command::Library(subcommand)), None) => args.subcommand = Some(subcommand),
+        (None, Some(LaunchTarget::File(file))) => args.open = Some(file),
+        (None, Some(LaunchTarget::Link(link))) => args.link = Some(link),
+        (None, None) => {}
+    }
+
+    args
+}
+
+/// Applies the paths given on the command line over the config file and environment
+pub fn apply_to_config(args: &Args, config: &mut Config) -> anyhow::Result<()> {
+    if !args.music_dirs.is_empty() {
+        let music_dirs: anyhow::Result<Vec<Utf8PathBuf>> =
+            args.music_dirs.iter().map(|dir| absolute(dir)).collect();
+        config.audio_directories = music_dirs?;
+    }
+
+    if let Some(db_path) = &args.db_path {
+        config.db_path = absolute(db_path)?;
+    }
+
+    Ok(())
+}
+
+/// The library's songs at a path; either one file, or everything under a directory
+pub fn songs_to_play(db: &SqlitePool, path: &Utf8Path) -> anyhow::Result<Vec<SongId>> {
+    let path = absolute(path)?;
+    let mut conn = db.get().context("checking out db connection")?;
+
+    let song_ids: Vec<SongId> = queries::all_songs(&mut conn)?
+        .into_iter()
+        .filter(|song| song.file.starts_with(&path))
+        .map(|song| song.id)
+        .collect();
+
+    if song_ids.is_empty() {
+        anyhow::bail!("no songs in the library at {path}; try --rescan or --music-dir");
+    }
+
+    Ok(song_ids)
+}
+
+/// NOTE the crawler saves paths under the music directories as they're given,
+/// so relative ones would only work from the same working directory
+pub fn absolute(path: &Utf8Path) -> anyhow::Result<Utf8PathBuf> {
+    if path.is_absolute() {
+        return Ok(path.to_owned());
+    }
+
+    let current_dir = std::env::current_dir().context("no working directory")?;
+    let current_dir =
+        Utf8PathBuf::try_from(current_dir).context("non-utf8 working directory")?;
+
+    Ok(current_dir.join(path))
+}
+
+pub fn run(
+    subcommand: Subcommand,
+    config: &Config,
+    db: &SqlitePool,
+) -> anyhow::Result<()> {
+    match subcommand {
+        Subcommand::Scan => scan(config, db),
+        Subcommand::Stats => stats(db),
+        Subcommand::Verify => verify(db),
+        Subcommand::Gaps => gaps(db),
+        Subcommand::Export { file } => export(db, &file),
+        Subcommand::Import { file } => import(config, db, &file),
+        Subcommand::ExportPlaylists { folder } => export_playlists(db, &folder),
+    }
+}
+
+fn scan(config: &Config, db: &SqlitePool) -> anyhow::Result<()> {
+    for audio_directory in &config.audio_directories {
+        println!("scanning {audio_directory}");
+    }
+
+    let summary = clef_ui::scan_library(config, db)?;
+
+    println!(
+        "scanned {} albums with {} songs ({} directories skipped)",
+        summary.albums, summary.songs, summary.skipped_directories
+    );
+    if summary.playlists > 0 {
+        println!("found {} playlist files", summary.playlists);
+    }
+    for conflict in &summary.playlist_conflicts {
+        println!("playlist changed on both sides; kept the folder's copy as {conflict}");
+    }
+
+    Ok(())
+}
+
+fn stats(db: &SqlitePool) -> anyhow::Result<()> {
+    let mut conn = db.get().context("checking out db connection")?;
+    let albums = queries::all_albums(&mut conn)?;
+    let songs = queries::all_songs(&mut conn)?;
+
+    let total_seconds: i64 = songs.iter().map(|s| s.total_seconds).sum();
+    let hours = total_seconds / 3600;
+    let minutes = (total_seconds % 3600) / 60;
+    let without_art = albums.iter().filter(|a| a.resized_art.is_none()).count();
+
+    let listened_seconds = queries::total_listened_seconds(&mut conn)? as i64;
+    let listened_hours = listened_seconds / 3600;
+    let listened_minutes = (listened_seconds % 3600) / 60;
+
+    println!("albums: {}", albums.len());
+    println!("songs: {}", songs.len());
+    println!("total length: {hours}h {minutes}m");
+    println!("time listened: {listened_hours}h {listened_minutes}m");
+    println!("albums without resized art: {without_art}");
+
+    Ok(())
+}
+
+fn verify(db: &SqlitePool) -> anyhow::Result<()> {
+    let mut conn = db.get().context("checking out db connection")?;
+    let albums = queries::all_albums(&mut conn)?;
+    let songs = queries::all_songs(&mut conn)?;
+
+    let mut missing = 0;
+    let mut report_missing = |kind: &str, path: &Utf8Path| {
+        println!("missing {kind}: {path}");
+        missing += 1;
+    };
+
+    for album in &albums {
+        if !album.directory.is_dir() {
+            report_missing("album directory", &album.directory);
+        }
+
+        if let Some(resized_art) = &album.resized_art {
+            if !resized_art.is_file() {
+                report_missing("resized art", resized_art);
+            }
+        }
+    }
+
+    for song in &songs {
+        if !song.file.is_file() {
+            report_missing("song", &song.file);
+        }
+    }
+
+    if missing > 0 {
+        anyhow::bail!("{missing} missing files; run 'clef scan' to remove them");
+    }
+
+    println!("verified {} albums and {} songs", albums.len(), songs.len());
+
+    Ok(())
+}
+
+fn gaps(db: &SqlitePool) -> anyhow::Result<()> {
+    let mut conn = db.get().context("checking out db connection")?;
+    let albums = queries::all_albums(&mut conn)?;
+    let songs = queries::all_songs(&mut conn)?;
+
+    let mut songs_by_album: HashMap<AlbumId, Vec<&Song>> = HashMap::new();
+    for song in &songs {
+        songs_by_album.entry(song.album_id).or_default().push(song);
+    }
+
+    let mut checked = 0;
+    let mut with_gaps = 0;
+    for album in &albums {
+        let album_songs = songs_by_album
+            .get(&album.id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let genres = album_songs.iter().filter_map(|song| song.genre.as_deref());
+        if !gapless::is_gapless_album(album.title.as_deref(), genres) {
+            continue;
+        }
+        checked += 1;
+
+        let gaps: Vec<(&Song, ExpectedGap)> = album_songs
+            .iter()
+            .filter_map(|song| Some((*song, gapless::expected_gap(&song.file)?)))
+            .collect();
+        if gaps.is_empty() {
+            continue;
+        }
+        with_gaps += 1;
+
+        println!(
+            "gaps expected in {} ({}): {} of {} songs",
+            album.title.as_deref().unwrap_or("untitled"),
+            album.artist.as_deref().unwrap_or("unknown artist"),
+            gaps.len(),
+            album_songs.len(),
+        );
+        for (song, gap) in gaps {
+            println!("  {}: {gap}", song.file);
+        }
+    }
+
+    if with_gaps > 0 {
+        println!(
+            "re-encode these from a lossless source (ie as flac, or mp3 with LAME), \
+             or play them with the Fade Out/In transition to soften the gaps"
+        );
+    }
+    println!("checked {checked} live or mixed albums; {with_gaps} will have gaps");
+
+    Ok(())
+}
+
+fn export(db: &SqlitePool, path: &Utf8Path) -> anyhow::Result<()> {
+    let summary = library_data::export(db, path)?;
+
+    println!(
+        "exported {} favorites, {} plays, {} playlists, and {} smart playlists to {path}",
+        summary.favorites, summary.plays, summary.playlists, summary.smart_playlists
+    );
+
+    Ok(())
+}
+
+fn import(config: &Config, db: &SqlitePool, path: &Utf8Path) -> anyhow::Result<()> {
+    let summary = library_data::import(db, path, &config.audio_directories)?;
+
+    println!(
+        "imported {} favorites, {} plays, {} playlists, and {} smart playlists from {path}",
+        summary.favorites, summary.plays, summary.playlists, summary.smart_playlists
+    );
+    if summary.already_present > 0 {
+        println!("skipped {} already in the library", summary.already_present);
+    }
+    if summary.unmatched > 0 {
+        println!(
+            "skipped {} for songs not found by path or tags; \
+             try 'clef scan' first if the music was just copied over",
+            summary.unmatched
+        );
+    }
+
+    Ok(())
+}
+
+fn export_playlists(db: &SqlitePool, folder: &Utf8Path) -> anyhow::Result<()> {
+    if !folder.is_dir() {
+        anyhow::bail!("{folder} isn't a folder");
+    }
+
+    let mut conn = db.get().context("checking out db connection")?;
+    let songs: HashMap<SongId, Song> = queries::all_songs(&mut conn)?
+        .into_iter()
+        .map(|song| (song.id, song))
+        .collect();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or_default();
+
+    let mut exports = Vec::new();
+    for playlist in queries::all_playlists(&mut conn)? {
+        let export = PlaylistExport {
+            name: playlist.name,
+            songs: PlaylistSongs::Listed(playlist.songs),
+            destination: folder.to_owned(),
+        };
+        exports.push((export, Vec::new()));
+    }
+    for smart_playlist in queries::all_smart_playlists(&mut conn)? {
+        let matching =
+            queries::smart_playlist_songs(&mut conn, &smart_playlist.rules, now)?;
+        let export = PlaylistExport {
+            name: smart_playlist.name,
+            songs: PlaylistSongs::Matching(smart_playlist.rules),
+            destination: folder.to_owned(),
+        };
+        exports.push((export, matching));
+    }
+
+    for (export, matching) in &exports {
+        let file = export
+            .write(matching, &songs)
+            .with_context(|| format!("writing {}", export.file()))?;
+        println!("wrote {file}");
+    }
+    println!("exported {} playlists to {folder}", exports.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_playlists_takes_a_folder_and_the_global_options() {
+        let args =
+            parse_args_from(["clef", "export-playlists", "out", "--db", "test.db"]);
+
+        assert_eq!(
+            args.subcommand,
+            Some(Subcommand::ExportPlaylists { folder: "out".into() })
+        );
+        assert_eq!(args.db_path, Some("test.db".into()));
+        assert!(args.play.is_none());
+        assert!(args.open.is_none());
+    }
+}
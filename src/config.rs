@@ -2,30 +2,113 @@ use anyhow::Context;
 use camino::{Utf8Path, Utf8PathBuf};
 use directories::{ProjectDirs, UserDirs};
 
-use clef_ui::Config;
+use clef_audio::replay_gain::{ReplayGainMode, ReplayGainSettings};
+use clef_ui::{Config, LibraryExtensions, SettingsFile, SETTINGS_FILE_NAME};
 
 const IMAGES_DIR_NAME: &str = "resized_images";
 
+// NOTE each of these takes precedence over the same setting in clef.toml
+
+/// Music directories to crawl, separated like PATH; defaults to the platform's music folder
+const MUSIC_DIRS_VAR: &str = "CLEF_MUSIC_DIRS";
+/// 'track', 'album', or 'off'; defaults to track
+const REPLAY_GAIN_MODE_VAR: &str = "CLEF_REPLAYGAIN";
+/// A pre-amp in dB, added to the tagged gain; defaults to 0
+const REPLAY_GAIN_PREAMP_VAR: &str = "CLEF_REPLAYGAIN_PREAMP";
+/// Megabytes of decoded album art to keep in memory; defaults to 64
+const ART_CACHE_VAR: &str = "CLEF_ART_CACHE_MB";
+const DEFAULT_ART_CACHE_MB: usize = 64;
+/// Comma separated file extensions to crawl as songs, replacing the defaults
+const AUDIO_EXTENSIONS_VAR: &str = "CLEF_AUDIO_EXTENSIONS";
+/// Comma separated file extensions to crawl as cover art, replacing jpg and png
+const IMAGE_EXTENSIONS_VAR: &str = "CLEF_IMAGE_EXTENSIONS";
+
 pub fn init() -> anyhow::Result<Config> {
     let local_data_directory = local_data_dir()?;
     std::fs::create_dir_all(&local_data_directory).ok();
 
-    let audio_directory = audio_dir()?;
-    std::fs::create_dir(&audio_directory).ok();
+    let settings_path = settings_path()?;
+    let settings = SettingsFile::load(&settings_path)?;
+
+    let audio_directories = audio_dirs(&settings)?;
 
     let db_path = db_path()?;
 
     let resized_images_directory = local_data_directory.join(IMAGES_DIR_NAME);
     std::fs::create_dir(&resized_images_directory).ok();
 
+    let replay_gain = replay_gain_settings(&settings)?;
+
+    let art_cache_mb: usize = match std::env::var(ART_CACHE_VAR) {
+        Ok(megabytes) => megabytes
+            .parse()
+            .with_context(|| format!("invalid {ART_CACHE_VAR}: {megabytes}"))?,
+        Err(_) => settings.art_cache_mb.unwrap_or(DEFAULT_ART_CACHE_MB),
+    };
+
+    let extensions = library_extensions(&settings)?;
+
     Ok(Config {
         local_data_directory,
-        audio_directory,
+        audio_directories,
         db_path,
         resized_images_directory,
+        replay_gain,
+        art_cache_bytes: art_cache_mb * 1024 * 1024,
+        extensions,
+        settings_path,
+        settings,
     })
 }
 
+fn library_extensions(settings: &SettingsFile) -> anyhow::Result<LibraryExtensions> {
+    let list = |var: &str| {
+        std::env::var(var)
+            .ok()
+            .map(|list| list.split(',').map(str::to_string).collect())
+    };
+
+    let mut extensions = LibraryExtensions::default();
+    if let Some(audio) = list(AUDIO_EXTENSIONS_VAR) {
+        extensions = extensions
+            .with_audio(audio)
+            .with_context(|| format!("invalid {AUDIO_EXTENSIONS_VAR}"))?;
+    } else if let Some(audio) = &settings.audio_extensions {
+        extensions = extensions
+            .with_audio(audio.clone())
+            .context("invalid audio_extensions in settings")?;
+    }
+    if let Some(image) = list(IMAGE_EXTENSIONS_VAR) {
+        extensions = extensions
+            .with_image(image)
+            .with_context(|| format!("invalid {IMAGE_EXTENSIONS_VAR}"))?;
+    } else if let Some(image) = &settings.image_extensions {
+        extensions = extensions
+            .with_image(image.clone())
+            .context("invalid image_extensions in settings")?;
+    }
+
+    Ok(extensions)
+}
+
+fn replay_gain_settings(settings: &SettingsFile) -> anyhow::Result<ReplayGainSettings> {
+    let from_file = settings.replay_gain();
+
+    let mode: ReplayGainMode = match std::env::var(REPLAY_GAIN_MODE_VAR) {
+        Ok(mode) => mode.parse()?,
+        Err(_) => from_file.mode,
+    };
+
+    let preamp_db: f32 = match std::env::var(REPLAY_GAIN_PREAMP_VAR) {
+        Ok(preamp) => preamp
+            .parse()
+            .with_context(|| format!("invalid {REPLAY_GAIN_PREAMP_VAR}: {preamp}"))?,
+        Err(_) => from_file.preamp_db,
+    };
+
+    Ok(ReplayGainSettings { mode, preamp_db })
+}
+
 fn local_data_dir() -> anyhow::Result<Utf8PathBuf> {
     let project_dirs =
         project_dirs().context("no project directory path for app found")?;
@@ -37,6 +120,38 @@ fn local_data_dir() -> anyhow::Result<Utf8PathBuf> {
     Ok(local_data.to_owned())
 }
 
+fn audio_dirs(settings: &SettingsFile) -> anyhow::Result<Vec<Utf8PathBuf>> {
+    let Some(music_dirs) = std::env::var_os(MUSIC_DIRS_VAR) else {
+        if !settings.music_directories.is_empty() {
+            return Ok(settings.music_directories.clone());
+        }
+
+        let audio_directory = audio_dir()?;
+        std::fs::create_dir(&audio_directory).ok();
+
+        return Ok(vec![audio_directory]);
+    };
+
+    let mut audio_directories = Vec::new();
+    for music_dir in std::env::split_paths(&music_dirs) {
+        if music_dir.as_os_str().is_empty() {
+            continue;
+        }
+
+        let music_dir = Utf8PathBuf::try_from(music_dir)
+            .with_context(|| format!("non-utf8 path in {MUSIC_DIRS_VAR}"))?;
+        if !audio_directories.contains(&music_dir) {
+            audio_directories.push(music_dir);
+        }
+    }
+
+    if audio_directories.is_empty() {
+        anyhow::bail!("no directories in {MUSIC_DIRS_VAR}");
+    }
+
+    Ok(audio_directories)
+}
+
 fn audio_dir() -> anyhow::Result<Utf8PathBuf> {
     let user_dirs = UserDirs::new().context("no user directories")?;
     let audio_dir = user_dirs.audio_dir().context("no audio directory")?;
@@ -49,6 +164,17 @@ fn project_dirs() -> Option<ProjectDirs> {
     ProjectDirs::from("", "", "Clef")
 }
 
+fn settings_path() -> anyhow::Result<Utf8PathBuf> {
+    let project_dirs =
+        project_dirs().context("no project directory path for app found")?;
+    let settings_path = project_dirs.config_dir().join(SETTINGS_FILE_NAME);
+    let settings_path: Utf8PathBuf = settings_path
+        .try_into()
+        .context("non-utf8 config directory")?;
+
+    Ok(settings_path)
+}
+
 fn db_path() -> anyhow::Result<Utf8PathBuf> {
     let project_dirs =
         project_dirs().context("no project directory path for app found")?;
@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+use camino::Utf8Path;
+use log::{error, info, warn};
+
+use clef_db::integrity::{self, DatabaseDamage, IntegrityCheck};
+use clef_db::SqlitePool;
+
+/// A quick check runs when the backup is older than this, and refreshes it
+const BACKUP_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Restores the backup if the last launch asked for it, then checks the db:
+/// thoroughly after an unclean shutdown, or quickly when the backup is due.
+/// Both happen before the pool opens the db and migrations write to it;
+/// a db that can't be opened or migrated is damaged too.
+/// NOTE this is for the instance that opens the ui; a handoff or subcommand
+/// shouldn't check a db that's in use
+pub fn on_launch(db_path: &Utf8Path) -> (SqlitePool, Option<DatabaseDamage>) {
+    if integrity::take_restore_request(db_path) {
+        restore_backup(db_path);
+    }
+
+    let unclean = integrity::mark_running(db_path).unwrap_or_else(|e| {
+        error!("failed to mark the db as in use: {e}");
+        false
+    });
+
+    let check = if unclean {
+        warn!("clef didn't close cleanly last time; checking the db");
+        Some(IntegrityCheck::Full)
+    } else if backup_is_due(db_path) {
+        Some(IntegrityCheck::Quick)
+    } else {
+        None
+    };
+
+    let mut problems = match check {
+        Some(check) => check_integrity(db_path, check),
+        None => Vec::new(),
+    };
+
+    let db_pool = match clef_db::create_pool(db_path) {
+        Ok(db_pool) => db_pool,
+        Err(e) => {
+            problems.push(format!("failed to open the db: {e}"));
+            clef_db::create_unchecked_pool(db_path)
+        }
+    };
+
+    // NOTE migrating a damaged db could make it worse
+    if problems.is_empty() {
+        if let Err(e) = clef_db::run_migrations(&db_pool) {
+            problems.push(format!("failed to run migrations: {e}"));
+        }
+    }
+
+    if problems.is_empty() {
+        // NOTE the backup is written after migrating, so that restoring it doesn't have to
+        if check.is_some() {
+            write_backup(&db_pool, db_path);
+        }
+
+        return (db_pool, None);
+    }
+
+    for problem in &problems {
+        error!("db integrity problem: {problem}");
+    }
+
+    let damage = DatabaseDamage {
+        problems,
+        backup_time: integrity::backup_time(db_path),
+    };
+
+    (db_pool, Some(damage))
+}
+
+/// Marks the ui as closed, so that the next launch can skip the full check
+pub fn on_close(db_path: &Utf8Path) {
+    if let Err(e) = integrity::mark_stopped(db_path) {
+        error!("failed to mark the db as closed: {e}");
+    }
+}
+
+fn restore_backup(db_path: &Utf8Path) {
+    let backup_path = integrity::backup_path(db_path);
+    match integrity::restore_backup(db_path) {
+        Ok(()) => info!("restored the db from {backup_path}"),
+        Err(e) => error!("failed to restore the db from {backup_path}: {e}"),
+    }
+}
+
+fn check_integrity(db_path: &Utf8Path, check: IntegrityCheck) -> Vec<String> {
+    let started_at = Instant::now();
+    let problems = match integrity::connect(db_path) {
+        Ok(mut conn) => match integrity::check_integrity(&mut conn, check) {
+            Ok(problems) => problems,
+            // NOTE a badly damaged db can fail the check itself
+            Err(e) => vec![e.to_string()],
+        },
+        Err(e) => vec![format!("failed to open the db: {e}")],
+    };
+    info!("ran {check:?} db check in {:?}", started_at.elapsed());
+
+    problems
+}
+
+fn write_backup(db_pool: &SqlitePool, db_path: &Utf8Path) {
+    let result = db_pool
+        .get()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut conn| Ok(integrity::write_backup(&mut conn, db_path)?));
+
+    if let Err(e) = result {
+        error!("failed to back up the db: {e}");
+    }
+}
+
+fn backup_is_due(db_path: &Utf8Path) -> bool {
+    let age = integrity::backup_time(db_path).and_then(|time| time.elapsed().ok());
+
+    match age {
+        Some(age) => age > BACKUP_INTERVAL,
+        None => true,
+    }
+}
@@ -2,5 +2,8 @@
 #![deny(missing_debug_implementations)]
 #![forbid(unsafe_code)]
 
+pub mod cli;
 pub mod config;
+pub mod db_check;
+pub mod library_data;
 pub mod logging;
@@ -0,0 +1,659 @@
+//! Favorites, play history, and playlists as a portable file,
+//! for moving a library to another machine without copying the db.
+//! Songs are written by path and tags, and found again by path first,
+//! or by their tags when the music lives somewhere else now
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use clef_db::queries::{self, Album, AlbumId, Play, SmartRule, Song, SongId};
+use clef_db::SqlitePool;
+use clef_ui::{
+    is_m3u, is_playlist_file, m3u8_file, read_playlist, to_m3u, PlaylistEntry,
+};
+
+/// Bumped when an older clef couldn't read the file correctly
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LibraryData {
+    version: u32,
+    favorites: Vec<SongRef>,
+    plays: Vec<ExportedPlay>,
+    /// NOTE missing from files exported before playlists were
+    #[serde(default)]
+    playlists: Vec<ExportedPlaylist>,
+    smart_playlists: Vec<ExportedSmartPlaylist>,
+}
+
+/// Enough to find a song again in another library
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SongRef {
+    path: Utf8PathBuf,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+}
+
+impl From<PlaylistEntry> for SongRef {
+    fn from(entry: PlaylistEntry) -> Self {
+        Self {
+            path: entry.path,
+            title: entry.title,
+            artist: entry.artist,
+            album: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedPlay {
+    song: SongRef,
+    started_at: i64,
+    completed_at: Option<i64>,
+    percent_played: f64,
+    listened_seconds: f64,
+}
+
+/// A playlist file from a music directory, with the songs from it that were in the library
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedPlaylist {
+    name: String,
+    file: Utf8PathBuf,
+    songs: Vec<SongRef>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedSmartPlaylist {
+    name: String,
+    rules: Vec<ExportedRule>,
+}
+
+/// A rule by its saved names; see SmartRule::to_saved
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedRule {
+    kind: String,
+    value: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    pub favorites: usize,
+    pub plays: usize,
+    pub playlists: usize,
+    pub smart_playlists: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub favorites: usize,
+    pub plays: usize,
+    pub playlists: usize,
+    pub smart_playlists: usize,
+    /// Entries for songs that couldn't be found in this library
+    pub unmatched: usize,
+    /// Plays and playlists that were already here, ie from an earlier import
+    pub already_present: usize,
+}
+
+/// Writes the library data as json, or just the favorites as a playlist
+/// when the file ends in .m3u or .m3u8
+pub fn export(db: &SqlitePool, path: &Utf8Path) -> anyhow::Result<ExportSummary> {
+    let mut conn = db.get().context("checking out db connection")?;
+    let albums: HashMap<AlbumId, Album> = queries::all_albums(&mut conn)?
+        .into_iter()
+        .map(|album| (album.id, album))
+        .collect();
+    let songs: HashMap<SongId, Song> = queries::all_songs(&mut conn)?
+        .into_iter()
+        .map(|song| (song.id, song))
+        .collect();
+
+    let song_ref = |song: &Song| SongRef {
+        path: song.file.clone(),
+        title: song.title.clone(),
+        artist: song.artist.clone(),
+        album: albums
+            .get(&song.album_id)
+            .and_then(|album| album.title.clone()),
+    };
+
+    let mut favorites: Vec<&Song> = songs.values().filter(|song| song.favorite).collect();
+    favorites.sort_by(|a, b| a.file.cmp(&b.file));
+
+    if is_m3u(path) {
+        std::fs::write(path, to_m3u(favorites.iter().copied()))
+            .with_context(|| format!("writing {path}"))?;
+
+        return Ok(ExportSummary {
+            favorites: favorites.len(),
+            ..ExportSummary::default()
+        });
+    }
+
+    // NOTE plays of songs since removed from the library are left out
+    let plays: Vec<ExportedPlay> = queries::all_plays(&mut conn)?
+        .into_iter()
+        .filter_map(|play| {
+            Some(ExportedPlay {
+                song: song_ref(songs.get(&play.song_id)?),
+                started_at: play.started_at,
+                completed_at: play.completed_at,
+                percent_played: play.percent_played,
+                listened_seconds: play.listened_seconds,
+            })
+        })
+        .collect();
+
+    let playlists: Vec<ExportedPlaylist> = queries::all_playlists(&mut conn)?
+        .into_iter()
+        .map(|playlist| ExportedPlaylist {
+            name: playlist.name,
+            file: playlist.file,
+            songs: playlist
+                .songs
+                .iter()
+                .filter_map(|song_id| Some(song_ref(songs.get(song_id)?)))
+                .collect(),
+        })
+        .collect();
+
+    let smart_playlists: Vec<ExportedSmartPlaylist> =
+        queries::all_smart_playlists(&mut conn)?
+            .into_iter()
+            .map(|playlist| ExportedSmartPlaylist {
+                name: playlist.name,
+                rules: playlist
+                    .rules
+                    .iter()
+                    .map(|rule| {
+                        let (kind, value) = rule.to_saved();
+                        ExportedRule { kind: kind.to_string(), value }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+    let data = LibraryData {
+        version: FORMAT_VERSION,
+        favorites: favorites.iter().map(|song| song_ref(song)).collect(),
+        plays,
+        playlists,
+        smart_playlists,
+    };
+    let json = serde_json::to_string_pretty(&data)?;
+    std::fs::write(path, json).with_context(|| format!("writing {path}"))?;
+
+    Ok(ExportSummary {
+        favorites: data.favorites.len(),
+        plays: data.plays.len(),
+        playlists: data.playlists.len(),
+        smart_playlists: data.smart_playlists.len(),
+    })
+}
+
+/// Adds an exported file to this library; either json from export, or a playlist
+/// file whose songs become favorites. Nothing is removed, and importing
+/// the same file twice doesn't duplicate plays or playlists.
+/// NOTE playlists are written back as files in a music directory,
+/// since a scan forgets playlists without one
+pub fn import(
+    db: &SqlitePool,
+    path: &Utf8Path,
+    music_directories: &[Utf8PathBuf],
+) -> anyhow::Result<ImportSummary> {
+    let data = if is_playlist_file(path) {
+        let entries = read_playlist(path).with_context(|| format!("reading {path}"))?;
+        LibraryData {
+            version: FORMAT_VERSION,
+            favorites: entries.into_iter().map(SongRef::from).collect(),
+            plays: Vec::new(),
+            playlists: Vec::new(),
+            smart_playlists: Vec::new(),
+        }
+    } else {
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+        serde_json::from_str(&contents).with_context(|| format!("parsing {path}"))?
+    };
+    if data.version > FORMAT_VERSION {
+        anyhow::bail!("{path} was exported by a newer version of clef");
+    }
+
+    let mut conn = db.get().context("checking out db connection")?;
+    conn.immediate_transaction(|tx| {
+        let albums = queries::all_albums(tx)?;
+        let songs = queries::all_songs(tx)?;
+        let matcher = SongMatcher::new(&albums, &songs);
+        let mut summary = ImportSummary::default();
+
+        for song_ref in &data.favorites {
+            match matcher.find(song_ref) {
+                Some(song_id) => {
+                    queries::set_song_favorite(tx, song_id, true)?;
+                    summary.favorites += 1;
+                }
+                None => summary.unmatched += 1,
+            }
+        }
+
+        let mut recorded: HashSet<(SongId, i64)> = queries::all_plays(tx)?
+            .into_iter()
+            .map(|play| (play.song_id, play.started_at))
+            .collect();
+        for exported in &data.plays {
+            let Some(song_id) = matcher.find(&exported.song) else {
+                summary.unmatched += 1;
+                continue;
+            };
+            if !recorded.insert((song_id, exported.started_at)) {
+                summary.already_present += 1;
+                continue;
+            }
+
+            let play = Play {
+                song_id,
+                started_at: exported.started_at,
+                completed_at: exported.completed_at,
+                percent_played: exported.percent_played,
+                listened_seconds: exported.listened_seconds,
+            };
+            queries::insert_play(tx, &play)?;
+            summary.plays += 1;
+        }
+
+        let songs_by_id: HashMap<SongId, &Song> =
+            songs.iter().map(|song| (song.id, song)).collect();
+        let mut playlist_names: HashSet<String> = queries::all_playlists(tx)?
+            .into_iter()
+            .map(|playlist| playlist.name)
+            .collect();
+        for playlist in &data.playlists {
+            if !playlist_names.insert(playlist.name.clone()) {
+                summary.already_present += 1;
+                continue;
+            }
+            let Some(file) = playlist_file(playlist, music_directories) else {
+                warn!(
+                    "no music directory to write the playlist {} to",
+                    playlist.name
+                );
+                summary.unmatched += 1;
+                continue;
+            };
+            // NOTE the next scan reads it, ie when the music was copied with its playlists
+            if file.exists() {
+                summary.already_present += 1;
+                continue;
+            }
+
+            let song_ids: Vec<SongId> = playlist
+                .songs
+                .iter()
+                .filter_map(|song_ref| matcher.find(song_ref))
+                .collect();
+            summary.unmatched += playlist.songs.len() - song_ids.len();
+
+            let listed = song_ids
+                .iter()
+                .filter_map(|song_id| songs_by_id.get(song_id));
+            std::fs::write(&file, to_m3u(listed.copied()))
+                .with_context(|| format!("writing {file}"))?;
+            queries::save_playlist(tx, &file, &playlist.name, &song_ids)?;
+            summary.playlists += 1;
+        }
+
+        let mut playlist_names: HashSet<String> = queries::all_smart_playlists(tx)?
+            .into_iter()
+            .map(|playlist| playlist.name)
+            .collect();
+        for playlist in &data.smart_playlists {
+            if !playlist_names.insert(playlist.name.clone()) {
+                summary.already_present += 1;
+                continue;
+            }
+
+            let rules: Vec<SmartRule> = playlist
+                .rules
+                .iter()
+                .filter_map(|rule| {
+                    let parsed = SmartRule::from_saved(&rule.kind, &rule.value);
+                    if parsed.is_none() {
+                        warn!(
+                            "skipping unknown rule in {}: {}",
+                            playlist.name, rule.kind
+                        );
+                    }
+                    parsed
+                })
+                .collect();
+            queries::create_smart_playlist(tx, &playlist.name, &rules)?;
+            summary.smart_playlists += 1;
+        }
+
+        Ok::<_, anyhow::Error>(summary)
+    })
+}
+
+/// The playlist's own file when it's in one of the music directories,
+/// or else a new file in the first one
+fn playlist_file(
+    playlist: &ExportedPlaylist,
+    music_directories: &[Utf8PathBuf],
+) -> Option<Utf8PathBuf> {
+    let in_library = music_directories
+        .iter()
+        .any(|directory| playlist.file.starts_with(directory));
+    if in_library && playlist.file.parent().is_some_and(Utf8Path::is_dir) {
+        return Some(playlist.file.clone());
+    }
+
+    let directory = music_directories
+        .iter()
+        .find(|directory| directory.is_dir())?;
+    Some(m3u8_file(directory, &playlist.name))
+}
+
+/// Finds exported songs by path, then by title, artist, and album.
+/// Tags shared by more than one song in this library don't match either
+struct SongMatcher<'a> {
+    by_path: HashMap<&'a Utf8Path, SongId>,
+    by_tags: HashMap<TagKey, Option<SongId>>,
+    /// For entries without an album, ie from a playlist file
+    by_title_and_artist: HashMap<TagKey, Option<SongId>>,
+}
+
+/// Lowercased title, artist, and album
+type TagKey = (String, Option<String>, Option<String>);
+
+impl<'a> SongMatcher<'a> {
+    fn new(albums: &[Album], songs: &'a [Song]) -> Self {
+        let album_titles: HashMap<AlbumId, &str> = albums
+            .iter()
+            .filter_map(|album| Some((album.id, album.title.as_deref()?)))
+            .collect();
+
+        let mut matcher = Self {
+            by_path: HashMap::new(),
+            by_tags: HashMap::new(),
+            by_title_and_artist: HashMap::new(),
+        };
+        for song in songs {
+            matcher.by_path.insert(&song.file, song.id);
+
+            let album = album_titles.get(&song.album_id).copied();
+            if let Some(key) =
+                tag_key(song.title.as_deref(), song.artist.as_deref(), album)
+            {
+                insert_unique(&mut matcher.by_tags, key, song.id);
+            }
+            if let Some(key) =
+                tag_key(song.title.as_deref(), song.artist.as_deref(), None)
+            {
+                insert_unique(&mut matcher.by_title_and_artist, key, song.id);
+            }
+        }
+
+        matcher
+    }
+
+    fn find(&self, song: &SongRef) -> Option<SongId> {
+        if let Some(song_id) = self.by_path.get(song.path.as_path()) {
+            return Some(*song_id);
+        }
+
+        let key = tag_key(
+            song.title.as_deref(),
+            song.artist.as_deref(),
+            song.album.as_deref(),
+        )?;
+        let by_tags = match song.album {
+            Some(_) => &self.by_tags,
+            None => &self.by_title_and_artist,
+        };
+
+        by_tags.get(&key).copied().flatten()
+    }
+}
+
+/// None for untitled songs, which are too likely to match the wrong file
+fn tag_key(
+    title: Option<&str>,
+    artist: Option<&str>,
+    album: Option<&str>,
+) -> Option<TagKey> {
+    let title = title?.trim();
+    if title.is_empty() {
+        return None;
+    }
+    let normalize = |tag: &str| tag.trim().to_lowercase();
+
+    Some((
+        normalize(title),
+        artist.map(normalize),
+        album.map(normalize),
+    ))
+}
+
+/// Marks tags seen more than once as ambiguous, with None
+fn insert_unique(
+    map: &mut HashMap<TagKey, Option<SongId>>,
+    key: TagKey,
+    song_id: SongId,
+) {
+    map.entry(key)
+        .and_modify(|existing| *existing = None)
+        .or_insert(Some(song_id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clef_db::queries::{NewAlbum, NewSong};
+    use clef_db::{create_pool, run_migrations};
+
+    /// A db in a temp directory, with its music directory
+    fn temp_library(name: &str) -> (Utf8PathBuf, Utf8PathBuf, SqlitePool) {
+        let root = std::env::temp_dir()
+            .join(format!("clef-library-data-{name}-{}", std::process::id()));
+        let root = Utf8PathBuf::try_from(root).unwrap();
+        let music = root.join("Music");
+        std::fs::create_dir_all(&music).unwrap();
+        let pool = create_pool(&root.join("db.sqlite")).unwrap();
+        run_migrations(&pool).unwrap();
+
+        (root, music, pool)
+    }
+
+    /// An album titled "Album" by "Artist", with a song for each title
+    fn add_album(
+        pool: &SqlitePool,
+        directory: &Utf8Path,
+        titles: &[&str],
+    ) -> Vec<SongId> {
+        let mut conn = pool.get().unwrap();
+        let new_album = NewAlbum {
+            directory: directory.to_owned(),
+            title: Some("Album".to_string()),
+            artist: Some("Artist".to_string()),
+            release_date: None,
+            original_art: None,
+            resized_art: None,
+            album_gain: None,
+            album_peak: None,
+            library_root: directory.parent().map(Utf8Path::to_owned),
+            disc_numbers: Vec::new(),
+        };
+        let (album, _) = queries::find_or_insert_album(&mut conn, new_album).unwrap();
+
+        let mut song_ids = Vec::new();
+        for (track, title) in (1..).zip(titles) {
+            let new_song = NewSong {
+                album_id: album.id,
+                file: directory.join(format!("0{track}.flac")),
+                total_seconds: 100,
+                title: Some(title.to_string()),
+                artist: Some("Artist".to_string()),
+                track_number: Some(track),
+                disc_number: None,
+                track_gain: None,
+                track_peak: None,
+                genre: None,
+                track_total: None,
+                bpm: None,
+                initial_key: None,
+            };
+            let (song, _) = queries::find_or_insert_song(&mut conn, new_song).unwrap();
+            song_ids.push(song.id);
+        }
+
+        song_ids
+    }
+
+    fn song_ref(path: &str, title: &str, album: Option<&str>) -> SongRef {
+        SongRef {
+            path: path.into(),
+            title: Some(title.to_string()),
+            artist: Some("Artist".to_string()),
+            album: album.map(String::from),
+        }
+    }
+
+    #[test]
+    fn songs_are_found_by_path_then_by_unique_tags() {
+        let (root, music, pool) = temp_library("matcher");
+        let album = add_album(&pool, &music.join("Album"), &["One", "Two"]);
+        let copy = add_album(&pool, &music.join("Album (Copy)"), &["One"]);
+
+        let mut conn = pool.get().unwrap();
+        let albums = queries::all_albums(&mut conn).unwrap();
+        let songs = queries::all_songs(&mut conn).unwrap();
+        let matcher = SongMatcher::new(&albums, &songs);
+
+        // by path, even when the tags are ambiguous
+        let copied_one = music.join("Album (Copy)/01.flac");
+        assert_eq!(
+            matcher.find(&song_ref(copied_one.as_str(), "One", Some("Album"))),
+            Some(copy[0])
+        );
+
+        // by tags, ignoring case and spaces, when the music moved
+        let moved = song_ref("/elsewhere/02.flac", " two ", Some("ALBUM"));
+        assert_eq!(matcher.find(&moved), Some(album[1]));
+        assert_eq!(
+            matcher.find(&song_ref("/elsewhere/02.flac", "Two", None)),
+            Some(album[1])
+        );
+
+        // the same tags on two songs match neither
+        let ambiguous = song_ref("/elsewhere/01.flac", "One", Some("Album"));
+        assert_eq!(matcher.find(&ambiguous), None);
+        assert_eq!(
+            matcher.find(&song_ref("/elsewhere/01.flac", "One", None)),
+            None
+        );
+
+        // nor do songs without a title
+        let untitled = SongRef {
+            title: None,
+            ..song_ref("/elsewhere/02.flac", "", Some("Album"))
+        };
+        assert_eq!(matcher.find(&untitled), None);
+
+        drop(conn);
+        drop(pool);
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn ambiguous_tags_are_marked_once_seen_twice() {
+        let key = || tag_key(Some("One"), Some("Artist"), None).unwrap();
+        let mut by_tags = HashMap::new();
+
+        insert_unique(&mut by_tags, key(), SongId::new(1));
+        assert_eq!(by_tags.get(&key()), Some(&Some(SongId::new(1))));
+        insert_unique(&mut by_tags, key(), SongId::new(2));
+        assert_eq!(by_tags.get(&key()), Some(&None));
+        insert_unique(&mut by_tags, key(), SongId::new(3));
+        assert_eq!(by_tags.get(&key()), Some(&None));
+    }
+
+    #[test]
+    fn importing_an_export_twice_adds_everything_once() {
+        let (old_root, old_music, old_pool) = temp_library("export");
+        let old_songs = add_album(&old_pool, &old_music.join("Album"), &["One", "Two"]);
+        let exported = old_root.join("library.json");
+        {
+            let mut conn = old_pool.get().unwrap();
+            queries::set_song_favorite(&mut conn, old_songs[0], true).unwrap();
+            let play = Play {
+                song_id: old_songs[1],
+                started_at: 1_688_212_800,
+                completed_at: Some(1_688_212_900),
+                percent_played: 100.0,
+                listened_seconds: 100.0,
+            };
+            queries::insert_play(&mut conn, &play).unwrap();
+            let mix = old_music.join("Mix.m3u");
+            queries::save_playlist(&mut conn, &mix, "Mix", &[old_songs[1], old_songs[0]])
+                .unwrap();
+            queries::create_smart_playlist(&mut conn, "Favs", &[SmartRule::Favorite])
+                .unwrap();
+        }
+        let summary = export(&old_pool, &exported).unwrap();
+        assert_eq!(
+            (
+                summary.favorites,
+                summary.plays,
+                summary.playlists,
+                summary.smart_playlists
+            ),
+            (1, 1, 1, 1)
+        );
+
+        // the same music, somewhere else
+        let (new_root, new_music, new_pool) = temp_library("import");
+        let new_songs = add_album(&new_pool, &new_music.join("Album"), &["One", "Two"]);
+        let music_directories = [new_music.clone()];
+
+        let summary = import(&new_pool, &exported, &music_directories).unwrap();
+        assert_eq!(
+            (
+                summary.favorites,
+                summary.plays,
+                summary.playlists,
+                summary.smart_playlists
+            ),
+            (1, 1, 1, 1)
+        );
+        assert_eq!((summary.unmatched, summary.already_present), (0, 0));
+
+        let mut conn = new_pool.get().unwrap();
+        let playlists = queries::all_playlists(&mut conn).unwrap();
+        assert_eq!(playlists.len(), 1);
+        assert_eq!(playlists[0].file, new_music.join("Mix.m3u8"));
+        assert_eq!(playlists[0].songs, [new_songs[1], new_songs[0]]);
+        assert!(playlists[0].file.is_file());
+        drop(conn);
+
+        let summary = import(&new_pool, &exported, &music_directories).unwrap();
+        assert_eq!(
+            (summary.plays, summary.playlists, summary.smart_playlists),
+            (0, 0, 0)
+        );
+        assert_eq!(summary.already_present, 3);
+        let mut conn = new_pool.get().unwrap();
+        assert_eq!(queries::all_plays(&mut conn).unwrap().len(), 1);
+        assert_eq!(queries::all_playlists(&mut conn).unwrap().len(), 1);
+        assert_eq!(queries::all_smart_playlists(&mut conn).unwrap().len(), 1);
+
+        drop(conn);
+        drop((old_pool, new_pool));
+        std::fs::remove_dir_all(&old_root).ok();
+        std::fs::remove_dir_all(&new_root).ok();
+    }
+}
@@ -1,15 +1,21 @@
 // This is a hacky setup to avoid needing to set env vars in powershell.
 // Consider using a config crate or something, and switching to tracing.
 
+use std::time::Instant;
+
+use log::{Level, Log, Metadata, Record};
+
 const VARS: [(&str, &str); 3] = [
     ("RUST_BACKTRACE", "full"),
     ("RUST_LIB_BACKTRACE", "full"),
     ("RUST_LOG", "clef=debug"),
 ];
 
-pub fn init() {
-    let debug = std::env::args().nth(1).as_deref() == Some("--debug");
+/// Clef's own logs at this level or above are kept for crash reports,
+/// even when they aren't printed
+const CRASH_REPORT_LEVEL: Level = Level::Info;
 
+pub fn init(debug: bool) {
     if debug {
         for (k, v) in VARS {
             std::env::set_var(k, v);
@@ -20,5 +26,54 @@ pub fn init() {
         }
     }
 
-    pretty_env_logger::init();
+    // NOTE this matches pretty_env_logger::init, with the recent log kept on the side
+    let mut builder = pretty_env_logger::formatted_builder();
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    let printed = builder.build();
+    let max_level = printed.filter().max(CRASH_REPORT_LEVEL.to_level_filter());
+
+    let logger = CrashReportLogger {
+        printed: Box::new(printed),
+        started_at: Instant::now(),
+    };
+    log::set_boxed_logger(Box::new(logger)).expect("failed to set logger");
+    log::set_max_level(max_level);
+}
+
+struct CrashReportLogger {
+    printed: Box<dyn Log>,
+    started_at: Instant,
+}
+
+impl CrashReportLogger {
+    fn is_recorded(metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= CRASH_REPORT_LEVEL && metadata.target().starts_with("clef")
+    }
+}
+
+impl Log for CrashReportLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        Self::is_recorded(metadata) || self.printed.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if Self::is_recorded(record.metadata()) {
+            let elapsed = self.started_at.elapsed().as_secs_f64();
+            clef_shared::crash_report::record_log(format!(
+                "{elapsed:>9.3}s {:<5} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+
+        // NOTE the printed logger applies its own filter
+        self.printed.log(record);
+    }
+
+    fn flush(&self) {
+        self.printed.flush();
+    }
 }
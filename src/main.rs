@@ -1,31 +1,111 @@
+use std::time::Instant;
+
+use anyhow::anyhow;
+use log::{error, info};
+
 use clef_audio::player::{AudioAction, AudioMessage, Player};
-use clef_ui::Flags;
+use clef_shared::crash_report;
+use clef_ui::{Flags, Handoff, InstanceClaim};
 
+use clef::cli;
 use clef::config;
+use clef::db_check;
 use clef::logging;
 
 fn main() -> anyhow::Result<()> {
-    logging::init();
+    let started_at = Instant::now();
+
+    let args = cli::parse_args();
+
+    logging::init(args.debug);
+
+    let mut config = config::init().expect("unable to build config");
+    cli::apply_to_config(&args, &mut config)?;
+
+    crash_report::install_panic_hook(config.local_data_directory.clone());
+
+    if let Some(subcommand) = args.subcommand {
+        let db_pool = clef_db::create_pool(&config.db_path)?;
+        clef_db::run_migrations(&db_pool).map_err(|e| anyhow!(e))?;
 
-    let config = config::init().expect("unable to build config");
+        return cli::run(subcommand, &config, &db_pool);
+    }
 
-    let db_pool =
-        clef_db::create_pool(&config.db_path).expect("failed to create db pool");
+    let open_on_launch = args.open.as_deref().map(cli::absolute).transpose()?;
+    let handoff = match (&args.play, &open_on_launch, args.link) {
+        (Some(path), _, _) => Handoff::Play(cli::absolute(path)?),
+        (None, Some(file), _) => Handoff::Open(file.clone()),
+        (None, None, Some(link)) => Handoff::PlayLink(link),
+        (None, None, None) => Handoff::Show,
+    };
+    let handoffs = match clef_ui::claim_instance(&config.local_data_directory, handoff) {
+        Ok(InstanceClaim::Primary(handoffs)) => handoffs,
+        Ok(InstanceClaim::HandedOff) => {
+            println!("clef is already running");
+            return Ok(());
+        }
+        Ok(InstanceClaim::Unresponsive) => {
+            anyhow::bail!("clef is already running, but didn't respond");
+        }
+        Err(e) => {
+            error!("failed to check for a running instance: {e:?}");
+            // NOTE the sender is dropped, so this never yields
+            flume::unbounded().1
+        }
+    };
+    info!("claimed instance after {:?}", started_at.elapsed());
+
+    // NOTE only after claiming the instance, so that a handoff never opens the db
+    let (db_pool, database_damage) = db_check::on_launch(&config.db_path);
+    info!("opened and checked db after {:?}", started_at.elapsed());
+
+    let crash_report = crash_report::take_unseen_report(&config.local_data_directory);
 
-    clef_db::run_migrations(&db_pool).expect("failed to run migrations");
+    // NOTE a damaged db is left alone until the user decides whether to restore it
+    if args.rescan && database_damage.is_none() {
+        cli::run(cli::Subcommand::Scan, &config, &db_pool)?;
+    }
+
+    let play_on_launch = match (&args.play, &database_damage) {
+        (Some(path), None) => cli::songs_to_play(&db_pool, path)?,
+        _ => Vec::new(),
+    };
 
     let (to_audio_tx, to_audio_rx) = flume::unbounded::<AudioAction>();
     let (to_ui_tx, to_ui_rx) = flume::unbounded::<AudioMessage>();
 
-    Player::spawn(to_audio_rx, to_ui_tx, to_audio_tx.clone())
-        .expect("failed to start audio thread");
+    Player::spawn(
+        to_audio_rx,
+        to_ui_tx,
+        to_audio_tx.clone(),
+        db_pool.clone(),
+        config.replay_gain,
+    )
+    .expect("failed to start audio thread");
+    if database_damage.is_some() {
+        to_audio_tx
+            .send(AudioAction::SetDbWrites(false))
+            .expect("failed to send to audio thread");
+    }
+    info!("started audio thread after {:?}", started_at.elapsed());
 
+    let db_path = config.db_path.clone();
     let flags = Flags {
         inbox: to_ui_rx,
         to_audio: to_audio_tx,
         db_pool,
         config,
+        started_at,
+        play_on_launch,
+        open_on_launch,
+        link_on_launch: args.link,
+        handoffs,
+        crash_report,
+        database_damage,
     };
 
-    clef_ui::setup::launch(flags)
+    let result = clef_ui::setup::launch(flags);
+    db_check::on_close(&db_path);
+
+    result
 }